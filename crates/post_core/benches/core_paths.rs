@@ -0,0 +1,131 @@
+//! Timings of the work every clipboard update goes through: hashing, signing, encryption
+//! and encoding for the wire
+//!
+//! Record a baseline before a change and compare against it afterwards:
+//!
+//! ```text
+//! cargo bench -p post_core -- --save-baseline before
+//! cargo bench -p post_core -- --baseline before
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use post_core::{
+    bench, calculate_hash, decode_message, derive_shared_secret, encode_message, generate_keypair,
+    generate_signing_keypair, sign_message_with_signing_key, verify_signature, ClipboardData,
+    CryptoSession, MessageData, MessageType, PostMessage, WireFormat, TEXT_PLAIN,
+};
+use std::collections::BTreeMap;
+use std::hint::black_box;
+
+/// Clipboard content sizes: a short snippet, a page of text, and a large paste
+const SIZES: [usize; 3] = [100, 10_000, 1_000_000];
+
+fn clipboard_update(size: usize) -> PostMessage {
+    PostMessage {
+        version: 1,
+        message_type: MessageType::ClipboardUpdate,
+        data: MessageData::ClipboardUpdate(ClipboardData {
+            content: bench::payload(size).into(),
+            timestamp: 1_700_000_000,
+            source_node: "node-a".to_string(),
+            sequence: 42,
+            tailnet: Some("example.ts.net".to_string()),
+            content_type: TEXT_PLAIN.to_string(),
+            metadata: BTreeMap::new(),
+        }),
+        signature: vec![0; 64],
+    }
+}
+
+fn wire_formats(c: &mut Criterion) {
+    for format in WireFormat::SUPPORTED {
+        let mut group = c.benchmark_group(format!("wire/{}", format));
+        for size in SIZES {
+            let message = clipboard_update(size);
+            let frame = encode_message(&message, format).unwrap();
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(BenchmarkId::new("encode", size), &message, |b, message| {
+                b.iter(|| encode_message(black_box(message), format).unwrap())
+            });
+            group.bench_with_input(BenchmarkId::new("decode", size), &frame, |b, frame| {
+                b.iter(|| decode_message(black_box(frame)).unwrap())
+            });
+        }
+        group.finish();
+    }
+}
+
+fn signatures(c: &mut Criterion) {
+    let keypair = generate_signing_keypair().unwrap();
+    let mut group = c.benchmark_group("signature");
+    for size in SIZES {
+        // Messages are signed over their JSON encoding
+        let bytes = serde_json::to_vec(&clipboard_update(size)).unwrap();
+        let signature = sign_message_with_signing_key(&keypair, &bytes).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("sign", size), &bytes, |b, bytes| {
+            b.iter(|| sign_message_with_signing_key(&keypair, black_box(bytes)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("verify", size), &bytes, |b, bytes| {
+            b.iter(|| {
+                verify_signature(&keypair.verifying_key, black_box(bytes), &signature).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn encryption(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let ours = generate_keypair().unwrap();
+    let theirs = generate_keypair().unwrap();
+    let secret = derive_shared_secret(&ours.private_key, &theirs.public_key).unwrap();
+    let session = CryptoSession::new(&secret).unwrap();
+
+    let mut group = c.benchmark_group("encryption");
+    for size in SIZES {
+        let plaintext = bench::payload(size).into_bytes();
+        let ciphertext = runtime.block_on(session.encrypt(&plaintext)).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(
+            BenchmarkId::new("encrypt", size),
+            &plaintext,
+            |b, plaintext| {
+                b.iter(|| {
+                    runtime
+                        .block_on(session.encrypt(black_box(plaintext)))
+                        .unwrap()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("decrypt", size),
+            &ciphertext,
+            |b, ciphertext| {
+                b.iter(|| {
+                    runtime
+                        .block_on(session.decrypt(black_box(ciphertext)))
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn hashing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash");
+    for size in SIZES {
+        let content = bench::payload(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &content, |b, content| {
+            b.iter(|| calculate_hash(black_box(content)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, wire_formats, signatures, encryption, hashing);
+criterion_main!(benches);
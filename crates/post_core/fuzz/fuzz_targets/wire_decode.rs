@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use post_core::decode_message;
+
+// Run with `cargo +nightly fuzz run wire_decode` from crates/post_core
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = decode_message(data) {
+        // Anything we accept must survive a round trip through the wire format
+        let encoded = serde_json::to_vec(&message).expect("accepted message must re-encode");
+        decode_message(&encoded).expect("re-encoded message must decode");
+    }
+});
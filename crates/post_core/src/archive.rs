@@ -0,0 +1,127 @@
+use crate::{PostError, Result};
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Starts every archive written by [`seal`], naming its format version
+const MAGIC: &[u8; 8] = b"POSTARC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN;
+
+/// Files moved to a new machine by `post export` and `post import`
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Archive {
+    /// Unix time the archive was made
+    pub created_at: u64,
+    /// Contents by name, e.g. `config.toml`
+    pub files: BTreeMap<String, String>,
+}
+
+impl Archive {
+    pub fn new() -> Self {
+        Self {
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+/// Encrypt `archive` with a key derived from `passphrase`
+pub fn seal(archive: &Archive, passphrase: &str) -> Result<Vec<u8>> {
+    if passphrase.is_empty() {
+        return Err(PostError::Crypto(
+            "An archive needs a passphrase".to_string(),
+        ));
+    }
+    let json = serde_json::to_vec(archive)
+        .map_err(|e| PostError::Serialization(format!("Failed to encode archive: {}", e)))?;
+
+    let mut header = MAGIC.to_vec();
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    header.extend_from_slice(&salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &json,
+                aad: &header,
+            },
+        )
+        .map_err(|e| PostError::Crypto(format!("Encryption failed: {}", e)))?;
+
+    let mut sealed = header;
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt an archive written by [`seal`]
+pub fn open(sealed: &[u8], passphrase: &str) -> Result<Archive> {
+    if sealed.len() < HEADER_LEN + NONCE_LEN || !sealed.starts_with(MAGIC) {
+        return Err(PostError::Crypto("Not a Post archive".to_string()));
+    }
+    let (header, rest) = sealed.split_at(HEADER_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let json = cipher(passphrase, &header[MAGIC.len()..])?
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .map_err(|_| PostError::Crypto("Wrong passphrase or damaged archive".to_string()))?;
+    serde_json::from_slice(&json)
+        .map_err(|e| PostError::Serialization(format!("Invalid archive contents: {}", e)))
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| PostError::Crypto(format!("Failed to derive archive key: {}", e)))?;
+    ChaCha20Poly1305::new_from_slice(&key)
+        .map_err(|e| PostError::Crypto(format!("Failed to create cipher: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let mut archive = Archive::new();
+        archive
+            .files
+            .insert("history.json".to_string(), r#"["secret"]"#.to_string());
+
+        assert!(seal(&archive, "").is_err());
+        let sealed = seal(&archive, "correct horse").unwrap();
+        assert!(!String::from_utf8_lossy(&sealed).contains("secret"));
+
+        let opened = open(&sealed, "correct horse").unwrap();
+        assert_eq!(opened.created_at, archive.created_at);
+        assert_eq!(opened.files, archive.files);
+
+        assert!(open(&sealed, "wrong").is_err());
+        assert!(open(b"POSTARC1", "correct horse").is_err());
+        let mut damaged = sealed;
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(open(&damaged, "correct horse").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Synthetic clipboard payloads for `post bench`, and the numbers it reports
+
+use rand::distributions::{Alphanumeric, DistString};
+use std::time::Duration;
+
+/// Payload sizes measured when none are given, in bytes
+pub const DEFAULT_SIZES: [usize; 4] = [100, 10_000, 100_000, 1_000_000];
+
+/// Largest payload a bench round may send, well under [`crate::MAX_MESSAGE_SIZE`]
+pub const MAX_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+
+/// Random text of `size` bytes, so compression along the way can't flatter the numbers
+pub fn payload(size: usize) -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), size)
+}
+
+/// Round trips of one payload size to a peer
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    /// Payload size in bytes
+    pub size: usize,
+    /// Rounds the peer answered
+    pub answered: usize,
+    /// Rounds left unanswered within the timeout, or not sent before time ran out
+    pub lost: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    /// Payload bytes per second over the answered rounds
+    pub throughput: f64,
+}
+
+impl BenchResult {
+    /// Summarize the `round_trips` of answered rounds and the number of `lost` ones
+    pub fn new(size: usize, mut round_trips: Vec<Duration>, lost: usize) -> Self {
+        round_trips.sort_unstable();
+        let total: Duration = round_trips.iter().sum();
+        let throughput = if total.is_zero() {
+            0.0
+        } else {
+            (size * round_trips.len()) as f64 / total.as_secs_f64()
+        };
+        Self {
+            size,
+            answered: round_trips.len(),
+            lost,
+            p50: percentile(&round_trips, 50),
+            p95: percentile(&round_trips, 95),
+            throughput,
+        }
+    }
+}
+
+/// The nearest-rank `percent`th percentile of `sorted`; zero when it is empty
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles_use_the_nearest_rank() {
+        let round_trips: Vec<Duration> = (1..=20).rev().map(Duration::from_millis).collect();
+        let result = BenchResult::new(1000, round_trips, 2);
+        assert_eq!(result.answered, 20);
+        assert_eq!(result.lost, 2);
+        assert_eq!(result.p50, Duration::from_millis(10));
+        assert_eq!(result.p95, Duration::from_millis(19));
+        // 20 KB over 210 ms
+        assert!((result.throughput - 20_000.0 / 0.21).abs() < 1.0);
+
+        let lost = BenchResult::new(1000, Vec::new(), 5);
+        assert_eq!(lost.p95, Duration::ZERO);
+        assert_eq!(lost.throughput, 0.0);
+        assert_eq!(payload(64).len(), 64);
+    }
+}
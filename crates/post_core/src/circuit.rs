@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Wait before the first probe of a peer that just crossed the failure threshold
+const BASE_BACKOFF: Duration = Duration::from_secs(15);
+
+/// A peer's circuit as reported by [`CircuitBreaker::circuits`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerCircuit {
+    /// Address messages are sent to
+    pub address: String,
+    /// Sends that failed in a row
+    pub consecutive_failures: u32,
+    /// Seconds until the next send is let through as a probe; unset while sends go
+    /// through normally, 0 when the probe is due
+    pub retry_in: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    failures: u32,
+    /// Sends are skipped until then once the failure threshold is reached
+    open_until: Option<Instant>,
+}
+
+/// Per-peer circuit breaker: after `threshold` failed sends in a row a peer is skipped,
+/// except for one probe send each time its backoff runs out, doubling up to `max_backoff`
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    max_backoff: Duration,
+    peers: Mutex<HashMap<String, CircuitState>>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(3, Duration::from_secs(300))
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, max_backoff: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            max_backoff: max_backoff.max(BASE_BACKOFF),
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether to send to `peer` now; a due probe is let through once, pushing back
+    /// the next one until its outcome is recorded
+    pub fn allow(&self, peer: &str) -> bool {
+        self.allow_at(peer, Instant::now())
+    }
+
+    fn allow_at(&self, peer: &str, now: Instant) -> bool {
+        let mut peers = self.lock();
+        let Some(state) = peers.get_mut(peer) else {
+            return true;
+        };
+        match state.open_until {
+            Some(until) if now < until => false,
+            Some(_) => {
+                state.open_until = Some(now + self.backoff(state.failures));
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self, peer: &str) {
+        self.lock().remove(peer);
+    }
+
+    /// Count a failed send to `peer`, returning whether it had been reachable until now
+    pub fn record_failure(&self, peer: &str) -> bool {
+        self.record_failure_at(peer, Instant::now())
+    }
+
+    fn record_failure_at(&self, peer: &str, now: Instant) -> bool {
+        let mut peers = self.lock();
+        let state = peers.entry(peer.to_string()).or_default();
+        state.failures += 1;
+        if state.failures >= self.threshold {
+            state.open_until = Some(now + self.backoff(state.failures));
+        }
+        state.failures == 1
+    }
+
+    /// Backoff after `failures` failed sends in a row: the base doubled for each failure
+    /// past the threshold, capped at `max_backoff`
+    fn backoff(&self, failures: u32) -> Duration {
+        let doublings = failures.saturating_sub(self.threshold).min(16);
+        (BASE_BACKOFF * 2u32.pow(doublings)).min(self.max_backoff)
+    }
+
+    /// Peers with failed sends since their last successful one, by address
+    pub fn circuits(&self) -> Vec<PeerCircuit> {
+        let now = Instant::now();
+        let mut circuits: Vec<PeerCircuit> = self
+            .lock()
+            .iter()
+            .map(|(address, state)| PeerCircuit {
+                address: address.clone(),
+                consecutive_failures: state.failures,
+                retry_in: state
+                    .open_until
+                    .map(|until| until.saturating_duration_since(now).as_secs()),
+            })
+            .collect();
+        circuits.sort_by(|a, b| a.address.cmp(&b.address));
+        circuits
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CircuitState>> {
+        self.peers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_is_skipped_after_threshold_and_probed_with_backoff() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(breaker.record_failure_at("100.64.0.2", start));
+        assert!(breaker.allow_at("100.64.0.2", start));
+        assert!(!breaker.record_failure_at("100.64.0.2", start));
+        assert!(!breaker.allow_at("100.64.0.2", start));
+        assert!(breaker.allow_at("100.64.0.3", start));
+
+        // One probe once the backoff runs out, then skipped again while it is in flight
+        let due = start + BASE_BACKOFF;
+        assert!(breaker.allow_at("100.64.0.2", due));
+        assert!(!breaker.allow_at("100.64.0.2", due));
+
+        // A failed probe doubles the wait
+        assert!(!breaker.record_failure_at("100.64.0.2", due));
+        assert!(!breaker.allow_at("100.64.0.2", due + BASE_BACKOFF));
+        assert!(breaker.allow_at("100.64.0.2", due + BASE_BACKOFF * 2));
+        assert_eq!(breaker.backoff(10), Duration::from_secs(60));
+
+        breaker.record_success("100.64.0.2");
+        assert!(breaker.allow_at("100.64.0.2", due));
+        assert!(breaker.circuits().is_empty());
+    }
+}
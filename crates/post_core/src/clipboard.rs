@@ -1,13 +1,21 @@
+use crate::redact::Redacted;
 use crate::{config::ClipboardConfig, PostError, Result};
 use copypasta::{ClipboardContext, ClipboardProvider};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 
 #[async_trait::async_trait]
 pub trait ClipboardManager: Send + Sync {
     async fn get_contents(&self) -> Result<String>;
     async fn set_contents(&self, content: &str) -> Result<()>;
+
+    /// Whether the current content was marked as a secret by the app that copied it
+    async fn is_concealed(&self) -> Result<bool> {
+        crate::concealed::is_concealed().await
+    }
 }
 
 #[async_trait::async_trait]
@@ -16,244 +24,477 @@ pub trait ClipboardWatcher: Send + Sync {
         &self,
         callback: Box<dyn Fn(String) + Send + Sync + 'static>,
     ) -> Result<()>;
+
+    /// Report when watching finds the clipboard unavailable, and once it recovers;
+    /// ignored by watchers that don't track this
+    fn set_health_callback(&self, _on_health: HealthCallback) {}
+}
+
+/// A clipboard that can be both read/written and watched for changes
+pub trait ClipboardBackend: ClipboardManager + ClipboardWatcher {}
+
+impl<T: ClipboardManager + ClipboardWatcher> ClipboardBackend for T {}
+
+#[async_trait::async_trait]
+impl<T: ClipboardManager + ?Sized> ClipboardManager for Arc<T> {
+    async fn get_contents(&self) -> Result<String> {
+        (**self).get_contents().await
+    }
+
+    async fn set_contents(&self, content: &str) -> Result<()> {
+        (**self).set_contents(content).await
+    }
+
+    async fn is_concealed(&self) -> Result<bool> {
+        (**self).is_concealed().await
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: ClipboardWatcher + ?Sized> ClipboardWatcher for Arc<T> {
+    async fn watch_changes(
+        &self,
+        callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+    ) -> Result<()> {
+        (**self).watch_changes(callback).await
+    }
+
+    fn set_health_callback(&self, on_health: HealthCallback) {
+        (**self).set_health_callback(on_health)
+    }
+}
+
+/// A manager and watcher built separately, used together as one backend
+struct CombinedClipboard {
+    manager: Box<dyn ClipboardManager>,
+    watcher: Box<dyn ClipboardWatcher>,
+}
+
+#[async_trait::async_trait]
+impl ClipboardManager for CombinedClipboard {
+    async fn get_contents(&self) -> Result<String> {
+        self.manager.get_contents().await
+    }
+
+    async fn set_contents(&self, content: &str) -> Result<()> {
+        self.manager.set_contents(content).await
+    }
+
+    async fn is_concealed(&self) -> Result<bool> {
+        self.manager.is_concealed().await
+    }
+}
+
+#[async_trait::async_trait]
+impl ClipboardWatcher for CombinedClipboard {
+    async fn watch_changes(
+        &self,
+        callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+    ) -> Result<()> {
+        self.watcher.watch_changes(callback).await
+    }
+
+    fn set_health_callback(&self, on_health: HealthCallback) {
+        self.watcher.set_health_callback(on_health)
+    }
 }
 
 pub struct SystemClipboard {
     context: Arc<Mutex<ClipboardContext>>,
     last_content: Arc<Mutex<String>>,
+    on_health: Arc<RwLock<Option<HealthCallback>>>,
 }
 
 impl SystemClipboard {
     pub fn new() -> Result<Self> {
         let context = ClipboardContext::new().map_err(|e| {
-            PostError::Clipboard(format!("Failed to create clipboard context: {}", e))
+            PostError::BackendUnavailable(format!("Failed to create clipboard context: {}", e))
         })?;
 
         Ok(Self {
             context: Arc::new(Mutex::new(context)),
             last_content: Arc::new(Mutex::new(String::new())),
+            on_health: Arc::default(),
         })
     }
 }
 
-/// Creates the best clipboard implementation for the current platform and environment
-pub fn create_clipboard() -> Result<Box<dyn ClipboardManager>> {
-    create_clipboard_with_config(&ClipboardConfig::default())
+/// Capability metadata advertised by a clipboard backend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackendCapabilities {
+    /// Backend can watch for clipboard changes
+    pub watch: bool,
+    /// Backend can read and write image content
+    pub images: bool,
 }
 
-/// Creates the best clipboard watcher implementation for the current platform and environment
-pub fn create_clipboard_watcher() -> Result<Box<dyn ClipboardWatcher>> {
-    create_clipboard_watcher_with_config(&ClipboardConfig::default())
+type AvailabilityFn = Arc<dyn Fn(&ClipboardConfig) -> bool + Send + Sync>;
+type ManagerConstructor =
+    Arc<dyn Fn(&ClipboardConfig) -> Result<Box<dyn ClipboardManager>> + Send + Sync>;
+type WatcherConstructor =
+    Arc<dyn Fn(&ClipboardConfig) -> Result<Box<dyn ClipboardWatcher>> + Send + Sync>;
+type BackendConstructor =
+    Arc<dyn Fn(&ClipboardConfig) -> Result<Arc<dyn ClipboardBackend>> + Send + Sync>;
+
+/// Describes how to detect and construct a single clipboard backend
+#[derive(Clone)]
+pub struct ClipboardBackendFactory {
+    name: String,
+    priority: i32,
+    auto_select: bool,
+    images: bool,
+    is_available: AvailabilityFn,
+    create_manager: ManagerConstructor,
+    create_watcher: Option<WatcherConstructor>,
+    create_backend: Option<BackendConstructor>,
 }
 
-/// Creates clipboard implementation with specific configuration
-pub fn create_clipboard_with_config(config: &ClipboardConfig) -> Result<Box<dyn ClipboardManager>> {
-    #[cfg(target_os = "linux")]
+impl ClipboardBackendFactory {
+    /// Creates a factory for a backend that is always available and cannot watch for changes
+    pub fn new<F>(name: &str, create_manager: F) -> Self
+    where
+        F: Fn(&ClipboardConfig) -> Result<Box<dyn ClipboardManager>> + Send + Sync + 'static,
     {
-        match config.backend.as_str() {
-            "wayland" => {
-                if linux::has_wl_clipboard() {
-                    debug!("Creating Wayland clipboard (forced via config)");
-                    Ok(Box::new(linux::WaylandClipboard::new(
-                        linux::WaylandClipboardType::Clipboard,
-                    )?))
-                } else {
-                    Err(PostError::Clipboard(
-                        "Wayland clipboard requested but wl-clipboard not available".to_string(),
-                    ))
-                }
-            }
-            "xclip" => {
-                if linux::has_xclip() {
-                    debug!("Creating xclip clipboard (forced via config)");
-                    Ok(Box::new(linux::XClipClipboard::new()?))
-                } else {
-                    Err(PostError::Clipboard(
-                        "xclip clipboard requested but xclip not available".to_string(),
-                    ))
-                }
-            }
-            "xsel" => {
-                if linux::has_xsel() {
-                    debug!("Creating xsel clipboard (forced via config)");
-                    Ok(Box::new(linux::XSelClipboard::new()?))
-                } else {
-                    Err(PostError::Clipboard(
-                        "xsel clipboard requested but xsel not available".to_string(),
-                    ))
-                }
-            }
-            "system" => {
-                debug!("Creating system clipboard (forced via config)");
-                Ok(Box::new(SystemClipboard::new()?))
-            }
-            _ => {
-                if linux::is_wayland_session() && config.wayland_fallback {
-                    debug!("Creating hybrid Linux clipboard for Wayland/Sway session");
-                    Ok(Box::new(linux::HybridLinuxClipboard::new_with_config(
-                        config,
-                    )?))
-                } else {
-                    // Choose best X11 clipboard utility
-                    let best_utility = linux::get_best_clipboard_utility();
-                    match best_utility.as_str() {
-                        "xclip" => {
-                            debug!("Creating xclip clipboard for X11 session");
-                            Ok(Box::new(linux::XClipClipboard::new()?))
-                        }
-                        "xsel" => {
-                            debug!("Creating xsel clipboard for X11 session");
-                            Ok(Box::new(linux::XSelClipboard::new()?))
-                        }
-                        _ => {
-                            debug!("Creating system clipboard for X11 session");
-                            Ok(Box::new(SystemClipboard::new()?))
-                        }
-                    }
-                }
-            }
+        Self {
+            name: name.to_string(),
+            priority: 0,
+            auto_select: true,
+            images: false,
+            is_available: Arc::new(|_| true),
+            create_manager: Arc::new(create_manager),
+            create_watcher: None,
+            create_backend: None,
         }
     }
 
-    #[cfg(target_os = "windows")]
+    /// Creates a factory for a backend that is always available and can watch for changes,
+    /// with one value serving as both manager and watcher
+    pub fn new_watching<F>(name: &str, create_backend: F) -> Self
+    where
+        F: Fn(&ClipboardConfig) -> Result<Arc<dyn ClipboardBackend>> + Send + Sync + 'static,
     {
-        match config.backend.as_str() {
-            "wsl" => {
-                if windows::is_wsl_environment() {
-                    debug!("Creating WSL clipboard (forced via config)");
-                    Ok(Box::new(windows::WSLClipboard::new()?))
-                } else {
-                    return Err(PostError::Clipboard(
-                        "WSL clipboard requested but not running in WSL environment".to_string(),
-                    ));
-                }
-            }
-            "windows" => {
-                debug!("Creating Windows system clipboard (forced via config)");
-                Ok(Box::new(SystemClipboard::new()?))
-            }
-            _ => {
-                if windows::is_wsl_environment() {
-                    debug!("Creating WSL clipboard for WSL environment");
-                    Ok(Box::new(windows::WSLClipboard::new()?))
-                } else {
-                    debug!("Creating Windows system clipboard");
-                    Ok(Box::new(SystemClipboard::new()?))
-                }
-            }
-        }
+        let create_backend: BackendConstructor = Arc::new(create_backend);
+        let create = Arc::clone(&create_backend);
+        let mut factory = Self::new(name, move |config| {
+            Ok(Box::new(create(config)?) as Box<dyn ClipboardManager>)
+        });
+        let create = Arc::clone(&create_backend);
+        factory.create_watcher = Some(Arc::new(move |config| {
+            Ok(Box::new(create(config)?) as Box<dyn ClipboardWatcher>)
+        }));
+        factory.create_backend = Some(create_backend);
+        factory
     }
 
-    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    /// Higher priority backends win during auto-selection
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Restricts the backend to environments where `is_available` returns true
+    pub fn with_availability<F>(mut self, is_available: F) -> Self
+    where
+        F: Fn(&ClipboardConfig) -> bool + Send + Sync + 'static,
     {
-        debug!(
-            "Creating system clipboard for non-Linux/Windows platform (config: backend={})",
-            config.backend
-        );
-        Ok(Box::new(SystemClipboard::new()?))
+        self.is_available = Arc::new(is_available);
+        self
     }
-}
 
-/// Creates clipboard watcher implementation with specific configuration
-pub fn create_clipboard_watcher_with_config(
-    config: &ClipboardConfig,
-) -> Result<Box<dyn ClipboardWatcher>> {
-    #[cfg(target_os = "linux")]
+    /// Adds change watching support to the backend
+    pub fn with_watcher<F>(mut self, create_watcher: F) -> Self
+    where
+        F: Fn(&ClipboardConfig) -> Result<Box<dyn ClipboardWatcher>> + Send + Sync + 'static,
     {
-        match config.backend.as_str() {
-            "wayland" => Err(PostError::Clipboard(
-                "Pure Wayland clipboard watching not supported, use hybrid mode".to_string(),
-            )),
-            "xclip" => {
-                if linux::has_xclip() {
-                    debug!("Creating xclip clipboard watcher (forced via config)");
-                    Ok(Box::new(linux::XClipClipboard::new()?))
-                } else {
-                    Err(PostError::Clipboard(
-                        "xclip clipboard watcher requested but xclip not available".to_string(),
-                    ))
-                }
-            }
-            "xsel" => {
-                if linux::has_xsel() {
-                    debug!("Creating xsel clipboard watcher (forced via config)");
-                    Ok(Box::new(linux::XSelClipboard::new()?))
-                } else {
-                    Err(PostError::Clipboard(
-                        "xsel clipboard watcher requested but xsel not available".to_string(),
-                    ))
-                }
-            }
-            "system" => {
-                debug!("Creating system clipboard watcher (forced via config)");
-                Ok(Box::new(SystemClipboard::new()?))
-            }
-            _ => {
-                if linux::is_wayland_session() && config.wayland_fallback {
-                    debug!("Creating hybrid Linux clipboard watcher for Wayland/Sway session");
-                    Ok(Box::new(linux::HybridLinuxClipboard::new_with_config(
+        self.create_watcher = Some(Arc::new(create_watcher));
+        self
+    }
+
+    /// Marks the backend as able to transfer image content
+    pub fn with_image_support(mut self) -> Self {
+        self.images = true;
+        self
+    }
+
+    /// Excludes the backend from auto-selection so it is only used when forced via config
+    pub fn manual_only(mut self) -> Self {
+        self.auto_select = false;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    pub fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            watch: self.create_watcher.is_some(),
+            images: self.images,
+        }
+    }
+
+    pub fn is_available(&self, config: &ClipboardConfig) -> bool {
+        (self.is_available)(config)
+    }
+
+    pub fn create_manager(&self, config: &ClipboardConfig) -> Result<Box<dyn ClipboardManager>> {
+        (self.create_manager)(config)
+    }
+
+    pub fn create_watcher(&self, config: &ClipboardConfig) -> Result<Box<dyn ClipboardWatcher>> {
+        match &self.create_watcher {
+            Some(create_watcher) => create_watcher(config),
+            None => Err(PostError::Clipboard(format!(
+                "Clipboard backend '{}' does not support watching for changes",
+                self.name
+            ))),
+        }
+    }
+
+    /// A clipboard that can be both read/written and watched, as the daemon needs
+    pub fn create_backend(&self, config: &ClipboardConfig) -> Result<Arc<dyn ClipboardBackend>> {
+        match &self.create_backend {
+            Some(create_backend) => create_backend(config),
+            None => Ok(Arc::new(CombinedClipboard {
+                watcher: self.create_watcher(config)?,
+                manager: self.create_manager(config)?,
+            })),
+        }
+    }
+}
+
+impl std::fmt::Debug for ClipboardBackendFactory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClipboardBackendFactory")
+            .field("name", &self.name)
+            .field("priority", &self.priority)
+            .field("auto_select", &self.auto_select)
+            .field("capabilities", &self.capabilities())
+            .finish()
+    }
+}
+
+/// Set of clipboard backends that can be selected by name or auto-detected
+#[derive(Clone, Default, Debug)]
+pub struct ClipboardRegistry {
+    factories: Vec<ClipboardBackendFactory>,
+}
+
+impl ClipboardRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry containing the backends built into post for the current platform
+    pub fn with_builtin_backends() -> Self {
+        let mut registry = Self::new();
+
+        registry.register(ClipboardBackendFactory::new_watching("system", |_| {
+            Ok(Arc::new(SystemClipboard::new()?))
+        }));
+
+        #[cfg(target_os = "linux")]
+        {
+            registry.register(
+                ClipboardBackendFactory::new_watching("hybrid", |config| {
+                    Ok(Arc::new(linux::HybridLinuxClipboard::new_with_config(
                         config,
                     )?))
-                } else {
-                    // Choose best X11 clipboard utility
-                    let best_utility = linux::get_best_clipboard_utility();
-                    match best_utility.as_str() {
-                        "xclip" => {
-                            debug!("Creating xclip clipboard watcher for X11 session");
-                            Ok(Box::new(linux::XClipClipboard::new()?))
-                        }
-                        "xsel" => {
-                            debug!("Creating xsel clipboard watcher for X11 session");
-                            Ok(Box::new(linux::XSelClipboard::new()?))
-                        }
-                        _ => {
-                            debug!("Creating system clipboard watcher for X11 session");
-                            Ok(Box::new(SystemClipboard::new()?))
-                        }
-                    }
-                }
-            }
+                })
+                .with_priority(100)
+                .with_availability(|config| linux::is_wayland_session() && config.wayland_fallback),
+            );
+            registry.register(
+                ClipboardBackendFactory::new("wayland", |_| {
+                    Ok(Box::new(linux::WaylandClipboard::new(
+                        linux::WaylandClipboardType::Clipboard,
+                    )?))
+                })
+                .with_availability(|_| linux::has_wl_clipboard())
+                .manual_only(),
+            );
+            registry.register(
+                ClipboardBackendFactory::new_watching("xclip", |_| {
+                    Ok(Arc::new(linux::XClipClipboard::new()?))
+                })
+                .with_priority(20)
+                .with_availability(|_| linux::has_xclip()),
+            );
+            registry.register(
+                ClipboardBackendFactory::new_watching("xsel", |_| {
+                    Ok(Arc::new(linux::XSelClipboard::new()?))
+                })
+                .with_priority(10)
+                .with_availability(|_| linux::has_xsel()),
+            );
+            registry.register(
+                ClipboardBackendFactory::new_watching("wsl", |config| {
+                    Ok(Arc::new(windows::WSLClipboard::new_with_config(config)?))
+                })
+                .manual_only(),
+            );
+            registry.register(
+                ClipboardBackendFactory::new_watching("wslg", |config| {
+                    Ok(Arc::new(linux::WslgClipboard::new_with_config(config)?))
+                })
+                .with_priority(110)
+                .with_availability(|config| {
+                    config.wslg_coherence
+                        && windows::is_wsl_environment()
+                        && linux::is_wayland_session()
+                        && linux::has_wl_clipboard()
+                }),
+            );
         }
+
+        #[cfg(target_os = "windows")]
+        {
+            registry.register(
+                ClipboardBackendFactory::new_watching("windows", |_| {
+                    Ok(Arc::new(SystemClipboard::new()?))
+                })
+                .manual_only(),
+            );
+            registry.register(
+                ClipboardBackendFactory::new_watching("wsl", |config| {
+                    Ok(Arc::new(windows::WSLClipboard::new_with_config(config)?))
+                })
+                .with_priority(100)
+                .with_availability(|_| windows::is_wsl_environment()),
+            );
+        }
+
+        registry
     }
 
-    #[cfg(target_os = "windows")]
-    {
-        match config.backend.as_str() {
-            "wsl" => {
-                if windows::is_wsl_environment() {
-                    debug!("Creating WSL clipboard watcher (forced via config)");
-                    Ok(Box::new(windows::WSLClipboard::new()?))
-                } else {
-                    return Err(PostError::Clipboard(
-                        "WSL clipboard watcher requested but not running in WSL environment"
-                            .to_string(),
-                    ));
-                }
-            }
-            "windows" => {
-                debug!("Creating Windows system clipboard watcher (forced via config)");
-                Ok(Box::new(SystemClipboard::new()?))
+    /// Adds a backend, replacing any existing backend registered under the same name
+    pub fn register(&mut self, factory: ClipboardBackendFactory) {
+        self.factories
+            .retain(|existing| existing.name != factory.name);
+        self.factories.push(factory);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ClipboardBackendFactory> {
+        self.factories.iter().find(|factory| factory.name == name)
+    }
+
+    pub fn backends(&self) -> impl Iterator<Item = &ClipboardBackendFactory> {
+        self.factories.iter()
+    }
+
+    /// Picks the backend for `config`: the forced backend if one is configured, otherwise the
+    /// highest priority auto-selectable backend that is available
+    pub fn select(
+        &self,
+        config: &ClipboardConfig,
+        require_watch: bool,
+    ) -> Result<&ClipboardBackendFactory> {
+        if let Some(factory) = self.get(&config.backend) {
+            if !factory.is_available(config) {
+                return Err(PostError::Clipboard(format!(
+                    "{} clipboard requested but not available in this environment",
+                    factory.name
+                )));
             }
-            _ => {
-                if windows::is_wsl_environment() {
-                    debug!("Creating WSL clipboard watcher for WSL environment");
-                    Ok(Box::new(windows::WSLClipboard::new()?))
-                } else {
-                    debug!("Creating Windows system clipboard watcher");
-                    Ok(Box::new(SystemClipboard::new()?))
-                }
+            if require_watch && !factory.capabilities().watch {
+                return Err(PostError::Clipboard(format!(
+                    "{} clipboard does not support watching for changes",
+                    factory.name
+                )));
             }
+            debug!(
+                "Using {} clipboard backend (forced via config)",
+                factory.name
+            );
+            return Ok(factory);
+        }
+
+        if config.backend != "auto" {
+            warn!(
+                "Unknown clipboard backend '{}', falling back to auto-detection",
+                config.backend
+            );
         }
+
+        self.factories
+            .iter()
+            .filter(|factory| factory.auto_select)
+            .filter(|factory| !require_watch || factory.capabilities().watch)
+            .filter(|factory| factory.is_available(config))
+            .max_by_key(|factory| factory.priority)
+            .inspect(|factory| debug!("Auto-selected {} clipboard backend", factory.name))
+            .ok_or_else(|| {
+                PostError::BackendUnavailable("No clipboard backend available".to_string())
+            })
     }
 
-    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
-    {
-        debug!(
-            "Creating system clipboard watcher for non-Linux/Windows platform (config: backend={})",
-            config.backend
-        );
-        Ok(Box::new(SystemClipboard::new()?))
+    pub fn create_manager(&self, config: &ClipboardConfig) -> Result<Box<dyn ClipboardManager>> {
+        self.select(config, false)?.create_manager(config)
+    }
+
+    pub fn create_watcher(&self, config: &ClipboardConfig) -> Result<Box<dyn ClipboardWatcher>> {
+        self.select(config, true)?.create_watcher(config)
     }
+
+    pub fn create_backend(&self, config: &ClipboardConfig) -> Result<Arc<dyn ClipboardBackend>> {
+        self.select(config, true)?.create_backend(config)
+    }
+}
+
+static CLIPBOARD_REGISTRY: OnceLock<RwLock<ClipboardRegistry>> = OnceLock::new();
+
+fn global_registry() -> &'static RwLock<ClipboardRegistry> {
+    CLIPBOARD_REGISTRY.get_or_init(|| RwLock::new(ClipboardRegistry::with_builtin_backends()))
+}
+
+/// Registers an additional clipboard backend used by the `create_clipboard*` functions
+pub fn register_clipboard_backend(factory: ClipboardBackendFactory) {
+    global_registry()
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .register(factory);
+}
+
+/// Returns a snapshot of the registered clipboard backends
+pub fn clipboard_registry() -> ClipboardRegistry {
+    global_registry()
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// Creates the best clipboard implementation for the current platform and environment
+pub fn create_clipboard() -> Result<Box<dyn ClipboardManager>> {
+    create_clipboard_with_config(&ClipboardConfig::default())
+}
+
+/// Creates the best clipboard watcher implementation for the current platform and environment
+pub fn create_clipboard_watcher() -> Result<Box<dyn ClipboardWatcher>> {
+    create_clipboard_watcher_with_config(&ClipboardConfig::default())
+}
+
+/// Creates clipboard implementation with specific configuration
+pub fn create_clipboard_with_config(config: &ClipboardConfig) -> Result<Box<dyn ClipboardManager>> {
+    clipboard_registry().create_manager(config)
+}
+
+/// Creates clipboard watcher implementation with specific configuration
+pub fn create_clipboard_watcher_with_config(
+    config: &ClipboardConfig,
+) -> Result<Box<dyn ClipboardWatcher>> {
+    clipboard_registry().create_watcher(config)
+}
+
+/// Creates a clipboard that can be both used and watched, with specific configuration
+pub fn create_clipboard_backend_with_config(
+    config: &ClipboardConfig,
+) -> Result<Arc<dyn ClipboardBackend>> {
+    clipboard_registry().create_backend(config)
 }
 
 #[async_trait::async_trait]
@@ -273,7 +514,7 @@ impl ClipboardManager for SystemClipboard {
         let mut last = self.last_content.lock().await;
         *last = content.to_owned();
 
-        debug!("Set clipboard contents: {} chars", content.len());
+        debug!("Set clipboard contents: {}", Redacted(content));
         Ok(())
     }
 }
@@ -284,88 +525,285 @@ impl ClipboardWatcher for SystemClipboard {
         &self,
         callback: Box<dyn Fn(String) + Send + Sync + 'static>,
     ) -> Result<()> {
-        let clipboard = Arc::clone(&self.context);
-        let last_content = Arc::clone(&self.last_content);
+        tokio::spawn(poll_system_clipboard(
+            Arc::clone(&self.context),
+            Arc::clone(&self.last_content),
+            Arc::clone(&self.on_health),
+            callback,
+        ));
+        Ok(())
+    }
 
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_millis(500));
+    fn set_health_callback(&self, on_health: HealthCallback) {
+        *self
+            .on_health
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(on_health);
+    }
+}
 
-            loop {
-                interval.tick().await;
+impl SystemClipboard {
+    pub async fn watch_changes_generic<F>(&self, callback: F) -> Result<()>
+    where
+        F: Fn(String) + Send + Sync + 'static,
+    {
+        self.watch_changes(Box::new(callback)).await
+    }
+}
 
-                let current_content = {
-                    let mut ctx = clipboard.lock().await;
-                    match ctx.get_contents() {
-                        Ok(content) => content,
-                        Err(e) => {
-                            warn!("Failed to check clipboard: {}", e);
-                            continue;
-                        }
-                    }
-                };
+/// Time between clipboard checks while reads work
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
 
-                let mut last = last_content.lock().await;
-                if current_content != *last && !current_content.is_empty() {
-                    *last = current_content.clone();
-                    drop(last);
+/// Longest gap between checks while reads keep failing
+const MAX_POLL_BACKOFF: Duration = Duration::from_secs(30);
 
-                    debug!("Clipboard changed: {} chars", current_content.len());
-                    callback(current_content);
-                }
+/// Failed reads in a row after which the clipboard counts as unavailable
+const UNAVAILABLE_AFTER: u32 = 3;
+
+/// Whether a watched clipboard can be read, reported as it changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardHealth {
+    /// Reads have been failing, e.g. because the compositor restarted
+    Unavailable(String),
+    /// Reads work again after the clipboard was unavailable
+    Recovered,
+}
+
+pub type HealthCallback = Arc<dyn Fn(ClipboardHealth) + Send + Sync>;
+
+/// Failed reads of a polled clipboard, and how long to wait before the next check
+#[derive(Debug)]
+struct PollHealth {
+    interval: Duration,
+    failures: u32,
+}
+
+impl Default for PollHealth {
+    fn default() -> Self {
+        Self::every(POLL_INTERVAL)
+    }
+}
+
+impl PollHealth {
+    /// Checks every `interval` while reads work
+    fn every(interval: Duration) -> Self {
+        Self {
+            interval,
+            failures: 0,
+        }
+    }
+
+    /// Delay before the next check; doubles with each failure past the first
+    fn next_delay(&self) -> Duration {
+        match self.failures {
+            0 => self.interval,
+            failures => {
+                (self.interval * 2u32.pow(failures.min(8))).min(MAX_POLL_BACKOFF.max(self.interval))
             }
-        });
+        }
+    }
 
-        Ok(())
+    /// Count a failed read; `true` the moment the clipboard becomes unavailable
+    fn failed(&mut self) -> bool {
+        self.failures += 1;
+        self.failures == UNAVAILABLE_AFTER
+    }
+
+    /// Count a working read; `true` if the clipboard was unavailable until now
+    fn succeeded(&mut self) -> bool {
+        let recovered = self.failures >= UNAVAILABLE_AFTER;
+        self.failures = 0;
+        recovered
+    }
+
+    fn is_unavailable(&self) -> bool {
+        self.failures >= UNAVAILABLE_AFTER
+    }
+
+    /// Count a failed read, warning only as the clipboard becomes unavailable
+    fn log_failure(&mut self, source: &str, error: &dyn std::fmt::Display) {
+        if self.failed() {
+            warn!("{} unavailable, checking less often: {}", source, error);
+        } else {
+            debug!("Failed to check {}: {}", source, error);
+        }
+    }
+
+    /// Count a working read, noting when the clipboard comes back
+    fn log_success(&mut self, source: &str) {
+        if self.succeeded() {
+            info!("{} available again", source);
+        }
     }
 }
 
-impl SystemClipboard {
-    pub async fn watch_changes_generic<F>(&self, callback: F) -> Result<()>
-    where
-        F: Fn(String) + Send + Sync + 'static,
-    {
-        let clipboard = Arc::clone(&self.context);
-        let last_content = Arc::clone(&self.last_content);
+async fn poll_system_clipboard(
+    clipboard: Arc<Mutex<ClipboardContext>>,
+    last_content: Arc<Mutex<String>>,
+    on_health: Arc<RwLock<Option<HealthCallback>>>,
+    callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+) {
+    let report = |health: ClipboardHealth| {
+        let on_health = on_health
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(on_health) = on_health.as_ref() {
+            on_health(health);
+        }
+    };
 
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_millis(500));
+    let mut health = PollHealth::default();
 
-            loop {
-                interval.tick().await;
+    loop {
+        tokio::time::sleep(health.next_delay()).await;
 
-                let current_content = {
-                    let mut ctx = clipboard.lock().await;
-                    match ctx.get_contents() {
-                        Ok(content) => content,
-                        Err(e) => {
-                            warn!("Failed to check clipboard: {}", e);
-                            continue;
+        let current_content = {
+            let mut ctx = clipboard.lock().await;
+            match ctx.get_contents() {
+                Ok(content) => content,
+                Err(e) => {
+                    let became_unavailable = health.failed();
+                    if became_unavailable {
+                        warn!("Clipboard unavailable, checking less often: {}", e);
+                        report(ClipboardHealth::Unavailable(e.to_string()));
+                    } else {
+                        debug!("Failed to check clipboard: {}", e);
+                    }
+
+                    // The connection to the display server may be gone for good, e.g.
+                    // after a compositor restart, so reconnect instead of retrying it
+                    if health.is_unavailable() {
+                        match ClipboardContext::new() {
+                            Ok(context) => *ctx = context,
+                            Err(e) => debug!("Failed to reconnect to the clipboard: {}", e),
                         }
                     }
-                };
+                    continue;
+                }
+            }
+        };
+        if health.succeeded() {
+            info!("Clipboard available again");
+            report(ClipboardHealth::Recovered);
+        }
 
-                let mut last = last_content.lock().await;
-                if current_content != *last && !current_content.is_empty() {
-                    *last = current_content.clone();
-                    drop(last);
+        let mut last = last_content.lock().await;
+        if current_content != *last && !current_content.is_empty() {
+            *last = current_content.clone();
+            drop(last);
 
-                    debug!("Clipboard changed: {} chars", current_content.len());
-                    callback(current_content);
-                }
+            debug!("Clipboard changed: {}", Redacted(&current_content));
+            callback(current_content);
+        }
+    }
+}
+
+/// How long a read is reused before the clipboard is asked again
+const CLIPBOARD_CACHE_TTL: Duration = Duration::from_millis(250);
+
+/// Clipboard wrapper that shares reads between the daemon's callers
+///
+/// Reads within the TTL return the cached content, and concurrent reads wait for a
+/// single backend call. Writes and watched changes refresh the cache and bump a
+/// change counter, so callers can tell whether anything changed without reading.
+pub struct ClipboardService {
+    inner: Arc<dyn ClipboardBackend>,
+    ttl: Duration,
+    cache: Arc<Mutex<Option<(String, Instant)>>>,
+    changes: Arc<AtomicU64>,
+}
+
+impl ClipboardService {
+    pub fn new(inner: Arc<dyn ClipboardBackend>) -> Self {
+        Self {
+            inner,
+            ttl: CLIPBOARD_CACHE_TTL,
+            cache: Arc::new(Mutex::new(None)),
+            changes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Reuse reads for `ttl`; zero disables the cache but still coalesces concurrent reads
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Number of writes and watched changes seen so far
+    pub fn change_count(&self) -> u64 {
+        self.changes.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait::async_trait]
+impl ClipboardManager for ClipboardService {
+    async fn get_contents(&self) -> Result<String> {
+        // Held across the read so callers arriving meanwhile reuse its result
+        let mut cache = self.cache.lock().await;
+        if let Some((content, read_at)) = cache.as_ref() {
+            if read_at.elapsed() < self.ttl {
+                return Ok(content.clone());
             }
-        });
+        }
+
+        let content = self.inner.get_contents().await?;
+        *cache = Some((content.clone(), Instant::now()));
+        Ok(content)
+    }
 
+    async fn set_contents(&self, content: &str) -> Result<()> {
+        let mut cache = self.cache.lock().await;
+        self.inner.set_contents(content).await?;
+        *cache = Some((content.to_owned(), Instant::now()));
+        self.changes.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
+
+    async fn is_concealed(&self) -> Result<bool> {
+        self.inner.is_concealed().await
+    }
+}
+
+#[async_trait::async_trait]
+impl ClipboardWatcher for ClipboardService {
+    async fn watch_changes(
+        &self,
+        callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+    ) -> Result<()> {
+        let cache = Arc::clone(&self.cache);
+        let changes = Arc::clone(&self.changes);
+
+        self.inner
+            .watch_changes(Box::new(move |content: String| {
+                // A read in progress holds the lock and will store fresh content itself
+                if let Ok(mut cache) = cache.try_lock() {
+                    *cache = Some((content.clone(), Instant::now()));
+                }
+                changes.fetch_add(1, Ordering::Relaxed);
+                callback(content);
+            }))
+            .await
+    }
+
+    fn set_health_callback(&self, on_health: HealthCallback) {
+        self.inner.set_health_callback(on_health)
+    }
 }
 
 #[cfg(target_os = "linux")]
 pub mod linux {
     use super::*;
+    use std::convert::Infallible;
     use std::env;
     use std::process::Command;
     use tokio::process::Command as TokioCommand;
 
+    /// First wait before `wl-paste --watch` is started again after it exits
+    const WL_PASTE_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+    /// A `wl-paste --watch` that ran this long is restarted without the wait having grown
+    const WL_PASTE_STABLE_AFTER: Duration = Duration::from_secs(60);
+
     #[derive(Debug, Clone)]
     pub enum WaylandClipboardType {
         Primary,
@@ -403,6 +841,18 @@ pub mod linux {
                 .unwrap_or(false)
     }
 
+    /// Whether the installed wl-paste can run a command on each change (wl-clipboard 2.0+)
+    pub fn has_wl_paste_watch() -> bool {
+        Command::new("wl-paste")
+            .arg("--help")
+            .output()
+            .map(|output| {
+                output.status.success()
+                    && String::from_utf8_lossy(&output.stdout).contains("--watch")
+            })
+            .unwrap_or(false)
+    }
+
     pub fn has_xclip() -> bool {
         Command::new("which")
             .arg("xclip")
@@ -638,18 +1088,71 @@ pub mod linux {
             Ok(Self { clipboard_type })
         }
 
-        async fn get_clipboard_contents(&self) -> Result<String> {
-            let selection_arg = match self.clipboard_type {
+        fn selection_arg(&self) -> &'static str {
+            match self.clipboard_type {
                 WaylandClipboardType::Primary => "--primary",
                 WaylandClipboardType::Clipboard => "--clipboard",
-            };
+            }
+        }
+
+        /// Report changes pushed by a single `wl-paste --watch` process until it exits or
+        /// fails to start, either of which ends in an error
+        async fn watch_with_wl_paste(
+            &self,
+            last_content: &Mutex<String>,
+            callback: &(dyn Fn(String) + Send + Sync),
+        ) -> Result<Infallible> {
+            use tokio::io::{AsyncBufReadExt, BufReader};
+
+            // `echo` prints one line per selection change, after which the new content is read
+            let mut child = TokioCommand::new("wl-paste")
+                .arg(self.selection_arg())
+                .arg("--watch")
+                .arg("echo")
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::null())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|e| PostError::from_command("wl-paste --watch", e))?;
+            let stdout = child.stdout.take().ok_or_else(|| {
+                PostError::Clipboard("wl-paste --watch has no stdout".to_string())
+            })?;
+            debug!("Watching the Wayland clipboard with wl-paste --watch");
+
+            let mut changes = BufReader::new(stdout).lines();
+            while changes.next_line().await?.is_some() {
+                let current_content = match self.get_clipboard_contents().await {
+                    Ok(content) => content,
+                    Err(e) => {
+                        debug!("Failed to read Wayland clipboard after a change: {}", e);
+                        continue;
+                    }
+                };
 
+                let mut last = last_content.lock().await;
+                if current_content != *last && !current_content.is_empty() {
+                    *last = current_content.clone();
+                    drop(last);
+
+                    debug!("Clipboard changed: {}", Redacted(&current_content));
+                    callback(current_content);
+                }
+            }
+
+            let status = child.wait().await?;
+            Err(PostError::Clipboard(format!(
+                "wl-paste --watch exited with {}",
+                status
+            )))
+        }
+
+        async fn get_clipboard_contents(&self) -> Result<String> {
             let output = TokioCommand::new("wl-paste")
-                .arg(selection_arg)
+                .arg(self.selection_arg())
                 .arg("--no-newline")
                 .output()
                 .await
-                .map_err(|e| PostError::Clipboard(format!("Failed to execute wl-paste: {}", e)))?;
+                .map_err(|e| PostError::from_command("wl-paste", e))?;
 
             if !output.status.success() {
                 // Empty clipboard is not an error - wl-paste exits with code 1 when clipboard is empty
@@ -665,18 +1168,13 @@ pub mod linux {
         }
 
         async fn set_clipboard_contents(&self, content: &str) -> Result<()> {
-            let selection_arg = match self.clipboard_type {
-                WaylandClipboardType::Primary => "--primary",
-                WaylandClipboardType::Clipboard => "--clipboard",
-            };
-
             let mut cmd = TokioCommand::new("wl-copy")
-                .arg(selection_arg)
+                .arg(self.selection_arg())
                 .arg("--type")
                 .arg("text/plain")
                 .stdin(std::process::Stdio::piped())
                 .spawn()
-                .map_err(|e| PostError::Clipboard(format!("Failed to execute wl-copy: {}", e)))?;
+                .map_err(|e| PostError::from_command("wl-copy", e))?;
 
             if let Some(stdin) = cmd.stdin.as_mut() {
                 use tokio::io::AsyncWriteExt;
@@ -701,8 +1199,8 @@ pub mod linux {
             }
 
             debug!(
-                "Set Wayland clipboard contents via wl-copy: {} chars",
-                content.len()
+                "Set Wayland clipboard contents via wl-copy: {}",
+                Redacted(content)
             );
             Ok(())
         }
@@ -740,7 +1238,7 @@ pub mod linux {
                 .arg("-o")
                 .output()
                 .await
-                .map_err(|e| PostError::Clipboard(format!("Failed to execute xclip: {}", e)))?;
+                .map_err(|e| PostError::from_command("xclip", e))?;
 
             if !output.status.success() {
                 // Empty clipboard is not an error - xclip exits with code 1 when clipboard is empty
@@ -762,7 +1260,7 @@ pub mod linux {
                 .arg("-i")
                 .stdin(std::process::Stdio::piped())
                 .spawn()
-                .map_err(|e| PostError::Clipboard(format!("Failed to execute xclip: {}", e)))?;
+                .map_err(|e| PostError::from_command("xclip", e))?;
 
             if let Some(stdin) = cmd.stdin.as_mut() {
                 use tokio::io::AsyncWriteExt;
@@ -787,8 +1285,8 @@ pub mod linux {
             }
 
             debug!(
-                "Set X11 clipboard contents via xclip: {} chars",
-                content.len()
+                "Set X11 clipboard contents via xclip: {}",
+                Redacted(content)
             );
             Ok(())
         }
@@ -819,10 +1317,10 @@ pub mod linux {
             let last_content = Arc::clone(&self.last_content);
 
             tokio::spawn(async move {
-                let mut interval = tokio::time::interval(std::time::Duration::from_millis(500));
+                let mut health = PollHealth::default();
 
                 loop {
-                    interval.tick().await;
+                    tokio::time::sleep(health.next_delay()).await;
 
                     let current_content = {
                         let output = TokioCommand::new("xclip")
@@ -836,12 +1334,22 @@ pub mod linux {
                             Ok(output) if output.status.success() => {
                                 String::from_utf8_lossy(&output.stdout).to_string()
                             }
-                            _ => {
-                                warn!("Failed to check clipboard via xclip");
+                            // xclip exits with code 1 when the clipboard is empty
+                            Ok(output) if output.status.code() == Some(1) => String::new(),
+                            Ok(output) => {
+                                health.log_failure(
+                                    "Clipboard via xclip",
+                                    &String::from_utf8_lossy(&output.stderr).trim(),
+                                );
+                                continue;
+                            }
+                            Err(e) => {
+                                health.log_failure("Clipboard via xclip", &e);
                                 continue;
                             }
                         }
                     };
+                    health.log_success("Clipboard via xclip");
 
                     let mut last = last_content.lock().await;
                     if current_content != *last && !current_content.is_empty() {
@@ -849,8 +1357,8 @@ pub mod linux {
                         drop(last);
 
                         debug!(
-                            "X11 clipboard changed via xclip: {} chars",
-                            current_content.len()
+                            "X11 clipboard changed via xclip: {}",
+                            Redacted(&current_content)
                         );
                         callback(current_content);
                     }
@@ -881,7 +1389,7 @@ pub mod linux {
                 .arg("--output")
                 .output()
                 .await
-                .map_err(|e| PostError::Clipboard(format!("Failed to execute xsel: {}", e)))?;
+                .map_err(|e| PostError::from_command("xsel", e))?;
 
             if !output.status.success() {
                 // Empty clipboard is not an error - xsel exits with code 1 when clipboard is empty
@@ -902,7 +1410,7 @@ pub mod linux {
                 .arg("--input")
                 .stdin(std::process::Stdio::piped())
                 .spawn()
-                .map_err(|e| PostError::Clipboard(format!("Failed to execute xsel: {}", e)))?;
+                .map_err(|e| PostError::from_command("xsel", e))?;
 
             if let Some(stdin) = cmd.stdin.as_mut() {
                 use tokio::io::AsyncWriteExt;
@@ -927,10 +1435,7 @@ pub mod linux {
                 )));
             }
 
-            debug!(
-                "Set X11 clipboard contents via xsel: {} chars",
-                content.len()
-            );
+            debug!("Set X11 clipboard contents via xsel: {}", Redacted(content));
             Ok(())
         }
     }
@@ -960,10 +1465,10 @@ pub mod linux {
             let last_content = Arc::clone(&self.last_content);
 
             tokio::spawn(async move {
-                let mut interval = tokio::time::interval(std::time::Duration::from_millis(500));
+                let mut health = PollHealth::default();
 
                 loop {
-                    interval.tick().await;
+                    tokio::time::sleep(health.next_delay()).await;
 
                     let current_content = {
                         let output = TokioCommand::new("xsel")
@@ -976,12 +1481,22 @@ pub mod linux {
                             Ok(output) if output.status.success() => {
                                 String::from_utf8_lossy(&output.stdout).to_string()
                             }
-                            _ => {
-                                warn!("Failed to check clipboard via xsel");
+                            // xsel exits with code 1 when the clipboard is empty
+                            Ok(output) if output.status.code() == Some(1) => String::new(),
+                            Ok(output) => {
+                                health.log_failure(
+                                    "Clipboard via xsel",
+                                    &String::from_utf8_lossy(&output.stderr).trim(),
+                                );
+                                continue;
+                            }
+                            Err(e) => {
+                                health.log_failure("Clipboard via xsel", &e);
                                 continue;
                             }
                         }
                     };
+                    health.log_success("Clipboard via xsel");
 
                     let mut last = last_content.lock().await;
                     if current_content != *last && !current_content.is_empty() {
@@ -989,8 +1504,8 @@ pub mod linux {
                         drop(last);
 
                         debug!(
-                            "X11 clipboard changed via xsel: {} chars",
-                            current_content.len()
+                            "X11 clipboard changed via xsel: {}",
+                            Redacted(&current_content)
                         );
                         callback(current_content);
                     }
@@ -1062,7 +1577,7 @@ pub mod linux {
                     content.len(),
                     self.config.max_content_size
                 );
-                Ok(content.chars().take(self.config.max_content_size).collect())
+                Ok(crate::text::truncate_bytes(&content, self.config.max_content_size).to_string())
             } else {
                 Ok(content)
             }
@@ -1096,18 +1611,256 @@ pub mod linux {
                 }
             }
 
-            system_result
+            system_result
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ClipboardManager for HybridLinuxClipboard {
+        async fn get_contents(&self) -> Result<String> {
+            self.get_preferred_contents().await
+        }
+
+        async fn set_contents(&self, content: &str) -> Result<()> {
+            self.set_preferred_contents(content).await?;
+
+            let mut last = self.last_content.lock().await;
+            *last = content.to_owned();
+
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ClipboardWatcher for HybridLinuxClipboard {
+        async fn watch_changes(
+            &self,
+            callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+        ) -> Result<()> {
+            let wayland_clipboard = self.wayland_clipboard.clone();
+            let system_clipboard = Arc::clone(&self.system_clipboard.context);
+            let last_content = Arc::clone(&self.last_content);
+
+            let poll_interval = self.config.poll_interval();
+            tokio::spawn(async move {
+                // Let wl-paste push changes where it can, polling only if it is too old or
+                // can't be run
+                if let Some(ref wayland_cb) = wayland_clipboard {
+                    if tokio::task::spawn_blocking(has_wl_paste_watch)
+                        .await
+                        .unwrap_or(false)
+                    {
+                        let mut restarts = PollHealth::every(WL_PASTE_RESTART_DELAY);
+                        loop {
+                            let started = Instant::now();
+                            let Err(e) = wayland_cb
+                                .watch_with_wl_paste(&last_content, callback.as_ref())
+                                .await;
+                            if matches!(
+                                e,
+                                PostError::BackendUnavailable(_) | PostError::BackendMissing(_)
+                            ) {
+                                warn!("Falling back to polling the Wayland clipboard: {}", e);
+                                break;
+                            }
+                            // Only quick exits in a row stretch the delay
+                            if started.elapsed() >= WL_PASTE_STABLE_AFTER {
+                                restarts.succeeded();
+                            }
+                            restarts.failed();
+                            let delay = restarts.next_delay();
+                            warn!("{}, restarting it in {:?}", e, delay);
+                            tokio::time::sleep(delay).await;
+                        }
+                    } else {
+                        debug!("wl-paste does not support --watch, polling the clipboard");
+                    }
+                }
+
+                let mut health = PollHealth::every(poll_interval);
+
+                loop {
+                    tokio::time::sleep(health.next_delay()).await;
+
+                    // Try Wayland clipboard first if available
+                    let current_content = if let Some(ref wayland_cb) = wayland_clipboard {
+                        match wayland_cb.get_contents().await as Result<String> {
+                            Ok(content) => content,
+                            Err(_) => {
+                                // Fall back to system clipboard
+                                let mut ctx = system_clipboard.lock().await;
+                                match ctx.get_contents() {
+                                    Ok(content) => content,
+                                    Err(e) => {
+                                        health.log_failure("Wayland and X11 clipboards", &e);
+                                        continue;
+                                    }
+                                }
+                            }
+                        }
+                    } else {
+                        // Use system clipboard only
+                        let mut ctx = system_clipboard.lock().await;
+                        match ctx.get_contents() {
+                            Ok(content) => content,
+                            Err(e) => {
+                                health.log_failure("Clipboard", &e);
+                                continue;
+                            }
+                        }
+                    };
+                    health.log_success("Clipboard");
+
+                    let mut last = last_content.lock().await;
+                    if current_content != *last && !current_content.is_empty() {
+                        *last = current_content.clone();
+                        drop(last);
+
+                        debug!("Clipboard changed: {}", Redacted(&current_content));
+                        callback(current_content);
+                    }
+                }
+            });
+
+            Ok(())
+        }
+    }
+
+    /// Which of a WSLg session's two clipboards changed since they were last compared
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Newest {
+        Linux,
+        Windows,
+        Unchanged,
+    }
+
+    /// Last contents seen on each side of a WSLg session, to tell which one changed
+    #[derive(Debug, Default)]
+    struct CoherenceState {
+        linux: String,
+        /// Kept without trailing whitespace, which PowerShell strips when reading
+        windows: String,
+        current: String,
+    }
+
+    impl CoherenceState {
+        /// Record both sides' contents and return the side holding the newest; Linux wins
+        /// if both changed
+        fn observe(&mut self, linux: String, windows: String) -> Newest {
+            let newest = if linux != self.linux {
+                self.current = linux.clone();
+                Newest::Linux
+            } else if windows.trim_end() != self.windows {
+                self.current = windows.clone();
+                Newest::Windows
+            } else {
+                Newest::Unchanged
+            };
+            self.linux = linux;
+            self.windows = windows.trim_end().to_string();
+            newest
+        }
+
+        /// Record content now present on both sides
+        fn written(&mut self, content: &str) {
+            self.linux = content.to_string();
+            self.windows = content.trim_end().to_string();
+            self.current = content.to_string();
+        }
+    }
+
+    /// The Wayland and Windows clipboards of a WSLg session, kept in step
+    struct Coherence {
+        wayland: WaylandClipboard,
+        windows: super::windows::WSLClipboard,
+        state: Mutex<CoherenceState>,
+    }
+
+    impl Coherence {
+        /// Read both clipboards and copy whichever changed to the other, returning the side
+        /// that changed and the newest content
+        async fn refresh(&self) -> (Newest, String) {
+            let linux = self.wayland.get_contents().await;
+            let windows = self.windows.get_contents().await;
+
+            let mut state = self.state.lock().await;
+            let linux = linux.unwrap_or_else(|e| {
+                debug!("Failed to read the Wayland clipboard: {}", e);
+                state.linux.clone()
+            });
+            let windows = windows.unwrap_or_else(|e| {
+                debug!("Failed to read the Windows clipboard: {}", e);
+                state.windows.clone()
+            });
+            let newest = state.observe(linux, windows);
+            let current = state.current.clone();
+            drop(state);
+
+            let mirrored = match newest {
+                Newest::Linux => self.windows.set_contents(&current).await,
+                Newest::Windows => self.wayland.set_contents(&current).await,
+                Newest::Unchanged => return (newest, current),
+            };
+            match mirrored {
+                Ok(()) => self.state.lock().await.written(&current),
+                Err(e) => debug!("Failed to mirror the WSLg clipboard: {}", e),
+            }
+            (newest, current)
+        }
+    }
+
+    /// Clipboard for WSL with WSLg: content is written to both the Linux (Wayland) and the
+    /// Windows clipboard, and whichever changed last is read, so Linux and Windows apps in
+    /// the session see the same thing
+    pub struct WslgClipboard {
+        coherence: Arc<Coherence>,
+        last_content: Arc<Mutex<String>>,
+        poll_interval: Duration,
+    }
+
+    impl WslgClipboard {
+        pub fn new_with_config(config: &ClipboardConfig) -> Result<Self> {
+            if !super::windows::is_wsl_environment() {
+                return Err(create_contextual_error(
+                    "WSLg clipboard requested but not running in WSL environment",
+                ));
+            }
+
+            Ok(Self {
+                coherence: Arc::new(Coherence {
+                    wayland: WaylandClipboard::new(WaylandClipboardType::Clipboard)?,
+                    windows: super::windows::WSLClipboard::new_with_config(config)?,
+                    state: Mutex::new(CoherenceState::default()),
+                }),
+                last_content: Arc::new(Mutex::new(String::new())),
+                // Reading the Windows side is as slow as under WSL alone
+                poll_interval: config.wsl_poll_interval(),
+            })
         }
     }
 
     #[async_trait::async_trait]
-    impl ClipboardManager for HybridLinuxClipboard {
+    impl ClipboardManager for WslgClipboard {
         async fn get_contents(&self) -> Result<String> {
-            self.get_preferred_contents().await
+            Ok(self.coherence.refresh().await.1)
         }
 
         async fn set_contents(&self, content: &str) -> Result<()> {
-            self.set_preferred_contents(content).await?;
+            let linux = self.coherence.wayland.set_contents(content).await;
+            let windows = self.coherence.windows.set_contents(content).await;
+            match (linux, windows) {
+                (Err(linux), Err(windows)) => {
+                    return Err(PostError::Clipboard(format!(
+                        "Failed to set both WSLg clipboards: {}; {}",
+                        linux, windows
+                    )));
+                }
+                (Err(e), Ok(())) | (Ok(()), Err(e)) => {
+                    warn!("Set only one of the WSLg clipboards: {}", e);
+                }
+                (Ok(()), Ok(())) => {}
+            }
+            self.coherence.state.lock().await.written(content);
 
             let mut last = self.last_content.lock().await;
             *last = content.to_owned();
@@ -1117,57 +1870,32 @@ pub mod linux {
     }
 
     #[async_trait::async_trait]
-    impl ClipboardWatcher for HybridLinuxClipboard {
+    impl ClipboardWatcher for WslgClipboard {
         async fn watch_changes(
             &self,
             callback: Box<dyn Fn(String) + Send + Sync + 'static>,
         ) -> Result<()> {
-            let wayland_clipboard = self.wayland_clipboard.clone();
-            let system_clipboard = Arc::clone(&self.system_clipboard.context);
+            let coherence = Arc::clone(&self.coherence);
             let last_content = Arc::clone(&self.last_content);
 
-            let poll_interval = self.config.poll_interval_ms;
+            let poll_interval = self.poll_interval;
             tokio::spawn(async move {
-                let mut interval =
-                    tokio::time::interval(std::time::Duration::from_millis(poll_interval));
+                let mut interval = tokio::time::interval(poll_interval);
 
                 loop {
                     interval.tick().await;
 
-                    // Try Wayland clipboard first if available
-                    let current_content = if let Some(ref wayland_cb) = wayland_clipboard {
-                        match wayland_cb.get_contents().await as Result<String> {
-                            Ok(content) => content,
-                            Err(_) => {
-                                // Fall back to system clipboard
-                                let mut ctx = system_clipboard.lock().await;
-                                match ctx.get_contents() {
-                                    Ok(content) => content,
-                                    Err(e) => {
-                                        warn!("Failed to check both clipboards: {}", e);
-                                        continue;
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        // Use system clipboard only
-                        let mut ctx = system_clipboard.lock().await;
-                        match ctx.get_contents() {
-                            Ok(content) => content,
-                            Err(e) => {
-                                warn!("Failed to check clipboard: {}", e);
-                                continue;
-                            }
-                        }
-                    };
+                    let (newest, current_content) = coherence.refresh().await;
+                    if newest == Newest::Unchanged {
+                        continue;
+                    }
 
                     let mut last = last_content.lock().await;
                     if current_content != *last && !current_content.is_empty() {
                         *last = current_content.clone();
                         drop(last);
 
-                        debug!("Clipboard changed: {} chars", current_content.len());
+                        debug!("WSLg clipboard changed: {}", Redacted(&current_content));
                         callback(current_content);
                     }
                 }
@@ -1176,6 +1904,39 @@ pub mod linux {
             Ok(())
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_coherence_reads_the_side_that_changed() {
+            let mut state = CoherenceState::default();
+            assert_eq!(
+                state.observe("linux".to_string(), String::new()),
+                Newest::Linux
+            );
+
+            // PowerShell drops the trailing newline of mirrored content
+            state.written("line\n");
+            assert_eq!(
+                state.observe("line\n".to_string(), "line".to_string()),
+                Newest::Unchanged
+            );
+
+            assert_eq!(
+                state.observe("line\n".to_string(), "windows".to_string()),
+                Newest::Windows
+            );
+            assert_eq!(state.current, "windows");
+
+            assert_eq!(
+                state.observe("both".to_string(), "changed".to_string()),
+                Newest::Linux
+            );
+            assert_eq!(state.current, "both");
+        }
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -1234,7 +1995,8 @@ pub mod macos {
     }
 }
 
-#[cfg(target_os = "windows")]
+// Also built on Linux, which is what runs inside WSL
+#[cfg(any(target_os = "windows", target_os = "linux"))]
 pub mod windows {
     use super::*;
     use std::env;
@@ -1279,16 +2041,33 @@ pub mod windows {
             .unwrap_or(false)
     }
 
+    /// Checked once per process, since each check starts PowerShell
     pub fn is_powershell_available() -> bool {
-        // Check if PowerShell is available for advanced clipboard operations
-        Command::new("powershell.exe")
-            .arg("-Command")
-            .arg("Get-Clipboard")
-            .arg("-Format")
-            .arg("Text")
-            .output()
-            .map(|output| output.status.success())
-            .unwrap_or(false)
+        static AVAILABLE: OnceLock<bool> = OnceLock::new();
+        *AVAILABLE.get_or_init(|| {
+            // Check if PowerShell is available for advanced clipboard operations
+            Command::new("powershell.exe")
+                .arg("-Command")
+                .arg("Get-Clipboard")
+                .arg("-Format")
+                .arg("Text")
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        })
+    }
+
+    /// Whether win32yank.exe, which reads and writes the Windows clipboard without
+    /// starting PowerShell, is on the PATH
+    pub fn has_win32yank() -> bool {
+        static AVAILABLE: OnceLock<bool> = OnceLock::new();
+        *AVAILABLE.get_or_init(|| {
+            Command::new("which")
+                .arg("win32yank.exe")
+                .output()
+                .map(|output| output.status.success())
+                .unwrap_or(false)
+        })
     }
 
     /// Provides detailed diagnostics for clipboard issues in Windows/WSL environments
@@ -1298,6 +2077,7 @@ pub mod windows {
         // Environment detection
         diagnostics.push(format!("WSL environment: {}", is_wsl_environment()));
         diagnostics.push(format!("clip.exe available: {}", is_clip_exe_available()));
+        diagnostics.push(format!("win32yank.exe available: {}", has_win32yank()));
         diagnostics.push(format!(
             "PowerShell available: {}",
             is_powershell_available()
@@ -1362,81 +2142,191 @@ pub mod windows {
         PostError::Clipboard(context)
     }
 
+    /// Prints the clipboard as one line of UTF-8 hex, so any content survives the console
+    const READ_CLIPBOARD_SCRIPT: &str =
+        "$b = [Text.Encoding]::UTF8.GetBytes([string](Get-Clipboard -Raw)); \
+        [Console]::Out.WriteLine([BitConverter]::ToString($b).Replace('-', '')); \
+        [Console]::Out.Flush()\n";
+
+    /// Longest wait for the PowerShell helper to answer before it is restarted
+    const HELPER_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A long-lived powershell.exe answering clipboard reads, so polls don't each pay
+    /// for starting PowerShell
+    struct PowerShellReader {
+        _child: tokio::process::Child,
+        stdin: tokio::process::ChildStdin,
+        stdout: tokio::io::Lines<tokio::io::BufReader<tokio::process::ChildStdout>>,
+    }
+
+    impl PowerShellReader {
+        fn spawn() -> Result<Self> {
+            let mut child = TokioCommand::new("powershell.exe")
+                .args(["-NoProfile", "-NoLogo", "-NonInteractive", "-Command", "-"])
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::null())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|e| PostError::from_command("PowerShell helper", e))?;
+            let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+                return Err(PostError::Clipboard(
+                    "PowerShell helper has no stdio".to_string(),
+                ));
+            };
+            debug!("Started PowerShell helper for WSL clipboard reads");
+
+            use tokio::io::AsyncBufReadExt;
+            Ok(Self {
+                _child: child,
+                stdin,
+                stdout: tokio::io::BufReader::new(stdout).lines(),
+            })
+        }
+
+        async fn read(&mut self) -> Result<String> {
+            use tokio::io::AsyncWriteExt;
+            self.stdin
+                .write_all(READ_CLIPBOARD_SCRIPT.as_bytes())
+                .await?;
+            self.stdin.flush().await?;
+
+            let line = tokio::time::timeout(HELPER_TIMEOUT, self.stdout.next_line())
+                .await
+                .map_err(|_| PostError::Clipboard("PowerShell helper timed out".to_string()))??
+                .ok_or_else(|| PostError::Clipboard("PowerShell helper exited".to_string()))?;
+            let bytes = decode_hex(line.trim()).ok_or_else(|| {
+                PostError::Clipboard("PowerShell helper sent malformed output".to_string())
+            })?;
+            let content = String::from_utf8(bytes)
+                .map_err(|e| PostError::Clipboard(format!("Invalid UTF-8 in clipboard: {}", e)))?;
+
+            // Match Get-Clipboard without -Raw, which drops the trailing newline
+            Ok(content.trim_end().to_string())
+        }
+    }
+
+    fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+
+    /// Read the Windows clipboard with win32yank.exe if installed, otherwise through the
+    /// PowerShell helper in `reader`, started on first use and again after it fails
+    async fn read_windows_clipboard(reader: &Mutex<Option<PowerShellReader>>) -> Result<String> {
+        if has_win32yank() {
+            let output = TokioCommand::new("win32yank.exe")
+                .arg("-o")
+                .arg("--lf")
+                .output()
+                .await
+                .map_err(|e| PostError::from_command("win32yank.exe", e))?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(PostError::Clipboard(format!(
+                    "win32yank.exe failed: {}",
+                    stderr
+                )));
+            }
+            return String::from_utf8(output.stdout)
+                .map_err(|e| PostError::Clipboard(format!("Invalid UTF-8 in clipboard: {}", e)));
+        }
+
+        if !is_powershell_available() {
+            return Err(PostError::Clipboard(
+                "Cannot read from clipboard: neither win32yank.exe nor PowerShell available"
+                    .to_string(),
+            ));
+        }
+
+        let mut reader = reader.lock().await;
+        let mut helper = match reader.take() {
+            Some(helper) => helper,
+            None => PowerShellReader::spawn()?,
+        };
+        let content = helper.read().await?;
+        *reader = Some(helper);
+        Ok(content)
+    }
+
     pub struct WSLClipboard {
         last_content: Arc<Mutex<String>>,
+        reader: Arc<Mutex<Option<PowerShellReader>>>,
+        poll_interval: Duration,
     }
 
     impl WSLClipboard {
         pub fn new() -> Result<Self> {
+            Self::new_with_config(&ClipboardConfig::default())
+        }
+
+        pub fn new_with_config(config: &ClipboardConfig) -> Result<Self> {
             if !is_wsl_environment() {
                 return Err(create_contextual_error(
                     "WSL clipboard requested but not running in WSL environment",
                 ));
             }
 
-            if !is_clip_exe_available() && !is_powershell_available() {
+            if !has_win32yank() && !is_clip_exe_available() && !is_powershell_available() {
                 return Err(create_contextual_error(
-                    "Neither clip.exe nor PowerShell are available for WSL clipboard operations",
+                    "None of win32yank.exe, clip.exe or PowerShell are available for WSL clipboard operations",
                 ));
             }
 
             Ok(Self {
                 last_content: Arc::new(Mutex::new(String::new())),
+                reader: Arc::new(Mutex::new(None)),
+                poll_interval: config.wsl_poll_interval(),
             })
         }
 
         async fn get_clipboard_contents(&self) -> Result<String> {
-            // Prefer PowerShell for getting clipboard contents as it handles Unicode better
-            if is_powershell_available() {
-                let output = TokioCommand::new("powershell.exe")
-                    .arg("-Command")
-                    .arg("Get-Clipboard")
-                    .arg("-Format")
-                    .arg("Text")
-                    .output()
-                    .await
-                    .map_err(|e| {
-                        PostError::Clipboard(format!(
-                            "Failed to execute PowerShell Get-Clipboard: {}",
-                            e
-                        ))
-                    })?;
+            read_windows_clipboard(&self.reader).await
+        }
 
-                if !output.status.success() {
-                    // Empty clipboard is not an error
-                    if output.status.code() == Some(1) {
-                        return Ok(String::new());
-                    }
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    return Err(PostError::Clipboard(format!(
-                        "PowerShell Get-Clipboard failed: {}",
-                        stderr
-                    )));
+        async fn set_clipboard_contents(&self, content: &str) -> Result<()> {
+            if has_win32yank() {
+                let mut cmd = TokioCommand::new("win32yank.exe")
+                    .arg("-i")
+                    .arg("--crlf")
+                    .stdin(std::process::Stdio::piped())
+                    .spawn()
+                    .map_err(|e| PostError::from_command("win32yank.exe", e))?;
+
+                if let Some(mut stdin) = cmd.stdin.take() {
+                    use tokio::io::AsyncWriteExt;
+                    stdin.write_all(content.as_bytes()).await.map_err(|e| {
+                        PostError::Clipboard(format!("Failed to write to win32yank.exe: {}", e))
+                    })?;
                 }
 
-                let content = String::from_utf8(output.stdout).map_err(|e| {
-                    PostError::Clipboard(format!("Invalid UTF-8 in clipboard: {}", e))
+                let status = cmd.wait().await.map_err(|e| {
+                    PostError::Clipboard(format!("Failed to wait for win32yank.exe: {}", e))
                 })?;
 
-                // Remove trailing newline that PowerShell adds
-                Ok(content.trim_end().to_string())
-            } else {
-                // Fallback: try to use clip.exe in a roundabout way (though it's primarily for setting)
-                Err(PostError::Clipboard(
-                    "Cannot read from clipboard: PowerShell not available".to_string(),
-                ))
-            }
-        }
+                if !status.success() {
+                    return Err(PostError::Clipboard(format!(
+                        "win32yank.exe failed with exit code: {:?}",
+                        status.code()
+                    )));
+                }
 
-        async fn set_clipboard_contents(&self, content: &str) -> Result<()> {
-            // Use clip.exe for setting clipboard contents
-            if is_clip_exe_available() {
+                debug!(
+                    "Set WSL clipboard contents via win32yank.exe: {}",
+                    Redacted(content)
+                );
+                Ok(())
+            } else if is_clip_exe_available() {
+                // Use clip.exe for setting clipboard contents
                 let mut cmd = TokioCommand::new("clip.exe")
                     .stdin(std::process::Stdio::piped())
                     .spawn()
-                    .map_err(|e| {
-                        PostError::Clipboard(format!("Failed to execute clip.exe: {}", e))
-                    })?;
+                    .map_err(|e| PostError::from_command("clip.exe", e))?;
 
                 if let Some(stdin) = cmd.stdin.as_mut() {
                     use tokio::io::AsyncWriteExt;
@@ -1460,8 +2350,8 @@ pub mod windows {
                 }
 
                 debug!(
-                    "Set WSL clipboard contents via clip.exe: {} chars",
-                    content.len()
+                    "Set WSL clipboard contents via clip.exe: {}",
+                    Redacted(content)
                 );
                 Ok(())
             } else if is_powershell_available() {
@@ -1472,12 +2362,7 @@ pub mod windows {
                     .arg("-Value")
                     .arg(content)
                     .spawn()
-                    .map_err(|e| {
-                        PostError::Clipboard(format!(
-                            "Failed to execute PowerShell Set-Clipboard: {}",
-                            e
-                        ))
-                    })?;
+                    .map_err(|e| PostError::from_command("PowerShell Set-Clipboard", e))?;
 
                 let status = cmd.wait().await.map_err(|e| {
                     PostError::Clipboard(format!("Failed to wait for PowerShell: {}", e))
@@ -1491,8 +2376,8 @@ pub mod windows {
                 }
 
                 debug!(
-                    "Set WSL clipboard contents via PowerShell: {} chars",
-                    content.len()
+                    "Set WSL clipboard contents via PowerShell: {}",
+                    Redacted(content)
                 );
                 Ok(())
             } else {
@@ -1526,45 +2411,30 @@ pub mod windows {
             callback: Box<dyn Fn(String) + Send + Sync + 'static>,
         ) -> Result<()> {
             let last_content = Arc::clone(&self.last_content);
+            let reader = Arc::clone(&self.reader);
 
+            let poll_interval = self.poll_interval;
             tokio::spawn(async move {
-                let mut interval = tokio::time::interval(std::time::Duration::from_millis(1000)); // Slightly slower for WSL
+                let mut health = PollHealth::every(poll_interval);
 
                 loop {
-                    interval.tick().await;
-
-                    // Get current clipboard content
-                    let current_content = if is_powershell_available() {
-                        let output = TokioCommand::new("powershell.exe")
-                            .arg("-Command")
-                            .arg("Get-Clipboard")
-                            .arg("-Format")
-                            .arg("Text")
-                            .output()
-                            .await;
+                    tokio::time::sleep(health.next_delay()).await;
 
-                        match output {
-                            Ok(output) if output.status.success() => {
-                                String::from_utf8_lossy(&output.stdout)
-                                    .trim_end()
-                                    .to_string()
-                            }
-                            _ => {
-                                warn!("Failed to check WSL clipboard via PowerShell");
-                                continue;
-                            }
+                    let current_content = match read_windows_clipboard(&reader).await {
+                        Ok(content) => content,
+                        Err(e) => {
+                            health.log_failure("WSL clipboard", &e);
+                            continue;
                         }
-                    } else {
-                        warn!("Cannot watch WSL clipboard: PowerShell not available");
-                        continue;
                     };
+                    health.log_success("WSL clipboard");
 
                     let mut last = last_content.lock().await;
                     if current_content != *last && !current_content.is_empty() {
                         *last = current_content.clone();
                         drop(last);
 
-                        debug!("WSL clipboard changed: {} chars", current_content.len());
+                        debug!("WSL clipboard changed: {}", Redacted(&current_content));
                         callback(current_content);
                     }
                 }
@@ -1573,4 +2443,246 @@ pub mod windows {
             Ok(())
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_helper_output_is_decoded_from_hex() {
+            assert_eq!(decode_hex("68C3A9").unwrap(), "hé".as_bytes());
+            assert_eq!(decode_hex("").unwrap(), Vec::<u8>::new());
+            assert!(decode_hex("6").is_none());
+            assert!(decode_hex("zz").is_none());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockClipboard;
+
+    struct NullClipboard;
+
+    #[async_trait::async_trait]
+    impl ClipboardManager for NullClipboard {
+        async fn get_contents(&self) -> Result<String> {
+            Ok(String::new())
+        }
+
+        async fn set_contents(&self, _content: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ClipboardWatcher for NullClipboard {
+        async fn watch_changes(
+            &self,
+            _callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Holds nothing but a password manager's marker
+    struct ConcealedClipboard;
+
+    #[async_trait::async_trait]
+    impl ClipboardManager for ConcealedClipboard {
+        async fn get_contents(&self) -> Result<String> {
+            Ok("hunter2".to_string())
+        }
+
+        async fn set_contents(&self, _content: &str) -> Result<()> {
+            Ok(())
+        }
+
+        async fn is_concealed(&self) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ClipboardWatcher for ConcealedClipboard {
+        async fn watch_changes(
+            &self,
+            _callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn null_backend(name: &str) -> ClipboardBackendFactory {
+        ClipboardBackendFactory::new(name, |_| Ok(Box::new(NullClipboard)))
+    }
+
+    fn config_with_backend(backend: &str) -> ClipboardConfig {
+        ClipboardConfig {
+            backend: backend.to_string(),
+            ..ClipboardConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_auto_selection_prefers_highest_available_priority() {
+        let mut registry = ClipboardRegistry::new();
+        registry.register(null_backend("low").with_priority(1));
+        registry.register(null_backend("high").with_priority(10));
+        registry.register(
+            null_backend("unavailable")
+                .with_priority(100)
+                .with_availability(|_| false),
+        );
+        registry.register(null_backend("manual").with_priority(1000).manual_only());
+
+        let selected = registry
+            .select(&config_with_backend("auto"), false)
+            .unwrap();
+        assert_eq!(selected.name(), "high");
+    }
+
+    #[test]
+    fn test_auto_selection_for_watching_skips_backends_without_watch() {
+        let mut registry = ClipboardRegistry::new();
+        registry.register(null_backend("read-write").with_priority(10));
+        registry.register(null_backend("watching").with_watcher(|_| Ok(Box::new(NullClipboard))));
+
+        let selected = registry.select(&config_with_backend("auto"), true).unwrap();
+        assert_eq!(selected.name(), "watching");
+        assert!(selected.capabilities().watch);
+        assert!(!selected.capabilities().images);
+    }
+
+    #[test]
+    fn test_forced_backend_is_used_even_if_not_auto_selectable() {
+        let mut registry = ClipboardRegistry::new();
+        registry.register(null_backend("default").with_priority(10));
+        registry.register(null_backend("manual").manual_only());
+
+        let selected = registry
+            .select(&config_with_backend("manual"), false)
+            .unwrap();
+        assert_eq!(selected.name(), "manual");
+
+        assert!(registry
+            .select(&config_with_backend("manual"), true)
+            .is_err());
+    }
+
+    #[test]
+    fn test_forced_unavailable_backend_is_an_error() {
+        let mut registry = ClipboardRegistry::new();
+        registry.register(null_backend("fallback"));
+        registry.register(null_backend("missing").with_availability(|_| false));
+
+        assert!(registry
+            .select(&config_with_backend("missing"), false)
+            .is_err());
+        assert_eq!(
+            registry
+                .select(&config_with_backend("does-not-exist"), false)
+                .unwrap()
+                .name(),
+            "fallback"
+        );
+    }
+
+    #[test]
+    fn test_register_replaces_backend_with_same_name() {
+        let mut registry = ClipboardRegistry::new();
+        registry.register(null_backend("custom").with_priority(1));
+        registry.register(null_backend("custom").with_priority(5));
+
+        assert_eq!(registry.backends().count(), 1);
+        assert_eq!(registry.get("custom").unwrap().priority(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_shared_backends_report_their_secret_marker() {
+        let backend: Arc<dyn ClipboardBackend> = Arc::new(ConcealedClipboard);
+        assert!(ClipboardManager::is_concealed(&backend).await.unwrap());
+
+        let combined = CombinedClipboard {
+            manager: Box::new(Arc::clone(&backend)),
+            watcher: Box::new(backend),
+        };
+        assert!(combined.is_concealed().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_clipboard_service_caches_reads_until_a_change() {
+        let mock = MockClipboard::new();
+        let service = ClipboardService::new(Arc::new(mock.clone())).with_ttl(Duration::MAX);
+        service.watch_changes(Box::new(|_| {})).await.unwrap();
+
+        mock.set_contents("first").await.unwrap();
+        assert_eq!(service.get_contents().await.unwrap(), "first");
+
+        // Programmatic writes to the backend aren't seen until the cache expires
+        mock.set_contents("unseen").await.unwrap();
+        assert_eq!(service.get_contents().await.unwrap(), "first");
+
+        mock.simulate_copy("copied");
+        assert_eq!(service.get_contents().await.unwrap(), "copied");
+        service.set_contents("written").await.unwrap();
+        assert_eq!(mock.contents(), "written");
+        assert_eq!(service.change_count(), 2);
+
+        let uncached = ClipboardService::new(Arc::new(mock.clone())).with_ttl(Duration::ZERO);
+        mock.set_contents("fresh").await.unwrap();
+        assert_eq!(uncached.get_contents().await.unwrap(), "fresh");
+    }
+
+    #[test]
+    fn test_poll_health_backs_off_and_reports_transitions_once() {
+        let mut health = PollHealth::default();
+        assert_eq!(health.next_delay(), POLL_INTERVAL);
+
+        assert!(!health.failed());
+        assert!(!health.failed());
+        assert!(health.failed());
+        assert!(!health.failed());
+        assert_eq!(health.next_delay(), POLL_INTERVAL * 16);
+        for _ in 0..20 {
+            health.failed();
+        }
+        assert_eq!(health.next_delay(), MAX_POLL_BACKOFF);
+
+        assert!(health.succeeded());
+        assert!(!health.succeeded());
+        assert_eq!(health.next_delay(), POLL_INTERVAL);
+
+        let mut wsl = PollHealth::every(Duration::from_secs(2));
+        assert_eq!(wsl.next_delay(), Duration::from_secs(2));
+        wsl.failed();
+        assert_eq!(wsl.next_delay(), Duration::from_secs(4));
+    }
+
+    #[tokio::test]
+    async fn test_create_backend_uses_the_configured_backend() {
+        let mock = MockClipboard::new();
+        mock.simulate_copy("from mock");
+        let mut registry = ClipboardRegistry::new();
+        registry.register(
+            ClipboardBackendFactory::new_watching("mock", move |_| Ok(Arc::new(mock.clone())))
+                .manual_only(),
+        );
+        registry.register(
+            null_backend("split")
+                .with_watcher(|_| Ok(Box::new(NullClipboard)))
+                .with_priority(10),
+        );
+
+        let backend = registry
+            .create_backend(&config_with_backend("mock"))
+            .unwrap();
+        assert_eq!(backend.get_contents().await.unwrap(), "from mock");
+
+        let backend = registry
+            .create_backend(&config_with_backend("auto"))
+            .unwrap();
+        assert_eq!(backend.get_contents().await.unwrap(), "");
+    }
 }
@@ -0,0 +1,150 @@
+//! How builds of different versions stay in sync with each other
+//!
+//! The protocol only grows in ways older builds can live with:
+//!
+//! - A new field has a default and is left off the wire while it holds it, so messages
+//!   that don't use it encode, and are signed, exactly as before. Fields a build doesn't
+//!   know are ignored when decoding, but they are covered by the signature, so a message
+//!   carrying one is only sent to peers that advertise the [`Capability`] it came with.
+//! - A new message type comes with a capability as well. Peers that advertise it are sent
+//!   the message; older ones are skipped, and ignore such messages if they get one anyway.
+//!
+//! `tests/protocol_compat.rs` pins messages as earlier builds sent them, so a change that
+//! would make them fail to decode or verify is caught.
+
+use crate::MessageData;
+
+/// Version of this build, advertised to peers in node discovery
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// An optional feature a peer may or may not support
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Encryption,
+    Compression,
+    Images,
+    Pins,
+    Taildrop,
+    Ping,
+    Collect,
+    /// Clipboard updates carrying [`crate::ClipboardData::metadata`]
+    Metadata,
+    Bench,
+}
+
+impl Capability {
+    /// Features this build supports; the others are named so newer peers' flags are understood
+    pub const SUPPORTED: [Capability; 7] = [
+        Capability::Encryption,
+        Capability::Pins,
+        Capability::Taildrop,
+        Capability::Ping,
+        Capability::Collect,
+        Capability::Metadata,
+        Capability::Bench,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::Encryption => "encryption",
+            Capability::Compression => "compression",
+            Capability::Images => "images",
+            Capability::Pins => "pins",
+            Capability::Taildrop => "taildrop",
+            Capability::Ping => "ping",
+            Capability::Collect => "collect",
+            Capability::Metadata => "metadata",
+            Capability::Bench => "bench",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "encryption" => Some(Capability::Encryption),
+            "compression" => Some(Capability::Compression),
+            "images" => Some(Capability::Images),
+            "pins" => Some(Capability::Pins),
+            "taildrop" => Some(Capability::Taildrop),
+            "ping" => Some(Capability::Ping),
+            "collect" => Some(Capability::Collect),
+            "metadata" => Some(Capability::Metadata),
+            "bench" => Some(Capability::Bench),
+            _ => None,
+        }
+    }
+
+    /// Names to advertise in node discovery
+    pub fn advertised() -> Vec<String> {
+        Self::SUPPORTED
+            .iter()
+            .map(|capability| capability.as_str().to_string())
+            .collect()
+    }
+
+    /// Feature a peer must support to be sent a message carrying `data`
+    pub fn required_by(data: &MessageData) -> Option<Self> {
+        match data {
+            MessageData::Pins(_) => Some(Capability::Pins),
+            MessageData::TaildropOffer(_) => Some(Capability::Taildrop),
+            MessageData::Ping(_) | MessageData::Pong(_) => Some(Capability::Ping),
+            MessageData::CollectRequest(_) | MessageData::CollectResponse(_) => {
+                Some(Capability::Collect)
+            }
+            MessageData::Bench(_) | MessageData::BenchReply(_) => Some(Capability::Bench),
+            MessageData::ClipboardUpdate(data) if !data.is_text() => Some(Capability::Images),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a peer advertising `capabilities` supports `capability`
+///
+/// Peers that advertise nothing predate capability flags, so only plain clipboard
+/// updates, which need none, are sent to them.
+pub fn peer_supports(capabilities: &[String], capability: Capability) -> bool {
+    capabilities.iter().any(|name| name == capability.as_str())
+}
+
+/// Whether a peer running `version` speaks our protocol: the same major version,
+/// or the same minor version before 1.0
+pub fn is_compatible_version(version: &str) -> bool {
+    fn major_minor(version: &str) -> Option<(u64, u64)> {
+        let mut parts = version.trim().split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        Some((major, minor))
+    }
+
+    match (major_minor(VERSION), major_minor(version)) {
+        (Some((0, ours)), Some((0, theirs))) => ours == theirs,
+        (Some((ours, _)), Some((theirs, _))) => ours == theirs,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_compatibility() {
+        assert!(is_compatible_version(VERSION));
+        assert!(!is_compatible_version("99.0.0"));
+        assert!(!is_compatible_version("unknown"));
+    }
+
+    #[test]
+    fn test_peers_without_flags_get_only_the_baseline() {
+        let pins_only = vec!["pins".to_string(), "images".to_string()];
+
+        assert!(peer_supports(&pins_only, Capability::Pins));
+        assert!(!peer_supports(&pins_only, Capability::Taildrop));
+        assert!(!peer_supports(&[], Capability::Taildrop));
+        assert!(!peer_supports(&[], Capability::Collect));
+        assert!(Capability::advertised().contains(&"encryption".to_string()));
+        assert_eq!(
+            Capability::parse("compression"),
+            Some(Capability::Compression)
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::Result;
+
+/// Clipboard types password managers add to mark content as a secret
+pub const CONCEALED_MARKERS: &[&str] = &[
+    "org.nspasteboard.ConcealedType",
+    "x-kde-passwordManagerHint",
+];
+
+/// Whether any of the clipboard's offered `types` is a password-manager marker
+pub fn has_marker<S: AsRef<str>>(types: &[S]) -> bool {
+    types
+        .iter()
+        .any(|t| CONCEALED_MARKERS.contains(&t.as_ref().trim()))
+}
+
+/// Whether the current clipboard content was marked as a secret by the app that copied it
+///
+/// Fails when the offered types can't be read, e.g. without `wl-paste` or `xclip`;
+/// callers treat that as marked rather than risk syncing a secret.
+pub async fn is_concealed() -> Result<bool> {
+    platform::is_concealed().await
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use crate::source_app::ns_string;
+    use crate::{PostError, Result};
+    use objc::runtime::Object;
+    use objc::{class, msg_send, sel, sel_impl};
+
+    pub async fn is_concealed() -> Result<bool> {
+        let types = unsafe {
+            let pasteboard: *mut Object = msg_send![class!(NSPasteboard), generalPasteboard];
+            if pasteboard.is_null() {
+                return Err(PostError::Clipboard(
+                    "No general pasteboard to read types from".to_string(),
+                ));
+            }
+            let types: *mut Object = msg_send![pasteboard, types];
+            if types.is_null() {
+                return Err(PostError::Clipboard(
+                    "The pasteboard didn't list its types".to_string(),
+                ));
+            }
+            let count: usize = msg_send![types, count];
+            (0..count)
+                .filter_map(|i| ns_string(msg_send![types, objectAtIndex: i]))
+                .collect::<Vec<_>>()
+        };
+        Ok(super::has_marker(&types))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use crate::clipboard::linux::is_wayland_session;
+    use crate::{PostError, Result};
+    use tokio::process::Command;
+
+    pub async fn is_concealed() -> Result<bool> {
+        let (tool, output) = if is_wayland_session() {
+            (
+                "wl-paste",
+                Command::new("wl-paste").arg("--list-types").output().await,
+            )
+        } else {
+            (
+                "xclip",
+                Command::new("xclip")
+                    .args(["-selection", "clipboard", "-t", "TARGETS", "-o"])
+                    .output()
+                    .await,
+            )
+        };
+
+        let output = output.map_err(|e| PostError::from_command(tool, e))?;
+        if !output.status.success() {
+            return Err(PostError::Clipboard(format!(
+                "{} couldn't list clipboard types: {}",
+                tool,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let types = String::from_utf8_lossy(&output.stdout);
+        Ok(super::has_marker(&types.lines().collect::<Vec<_>>()))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use crate::{PostError, Result};
+    use std::iter;
+    use windows_sys::Win32::System::DataExchange::{
+        IsClipboardFormatAvailable, RegisterClipboardFormatW,
+    };
+
+    /// Windows' own marker, set by password managers alongside or instead of the others
+    const WINDOWS_MARKER: &str = "ExcludeClipboardContentFromMonitorProcessing";
+
+    pub async fn is_concealed() -> Result<bool> {
+        for marker in iter::once(WINDOWS_MARKER).chain(super::CONCEALED_MARKERS.iter().copied()) {
+            let name: Vec<u16> = marker.encode_utf16().chain(iter::once(0)).collect();
+            let format = unsafe { RegisterClipboardFormatW(name.as_ptr()) };
+            if format == 0 {
+                return Err(PostError::Clipboard(format!(
+                    "Couldn't register the {} clipboard format",
+                    marker
+                )));
+            }
+            if unsafe { IsClipboardFormatAvailable(format) } != 0 {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux", windows)))]
+mod platform {
+    use crate::Result;
+
+    /// No password manager here marks its copies
+    pub async fn is_concealed() -> Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_password_manager_markers() {
+        assert!(has_marker(&["text/plain", "x-kde-passwordManagerHint"]));
+        assert!(has_marker(&[
+            "public.utf8-plain-text",
+            "org.nspasteboard.ConcealedType"
+        ]));
+        assert!(!has_marker(&["text/plain", "UTF8_STRING", "TARGETS"]));
+    }
+}
@@ -1,8 +1,17 @@
+use crate::source_app::AppRule;
+use crate::transform::Transform;
 use crate::{PostError, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
 use tokio::fs;
 
+/// Profile this process runs as, chosen with `--profile`
+static PROFILE: OnceLock<String> = OnceLock::new();
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PostConfig {
     pub node: NodeConfig,
@@ -11,20 +20,310 @@ pub struct PostConfig {
     pub ui: UiConfig,
     pub filters: FilterConfig,
     pub clipboard: ClipboardConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    pub kdeconnect: KdeConnectConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub journal: JournalConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+    /// Named templates for `post snippet`
+    #[serde(default)]
+    pub snippets: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeConfig {
+    /// Name shown to peers; empty to use the Tailscale machine name
     pub name: String,
+    /// Stable node ID override, useful when the Tailscale ID changes after re-auth
     pub id: Option<String>,
 }
 
+impl NodeConfig {
+    /// Node ID to sync as: the configured override, or the one Tailscale assigned
+    pub fn resolve_id(&self, tailscale_id: String) -> String {
+        match self.id.as_deref().map(str::trim) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => tailscale_id,
+        }
+    }
+
+    /// Configured display name, if one is set
+    pub fn display_name(&self) -> Option<&str> {
+        let name = self.name.trim();
+        (!name.is_empty()).then_some(name)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkConfig {
+    /// Tailscale local API socket; auto-detected when unset
     pub tailscale_socket: Option<String>,
     pub port: u16,
     pub discovery_interval: u64,
     pub heartbeat_interval: u64,
+    /// Address for the sync listener; defaults to this node's Tailscale IPs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_address: Option<String>,
+    /// Address family to use for peers that have both (prefer-v4, prefer-v6)
+    #[serde(default)]
+    pub ip_preference: IpPreference,
+    /// Address peers should connect to instead of this node's Tailscale IP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub advertise_address: Option<String>,
+    /// Port peers should connect to when `port` is remapped, e.g. in a container
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub advertise_port: Option<u16>,
+    /// Seconds between Tailscale connectivity checks (default 2)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_interval: Option<u64>,
+    /// Longest gap, in seconds, the checks back off to while disconnected (default 60)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_probe_interval: Option<u64>,
+    /// Failed sends in a row after which a peer is skipped, apart from probes (default 3)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker_threshold: Option<u32>,
+    /// Longest wait, in seconds, between probes of a skipped peer (default 300)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker_max_backoff: Option<u64>,
+    /// Peers a message is sent to at once (default 8)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_concurrency: Option<usize>,
+    /// Milliseconds a peer has to accept a connection before the send fails (default 3000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_ms: Option<u64>,
+    /// Milliseconds writing a message to a connected peer may take (default 5000)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_timeout_ms: Option<u64>,
+    /// Inbound peer connections handled at once; more are refused (default 64)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<usize>,
+    /// Messages from peers queued for handling before some are dropped (default 1024)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inbox_capacity: Option<usize>,
+    /// Run a private tailscaled and log it in with an auth key instead of using the host's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedded: Option<EmbeddedConfig>,
+    /// Which peers to sync with, by their Tailscale ACL tags
+    #[serde(default, skip_serializing_if = "TagPolicy::is_empty")]
+    pub peer_tags: TagPolicy,
+}
+
+/// Peers to sync with by their Tailscale ACL tags, e.g. only those tagged `tag:personal`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TagPolicy {
+    /// Only sync with peers carrying at least one of these tags; empty for any peer
+    pub require: Vec<String>,
+    /// Never sync with peers carrying any of these tags
+    pub exclude: Vec<String>,
+}
+
+impl TagPolicy {
+    /// Whether this policy lets every peer through
+    pub fn is_empty(&self) -> bool {
+        self.require.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether a peer carrying `tags` may be synced with
+    pub fn allows(&self, tags: &[String]) -> bool {
+        let has = |wanted: &String| tags.contains(wanted);
+        (self.require.is_empty() || self.require.iter().any(has)) && !self.exclude.iter().any(has)
+    }
+}
+
+/// A tailscaled owned by the daemon, for hosts without Tailscale installed as a service
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddedConfig {
+    /// Auth key for joining the tailnet; falls back to the `TS_AUTHKEY` environment variable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_key: Option<Secret>,
+    /// Machine name on the tailnet; defaults to the OS hostname
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// Where tailscaled keeps its state; defaults to `tailscale` in Post's data directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_dir: Option<String>,
+    /// tailscaled binary; found on PATH when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tailscaled: Option<String>,
+    /// TUN interface name; tailscaled's default when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tun: Option<String>,
+}
+
+impl EmbeddedConfig {
+    /// Configured auth key, or `TS_AUTHKEY` from the environment
+    pub fn auth_key(&self) -> Option<String> {
+        self.auth_key
+            .as_ref()
+            .map(|key| key.expose().to_string())
+            .or_else(|| std::env::var("TS_AUTHKEY").ok())
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+    }
+
+    /// Configured `state_dir`, or `tailscale` in Post's data directory
+    pub fn state_dir(&self) -> Result<PathBuf> {
+        match self.state_dir.as_deref().map(str::trim) {
+            Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+            _ => dirs::data_dir()
+                .map(|dir| dir.join(dir_name()).join("tailscale"))
+                .ok_or_else(|| PostError::Config("Could not find data directory".to_string())),
+        }
+    }
+
+    /// Local API socket the embedded tailscaled listens on
+    pub fn socket_path(&self) -> Result<PathBuf> {
+        Ok(self.state_dir()?.join("tailscaled.sock"))
+    }
+}
+
+/// Which Tailscale address to use when a peer has both an IPv4 and an IPv6 one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpPreference {
+    #[default]
+    PreferV4,
+    PreferV6,
+}
+
+impl IpPreference {
+    fn prefers(&self, ip: &IpAddr) -> bool {
+        match self {
+            IpPreference::PreferV4 => ip.is_ipv4(),
+            IpPreference::PreferV6 => ip.is_ipv6(),
+        }
+    }
+
+    /// The preferred address, or any address if none match the preferred family
+    pub fn pick(&self, addresses: &[IpAddr]) -> Option<IpAddr> {
+        addresses
+            .iter()
+            .find(|ip| self.prefers(ip))
+            .or_else(|| addresses.first())
+            .copied()
+    }
+
+    /// Order addresses so the preferred family comes first
+    pub fn sort(&self, addresses: &mut [IpAddr]) {
+        addresses.sort_by_key(|ip| !self.prefers(ip));
+    }
+
+    /// Wildcard address of the preferred family
+    pub fn unspecified(&self) -> IpAddr {
+        match self {
+            IpPreference::PreferV4 => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpPreference::PreferV6 => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Parsed `bind_address`, or `None` to bind to the Tailscale IP
+    pub fn bind_ip(&self) -> Result<Option<IpAddr>> {
+        match self.bind_address.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(address) => address.parse().map(Some).map_err(|_| {
+                PostError::Config(format!("Invalid network.bind_address: {}", address))
+            }),
+        }
+    }
+
+    /// The embedded tailscaled's socket, the configured `tailscale_socket`, or `None`
+    /// to auto-detect it
+    ///
+    /// Fails when the embedded tailscaled has no state directory to put its socket in,
+    /// rather than silently falling back to some other tailscaled.
+    pub fn socket_path(&self) -> Result<Option<String>> {
+        if let Some(embedded) = &self.embedded {
+            return Ok(Some(embedded.socket_path()?.display().to_string()));
+        }
+        Ok(self
+            .tailscale_socket
+            .as_deref()
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+            .map(str::to_string))
+    }
+
+    /// Parsed `advertise_address`, or `None` to let peers use the Tailscale IP
+    pub fn advertise_ip(&self) -> Result<Option<IpAddr>> {
+        match self.advertise_address.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(address) => address.parse().map(Some).map_err(|_| {
+                PostError::Config(format!("Invalid network.advertise_address: {}", address))
+            }),
+        }
+    }
+
+    /// Port announced to peers in node discovery
+    pub fn advertised_port(&self) -> u16 {
+        self.advertise_port.unwrap_or(self.port)
+    }
+
+    /// Time between Tailscale connectivity checks while connected
+    pub fn probe_interval(&self) -> Duration {
+        Duration::from_secs(self.probe_interval.unwrap_or(2).max(1))
+    }
+
+    /// Upper bound for the check interval while backing off
+    pub fn max_probe_interval(&self) -> Duration {
+        Duration::from_secs(self.max_probe_interval.unwrap_or(60)).max(self.probe_interval())
+    }
+
+    pub fn circuit_breaker_threshold(&self) -> u32 {
+        self.circuit_breaker_threshold.unwrap_or(3)
+    }
+
+    pub fn circuit_breaker_max_backoff(&self) -> Duration {
+        Duration::from_secs(self.circuit_breaker_max_backoff.unwrap_or(300))
+    }
+
+    pub fn send_concurrency(&self) -> usize {
+        self.send_concurrency
+            .unwrap_or(crate::transport::DEFAULT_SEND_CONCURRENCY)
+    }
+
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+            .unwrap_or(crate::transport::DEFAULT_MAX_CONNECTIONS)
+    }
+
+    pub fn inbox_capacity(&self) -> usize {
+        self.inbox_capacity
+            .unwrap_or(crate::inbox::DEFAULT_CAPACITY)
+            .max(1)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout_ms.map_or(
+            crate::transport::DEFAULT_CONNECT_TIMEOUT,
+            Duration::from_millis,
+        )
+    }
+
+    pub fn send_timeout(&self) -> Duration {
+        self.send_timeout_ms.map_or(
+            crate::transport::DEFAULT_SEND_TIMEOUT,
+            Duration::from_millis,
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,17 +347,177 @@ pub struct ColorConfig {
     pub error: String,
 }
 
+/// Regexes compiled once when the config is read, so an invalid one fails the load
+/// rather than every check
+#[derive(Debug, Clone)]
+pub struct PatternSet {
+    set: regex::RegexSet,
+}
+
+impl PatternSet {
+    pub fn new<I, S>(patterns: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        regex::RegexSet::new(patterns)
+            .map(|set| Self { set })
+            .map_err(|e| PostError::Config(format!("Invalid pattern: {}", e)))
+    }
+
+    /// The patterns as written
+    pub fn patterns(&self) -> &[String] {
+        self.set.patterns()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// First pattern matching `content`, in the order written
+    pub fn first_match(&self, content: &str) -> Option<&str> {
+        self.set
+            .matches(content)
+            .iter()
+            .next()
+            .map(|index| self.set.patterns()[index].as_str())
+    }
+}
+
+impl Default for PatternSet {
+    fn default() -> Self {
+        Self {
+            set: regex::RegexSet::empty(),
+        }
+    }
+}
+
+impl PartialEq for PatternSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns() == other.patterns()
+    }
+}
+
+impl Serialize for PatternSet {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.patterns().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PatternSet {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let patterns = Vec::<String>::deserialize(deserializer)?;
+        Self::new(patterns).map_err(serde::de::Error::custom)
+    }
+}
+
+/// What secrets and excluded patterns are replaced with in state dumps
+pub const REDACTED: &str = "<redacted>";
+
+thread_local! {
+    /// Set inside [`reveal_secrets`]
+    static REVEAL_SECRETS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// A credential from the config, such as an auth key or a password
+///
+/// It serializes and debug-prints as [`REDACTED`], so it can't end up in a state dump or a
+/// log, except inside [`reveal_secrets`].
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+
+    /// The secret itself, for whatever it is handed to
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match REVEAL_SECRETS.with(std::cell::Cell::get) {
+            true => serializer.serialize_str(&self.0),
+            false => serializer.serialize_str(REDACTED),
+        }
+    }
+}
+
+/// Run `f` with [`Secret`]s serializing as themselves, for writing the config file or
+/// comparing configs
+pub fn reveal_secrets<T>(f: impl FnOnce() -> T) -> T {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            REVEAL_SECRETS.with(|reveal| reveal.set(self.0));
+        }
+    }
+    let _restore = Restore(REVEAL_SECRETS.with(|reveal| reveal.replace(true)));
+    f()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilterConfig {
     pub lua_hooks: Vec<String>,
     pub js_hooks: Vec<String>,
     pub max_length: Option<usize>,
-    pub exclude_patterns: Vec<String>,
+    pub exclude_patterns: PatternSet,
+    /// Per-app rules for local clipboard changes, checked in order (macOS and Windows)
+    #[serde(default)]
+    pub app_rules: Vec<AppRule>,
+    /// Sync content that password managers mark as a secret, which is skipped by default
+    #[serde(default)]
+    pub sync_concealed: bool,
+    /// Rewrites applied, in order, to clipboard content before it is sent to peers
+    #[serde(default)]
+    pub transforms: Vec<Transform>,
+}
+
+impl FilterConfig {
+    /// Reject content longer than `max_length` or matching any `exclude_patterns` regex
+    pub fn check(&self, content: &str) -> Result<()> {
+        if let Some(max_length) = self.max_length {
+            let length = content.chars().count();
+            if length > max_length {
+                return Err(PostError::Filtered(format!(
+                    "content is {} characters, limit is {}",
+                    length, max_length
+                )));
+            }
+        }
+
+        if let Some(pattern) = self.exclude_patterns.first_match(content) {
+            return Err(PostError::Filtered(format!(
+                "content matches excluded pattern {}",
+                pattern
+            )));
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClipboardConfig {
-    /// Force specific clipboard backend (auto, system, wayland, xclip, xsel, wsl, windows)
+    /// Force specific clipboard backend (auto, system, hybrid, wayland, xclip, xsel, wsl, wslg, windows)
     pub backend: String,
     /// Enable wl-clipboard fallback for Wayland sessions
     pub wayland_fallback: bool,
@@ -70,6 +529,453 @@ pub struct ClipboardConfig {
     pub sway_optimizations: bool,
     /// Priority order for clipboard selections (clipboard, primary)
     pub selection_priority: Vec<String>,
+    /// Under WSLg, write to both the Linux and the Windows clipboard and read whichever
+    /// changed last
+    #[serde(default)]
+    pub wslg_coherence: bool,
+    /// Polling interval for the Windows clipboard under WSL in milliseconds; defaults to 1000
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wsl_poll_interval_ms: Option<u64>,
+}
+
+impl ClipboardConfig {
+    /// How often a clipboard without change notifications is polled, at least every
+    /// millisecond
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms.max(1))
+    }
+
+    /// How often the Windows clipboard is polled under WSL, at least every millisecond
+    pub fn wsl_poll_interval(&self) -> Duration {
+        Duration::from_millis(self.wsl_poll_interval_ms.unwrap_or(1000).max(1))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// What to replay to a peer that was offline when updates were sent (latest, history)
+    pub offline_replay: ReplayMode,
+    /// Number of recent updates kept for replay in history mode
+    pub replay_history_size: usize,
+    /// Send content larger than this many bytes with Taildrop instead; unset to disable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taildrop_threshold: Option<usize>,
+    /// Where received Taildrop payloads are saved; defaults to the Downloads folder
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taildrop_dir: Option<PathBuf>,
+    /// Recent distinct clipboard items kept on every device, newest first
+    pub stack_size: usize,
+    /// Keep the clipboard stack in the data directory so it survives restarts; off by
+    /// default, as it is stored unencrypted
+    pub persist_history: bool,
+    /// Warn when a peer's clock differs from this one by more than this many seconds; 0
+    /// to never warn
+    pub max_clock_skew_secs: u64,
+    /// Keep updates peers have yet to receive in the data directory, so they are still
+    /// delivered after a restart; follows `persist_history` unless set, as they are
+    /// stored unencrypted too
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persist_queue: Option<bool>,
+    /// Updates still unacknowledged after this many seconds are given up on
+    pub queue_max_age_secs: u64,
+    /// Oldest queued updates are dropped once their content exceeds this many bytes
+    pub queue_max_bytes: usize,
+    /// Tailnets, besides this node's own, whose peers are synced with; `*` for any. While
+    /// set, updates that don't say which tailnet they come from are refused
+    pub allowed_tailnets: Vec<String>,
+    /// Tell peers which app content was copied in (macOS and Windows)
+    pub share_origin_app: bool,
+    /// Let peers collect the clipboard with `post collect`
+    pub allow_collect: bool,
+    /// How content from peers lands on the clipboard (replace, append)
+    pub apply_mode: ApplyMode,
+    /// `apply_mode` for content from particular peers, by name or node ID
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub apply_mode_by_peer: BTreeMap<String, ApplyMode>,
+    /// Put between the clipboard and appended content
+    pub append_separator: String,
+    /// Log what would be sent or applied without doing either; set by `post daemon --dry-run`
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+/// Which missed clipboard updates a reappearing peer receives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReplayMode {
+    /// Only the most recent update it missed
+    #[default]
+    Latest,
+    /// Every update it missed, up to `replay_history_size`
+    History,
+}
+
+/// How content from a peer is applied to the local clipboard
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApplyMode {
+    /// The content replaces what is on the clipboard
+    #[default]
+    Replace,
+    /// The content is added after what is on the clipboard, following `append_separator`
+    Append,
+}
+
+impl SyncConfig {
+    /// How to apply content from the peer with `node_id` and `node_name`
+    pub fn apply_mode_for(&self, node_id: &str, node_name: &str) -> ApplyMode {
+        self.apply_mode_by_peer
+            .get(node_name)
+            .or_else(|| self.apply_mode_by_peer.get(node_id))
+            .copied()
+            .unwrap_or(self.apply_mode)
+    }
+
+    /// Number of recent updates to keep for replay
+    pub fn replay_capacity(&self) -> usize {
+        match self.offline_replay {
+            ReplayMode::Latest => 1,
+            ReplayMode::History => self.replay_history_size.max(1),
+        }
+    }
+
+    /// Whether updates peers have yet to receive are kept between runs
+    pub fn persists_queue(&self) -> bool {
+        self.persist_queue.unwrap_or(self.persist_history)
+    }
+
+    /// Whether a peer clock `skew_secs` away from ours is worth warning about
+    pub fn is_clock_skew_excessive(&self, skew_secs: i64) -> bool {
+        self.max_clock_skew_secs > 0 && skew_secs.unsigned_abs() > self.max_clock_skew_secs
+    }
+
+    /// Whether updates tagged with `tailnet` may be applied on a node in `own`
+    pub fn allows_tailnet(&self, own: &str, tailnet: &str) -> bool {
+        tailnet_allowed(&self.allowed_tailnets, own, tailnet)
+    }
+
+    /// Directory received Taildrop payloads are saved to
+    pub fn taildrop_receive_dir(&self) -> PathBuf {
+        self.taildrop_dir
+            .clone()
+            .unwrap_or_else(crate::taildrop::default_receive_dir)
+    }
+}
+
+/// Whether a peer in `tailnet` may be synced with by a node in `own` that also allows
+/// `allowed`, see `sync.allowed_tailnets`
+pub fn tailnet_allowed(allowed: &[String], own: &str, tailnet: &str) -> bool {
+    tailnet.eq_ignore_ascii_case(own)
+        || allowed
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(tailnet))
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            offline_replay: ReplayMode::default(),
+            replay_history_size: 20,
+            taildrop_threshold: None,
+            taildrop_dir: None,
+            stack_size: 10,
+            persist_history: false,
+            max_clock_skew_secs: 30,
+            persist_queue: None,
+            queue_max_age_secs: 24 * 60 * 60,
+            queue_max_bytes: 4 * 1024 * 1024,
+            allowed_tailnets: Vec::new(),
+            share_origin_app: false,
+            allow_collect: false,
+            apply_mode: ApplyMode::default(),
+            apply_mode_by_peer: BTreeMap::new(),
+            append_separator: "\n".to_string(),
+            dry_run: false,
+        }
+    }
+}
+
+/// How much the daemon logs, and what it may reveal about clipboard content
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Default level (error, warn, info, debug, trace); info when unset, debug with `--verbose`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    /// Levels for individual modules, e.g. `"post_core::sync" = "trace"`
+    pub filters: BTreeMap<String, String>,
+    /// Show the first characters of clipboard content in debug logs; for troubleshooting only
+    pub content_previews: bool,
+    /// Show a desktop notification when the daemon panics
+    pub notify_on_crash: bool,
+}
+
+/// Local HTTP API served by the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Whether the daemon serves the API at all
+    pub enabled: bool,
+    pub port: u16,
+    /// Listen address; use this node's Tailscale IP to reach the API from other devices
+    pub bind: String,
+    /// Serve HTTPS with a certificate from `tailscale cert` (requires HTTPS on the tailnet)
+    pub tls: bool,
+    /// Serve on a Unix socket instead of `bind` and `port`, e.g.
+    /// `unix:/run/user/1000/post.sock`; only the owner may connect to it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+}
+
+impl ApiConfig {
+    /// Parsed `bind` address
+    pub fn bind_ip(&self) -> Result<IpAddr> {
+        self.bind
+            .trim()
+            .parse()
+            .map_err(|_| PostError::Config(format!("Invalid api.bind: {}", self.bind)))
+    }
+
+    /// Whether only this machine can reach the API: on a Unix socket or a loopback address
+    pub fn is_local_only(&self) -> bool {
+        matches!(self.unix_socket(), Ok(Some(_))) || self.bind_ip().is_ok_and(|ip| ip.is_loopback())
+    }
+
+    /// Socket path from `listen`, if the API is served on a Unix socket
+    pub fn unix_socket(&self) -> Result<Option<PathBuf>> {
+        let Some(listen) = self.listen.as_deref().map(str::trim) else {
+            return Ok(None);
+        };
+        let path = listen
+            .strip_prefix("unix:")
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| {
+                PostError::Config(format!(
+                    "Invalid api.listen: {}; expected unix:<socket path>",
+                    listen
+                ))
+            })?;
+        if self.tls {
+            return Err(PostError::Config(
+                "api.tls can't be used with a Unix socket in api.listen".to_string(),
+            ));
+        }
+        Ok(Some(PathBuf::from(path)))
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 19828,
+            bind: "127.0.0.1".to_string(),
+            tls: false,
+            listen: None,
+        }
+    }
+}
+
+/// gRPC control service served alongside the HTTP API, with the same tokens
+///
+/// Only used by builds with the `grpc` feature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// Listen address, like `api.bind`
+    pub bind: String,
+}
+
+impl GrpcConfig {
+    /// Parsed `bind` address
+    pub fn bind_ip(&self) -> Result<IpAddr> {
+        self.bind
+            .trim()
+            .parse()
+            .map_err(|_| PostError::Config(format!("Invalid grpc.bind: {}", self.bind)))
+    }
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 19829,
+            bind: "127.0.0.1".to_string(),
+        }
+    }
+}
+
+/// MQTT broker the daemon reports status and sync events to, e.g. for Home Assistant
+///
+/// Only used by builds with the `mqtt` feature. Clipboard content is never published.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// Prefix for the `status`, `events` and `availability` topics; a profile's go under
+    /// `<topic>/<profile>`
+    pub topic: String,
+    /// Defaults to `post-<node name>`, or `post-<node name>-<profile>` for a profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Secret>,
+    /// Seconds between status updates
+    pub status_interval: u64,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            topic: "post".to_string(),
+            client_id: None,
+            username: None,
+            password: None,
+            status_interval: 60,
+        }
+    }
+}
+
+impl MqttConfig {
+    /// Whether the broker runs on this machine, the only place credentials may be sent
+    /// without TLS
+    pub fn is_local(&self) -> bool {
+        let host = self.host.trim();
+        host.eq_ignore_ascii_case("localhost")
+            || host
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| ip.to_canonical().is_loopback())
+    }
+}
+
+/// Forwarding of synced content to phones paired with KDE Connect (Linux only)
+///
+/// Content the phones send lands on the desktop clipboard, which is synced as usual.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KdeConnectConfig {
+    pub enabled: bool,
+    /// KDE Connect device IDs to forward to; every reachable paired device when empty
+    pub devices: Vec<String>,
+}
+
+/// Limits on what the daemon keeps in its data directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Bytes the data directory may take before the oldest clipboard history, then the
+    /// log, are pruned; unset for no cap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+}
+
+/// Copy of every clipboard entry this node syncs, sent or received, for journaling
+///
+/// Content `[filters]` rejects is never synced, so it is never journaled either.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalConfig {
+    pub enabled: bool,
+    /// File entries are appended to, or the directory for `per_entry_files`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Write each entry to its own timestamped file in `path`
+    pub per_entry_files: bool,
+    /// Entries are cut to this many bytes
+    pub max_entry_size: usize,
+    /// The journal file moves to `<path>.1` once it is this many bytes; 0 for no limit
+    pub max_file_size: u64,
+    /// Regexes for content to leave out of the journal, on top of `[filters]`
+    pub exclude_patterns: PatternSet,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            per_entry_files: false,
+            max_entry_size: 64 * 1024,
+            max_file_size: 10 * 1024 * 1024,
+            exclude_patterns: PatternSet::default(),
+        }
+    }
+}
+
+/// Append-only record of API calls and of content synced or refused, kept apart from the
+/// debug log for machines that need an audit trail
+///
+/// It says who did what and when, never what was copied.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    /// File entries are appended to; `audit.log` in the data directory when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+}
+
+/// Desktop notifications shown by the daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Show notifications at all; crash notifications follow `logging.notify_on_crash`
+    pub enabled: bool,
+    /// Seconds a notification stays on screen
+    pub timeout_secs: u64,
+    /// Include the start of received content in its notification
+    pub preview: bool,
+    /// Tailscale connecting and disconnecting, and starting offline
+    pub connection: bool,
+    /// Content received from a peer
+    pub received: bool,
+    /// Content too large to sync arriving as a Taildrop file
+    pub taildrop: bool,
+    /// The local clipboard becoming unreadable and recovering
+    pub clipboard_health: bool,
+    /// Daemon tasks failing and being restarted
+    pub task_failures: bool,
+    /// A peer's clock drifting past `sync.max_clock_skew_secs`
+    pub clock_skew: bool,
+    /// A peer going quiet for long enough to be dropped
+    pub peer_offline: bool,
+    /// Tailnet peers coming online and going offline in Tailscale
+    pub tailnet_peers: bool,
+    /// Connection changes notify only once they have lasted this many seconds; 0 for at once
+    pub settle_secs: u64,
+    /// Identical notifications within this many seconds are shown once
+    pub cooldown_secs: u64,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_secs: 5,
+            preview: false,
+            connection: true,
+            received: false,
+            taildrop: true,
+            clipboard_health: true,
+            task_failures: true,
+            clock_skew: true,
+            peer_offline: true,
+            tailnet_peers: false,
+            settle_secs: 10,
+            cooldown_secs: 60,
+        }
+    }
 }
 
 impl Default for ClipboardConfig {
@@ -81,6 +987,8 @@ impl Default for ClipboardConfig {
             max_content_size: 1024 * 1024, // 1MB
             sway_optimizations: true,
             selection_priority: vec!["clipboard".to_string(), "primary".to_string()],
+            wslg_coherence: false,
+            wsl_poll_interval_ms: None,
         }
     }
 }
@@ -100,6 +1008,21 @@ impl Default for PostConfig {
                 port: 19827,
                 discovery_interval: 30,
                 heartbeat_interval: 10,
+                bind_address: None,
+                ip_preference: IpPreference::default(),
+                advertise_address: None,
+                advertise_port: None,
+                probe_interval: None,
+                max_probe_interval: None,
+                circuit_breaker_threshold: None,
+                circuit_breaker_max_backoff: None,
+                send_concurrency: None,
+                connect_timeout_ms: None,
+                send_timeout_ms: None,
+                max_connections: None,
+                inbox_capacity: None,
+                embedded: None,
+                peer_tags: TagPolicy::default(),
             },
             security: SecurityConfig {
                 enable_encryption: true,
@@ -119,7 +1042,10 @@ impl Default for PostConfig {
                 lua_hooks: vec![],
                 js_hooks: vec![],
                 max_length: Some(10_000),
-                exclude_patterns: vec![],
+                exclude_patterns: PatternSet::default(),
+                app_rules: vec![],
+                sync_concealed: false,
+                transforms: vec![],
             },
             clipboard: ClipboardConfig {
                 backend: "auto".to_string(),
@@ -128,15 +1054,122 @@ impl Default for PostConfig {
                 max_content_size: 1024 * 1024, // 1MB
                 sway_optimizations: true,
                 selection_priority: vec!["clipboard".to_string(), "primary".to_string()],
+                wslg_coherence: false,
+                wsl_poll_interval_ms: None,
             },
+            sync: SyncConfig::default(),
+            api: ApiConfig::default(),
+            grpc: GrpcConfig::default(),
+            logging: LoggingConfig::default(),
+            mqtt: MqttConfig::default(),
+            kdeconnect: KdeConnectConfig::default(),
+            storage: StorageConfig::default(),
+            journal: JournalConfig::default(),
+            audit: AuditConfig::default(),
+            notifications: NotificationConfig::default(),
+            snippets: BTreeMap::new(),
         }
     }
 }
 
+/// Run as the profile `name`, with its own config, data directory, ports and keys
+///
+/// Call once at startup, before any of Post's directories are looked up.
+pub fn set_profile(name: &str) -> Result<()> {
+    validate_profile(name)?;
+    match PROFILE.get_or_init(|| name.to_string()) {
+        set if set == name => Ok(()),
+        set => Err(PostError::Config(format!(
+            "Already running as profile {}",
+            set
+        ))),
+    }
+}
+
+/// Profile this process runs as, if not the default one
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().map(String::as_str)
+}
+
+/// The profile with `separator` in front, telling a profile's service, MQTT topics and
+/// the like apart from the default profile's; empty for the default profile
+pub fn profile_suffix(separator: &str) -> String {
+    profile()
+        .map(|profile| format!("{}{}", separator, profile))
+        .unwrap_or_default()
+}
+
+/// Name of the config and data directories: `post`, or `post-<profile>` for a profile
+pub fn dir_name() -> String {
+    match profile() {
+        Some(name) => format!("post-{}", name),
+        None => "post".to_string(),
+    }
+}
+
+fn validate_profile(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(PostError::Config(format!(
+            "Invalid profile name {:?}: use up to 32 letters, digits, '-' or '_'",
+            name
+        )))
+    }
+}
+
+/// Peer and API ports a new config for profile `name` starts with
+///
+/// Derived from the name, so the same profile agrees on its port across machines without
+/// clashing with the default profile's.
+pub fn profile_ports(name: &str) -> (u16, u16) {
+    // FNV-1a, which unlike the std hasher is the same in every build
+    let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    let port = 20_000 + 2 * (hash % 1000) as u16;
+    (port, port + 1)
+}
+
 impl PostConfig {
+    /// Default config for the current profile, whose ports differ from the default
+    /// profile's so both can run at once
+    pub fn profile_default() -> Self {
+        let mut config = Self::default();
+        if let Some(name) = profile() {
+            (config.network.port, config.api.port) = profile_ports(name);
+        }
+        config
+    }
+
+    /// Copy with the excluded patterns, which often spell out the secrets they keep from
+    /// syncing, replaced; serialized, it is safe to attach to bug reports since [`Secret`]s
+    /// never serialize as themselves
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        for patterns in [
+            &mut config.filters.exclude_patterns,
+            &mut config.journal.exclude_patterns,
+        ] {
+            *patterns = PatternSet::new(vec![regex::escape(REDACTED); patterns.patterns().len()])
+                .expect("an escaped pattern is valid");
+        }
+        config
+    }
+
+    /// The config as written to its file, secrets included
+    pub fn to_toml(&self) -> Result<String> {
+        reveal_secrets(|| toml::to_string_pretty(self)).map_err(serialize_error)
+    }
+
     pub fn config_dir() -> Result<PathBuf> {
         dirs::home_dir()
-            .map(|d| d.join(".config").join("post"))
+            .map(|d| d.join(".config").join(dir_name()))
             .ok_or_else(|| PostError::Config("Unable to determine home directory".to_string()))
     }
 
@@ -148,7 +1181,7 @@ impl PostConfig {
         let path = Self::config_path()?;
 
         if !path.exists() {
-            let config = Self::default();
+            let config = Self::profile_default();
             config.save().await?;
             return Ok(config);
         }
@@ -171,10 +1204,7 @@ impl PostConfig {
         }
 
         let path = Self::config_path()?;
-        let contents = toml::to_string_pretty(self)
-            .map_err(|e| PostError::Config(format!("Failed to serialize config: {}", e)))?;
-
-        fs::write(&path, contents).await?;
+        fs::write(&path, self.to_toml()?).await?;
 
         // Set secure permissions on config file (600 - owner read/write only)
         #[cfg(unix)]
@@ -186,4 +1216,279 @@ impl PostConfig {
 
         Ok(())
     }
+
+    /// Copy of this config with the dotted `key` (e.g. `network.port`) set to `value`,
+    /// read as a TOML value such as `true` or `["a"]`, or as a plain string otherwise
+    pub fn with_value(&self, key: &str, value: &str) -> Result<Self> {
+        let literal = format!("value = {}", value)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut table| table.remove("value"));
+        let string = toml::Value::String(value.to_string());
+
+        let updated = match literal {
+            Some(literal) => self
+                .with_toml_value(key, literal)
+                .or_else(|_| self.with_toml_value(key, string))?,
+            None => self.with_toml_value(key, string)?,
+        };
+
+        // Unknown keys are ignored when deserializing, so check the key survived the round trip
+        let table = reveal_secrets(|| toml::Table::try_from(&updated)).map_err(serialize_error)?;
+        if lookup(&table, key).is_none() {
+            return Err(PostError::Config(format!("Unknown config key {}", key)));
+        }
+        Ok(updated)
+    }
+
+    fn with_toml_value(&self, key: &str, value: toml::Value) -> Result<Self> {
+        let mut table = reveal_secrets(|| toml::Table::try_from(self)).map_err(serialize_error)?;
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let last = parts
+            .pop()
+            .filter(|last| !last.is_empty())
+            .ok_or_else(|| PostError::Config(format!("Invalid config key {:?}", key)))?;
+
+        let mut section = &mut table;
+        for part in parts {
+            section = section
+                .entry(part)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| PostError::Config(format!("{} is not a config section", part)))?;
+        }
+        section.insert(last.to_string(), value);
+
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| PostError::Config(format!("Invalid value for {}: {}", key, e)))
+    }
+}
+
+fn lookup<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    match key.split_once('.') {
+        Some((section, rest)) => lookup(table.get(section)?.as_table()?, rest),
+        None => table.get(key),
+    }
+}
+
+fn serialize_error(e: toml::ser::Error) -> PostError {
+    PostError::Config(format!("Failed to serialize config: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacted_config_hides_credentials() {
+        let mut config = PostConfig::default();
+        config.mqtt.password = Some(Secret::new("hunter2"));
+        config.network.embedded = Some(EmbeddedConfig {
+            auth_key: Some(Secret::new("tskey-auth-123")),
+            hostname: Some("desk".to_string()),
+            ..EmbeddedConfig::default()
+        });
+        config.filters.exclude_patterns = PatternSet::new(["^my-api-key-123$"]).unwrap();
+        config.journal.exclude_patterns = PatternSet::new(["^bank-pin-4321$"]).unwrap();
+
+        let dump = serde_json::to_string(&config.redacted()).unwrap();
+        for secret in [
+            "hunter2",
+            "tskey-auth-123",
+            "my-api-key-123",
+            "bank-pin-4321",
+        ] {
+            assert!(!dump.contains(secret), "{} leaked", secret);
+        }
+        assert!(dump.contains("desk"));
+        assert!(!format!("{:?}", config).contains("hunter2"));
+        assert!(PostConfig::default().redacted().mqtt.password.is_none());
+
+        // Only the config file gets the secrets themselves
+        let written: PostConfig = toml::from_str(&config.to_toml().unwrap()).unwrap();
+        assert_eq!(written.mqtt.password, Some(Secret::new("hunter2")));
+        let updated = config.with_value("mqtt.port", "1884").unwrap();
+        assert_eq!(
+            updated.network.embedded.unwrap().auth_key(),
+            Some("tskey-auth-123".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tag_policy_requires_and_excludes_tags() {
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+        let policy = TagPolicy {
+            require: tags(&["tag:personal", "tag:family"]),
+            exclude: tags(&["tag:shared"]),
+        };
+        assert!(policy.allows(&tags(&["tag:personal"])));
+        assert!(!policy.allows(&tags(&[])));
+        assert!(!policy.allows(&tags(&["tag:work"])));
+        assert!(!policy.allows(&tags(&["tag:family", "tag:shared"])));
+        assert!(TagPolicy::default().allows(&tags(&[])));
+    }
+
+    #[test]
+    fn test_profile_names_and_ports() {
+        assert!(validate_profile("work").is_ok());
+        assert!(validate_profile("home_2").is_ok());
+        for name in ["", "../work", "my work", &"x".repeat(33)] {
+            assert!(validate_profile(name).is_err(), "{:?}", name);
+        }
+
+        let (network, api) = profile_ports("work");
+        assert_eq!(profile_ports("work"), (network, api));
+        assert_ne!(profile_ports("personal"), (network, api));
+        let defaults = PostConfig::default();
+        for port in [network, api] {
+            assert!(port != defaults.network.port && port != defaults.api.port);
+        }
+    }
+
+    #[test]
+    fn test_with_value_sets_dotted_keys() {
+        let config = PostConfig::default()
+            .with_value("network.port", "9000")
+            .unwrap()
+            .with_value("node.id", "laptop-1")
+            .unwrap()
+            .with_value("filters.exclude_patterns", r#"["^secret"]"#)
+            .unwrap();
+
+        assert_eq!(config.network.port, 9000);
+        assert_eq!(config.node.id.as_deref(), Some("laptop-1"));
+        assert_eq!(config.filters.exclude_patterns.patterns(), ["^secret"]);
+        assert_eq!(
+            config.with_value("node.name", "true").unwrap().node.name,
+            "true"
+        );
+        assert!(config.with_value("network.port", "high").is_err());
+        assert!(config.with_value("network.prot", "9000").is_err());
+    }
+
+    #[test]
+    fn test_exclude_patterns_are_compiled_when_read() {
+        let config = PostConfig::default()
+            .with_value("filters.exclude_patterns", r#"["^secret", "\\d{4}"]"#)
+            .unwrap();
+        let filters = &config.filters;
+        assert!(filters.check("public").is_ok());
+        assert!(matches!(
+            filters.check("secret 1234"),
+            Err(PostError::Filtered(reason)) if reason.ends_with("^secret")
+        ));
+        assert!(PostConfig::default()
+            .with_value("filters.exclude_patterns", r#"["("]"#)
+            .is_err());
+        assert!(PostConfig::default()
+            .with_value("journal.exclude_patterns", r#"["("]"#)
+            .is_err());
+    }
+
+    #[test]
+    fn test_clock_skew_past_the_limit_either_way_is_excessive() {
+        let mut sync = SyncConfig::default();
+        assert!(!sync.is_clock_skew_excessive(-30));
+        assert!(sync.is_clock_skew_excessive(31));
+        assert!(sync.is_clock_skew_excessive(-31));
+
+        sync.max_clock_skew_secs = 0;
+        assert!(!sync.is_clock_skew_excessive(i64::MIN));
+    }
+
+    #[test]
+    fn test_bind_address_defaults_to_the_tailscale_ip() {
+        let mut network = PostConfig::default().network;
+        assert_eq!(network.bind_ip().unwrap(), None);
+
+        network.bind_address = Some(" ".to_string());
+        assert_eq!(network.bind_ip().unwrap(), None);
+        network.bind_address = Some(" 100.64.0.1 ".to_string());
+        assert_eq!(
+            network.bind_ip().unwrap(),
+            Some(IpAddr::from([100, 64, 0, 1]))
+        );
+        network.bind_address = Some("0.0.0.0:8412".to_string());
+        assert!(matches!(network.bind_ip(), Err(PostError::Config(_))));
+    }
+
+    #[test]
+    fn test_ip_preference_picks_the_preferred_family() {
+        let v4 = IpAddr::from([100, 64, 0, 1]);
+        let v6: IpAddr = "fd7a:115c:a1e0::1".parse().unwrap();
+
+        assert_eq!(IpPreference::PreferV4.pick(&[v6, v4]), Some(v4));
+        assert_eq!(IpPreference::PreferV6.pick(&[v4, v6]), Some(v6));
+        // Falls back to whatever the peer has
+        assert_eq!(IpPreference::PreferV6.pick(&[v4]), Some(v4));
+        assert_eq!(IpPreference::PreferV4.pick(&[]), None);
+
+        let mut addresses = [v4, v6];
+        IpPreference::PreferV6.sort(&mut addresses);
+        assert_eq!(addresses, [v6, v4]);
+        assert!(IpPreference::PreferV6.unspecified().is_ipv6());
+    }
+
+    #[test]
+    fn test_embedded_tailscaled_socket_takes_precedence() {
+        let mut config = PostConfig::default();
+        config.network.tailscale_socket = Some(" /run/tailscale.sock ".to_string());
+        assert_eq!(
+            config.network.socket_path().unwrap().as_deref(),
+            Some("/run/tailscale.sock")
+        );
+
+        let config = config
+            .with_value("network.embedded.state_dir", "/var/lib/post/tailscale")
+            .unwrap();
+        let expected = PathBuf::from("/var/lib/post/tailscale").join("tailscaled.sock");
+        assert_eq!(
+            config.network.socket_path().unwrap(),
+            Some(expected.display().to_string())
+        );
+    }
+
+    #[test]
+    fn test_only_loopback_mqtt_brokers_are_local() {
+        let broker = |host: &str| MqttConfig {
+            host: host.to_string(),
+            ..MqttConfig::default()
+        };
+        assert!(broker("localhost").is_local());
+        assert!(broker("127.0.0.1").is_local());
+        assert!(broker("::1").is_local());
+        assert!(!broker("homeassistant.local").is_local());
+        assert!(!broker("100.64.0.2").is_local());
+    }
+
+    #[test]
+    fn test_zero_poll_intervals_are_raised_to_a_millisecond() {
+        let config = PostConfig::default()
+            .with_value("clipboard.wsl_poll_interval_ms", "0")
+            .unwrap()
+            .with_value("clipboard.poll_interval_ms", "0")
+            .unwrap();
+        assert_eq!(
+            config.clipboard.wsl_poll_interval(),
+            Duration::from_millis(1)
+        );
+        assert_eq!(config.clipboard.poll_interval(), Duration::from_millis(1));
+        assert_eq!(
+            PostConfig::default().clipboard.wsl_poll_interval(),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_timeouts_are_configured_in_milliseconds() {
+        let config = PostConfig::default();
+        assert_eq!(config.network.connect_timeout(), Duration::from_secs(3));
+
+        let config = config
+            .with_value("network.connect_timeout_ms", "250")
+            .unwrap();
+        assert_eq!(config.network.connect_timeout(), Duration::from_millis(250));
+        assert_eq!(config.network.send_timeout(), Duration::from_secs(5));
+    }
 }
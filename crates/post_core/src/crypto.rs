@@ -11,7 +11,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::debug;
-use x25519_dalek::{EphemeralSecret, PublicKey};
+use x25519_dalek::{PublicKey, StaticSecret};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KeyPair {
@@ -115,23 +115,20 @@ impl CryptoSession {
 }
 
 pub fn generate_keypair() -> Result<KeyPair> {
-    let secret = EphemeralSecret::random_from_rng(OsRng);
+    let secret = StaticSecret::random_from_rng(OsRng);
     let public = PublicKey::from(&secret);
 
-    // For storage, we need the private key bytes
-    let mut private_bytes = [0u8; 32];
-    OsRng.fill_bytes(&mut private_bytes);
-
     Ok(KeyPair {
         public_key: public.to_bytes().to_vec(),
-        private_key: private_bytes.to_vec(),
+        private_key: secret.to_bytes().to_vec(),
     })
 }
 
-pub fn derive_shared_secret(_private_key: &[u8], public_key: &[u8]) -> Result<[u8; 32]> {
-    // For now, we'll use a simple key derivation approach
-    // In a real implementation, you'd want to properly reconstruct the secret from stored bytes
-    let secret = EphemeralSecret::random_from_rng(OsRng);
+pub fn derive_shared_secret(private_key: &[u8], public_key: &[u8]) -> Result<[u8; 32]> {
+    let secret = StaticSecret::from(
+        <[u8; 32]>::try_from(private_key)
+            .map_err(|_| PostError::Crypto("Invalid private key length".to_string()))?,
+    );
 
     let public = PublicKey::from(
         <[u8; 32]>::try_from(public_key)
@@ -150,6 +147,18 @@ pub fn derive_encryption_key(shared_secret: &[u8]) -> Result<[u8; 32]> {
     Ok(result.into())
 }
 
+/// Short, human-comparable fingerprint of a public key, e.g. `1a2b:3c4d:...` (8 groups)
+pub fn key_fingerprint(public_key: &[u8]) -> String {
+    let mut hasher = Blake2s256::new();
+    hasher.update(b"post-key-fingerprint-v1");
+    hasher.update(public_key);
+    hasher.finalize()[..16]
+        .chunks(2)
+        .map(|pair| format!("{:02x}{:02x}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
 pub fn derive_key_from_tailscale_identity(identity: &[u8]) -> Result<[u8; 32]> {
     let mut hasher = Blake2s256::new();
     hasher.update(b"post-tailscale-identity-v1");
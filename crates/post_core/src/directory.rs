@@ -0,0 +1,262 @@
+//! Tailnet peers as last read from the transport, refreshed in the background
+//!
+//! Components that need peer names or want to know when a peer comes and goes read the
+//! directory or subscribe to its events, rather than each asking the local API.
+
+use crate::events::{EventSender, SyncEvent};
+use crate::{Result, TailnetPeer, Transport};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// How often the directory is refreshed unless configured otherwise
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A tailnet peer seen since the daemon started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryPeer {
+    pub address: String,
+    pub name: String,
+    /// Whether it was online at the last refresh
+    pub online: bool,
+}
+
+#[derive(Default)]
+struct Entries {
+    peers: BTreeMap<String, DirectoryPeer>,
+    /// Set by the first refresh, whose peers aren't reported as coming online
+    seeded: bool,
+    /// When the last refresh succeeded, cleared when one fails
+    refreshed_at: Option<Instant>,
+    /// How often `run` refreshes, once started
+    interval: Option<Duration>,
+}
+
+pub struct PeerDirectory {
+    transport: Arc<dyn Transport>,
+    entries: RwLock<Entries>,
+    events: Option<EventSender>,
+}
+
+impl PeerDirectory {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            entries: RwLock::new(Entries::default()),
+            events: None,
+        }
+    }
+
+    /// Publish peers coming online and going offline to `events`
+    pub fn with_events(mut self, events: EventSender) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Every peer seen so far, by address
+    pub fn peers(&self) -> Vec<DirectoryPeer> {
+        self.read().peers.values().cloned().collect()
+    }
+
+    /// Friendly name of the peer at `address`, if it has been seen
+    pub fn name_of(&self, address: &str) -> Option<String> {
+        self.read().peers.get(address).map(|peer| peer.name.clone())
+    }
+
+    /// Addresses of the online peers, or `None` unless the last refresh succeeded
+    /// within two refresh intervals
+    pub fn online_addresses(&self) -> Option<Vec<String>> {
+        let entries = self.read();
+        let interval = entries.interval.unwrap_or(DEFAULT_REFRESH_INTERVAL);
+        let fresh = entries
+            .refreshed_at
+            .is_some_and(|at| at.elapsed() <= interval * 2);
+        fresh.then(|| {
+            entries
+                .peers
+                .values()
+                .filter(|peer| peer.online)
+                .map(|peer| peer.address.clone())
+                .collect()
+        })
+    }
+
+    /// Whether the last refresh succeeded no longer than `age` ago
+    pub fn refreshed_within(&self, age: Duration) -> bool {
+        self.read()
+            .refreshed_at
+            .is_some_and(|at| at.elapsed() <= age)
+    }
+
+    /// Read the peers from the transport, publishing the ones that came or went
+    pub async fn refresh(&self) -> Result<()> {
+        let current = match self.transport.get_tailnet_peers().await {
+            Ok(current) => current,
+            Err(e) => {
+                self.write().refreshed_at = None;
+                return Err(e);
+            }
+        };
+        for event in self.update(current) {
+            debug!("Peer directory: {:?}", event);
+            if let Some(events) = &self.events {
+                // Nobody listening is fine
+                let _ = events.send(event);
+            }
+        }
+        Ok(())
+    }
+
+    /// Refresh every `interval` until the task is cancelled
+    pub async fn run(&self, interval: Duration) -> Result<()> {
+        info!("Refreshing the tailnet peer directory every {:?}", interval);
+        self.write().interval = Some(interval);
+        loop {
+            // Tailscale being down is reported by the connectivity monitor
+            if let Err(e) = self.refresh().await {
+                debug!("Couldn't refresh the peer directory: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Replace the online peers with `current`, returning what changed
+    fn update(&self, current: Vec<TailnetPeer>) -> Vec<SyncEvent> {
+        let mut entries = self.write();
+        let report = entries.seeded;
+        entries.seeded = true;
+        entries.refreshed_at = Some(Instant::now());
+
+        let mut changes = Vec::new();
+        let mut online: BTreeMap<String, String> = current
+            .into_iter()
+            .map(|peer| (peer.address, peer.name))
+            .collect();
+        for peer in entries.peers.values_mut() {
+            match online.remove(&peer.address) {
+                Some(name) => {
+                    peer.name = name;
+                    if !peer.online {
+                        peer.online = true;
+                        changes.push(SyncEvent::TailnetPeerOnline {
+                            address: peer.address.clone(),
+                            name: peer.name.clone(),
+                        });
+                    }
+                }
+                None if peer.online => {
+                    peer.online = false;
+                    changes.push(SyncEvent::TailnetPeerOffline {
+                        address: peer.address.clone(),
+                        name: peer.name.clone(),
+                    });
+                }
+                None => {}
+            }
+        }
+        for (address, name) in online {
+            changes.push(SyncEvent::TailnetPeerOnline {
+                address: address.clone(),
+                name: name.clone(),
+            });
+            entries.peers.insert(
+                address.clone(),
+                DirectoryPeer {
+                    address,
+                    name,
+                    online: true,
+                },
+            );
+        }
+
+        if report {
+            changes
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Entries> {
+        self.entries.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Entries> {
+        self.entries.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockTransport;
+
+    fn peer(address: &str, name: &str) -> TailnetPeer {
+        TailnetPeer {
+            address: address.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_peers_coming_and_going_are_reported_after_the_first_refresh() {
+        let directory = PeerDirectory::new(Arc::new(MockTransport::new("node-a".to_string())));
+        assert!(directory
+            .update(vec![peer("100.64.0.2", "laptop")])
+            .is_empty());
+        assert_eq!(directory.name_of("100.64.0.2").as_deref(), Some("laptop"));
+
+        assert_eq!(
+            directory.update(vec![peer("100.64.0.3", "phone")]),
+            [
+                SyncEvent::TailnetPeerOffline {
+                    address: "100.64.0.2".to_string(),
+                    name: "laptop".to_string(),
+                },
+                SyncEvent::TailnetPeerOnline {
+                    address: "100.64.0.3".to_string(),
+                    name: "phone".to_string(),
+                },
+            ]
+        );
+        // Offline peers stay known by name
+        assert_eq!(directory.peers().len(), 2);
+        assert!(!directory.peers()[0].online);
+
+        assert!(directory
+            .update(vec![peer("100.64.0.3", "phone")])
+            .is_empty());
+        assert_eq!(
+            directory.update(vec![
+                peer("100.64.0.2", "work-laptop"),
+                peer("100.64.0.3", "phone")
+            ]),
+            [SyncEvent::TailnetPeerOnline {
+                address: "100.64.0.2".to_string(),
+                name: "work-laptop".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_online_addresses_need_a_recent_refresh() {
+        let directory = PeerDirectory::new(Arc::new(MockTransport::new("node-a".to_string())));
+        assert_eq!(directory.online_addresses(), None);
+        assert!(!directory.refreshed_within(Duration::from_secs(60)));
+
+        directory.update(vec![
+            peer("100.64.0.2", "laptop"),
+            peer("100.64.0.3", "phone"),
+        ]);
+        directory.update(vec![peer("100.64.0.3", "phone")]);
+        assert_eq!(
+            directory.online_addresses(),
+            Some(vec!["100.64.0.3".to_string()])
+        );
+        assert!(directory.refreshed_within(Duration::from_secs(60)));
+
+        // A refresh that fails leaves nothing to read from
+        directory.write().refreshed_at = None;
+        assert_eq!(directory.online_addresses(), None);
+    }
+}
@@ -17,17 +17,88 @@ pub enum PostError {
     #[error("Network error: {0}")]
     Network(String),
 
+    /// A peer or helper took longer than allowed to answer
+    #[error("Timed out: {0}")]
+    Timeout(String),
+
+    /// A peer couldn't be connected to
+    #[error("Peer unreachable: {0}")]
+    PeerUnreachable(String),
+
+    /// A message's signature doesn't match the key its sender announced
+    #[error("Invalid signature: {0}")]
+    SignatureInvalid(String),
+
+    /// A message came from a node whose keys haven't been announced to this one
+    #[error("Unknown peer: {0}")]
+    UnknownPeer(String),
+
+    /// The clipboard backend, such as wl-clipboard or the system clipboard, can't be used
+    #[error("Clipboard backend unavailable: {0}")]
+    BackendUnavailable(String),
+
+    /// A clipboard tool, such as wl-copy or xclip, isn't installed
+    #[error("Clipboard tool not installed: {0}")]
+    BackendMissing(String),
+
     #[error("Serialization error: {0}")]
     Serialization(String),
 
+    #[error("Storage error: {0}")]
+    Storage(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("TOML error: {0}")]
     Toml(#[from] toml::de::Error),
 
+    #[error("Filtered: {0}")]
+    Filtered(String),
+
     #[error("Other error: {0}")]
     Other(String),
 }
 
+impl PostError {
+    /// Why the clipboard tool `command` couldn't be run: missing, which trying again won't
+    /// fix, or otherwise unavailable
+    pub fn from_command(command: &str, e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => PostError::BackendMissing(command.to_string()),
+            _ => PostError::BackendUnavailable(format!("Failed to execute {}: {}", command, e)),
+        }
+    }
+
+    /// Whether trying again later may succeed, as when a peer was briefly offline, rather
+    /// than failing the same way every time
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            PostError::Network(_)
+                | PostError::Timeout(_)
+                | PostError::PeerUnreachable(_)
+                | PostError::BackendUnavailable(_)
+        )
+    }
+}
+
 pub type Result<T> = std::result::Result<T, PostError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_transient_failures_are_retryable() {
+        assert!(PostError::Timeout("connecting to 100.64.0.2:8412".to_string()).is_retryable());
+        assert!(PostError::PeerUnreachable("100.64.0.2:8412".to_string()).is_retryable());
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(!PostError::from_command("wl-paste", missing).is_retryable());
+        let busy = std::io::Error::from(std::io::ErrorKind::ResourceBusy);
+        assert!(PostError::from_command("wl-paste", busy).is_retryable());
+        assert!(!PostError::SignatureInvalid("from node-b".to_string()).is_retryable());
+        assert!(!PostError::UnknownPeer("node-b".to_string()).is_retryable());
+        assert!(!PostError::Config("invalid network.port".to_string()).is_retryable());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::redact::Redacted;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio::sync::broadcast;
+
+/// Events a slow subscriber can fall behind by before it misses some
+const EVENT_BUFFER: usize = 256;
+
+/// Publishes [`SyncEvent`]s to local subscribers such as `post watch`
+pub type EventSender = broadcast::Sender<SyncEvent>;
+
+/// Sees every [`SyncEvent`] as it happens, for consumers such as the audit log that
+/// mustn't miss any when a broadcast subscriber would lag
+pub type EventHook = std::sync::Arc<dyn Fn(&SyncEvent) + Send + Sync>;
+
+/// A sender with no subscribers yet
+pub fn event_channel() -> EventSender {
+    broadcast::channel(EVENT_BUFFER).0
+}
+
+/// Something the daemon did that scripts may want to react to
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SyncEvent {
+    /// Clipboard content from a peer was applied here
+    Received {
+        from: String,
+        from_name: String,
+        content: String,
+        timestamp: u64,
+    },
+    /// Clipboard content copied here was sent to `peers` peers
+    Sent {
+        content: String,
+        peers: usize,
+        timestamp: u64,
+    },
+    PeerDiscovered {
+        id: String,
+        name: String,
+    },
+    /// A peer went quiet for long enough to be dropped until it announces itself again
+    PeerOffline {
+        id: String,
+        name: String,
+        /// Unix time it was last heard from
+        last_seen: u64,
+    },
+    /// A tailnet peer came online, whether or not it runs Post
+    TailnetPeerOnline {
+        address: String,
+        name: String,
+    },
+    /// A tailnet peer went offline in Tailscale
+    TailnetPeerOffline {
+        address: String,
+        name: String,
+    },
+    Connected {
+        node_id: String,
+    },
+    Disconnected,
+    /// The local clipboard stopped answering, so copies here aren't synced for now
+    ClipboardUnavailable {
+        error: String,
+    },
+    ClipboardRecovered,
+    /// Copies here stopped being synced until sync is resumed
+    Paused,
+    Resumed,
+    /// A peer's clock drifted more than `sync.max_clock_skew_secs` from this node's
+    ClockSkew {
+        node_id: String,
+        node_name: String,
+        /// Seconds the peer's clock is ahead; negative when it is behind
+        skew_secs: i64,
+    },
+    /// Clipboard content was left unsynced by a filter or rule, or a peer's message was
+    /// refused
+    Filtered {
+        /// Peer ID the content came from; unset for a copy made here
+        from: Option<String>,
+        reason: String,
+    },
+}
+
+/// Redacts clipboard content, like [`crate::ClipboardData`]
+impl fmt::Debug for SyncEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncEvent::Received { from, content, .. } => f
+                .debug_struct("Received")
+                .field("from", from)
+                .field("content", &Redacted(content))
+                .finish(),
+            SyncEvent::Sent { content, peers, .. } => f
+                .debug_struct("Sent")
+                .field("content", &Redacted(content))
+                .field("peers", peers)
+                .finish(),
+            SyncEvent::PeerDiscovered { id, name } => f
+                .debug_struct("PeerDiscovered")
+                .field("id", id)
+                .field("name", name)
+                .finish(),
+            SyncEvent::PeerOffline {
+                id,
+                name,
+                last_seen,
+            } => f
+                .debug_struct("PeerOffline")
+                .field("id", id)
+                .field("name", name)
+                .field("last_seen", last_seen)
+                .finish(),
+            SyncEvent::TailnetPeerOnline { address, name } => f
+                .debug_struct("TailnetPeerOnline")
+                .field("address", address)
+                .field("name", name)
+                .finish(),
+            SyncEvent::TailnetPeerOffline { address, name } => f
+                .debug_struct("TailnetPeerOffline")
+                .field("address", address)
+                .field("name", name)
+                .finish(),
+            SyncEvent::Connected { node_id } => f
+                .debug_struct("Connected")
+                .field("node_id", node_id)
+                .finish(),
+            SyncEvent::Disconnected => f.write_str("Disconnected"),
+            SyncEvent::ClipboardUnavailable { error } => f
+                .debug_struct("ClipboardUnavailable")
+                .field("error", error)
+                .finish(),
+            SyncEvent::ClipboardRecovered => f.write_str("ClipboardRecovered"),
+            SyncEvent::Paused => f.write_str("Paused"),
+            SyncEvent::Resumed => f.write_str("Resumed"),
+            SyncEvent::ClockSkew {
+                node_id,
+                node_name,
+                skew_secs,
+            } => f
+                .debug_struct("ClockSkew")
+                .field("node_id", node_id)
+                .field("node_name", node_name)
+                .field("skew_secs", skew_secs)
+                .finish(),
+            SyncEvent::Filtered { from, reason } => f
+                .debug_struct("Filtered")
+                .field("from", from)
+                .field("reason", reason)
+                .finish(),
+        }
+    }
+}
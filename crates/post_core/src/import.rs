@@ -0,0 +1,322 @@
+//! Clipboard history kept by other clipboard managers, read so it can move into Post's
+
+use crate::{PostError, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+/// A clipboard manager whose history can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistorySource {
+    /// clipman's history file, a JSON array of strings with the newest last
+    Clipman,
+    /// A CopyQ tab's item file, such as `~/.config/copyq/copyq_tab_JmNsaXBib2FyZA==.dat`
+    Copyq,
+    /// Maccy's `Storage.sqlite` database
+    Maccy,
+}
+
+impl HistorySource {
+    pub const ALL: [HistorySource; 3] = [
+        HistorySource::Clipman,
+        HistorySource::Copyq,
+        HistorySource::Maccy,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HistorySource::Clipman => "clipman",
+            HistorySource::Copyq => "copyq",
+            HistorySource::Maccy => "maccy",
+        }
+    }
+
+    /// Text items of the history at `path`, newest first
+    pub fn read(self, path: &Path) -> Result<Vec<String>> {
+        let invalid = |e: String| {
+            PostError::Serialization(format!(
+                "{} is not a {} history: {}",
+                path.display(),
+                self,
+                e
+            ))
+        };
+        let items = match self {
+            HistorySource::Clipman => {
+                let mut items: Vec<String> = serde_json::from_slice(&std::fs::read(path)?)
+                    .map_err(|e| invalid(e.to_string()))?;
+                items.reverse();
+                items
+            }
+            HistorySource::Copyq => parse_copyq(&std::fs::read(path)?).map_err(invalid)?,
+            HistorySource::Maccy => read_maccy(path).map_err(|e| invalid(e.to_string()))?,
+        };
+        Ok(items
+            .into_iter()
+            .filter(|item| !item.trim().is_empty())
+            .collect())
+    }
+}
+
+impl fmt::Display for HistorySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for HistorySource {
+    type Err = PostError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        HistorySource::ALL
+            .into_iter()
+            .find(|source| source.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                PostError::Other(format!(
+                    "Unknown clipboard manager {}; expected clipman, copyq or maccy",
+                    s
+                ))
+            })
+    }
+}
+
+/// `history` followed by the `imported` items it doesn't already hold, both newest first,
+/// keeping at most `limit` items
+pub fn merge(history: Vec<String>, imported: Vec<String>, limit: usize) -> Vec<String> {
+    let mut seen = HashSet::new();
+    history
+        .into_iter()
+        .chain(imported)
+        .filter(|item| seen.insert(item.clone()))
+        .take(limit)
+        .collect()
+}
+
+/// MIME type of plain text, as CopyQ abbreviates it in its item files
+const COPYQ_TEXT: [&str; 2] = ["text/plain", "1plain"];
+
+/// Items of a CopyQ tab file, which Qt's `QDataStream` wrote as a version header, the item
+/// count, then each item's data by MIME type
+fn parse_copyq(data: &[u8]) -> std::result::Result<Vec<String>, String> {
+    let mut stream = QDataStream { data };
+    let header = stream.string()?;
+    if !header.starts_with("CopyQ v") {
+        return Err("missing the CopyQ header".to_string());
+    }
+
+    let count = stream.i32()?;
+    let mut items = Vec::new();
+    for _ in 0..count {
+        // Versions since CopyQ 3 mark items with -2; older ones compressed every item
+        if stream.i32()? != -2 {
+            return Err(
+                "items are in an old format; open the tab in a newer CopyQ first".to_string(),
+            );
+        }
+        let mut text = None;
+        for _ in 0..stream.i32()? {
+            let mime = stream.string()?;
+            let compressed = stream.bool()?;
+            let bytes = stream.bytes()?;
+            let is_text = COPYQ_TEXT
+                .iter()
+                .any(|text| mime.split(';').next() == Some(*text));
+            if !is_text {
+                continue;
+            }
+            let bytes = if compressed {
+                q_uncompress(bytes)?
+            } else {
+                bytes.to_vec()
+            };
+            text = Some(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        items.extend(text);
+    }
+    Ok(items)
+}
+
+/// Data compressed by Qt's `qCompress`: its length as a big-endian `u32`, then zlib
+fn q_uncompress(data: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let Some((len, zlib)) = data.split_first_chunk::<4>() else {
+        return Err("a compressed item ends early".to_string());
+    };
+    let mut bytes = Vec::with_capacity(u32::from_be_bytes(*len).min(1 << 24) as usize);
+    flate2::read::ZlibDecoder::new(zlib)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("a compressed item is damaged: {}", e))?;
+    Ok(bytes)
+}
+
+/// Reads big-endian values the way Qt's `QDataStream` writes them
+struct QDataStream<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> QDataStream<'a> {
+    fn take(&mut self, len: usize) -> std::result::Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("the file ends early".to_string());
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> std::result::Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> std::result::Result<i32, String> {
+        self.u32().map(|n| n as i32)
+    }
+
+    fn bool(&mut self) -> std::result::Result<bool, String> {
+        Ok(self.take(1)?[0] != 0)
+    }
+
+    /// A `QByteArray`; a null one reads as empty
+    fn bytes(&mut self) -> std::result::Result<&'a [u8], String> {
+        match self.u32()? {
+            u32::MAX => Ok(&[]),
+            len => self.take(len as usize),
+        }
+    }
+
+    /// A `QString`, in UTF-16
+    fn string(&mut self) -> std::result::Result<String, String> {
+        let units: Vec<u16> = self
+            .bytes()?
+            .chunks_exact(2)
+            .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+            .collect();
+        String::from_utf16(&units).map_err(|e| e.to_string())
+    }
+}
+
+/// Text items of Maccy's Core Data store, most recently copied first
+fn read_maccy(path: &Path) -> rusqlite::Result<Vec<String>> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut query = connection.prepare(
+        "SELECT content.ZVALUE FROM ZHISTORYITEMCONTENT content
+         JOIN ZHISTORYITEM item ON content.ZITEM = item.Z_PK
+         WHERE content.ZTYPE = 'public.utf8-plain-text'
+         ORDER BY item.ZLASTCOPIEDAT DESC",
+    )?;
+    let items = query.query_map([], |row| row.get::<_, Vec<u8>>(0))?;
+    items
+        .map(|item| item.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qt_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+        out.extend((bytes.len() as u32).to_be_bytes());
+        out.extend(bytes);
+    }
+
+    fn qt_string(out: &mut Vec<u8>, text: &str) {
+        let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        qt_bytes(out, &utf16);
+    }
+
+    #[test]
+    fn test_copyq_tab_items_are_read_newest_first() {
+        let mut tab = Vec::new();
+        qt_string(&mut tab, "CopyQ v3");
+        tab.extend(3i32.to_be_bytes());
+        for (mime, data) in [
+            ("1plain", "newest"),
+            ("1html", "<b>no plain text</b>"),
+            ("text/plain;charset=utf-8", "oldest \u{1f4cb}"),
+        ] {
+            tab.extend((-2i32).to_be_bytes());
+            tab.extend(2i32.to_be_bytes());
+            qt_string(&mut tab, "0owner");
+            tab.push(0);
+            qt_bytes(&mut tab, b"window");
+            qt_string(&mut tab, mime);
+            tab.push(0);
+            qt_bytes(&mut tab, data.as_bytes());
+        }
+
+        assert_eq!(parse_copyq(&tab).unwrap(), ["newest", "oldest \u{1f4cb}"]);
+        assert!(parse_copyq(&tab[..tab.len() - 1]).is_err());
+        assert!(parse_copyq(b"not a tab").is_err());
+    }
+
+    #[test]
+    fn test_compressed_copyq_items_are_uncompressed() {
+        use std::io::Write;
+
+        let text = "compressed ".repeat(20);
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        let mut compressed = (text.len() as u32).to_be_bytes().to_vec();
+        compressed.extend(encoder.finish().unwrap());
+
+        let mut tab = Vec::new();
+        qt_string(&mut tab, "CopyQ v3");
+        tab.extend(1i32.to_be_bytes());
+        tab.extend((-2i32).to_be_bytes());
+        tab.extend(1i32.to_be_bytes());
+        qt_string(&mut tab, "1plain");
+        tab.push(1);
+        qt_bytes(&mut tab, &compressed);
+        assert_eq!(parse_copyq(&tab).unwrap(), [text]);
+
+        let damaged = tab.len() - 2;
+        tab[damaged] ^= 0xff;
+        assert!(parse_copyq(&tab).is_err());
+    }
+
+    #[test]
+    fn test_clipman_and_maccy_histories_are_read_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let clipman = dir.path().join("clipman.json");
+        std::fs::write(&clipman, r#"["oldest", " ", "newest"]"#).unwrap();
+        assert_eq!(
+            HistorySource::Clipman.read(&clipman).unwrap(),
+            ["newest", "oldest"]
+        );
+
+        let maccy = dir.path().join("Storage.sqlite");
+        let connection = Connection::open(&maccy).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE ZHISTORYITEM (Z_PK INTEGER PRIMARY KEY, ZLASTCOPIEDAT REAL);
+                 CREATE TABLE ZHISTORYITEMCONTENT (Z_PK INTEGER PRIMARY KEY, ZITEM INTEGER, ZTYPE TEXT, ZVALUE BLOB);
+                 INSERT INTO ZHISTORYITEM VALUES (1, 100.0), (2, 300.0), (3, 200.0);
+                 INSERT INTO ZHISTORYITEMCONTENT VALUES
+                     (1, 1, 'public.utf8-plain-text', CAST('first' AS BLOB)),
+                     (2, 2, 'public.png', X'89504E47'),
+                     (3, 2, 'public.utf8-plain-text', CAST('latest' AS BLOB)),
+                     (4, 3, 'public.utf8-plain-text', CAST('middle' AS BLOB));",
+            )
+            .unwrap();
+        drop(connection);
+        assert_eq!(
+            HistorySource::Maccy.read(&maccy).unwrap(),
+            ["latest", "middle", "first"]
+        );
+        assert!(HistorySource::Maccy.read(&clipman).is_err());
+    }
+
+    #[test]
+    fn test_imported_items_go_after_existing_history() {
+        let history = vec!["b".to_string(), "a".to_string()];
+        let imported = vec!["c".to_string(), "a".to_string(), "d".to_string()];
+        assert_eq!(
+            merge(history.clone(), imported.clone(), 10),
+            ["b", "a", "c", "d"]
+        );
+        assert_eq!(merge(history, imported, 3), ["b", "a", "c"]);
+    }
+}
@@ -0,0 +1,356 @@
+//! Bounded queue between the transport and the daemon for messages from peers
+//!
+//! A flood of messages must not exhaust memory, so the queue holds at most `capacity`
+//! messages. When it is full, the node with the most messages queued makes room: a new
+//! clipboard update pushes out its oldest queued update (or its oldest message, if it has
+//! no update queued), since only the latest content ends up on the clipboard anyway. The
+//! queue is filled before signatures are checked, so this keeps one flooding host from
+//! pushing out everyone else's messages. Any other message arriving at a full queue from
+//! the busiest node is dropped. Drops are counted per inbox in [`InboxCounters`].
+
+use crate::{MessageData, PostMessage};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+/// Messages queued before the overflow policy kicks in
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Counters for messages the inbox had no room for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InboxMetrics {
+    /// Queued clipboard updates pushed out by newer ones
+    pub clipboard_updates_dropped: u64,
+    /// Other messages dropped on arrival
+    pub other_dropped: u64,
+}
+
+impl InboxMetrics {
+    pub fn dropped(&self) -> u64 {
+        self.clipboard_updates_dropped + self.other_dropped
+    }
+}
+
+/// Drop counters of one inbox, shared with whatever reports them
+#[derive(Debug, Clone, Default)]
+pub struct InboxCounters {
+    clipboard_updates_dropped: Arc<AtomicU64>,
+    other_dropped: Arc<AtomicU64>,
+}
+
+impl InboxCounters {
+    pub fn metrics(&self) -> InboxMetrics {
+        InboxMetrics {
+            clipboard_updates_dropped: self.clipboard_updates_dropped.load(Ordering::Relaxed),
+            other_dropped: self.other_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The daemon stopped taking messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InboxClosed;
+
+impl std::fmt::Display for InboxClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "inbox closed")
+    }
+}
+
+impl std::error::Error for InboxClosed {}
+
+struct Shared {
+    queue: Mutex<VecDeque<PostMessage>>,
+    capacity: usize,
+    counters: InboxCounters,
+    ready: Notify,
+    senders: AtomicUsize,
+    receiver_dropped: AtomicBool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, VecDeque<PostMessage>> {
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Create an inbox holding up to `capacity` messages
+pub fn channel(capacity: usize) -> (InboxSender, InboxReceiver) {
+    channel_counted(capacity, InboxCounters::default())
+}
+
+/// Create an inbox holding up to `capacity` messages that counts its drops in `counters`
+pub fn channel_counted(capacity: usize, counters: InboxCounters) -> (InboxSender, InboxReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::new()),
+        capacity: capacity.max(1),
+        counters,
+        ready: Notify::new(),
+        senders: AtomicUsize::new(1),
+        receiver_dropped: AtomicBool::new(false),
+    });
+    (
+        InboxSender {
+            shared: Arc::clone(&shared),
+        },
+        InboxReceiver { shared },
+    )
+}
+
+fn is_clipboard_update(message: &PostMessage) -> bool {
+    matches!(message.data, MessageData::ClipboardUpdate(_))
+}
+
+/// The node with the most messages in `queue`, counting `incoming` from `source`;
+/// `source` itself on a tie
+fn busiest_source(queue: &VecDeque<PostMessage>, source: &str) -> String {
+    let mut queued: HashMap<&str, usize> = HashMap::new();
+    for message in queue {
+        *queued.entry(message.source_node()).or_default() += 1;
+    }
+    let own = queued.get(source).copied().unwrap_or(0) + 1;
+    queued
+        .into_iter()
+        .filter(|(node, count)| *node != source && *count > own)
+        .max_by_key(|(_, count)| *count)
+        .map_or(source, |(node, _)| node)
+        .to_string()
+}
+
+/// The transport's end of the inbox
+pub struct InboxSender {
+    shared: Arc<Shared>,
+}
+
+impl InboxSender {
+    /// Queue `message`, applying the overflow policy when the inbox is full
+    ///
+    /// Only fails once the receiver is gone; a dropped message is not an error.
+    pub fn send(&self, message: PostMessage) -> std::result::Result<(), InboxClosed> {
+        if self.shared.receiver_dropped.load(Ordering::Acquire) {
+            return Err(InboxClosed);
+        }
+
+        let counters = &self.shared.counters;
+        let mut queue = self.shared.lock();
+        if queue.len() >= self.shared.capacity {
+            let source = message.source_node();
+            let busiest = busiest_source(&queue, source);
+            if busiest == source && !is_clipboard_update(&message) {
+                drop(queue);
+                record_drop(&counters.other_dropped, "message");
+                return Ok(());
+            }
+            let from_busiest = |queued: &PostMessage| queued.source_node() == busiest;
+            // With every node down to a message or so, fall back to the oldest update
+            let oldest = queue
+                .iter()
+                .position(|queued| from_busiest(queued) && is_clipboard_update(queued))
+                .or_else(|| queue.iter().position(from_busiest))
+                .or_else(|| queue.iter().position(is_clipboard_update))
+                .unwrap_or(0);
+            let pushed_out = queue.remove(oldest);
+            if pushed_out.as_ref().is_some_and(is_clipboard_update) {
+                record_drop(&counters.clipboard_updates_dropped, "clipboard update");
+            } else {
+                record_drop(&counters.other_dropped, "message");
+            }
+        }
+        queue.push_back(message);
+        drop(queue);
+
+        self.shared.ready.notify_one();
+        Ok(())
+    }
+
+    /// Where this inbox counts the messages it had no room for
+    pub fn counters(&self) -> InboxCounters {
+        self.shared.counters.clone()
+    }
+
+    /// Messages waiting to be handled
+    pub fn len(&self) -> usize {
+        self.shared.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Count a drop, warning on the first and then ever more rarely so a flood doesn't
+/// flood the log too
+fn record_drop(counter: &AtomicU64, what: &str) {
+    let dropped = counter.fetch_add(1, Ordering::Relaxed) + 1;
+    if dropped.is_power_of_two() {
+        warn!(
+            "Inbox full; dropped a {} ({} so far). Peers are sending faster than they can be handled.",
+            what, dropped
+        );
+    } else {
+        debug!("Inbox full; dropped a {}", what);
+    }
+}
+
+impl Clone for InboxSender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Drop for InboxSender {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Wake the receiver so it sees there is nothing more to come
+            self.shared.ready.notify_one();
+        }
+    }
+}
+
+/// The daemon's end of the inbox
+pub struct InboxReceiver {
+    shared: Arc<Shared>,
+}
+
+impl InboxReceiver {
+    /// The next message, oldest first; `None` once every sender is gone and the queue
+    /// is drained
+    pub async fn recv(&mut self) -> Option<PostMessage> {
+        loop {
+            let ready = self.shared.ready.notified();
+            {
+                let mut queue = self.shared.lock();
+                if let Some(message) = queue.pop_front() {
+                    return Some(message);
+                }
+                if self.shared.senders.load(Ordering::Acquire) == 0 {
+                    return None;
+                }
+            }
+            ready.await;
+        }
+    }
+}
+
+impl Drop for InboxReceiver {
+    fn drop(&mut self) {
+        self.shared.receiver_dropped.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClipboardData, HeartbeatData, MessageType, TEXT_PLAIN};
+
+    fn update(content: &str) -> PostMessage {
+        update_from("node-a", content)
+    }
+
+    fn update_from(source_node: &str, content: &str) -> PostMessage {
+        PostMessage {
+            version: 1,
+            message_type: MessageType::ClipboardUpdate,
+            data: MessageData::ClipboardUpdate(ClipboardData {
+                content: content.into(),
+                timestamp: 1,
+                source_node: source_node.to_string(),
+                sequence: 1,
+                tailnet: None,
+                content_type: TEXT_PLAIN.to_string(),
+                metadata: Default::default(),
+            }),
+            signature: Vec::new(),
+        }
+    }
+
+    fn heartbeat() -> PostMessage {
+        PostMessage {
+            version: 1,
+            message_type: MessageType::Heartbeat,
+            data: MessageData::Heartbeat(HeartbeatData {
+                source_node: "node-a".to_string(),
+                timestamp: 1,
+            }),
+            signature: Vec::new(),
+        }
+    }
+
+    fn content(message: &PostMessage) -> &str {
+        match &message.data {
+            MessageData::ClipboardUpdate(data) => &data.content,
+            _ => "heartbeat",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_inbox_keeps_the_latest_clipboard_updates() {
+        let (sender, mut receiver) = channel(3);
+        sender.send(heartbeat()).unwrap();
+        sender.send(update("first")).unwrap();
+        sender.send(update("second")).unwrap();
+
+        // The oldest update makes way, and the heartbeat keeps its place
+        sender.send(update("third")).unwrap();
+        // Other messages have to wait for room
+        sender.send(heartbeat()).unwrap();
+        assert_eq!(sender.len(), 3);
+        assert_eq!(
+            sender.counters().metrics(),
+            InboxMetrics {
+                clipboard_updates_dropped: 1,
+                other_dropped: 1,
+            }
+        );
+
+        drop(sender);
+        let mut received = Vec::new();
+        while let Some(message) = receiver.recv().await {
+            received.push(content(&message).to_string());
+        }
+        assert_eq!(received, ["heartbeat", "second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn test_a_flooding_node_only_pushes_out_its_own_messages() {
+        let (sender, mut receiver) = channel(4);
+        sender.send(update_from("node-a", "from a")).unwrap();
+        sender.send(update_from("node-b", "from b")).unwrap();
+        for n in 0..10 {
+            sender
+                .send(update_from("mallory", &format!("flood {}", n)))
+                .unwrap();
+        }
+        // Once mallory has the most queued, newcomers push out mallory's messages
+        sender.send(update_from("node-c", "from c")).unwrap();
+        assert_eq!(sender.counters().metrics().clipboard_updates_dropped, 9);
+
+        drop(sender);
+        let mut received = Vec::new();
+        while let Some(message) = receiver.recv().await {
+            received.push(content(&message).to_string());
+        }
+        assert_eq!(received, ["from a", "from b", "flood 9", "from c"]);
+    }
+
+    #[tokio::test]
+    async fn test_receiver_waits_for_messages_and_sender_sees_it_close() {
+        let (sender, mut receiver) = channel(DEFAULT_CAPACITY);
+        let late = sender.clone();
+        tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            late.send(update("late")).unwrap();
+        });
+        assert_eq!(content(&receiver.recv().await.unwrap()), "late");
+
+        drop(receiver);
+        assert_eq!(sender.send(heartbeat()), Err(InboxClosed));
+    }
+}
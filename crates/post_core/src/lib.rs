@@ -1,26 +1,117 @@
+pub mod archive;
+pub mod bench;
+pub mod circuit;
 pub mod clipboard;
+pub mod compat;
+pub mod concealed;
 pub mod config;
 pub mod crypto;
+pub mod directory;
 pub mod error;
+pub mod events;
+pub mod import;
+pub mod inbox;
+mod mock;
+pub mod pins;
+pub mod redact;
+pub mod search;
+pub mod selftest;
+pub mod snippets;
+pub mod source_app;
+pub mod store;
 pub mod sync;
+pub mod taildrop;
+pub mod tailscale_cli;
+pub mod text;
+pub mod transform;
 pub mod transport;
+pub mod wire;
 
 pub use clipboard::*;
 pub use config::*;
 pub use crypto::*;
 pub use error::*;
+pub use events::*;
+#[cfg(any(test, feature = "test-util"))]
+pub use mock::{InMemoryNetwork, InMemoryTransport, MockClipboard, MockTransport};
+pub use store::{StateKey, StateStore};
 pub use sync::*;
 pub use transport::*;
+pub use wire::*;
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Content type of clipboard updates from builds that predate the field
+pub const TEXT_PLAIN: &str = "text/plain";
+
+/// Keys of [`ClipboardData::metadata`] that Post itself sets
+pub mod metadata {
+    /// Character encoding `content` was copied in, when it wasn't UTF-8
+    pub const ENCODING: &str = "encoding";
+    /// Application `content` was copied in, shared with `sync.share_origin_app`
+    pub const ORIGIN_APP: &str = "origin_app";
+}
+
+/// A clipboard update
+///
+/// Every field, the content type and metadata included, is covered by the message
+/// signature. Fields only appear on the wire when they differ from their defaults, so
+/// plain-text updates without metadata still verify on builds that predate them.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ClipboardData {
-    pub content: String,
+    /// Shared, so the copies of an update kept for retries and replays cost no more
+    /// than a reference count
+    pub content: Arc<str>,
     pub timestamp: u64,
     pub source_node: String,
     pub sequence: u64,
+    /// Tailnet the sender is in, as its MagicDNS suffix; unset by older builds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tailnet: Option<String>,
+    /// MIME type of `content`; receivers refuse types they can't put on a clipboard
+    #[serde(default = "text_plain", skip_serializing_if = "is_text_plain")]
+    pub content_type: String,
+    /// Extra facts about the content, such as [`metadata::ORIGIN_APP`]; keys a receiver
+    /// doesn't know are kept, so new ones need no change to the message
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl ClipboardData {
+    /// Whether `content_type` is text, which is all a clipboard update can carry so far
+    pub fn is_text(&self) -> bool {
+        self.content_type
+            .split(';')
+            .next()
+            .is_some_and(|mime| mime.trim().starts_with("text/"))
+    }
+}
+
+fn text_plain() -> String {
+    TEXT_PLAIN.to_string()
+}
+
+fn is_text_plain(content_type: &str) -> bool {
+    content_type == TEXT_PLAIN
+}
+
+/// Redacts `content`, so logging a message never reveals what was copied
+impl fmt::Debug for ClipboardData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClipboardData")
+            .field("content", &redact::Redacted(&self.content))
+            .field("timestamp", &self.timestamp)
+            .field("source_node", &self.source_node)
+            .field("sequence", &self.sequence)
+            .field("tailnet", &self.tailnet)
+            .field("content_type", &self.content_type)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +120,27 @@ pub struct NodeDiscoveryData {
     pub timestamp: u64,
     pub public_key: [u8; 32],
     pub signing_public_key: [u8; 32],
+    /// Wire formats the sender can decode, most preferred first
+    #[serde(default)]
+    pub wire_formats: Vec<String>,
+    /// Human-friendly name of the sender, e.g. its Tailscale machine name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_name: Option<String>,
+    /// Ask receivers to answer with their own discovery message
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub wants_reply: bool,
+    /// Post version of the sender; unset for builds that predate it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Optional features the sender supports, see [`compat::Capability`]
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Address to connect to the sender on, when it is not its Tailscale IP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Port the sender accepts sync connections on; unset for older builds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,11 +149,142 @@ pub struct HeartbeatData {
     pub timestamp: u64,
 }
 
+/// Confirms that `source_node` applied the update `sequence` from `origin_node`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AckData {
+    pub source_node: String,
+    pub origin_node: String,
+    pub sequence: u64,
+    pub timestamp: u64,
+}
+
+/// Announces clipboard content too large for the sync channel, sent ahead via Taildrop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaildropData {
+    pub source_node: String,
+    pub file_name: String,
+    pub size: u64,
+    pub sequence: u64,
+    pub timestamp: u64,
+}
+
+/// Asks `target_node` to answer with a [`PongData`], to check that it can be reached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingData {
+    pub source_node: String,
+    pub target_node: String,
+    pub nonce: u64,
+    pub timestamp: u64,
+}
+
+/// Answers the ping `nonce` from `origin_node`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PongData {
+    pub source_node: String,
+    pub origin_node: String,
+    pub nonce: u64,
+    /// Whether the ping's signature checked out against the key known for `origin_node`;
+    /// always true from builds that only answer pings they could verify
+    pub verified: bool,
+    pub timestamp: u64,
+}
+
+/// Asks `target_node` for its current clipboard, answered with a [`CollectResponseData`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectRequestData {
+    pub source_node: String,
+    pub target_node: String,
+    pub nonce: u64,
+    pub timestamp: u64,
+}
+
+/// Answers the collect request `nonce` from `origin_node`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CollectResponseData {
+    pub source_node: String,
+    pub origin_node: String,
+    pub nonce: u64,
+    /// The clipboard, or why it wasn't shared
+    pub content: std::result::Result<String, String>,
+    pub timestamp: u64,
+}
+
+/// A synthetic payload for `target_node` to answer with a [`BenchReplyData`], timing
+/// the sync channel
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BenchData {
+    pub source_node: String,
+    pub target_node: String,
+    pub nonce: u64,
+    pub payload: String,
+    pub timestamp: u64,
+}
+
+/// Shows the size of `payload` rather than megabytes of filler
+impl fmt::Debug for BenchData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BenchData")
+            .field("source_node", &self.source_node)
+            .field("target_node", &self.target_node)
+            .field("nonce", &self.nonce)
+            .field("payload", &format_args!("<{} bytes>", self.payload.len()))
+            .field("timestamp", &self.timestamp)
+            .finish()
+    }
+}
+
+/// Answers the bench payload `nonce` from `origin_node`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReplyData {
+    pub source_node: String,
+    pub origin_node: String,
+    pub nonce: u64,
+    /// Bytes of payload that arrived
+    pub received: u64,
+    pub timestamp: u64,
+}
+
+/// Redacts `content`, like [`ClipboardData`]
+impl fmt::Debug for CollectResponseData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CollectResponseData")
+            .field("source_node", &self.source_node)
+            .field("origin_node", &self.origin_node)
+            .field("nonce", &self.nonce)
+            .field(
+                "content",
+                &self
+                    .content
+                    .as_ref()
+                    .map(|content| redact::Redacted(content)),
+            )
+            .field("timestamp", &self.timestamp)
+            .finish()
+    }
+}
+
+/// Changes to `source_node`'s pinned items, or all of them for a newly discovered peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinsData {
+    pub source_node: String,
+    pub entries: Vec<pins::PinEntry>,
+    pub timestamp: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageData {
     ClipboardUpdate(ClipboardData),
     NodeDiscovery(NodeDiscoveryData),
     Heartbeat(HeartbeatData),
+    Ack(AckData),
+    TaildropOffer(TaildropData),
+    Pins(PinsData),
+    Ping(PingData),
+    Pong(PongData),
+    CollectRequest(CollectRequestData),
+    CollectResponse(CollectResponseData),
+    Bench(BenchData),
+    BenchReply(BenchReplyData),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,22 +292,158 @@ pub struct PostMessage {
     pub version: u8,
     pub message_type: MessageType,
     pub data: MessageData,
+    #[serde(with = "serde_bytes")]
     pub signature: Vec<u8>,
 }
 
+impl PostMessage {
+    /// Check that a discovery message was signed with the key it announces
+    ///
+    /// That only proves the sender holds the key; whether the key belongs to the node it
+    /// claims to be is up to [`SyncManager`].
+    pub fn verify_discovery(&self, data: &NodeDiscoveryData) -> Result<()> {
+        let mut unsigned = self.clone();
+        unsigned.signature = Vec::new();
+        let message_bytes = serde_json::to_vec(&unsigned).map_err(|e| {
+            PostError::Serialization(format!(
+                "Failed to serialize message for verification: {}",
+                e
+            ))
+        })?;
+
+        if !verify_signature(&data.signing_public_key, &message_bytes, &self.signature)? {
+            return Err(PostError::SignatureInvalid(format!(
+                "node discovery from {}",
+                data.source_node
+            )));
+        }
+        Ok(())
+    }
+
+    /// The node this message says it is from, before any signature is checked
+    pub fn source_node(&self) -> &str {
+        match &self.data {
+            MessageData::ClipboardUpdate(data) => &data.source_node,
+            MessageData::NodeDiscovery(data) => &data.source_node,
+            MessageData::Heartbeat(data) => &data.source_node,
+            MessageData::Ack(data) => &data.source_node,
+            MessageData::TaildropOffer(data) => &data.source_node,
+            MessageData::Pins(data) => &data.source_node,
+            MessageData::Ping(data) => &data.source_node,
+            MessageData::Pong(data) => &data.source_node,
+            MessageData::CollectRequest(data) => &data.source_node,
+            MessageData::CollectResponse(data) => &data.source_node,
+            MessageData::Bench(data) => &data.source_node,
+            MessageData::BenchReply(data) => &data.source_node,
+        }
+    }
+
+    /// The one node this message is for, when it isn't meant for every peer
+    pub fn recipient(&self) -> Option<&str> {
+        match &self.data {
+            MessageData::Ping(data) => Some(&data.target_node),
+            MessageData::Pong(data) => Some(&data.origin_node),
+            MessageData::CollectRequest(data) => Some(&data.target_node),
+            MessageData::CollectResponse(data) => Some(&data.origin_node),
+            MessageData::Bench(data) => Some(&data.target_node),
+            MessageData::BenchReply(data) => Some(&data.origin_node),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageType {
     ClipboardUpdate,
     Heartbeat,
     NodeDiscovery,
+    Ack,
+    TaildropOffer,
+    Pins,
+    Ping,
+    Pong,
+    CollectRequest,
+    CollectResponse,
+    Bench,
+    BenchReply,
+}
+
+/// Sync activity with one peer, kept between runs along with the known peers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerStats {
+    /// Clipboard updates broadcast while the peer was known
+    pub updates_sent: u64,
+    pub bytes_sent: u64,
+    /// Verified clipboard updates received from the peer
+    pub updates_received: u64,
+    pub bytes_received: u64,
+    /// Acknowledgements the peer sent for our updates
+    pub acks_received: u64,
+    /// Updates the peer never acknowledged before they expired
+    pub updates_failed: u64,
+    /// Summed round trip of updates acknowledged without a retry
+    pub ack_latency_total: Duration,
+    pub ack_latency_samples: u64,
+    /// Unix time of the last update received from the peer
+    pub last_update: Option<u64>,
+    /// Whether the peer has yet to acknowledge our latest update
+    #[serde(skip)]
+    pub awaiting_ack: bool,
 }
 
-#[derive(Debug, Clone)]
+impl PeerStats {
+    /// Mean time from sending an update to the peer acknowledging it
+    pub fn average_ack_latency(&self) -> Option<Duration> {
+        (self.ack_latency_samples > 0)
+            .then(|| self.ack_latency_total / self.ack_latency_samples as u32)
+    }
+}
+
+/// How firmly a peer's verifying key is held
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerTrust {
+    /// The peer announced this key since startup; announcements with another key are refused
+    #[default]
+    Confirmed,
+    /// Known from an earlier run; its messages verify, and its next announcement may bring
+    /// a new key, since every node generates fresh keys when it starts
+    Remembered,
+}
+
+impl PeerTrust {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PeerTrust::Confirmed => "confirmed",
+            PeerTrust::Remembered => "remembered",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeInfo {
     pub id: String,
     pub name: String,
     pub last_seen: u64,
     pub public_key: Vec<u8>,
+    /// Post version the node advertised, if any
+    pub version: Option<String>,
+    pub capabilities: Vec<String>,
+    /// Sync endpoint the node advertised, if it differs from the defaults
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub trust: PeerTrust,
+}
+
+impl NodeInfo {
+    /// False only when the node advertised a version whose protocol differs from ours
+    pub fn is_compatible(&self) -> bool {
+        self.version
+            .as_deref()
+            .is_none_or(compat::is_compatible_version)
+    }
 }
 
 pub type NodeMap = HashMap<String, NodeInfo>;
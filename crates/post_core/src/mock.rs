@@ -0,0 +1,345 @@
+//! In-process stand-ins for Tailscale and the system clipboard, used by `post selftest`
+//! and, with the `test-util` feature, by tests
+
+use crate::inbox::InboxSender;
+use crate::wire::{decode_message, encode_message, WireFormat};
+use crate::{
+    ClipboardManager, ClipboardWatcher, MessageData, PostError, PostMessage, Result, Transport,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+
+#[cfg(any(test, feature = "test-util"))]
+pub struct MockTransport {
+    node_id: String,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockTransport {
+    pub fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+#[async_trait]
+impl Transport for MockTransport {
+    async fn send_message(&self, message: PostMessage) -> Result<()> {
+        debug!(
+            "Mock transport: would send message {:?}",
+            message.message_type
+        );
+        Ok(())
+    }
+
+    async fn start_listening(&self, _sender: InboxSender) -> Result<()> {
+        debug!("Mock transport: listening (no-op)");
+        tokio::time::sleep(std::time::Duration::from_secs(u64::MAX)).await;
+        Ok(())
+    }
+
+    async fn get_node_id(&self) -> Result<String> {
+        Ok(self.node_id.clone())
+    }
+
+    async fn get_tailnet_nodes(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(true) // Mock transport is always "connected"
+    }
+}
+
+#[derive(Default)]
+struct InMemoryNode {
+    inbox: Option<InboxSender>,
+    online: bool,
+}
+
+/// Shared in-process "tailnet" that routes messages between `InMemoryTransport`s.
+///
+/// Messages are serialized to the wire format and parsed again on delivery, so signatures
+/// are verified against exactly what a remote peer would receive.
+#[derive(Clone, Default)]
+pub struct InMemoryNetwork {
+    nodes: Arc<std::sync::Mutex<HashMap<String, InMemoryNode>>>,
+    /// Transport each node ID announced itself from, like a peer's IP over Tailscale
+    announced: Arc<std::sync::Mutex<HashMap<String, String>>>,
+    wire_format: WireFormat,
+}
+
+impl InMemoryNetwork {
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Network whose nodes exchange messages in `format` instead of JSON
+    pub fn with_wire_format(format: WireFormat) -> Self {
+        Self {
+            wire_format: format,
+            ..Self::default()
+        }
+    }
+
+    /// Join the network as `node_id`, returning a transport bound to that node
+    pub fn transport(&self, node_id: &str) -> InMemoryTransport {
+        self.lock_nodes().insert(
+            node_id.to_string(),
+            InMemoryNode {
+                inbox: None,
+                online: true,
+            },
+        );
+
+        InMemoryTransport {
+            node_id: node_id.to_string(),
+            network: self.clone(),
+        }
+    }
+
+    /// Take a node offline; it can neither send nor receive until `set_online(.., true)`
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn set_online(&self, node_id: &str, online: bool) {
+        if let Some(node) = self.lock_nodes().get_mut(node_id) {
+            node.online = online;
+        }
+    }
+
+    /// Whether `node_id` has started listening and will receive messages
+    pub fn is_listening(&self, node_id: &str) -> bool {
+        self.lock_nodes()
+            .get(node_id)
+            .map(|node| node.inbox.is_some())
+            .unwrap_or(false)
+    }
+
+    fn lock_nodes(&self) -> std::sync::MutexGuard<'_, HashMap<String, InMemoryNode>> {
+        self.nodes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_online(&self, node_id: &str) -> bool {
+        self.lock_nodes()
+            .get(node_id)
+            .map(|node| node.online)
+            .unwrap_or(false)
+    }
+
+    /// Where `message` goes: only the node it is meant for, if any, else every peer
+    fn recipients(&self, sender: &str, message: &PostMessage) -> Vec<String> {
+        match message.recipient() {
+            Some(node_id) => self
+                .announced
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .get(node_id)
+                .cloned()
+                .into_iter()
+                .collect(),
+            None => self.peers_of(sender),
+        }
+    }
+
+    fn peers_of(&self, node_id: &str) -> Vec<String> {
+        let mut peers: Vec<String> = self
+            .lock_nodes()
+            .iter()
+            .filter(|(id, node)| id.as_str() != node_id && node.online)
+            .map(|(id, _)| id.clone())
+            .collect();
+        peers.sort();
+        peers
+    }
+
+    fn deliver(&self, node_id: &str, wire: &[u8]) -> Result<()> {
+        let message = decode_message(wire)?;
+
+        let nodes = self.lock_nodes();
+        let inbox = nodes
+            .get(node_id)
+            .filter(|node| node.online)
+            .and_then(|node| node.inbox.as_ref())
+            .ok_or_else(|| PostError::Network(format!("Node {} is not listening", node_id)))?;
+
+        inbox
+            .send(message)
+            .map_err(|e| PostError::Network(format!("Failed to deliver to {}: {}", node_id, e)))
+    }
+}
+
+/// Transport for tests that connects `SyncManager`s inside a single process
+pub struct InMemoryTransport {
+    node_id: String,
+    network: InMemoryNetwork,
+}
+
+impl InMemoryTransport {
+    /// Encode `message` in the network's format, except discovery, which goes as JSON
+    /// like it does over TailscaleTransport
+    fn encode(&self, message: &PostMessage) -> Result<Vec<u8>> {
+        let format = match message.data {
+            MessageData::NodeDiscovery(_) => WireFormat::Json,
+            _ => self.network.wire_format,
+        };
+        encode_message(message, format)
+    }
+}
+
+#[async_trait]
+impl Transport for InMemoryTransport {
+    async fn send_message(&self, message: PostMessage) -> Result<()> {
+        if !self.network.is_online(&self.node_id) {
+            return Err(PostError::Network(format!(
+                "Node {} is offline",
+                self.node_id
+            )));
+        }
+
+        let wire = self.encode(&message)?;
+        if let MessageData::NodeDiscovery(data) = &message.data {
+            self.network
+                .announced
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .insert(data.source_node.clone(), self.node_id.clone());
+        }
+
+        for peer in self.network.recipients(&self.node_id, &message) {
+            if let Err(e) = self.network.deliver(&peer, &wire) {
+                debug!("In-memory transport: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn start_listening(&self, sender: InboxSender) -> Result<()> {
+        if let Some(node) = self.network.lock_nodes().get_mut(&self.node_id) {
+            node.inbox = Some(sender);
+        }
+
+        std::future::pending::<()>().await;
+        Ok(())
+    }
+
+    fn is_listening(&self) -> bool {
+        self.network.is_listening(&self.node_id)
+    }
+
+    async fn get_node_id(&self) -> Result<String> {
+        Ok(self.node_id.clone())
+    }
+
+    async fn get_tailnet_nodes(&self) -> Result<Vec<String>> {
+        Ok(self.network.peers_of(&self.node_id))
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(self.network.is_online(&self.node_id))
+    }
+}
+
+type ChangeCallback = Arc<dyn Fn(String) + Send + Sync>;
+
+/// In-memory clipboard for tests and simulations.
+///
+/// `set_contents` behaves like a programmatic write and does not notify watchers, while
+/// `simulate_copy` behaves like a user copying text and notifies every registered watcher.
+#[derive(Clone, Default)]
+pub struct MockClipboard {
+    content: Arc<std::sync::Mutex<String>>,
+    watchers: Arc<std::sync::Mutex<Vec<ChangeCallback>>>,
+}
+
+impl MockClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the clipboard content and notify watchers, as if a user copied `content`
+    pub fn simulate_copy(&self, content: &str) {
+        *self
+            .content
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = content.to_owned();
+
+        let watchers = self
+            .watchers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        for watcher in watchers {
+            watcher(content.to_owned());
+        }
+    }
+
+    /// Current content without going through the async trait
+    pub fn contents(&self) -> String {
+        self.content
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl ClipboardManager for MockClipboard {
+    async fn get_contents(&self) -> Result<String> {
+        Ok(self.contents())
+    }
+
+    async fn set_contents(&self, content: &str) -> Result<()> {
+        *self
+            .content
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = content.to_owned();
+        Ok(())
+    }
+
+    /// Nothing copied here carries a password manager's marker
+    async fn is_concealed(&self) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+#[async_trait]
+impl ClipboardWatcher for MockClipboard {
+    async fn watch_changes(
+        &self,
+        callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+    ) -> Result<()> {
+        self.watchers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(Arc::from(callback));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::BINARY_FRAME_MAGIC;
+    use crate::SyncManager;
+
+    #[tokio::test]
+    async fn test_discovery_goes_as_json_on_binary_networks() {
+        let network = InMemoryNetwork::with_wire_format(WireFormat::MessagePack);
+        let transport = network.transport("node-a");
+        let sync = SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string()).unwrap();
+
+        let discovery = sync.create_node_discovery_message().await.unwrap();
+        assert_eq!(transport.encode(&discovery).unwrap().first(), Some(&b'{'));
+
+        let heartbeat = sync.create_heartbeat_message().await.unwrap();
+        assert_eq!(
+            transport.encode(&heartbeat).unwrap().first(),
+            Some(&BINARY_FRAME_MAGIC)
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use crate::redact::Redacted;
+use crate::{PostError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Longest pin name accepted
+const MAX_NAME_LEN: usize = 64;
+
+/// A named clipboard item; `content` is unset once unpinned so the removal syncs too
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinEntry {
+    pub name: String,
+    pub content: Option<String>,
+    /// Unix time in milliseconds; the newest change to a name wins
+    pub updated: u64,
+}
+
+impl fmt::Debug for PinEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinEntry")
+            .field("name", &self.name)
+            .field("content", &self.content.as_deref().map(Redacted))
+            .field("updated", &self.updated)
+            .finish()
+    }
+}
+
+/// Pinned clipboard items by name, merged last-writer-wins between devices
+#[derive(Debug, Clone, Default)]
+pub struct PinSet {
+    entries: BTreeMap<String, PinEntry>,
+}
+
+impl PinSet {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.get(name)?.content.as_deref()
+    }
+
+    /// Pinned items by name, leaving out removed ones
+    pub fn pinned(&self) -> BTreeMap<String, String> {
+        self.entries
+            .values()
+            .filter_map(|entry| Some((entry.name.clone(), entry.content.clone()?)))
+            .collect()
+    }
+
+    /// Every entry including removals, as sent to peers
+    pub fn entries(&self) -> Vec<PinEntry> {
+        self.entries.values().cloned().collect()
+    }
+
+    /// Pin `content` as `name`, or unpin it with `None`, returning the change to sync
+    pub fn set(&mut self, name: &str, content: Option<String>) -> PinEntry {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        // Stay ahead of the entry being replaced even if this clock is behind
+        let updated = match self.entries.get(name) {
+            Some(existing) => now.max(existing.updated.saturating_add(1)),
+            None => now,
+        };
+
+        let entry = PinEntry {
+            name: name.to_string(),
+            content,
+            updated,
+        };
+        self.entries.insert(entry.name.clone(), entry.clone());
+        entry
+    }
+
+    /// Take each entry newer than ours, returning whether anything changed
+    pub fn merge(&mut self, entries: Vec<PinEntry>) -> bool {
+        let mut changed = false;
+        for entry in entries {
+            if validate_name(&entry.name).is_err() {
+                continue;
+            }
+            let newer = self
+                .entries
+                .get(&entry.name)
+                .is_none_or(|existing| entry.updated > existing.updated);
+            if newer {
+                self.entries.insert(entry.name.clone(), entry);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Check that `name` is usable as a pin name: letters, digits, `-`, `_` and `.`
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(PostError::Config(format!(
+            "Invalid pin name {:?}; use up to {} letters, digits, '-', '_' or '.'",
+            name, MAX_NAME_LEN
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newest_change_wins() {
+        let mut laptop = PinSet::default();
+        let mut desktop = PinSet::default();
+
+        let first = laptop.set("work-address", Some("1 Main St".to_string()));
+        desktop.merge(vec![first.clone()]);
+        let moved = desktop.set("work-address", Some("2 Side St".to_string()));
+
+        assert!(laptop.merge(vec![moved]));
+        assert!(!desktop.merge(vec![first]));
+        assert_eq!(laptop.get("work-address"), Some("2 Side St"));
+        assert_eq!(desktop.get("work-address"), Some("2 Side St"));
+
+        let removed = laptop.set("work-address", None);
+        desktop.merge(vec![removed]);
+        assert!(desktop.pinned().is_empty());
+    }
+
+    #[test]
+    fn test_a_far_future_entry_can_still_be_replaced() {
+        let mut pins = PinSet::default();
+        pins.merge(vec![PinEntry {
+            name: "work-address".to_string(),
+            content: Some("1 Main St".to_string()),
+            updated: u64::MAX,
+        }]);
+
+        let entry = pins.set("work-address", Some("2 Side St".to_string()));
+        assert_eq!(entry.updated, u64::MAX);
+        assert_eq!(pins.get("work-address"), Some("2 Side St"));
+    }
+
+    #[test]
+    fn test_pin_names_are_validated() {
+        assert!(validate_name("work-address").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../etc").is_err());
+        assert!(validate_name("with space").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use blake2::digest::{KeyInit, Mac};
+use blake2::Blake2sMac256;
+use rand::RngCore;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// Characters of content shown when previews are enabled
+const PREVIEW_CHARS: usize = 24;
+
+static PREVIEWS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Random key for [`Redacted::hash_prefix`], so a logged prefix can't be checked against
+/// guesses at short content such as PINs
+static HASH_KEY: OnceLock<[u8; 32]> = OnceLock::new();
+
+/// Let [`Redacted`] include the start of the content; meant for debugging only
+pub fn set_previews_enabled(enabled: bool) {
+    PREVIEWS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Rough kind of clipboard content, safe to log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Empty,
+    Url,
+    Email,
+    Path,
+    Number,
+    Json,
+    Multiline,
+    Text,
+}
+
+impl ContentKind {
+    pub fn detect(content: &str) -> Self {
+        let trimmed = content.trim();
+        let single_word = !trimmed.contains(char::is_whitespace);
+
+        if trimmed.is_empty() {
+            Self::Empty
+        } else if single_word && trimmed.contains("://") {
+            Self::Url
+        } else if single_word
+            && trimmed
+                .split_once('@')
+                .is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'))
+        {
+            Self::Email
+        } else if single_word
+            && (trimmed.starts_with('/') || trimmed.starts_with("~/") || trimmed.contains(":\\"))
+        {
+            Self::Path
+        } else if trimmed.parse::<f64>().is_ok() {
+            Self::Number
+        } else if (trimmed.starts_with('{') && trimmed.ends_with('}'))
+            || (trimmed.starts_with('[') && trimmed.ends_with(']'))
+        {
+            Self::Json
+        } else if trimmed.contains('\n') {
+            Self::Multiline
+        } else {
+            Self::Text
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::Url => "url",
+            Self::Email => "email",
+            Self::Path => "path",
+            Self::Number => "number",
+            Self::Json => "json",
+            Self::Multiline => "multiline text",
+            Self::Text => "text",
+        }
+    }
+}
+
+impl fmt::Display for ContentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Clipboard content formatted as its length, kind and hash prefix instead of verbatim
+pub struct Redacted<'a>(pub &'a str);
+
+impl Redacted<'_> {
+    /// First 8 hex digits of the content's keyed BLAKE2s hash, for telling updates apart
+    ///
+    /// The key is made afresh for each run, so prefixes only match within one log.
+    pub fn hash_prefix(&self) -> String {
+        let key = HASH_KEY.get_or_init(|| {
+            let mut key = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut key);
+            key
+        });
+        let mut mac = <Blake2sMac256 as KeyInit>::new_from_slice(key)
+            .expect("32 bytes is a valid BLAKE2s key");
+        mac.update(self.0.as_bytes());
+        mac.finalize().into_bytes()[..4]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} chars, {}, #{}",
+            self.0.chars().count(),
+            ContentKind::detect(self.0),
+            self.hash_prefix()
+        )?;
+
+        if PREVIEWS_ENABLED.load(Ordering::Relaxed) {
+            let preview = crate::text::truncate_chars(self.0, PREVIEW_CHARS);
+            write!(f, ", preview {:?}", preview)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}>", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_content_kind() {
+        assert_eq!(ContentKind::detect("  "), ContentKind::Empty);
+        assert_eq!(
+            ContentKind::detect("https://example.com/a"),
+            ContentKind::Url
+        );
+        assert_eq!(ContentKind::detect("me@example.com"), ContentKind::Email);
+        assert_eq!(ContentKind::detect("/etc/hosts"), ContentKind::Path);
+        assert_eq!(ContentKind::detect("-12.5"), ContentKind::Number);
+        assert_eq!(ContentKind::detect(r#"{"a": 1}"#), ContentKind::Json);
+        assert_eq!(ContentKind::detect("one\ntwo"), ContentKind::Multiline);
+        assert_eq!(ContentKind::detect("hunter2 is it"), ContentKind::Text);
+    }
+
+    #[test]
+    fn test_redacted_hides_content() {
+        let shown = Redacted("correct horse battery staple").to_string();
+
+        assert!(!shown.contains("horse"));
+        assert!(shown.starts_with("28 chars, text, #"));
+        assert_eq!(shown, Redacted("correct horse battery staple").to_string());
+        assert_ne!(shown, Redacted("correct horse battery stapler").to_string());
+    }
+
+    #[test]
+    fn test_hash_prefix_is_keyed() {
+        use blake2::{Blake2s256, Digest};
+
+        let unkeyed: String = Blake2s256::digest(b"1234")[..4]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_ne!(Redacted("1234").hash_prefix(), unkeyed);
+    }
+}
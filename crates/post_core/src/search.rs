@@ -0,0 +1,188 @@
+//! Full-text index over clipboard history, so searching stays instant with tens of
+//! thousands of items
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+/// A history item matching a search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch {
+    /// Position in the history; 0 is the newest item
+    pub position: usize,
+    pub content: String,
+}
+
+/// Words of history items, mapped to the items containing them
+///
+/// Items are distinct, as in the clipboard stack; inserting one again makes it the newest.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    next_id: u64,
+    /// Each item's ID; IDs grow with every insert, so newer items have larger ones
+    ids: HashMap<Arc<str>, u64>,
+    items: BTreeMap<u64, Arc<str>>,
+    /// Lowercased words to the IDs of the items containing them
+    words: BTreeMap<String, HashSet<u64>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An index of `items`, given newest first like the clipboard stack
+    pub fn with_items<'a>(items: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut index = Self::new();
+        let items: Vec<_> = items.into_iter().collect();
+        for item in items.into_iter().rev() {
+            index.insert(item);
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Add `content` as the newest item, moving it up if it is already indexed
+    pub fn insert(&mut self, content: &str) {
+        self.remove(content);
+        let id = self.next_id;
+        self.next_id += 1;
+        let content: Arc<str> = Arc::from(content);
+        for word in words(&content) {
+            self.words.entry(word).or_default().insert(id);
+        }
+        self.ids.insert(Arc::clone(&content), id);
+        self.items.insert(id, content);
+    }
+
+    pub fn remove(&mut self, content: &str) {
+        let Some(id) = self.ids.remove(content) else {
+            return;
+        };
+        self.items.remove(&id);
+        for word in words(content) {
+            if let Some(ids) = self.words.get_mut(&word) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Drop all but the `keep` newest items
+    pub fn truncate(&mut self, keep: usize) {
+        while self.items.len() > keep {
+            let Some((_, oldest)) = self.items.first_key_value() else {
+                break;
+            };
+            let oldest = Arc::clone(oldest);
+            self.remove(&oldest);
+        }
+    }
+
+    /// Items containing every word of `query`, newest first and at most `limit` of them
+    ///
+    /// Matching ignores case, and the query's words may be the start of longer ones, so
+    /// results narrow while a query is typed. A query without words matches nothing.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchMatch> {
+        let mut found: Option<HashSet<u64>> = None;
+        for word in words(query) {
+            let ids: HashSet<u64> = self
+                .words
+                .range(word.clone()..)
+                .take_while(|(indexed, _)| indexed.starts_with(&word))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .filter(|id| found.as_ref().is_none_or(|found| found.contains(id)))
+                .collect();
+            if ids.is_empty() {
+                return Vec::new();
+            }
+            found = Some(ids);
+        }
+
+        let Some(found) = found.filter(|found| !found.is_empty()) else {
+            return Vec::new();
+        };
+        // One walk from the newest item numbers every match, instead of counting the
+        // newer items again for each of them
+        self.items
+            .iter()
+            .rev()
+            .enumerate()
+            .filter(|(_, (id, _))| found.contains(id))
+            .take(limit)
+            .map(|(position, (_, content))| SearchMatch {
+                position,
+                content: content.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Distinct lowercased words of `text`, split at anything that isn't a letter or digit
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(matches: &[SearchMatch]) -> Vec<&str> {
+        matches.iter().map(|found| found.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_search_matches_every_word_by_prefix_ignoring_case() {
+        let mut index = SearchIndex::new();
+        index.insert("Meeting notes for Monday");
+        index.insert("ssh deploy@build-server");
+        index.insert("monday standup link");
+
+        assert_eq!(
+            contents(&index.search("mon", 10)),
+            ["monday standup link", "Meeting notes for Monday"]
+        );
+        assert_eq!(
+            contents(&index.search("MEET mon", 10)),
+            ["Meeting notes for Monday"]
+        );
+        assert_eq!(
+            contents(&index.search("build", 10)),
+            ["ssh deploy@build-server"]
+        );
+        assert!(index.search("tuesday", 10).is_empty());
+        assert!(index.search("  ", 10).is_empty());
+        assert_eq!(index.search("mon", 1).len(), 1);
+    }
+
+    #[test]
+    fn test_positions_follow_reinserts_and_truncation() {
+        let stack = vec![
+            "third".to_string(),
+            "second".to_string(),
+            "first".to_string(),
+        ];
+        let mut index = SearchIndex::with_items(&stack);
+        assert_eq!(index.search("first", 10)[0].position, 2);
+
+        index.insert("first");
+        assert_eq!(index.search("first", 10)[0].position, 0);
+        assert_eq!(index.search("third", 10)[0].position, 1);
+
+        index.truncate(2);
+        assert_eq!(index.len(), 2);
+        assert!(index.search("second", 10).is_empty());
+        assert!(!index.words.contains_key("second"));
+    }
+}
@@ -0,0 +1,304 @@
+//! End-to-end check of the sync pipeline against an in-process peer, without touching
+//! the network or the system clipboard
+
+use crate::inbox::{self, InboxReceiver};
+use crate::mock::{InMemoryNetwork, InMemoryTransport, MockClipboard};
+use crate::{MessageData, PostError, Result, SyncManager, Transport, WireFormat};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Longest a single stage may take before it counts as failed
+pub const STAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Stages in the order they run; each needs the ones before it to have passed
+pub const STAGES: [&str; 4] = [
+    "discovery",
+    "key exchange",
+    "encryption",
+    "clipboard round trip",
+];
+
+const LOCAL_NODE: &str = "selftest-local";
+const PEER_NODE: &str = "selftest-peer";
+const SAMPLE: &str = "Post self-test \u{1f4cb}";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StageOutcome {
+    Passed,
+    Failed(String),
+    /// Not run because an earlier stage failed
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageResult {
+    pub name: &'static str,
+    pub outcome: StageOutcome,
+    pub elapsed: Duration,
+}
+
+/// Run every stage against a mock peer, reporting each one's outcome in [`STAGES`] order
+pub async fn run() -> Vec<StageResult> {
+    let mut report = Report::default();
+    let network = InMemoryNetwork::with_wire_format(WireFormat::SUPPORTED[0]);
+
+    if let Some((mut local, mut peer)) = report.stage(STAGES[0], discover(&network)).await {
+        report.stage(STAGES[1], exchange_keys(&local, &peer)).await;
+        report.stage(STAGES[2], encrypt(&local, &peer)).await;
+        report
+            .stage(STAGES[3], round_trip(&mut local, &mut peer))
+            .await;
+    }
+    report.finish()
+}
+
+#[derive(Default)]
+struct Report {
+    results: Vec<StageResult>,
+}
+
+impl Report {
+    /// Run `stage` unless an earlier one failed, recording how it went
+    async fn stage<T>(
+        &mut self,
+        name: &'static str,
+        stage: impl Future<Output = Result<T>>,
+    ) -> Option<T> {
+        if self
+            .results
+            .iter()
+            .any(|result| result.outcome != StageOutcome::Passed)
+        {
+            self.skip(name);
+            return None;
+        }
+
+        let started = Instant::now();
+        let (outcome, value) = match tokio::time::timeout(STAGE_TIMEOUT, stage).await {
+            Ok(Ok(value)) => (StageOutcome::Passed, Some(value)),
+            Ok(Err(e)) => (StageOutcome::Failed(e.to_string()), None),
+            Err(_) => (
+                StageOutcome::Failed(format!("timed out after {:?}", STAGE_TIMEOUT)),
+                None,
+            ),
+        };
+        self.results.push(StageResult {
+            name,
+            outcome,
+            elapsed: started.elapsed(),
+        });
+        value
+    }
+
+    fn skip(&mut self, name: &'static str) {
+        self.results.push(StageResult {
+            name,
+            outcome: StageOutcome::Skipped,
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    /// The results, with stages that never got to run marked as skipped
+    fn finish(mut self) -> Vec<StageResult> {
+        for name in STAGES {
+            if !self.results.iter().any(|result| result.name == name) {
+                self.skip(name);
+            }
+        }
+        self.results
+    }
+}
+
+/// A node running the real sync manager over an in-memory transport and mock clipboard
+struct Node {
+    id: &'static str,
+    clipboard: MockClipboard,
+    sync: Arc<SyncManager>,
+    transport: Arc<InMemoryTransport>,
+    inbox: InboxReceiver,
+}
+
+impl Node {
+    async fn join(network: &InMemoryNetwork, node_id: &'static str) -> Result<Self> {
+        let clipboard = MockClipboard::new();
+        // Concealed-content detection would look at the real clipboard
+        let sync = Arc::new(
+            SyncManager::new(Arc::new(clipboard.clone()), node_id.to_string())?
+                .with_concealed_sync(true),
+        );
+        let transport = Arc::new(network.transport(node_id));
+
+        let (tx, inbox) = inbox::channel(inbox::DEFAULT_CAPACITY);
+        let listener = Arc::clone(&transport);
+        tokio::spawn(async move {
+            let _ = listener.start_listening(tx).await;
+        });
+        while !network.is_listening(node_id) {
+            tokio::task::yield_now().await;
+        }
+
+        let sender = Arc::clone(&transport);
+        sync.start_sync_loop(move |message| {
+            let sender = Arc::clone(&sender);
+            tokio::spawn(async move {
+                let _ = sender.send_message(message).await;
+            });
+        })
+        .await?;
+
+        Ok(Self {
+            id: node_id,
+            clipboard,
+            sync,
+            transport,
+            inbox,
+        })
+    }
+
+    async fn announce(&self) -> Result<()> {
+        let discovery = self.sync.create_node_discovery_message().await?;
+        self.transport.send_message(discovery).await
+    }
+
+    /// Handle incoming messages up to and including the first one `wanted` matches
+    async fn handle_until(&mut self, wanted: fn(&MessageData) -> bool) -> Result<()> {
+        loop {
+            let message = self
+                .inbox
+                .recv()
+                .await
+                .ok_or_else(|| PostError::Network("In-memory transport closed".to_string()))?;
+            let is_wanted = wanted(&message.data);
+            let result = self.sync.handle_message(message).await;
+            if is_wanted {
+                return result;
+            }
+        }
+    }
+}
+
+/// Both nodes announce themselves and register each other
+async fn discover(network: &InMemoryNetwork) -> Result<(Node, Node)> {
+    let mut local = Node::join(network, LOCAL_NODE).await?;
+    let mut peer = Node::join(network, PEER_NODE).await?;
+
+    local.announce().await?;
+    peer.announce().await?;
+    let is_discovery = |data: &MessageData| matches!(data, MessageData::NodeDiscovery(_));
+    peer.handle_until(is_discovery).await?;
+    local.handle_until(is_discovery).await?;
+
+    for (node, other) in [(&local, PEER_NODE), (&peer, LOCAL_NODE)] {
+        if !node.sync.get_nodes().await.contains_key(other) {
+            return Err(PostError::Other(format!(
+                "{} did not register {}",
+                node.id, other
+            )));
+        }
+    }
+    Ok((local, peer))
+}
+
+/// Both nodes derived a crypto session for the other from its announced key
+async fn exchange_keys(local: &Node, peer: &Node) -> Result<()> {
+    for (node, other) in [(local, PEER_NODE), (peer, LOCAL_NODE)] {
+        if node.sync.get_crypto_session(other).await.is_none() {
+            return Err(PostError::Crypto(format!(
+                "{} has no session key for {}",
+                node.id, other
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// What one node encrypts, the other decrypts, so both derived the same key
+async fn encrypt(local: &Node, peer: &Node) -> Result<()> {
+    let missing = || PostError::Crypto("Session key disappeared".to_string());
+    let sealing = local
+        .sync
+        .get_crypto_session(PEER_NODE)
+        .await
+        .ok_or_else(missing)?;
+    let opening = peer
+        .sync
+        .get_crypto_session(LOCAL_NODE)
+        .await
+        .ok_or_else(missing)?;
+
+    let ciphertext = sealing.encrypt(SAMPLE.as_bytes()).await?;
+    if ciphertext
+        .windows(SAMPLE.len())
+        .any(|window| window == SAMPLE.as_bytes())
+    {
+        return Err(PostError::Crypto(
+            "Ciphertext contains the plaintext".to_string(),
+        ));
+    }
+    if opening.decrypt(&ciphertext).await? != SAMPLE.as_bytes() {
+        return Err(PostError::Crypto(
+            "Peer decrypted something other than what was sent".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// A copy on one node lands on the other's clipboard, and its acknowledgement comes back
+async fn round_trip(local: &mut Node, peer: &mut Node) -> Result<()> {
+    local.clipboard.simulate_copy(SAMPLE);
+    peer.handle_until(|data| matches!(data, MessageData::ClipboardUpdate(_)))
+        .await?;
+    let applied = peer.clipboard.contents();
+    if applied != SAMPLE {
+        return Err(PostError::Clipboard(format!(
+            "Peer clipboard holds {:?} instead of {:?}",
+            applied, SAMPLE
+        )));
+    }
+
+    local
+        .handle_until(|data| matches!(data, MessageData::Ack(_)))
+        .await?;
+    if local.sync.pending_ack_count().await != 0 {
+        return Err(PostError::Network(
+            "Peer acknowledged a different update".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_every_stage_passes_against_mock_peer() {
+        let results = run().await;
+        let names: Vec<_> = results.iter().map(|result| result.name).collect();
+        assert_eq!(names, STAGES);
+        for result in results {
+            assert_eq!(result.outcome, StageOutcome::Passed, "{}", result.name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stages_after_a_failure_are_skipped() {
+        let mut report = Report::default();
+        report
+            .stage(STAGES[0], async {
+                Err::<(), _>(PostError::Network("unreachable".to_string()))
+            })
+            .await;
+        report.stage(STAGES[1], async { Ok(()) }).await;
+        let results = report.finish();
+
+        assert_eq!(
+            results[0].outcome,
+            StageOutcome::Failed("Network error: unreachable".to_string())
+        );
+        assert!(results[1..]
+            .iter()
+            .all(|result| result.outcome == StageOutcome::Skipped));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{PostError, Result};
+use chrono::{DateTime, Local};
+
+/// Values substituted into snippet templates
+#[derive(Debug, Clone)]
+pub struct SnippetContext {
+    pub now: DateTime<Local>,
+    pub hostname: String,
+}
+
+impl SnippetContext {
+    /// This machine's clock and hostname
+    pub fn current() -> Self {
+        Self {
+            now: Local::now(),
+            hostname: hostname::get()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn value(&self, placeholder: &str) -> Option<String> {
+        let value = match placeholder {
+            "date" => self.now.format("%Y-%m-%d").to_string(),
+            "time" => self.now.format("%H:%M:%S").to_string(),
+            "datetime" => self.now.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "hostname" => self.hostname.clone(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Fill in `{date}`, `{time}`, `{datetime}` and `{hostname}`; `{{` and `}}` are literal braces
+pub fn render(template: &str, context: &SnippetContext) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                rendered.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                rendered.push('}');
+            }
+            '{' => {
+                let placeholder: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let value = context.value(placeholder.trim()).ok_or_else(|| {
+                    PostError::Config(format!("Unknown snippet placeholder {{{}}}", placeholder))
+                })?;
+                rendered.push_str(&value);
+            }
+            c => rendered.push(c),
+        }
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context() -> SnippetContext {
+        SnippetContext {
+            now: Local.with_ymd_and_hms(2024, 3, 9, 14, 5, 0).unwrap(),
+            hostname: "laptop".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_fills_placeholders() {
+        let rendered = render(
+            "Sent from {hostname} on {date} at {time} {{ok}}",
+            &context(),
+        );
+        assert_eq!(
+            rendered.unwrap(),
+            "Sent from laptop on 2024-03-09 at 14:05:00 {ok}"
+        );
+    }
+
+    #[test]
+    fn test_render_rejects_unknown_placeholders() {
+        assert!(render("Hi {name}", &context()).is_err());
+    }
+}
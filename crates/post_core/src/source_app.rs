@@ -0,0 +1,213 @@
+use crate::{PostError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The application in the foreground when the clipboard changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceApp {
+    /// Display name, e.g. "1Password 7" or "KeePassXC"
+    pub name: String,
+    /// Bundle identifier on macOS, executable path on Windows
+    pub id: Option<String>,
+}
+
+impl SourceApp {
+    /// Whether `pattern` names this app by display name, bundle ID, path or executable name
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.trim();
+        if pattern == "*" {
+            return true;
+        }
+
+        let executable = self
+            .id
+            .as_deref()
+            .and_then(|id| Path::new(id).file_stem())
+            .and_then(|stem| stem.to_str());
+        [Some(self.name.as_str()), self.id.as_deref(), executable]
+            .into_iter()
+            .flatten()
+            .any(|candidate| candidate.eq_ignore_ascii_case(pattern))
+    }
+}
+
+/// Whether to sync clipboard changes made in the apps an [`AppRule`] matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AppRuleAction {
+    Block,
+    Allow,
+}
+
+/// One entry of `filters.app_rules`; `app` is a name, bundle ID or executable, or `*` for any
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppRule {
+    pub app: String,
+    pub action: AppRuleAction,
+}
+
+/// Apply the first rule matching `app`; apps no rule matches are synced
+pub fn check_rules(rules: &[AppRule], app: &SourceApp) -> Result<()> {
+    match rules.iter().find(|rule| app.matches(&rule.app)) {
+        Some(rule) if rule.action == AppRuleAction::Block => Err(PostError::Filtered(format!(
+            "copied in {}, which filters.app_rules blocks",
+            app.name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The application that currently has focus, where the platform can tell
+pub fn frontmost_app() -> Option<SourceApp> {
+    platform::frontmost_app()
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{ns_string, SourceApp};
+    use objc::runtime::Object;
+    use objc::{class, msg_send, sel, sel_impl};
+
+    #[link(name = "AppKit", kind = "framework")]
+    extern "C" {}
+
+    pub fn frontmost_app() -> Option<SourceApp> {
+        unsafe {
+            let workspace: *mut Object = msg_send![class!(NSWorkspace), sharedWorkspace];
+            if workspace.is_null() {
+                return None;
+            }
+            let app: *mut Object = msg_send![workspace, frontmostApplication];
+            if app.is_null() {
+                return None;
+            }
+
+            let id = ns_string(msg_send![app, bundleIdentifier]);
+            let name = ns_string(msg_send![app, localizedName]).or_else(|| id.clone())?;
+            Some(SourceApp { name, id })
+        }
+    }
+}
+
+/// Copy an `NSString` into a Rust string
+#[cfg(target_os = "macos")]
+pub(crate) unsafe fn ns_string(string: *mut objc::runtime::Object) -> Option<String> {
+    use objc::{msg_send, sel, sel_impl};
+
+    if string.is_null() {
+        return None;
+    }
+    let utf8: *const std::os::raw::c_char = msg_send![string, UTF8String];
+    if utf8.is_null() {
+        return None;
+    }
+    Some(
+        std::ffi::CStr::from_ptr(utf8)
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::SourceApp;
+    use std::path::Path;
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowThreadProcessId,
+    };
+
+    pub fn frontmost_app() -> Option<SourceApp> {
+        unsafe {
+            let window = GetForegroundWindow();
+            if window == 0 {
+                return None;
+            }
+            let mut process_id = 0u32;
+            GetWindowThreadProcessId(window, &mut process_id);
+            if process_id == 0 {
+                return None;
+            }
+
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id);
+            if process == 0 {
+                return None;
+            }
+            let mut buffer = [0u16; 1024];
+            let mut len = buffer.len() as u32;
+            let ok = QueryFullProcessImageNameW(process, 0, buffer.as_mut_ptr(), &mut len);
+            CloseHandle(process);
+            if ok == 0 {
+                return None;
+            }
+
+            let path = String::from_utf16_lossy(&buffer[..len as usize]);
+            let name = Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.clone());
+            Some(SourceApp {
+                name,
+                id: Some(path),
+            })
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod platform {
+    use super::SourceApp;
+
+    /// X11 and Wayland don't reliably say which client owns the clipboard
+    pub fn frontmost_app() -> Option<SourceApp> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_matches_by_name_id_or_executable() {
+        let mac = SourceApp {
+            name: "1Password 7".to_string(),
+            id: Some("com.agilebits.onepassword7".to_string()),
+        };
+        assert!(mac.matches("1password 7"));
+        assert!(mac.matches("com.agilebits.onepassword7"));
+        assert!(!mac.matches("1Password"));
+
+        let windows = SourceApp {
+            name: "KeePassXC".to_string(),
+            id: Some("C:/Program Files/KeePassXC/KeePassXC.exe".to_string()),
+        };
+        assert!(windows.matches("keepassxc"));
+        assert!(windows.matches("*"));
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules = vec![
+            AppRule {
+                app: "Terminal".to_string(),
+                action: AppRuleAction::Allow,
+            },
+            AppRule {
+                app: "*".to_string(),
+                action: AppRuleAction::Block,
+            },
+        ];
+        let app = |name: &str| SourceApp {
+            name: name.to_string(),
+            id: None,
+        };
+
+        assert!(check_rules(&rules, &app("terminal")).is_ok());
+        assert!(check_rules(&rules, &app("KeePassXC")).is_err());
+        assert!(check_rules(&[], &app("KeePassXC")).is_ok());
+    }
+}
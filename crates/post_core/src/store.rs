@@ -0,0 +1,385 @@
+//! State kept between runs, in an embedded SQLite database
+
+use crate::{PostError, Result};
+use rusqlite::{Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a write waits while another process, such as `post storage vacuum`, holds
+/// the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A piece of state, each read and written as one JSON document
+///
+/// History is stored one row per item, so a copy writes that item rather than the whole
+/// stack; the rest is small enough to keep as the document itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StateKey {
+    /// The clipboard stack, newest first
+    History,
+    Pins,
+    /// Known peers and their verifying keys
+    Peers,
+    /// Updates peers have yet to acknowledge
+    Queue,
+    /// Latest sequence numbers sent and applied
+    Sequences,
+    /// Sync activity with each peer
+    Stats,
+}
+
+impl StateKey {
+    pub const ALL: [StateKey; 6] = [
+        StateKey::History,
+        StateKey::Pins,
+        StateKey::Peers,
+        StateKey::Queue,
+        StateKey::Sequences,
+        StateKey::Stats,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StateKey::History => "history",
+            StateKey::Pins => "pins",
+            StateKey::Peers => "peers",
+            StateKey::Queue => "queue",
+            StateKey::Sequences => "sequences",
+            StateKey::Stats => "stats",
+        }
+    }
+}
+
+impl fmt::Display for StateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The state database
+///
+/// It is written in WAL mode, so a crash or power loss mid-write leaves the state as it
+/// was before that write rather than a truncated file.
+pub struct StateStore {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl fmt::Debug for StateStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StateStore {
+    /// Open the database at `path`, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(path).map_err(storage_error)?;
+        connection
+            .busy_timeout(BUSY_TIMEOUT)
+            .map_err(storage_error)?;
+        let mode: String = connection
+            .query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))
+            .map_err(storage_error)?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            return Err(PostError::Storage(format!(
+                "{} can't use write-ahead logging (journal mode {})",
+                path.display(),
+                mode
+            )));
+        }
+        // Safe against corruption in WAL mode; only the last commits can be lost on power loss
+        connection
+            .execute_batch(
+                "PRAGMA synchronous = NORMAL;
+                 CREATE TABLE IF NOT EXISTS state (
+                     key TEXT PRIMARY KEY,
+                     value TEXT NOT NULL,
+                     updated INTEGER NOT NULL
+                 );
+                 CREATE TABLE IF NOT EXISTS history (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     content TEXT NOT NULL
+                 );",
+            )
+            .map_err(storage_error)?;
+
+        let store = Self {
+            path: path.to_path_buf(),
+            connection: Mutex::new(connection),
+        };
+        store.move_history_into_rows()?;
+        Ok(store)
+    }
+
+    /// Open the database at `path` if it exists, without creating it
+    pub fn open_existing(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::open(path).map(Some)
+    }
+
+    /// Databases written before history had rows of its own keep it as a document
+    fn move_history_into_rows(&self) -> Result<()> {
+        let json: Option<String> = self
+            .connection()
+            .query_row(
+                "SELECT value FROM state WHERE key = ?1",
+                [StateKey::History.as_str()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_error)?;
+        // A damaged document stays where it is for the user to look at
+        let Some(history) = json.and_then(|json| serde_json::from_str::<Vec<String>>(&json).ok())
+        else {
+            return Ok(());
+        };
+        self.replace_history(&history)?;
+        self.connection()
+            .execute(
+                "DELETE FROM state WHERE key = ?1",
+                [StateKey::History.as_str()],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The JSON document kept as `key`, if any
+    pub fn get(&self, key: StateKey) -> Result<Option<String>> {
+        if key == StateKey::History {
+            let history = self.history()?;
+            if history.is_empty() {
+                return Ok(None);
+            }
+            return serde_json::to_string(&history)
+                .map(Some)
+                .map_err(|e| PostError::Serialization(format!("Failed to encode {}: {}", key, e)));
+        }
+        self.connection()
+            .query_row(
+                "SELECT value FROM state WHERE key = ?1",
+                [key.as_str()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_error)
+    }
+
+    /// Replace the JSON document kept as `key`
+    pub fn put(&self, key: StateKey, json: &str) -> Result<()> {
+        if key == StateKey::History {
+            let history: Vec<String> = serde_json::from_str(json)
+                .map_err(|e| PostError::Serialization(format!("Invalid {}: {}", key, e)))?;
+            return self.replace_history(&history);
+        }
+        let updated = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.connection()
+            .execute(
+                "INSERT INTO state (key, value, updated) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (key) DO UPDATE SET value = excluded.value, updated = excluded.updated",
+                rusqlite::params![key.as_str(), json, updated],
+            )
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, key: StateKey) -> Result<Option<T>> {
+        let Some(json) = self.get(key)? else {
+            return Ok(None);
+        };
+        serde_json::from_str(&json).map(Some).map_err(|e| {
+            PostError::Serialization(format!("Invalid {} in {}: {}", key, self.path.display(), e))
+        })
+    }
+
+    pub fn save<T: Serialize + ?Sized>(&self, key: StateKey, value: &T) -> Result<()> {
+        let json = serde_json::to_string(value)
+            .map_err(|e| PostError::Serialization(format!("Failed to encode {}: {}", key, e)))?;
+        self.put(key, &json)
+    }
+
+    /// The clipboard stack, newest first
+    pub fn history(&self) -> Result<Vec<String>> {
+        let connection = self.connection();
+        let mut statement = connection
+            .prepare("SELECT content FROM history ORDER BY id DESC")
+            .map_err(storage_error)?;
+        let rows = statement
+            .query_map([], |row| row.get(0))
+            .map_err(storage_error)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(storage_error)
+    }
+
+    /// Replace the clipboard stack with `items`, newest first
+    pub fn replace_history(&self, items: &[String]) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(storage_error)?;
+        transaction
+            .execute("DELETE FROM history", [])
+            .map_err(storage_error)?;
+        for item in items.iter().rev() {
+            transaction
+                .execute("INSERT INTO history (content) VALUES (?1)", [item])
+                .map_err(storage_error)?;
+        }
+        transaction.commit().map_err(storage_error)
+    }
+
+    /// Make `content` the newest stack item, moving it up if it was already there, and
+    /// drop all but the `keep` newest
+    pub fn push_history(&self, content: &str, keep: usize) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction().map_err(storage_error)?;
+        transaction
+            .execute("DELETE FROM history WHERE content = ?1", [content])
+            .map_err(storage_error)?;
+        transaction
+            .execute("INSERT INTO history (content) VALUES (?1)", [content])
+            .map_err(storage_error)?;
+        truncate_history(&transaction, keep)?;
+        transaction.commit().map_err(storage_error)
+    }
+
+    /// Drop all but the `keep` newest stack items
+    pub fn truncate_history(&self, keep: usize) -> Result<()> {
+        truncate_history(&self.connection(), keep)
+    }
+
+    /// Rebuild the database to give back the space of removed and rewritten state, and
+    /// fold the write-ahead log into it
+    pub fn vacuum(&self) -> Result<()> {
+        let connection = self.connection();
+        connection.execute_batch("VACUUM").map_err(storage_error)?;
+        connection
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .map_err(storage_error)
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn truncate_history(connection: &Connection, keep: usize) -> Result<()> {
+    connection
+        .execute(
+            "DELETE FROM history WHERE id NOT IN
+                 (SELECT id FROM history ORDER BY id DESC LIMIT ?1)",
+            [keep as i64],
+        )
+        .map_err(storage_error)?;
+    Ok(())
+}
+
+fn storage_error(e: rusqlite::Error) -> PostError {
+    PostError::Storage(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_survives_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+
+        let store = StateStore::open(&path).unwrap();
+        assert_eq!(store.load::<Vec<String>>(StateKey::History).unwrap(), None);
+        store
+            .save(StateKey::History, &vec!["newest", "oldest"])
+            .unwrap();
+        store.save(StateKey::History, &vec!["replaced"]).unwrap();
+        drop(store);
+
+        let store = StateStore::open(&path).unwrap();
+        assert_eq!(
+            store.load::<Vec<String>>(StateKey::History).unwrap(),
+            Some(vec!["replaced".to_string()])
+        );
+        assert!(store.get(StateKey::Pins).unwrap().is_none());
+
+        store.put(StateKey::Pins, "not json").unwrap();
+        assert!(store.load::<Vec<String>>(StateKey::Pins).is_err());
+    }
+
+    #[test]
+    fn test_history_is_kept_one_item_per_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        let store = StateStore::open(&path).unwrap();
+        for item in ["first", "second", "third"] {
+            store.push_history(item, 3).unwrap();
+        }
+        store.push_history("first", 3).unwrap();
+        assert_eq!(store.history().unwrap(), ["first", "third", "second"]);
+        store.push_history("fourth", 3).unwrap();
+        assert_eq!(store.history().unwrap(), ["fourth", "first", "third"]);
+        store.truncate_history(1).unwrap();
+        assert_eq!(
+            store.load::<Vec<String>>(StateKey::History).unwrap(),
+            Some(vec!["fourth".to_string()])
+        );
+
+        // History kept as a document by older builds moves into rows
+        store
+            .connection()
+            .execute(
+                "INSERT INTO state (key, value, updated) VALUES ('history', '[\"newest\",\"oldest\"]', 0)",
+                [],
+            )
+            .unwrap();
+        drop(store);
+        let store = StateStore::open(&path).unwrap();
+        assert_eq!(store.history().unwrap(), ["newest", "oldest"]);
+    }
+
+    #[test]
+    fn test_opening_an_existing_database_does_not_create_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        assert!(StateStore::open_existing(&path).unwrap().is_none());
+        assert!(!path.exists());
+        StateStore::open(&path).unwrap();
+        assert!(StateStore::open_existing(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_vacuum_folds_the_log_into_the_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        let store = StateStore::open(&path).unwrap();
+        store
+            .save(StateKey::History, &vec!["x".repeat(100_000)])
+            .unwrap();
+        store
+            .save(StateKey::History, &Vec::<String>::new())
+            .unwrap();
+
+        store.vacuum().unwrap();
+        let wal = dir.path().join("state.db-wal");
+        assert_eq!(std::fs::metadata(wal).map_or(0, |wal| wal.len()), 0);
+        assert!(std::fs::metadata(&path).unwrap().len() < 100_000);
+        assert!(store.history().unwrap().is_empty());
+    }
+}
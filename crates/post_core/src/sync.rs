@@ -1,132 +1,1047 @@
+use crate::bench::{self, BenchResult};
+use crate::compat::{self, Capability};
+use crate::events::{EventHook, EventSender, SyncEvent};
+use crate::pins::{self, PinEntry, PinSet};
+use crate::redact::Redacted;
+use crate::search::{SearchIndex, SearchMatch};
+use crate::source_app::{self, AppRule};
+use crate::store::{StateKey, StateStore};
+use crate::taildrop;
+use crate::transform::{self, Transform};
 use crate::{
-    derive_shared_secret, generate_keypair, generate_signing_keypair,
-    sign_message_with_signing_key, verify_signature, ClipboardData, ClipboardManager,
-    CryptoSession, KeyPair, MessageData, MessageType, NodeDiscoveryData, NodeInfo, NodeMap,
-    PostMessage, Result, SigningKeyPair, SystemClipboard,
+    derive_shared_secret, generate_keypair, generate_signing_keypair, key_fingerprint,
+    sign_message_with_signing_key, verify_signature, AckData, ApplyMode, BenchData, BenchReplyData,
+    ClipboardBackend, ClipboardData, CollectRequestData, CollectResponseData, CryptoSession,
+    HeartbeatData, KeyPair, MessageData, MessageType, NodeDiscoveryData, NodeInfo, NodeMap,
+    PeerStats, PeerTrust, PingData, PinsData, PongData, PostMessage, ReplayMode, Result,
+    SigningKeyPair, SyncConfig, TaildropData, WireFormat,
 };
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::{Mutex, RwLock};
-use tracing::{debug, error, info};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tracing::{debug, error, info, warn};
 use x25519_dalek;
 
+/// First retry delay for an unacknowledged update; doubles on every attempt
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Longest delay between retries, so a waking laptop catches up quickly
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+/// Queue changes within this long of each other are saved together
+const QUEUE_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Stats change with every update and matter less, so their saves are batched for longer
+const STATS_SAVE_DELAY: Duration = Duration::from_secs(30);
+
+type OutboundFn = Arc<dyn Fn(PostMessage) + Send + Sync>;
+
+/// Where a peer's answer to a collect request goes: its clipboard, or why it refused
+type CollectReply = oneshot::Sender<std::result::Result<String, String>>;
+
+/// Latest clipboard update a peer has not acknowledged yet
+struct PendingUpdate {
+    message: PostMessage,
+    sequence: u64,
+    attempts: u32,
+    created: Instant,
+    next_retry: Instant,
+}
+
+impl PendingUpdate {
+    fn new(message: PostMessage, sequence: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            message,
+            sequence,
+            attempts: 0,
+            created: now,
+            next_retry: now + INITIAL_RETRY_DELAY,
+        }
+    }
+
+    fn schedule_next_retry(&mut self, now: Instant) {
+        self.attempts += 1;
+        let delay = INITIAL_RETRY_DELAY
+            .saturating_mul(2u32.saturating_pow(self.attempts))
+            .min(MAX_RETRY_DELAY);
+        self.next_retry = now + delay;
+    }
+}
+
+/// Where the local clipboard's current content came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardSource {
+    pub node_id: String,
+    pub node_name: String,
+    /// Whether it was copied on this node
+    pub local: bool,
+    /// App it was copied in, when the peer shares it with `sync.share_origin_app`
+    pub app: Option<String>,
+    /// Unix time it was copied
+    pub timestamp: u64,
+}
+
+/// Origin of the local clipboard's content as recorded when it changed
+#[derive(Debug, Clone)]
+struct SourceRecord {
+    /// ID and name of the peer it came from; unset when copied here
+    peer: Option<(String, String)>,
+    /// App it was copied in, if the peer shared it
+    app: Option<String>,
+    timestamp: u64,
+    /// Tells content applied from a peer apart from a new copy when the watcher sees it
+    content_hash: u64,
+}
+
+/// Whether a peer has applied an update
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    Applied,
+    /// Not acknowledged yet; retried while the peer is online
+    Pending,
+    /// Not acknowledged, and the peer has since gone away
+    Offline,
+}
+
+impl DeliveryState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeliveryState::Applied => "applied",
+            DeliveryState::Pending => "pending",
+            DeliveryState::Offline => "offline",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerDelivery {
+    pub node_id: String,
+    pub node_name: String,
+    pub state: DeliveryState,
+}
+
+/// Which of the peers known when this node's latest clipboard update was sent have
+/// applied it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryReport {
+    pub sequence: u64,
+    /// Unix time the update was sent
+    pub timestamp: u64,
+    pub peers: Vec<PeerDelivery>,
+}
+
+/// A peer's answer to [`SyncManager::ping`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingReply {
+    pub round_trip: Duration,
+    /// Whether the peer could verify the ping's signature
+    pub verified: bool,
+}
+
+/// A peer whose clock differs from this node's by more than `sync.max_clock_skew_secs`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockSkew {
+    pub node_id: String,
+    pub node_name: String,
+    /// Seconds the peer's clock is ahead of this one; negative when it is behind
+    pub skew_secs: i64,
+}
+
+/// A peer dropped by [`SyncManager::cleanup_stale_nodes`] after going quiet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OfflinePeer {
+    pub node_id: String,
+    pub node_name: String,
+    /// Unix time the peer was last heard from
+    pub last_seen: u64,
+    /// Unix time it was dropped
+    pub evicted_at: u64,
+}
+
+/// A peer this node has an encryption session with, known only by key fingerprints
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerSession {
+    pub node_id: String,
+    /// Fingerprint of the public key the session was derived from
+    pub public_key: Option<String>,
+    /// Fingerprint of the key the peer's messages are verified with
+    pub verifying_key: Option<String>,
+}
+
+/// How much the sync manager is holding on to, for state dumps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueSizes {
+    /// Peers with an unacknowledged update
+    pub pending_acks: usize,
+    /// Updates kept for peers that missed them
+    pub recent_updates: usize,
+    pub clipboard_stack: usize,
+    /// Pings, collects and benches waiting for a reply
+    pub awaiting_replies: usize,
+    pub offline_peers: usize,
+}
+
+/// This node's latest clipboard update sent over the sync channel
+#[derive(Debug, Clone)]
+struct SentUpdate {
+    sequence: u64,
+    timestamp: u64,
+    /// IDs and names of the peers it was sent to
+    recipients: Vec<(String, String)>,
+}
+
+/// Clones share all state with the original
+///
+/// Locks held at the same time are always taken in this order, so two tasks can never
+/// wait on each other: `nodes` before `node_verifying_keys`, `pending_acks` before
+/// `peer_stats`, and `clipboard_stack` before `stack_index`. Every other lock is released
+/// before another is taken. The sequence counter and last clipboard hash are atomics, so
+/// sending and receiving updates don't queue up behind each other on them.
+#[derive(Clone)]
 pub struct SyncManager {
-    clipboard: Arc<SystemClipboard>,
+    clipboard: Arc<dyn ClipboardBackend>,
     nodes: Arc<RwLock<NodeMap>>,
-    sequence_counter: Arc<Mutex<u64>>,
+    sequence_counter: Arc<AtomicU64>,
     node_id: Arc<Mutex<String>>,
-    last_clipboard_hash: Arc<Mutex<u64>>,
+    node_name: Arc<Mutex<Option<String>>>,
+    /// Tailnet this node is in; updates from other tailnets are refused unless allowed
+    tailnet: Arc<Mutex<Option<String>>>,
+    last_clipboard_hash: Arc<AtomicU64>,
+    /// Clipboard content made by appending an update to what was there, which isn't
+    /// synced content itself but mustn't be sent back either
+    appended_hash: Arc<AtomicU64>,
     crypto_sessions: Arc<Mutex<HashMap<String, CryptoSession>>>,
     signing_keypair: SigningKeyPair,
     exchange_keypair: KeyPair,
-    node_verifying_keys: Arc<Mutex<HashMap<String, [u8; 32]>>>,
+    node_verifying_keys: Arc<RwLock<HashMap<String, [u8; 32]>>>,
+    outbound: Arc<std::sync::RwLock<Option<OutboundFn>>>,
+    pending_acks: Arc<Mutex<HashMap<String, PendingUpdate>>>,
+    applied_sequences: Arc<Mutex<HashMap<String, u64>>>,
+    sync_config: SyncConfig,
+    recent_updates: Arc<Mutex<VecDeque<(u64, PostMessage)>>>,
+    acked_sequences: Arc<Mutex<HashMap<String, u64>>>,
+    peer_stats: Arc<Mutex<HashMap<String, PeerStats>>>,
+    clipboard_stack: Arc<Mutex<VecDeque<String>>>,
+    /// Full-text index of the stack, kept in step with it under its lock
+    stack_index: Arc<Mutex<SearchIndex>>,
+    pins: Arc<Mutex<PinSet>>,
+    /// Where state is kept between runs, see [`SyncManager::with_state_store`]
+    state_store: Option<Arc<StateStore>>,
+    /// Set while a save of the queue is scheduled
+    queue_save_pending: Arc<AtomicBool>,
+    /// Set while a save of `peer_stats` is scheduled
+    stats_save_pending: Arc<AtomicBool>,
+    app_rules: Arc<Vec<AppRule>>,
+    sync_concealed: bool,
+    transforms: Arc<Vec<Transform>>,
+    /// Longest content, in characters, appending may leave on the clipboard
+    max_length: Option<usize>,
+    advertised_address: Option<IpAddr>,
+    advertised_port: Option<u16>,
+    events: Option<EventSender>,
+    event_hook: Option<EventHook>,
+    paused: Arc<AtomicBool>,
+    clipboard_source: Arc<std::sync::Mutex<Option<SourceRecord>>>,
+    last_sent: Arc<Mutex<Option<SentUpdate>>>,
+    /// Pings awaiting an answer, by nonce
+    pending_pings: Arc<Mutex<HashMap<u64, oneshot::Sender<bool>>>>,
+    /// Collect requests awaiting an answer, by nonce
+    pending_collects: Arc<Mutex<HashMap<u64, CollectReply>>>,
+    /// Bench payloads awaiting an answer, by nonce; answers carry the bytes that arrived
+    pending_benches: Arc<Mutex<HashMap<u64, oneshot::Sender<u64>>>>,
+    /// Seconds each peer's clock was ahead of ours at its latest heartbeat
+    clock_skews: Arc<Mutex<HashMap<String, i64>>>,
+    /// Peers dropped for going quiet, until they announce themselves again
+    offline_peers: Arc<Mutex<HashMap<String, OfflinePeer>>>,
 }
 
 impl SyncManager {
-    pub fn new(clipboard: Arc<SystemClipboard>, node_id: String) -> Result<Self> {
+    pub fn new(clipboard: Arc<dyn ClipboardBackend>, node_id: String) -> Result<Self> {
         let signing_keypair = generate_signing_keypair()?;
         let exchange_keypair = generate_keypair()?;
 
         Ok(Self {
             clipboard,
             nodes: Arc::new(RwLock::new(HashMap::new())),
-            sequence_counter: Arc::new(Mutex::new(0)),
+            sequence_counter: Arc::new(AtomicU64::new(0)),
             node_id: Arc::new(Mutex::new(node_id)),
-            last_clipboard_hash: Arc::new(Mutex::new(0)),
+            node_name: Arc::new(Mutex::new(None)),
+            tailnet: Arc::new(Mutex::new(None)),
+            last_clipboard_hash: Arc::new(AtomicU64::new(0)),
+            appended_hash: Arc::new(AtomicU64::new(0)),
             crypto_sessions: Arc::new(Mutex::new(HashMap::new())),
             signing_keypair,
             exchange_keypair,
-            node_verifying_keys: Arc::new(Mutex::new(HashMap::new())),
+            node_verifying_keys: Arc::new(RwLock::new(HashMap::new())),
+            outbound: Arc::new(std::sync::RwLock::new(None)),
+            pending_acks: Arc::new(Mutex::new(HashMap::new())),
+            applied_sequences: Arc::new(Mutex::new(HashMap::new())),
+            sync_config: SyncConfig::default(),
+            recent_updates: Arc::new(Mutex::new(VecDeque::new())),
+            acked_sequences: Arc::new(Mutex::new(HashMap::new())),
+            peer_stats: Arc::new(Mutex::new(HashMap::new())),
+            clipboard_stack: Arc::new(Mutex::new(VecDeque::new())),
+            stack_index: Arc::new(Mutex::new(SearchIndex::new())),
+            pins: Arc::new(Mutex::new(PinSet::default())),
+            state_store: None,
+            queue_save_pending: Arc::new(AtomicBool::new(false)),
+            stats_save_pending: Arc::new(AtomicBool::new(false)),
+            app_rules: Arc::new(Vec::new()),
+            sync_concealed: false,
+            transforms: Arc::new(Vec::new()),
+            max_length: None,
+            advertised_address: None,
+            advertised_port: None,
+            events: None,
+            event_hook: None,
+            paused: Arc::new(AtomicBool::new(false)),
+            clipboard_source: Arc::new(std::sync::Mutex::new(None)),
+            last_sent: Arc::new(Mutex::new(None)),
+            pending_pings: Arc::new(Mutex::new(HashMap::new())),
+            pending_collects: Arc::new(Mutex::new(HashMap::new())),
+            pending_benches: Arc::new(Mutex::new(HashMap::new())),
+            clock_skews: Arc::new(Mutex::new(HashMap::new())),
+            offline_peers: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
-    /// Update the node ID - useful when Tailscale becomes available after startup
+    /// Configure which missed updates are replayed to peers when they reappear
+    pub fn with_sync_config(mut self, sync_config: SyncConfig) -> Self {
+        self.sync_config = sync_config;
+        self
+    }
+
+    /// Keep state between runs in `store`, starting from any saved there
+    ///
+    /// Pins, known peers, their stats and sequence numbers are always kept; the clipboard stack and
+    /// unacknowledged updates only with `sync.persist_history` and `sync.persist_queue`.
+    /// Apply after [`SyncManager::with_sync_config`], whose limits restored state is held to.
+    pub fn with_state_store(mut self, store: Arc<StateStore>) -> Self {
+        self.state_store = Some(Arc::clone(&store));
+        let what = |key: StateKey| match key {
+            StateKey::History => "clipboard history",
+            StateKey::Pins => "pinned items",
+            StateKey::Peers => "known peers",
+            StateKey::Queue => "queued updates",
+            StateKey::Sequences => "sequence numbers",
+            StateKey::Stats => "peer stats",
+        };
+        for key in StateKey::ALL {
+            let restored = match key {
+                StateKey::History if !self.sync_config.persist_history => Ok(()),
+                StateKey::Queue if !self.sync_config.persists_queue() => Ok(()),
+                StateKey::History => store.history().map(|items| self.restore_history(items)),
+                StateKey::Pins => store.load(key).map(|entries| self.restore_pins(entries)),
+                StateKey::Peers => store.load(key).map(|peers| self.restore_peers(peers)),
+                StateKey::Queue => store
+                    .load(key)
+                    .and_then(|queue| self.restore_queue(queue.unwrap_or_default())),
+                StateKey::Sequences => store
+                    .load(key)
+                    .map(|sequences| self.restore_sequences(sequences)),
+                StateKey::Stats => store.load(key).map(|stats| {
+                    self.peer_stats = Arc::new(Mutex::new(stats.unwrap_or_default()));
+                }),
+            };
+            if let Err(e) = restored {
+                warn!("Failed to restore {}: {}", what(key), e);
+            }
+        }
+        self
+    }
+
+    fn restore_pins(&mut self, entries: Option<Vec<PinEntry>>) {
+        let mut pins = PinSet::default();
+        pins.merge(entries.unwrap_or_default());
+        self.pins = Arc::new(Mutex::new(pins));
+    }
+
+    fn restore_history(&mut self, mut items: Vec<String>) {
+        items.truncate(self.sync_config.stack_size.max(1));
+        self.stack_index = Arc::new(Mutex::new(SearchIndex::with_items(&items)));
+        self.clipboard_stack = Arc::new(Mutex::new(items.into()));
+    }
+
+    /// Saved peers are [`PeerTrust::Remembered`], so their messages verify right away
+    /// while a newer announcement may still replace their key.
+    fn restore_peers(&mut self, peers: Option<Vec<StoredPeer>>) {
+        let Some(peers) = peers else {
+            return;
+        };
+        let mut nodes = NodeMap::new();
+        let mut keys = HashMap::new();
+        for StoredPeer {
+            mut node,
+            verifying_key,
+        } in peers
+        {
+            node.trust = PeerTrust::Remembered;
+            keys.insert(node.id.clone(), verifying_key);
+            nodes.insert(node.id.clone(), node);
+        }
+        if !nodes.is_empty() {
+            info!("Remembered {} peers from the last run", nodes.len());
+        }
+        self.nodes = Arc::new(RwLock::new(nodes));
+        self.node_verifying_keys = Arc::new(RwLock::new(keys));
+    }
+
+    /// Without saved sequences they start over at each restart, and updates from peers
+    /// are applied again even if older.
+    fn restore_sequences(&mut self, stored: Option<StoredSequences>) {
+        let Some(stored) = stored else {
+            return;
+        };
+        self.sequence_counter
+            .fetch_max(stored.last_sent, Ordering::SeqCst);
+        self.applied_sequences = Arc::new(Mutex::new(stored.applied));
+    }
+
+    /// Save `value` as `key` in the state store, if there is one
+    async fn persist<T: Serialize>(&self, key: StateKey, value: &T) -> Result<()> {
+        let Some(store) = &self.state_store else {
+            return Ok(());
+        };
+        let json = serde_json::to_string(value).map_err(|e| {
+            crate::PostError::Serialization(format!("Failed to encode {}: {}", key, e))
+        })?;
+        let store = Arc::clone(store);
+        tokio::task::spawn_blocking(move || store.put(key, &json))
+            .await
+            .map_err(|e| crate::PostError::Storage(e.to_string()))?
+    }
+
+    async fn persist_sequences(&self) {
+        if self.state_store.is_none() {
+            return;
+        }
+        let stored = StoredSequences {
+            last_sent: self.sequence_counter.load(Ordering::SeqCst),
+            applied: self.applied_sequences.lock().await.clone(),
+        };
+        if let Err(e) = self.persist(StateKey::Sequences, &stored).await {
+            warn!("Failed to save sequence numbers: {}", e);
+        }
+    }
+
+    /// Saved updates are signed again with this run's key
+    fn restore_queue(&mut self, mut queue: StoredQueue) -> Result<()> {
+        queue.bound(&self.sync_config, unix_now());
+        // Nothing else holds the lock while the manager is being built
+        let node_id = self
+            .node_id
+            .try_lock()
+            .map(|id| id.clone())
+            .map_err(|_| crate::PostError::Other("Node ID is in use".to_string()))?;
+
+        let mut messages = HashMap::new();
+        for update in &queue.updates {
+            let mut message = PostMessage {
+                version: 1,
+                message_type: MessageType::ClipboardUpdate,
+                data: MessageData::ClipboardUpdate(ClipboardData {
+                    content: update.content.clone(),
+                    timestamp: update.timestamp,
+                    source_node: node_id.clone(),
+                    sequence: update.sequence,
+                    tailnet: update.tailnet.clone(),
+                    content_type: update.content_type.clone(),
+                    metadata: update.metadata.clone(),
+                }),
+                signature: vec![],
+            };
+            Self::sign_post_message(&mut message, &self.signing_keypair)?;
+            messages.insert(update.sequence, message);
+        }
+
+        let now = Instant::now();
+        let mut pending = HashMap::new();
+        for (peer, stored) in queue.pending {
+            let Some(message) = messages.get(&stored.sequence) else {
+                continue;
+            };
+            let age = Duration::from_secs(unix_now().saturating_sub(stored.created));
+            pending.insert(
+                peer,
+                PendingUpdate {
+                    message: message.clone(),
+                    sequence: stored.sequence,
+                    attempts: stored.attempts,
+                    created: now.checked_sub(age).unwrap_or(now),
+                    next_retry: now + INITIAL_RETRY_DELAY,
+                },
+            );
+        }
+
+        let mut recent: Vec<(u64, PostMessage)> = messages.into_iter().collect();
+        recent.sort_by_key(|(sequence, _)| *sequence);
+        let keep = self.sync_config.replay_capacity();
+        let recent: VecDeque<_> = recent.into_iter().rev().take(keep).rev().collect();
+
+        if !pending.is_empty() {
+            info!(
+                "Restored {} queued update(s) for {} peer(s)",
+                recent.len(),
+                pending.len()
+            );
+        }
+        self.pending_acks = Arc::new(Mutex::new(pending));
+        self.recent_updates = Arc::new(Mutex::new(recent));
+        self.acked_sequences = Arc::new(Mutex::new(queue.acked));
+        Ok(())
+    }
+
+    /// Save the updates peers have yet to acknowledge, if they are kept, together with any
+    /// other change within [`QUEUE_SAVE_DELAY`]
+    async fn persist_queue(&self) {
+        if self.state_store.is_none() || !self.sync_config.persists_queue() {
+            return;
+        }
+        if self.queue_save_pending.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let sync = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(QUEUE_SAVE_DELAY).await;
+            sync.flush_queue().await;
+        });
+    }
+
+    /// Save the peer stats together with any other change within [`STATS_SAVE_DELAY`]
+    fn persist_stats(&self) {
+        if self.state_store.is_none() || self.stats_save_pending.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let sync = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(STATS_SAVE_DELAY).await;
+            sync.flush_stats().await;
+        });
+    }
+
+    /// Write state whose save is still scheduled, e.g. before the daemon exits
+    pub async fn flush_state(&self) {
+        self.flush_queue().await;
+        self.flush_stats().await;
+    }
+
+    async fn flush_stats(&self) {
+        if !self.stats_save_pending.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let stats = self.peer_stats.lock().await.clone();
+        if let Err(e) = self.persist(StateKey::Stats, &stats).await {
+            warn!("Failed to save peer stats: {}", e);
+        }
+    }
+
+    async fn flush_queue(&self) {
+        if !self.queue_save_pending.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let recent = self.recent_updates.lock().await.clone();
+        let pending: Vec<(String, u64, u32, Instant, PostMessage)> = self
+            .pending_acks
+            .lock()
+            .await
+            .iter()
+            .map(|(peer, update)| {
+                (
+                    peer.clone(),
+                    update.sequence,
+                    update.attempts,
+                    update.created,
+                    update.message.clone(),
+                )
+            })
+            .collect();
+        let acked = self.acked_sequences.lock().await.clone();
+
+        let now = unix_now();
+        let mut queue = StoredQueue {
+            acked,
+            ..StoredQueue::default()
+        };
+        let messages = recent
+            .iter()
+            .map(|(_, message)| message)
+            .chain(pending.iter().map(|(.., message)| message));
+        for message in messages {
+            if let MessageData::ClipboardUpdate(data) = &message.data {
+                if !queue.updates.iter().any(|u| u.sequence == data.sequence) {
+                    queue.updates.push_back(StoredUpdate {
+                        sequence: data.sequence,
+                        timestamp: data.timestamp,
+                        content: data.content.clone(),
+                        tailnet: data.tailnet.clone(),
+                        content_type: data.content_type.clone(),
+                        metadata: data.metadata.clone(),
+                    });
+                }
+            }
+        }
+        for (peer, sequence, attempts, created, _) in pending {
+            queue.pending.insert(
+                peer,
+                StoredPending {
+                    sequence,
+                    attempts,
+                    created: now.saturating_sub(created.elapsed().as_secs()),
+                },
+            );
+        }
+        queue.bound(&self.sync_config, now);
+
+        if let Err(e) = self.persist(StateKey::Queue, &queue).await {
+            warn!("Failed to save queued updates: {}", e);
+        }
+    }
+
+    /// Skip local clipboard changes made in apps these rules block
+    pub fn with_app_rules(mut self, rules: Vec<AppRule>) -> Self {
+        self.app_rules = Arc::new(rules);
+        self
+    }
+
+    /// Also sync content password managers mark as a secret instead of skipping it
+    pub fn with_concealed_sync(mut self, sync_concealed: bool) -> Self {
+        self.sync_concealed = sync_concealed;
+        self
+    }
+
+    /// Rewrite content with `transforms`, in order, before it is broadcast
+    pub fn with_transforms(mut self, transforms: Vec<Transform>) -> Self {
+        self.transforms = Arc::new(transforms);
+        self
+    }
+
+    /// Replace the clipboard instead of appending to it when the result would be longer
+    /// than `max_length` characters
+    pub fn with_max_length(mut self, max_length: Option<usize>) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Endpoint peers are told to connect to; `address` overrides this node's Tailscale IP
+    pub fn with_advertised_endpoint(mut self, address: Option<IpAddr>, port: u16) -> Self {
+        self.advertised_address = address;
+        self.advertised_port = Some(port);
+        self
+    }
+
+    /// Leave local clipboard changes unsynced while `paused` is set; shared so the
+    /// setting outlives this manager
+    pub fn with_pause_switch(mut self, paused: Arc<AtomicBool>) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// Whether local clipboard changes are currently left unsynced
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Publish sync activity to `events`
+    pub fn with_events(mut self, events: EventSender) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Also hand every event to `hook`, synchronously and before it is published
+    pub fn with_event_hook(mut self, hook: EventHook) -> Self {
+        self.event_hook = Some(hook);
+        self
+    }
+
+    fn emit(&self, event: SyncEvent) {
+        if let Some(hook) = &self.event_hook {
+            hook(&event);
+        }
+        if let Some(events) = &self.events {
+            // Nobody listening is fine
+            let _ = events.send(event);
+        }
+    }
+
+    /// Whether updates are only logged, never sent or applied
+    pub fn is_dry_run(&self) -> bool {
+        self.sync_config.dry_run
+    }
+
+    /// Switch to a new node ID, e.g. after a Tailscale re-login or machine rename
+    ///
+    /// Peers and sessions learned under the old ID are forgotten and peers are asked to
+    /// announce themselves again, so both sides re-learn each other under the new one.
     pub async fn update_node_id(&self, new_node_id: String) -> Result<()> {
-        let mut node_id = self.node_id.lock().await;
-        if *node_id != new_node_id {
+        {
+            let mut node_id = self.node_id.lock().await;
+            if *node_id == new_node_id {
+                return Ok(());
+            }
             info!("Updating node ID from {} to {}", *node_id, new_node_id);
             *node_id = new_node_id;
         }
+
+        self.forget_peers().await;
+        if let Some(outbound) = self.outbound_fn() {
+            outbound(self.create_rediscovery_message().await?);
+        }
         Ok(())
     }
 
+    /// Ed25519 key peers verify this node's messages with
+    pub fn signing_public_key(&self) -> &[u8] {
+        &self.signing_keypair.verifying_key
+    }
+
     /// Get the current node ID
     pub async fn get_node_id(&self) -> String {
         self.node_id.lock().await.clone()
     }
 
+    /// Set the friendly name advertised to peers in node discovery
+    pub async fn update_node_name(&self, new_node_name: String) {
+        *self.node_name.lock().await = sanitize_node_name(&new_node_name);
+    }
+
+    /// Set the tailnet this node is in, which outgoing updates are tagged with
+    pub async fn update_tailnet(&self, tailnet: Option<String>) {
+        *self.tailnet.lock().await = tailnet;
+    }
+
+    /// Get the friendly name of this node, falling back to its ID
+    pub async fn get_node_name(&self) -> String {
+        match self.node_name.lock().await.clone() {
+            Some(name) => name,
+            None => self.get_node_id().await,
+        }
+    }
+
     pub async fn start_sync_loop<F>(&self, send_message: F) -> Result<()>
     where
         F: Fn(PostMessage) + Send + Sync + 'static + Clone,
     {
-        let clipboard = Arc::clone(&self.clipboard);
-        let sequence_counter = Arc::clone(&self.sequence_counter);
-        let node_id = self.node_id.clone();
-        let last_hash = Arc::clone(&self.last_clipboard_hash);
-        let send_fn = send_message.clone();
-        let signing_keypair = self.signing_keypair.clone();
-
-        clipboard
-            .watch_changes_generic(move |content| {
-                let send_fn = send_fn.clone();
-                let sequence_counter = Arc::clone(&sequence_counter);
-                let node_id = node_id.clone();
-                let last_hash = Arc::clone(&last_hash);
-                let signing_keypair = signing_keypair.clone();
-
-                tokio::spawn(async move {
-                    let content_hash = calculate_hash(&content);
-                    let mut last = last_hash.lock().await;
-
-                    if content_hash == *last {
-                        return;
-                    }
-                    *last = content_hash;
-                    drop(last);
-
-                    let mut seq = sequence_counter.lock().await;
-                    *seq += 1;
-                    let sequence = *seq;
-                    drop(seq);
-
-                    let timestamp = SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs();
-
-                    let source_node = node_id.lock().await.clone();
-                    let clipboard_data = ClipboardData {
-                        content,
-                        timestamp,
-                        source_node,
-                        sequence,
-                    };
-
-                    let mut message = PostMessage {
-                        version: 1,
-                        message_type: MessageType::ClipboardUpdate,
-                        data: MessageData::ClipboardUpdate(clipboard_data),
-                        signature: vec![],
-                    };
+        *self
+            .outbound
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Arc::new(send_message));
 
-                    // Sign the message
-                    match Self::sign_post_message(&mut message, &signing_keypair) {
-                        Ok(()) => {
-                            debug!("Broadcasting clipboard update (seq: {})", sequence);
-                            send_fn(message);
+        let sync = self.clone();
+        self.clipboard
+            .watch_changes(Box::new(move |content| {
+                sync.note_local_copy(&content);
+                if sync.is_paused() {
+                    sync.log_skipped("sync is paused");
+                    return;
+                }
+                // Look up the app now, while it most likely still has focus
+                let mut metadata = BTreeMap::new();
+                if !sync.app_rules.is_empty() || sync.sync_config.share_origin_app {
+                    if let Some(app) = source_app::frontmost_app() {
+                        if let Err(e) = source_app::check_rules(&sync.app_rules, &app) {
+                            sync.log_skipped(&e.to_string());
+                            return;
+                        }
+                        if sync.sync_config.share_origin_app {
+                            metadata.insert(crate::metadata::ORIGIN_APP.to_string(), app.name);
                         }
-                        Err(e) => {
-                            tracing::error!("Failed to sign clipboard update message: {}", e);
+                    }
+                }
+                let sync = sync.clone();
+                tokio::spawn(async move {
+                    if !sync.sync_concealed {
+                        if let Some(reason) = sync.concealed_skip_reason(&content).await {
+                            sync.log_skipped(&reason);
+                            return;
                         }
                     }
+                    if let Err(e) = sync
+                        .broadcast_content_with_metadata(content, metadata)
+                        .await
+                    {
+                        error!("Failed to broadcast clipboard update: {}", e);
+                    }
                 });
-            })
+            }))
             .await?;
 
         Ok(())
     }
 
+    /// Where the local clipboard's current content came from, once it changed
+    pub async fn get_clipboard_source(&self) -> Option<ClipboardSource> {
+        let record = self.lock_source().clone()?;
+        Some(match record.peer {
+            Some((node_id, node_name)) => ClipboardSource {
+                node_id,
+                node_name,
+                local: false,
+                app: record.app,
+                timestamp: record.timestamp,
+            },
+            None => ClipboardSource {
+                node_id: self.get_node_id().await,
+                node_name: self.get_node_name().await,
+                local: true,
+                app: None,
+                timestamp: record.timestamp,
+            },
+        })
+    }
+
+    /// Attribute content seen by the clipboard watcher to this node, unless it is what
+    /// was just applied from a peer
+    fn note_local_copy(&self, content: &str) {
+        let content_hash = calculate_hash(content);
+        let mut source = self.lock_source();
+        if source
+            .as_ref()
+            .is_some_and(|source| source.content_hash == content_hash)
+        {
+            return;
+        }
+        *source = Some(SourceRecord {
+            peer: None,
+            app: None,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            content_hash,
+        });
+    }
+
+    fn lock_source(&self) -> std::sync::MutexGuard<'_, Option<SourceRecord>> {
+        self.clipboard_source
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Why a local copy of `content` mustn't be synced as a possible secret, if it mustn't
+    ///
+    /// The marker is read after the copy, so it's only trusted while the clipboard still
+    /// holds `content`; a newer copy gets its own check. If the marker can't be read the
+    /// copy is treated as marked.
+    async fn concealed_skip_reason(&self, content: &str) -> Option<String> {
+        match self.clipboard.is_concealed().await {
+            Ok(true) => Some("marked as a secret".to_string()),
+            Ok(false) => match self.clipboard.get_contents().await {
+                Ok(current) if current == content => None,
+                _ => Some("changed while checking whether it was marked as a secret".to_string()),
+            },
+            Err(e) => {
+                warn!(
+                    "Can't tell whether copies are marked as secrets, so they aren't synced \
+                     (set filters.sync_concealed = true to sync them anyway): {}",
+                    e
+                );
+                Some("couldn't check whether it was marked as a secret".to_string())
+            }
+        }
+    }
+
+    /// Filters are what a dry run checks, so show their decisions without debug logging
+    fn log_skipped(&self, reason: &str) {
+        if self.is_dry_run() {
+            info!("Dry run: would not sync clipboard change: {}", reason);
+        } else {
+            debug!("Not syncing clipboard change: {}", reason);
+        }
+        self.emit(SyncEvent::Filtered {
+            from: None,
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Send `content` to every peer as a new clipboard update
+    ///
+    /// Returns `false` without sending when it matches the last synced content
+    /// or is empty once the configured transforms ran.
+    /// The sync loop must have been started.
+    pub async fn broadcast_content(&self, content: String) -> Result<bool> {
+        self.broadcast_content_with_metadata(content, BTreeMap::new())
+            .await
+    }
+
+    /// Like [`SyncManager::broadcast_content`], with `metadata` such as the app it was
+    /// copied in sent along
+    pub async fn broadcast_content_with_metadata(
+        &self,
+        content: String,
+        mut metadata: BTreeMap<String, String>,
+    ) -> Result<bool> {
+        let send_fn = self
+            .outbound_fn()
+            .ok_or_else(|| crate::PostError::Other("Sync loop has not been started".to_string()))?;
+
+        let content = transform::apply_all(&self.transforms, content);
+        if content.is_empty() {
+            return Ok(false);
+        }
+
+        let content_hash = calculate_hash(&content);
+        if content_hash == self.appended_hash.load(Ordering::SeqCst)
+            || self
+                .last_clipboard_hash
+                .swap(content_hash, Ordering::SeqCst)
+                == content_hash
+        {
+            return Ok(false);
+        }
+
+        if self.is_dry_run() {
+            let nodes = self.nodes.read().await;
+            let peers: Vec<&str> = nodes.values().map(|node| node.name.as_str()).collect();
+            info!(
+                "Dry run: would send clipboard update ({}) to {}",
+                Redacted(&content),
+                if peers.is_empty() {
+                    "no peers".to_string()
+                } else {
+                    peers.join(", ")
+                }
+            );
+            return Ok(false);
+        }
+
+        let sequence = self.sequence_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        self.persist_sequences().await;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        self.push_to_stack(&content).await;
+
+        let source_node = self.node_id.lock().await.clone();
+        let peers: Vec<String> = self.nodes.read().await.keys().cloned().collect();
+        self.record_sent_to(&peers, content.len()).await;
+        self.emit(SyncEvent::Sent {
+            content: content.clone(),
+            peers: peers.len(),
+            timestamp,
+        });
+
+        // Too large for the sync channel; Taildrop carries the content instead
+        if self
+            .sync_config
+            .taildrop_threshold
+            .is_some_and(|threshold| content.len() > threshold)
+        {
+            let mut offer =
+                Self::taildrop_offer(&content, source_node, sequence, timestamp).await?;
+            Self::sign_post_message(&mut offer, &self.signing_keypair)?;
+            info!(
+                "Sending clipboard update (seq: {}, {}) with Taildrop",
+                sequence,
+                Redacted(&content)
+            );
+            send_fn(offer);
+            return Ok(true);
+        }
+
+        // Older peers would drop the metadata, and then fail to verify the update
+        if !metadata.is_empty() {
+            let nodes = self.nodes.read().await;
+            if let Some(node) = nodes
+                .values()
+                .find(|node| !compat::peer_supports(&node.capabilities, Capability::Metadata))
+            {
+                debug!(
+                    "Leaving out clipboard metadata, which {} can't verify",
+                    node.name
+                );
+                metadata.clear();
+            }
+        }
+
+        let clipboard_data = ClipboardData {
+            content: content.into(),
+            timestamp,
+            source_node,
+            sequence,
+            tailnet: self.tailnet.lock().await.clone(),
+            content_type: crate::TEXT_PLAIN.to_string(),
+            metadata,
+        };
+
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::ClipboardUpdate,
+            data: MessageData::ClipboardUpdate(clipboard_data),
+            signature: vec![],
+        };
+
+        // Sign the message
+        Self::sign_post_message(&mut message, &self.signing_keypair)?;
+        debug!("Broadcasting clipboard update (seq: {})", sequence);
+
+        let nodes = self.nodes.read().await;
+        let recipients = peers
+            .iter()
+            .map(|id| {
+                let name = nodes
+                    .get(id)
+                    .map_or_else(|| id.clone(), |node| node.name.clone());
+                (id.clone(), name)
+            })
+            .collect();
+        drop(nodes);
+        *self.last_sent.lock().await = Some(SentUpdate {
+            sequence,
+            timestamp,
+            recipients,
+        });
+
+        // Every known peer must acknowledge this update or it is retried
+        let mut pending = self.pending_acks.lock().await;
+        for peer in peers {
+            pending.insert(peer, PendingUpdate::new(message.clone(), sequence));
+        }
+        drop(pending);
+
+        // Kept for peers that are offline right now
+        let mut recent = self.recent_updates.lock().await;
+        recent.push_back((sequence, message.clone()));
+        while recent.len() > self.sync_config.replay_capacity() {
+            recent.pop_front();
+        }
+        drop(recent);
+        self.persist_queue().await;
+
+        send_fn(message);
+        Ok(true)
+    }
+
+    /// Stage `content` for Taildrop and build the unsigned offer announcing it
+    async fn taildrop_offer(
+        content: &str,
+        source_node: String,
+        sequence: u64,
+        timestamp: u64,
+    ) -> Result<PostMessage> {
+        let file_name = taildrop::payload_file_name(sequence);
+        taildrop::stage_payload(&file_name, content).await?;
+
+        Ok(PostMessage {
+            version: 1,
+            message_type: MessageType::TaildropOffer,
+            data: MessageData::TaildropOffer(TaildropData {
+                source_node,
+                file_name,
+                size: content.len() as u64,
+                sequence,
+                timestamp,
+            }),
+            signature: vec![],
+        })
+    }
+
     fn sign_post_message(
         message: &mut PostMessage,
         signing_keypair: &SigningKeyPair,
@@ -144,146 +1059,1156 @@ impl SyncManager {
         Ok(())
     }
 
-    fn sign_message_with_keypair(
-        signing_keypair: &SigningKeyPair,
-        message: &[u8],
-    ) -> Result<Vec<u8>> {
-        sign_message_with_signing_key(signing_keypair, message)
+    fn sign_message_with_keypair(
+        signing_keypair: &SigningKeyPair,
+        message: &[u8],
+    ) -> Result<Vec<u8>> {
+        sign_message_with_signing_key(signing_keypair, message)
+    }
+
+    async fn verify_message_signature(
+        &self,
+        message: &PostMessage,
+        source_node: &str,
+    ) -> Result<()> {
+        // Create a message copy without the signature for verification
+        let mut message_for_verification = message.clone();
+        message_for_verification.signature = Vec::new();
+
+        let message_bytes = serde_json::to_vec(&message_for_verification).map_err(|e| {
+            crate::PostError::Serialization(format!(
+                "Failed to serialize message for verification: {}",
+                e
+            ))
+        })?;
+
+        // Get the verifying key for this node
+        let verifying_key = self
+            .node_verifying_keys
+            .read()
+            .await
+            .get(source_node)
+            .copied();
+        let refused = match verifying_key {
+            None => crate::PostError::UnknownPeer(format!("no verifying key for {}", source_node)),
+            Some(key) if verify_signature(&key, &message_bytes, &message.signature)? => {
+                return Ok(())
+            }
+            Some(_) => crate::PostError::SignatureInvalid(format!("message from {}", source_node)),
+        };
+        self.log_refused(
+            source_node,
+            format!("{:?} refused: {}", message.message_type, refused),
+        );
+        Err(refused)
+    }
+
+    /// Report a message from `source_node` that was refused, e.g. to the audit log
+    fn log_refused(&self, source_node: &str, reason: String) {
+        self.emit(SyncEvent::Filtered {
+            from: Some(source_node.to_string()),
+            reason,
+        });
+    }
+
+    pub async fn handle_message(&self, message: PostMessage) -> Result<()> {
+        match &message.data {
+            MessageData::ClipboardUpdate(data) => {
+                // Verify message signature
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                self.record_update_from(&data.source_node, data.content.len())
+                    .await;
+                if self.is_dry_run() {
+                    info!(
+                        "Dry run: would apply clipboard update {} from {} ({})",
+                        data.sequence,
+                        data.source_node,
+                        Redacted(&data.content)
+                    );
+                    return Ok(());
+                }
+                self.handle_clipboard_update(data.clone()).await?;
+            }
+            MessageData::Ack(data) => {
+                // Acks are broadcast; only the node that sent the update cares
+                if data.origin_node != *self.node_id.lock().await {
+                    return Ok(());
+                }
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                self.handle_ack(data).await;
+            }
+            MessageData::TaildropOffer(data) => {
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                if !taildrop::is_payload_file_name(&data.file_name) {
+                    self.log_refused(
+                        &data.source_node,
+                        format!("invalid Taildrop file name {:?}", data.file_name),
+                    );
+                    return Err(crate::PostError::Tailscale(format!(
+                        "Invalid Taildrop file name from {}: {}",
+                        data.source_node, data.file_name
+                    )));
+                }
+                // The daemon fetches the file; there is no clipboard content to apply
+                info!(
+                    "Node {} sent {} bytes with Taildrop as {}",
+                    data.source_node, data.size, data.file_name
+                );
+            }
+            MessageData::Pins(data) => {
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                self.handle_pins(data).await;
+            }
+            MessageData::Ping(data) => {
+                if data.target_node != *self.node_id.lock().await {
+                    return Ok(());
+                }
+                // A forged ping must not get a signed answer out of this node
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                self.send_pong(data).await?;
+            }
+            MessageData::Pong(data) => {
+                if data.origin_node != *self.node_id.lock().await {
+                    return Ok(());
+                }
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                if let Some(reply) = self.pending_pings.lock().await.remove(&data.nonce) {
+                    let _ = reply.send(data.verified);
+                }
+            }
+            MessageData::CollectRequest(data) => {
+                if data.target_node != *self.node_id.lock().await {
+                    return Ok(());
+                }
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                self.answer_collect(data).await?;
+            }
+            MessageData::CollectResponse(data) => {
+                if data.origin_node != *self.node_id.lock().await {
+                    return Ok(());
+                }
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                if let Some(reply) = self.pending_collects.lock().await.remove(&data.nonce) {
+                    let _ = reply.send(data.content.clone());
+                }
+            }
+            MessageData::Bench(data) => {
+                if data.target_node != *self.node_id.lock().await {
+                    return Ok(());
+                }
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                self.send_bench_reply(data).await?;
+            }
+            MessageData::BenchReply(data) => {
+                if data.origin_node != *self.node_id.lock().await {
+                    return Ok(());
+                }
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                if let Some(reply) = self.pending_benches.lock().await.remove(&data.nonce) {
+                    let _ = reply.send(data.received);
+                }
+            }
+            MessageData::Heartbeat(data) => {
+                // Verify message signature
+                self.verify_message_signature(&message, &data.source_node)
+                    .await?;
+                self.handle_heartbeat(&data.source_node).await?;
+                self.record_clock_skew(&data.source_node, data.timestamp)
+                    .await;
+            }
+            MessageData::NodeDiscovery(data) => {
+                message.verify_discovery(data)?;
+
+                // Validate that the key is not all zeros (common security mistake)
+                if data.public_key.iter().all(|&b| b == 0) {
+                    self.log_refused(&data.source_node, "all-zero public key".to_string());
+                    return Err(crate::PostError::Crypto(
+                        "Invalid X25519 public key: all zeros".to_string(),
+                    ));
+                }
+
+                let remembered = self
+                    .nodes
+                    .read()
+                    .await
+                    .get(&data.source_node)
+                    .is_some_and(|node| node.trust == PeerTrust::Remembered);
+
+                // Store the binding between source_node and verifying key
+                let mut node_keys = self.node_verifying_keys.write().await;
+                if let Some(existing_key) = node_keys.get(&data.source_node) {
+                    // Verify the node is still using the same verifying key, unless the
+                    // key is from an earlier run and the node has restarted since
+                    let rekeyed = existing_key != &data.signing_public_key;
+                    if rekeyed {
+                        if !remembered {
+                            self.log_refused(
+                                &data.source_node,
+                                "announced a different verifying key".to_string(),
+                            );
+                            return Err(crate::PostError::Crypto(format!(
+                                "Node {} attempted to change verifying key",
+                                data.source_node
+                            )));
+                        }
+                        debug!(
+                            "Node {} announced a new verifying key since the last run",
+                            data.source_node
+                        );
+                        node_keys.insert(data.source_node.clone(), data.signing_public_key);
+                    }
+                    drop(node_keys);
+                } else {
+                    // The same key under another ID is that peer after its identity changed
+                    let previous_ids: Vec<String> = node_keys
+                        .iter()
+                        .filter(|(_, key)| **key == data.signing_public_key)
+                        .map(|(id, _)| id.clone())
+                        .collect();
+                    for id in &previous_ids {
+                        node_keys.remove(id);
+                    }
+                    // Store the new binding
+                    node_keys.insert(data.source_node.clone(), data.signing_public_key);
+                    drop(node_keys);
+
+                    for id in previous_ids {
+                        info!("Node {} is now {}", id, data.source_node);
+                        self.forget_node(&id).await;
+                    }
+                }
+
+                // Only now proceed with session derivation after successful verification
+                self.handle_node_discovery(data).await?;
+
+                if data.wants_reply {
+                    self.reply_to_discovery(&data.source_node).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_clipboard_update(&self, data: ClipboardData) -> Result<()> {
+        let current_node_id = self.node_id.lock().await.clone();
+        if data.source_node == current_node_id {
+            debug!("Ignoring own clipboard update");
+            return Ok(());
+        }
+
+        // Untagged updates come from builds that predate tailnet tags, and are only trusted
+        // while no other tailnet is; the transport also checks the connection's tailnet
+        let own_tailnet = self.tailnet.lock().await.clone();
+        if own_tailnet.is_some()
+            && data.tailnet.is_none()
+            && !self.sync_config.allowed_tailnets.is_empty()
+        {
+            warn!(
+                "Refusing clipboard update from {} that doesn't say which tailnet it is from, \
+                 as sync.allowed_tailnets is set",
+                data.source_node
+            );
+            self.emit(SyncEvent::Filtered {
+                from: Some(data.source_node.clone()),
+                reason: "sender didn't say which tailnet it is in".to_string(),
+            });
+            return Err(crate::PostError::Filtered(
+                "Clipboard update without a tailnet".to_string(),
+            ));
+        }
+        if let (Some(own), Some(tailnet)) = (own_tailnet, &data.tailnet) {
+            if !self.sync_config.allows_tailnet(&own, tailnet) {
+                warn!(
+                    "Refusing clipboard update from {} in tailnet {}; add it to sync.allowed_tailnets to accept it",
+                    data.source_node, tailnet
+                );
+                self.emit(SyncEvent::Filtered {
+                    from: Some(data.source_node.clone()),
+                    reason: format!("sender is in tailnet {}", tailnet),
+                });
+                return Err(crate::PostError::Filtered(format!(
+                    "Clipboard update from tailnet {}",
+                    tailnet
+                )));
+            }
+        }
+
+        if !data.is_text() {
+            warn!(
+                "Refusing clipboard update from {} of type {}, which this version can't apply",
+                data.source_node, data.content_type
+            );
+            self.emit(SyncEvent::Filtered {
+                from: Some(data.source_node.clone()),
+                reason: format!("unsupported content type {}", data.content_type),
+            });
+            return Err(crate::PostError::Filtered(format!(
+                "Clipboard update of type {}",
+                data.content_type
+            )));
+        }
+
+        // Retries can arrive after a newer update from the same node; never go backwards
+        let mut applied = self.applied_sequences.lock().await;
+        if applied
+            .get(&data.source_node)
+            .is_some_and(|&last| data.sequence <= last)
+        {
+            debug!(
+                "Ignoring already applied update {} from {}",
+                data.sequence, data.source_node
+            );
+            drop(applied);
+            self.send_ack(&data).await;
+            return Ok(());
+        }
+        let previous_sequence = applied.insert(data.source_node.clone(), data.sequence);
+        drop(applied);
+
+        // Checking and recording in one step, so a copy the watcher records meanwhile
+        // either counts as the duplicate or is overwritten as the older of the two.
+        // Recorded before setting, since the watcher may see the content first.
+        let content_hash = calculate_hash(&data.content);
+        let previous_hash = self
+            .last_clipboard_hash
+            .swap(content_hash, Ordering::SeqCst);
+        if previous_hash == content_hash {
+            debug!("Duplicate clipboard content, ignoring");
+            self.send_ack(&data).await;
+            return Ok(());
+        }
+
+        let source_name = self
+            .nodes
+            .read()
+            .await
+            .get(&data.source_node)
+            .map(|node| node.name.clone())
+            .unwrap_or_else(|| data.source_node.clone());
+        info!(
+            "Received clipboard update from {}: {}",
+            source_name,
+            Redacted(&data.content)
+        );
+
+        let applied = match self
+            .sync_config
+            .apply_mode_for(&data.source_node, &source_name)
+        {
+            ApplyMode::Replace => data.content.to_string(),
+            ApplyMode::Append => match self.clipboard.get_contents().await {
+                Ok(current) if !current.is_empty() => {
+                    let combined = format!(
+                        "{}{}{}",
+                        current, self.sync_config.append_separator, data.content
+                    );
+                    if self
+                        .max_length
+                        .is_some_and(|max_length| combined.chars().count() > max_length)
+                    {
+                        debug!("Appending would pass the length limit, replacing instead");
+                        data.content.to_string()
+                    } else {
+                        combined
+                    }
+                }
+                Ok(_) => data.content.to_string(),
+                Err(e) => {
+                    warn!(
+                        "Couldn't read the clipboard to append to, replacing it: {}",
+                        e
+                    );
+                    data.content.to_string()
+                }
+            },
+        };
+        // The watcher sees what was set, which differs from the update when appending.
+        // Only the update counts as synced, so local content it was appended to is never
+        // handed out to peers.
+        let applied_hash = calculate_hash(&applied);
+        let appended_hash = if applied_hash == content_hash {
+            0
+        } else {
+            applied_hash
+        };
+
+        let previous_appended = self.appended_hash.swap(appended_hash, Ordering::SeqCst);
+        let previous_source = self.lock_source().replace(SourceRecord {
+            peer: Some((data.source_node.clone(), source_name.clone())),
+            app: data.metadata.get(crate::metadata::ORIGIN_APP).cloned(),
+            timestamp: data.timestamp,
+            content_hash: applied_hash,
+        });
+        match self.clipboard.set_contents(&applied).await {
+            Ok(()) => {
+                info!("Successfully set clipboard contents on Linux");
+            }
+            Err(e) => {
+                error!("Failed to set clipboard contents on Linux: {}", e);
+                // Unless the watcher has recorded a copy of its own since
+                let _ = self.last_clipboard_hash.compare_exchange(
+                    content_hash,
+                    previous_hash,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                );
+                let _ = self.appended_hash.compare_exchange(
+                    appended_hash,
+                    previous_appended,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                );
+                {
+                    let mut source = self.lock_source();
+                    if source
+                        .as_ref()
+                        .is_some_and(|source| source.content_hash == applied_hash)
+                    {
+                        *source = previous_source;
+                    }
+                }
+                // Let the sender retry rather than treating the update as applied, while
+                // still refusing anything older than what was applied before
+                let mut applied = self.applied_sequences.lock().await;
+                if applied.get(&data.source_node) == Some(&data.sequence) {
+                    match previous_sequence {
+                        Some(sequence) => applied.insert(data.source_node.clone(), sequence),
+                        None => applied.remove(&data.source_node),
+                    };
+                }
+                return Err(e);
+            }
+        }
+        self.persist_sequences().await;
+
+        self.push_to_stack(&data.content).await;
+        self.send_ack(&data).await;
+        self.emit(SyncEvent::Received {
+            from: data.source_node,
+            from_name: source_name,
+            content: data.content.to_string(),
+            timestamp: data.timestamp,
+        });
+        Ok(())
+    }
+
+    /// Tell the sender of `update` that it has been applied
+    async fn send_ack(&self, update: &ClipboardData) {
+        let Some(outbound) = self.outbound_fn() else {
+            return;
+        };
+
+        let ack = AckData {
+            source_node: self.node_id.lock().await.clone(),
+            origin_node: update.source_node.clone(),
+            sequence: update.sequence,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        };
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::Ack,
+            data: MessageData::Ack(ack),
+            signature: vec![],
+        };
+
+        match Self::sign_post_message(&mut message, &self.signing_keypair) {
+            Ok(()) => outbound(message),
+            Err(e) => error!("Failed to sign ack: {}", e),
+        }
+    }
+
+    async fn handle_ack(&self, ack: &AckData) {
+        let mut acked = self.acked_sequences.lock().await;
+        let last_acked = acked.entry(ack.source_node.clone()).or_default();
+        *last_acked = (*last_acked).max(ack.sequence);
+        drop(acked);
+
+        let mut pending = self.pending_acks.lock().await;
+        let acknowledged = pending
+            .get(&ack.source_node)
+            .is_some_and(|update| ack.sequence >= update.sequence);
+        let update = if acknowledged {
+            debug!(
+                "Node {} acknowledged update {}",
+                ack.source_node, ack.sequence
+            );
+            pending.remove(&ack.source_node)
+        } else {
+            None
+        };
+        drop(pending);
+
+        self.persist_queue().await;
+
+        let mut stats = self.peer_stats.lock().await;
+        let peer = stats.entry(ack.source_node.clone()).or_default();
+        peer.acks_received += 1;
+        // Retried updates would count the time the peer was offline
+        if let Some(update) = update.filter(|update| update.attempts == 0) {
+            peer.ack_latency_total += update.created.elapsed();
+            peer.ack_latency_samples += 1;
+        }
+        drop(stats);
+        self.persist_stats();
+    }
+
+    /// Which peers have applied this node's latest clipboard update, once one was sent
+    pub async fn last_delivery(&self) -> Option<DeliveryReport> {
+        let sent = self.last_sent.lock().await.clone()?;
+        let acked = self.acked_sequences.lock().await.clone();
+        let nodes = self.nodes.read().await;
+        let peers = sent
+            .recipients
+            .into_iter()
+            .map(|(node_id, node_name)| {
+                let state = if acked
+                    .get(&node_id)
+                    .is_some_and(|&sequence| sequence >= sent.sequence)
+                {
+                    DeliveryState::Applied
+                } else if nodes.contains_key(&node_id) {
+                    DeliveryState::Pending
+                } else {
+                    DeliveryState::Offline
+                };
+                PeerDelivery {
+                    node_id,
+                    node_name,
+                    state,
+                }
+            })
+            .collect();
+        Some(DeliveryReport {
+            sequence: sent.sequence,
+            timestamp: sent.timestamp,
+            peers,
+        })
+    }
+
+    /// Re-broadcast updates that peers have not acknowledged and whose backoff has elapsed
+    ///
+    /// Returns the number of updates sent. Call periodically once the sync loop is running.
+    pub async fn retry_unacknowledged(&self) -> usize {
+        let Some(outbound) = self.outbound_fn() else {
+            return 0;
+        };
+
+        let now = Instant::now();
+        let max_age = Duration::from_secs(self.sync_config.queue_max_age_secs);
+        let mut pending = self.pending_acks.lock().await;
+        let mut expired = Vec::new();
+        pending.retain(|peer, update| {
+            if now.duration_since(update.created) <= max_age {
+                return true;
+            }
+            debug!("Giving up on update {} for {}", update.sequence, peer);
+            expired.push(peer.clone());
+            false
+        });
+
+        let any_expired = !expired.is_empty();
+        if any_expired {
+            let mut stats = self.peer_stats.lock().await;
+            for peer in expired {
+                stats.entry(peer).or_default().updates_failed += 1;
+            }
+            drop(stats);
+            self.persist_stats();
+        }
+
+        // Peers waiting on the same update share one broadcast
+        let mut sent = HashSet::new();
+        let mut due = Vec::new();
+        for (peer, update) in pending.iter_mut() {
+            if update.next_retry > now {
+                continue;
+            }
+            update.schedule_next_retry(now);
+            if sent.insert(update.sequence) {
+                debug!(
+                    "Retrying update {} for {} (attempt {})",
+                    update.sequence, peer, update.attempts
+                );
+                due.push(update.message.clone());
+            }
+        }
+        drop(pending);
+        if any_expired {
+            self.persist_queue().await;
+        }
+
+        let count = due.len();
+        for message in due {
+            outbound(message);
+        }
+        count
+    }
+
+    /// Number of peers with an unacknowledged update
+    pub async fn pending_ack_count(&self) -> usize {
+        self.pending_acks.lock().await.len()
+    }
+
+    pub async fn queue_sizes(&self) -> QueueSizes {
+        QueueSizes {
+            pending_acks: self.pending_ack_count().await,
+            recent_updates: self.recent_updates.lock().await.len(),
+            clipboard_stack: self.clipboard_stack.lock().await.len(),
+            awaiting_replies: self.pending_pings.lock().await.len()
+                + self.pending_collects.lock().await.len()
+                + self.pending_benches.lock().await.len(),
+            offline_peers: self.offline_peers.lock().await.len(),
+        }
+    }
+
+    /// Peers with an encryption session, by node ID
+    pub async fn peer_sessions(&self) -> Vec<PeerSession> {
+        let mut node_ids: Vec<String> = self.crypto_sessions.lock().await.keys().cloned().collect();
+        node_ids.sort();
+        let nodes = self.nodes.read().await;
+        let verifying_keys = self.node_verifying_keys.read().await;
+        node_ids
+            .into_iter()
+            .map(|node_id| PeerSession {
+                public_key: nodes
+                    .get(&node_id)
+                    .map(|node| key_fingerprint(&node.public_key)),
+                verifying_key: verifying_keys.get(&node_id).map(|key| key_fingerprint(key)),
+                node_id,
+            })
+            .collect()
+    }
+
+    /// Make `content` the newest stack item, moving it up if it was already there
+    async fn push_to_stack(&self, content: &str) {
+        let mut stack = self.clipboard_stack.lock().await;
+        stack.retain(|item| item != content);
+        stack.push_front(content.to_string());
+        stack.truncate(self.sync_config.stack_size.max(1));
+        let mut index = self.stack_index.lock().await;
+        index.insert(content);
+        index.truncate(stack.len());
+        drop(index);
+        let (content, keep) = (content.to_string(), stack.len());
+        self.save_stack(move |store| store.push_history(&content, keep))
+            .await;
+    }
+
+    /// Drop all but the `keep` newest stack items, e.g. to free disk space
+    pub async fn trim_clipboard_stack(&self, keep: usize) {
+        let mut stack = self.clipboard_stack.lock().await;
+        stack.truncate(keep);
+        self.stack_index.lock().await.truncate(keep);
+        self.save_stack(move |store| store.truncate_history(keep))
+            .await;
+    }
+
+    /// Whether the stack is kept between runs, see [`SyncManager::with_state_store`]
+    pub fn persists_history(&self) -> bool {
+        self.state_store.is_some() && self.sync_config.persist_history
+    }
+
+    async fn persist_peers(&self) {
+        if self.state_store.is_none() {
+            return;
+        }
+        let peers: Vec<StoredPeer> = {
+            let nodes = self.nodes.read().await;
+            let keys = self.node_verifying_keys.read().await;
+            nodes
+                .values()
+                .filter_map(|node| {
+                    keys.get(&node.id).map(|key| StoredPeer {
+                        node: node.clone(),
+                        verifying_key: *key,
+                    })
+                })
+                .collect()
+        };
+        if let Err(e) = self.persist(StateKey::Peers, &peers).await {
+            warn!("Failed to save known peers: {}", e);
+        }
+    }
+
+    /// Apply a change of the stack to the stored history, if it is kept
+    async fn save_stack(&self, change: impl FnOnce(&StateStore) -> Result<()> + Send + 'static) {
+        let Some(store) = self
+            .state_store
+            .as_ref()
+            .filter(|_| self.persists_history())
+        else {
+            return;
+        };
+        let store = Arc::clone(store);
+        let saved = tokio::task::spawn_blocking(move || change(&store))
+            .await
+            .map_err(|e| crate::PostError::Storage(e.to_string()))
+            .and_then(|saved| saved);
+        if let Err(e) = saved {
+            warn!("Failed to save clipboard history: {}", e);
+        }
+    }
+
+    /// Recent clipboard items from this device and its peers, newest first
+    ///
+    /// Built only from the updates this node sent or applied; stacks aren't exchanged,
+    /// so copies made while a peer was unreachable are missing from its stack.
+    pub async fn get_clipboard_stack(&self) -> Vec<String> {
+        self.clipboard_stack.lock().await.iter().cloned().collect()
+    }
+
+    /// Stack items containing every word of `query`, newest first, see [`SearchIndex::search`]
+    pub async fn search_clipboard_stack(&self, query: &str, limit: usize) -> Vec<SearchMatch> {
+        self.stack_index.lock().await.search(query, limit)
+    }
+
+    /// Pin `content` as `name` on this device and every peer
+    pub async fn pin(&self, name: &str, content: String) -> Result<()> {
+        pins::validate_name(name)?;
+        self.change_pin(name, Some(content)).await
+    }
+
+    /// Remove the pin `name` everywhere; `false` if nothing was pinned under it
+    pub async fn unpin(&self, name: &str) -> Result<bool> {
+        if self.pins.lock().await.get(name).is_none() {
+            return Ok(false);
+        }
+        self.change_pin(name, None).await?;
+        Ok(true)
+    }
+
+    pub async fn get_pin(&self, name: &str) -> Option<String> {
+        self.pins.lock().await.get(name).map(str::to_string)
+    }
+
+    /// Pinned items by name
+    pub async fn get_pins(&self) -> BTreeMap<String, String> {
+        self.pins.lock().await.pinned()
+    }
+
+    async fn change_pin(&self, name: &str, content: Option<String>) -> Result<()> {
+        let mut pins = self.pins.lock().await;
+        let entry = pins.set(name, content);
+        self.save_pins(&pins).await;
+        drop(pins);
+
+        self.send_pins(vec![entry]).await
     }
 
-    async fn verify_message_signature(
-        &self,
-        message: &PostMessage,
-        source_node: &str,
-    ) -> Result<()> {
-        // Create a message copy without the signature for verification
-        let mut message_for_verification = message.clone();
-        message_for_verification.signature = Vec::new();
+    async fn handle_pins(&self, data: &PinsData) {
+        if self.is_dry_run() {
+            info!(
+                "Dry run: would merge {} pinned items from {}",
+                data.entries.len(),
+                data.source_node
+            );
+            return;
+        }
+        let mut pins = self.pins.lock().await;
+        if pins.merge(data.entries.clone()) {
+            debug!("Merged pinned items from {}", data.source_node);
+            self.save_pins(&pins).await;
+        }
+    }
 
-        let message_bytes = serde_json::to_vec(&message_for_verification).map_err(|e| {
-            crate::PostError::Serialization(format!(
-                "Failed to serialize message for verification: {}",
-                e
-            ))
-        })?;
+    async fn save_pins(&self, pins: &PinSet) {
+        if let Err(e) = self.persist(StateKey::Pins, &pins.entries()).await {
+            warn!("Failed to save pinned items: {}", e);
+        }
+    }
 
-        // Get the verifying key for this node
-        let node_keys = self.node_verifying_keys.lock().await;
-        let verifying_key = node_keys.get(source_node).ok_or_else(|| {
-            crate::PostError::Crypto(format!("No verifying key found for node: {}", source_node))
-        })?;
+    /// Send every pin, removals included, so a newly discovered peer catches up
+    async fn share_pins(&self) {
+        let entries = self.pins.lock().await.entries();
+        if entries.is_empty() {
+            return;
+        }
+        if let Err(e) = self.send_pins(entries).await {
+            error!("Failed to share pinned items: {}", e);
+        }
+    }
 
-        // Verify the signature
-        let signature_valid = verify_signature(verifying_key, &message_bytes, &message.signature)?;
-        if !signature_valid {
-            return Err(crate::PostError::Crypto(format!(
-                "Invalid signature on message from node: {}",
-                source_node
+    /// Send `node`, a peer ID or name, a signed ping and wait up to `timeout` for its answer
+    pub async fn ping(&self, node: &str, timeout: Duration) -> Result<PingReply> {
+        let outbound = self
+            .outbound_fn()
+            .ok_or_else(|| crate::PostError::Other("Sync loop has not been started".to_string()))?;
+        let target = self
+            .nodes
+            .read()
+            .await
+            .values()
+            .find(|info| info.id == node || info.name == node)
+            .cloned()
+            .ok_or_else(|| crate::PostError::UnknownPeer(node.to_string()))?;
+        if !compat::peer_supports(&target.capabilities, Capability::Ping) {
+            return Err(crate::PostError::Other(format!(
+                "{} runs a version of Post that can't answer pings",
+                target.name
             )));
         }
 
-        Ok(())
+        let nonce = rand::random::<u64>();
+        let (reply, answered) = oneshot::channel();
+        self.pending_pings.lock().await.insert(nonce, reply);
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::Ping,
+            data: MessageData::Ping(PingData {
+                source_node: self.get_node_id().await,
+                target_node: target.id,
+                nonce,
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            }),
+            signature: vec![],
+        };
+        Self::sign_post_message(&mut message, &self.signing_keypair)?;
+
+        let started = Instant::now();
+        outbound(message);
+        let answer = tokio::time::timeout(timeout, answered).await;
+        self.pending_pings.lock().await.remove(&nonce);
+        match answer {
+            Ok(Ok(verified)) => Ok(PingReply {
+                round_trip: started.elapsed(),
+                verified,
+            }),
+            _ => Err(crate::PostError::Timeout(format!(
+                "no answer from {} within {:?}; peers don't answer pings they can't verify, \
+                 so it may have missed this node's discovery or hold an older key for it",
+                target.name, timeout
+            ))),
+        }
     }
 
-    pub async fn handle_message(&self, message: PostMessage) -> Result<()> {
-        match &message.data {
-            MessageData::ClipboardUpdate(data) => {
-                // Verify message signature
-                self.verify_message_signature(&message, &data.source_node)
-                    .await?;
-                self.handle_clipboard_update(data.clone()).await?;
-            }
-            MessageData::Heartbeat(data) => {
-                // Verify message signature
-                self.verify_message_signature(&message, &data.source_node)
-                    .await?;
-                self.handle_heartbeat(&data.source_node).await?;
-            }
-            MessageData::NodeDiscovery(data) => {
-                // Create a message copy without the signature for verification
-                let mut message_for_verification = message.clone();
-                message_for_verification.signature = Vec::new();
+    /// Ask `node`, a peer ID or name, for its current clipboard, waiting up to `timeout`
+    ///
+    /// Peers only share content they synced or received, never what their filters kept
+    /// back, and nothing while paused.
+    pub async fn collect(&self, node: &str, timeout: Duration) -> Result<String> {
+        let outbound = self
+            .outbound_fn()
+            .ok_or_else(|| crate::PostError::Other("Sync loop has not been started".to_string()))?;
+        let target = self
+            .nodes
+            .read()
+            .await
+            .values()
+            .find(|info| info.id == node || info.name == node)
+            .cloned()
+            .ok_or_else(|| crate::PostError::UnknownPeer(node.to_string()))?;
+        if !compat::peer_supports(&target.capabilities, Capability::Collect) {
+            return Err(crate::PostError::Other(format!(
+                "{} runs a version of Post that can't share its clipboard on request",
+                target.name
+            )));
+        }
 
-                let message_bytes = serde_json::to_vec(&message_for_verification).map_err(|e| {
-                    crate::PostError::Serialization(format!(
-                        "Failed to serialize message for verification: {}",
-                        e
-                    ))
-                })?;
+        let nonce = rand::random::<u64>();
+        let (reply, answered) = oneshot::channel();
+        self.pending_collects.lock().await.insert(nonce, reply);
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::CollectRequest,
+            data: MessageData::CollectRequest(CollectRequestData {
+                source_node: self.get_node_id().await,
+                target_node: target.id,
+                nonce,
+                timestamp: unix_now(),
+            }),
+            signature: vec![],
+        };
+        Self::sign_post_message(&mut message, &self.signing_keypair)?;
 
-                // Verify the signature
-                let signature_valid =
-                    verify_signature(&data.signing_public_key, &message_bytes, &message.signature)?;
-                if !signature_valid {
-                    return Err(crate::PostError::Crypto(
-                        "Invalid Ed25519 signature on node discovery message".to_string(),
-                    ));
-                }
+        outbound(message);
+        let answer = tokio::time::timeout(timeout, answered).await;
+        self.pending_collects.lock().await.remove(&nonce);
+        match answer {
+            Ok(Ok(Ok(content))) => Ok(content),
+            Ok(Ok(Err(reason))) => Err(crate::PostError::Other(format!(
+                "{} didn't share its clipboard: {}",
+                target.name, reason
+            ))),
+            _ => Err(crate::PostError::Timeout(format!(
+                "no answer from {} within {:?}",
+                target.name, timeout
+            ))),
+        }
+    }
 
-                // Validate that the key is not all zeros (common security mistake)
-                if data.public_key.iter().all(|&b| b == 0) {
-                    return Err(crate::PostError::Crypto(
-                        "Invalid X25519 public key: all zeros".to_string(),
-                    ));
+    /// Send `node`, a peer ID or name, `rounds` synthetic payloads of each of `sizes`
+    /// bytes, one at a time, timing how long each takes to be verified and answered
+    ///
+    /// A round left unanswered for `timeout` counts as lost, as does every round not yet
+    /// sent once `max_duration` has passed. Payloads go to that peer only and never reach
+    /// its clipboard.
+    pub async fn bench(
+        &self,
+        node: &str,
+        sizes: &[usize],
+        rounds: usize,
+        timeout: Duration,
+        max_duration: Duration,
+    ) -> Result<Vec<BenchResult>> {
+        let outbound = self
+            .outbound_fn()
+            .ok_or_else(|| crate::PostError::Other("Sync loop has not been started".to_string()))?;
+        let target = self
+            .nodes
+            .read()
+            .await
+            .values()
+            .find(|info| info.id == node || info.name == node)
+            .cloned()
+            .ok_or_else(|| crate::PostError::UnknownPeer(node.to_string()))?;
+        if !compat::peer_supports(&target.capabilities, Capability::Bench) {
+            return Err(crate::PostError::Other(format!(
+                "{} runs a version of Post that can't answer benchmarks",
+                target.name
+            )));
+        }
+        if let Some(size) = sizes.iter().find(|size| **size > bench::MAX_PAYLOAD_SIZE) {
+            return Err(crate::PostError::Other(format!(
+                "Payloads of {} bytes are over the limit of {} bytes",
+                size,
+                bench::MAX_PAYLOAD_SIZE
+            )));
+        }
+
+        let source_node = self.get_node_id().await;
+        let deadline = Instant::now() + max_duration;
+        let mut results = Vec::with_capacity(sizes.len());
+        for &size in sizes {
+            let mut round_trips = Vec::with_capacity(rounds);
+            let mut lost = 0;
+            for _ in 0..rounds {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    lost += 1;
+                    continue;
                 }
+                let payload = bench::payload(size);
+                let nonce = rand::random::<u64>();
+                let (reply, answered) = oneshot::channel();
+                self.pending_benches.lock().await.insert(nonce, reply);
 
-                // Store the binding between source_node and verifying key
-                let mut node_keys = self.node_verifying_keys.lock().await;
-                if let Some(existing_key) = node_keys.get(&data.source_node) {
-                    // Verify the node is still using the same verifying key
-                    if existing_key != &data.signing_public_key {
-                        return Err(crate::PostError::Crypto(format!(
-                            "Node {} attempted to change verifying key",
-                            data.source_node
-                        )));
+                // Signing is part of what a clipboard update costs, so it is timed too
+                let started = Instant::now();
+                let mut message = PostMessage {
+                    version: 1,
+                    message_type: MessageType::Bench,
+                    data: MessageData::Bench(BenchData {
+                        source_node: source_node.clone(),
+                        target_node: target.id.clone(),
+                        nonce,
+                        payload,
+                        timestamp: unix_now(),
+                    }),
+                    signature: vec![],
+                };
+                Self::sign_post_message(&mut message, &self.signing_keypair)?;
+                outbound(message);
+                let answer = tokio::time::timeout(timeout.min(remaining), answered).await;
+                self.pending_benches.lock().await.remove(&nonce);
+                match answer {
+                    Ok(Ok(received)) if received == size as u64 => {
+                        round_trips.push(started.elapsed())
                     }
-                } else {
-                    // Store the new binding
-                    node_keys.insert(data.source_node.clone(), data.signing_public_key);
+                    _ => lost += 1,
                 }
-                drop(node_keys);
-
-                // Only now proceed with session derivation after successful verification
-                self.handle_node_discovery(&data.source_node, &data.public_key)
-                    .await?;
             }
+            debug!(
+                "Bench to {}: {} of {} rounds of {} bytes answered",
+                target.name,
+                round_trips.len(),
+                rounds,
+                size
+            );
+            results.push(BenchResult::new(size, round_trips, lost));
         }
+        Ok(results)
+    }
+
+    async fn send_bench_reply(&self, request: &BenchData) -> Result<()> {
+        let Some(outbound) = self.outbound_fn() else {
+            return Ok(());
+        };
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::BenchReply,
+            data: MessageData::BenchReply(BenchReplyData {
+                source_node: self.node_id.lock().await.clone(),
+                origin_node: request.source_node.clone(),
+                nonce: request.nonce,
+                received: request.payload.len() as u64,
+                timestamp: unix_now(),
+            }),
+            signature: vec![],
+        };
+        Self::sign_post_message(&mut message, &self.signing_keypair)?;
+        outbound(message);
         Ok(())
     }
 
-    async fn handle_clipboard_update(&self, data: ClipboardData) -> Result<()> {
-        let current_node_id = self.node_id.lock().await.clone();
-        if data.source_node == current_node_id {
-            debug!("Ignoring own clipboard update");
+    /// Send the requester alone our clipboard, if collecting is allowed and it holds what
+    /// was last synced or applied
+    async fn answer_collect(&self, request: &CollectRequestData) -> Result<()> {
+        let Some(outbound) = self.outbound_fn() else {
             return Ok(());
+        };
+        let content = if !self.sync_config.allow_collect {
+            Err("it doesn't allow collecting (sync.allow_collect)".to_string())
+        } else if self.is_paused() {
+            Err("sync is paused".to_string())
+        } else {
+            match self.clipboard.get_contents().await {
+                Ok(content)
+                    if calculate_hash(&content)
+                        == self.last_clipboard_hash.load(Ordering::SeqCst) =>
+                {
+                    Ok(content)
+                }
+                Ok(_) => Err("its clipboard holds content that wasn't synced".to_string()),
+                Err(e) => Err(e.to_string()),
+            }
+        };
+        match &content {
+            Ok(_) => info!("{} collected the clipboard", request.source_node),
+            Err(reason) => {
+                info!("{} was refused the clipboard", request.source_node);
+                self.log_refused(
+                    &request.source_node,
+                    format!("collect request refused: {}", reason),
+                );
+            }
         }
 
-        let content_hash = calculate_hash(&data.content);
-        let mut last_hash = self.last_clipboard_hash.lock().await;
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::CollectResponse,
+            data: MessageData::CollectResponse(CollectResponseData {
+                source_node: self.node_id.lock().await.clone(),
+                origin_node: request.source_node.clone(),
+                nonce: request.nonce,
+                content,
+                timestamp: unix_now(),
+            }),
+            signature: vec![],
+        };
+        Self::sign_post_message(&mut message, &self.signing_keypair)?;
+        outbound(message);
+        Ok(())
+    }
 
-        if content_hash == *last_hash {
-            debug!("Duplicate clipboard content, ignoring");
+    /// Answer a verified ping; the pong goes to its sender alone
+    async fn send_pong(&self, ping: &PingData) -> Result<()> {
+        let Some(outbound) = self.outbound_fn() else {
+            return Ok(());
+        };
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::Pong,
+            data: MessageData::Pong(PongData {
+                source_node: self.node_id.lock().await.clone(),
+                origin_node: ping.source_node.clone(),
+                nonce: ping.nonce,
+                verified: true,
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            }),
+            signature: vec![],
+        };
+        Self::sign_post_message(&mut message, &self.signing_keypair)?;
+        outbound(message);
+        Ok(())
+    }
+
+    /// Broadcast pin changes; before the sync loop starts they wait for the next discovery
+    async fn send_pins(&self, entries: Vec<PinEntry>) -> Result<()> {
+        let Some(outbound) = self.outbound_fn() else {
+            return Ok(());
+        };
+        if self.is_dry_run() {
+            info!("Dry run: would send {} pinned items", entries.len());
             return Ok(());
         }
 
-        info!(
-            "Received clipboard update from {}: {} chars",
-            data.source_node,
-            data.content.len()
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::Pins,
+            data: MessageData::Pins(PinsData {
+                source_node: self.node_id.lock().await.clone(),
+                entries,
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            }),
+            signature: vec![],
+        };
+        Self::sign_post_message(&mut message, &self.signing_keypair)?;
+        outbound(message);
+        Ok(())
+    }
+
+    async fn record_sent_to(&self, peers: &[String], bytes: usize) {
+        let mut stats = self.peer_stats.lock().await;
+        for peer in peers {
+            let peer = stats.entry(peer.clone()).or_default();
+            peer.updates_sent += 1;
+            peer.bytes_sent += bytes as u64;
+        }
+        drop(stats);
+        self.persist_stats();
+    }
+
+    async fn record_update_from(&self, node_id: &str, bytes: usize) {
+        let mut stats = self.peer_stats.lock().await;
+        let peer = stats.entry(node_id.to_string()).or_default();
+        peer.updates_received += 1;
+        peer.bytes_received += bytes as u64;
+        peer.last_update = Some(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
         );
+        drop(stats);
+        self.persist_stats();
+    }
 
-        match self.clipboard.set_contents(&data.content).await {
-            Ok(()) => {
-                info!("Successfully set clipboard contents on Linux");
-                *last_hash = content_hash;
-            }
-            Err(e) => {
-                error!("Failed to set clipboard contents on Linux: {}", e);
-                return Err(e);
-            }
+    /// Sync activity for each peer that has exchanged updates with us
+    pub async fn get_peer_stats(&self) -> HashMap<String, PeerStats> {
+        let mut stats = self.peer_stats.lock().await.clone();
+        for peer in self.pending_acks.lock().await.keys() {
+            stats.entry(peer.clone()).or_default().awaiting_ack = true;
         }
+        stats
+    }
 
-        Ok(())
+    fn outbound_fn(&self) -> Option<OutboundFn> {
+        self.outbound
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
     }
 
     async fn handle_heartbeat(&self, node_id: &str) -> Result<()> {
@@ -298,38 +2223,197 @@ impl SyncManager {
         Ok(())
     }
 
-    async fn handle_node_discovery(
-        &self,
-        node_id: &str,
-        remote_public_key: &[u8; 32],
-    ) -> Result<()> {
+    /// Compare a peer's heartbeat timestamp with our clock, warning when the difference
+    /// first exceeds the configured limit
+    async fn record_clock_skew(&self, node_id: &str, timestamp: u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        // Peers' timestamps are signed but otherwise arbitrary, so this mustn't wrap
+        let skew = (i128::from(timestamp) - i128::from(now))
+            .clamp(i128::from(i64::MIN), i128::from(i64::MAX)) as i64;
+        let previous = self
+            .clock_skews
+            .lock()
+            .await
+            .insert(node_id.to_string(), skew);
+
+        let excessive = |skew: i64| self.sync_config.is_clock_skew_excessive(skew);
+        if !excessive(skew) || previous.is_some_and(excessive) {
+            return;
+        }
+        let node_name = self
+            .nodes
+            .read()
+            .await
+            .get(node_id)
+            .map(|node| node.name.clone())
+            .unwrap_or_else(|| node_id.to_string());
+        warn!(
+            "Clock on {} is {}; clipboard timestamps from it will be misleading",
+            node_name,
+            describe_clock_skew(skew)
+        );
+        self.emit(SyncEvent::ClockSkew {
+            node_id: node_id.to_string(),
+            node_name,
+            skew_secs: skew,
+        });
+    }
+
+    /// Known peers whose clock differed too much from ours at their latest heartbeat
+    pub async fn clock_skews(&self) -> Vec<ClockSkew> {
+        let nodes = self.nodes.read().await;
+        let mut skews: Vec<ClockSkew> = self
+            .clock_skews
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, skew)| self.sync_config.is_clock_skew_excessive(**skew))
+            .filter_map(|(id, skew)| {
+                nodes.get(id).map(|node| ClockSkew {
+                    node_id: id.clone(),
+                    node_name: node.name.clone(),
+                    skew_secs: *skew,
+                })
+            })
+            .collect();
+        skews.sort_by(|a, b| a.node_name.cmp(&b.node_name));
+        skews
+    }
+
+    async fn handle_node_discovery(&self, data: &NodeDiscoveryData) -> Result<()> {
+        let node_id = data.source_node.as_str();
+        let name = data
+            .node_name
+            .as_deref()
+            .and_then(sanitize_node_name)
+            .unwrap_or_else(|| node_id.to_string());
+
         let mut nodes = self.nodes.write().await;
-        if !nodes.contains_key(node_id) {
+        let confirmed = nodes
+            .get_mut(node_id)
+            .filter(|node| node.trust == PeerTrust::Confirmed);
+        if let Some(node) = confirmed {
+            let mut changed = false;
+            if node.name != name {
+                info!("Node {} is now known as {}", node_id, name);
+                node.name = name;
+                changed = true;
+            }
+            if node.version != data.version {
+                node.version = data.version.clone();
+                warn_if_incompatible(node);
+                changed = true;
+            }
+            if node.capabilities != data.capabilities {
+                node.capabilities = data.capabilities.clone();
+                changed = true;
+            }
+            if (&node.address, node.port) != (&data.address, data.port) {
+                info!(
+                    "Node {} now advertises endpoint {:?}:{:?}",
+                    node_id, data.address, data.port
+                );
+                node.address = data.address.clone();
+                node.port = data.port;
+                changed = true;
+            }
+            drop(nodes);
+            if changed {
+                self.persist_peers().await;
+            }
+        } else {
             let node_info = NodeInfo {
                 id: node_id.to_string(),
-                name: node_id.to_string(), // TODO: Get actual node name
+                name,
                 last_seen: SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap_or_default()
                     .as_secs(),
-                public_key: remote_public_key.to_vec(),
+                public_key: data.public_key.to_vec(),
+                version: data.version.clone(),
+                capabilities: data.capabilities.clone(),
+                address: data.address.clone(),
+                port: data.port,
+                trust: PeerTrust::Confirmed,
             };
-            nodes.insert(node_id.to_string(), node_info.clone());
+            // A remembered peer announcing itself again is discovered anew, with its
+            // current keys
+            let remembered = nodes
+                .insert(node_id.to_string(), node_info.clone())
+                .is_some();
             drop(nodes);
+            warn_if_incompatible(&node_info);
 
             // Create crypto session for the new node
             self.create_crypto_session_for_node(node_id, &node_info.public_key)
                 .await?;
 
-            info!("Discovered new node: {}", node_id);
+            if remembered {
+                info!("Remembered node is back: {} ({})", node_info.name, node_id);
+            } else {
+                info!("Discovered new node: {} ({})", node_info.name, node_id);
+            }
+            self.persist_peers().await;
+            self.offline_peers.lock().await.remove(node_id);
+            self.emit(SyncEvent::PeerDiscovered {
+                id: node_id.to_string(),
+                name: node_info.name,
+            });
+            self.share_pins().await;
         }
+
+        self.replay_missed_updates(node_id).await;
         Ok(())
     }
 
+    /// Send a peer that has announced itself the updates it has not acknowledged
+    async fn replay_missed_updates(&self, node_id: &str) {
+        // A peer seen for the first time only needs updates from now on
+        let current = self.sequence_counter.load(Ordering::SeqCst);
+        let last_acked = *self
+            .acked_sequences
+            .lock()
+            .await
+            .entry(node_id.to_string())
+            .or_insert(current);
+
+        let recent = self.recent_updates.lock().await;
+        let mut missed: Vec<PostMessage> = recent
+            .iter()
+            .filter(|(sequence, _)| *sequence > last_acked)
+            .map(|(_, message)| message.clone())
+            .collect();
+        drop(recent);
+
+        if self.sync_config.offline_replay == ReplayMode::Latest && missed.len() > 1 {
+            missed.drain(..missed.len() - 1);
+        }
+        if missed.is_empty() {
+            return;
+        }
+        let Some(outbound) = self.outbound_fn() else {
+            return;
+        };
+
+        // The replay stands in for this peer's next retry
+        if let Some(update) = self.pending_acks.lock().await.get_mut(node_id) {
+            update.schedule_next_retry(Instant::now());
+        }
+
+        info!("Replaying {} missed update(s) to {}", missed.len(), node_id);
+        for message in missed {
+            outbound(message);
+        }
+    }
+
     pub async fn get_nodes(&self) -> NodeMap {
         self.nodes.read().await.clone()
     }
 
+    /// Drop peers not heard from in `max_age_seconds`, recording them as offline
     pub async fn cleanup_stale_nodes(&self, max_age_seconds: u64) -> Result<()> {
         let current_time = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -337,23 +2421,61 @@ impl SyncManager {
             .as_secs();
 
         let mut nodes = self.nodes.write().await;
-        let initial_count = nodes.len();
-
-        nodes.retain(|id, node| {
-            let is_stale = current_time.saturating_sub(node.last_seen) > max_age_seconds;
-            if is_stale {
-                debug!("Removing stale node: {}", id);
-            }
-            !is_stale
-        });
+        let stale: Vec<NodeInfo> = nodes
+            .values()
+            .filter(|node| current_time.saturating_sub(node.last_seen) > max_age_seconds)
+            .cloned()
+            .collect();
+        for node in &stale {
+            nodes.remove(&node.id);
+        }
+        drop(nodes);
+        if stale.is_empty() {
+            return Ok(());
+        }
 
-        if nodes.len() != initial_count {
-            info!("Cleaned up {} stale nodes", initial_count - nodes.len());
+        let mut offline = self.offline_peers.lock().await;
+        for node in stale {
+            info!(
+                "Node {} ({}) went offline: not heard from for {} seconds",
+                node.name,
+                node.id,
+                current_time.saturating_sub(node.last_seen)
+            );
+            offline.insert(
+                node.id.clone(),
+                OfflinePeer {
+                    node_id: node.id.clone(),
+                    node_name: node.name.clone(),
+                    last_seen: node.last_seen,
+                    evicted_at: current_time,
+                },
+            );
+            self.emit(SyncEvent::PeerOffline {
+                id: node.id,
+                name: node.name,
+                last_seen: node.last_seen,
+            });
         }
+        drop(offline);
+        self.persist_peers().await;
 
         Ok(())
     }
 
+    /// Peers dropped for going quiet that have not announced themselves since, most
+    /// recently dropped first
+    pub async fn offline_peers(&self) -> Vec<OfflinePeer> {
+        let mut peers: Vec<OfflinePeer> =
+            self.offline_peers.lock().await.values().cloned().collect();
+        peers.sort_by(|a, b| {
+            b.evicted_at
+                .cmp(&a.evicted_at)
+                .then(a.node_id.cmp(&b.node_id))
+        });
+        peers
+    }
+
     async fn create_crypto_session_for_node(&self, node_id: &str, public_key: &[u8]) -> Result<()> {
         // Validate public key by parsing into x25519_dalek::PublicKey
         let public_key_array: [u8; 32] = public_key
@@ -387,6 +2509,80 @@ impl SyncManager {
     }
 
     pub async fn create_node_discovery_message(&self) -> Result<PostMessage> {
+        self.discovery_message(false).await
+    }
+
+    /// Discovery message asking every peer to announce itself again
+    pub async fn create_rediscovery_message(&self) -> Result<PostMessage> {
+        self.discovery_message(true).await
+    }
+
+    /// Forget all peers along with their keys and sessions so they are re-learned
+    ///
+    /// Returns the number of peers forgotten. Used when a peer has rotated its keys.
+    pub async fn forget_peers(&self) -> usize {
+        let forgotten = {
+            let mut nodes = self.nodes.write().await;
+            let count = nodes.len();
+            nodes.clear();
+            count
+        };
+        self.node_verifying_keys.write().await.clear();
+        self.crypto_sessions.lock().await.clear();
+        self.persist_peers().await;
+        self.applied_sequences.lock().await.clear();
+        self.persist_sequences().await;
+
+        info!("Forgot {} peers for rediscovery", forgotten);
+        forgotten
+    }
+
+    /// Drop a peer that has gone by another ID, along with its crypto session
+    async fn forget_node(&self, node_id: &str) {
+        self.nodes.write().await.remove(node_id);
+        self.crypto_sessions.lock().await.remove(node_id);
+        self.persist_peers().await;
+    }
+
+    async fn reply_to_discovery(&self, node_id: &str) {
+        let Some(outbound) = self.outbound_fn() else {
+            return;
+        };
+        match self.create_node_discovery_message().await {
+            Ok(message) => {
+                debug!("Answering rediscovery request from {}", node_id);
+                outbound(message);
+            }
+            Err(e) => error!("Failed to create discovery reply: {}", e),
+        }
+    }
+
+    /// Signed heartbeat telling peers this node is alive and what time it thinks it is
+    pub async fn create_heartbeat_message(&self) -> Result<PostMessage> {
+        self.heartbeat_at(unix_now()).await
+    }
+
+    /// Heartbeat as sent by a node whose clock reads `timestamp`
+    #[cfg(any(test, feature = "test-util"))]
+    pub async fn create_heartbeat_message_at(&self, timestamp: u64) -> Result<PostMessage> {
+        self.heartbeat_at(timestamp).await
+    }
+
+    async fn heartbeat_at(&self, timestamp: u64) -> Result<PostMessage> {
+        let mut message = PostMessage {
+            version: 1,
+            message_type: MessageType::Heartbeat,
+            data: MessageData::Heartbeat(HeartbeatData {
+                source_node: self.node_id.lock().await.clone(),
+                timestamp,
+            }),
+            signature: vec![],
+        };
+        Self::sign_post_message(&mut message, &self.signing_keypair)?;
+        Ok(message)
+    }
+
+    async fn discovery_message(&self, wants_reply: bool) -> Result<PostMessage> {
         let timestamp = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
@@ -405,6 +2601,13 @@ impl SyncManager {
             .map_err(|_| {
                 crate::PostError::Crypto("Signing public key must be 32 bytes".to_string())
             })?,
+            wire_formats: WireFormat::advertised(),
+            node_name: self.node_name.lock().await.clone(),
+            wants_reply,
+            version: Some(compat::VERSION.to_string()),
+            capabilities: Capability::advertised(),
+            address: self.advertised_address.map(|ip| ip.to_string()),
+            port: self.advertised_port,
         };
 
         let mut message = PostMessage {
@@ -421,7 +2624,122 @@ impl SyncManager {
     }
 }
 
-fn calculate_hash(content: &str) -> u64 {
+/// A clock offset as "N seconds ahead" or "N seconds behind"
+pub fn describe_clock_skew(skew_secs: i64) -> String {
+    let direction = if skew_secs < 0 { "behind" } else { "ahead" };
+    format!("{} seconds {}", skew_secs.unsigned_abs(), direction)
+}
+
+/// Longest friendly name accepted from a peer
+const MAX_NODE_NAME_LEN: usize = 64;
+
+fn warn_if_incompatible(node: &NodeInfo) {
+    if !node.is_compatible() {
+        warn!(
+            "Node {} ({}) runs Post {}, which may not interoperate with this version ({})",
+            node.name,
+            node.id,
+            node.version.as_deref().unwrap_or("unknown"),
+            compat::VERSION
+        );
+    }
+}
+
+/// Strip control characters and bound the length of a peer-supplied name
+fn sanitize_node_name(name: &str) -> Option<String> {
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NODE_NAME_LEN)
+        .collect();
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Sequence numbers as kept in the state store
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoredSequences {
+    /// Latest sequence this node gave an update
+    last_sent: u64,
+    /// Latest sequence applied from each peer
+    applied: HashMap<String, u64>,
+}
+
+/// Outbound updates as kept in the state store
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoredQueue {
+    /// Updates awaiting acknowledgement or kept for replay
+    updates: VecDeque<StoredUpdate>,
+    /// The update each peer has yet to acknowledge
+    pending: HashMap<String, StoredPending>,
+    /// Latest sequence each peer acknowledged, which decides what it is replayed
+    acked: HashMap<String, u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredUpdate {
+    sequence: u64,
+    timestamp: u64,
+    content: Arc<str>,
+    #[serde(default)]
+    tailnet: Option<String>,
+    #[serde(default = "crate::text_plain")]
+    content_type: String,
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredPending {
+    sequence: u64,
+    attempts: u32,
+    /// Unix time the update was first sent to the peer
+    created: u64,
+}
+
+impl StoredQueue {
+    /// Drop updates past the configured age, then the oldest until the rest fit the size
+    /// limit, along with anything waiting on them
+    fn bound(&mut self, config: &SyncConfig, now: u64) {
+        self.updates
+            .make_contiguous()
+            .sort_by_key(|update| update.sequence);
+        self.updates
+            .retain(|update| now.saturating_sub(update.timestamp) <= config.queue_max_age_secs);
+        let mut bytes: usize = self.updates.iter().map(|update| update.content.len()).sum();
+        let mut oldest = 0;
+        for update in &self.updates {
+            if bytes <= config.queue_max_bytes {
+                break;
+            }
+            bytes -= update.content.len();
+            oldest += 1;
+        }
+        self.updates.drain(..oldest);
+
+        let kept: HashSet<u64> = self.updates.iter().map(|update| update.sequence).collect();
+        self.pending
+            .retain(|_, pending| kept.contains(&pending.sequence));
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A known peer as kept in the state store
+#[derive(Serialize, Deserialize)]
+struct StoredPeer {
+    #[serde(flatten)]
+    node: NodeInfo,
+    verifying_key: [u8; 32],
+}
+
+/// Hash identifying clipboard content, to tell content already synced from new copies
+pub fn calculate_hash(content: &str) -> u64 {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
 
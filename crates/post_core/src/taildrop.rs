@@ -0,0 +1,189 @@
+use crate::tailscale_cli;
+use crate::{config, PostError, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tempfile::TempDir;
+use tracing::debug;
+
+/// Prefix of files carrying clipboard content, so received files can be told apart
+const PAYLOAD_PREFIX: &str = "post-clipboard-";
+
+/// Name of the file carrying clipboard update `sequence`
+pub fn payload_file_name(sequence: u64) -> String {
+    format!("{}{}.txt", PAYLOAD_PREFIX, sequence)
+}
+
+/// Whether `name` is a payload file name, with no path components
+pub fn is_payload_file_name(name: &str) -> bool {
+    name.starts_with(PAYLOAD_PREFIX)
+        && Path::new(name).file_name().and_then(|n| n.to_str()) == Some(name)
+}
+
+/// Prefix of the directories payloads are staged in
+const STAGING_PREFIX: &str = "taildrop-";
+
+/// This run's staging directory, made on first use
+static STAGING_DIR: Mutex<Option<TempDir>> = Mutex::new(None);
+
+/// Directory payloads wait in until Taildrop has sent them
+///
+/// Made afresh for each run in the profile's data directory, readable by the owner
+/// only, after clearing whatever earlier runs left behind.
+fn staging_dir() -> Result<PathBuf> {
+    let mut staging = STAGING_DIR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(dir) = staging.as_ref() {
+        return Ok(dir.path().to_path_buf());
+    }
+
+    let parent = dirs::data_dir()
+        .ok_or_else(|| PostError::Config("Could not find data directory".to_string()))?
+        .join(config::dir_name());
+    std::fs::create_dir_all(&parent)?;
+    remove_stale_staging_dirs(&parent);
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(STAGING_PREFIX);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o700));
+    }
+    let dir = builder.tempdir_in(&parent)?;
+    let path = dir.path().to_path_buf();
+    *staging = Some(dir);
+    Ok(path)
+}
+
+/// Remove staging directories left by runs that exited before sending their payloads
+fn remove_stale_staging_dirs(parent: &Path) {
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let is_staging_dir = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(STAGING_PREFIX)
+            && entry.file_type().is_ok_and(|kind| kind.is_dir());
+        if is_staging_dir {
+            if let Err(e) = std::fs::remove_dir_all(entry.path()) {
+                debug!(
+                    "Failed to remove stale Taildrop staging directory {}: {}",
+                    entry.path().display(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Where a payload waits until Taildrop has sent it
+pub fn staging_path(file_name: &str) -> Result<PathBuf> {
+    Ok(staging_dir()?.join(file_name))
+}
+
+/// Write `content` to the staging area so it can be sent with Taildrop
+pub async fn stage_payload(file_name: &str, content: &str) -> Result<PathBuf> {
+    let path = staging_path(file_name)?;
+    tokio::fs::write(&path, content).await?;
+    Ok(path)
+}
+
+/// Send `path` to a peer, given by Tailscale IP or name, with `tailscale file cp`
+pub async fn send_file(path: &Path, target: &str) -> Result<()> {
+    debug!("Sending {} to {} with Taildrop", path.display(), target);
+
+    let output = tailscale_cli::command()
+        .arg("file")
+        .arg("cp")
+        .arg(path)
+        .arg(format!("{}:", target))
+        .output()
+        .await
+        .map_err(|e| PostError::Tailscale(format!("Failed to run tailscale file cp: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(PostError::Tailscale(format!(
+            "Taildrop to {} failed: {}",
+            target,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Default directory for received payloads
+pub fn default_receive_dir() -> PathBuf {
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Collect a received payload into `dir` and return where it landed
+///
+/// On Linux files wait in the Taildrop inbox until fetched; the macOS and Windows
+/// clients save them to Downloads on their own.
+pub async fn receive_file(file_name: &str, dir: &Path) -> Result<PathBuf> {
+    if !is_payload_file_name(file_name) {
+        return Err(PostError::Tailscale(format!(
+            "Refusing unexpected Taildrop file name: {}",
+            file_name
+        )));
+    }
+
+    if cfg!(target_os = "linux") {
+        tokio::fs::create_dir_all(dir).await?;
+        let output = tailscale_cli::command()
+            .arg("file")
+            .arg("get")
+            // Payload names are unique per sender, and a renamed file couldn't be found
+            .arg("--conflict=overwrite")
+            .arg(dir)
+            .output()
+            .await
+            .map_err(|e| {
+                PostError::Tailscale(format!("Failed to run tailscale file get: {}", e))
+            })?;
+
+        if !output.status.success() {
+            return Err(PostError::Tailscale(format!(
+                "Failed to fetch Taildrop files: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(dir.join(file_name))
+    } else {
+        Ok(default_receive_dir().join(file_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_file_names() {
+        let name = payload_file_name(42);
+        assert_eq!(name, "post-clipboard-42.txt");
+        assert!(is_payload_file_name(&name));
+        assert!(!is_payload_file_name("notes.txt"));
+        assert!(!is_payload_file_name("post-clipboard-1/../../.bashrc"));
+    }
+
+    #[tokio::test]
+    async fn test_stage_payload_writes_content() {
+        let name = payload_file_name(u64::MAX);
+        let path = stage_payload(&name, "large clipboard").await.unwrap();
+
+        assert_eq!(path, staging_path(&name).unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "large clipboard");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir = std::fs::metadata(path.parent().unwrap()).unwrap();
+            assert_eq!(dir.permissions().mode() & 0o777, 0o700);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
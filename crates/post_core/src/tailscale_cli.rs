@@ -0,0 +1,123 @@
+use crate::{PostError, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+use tokio::process::Command;
+use tracing::debug;
+
+/// CLI bundled with the macOS app, which is usually not on PATH
+#[cfg(target_os = "macos")]
+const MACOS_APP_CLI: &str = "/Applications/Tailscale.app/Contents/MacOS/Tailscale";
+
+/// A `tailscale` CLI invocation
+pub(crate) fn command() -> Command {
+    #[cfg(target_os = "macos")]
+    {
+        if Path::new(MACOS_APP_CLI).exists() {
+            return Command::new(MACOS_APP_CLI);
+        }
+    }
+    Command::new("tailscale")
+}
+
+/// Fetch or renew the TLS certificate for `domain` with `tailscale cert`
+///
+/// Returns the certificate and key paths inside `dir`. The tailnet must have HTTPS enabled.
+pub async fn fetch_certificate(domain: &str, dir: &Path) -> Result<(PathBuf, PathBuf)> {
+    tokio::fs::create_dir_all(dir).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+
+    let cert_file = dir.join(format!("{}.crt", domain));
+    let key_file = dir.join(format!("{}.key", domain));
+    debug!("Fetching TLS certificate for {}", domain);
+
+    let output = command()
+        .arg("cert")
+        .arg("--cert-file")
+        .arg(&cert_file)
+        .arg("--key-file")
+        .arg(&key_file)
+        .arg(domain)
+        .output()
+        .await
+        .map_err(|e| PostError::Tailscale(format!("Failed to run tailscale cert: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(PostError::Tailscale(format!(
+            "Failed to get a certificate for {}: {}",
+            domain,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok((cert_file, key_file))
+}
+
+/// Log the tailscaled listening on `socket` into the tailnet with `auth_key`
+///
+/// The key is handed over in a file only we can read, next to the socket, as command
+/// lines are visible to every local user.
+pub async fn login(socket: &Path, auth_key: &str, hostname: Option<&str>) -> Result<()> {
+    let key_file = write_auth_key(socket.parent().unwrap_or(Path::new(".")), auth_key)?;
+    let mut up = command();
+    up.arg(format!("--socket={}", socket.display()))
+        .arg("up")
+        .arg(format!("--authkey=file:{}", key_file.path().display()));
+    if let Some(hostname) = hostname {
+        up.arg(format!("--hostname={}", hostname));
+    }
+
+    debug!("Logging in to Tailscale through {}", socket.display());
+    let output = up
+        .output()
+        .await
+        .map_err(|e| PostError::Tailscale(format!("Failed to run tailscale up: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(PostError::Tailscale(format!(
+            "Failed to log in with the auth key: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Write `auth_key` to a file in `dir` readable only by us, removed once dropped
+fn write_auth_key(dir: &Path, auth_key: &str) -> Result<NamedTempFile> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("authkey-");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(std::fs::Permissions::from_mode(0o600));
+    }
+    let mut file = builder.tempfile_in(dir)?;
+    file.write_all(auth_key.as_bytes())?;
+    file.flush()?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_key_file_is_private_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = write_auth_key(dir.path(), "tskey-auth-123").unwrap();
+        let path = file.path().to_path_buf();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "tskey-auth-123");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        drop(file);
+        assert!(!path.exists());
+    }
+}
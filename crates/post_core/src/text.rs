@@ -0,0 +1,54 @@
+/// Longest prefix of `text` that fits in `max_bytes` without splitting a character
+pub fn truncate_bytes(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// The first `max_chars` characters of `text`
+pub fn truncate_chars(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// `text` cut to `max_chars` characters, with "..." appended when anything was cut
+pub fn preview(text: &str, max_chars: usize) -> String {
+    let shown = truncate_chars(text, max_chars);
+    if shown.len() < text.len() {
+        format!("{}...", shown)
+    } else {
+        shown.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_bytes_keeps_characters_whole() {
+        // "é" is 2 bytes, "日" 3 and "👍" 4
+        assert_eq!(truncate_bytes("héllo", 2), "h");
+        assert_eq!(truncate_bytes("日本語", 7), "日本");
+        assert_eq!(truncate_bytes("👍👍", 5), "👍");
+        assert_eq!(truncate_bytes("👍", 3), "");
+        assert_eq!(truncate_bytes("plain", 10), "plain");
+    }
+
+    #[test]
+    fn test_truncate_chars_and_preview() {
+        assert_eq!(truncate_chars("日本語テキスト", 3), "日本語");
+        assert_eq!(truncate_chars("👍🏽ok", 2), "👍🏽");
+        assert_eq!(truncate_chars("short", 10), "short");
+
+        assert_eq!(preview("こんにちは世界", 5), "こんにちは...");
+        assert_eq!(preview("🎉🎉", 2), "🎉🎉");
+    }
+}
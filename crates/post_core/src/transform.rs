@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+/// Query parameters that only track where a link was shared from
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid",
+    "gclid",
+    "dclid",
+    "gbraid",
+    "wbraid",
+    "msclkid",
+    "yclid",
+    "twclid",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "_hsenc",
+    "_hsmi",
+    "mkt_tok",
+    "oly_anon_id",
+    "oly_enc_id",
+    "vero_id",
+];
+
+/// A rewrite applied to local clipboard content before it is sent to peers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transform {
+    /// Remove leading and trailing whitespace
+    Trim,
+    /// Convert line endings to `\n`
+    Lf,
+    /// Convert line endings to `\r\n`
+    Crlf,
+    /// Drop `utm_*` and other tracking parameters when the content is a single URL
+    StripTrackingParams,
+}
+
+impl Transform {
+    pub fn apply(&self, content: &str) -> String {
+        match self {
+            Self::Trim => content.trim().to_string(),
+            Self::Lf => content.replace("\r\n", "\n"),
+            Self::Crlf => content.replace("\r\n", "\n").replace('\n', "\r\n"),
+            Self::StripTrackingParams => strip_tracking_params(content),
+        }
+    }
+}
+
+/// Run `transforms` over `content` in order
+pub fn apply_all(transforms: &[Transform], content: String) -> String {
+    transforms
+        .iter()
+        .fold(content, |content, transform| transform.apply(&content))
+}
+
+fn is_tracking_param(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key.starts_with("utm_") || TRACKING_PARAMS.contains(&key.as_str())
+}
+
+fn strip_tracking_params(content: &str) -> String {
+    let url = content.trim();
+    let is_url = (url.starts_with("http://") || url.starts_with("https://"))
+        && !url.contains(char::is_whitespace);
+    let Some((base, rest)) = url.split_once('?').filter(|_| is_url) else {
+        return content.to_string();
+    };
+
+    let (query, fragment) = match rest.split_once('#') {
+        Some((query, fragment)) => (query, Some(fragment)),
+        None => (rest, None),
+    };
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|param| {
+            let key = param.split('=').next().unwrap_or_default();
+            !param.is_empty() && !is_tracking_param(key)
+        })
+        .collect();
+
+    // Keep surrounding whitespace so a trailing newline survives unless `trim` runs too
+    let start = content.len() - content.trim_start().len();
+    let end = content.trim_end().len();
+    let mut stripped = format!("{}{}", &content[..start], base);
+    if !kept.is_empty() {
+        stripped.push('?');
+        stripped.push_str(&kept.join("&"));
+    }
+    if let Some(fragment) = fragment {
+        stripped.push('#');
+        stripped.push_str(fragment);
+    }
+    stripped.push_str(&content[end..]);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transforms_run_in_order() {
+        let content = "  one\r\ntwo\n".to_string();
+
+        assert_eq!(
+            apply_all(&[Transform::Trim, Transform::Crlf], content.clone()),
+            "one\r\ntwo"
+        );
+        assert_eq!(
+            apply_all(&[Transform::Crlf, Transform::Lf], content.clone()),
+            "  one\ntwo\n"
+        );
+        assert_eq!(apply_all(&[], content.clone()), content);
+    }
+
+    #[test]
+    fn test_strips_tracking_params_from_urls() {
+        let strip = |content: &str| Transform::StripTrackingParams.apply(content);
+
+        assert_eq!(
+            strip("https://example.com/a?id=7&utm_source=x&UTM_Medium=y&fbclid=z#top"),
+            "https://example.com/a?id=7#top"
+        );
+        assert_eq!(
+            strip("https://example.com/?utm_campaign=spring\n"),
+            "https://example.com/\n"
+        );
+        assert_eq!(
+            strip("see https://x.io/?utm_source=a"),
+            "see https://x.io/?utm_source=a"
+        );
+        assert_eq!(
+            strip("https://example.com/?q=1"),
+            "https://example.com/?q=1"
+        );
+    }
+}
@@ -1,16 +1,55 @@
-use crate::{PostError, PostMessage, Result};
+use crate::circuit::{CircuitBreaker, PeerCircuit};
+use crate::compat::{self, Capability};
+use crate::directory::PeerDirectory;
+use crate::inbox::InboxSender;
+use crate::wire::{
+    decode_message, encode_message, record_oversized_frame, take_frame, DecodeError, WireFormat,
+    BINARY_FRAME_MAGIC, MAX_MESSAGE_SIZE,
+};
+use crate::{
+    taildrop, IpPreference, MessageData, NodeDiscoveryData, PostError, PostMessage, Result,
+    TagPolicy,
+};
 use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt};
 use serde::Deserialize;
-use serde_json;
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
+use std::sync::{Arc, OnceLock, RwLock, Weak};
+use std::time::{Duration, Instant};
 use tailscale_localapi::{LocalApi, UnixStreamClient};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc;
+use tokio::sync::{watch, Semaphore};
 use tracing::{debug, error, info, warn};
 
+/// Peers a message is sent to at once unless configured otherwise
+pub const DEFAULT_SEND_CONCURRENCY: usize = 8;
+
+/// Inbound connections handled at once unless configured otherwise
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Inbound connections one address may have open at once, so a single host can't take
+/// every slot
+const MAX_CONNECTIONS_PER_SOURCE: usize = 8;
+
+/// Inbound connections with nothing to read for this long are closed
+const CONNECTION_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Inbound connections that haven't sent a whole frame yet are closed sooner
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a peer has to accept a connection unless configured otherwise
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Time a peer has to take a message once connected unless configured otherwise
+pub const DEFAULT_SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the peers found in a Tailscale status lookup are reused for sends
+const STATUS_CACHE_TTL: Duration = Duration::from_secs(3);
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct TcpApiStatus {
     #[serde(rename = "BackendState")]
@@ -25,20 +64,48 @@ pub struct TcpApiStatus {
 pub struct TcpApiSelfStatus {
     #[serde(rename = "ID")]
     pub id: String,
+    #[serde(rename = "TailscaleIPs", default)]
+    pub tailscale_ips: Vec<String>,
+    #[serde(rename = "HostName", default)]
+    pub host_name: String,
+    #[serde(rename = "DNSName", default)]
+    pub dns_name: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct TcpApiPeer {
+    #[serde(rename = "HostName", default)]
+    pub host_name: String,
+    #[serde(rename = "DNSName", default)]
+    pub dns_name: String,
     #[serde(rename = "Online")]
     pub online: bool,
     #[serde(rename = "TailscaleIPs")]
     pub tailscale_ips: Vec<String>,
+    #[serde(rename = "Tags", default)]
+    pub tags: Vec<String>,
+}
+
+/// Failure reading the local API's JSON status
+#[derive(Debug, thiserror::Error)]
+pub enum TcpApiError {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Response(String),
 }
 
+/// Client for the local API's JSON endpoints, over TCP for the macOS App Store build or
+/// over tailscaled's named pipe on Windows
 pub struct TcpApiClient {
     base_url: String,
     client: reqwest::Client,
     auth_token: Option<String>,
+    /// Named pipe to send requests through instead of `base_url`
+    #[cfg(windows)]
+    pipe_path: Option<String>,
 }
 
 impl TcpApiClient {
@@ -48,9 +115,40 @@ impl TcpApiClient {
             base_url: format!("http://localhost:{}/localapi/v0", port),
             client: reqwest::Client::new(),
             auth_token,
+            #[cfg(windows)]
+            pipe_path: None,
         }
     }
 
+    /// Client speaking HTTP over the named pipe at `path`
+    #[cfg(windows)]
+    pub fn named_pipe(path: &str) -> Self {
+        Self {
+            base_url: String::new(),
+            client: reqwest::Client::new(),
+            auth_token: None,
+            pipe_path: Some(path.to_string()),
+        }
+    }
+
+    #[cfg(windows)]
+    async fn pipe_get(pipe_path: &str, path: &str) -> std::result::Result<Vec<u8>, TcpApiError> {
+        use tokio::io::AsyncReadExt;
+        use tokio::net::windows::named_pipe::ClientOptions;
+
+        let mut pipe = ClientOptions::new().open(pipe_path)?;
+        // HTTP/1.0 so tailscaled answers with a plain body and closes the pipe
+        let request = format!(
+            "GET /localapi/v0/{} HTTP/1.0\r\nHost: local-tailscaled.sock\r\nSec-Tailscale: localapi\r\n\r\n",
+            path
+        );
+        pipe.write_all(request.as_bytes()).await?;
+
+        let mut response = Vec::new();
+        pipe.read_to_end(&mut response).await?;
+        http_response_body(&response)
+    }
+
     fn read_auth_token(port: u16) -> Option<String> {
         let token_file_path = format!("/Library/Tailscale/sameuserproof-{}", port);
         debug!("Trying to read auth token from: {}", token_file_path);
@@ -67,7 +165,14 @@ impl TcpApiClient {
         }
     }
 
-    pub async fn status(&self) -> std::result::Result<TcpApiStatus, reqwest::Error> {
+    pub async fn status(&self) -> std::result::Result<TcpApiStatus, TcpApiError> {
+        #[cfg(windows)]
+        if let Some(ref pipe_path) = self.pipe_path {
+            let body = Self::pipe_get(pipe_path, "status").await?;
+            return serde_json::from_slice(&body)
+                .map_err(|e| TcpApiError::Response(format!("Invalid status JSON: {}", e)));
+        }
+
         let mut request = self.client.get(format!("{}/status", self.base_url));
 
         if let Some(ref token) = self.auth_token {
@@ -75,10 +180,15 @@ impl TcpApiClient {
         }
 
         let response = request.send().await?;
-        response.json().await
+        Ok(response.json().await?)
     }
 
-    pub async fn test_connection(&self) -> std::result::Result<(), reqwest::Error> {
+    pub async fn test_connection(&self) -> std::result::Result<(), TcpApiError> {
+        #[cfg(windows)]
+        if self.pipe_path.is_some() {
+            return self.status().await.map(|_| ());
+        }
+
         let mut request = self.client.get(format!("{}/status", self.base_url));
 
         if let Some(ref token) = self.auth_token {
@@ -90,13 +200,334 @@ impl TcpApiClient {
     }
 }
 
+/// Body of a raw HTTP response, or an error for a non-2xx status
+#[cfg(any(windows, test))]
+fn http_response_body(response: &[u8]) -> std::result::Result<Vec<u8>, TcpApiError> {
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| TcpApiError::Response("Truncated local API response".to_string()))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let status_line = head.lines().next().unwrap_or_default();
+
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(response[split + 4..].to_vec()),
+        _ => Err(TcpApiError::Response(format!(
+            "Local API answered {:?}",
+            status_line
+        ))),
+    }
+}
+
+/// An online peer together with its human-friendly name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TailnetPeer {
+    pub name: String,
+    pub address: String,
+}
+
+/// Short machine name from Tailscale's MagicDNS name, falling back to the OS hostname
+///
+/// `mac-studio.tail1234.ts.net.` becomes `mac-studio`.
+pub fn friendly_node_name(dns_name: &str, host_name: &str) -> Option<String> {
+    let dns_label = dns_name.split('.').next().unwrap_or_default();
+    [dns_label, host_name.trim()]
+        .into_iter()
+        .find(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// Tailscale ACL tags of each peer IP, as last read from the status, checked against
+/// `network.peer_tags`
+#[derive(Debug, Default)]
+struct PeerTags {
+    policy: TagPolicy,
+    tags: RwLock<HashMap<String, Vec<String>>>,
+}
+
+impl PeerTags {
+    fn record(&self, ips: &[IpAddr], tags: &[String]) {
+        let mut known = self
+            .tags
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for ip in ips {
+            known.insert(ip.to_canonical().to_string(), tags.to_vec());
+        }
+    }
+
+    /// Whether the policy lets us sync with `ip`; while one is set, peers not yet seen in
+    /// the status are refused
+    fn allows(&self, ip: &str) -> bool {
+        self.policy.is_empty()
+            || self
+                .tags
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .get(ip)
+                .is_some_and(|tags| self.policy.allows(tags))
+    }
+}
+
+/// Tailnet of each peer IP, from its MagicDNS name in the status, checked against
+/// `sync.allowed_tailnets`
+///
+/// Peers say which tailnet they are in themselves, so connections are checked against
+/// what Tailscale reports instead.
+#[derive(Debug, Default)]
+struct PeerTailnets {
+    allowed: Vec<String>,
+    /// This node's tailnet; nothing is refused until it is known
+    own: RwLock<Option<String>>,
+    tailnets: RwLock<HashMap<String, Option<String>>>,
+}
+
+impl PeerTailnets {
+    fn record_own(&self, dns_name: &str) {
+        *self
+            .own
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = tailnet_of(dns_name);
+    }
+
+    fn record(&self, ips: &[IpAddr], dns_name: &str) {
+        let tailnet = tailnet_of(dns_name);
+        let mut known = self
+            .tailnets
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for ip in ips {
+            known.insert(ip.to_canonical().to_string(), tailnet.clone());
+        }
+    }
+
+    /// Whether `ip` is in this node's tailnet or an allowed one; peers whose tailnet isn't
+    /// known are refused while other tailnets are allowed, like untagged updates
+    fn allows(&self, ip: &str) -> bool {
+        let own = self
+            .own
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(own) = own.as_deref() else {
+            return true;
+        };
+        match self
+            .tailnets
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(ip)
+        {
+            Some(Some(tailnet)) => crate::config::tailnet_allowed(&self.allowed, own, tailnet),
+            _ => self.allowed.is_empty() || self.allowed.iter().any(|allowed| allowed == "*"),
+        }
+    }
+}
+
+/// Peers to send to from the last status lookup, so a burst of clipboard changes doesn't
+/// ask the local API for the status every time
+#[derive(Debug, Default)]
+struct StatusCache {
+    entry: std::sync::Mutex<Option<(Instant, Vec<String>)>>,
+}
+
+impl StatusCache {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<(Instant, Vec<String>)>> {
+        self.entry
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The cached peers, unless they were looked up more than `ttl` ago
+    fn get(&self, ttl: Duration) -> Option<Vec<String>> {
+        self.lock()
+            .as_ref()
+            .filter(|(looked_up, _)| looked_up.elapsed() <= ttl)
+            .map(|(_, nodes)| nodes.clone())
+    }
+
+    fn put(&self, nodes: Vec<String>) {
+        *self.lock() = Some((Instant::now(), nodes));
+    }
+
+    fn invalidate(&self) {
+        self.lock().take();
+    }
+}
+
+/// Slots for inbound connections, shared by every listener and limited per source address
+#[derive(Debug)]
+struct ConnectionSlots {
+    total: Arc<Semaphore>,
+    per_source: usize,
+    open: Arc<std::sync::Mutex<HashMap<IpAddr, usize>>>,
+}
+
+/// A connection's slot, given back when dropped
+struct ConnectionSlot {
+    _permit: tokio::sync::OwnedSemaphorePermit,
+    source: IpAddr,
+    open: Arc<std::sync::Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnectionSlots {
+    fn new(max: usize) -> Self {
+        Self {
+            total: Arc::new(Semaphore::new(max)),
+            per_source: MAX_CONNECTIONS_PER_SOURCE.min(max),
+            open: Arc::default(),
+        }
+    }
+
+    /// A slot for a connection from `source`, or `None` when it or everyone together
+    /// already has as many open as allowed
+    fn try_acquire(&self, source: IpAddr) -> Option<ConnectionSlot> {
+        let source = source.to_canonical();
+        let mut open = self
+            .open
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let count = open.entry(source).or_default();
+        if *count >= self.per_source {
+            return None;
+        }
+        let permit = Arc::clone(&self.total).try_acquire_owned().ok()?;
+        *count += 1;
+        Some(ConnectionSlot {
+            _permit: permit,
+            source,
+            open: Arc::clone(&self.open),
+        })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut open = self
+            .open
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(count) = open.get_mut(&self.source) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.source);
+            }
+        }
+    }
+}
+
+/// Tailnet a MagicDNS name belongs to, as its suffix
+///
+/// `mac-studio.tail1234.ts.net.` is in `tail1234.ts.net`.
+pub fn tailnet_of(dns_name: &str) -> Option<String> {
+    let (_, suffix) = dns_name.trim_end_matches('.').split_once('.')?;
+    (!suffix.is_empty()).then(|| suffix.to_ascii_lowercase())
+}
+
+/// Parse the string IPs reported by the TCP local API, skipping malformed entries
+fn parse_ips(ips: &[String]) -> Vec<IpAddr> {
+    ips.iter().filter_map(|ip| ip.parse().ok()).collect()
+}
+
+/// Whether `ip` is in the ranges Tailscale assigns node addresses from
+fn is_tailscale_ip(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        // 100.64.0.0/10
+        IpAddr::V4(ip) => ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64,
+        // fd7a:115c:a1e0::/48
+        IpAddr::V6(ip) => ip.segments()[..3] == [0xfd7a, 0x115c, 0xa1e0],
+    }
+}
+
+/// Endpoint a peer connecting from `source` asked to be reached on, or `None` when it
+/// is the default of its own IP on our `port`
+///
+/// Only the peer's own IP or another tailnet address is accepted, so a peer can't have
+/// its messages sent to some host outside the tailnet.
+fn advertised_endpoint(source: IpAddr, data: &NodeDiscoveryData, port: u16) -> Option<SocketAddr> {
+    let ip = data
+        .address
+        .as_deref()
+        .and_then(|address| address.parse().ok())
+        .unwrap_or(source);
+    if ip != source && !is_tailscale_ip(ip) {
+        warn!(
+            "Ignoring endpoint {} advertised by {}, which isn't a tailnet address",
+            ip, source
+        );
+        return None;
+    }
+    let endpoint = SocketAddr::new(ip, data.port.unwrap_or(port));
+    (endpoint != SocketAddr::new(source, port)).then_some(endpoint)
+}
+
 #[async_trait]
 pub trait Transport: Send + Sync {
     async fn send_message(&self, message: PostMessage) -> Result<()>;
-    async fn start_listening(&self, sender: mpsc::UnboundedSender<PostMessage>) -> Result<()>;
+
+    /// Send `message` to the peer at `address` only; transports that can't single out a
+    /// peer send it to every peer
+    async fn send_message_to(&self, _address: &str, message: PostMessage) -> Result<()> {
+        self.send_message(message).await
+    }
+
+    /// Read online peers from `directory` instead of looking them up for every send;
+    /// transports that don't look peers up ignore it
+    fn use_peer_directory(&self, _directory: Weak<PeerDirectory>) {}
+
+    async fn start_listening(&self, sender: InboxSender) -> Result<()>;
     async fn get_node_id(&self) -> Result<String>;
     async fn get_tailnet_nodes(&self) -> Result<Vec<String>>;
     async fn is_connected(&self) -> Result<bool>;
+
+    /// Human-friendly name of this node; defaults to the node ID
+    async fn get_node_name(&self) -> Result<String> {
+        self.get_node_id().await
+    }
+
+    /// Full MagicDNS name of this node, as used for its TLS certificate
+    async fn get_dns_name(&self) -> Result<String> {
+        Err(PostError::Tailscale(
+            "This transport has no MagicDNS name".to_string(),
+        ))
+    }
+
+    /// Tailnet this node is in, from its MagicDNS name
+    async fn get_tailnet(&self) -> Result<String> {
+        let dns_name = self.get_dns_name().await?;
+        tailnet_of(&dns_name).ok_or_else(|| {
+            PostError::Tailscale(format!("No tailnet in MagicDNS name {}", dns_name))
+        })
+    }
+
+    /// Peers whose recent sends failed; empty for transports without a circuit breaker
+    fn peer_circuits(&self) -> Vec<PeerCircuit> {
+        Vec::new()
+    }
+
+    /// Whether peers' connections are being accepted; transports without listeners of
+    /// their own always are
+    fn is_listening(&self) -> bool {
+        true
+    }
+
+    /// Wait for `start_listening` to first try binding its listeners, failing with why
+    /// it couldn't; transports without listeners of their own return at once
+    async fn wait_listening(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Online peers with their friendly names; defaults to naming peers by address
+    async fn get_tailnet_peers(&self) -> Result<Vec<TailnetPeer>> {
+        Ok(self
+            .get_tailnet_nodes()
+            .await?
+            .into_iter()
+            .map(|address| TailnetPeer {
+                name: address.clone(),
+                address,
+            })
+            .collect())
+    }
 }
 
 pub enum TailscaleClient {
@@ -108,22 +539,90 @@ pub struct TailscaleTransport {
     client: TailscaleClient,
     port: u16,
     connection_info: String,
+    /// Explicit listener address; `None` binds to this node's Tailscale IPs
+    bind_address: Option<IpAddr>,
+    /// Which of a peer's Tailscale IPs to connect to
+    ip_preference: IpPreference,
+    /// Wire format negotiated with each peer IP, learned from its discovery messages
+    peer_formats: Arc<RwLock<HashMap<String, WireFormat>>>,
+    /// Capabilities each peer IP advertised in its discovery messages
+    peer_capabilities: Arc<RwLock<HashMap<String, Vec<String>>>>,
+    /// Sync endpoint each peer IP advertised, when it differs from its IP and our port
+    peer_endpoints: Arc<RwLock<HashMap<String, SocketAddr>>>,
+    /// IP each node ID's latest signed discovery came from, for messages meant for it alone
+    peer_addresses: Arc<RwLock<HashMap<String, String>>>,
+    /// Skips peers whose sends keep failing, probing them now and then
+    circuits: Arc<CircuitBreaker>,
+    /// Online peers from a recent status lookup, for while there is no directory to read
+    send_targets: Arc<StatusCache>,
+    /// The daemon's peer directory, which online peers are read from once it has them
+    peer_directory: Arc<OnceLock<Weak<PeerDirectory>>>,
+    /// How the latest attempt to bind the listeners went, `None` before the first
+    listen_status: Arc<watch::Sender<Option<std::result::Result<(), String>>>>,
+    /// Which peers may be synced with, by their ACL tags
+    peer_tags: Arc<PeerTags>,
+    /// Which peers may be synced with, by their tailnet
+    peer_tailnets: Arc<PeerTailnets>,
+    /// Peers a message is sent to at once
+    send_concurrency: usize,
+    /// Longest a peer may take to accept a connection
+    connect_timeout: Duration,
+    /// Longest writing a message to a connected peer may take
+    send_timeout: Duration,
+    /// Inbound connections handled at once; more are closed as they arrive
+    max_connections: usize,
 }
 
 impl TailscaleTransport {
-    pub fn new(port: u16) -> Self {
-        let socket_path = Self::detect_tailscale_socket_path();
+    /// Transport using `socket_path`, or the platform's usual socket, without checking it
+    pub fn new(port: u16, socket_path: Option<&str>) -> Self {
+        let socket_path = socket_path
+            .map(str::to_string)
+            .unwrap_or_else(Self::detect_tailscale_socket_path);
         debug!("Using Tailscale socket path: {}", socket_path);
         Self {
-            client: TailscaleClient::Unix(LocalApi::<UnixStreamClient>::new_with_socket_path(
-                &socket_path,
-            )),
+            client: Self::client_for_socket(&socket_path),
             port,
             connection_info: socket_path.clone(),
+            bind_address: None,
+            ip_preference: IpPreference::default(),
+            peer_formats: Arc::default(),
+            peer_capabilities: Arc::default(),
+            peer_endpoints: Arc::default(),
+            peer_addresses: Arc::default(),
+            circuits: Arc::default(),
+            send_targets: Arc::default(),
+            peer_directory: Arc::default(),
+            listen_status: Arc::new(watch::channel(None).0),
+            peer_tags: Arc::default(),
+            peer_tailnets: Arc::default(),
+            send_concurrency: DEFAULT_SEND_CONCURRENCY,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            send_timeout: DEFAULT_SEND_TIMEOUT,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
         }
     }
 
-    pub async fn new_with_detection(port: u16) -> Result<Self> {
+    /// Connect through `socket_path` if configured, otherwise through the first socket
+    /// (or, on macOS, TCP port) that answers
+    pub async fn new_with_detection(port: u16, socket_path: Option<&str>) -> Result<Self> {
+        if let Some(socket_path) = socket_path {
+            let transport = Self::new(port, Some(socket_path));
+            return match transport.test_connection().await {
+                Ok(()) => {
+                    info!(
+                        "Connected to configured Tailscale socket at: {}",
+                        socket_path
+                    );
+                    Ok(transport)
+                }
+                Err(e) => Err(PostError::Tailscale(format!(
+                    "Could not connect to Tailscale at network.tailscale_socket {}: {}",
+                    socket_path, e
+                ))),
+            };
+        }
+
         let socket_paths = Self::get_possible_socket_paths();
 
         // First try Unix sockets
@@ -133,11 +632,25 @@ impl TailscaleTransport {
             // Check if socket exists and is accessible
             if Self::is_socket_accessible(&socket_path).await {
                 let transport = Self {
-                    client: TailscaleClient::Unix(
-                        LocalApi::<UnixStreamClient>::new_with_socket_path(&socket_path),
-                    ),
+                    client: Self::client_for_socket(&socket_path),
                     port,
                     connection_info: socket_path.clone(),
+                    bind_address: None,
+                    ip_preference: IpPreference::default(),
+                    peer_formats: Arc::default(),
+                    peer_capabilities: Arc::default(),
+                    peer_endpoints: Arc::default(),
+                    peer_addresses: Arc::default(),
+                    circuits: Arc::default(),
+                    send_targets: Arc::default(),
+                    peer_directory: Arc::default(),
+                    listen_status: Arc::new(watch::channel(None).0),
+                    peer_tags: Arc::default(),
+                    peer_tailnets: Arc::default(),
+                    send_concurrency: DEFAULT_SEND_CONCURRENCY,
+                    connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+                    send_timeout: DEFAULT_SEND_TIMEOUT,
+                    max_connections: DEFAULT_MAX_CONNECTIONS,
                 };
 
                 // Test if we can actually connect and get status
@@ -169,6 +682,22 @@ impl TailscaleTransport {
                             client: TailscaleClient::Tcp(tcp_client),
                             port,
                             connection_info: format!("TCP localhost:{}", tcp_port),
+                            bind_address: None,
+                            ip_preference: IpPreference::default(),
+                            peer_formats: Arc::default(),
+                            peer_capabilities: Arc::default(),
+                            peer_endpoints: Arc::default(),
+                            peer_addresses: Arc::default(),
+                            circuits: Arc::default(),
+                            send_targets: Arc::default(),
+                            peer_directory: Arc::default(),
+                            listen_status: Arc::new(watch::channel(None).0),
+                            peer_tags: Arc::default(),
+                            peer_tailnets: Arc::default(),
+                            send_concurrency: DEFAULT_SEND_CONCURRENCY,
+                            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+                            send_timeout: DEFAULT_SEND_TIMEOUT,
+                            max_connections: DEFAULT_MAX_CONNECTIONS,
                         });
                     }
                     Err(e) => {
@@ -188,6 +717,21 @@ impl TailscaleTransport {
         ))
     }
 
+    /// Local API client for the Unix socket, or on Windows the named pipe, at `socket_path`
+    fn client_for_socket(socket_path: &str) -> TailscaleClient {
+        #[cfg(windows)]
+        {
+            TailscaleClient::Tcp(TcpApiClient::named_pipe(socket_path))
+        }
+
+        #[cfg(not(windows))]
+        {
+            TailscaleClient::Unix(LocalApi::<UnixStreamClient>::new_with_socket_path(
+                socket_path,
+            ))
+        }
+    }
+
     fn detect_tailscale_socket_path() -> String {
         // Check if running in container first
         if Self::is_running_in_container() {
@@ -334,6 +878,305 @@ impl TailscaleTransport {
         &self.connection_info
     }
 
+    /// Listen on `address` instead of this node's Tailscale IP
+    pub fn with_bind_address(mut self, address: Option<IpAddr>) -> Self {
+        self.bind_address = address;
+        self
+    }
+
+    /// Which address family to use when a peer has both IPv4 and IPv6 addresses
+    pub fn with_ip_preference(mut self, preference: IpPreference) -> Self {
+        self.ip_preference = preference;
+        self
+    }
+
+    /// Send to up to `concurrency` peers at once
+    pub fn with_send_concurrency(mut self, concurrency: usize) -> Self {
+        self.send_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Fail a send when connecting takes longer than `connect` or writing than `send`
+    pub fn with_timeouts(mut self, connect: Duration, send: Duration) -> Self {
+        self.connect_timeout = connect;
+        self.send_timeout = send;
+        self
+    }
+
+    /// Handle at most `max` inbound connections at once, closing others right away
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max.max(1);
+        self
+    }
+
+    /// Skip a peer after `threshold` failed sends in a row, probing it with backoff
+    /// of up to `max_backoff`
+    pub fn with_circuit_breaker(mut self, threshold: u32, max_backoff: Duration) -> Self {
+        self.circuits = Arc::new(CircuitBreaker::new(threshold, max_backoff));
+        self
+    }
+
+    /// Only sync with peers in this node's tailnet and in `allowed`, see
+    /// `sync.allowed_tailnets`
+    pub fn with_allowed_tailnets(mut self, allowed: Vec<String>) -> Self {
+        self.peer_tailnets = Arc::new(PeerTailnets {
+            allowed,
+            ..PeerTailnets::default()
+        });
+        self
+    }
+
+    /// Only sync with peers whose Tailscale ACL tags `policy` allows
+    pub fn with_tag_policy(mut self, policy: TagPolicy) -> Self {
+        self.peer_tags = Arc::new(PeerTags {
+            policy,
+            tags: RwLock::default(),
+        });
+        self
+    }
+
+    /// This node's own Tailscale IPs
+    pub async fn get_local_addresses(&self) -> Result<Vec<IpAddr>> {
+        match &self.client {
+            TailscaleClient::Unix(local_api) => {
+                let status = local_api
+                    .status()
+                    .await
+                    .map_err(|e| PostError::Tailscale(format!("Failed to get status: {}", e)))?;
+                Ok(status.self_status.tailscale_ips)
+            }
+            TailscaleClient::Tcp(tcp_client) => {
+                let status = tcp_client
+                    .status()
+                    .await
+                    .map_err(|e| PostError::Tailscale(format!("Failed to get status: {}", e)))?;
+                Ok(parse_ips(&status.self_status.tailscale_ips))
+            }
+        }
+    }
+
+    /// Bind sync listeners on every Tailscale IP, keeping the port off other interfaces
+    /// unless configured otherwise
+    async fn bind_listeners(&self) -> Result<Vec<TcpListener>> {
+        if let Some(ip) = self.bind_address {
+            let addr = SocketAddr::new(ip, self.port);
+            let listener = TcpListener::bind(addr)
+                .await
+                .map_err(|e| PostError::Network(format!("Failed to bind to {}: {}", addr, e)))?;
+            return Ok(vec![listener]);
+        }
+
+        let mut addresses = self.get_local_addresses().await?;
+        if addresses.is_empty() {
+            return Err(PostError::Network(
+                "No Tailscale address to listen on yet".to_string(),
+            ));
+        }
+        self.ip_preference.sort(&mut addresses);
+
+        let mut listeners = Vec::new();
+        for ip in &addresses {
+            let addr = SocketAddr::new(*ip, self.port);
+            match TcpListener::bind(addr).await {
+                Ok(listener) => listeners.push(listener),
+                Err(e) => debug!("Failed to bind to Tailscale address {}: {}", addr, e),
+            }
+        }
+        if !listeners.is_empty() {
+            return Ok(listeners);
+        }
+
+        // Userspace networking doesn't put the Tailscale IP on a local interface
+        warn!(
+            "Could not bind to a Tailscale address ({:?}); listening on all interfaces. \
+             Set network.bind_address to restrict this.",
+            addresses
+        );
+        let addr = SocketAddr::new(self.ip_preference.unspecified(), self.port);
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| PostError::Network(format!("Failed to bind to {}: {}", addr, e)))?;
+        Ok(vec![listener])
+    }
+
+    /// Accept peer connections on `listener` and forward decoded messages to `sender`
+    #[allow(clippy::too_many_arguments)]
+    async fn accept_connections(
+        listener: TcpListener,
+        sender: InboxSender,
+        peer_formats: Arc<RwLock<HashMap<String, WireFormat>>>,
+        peer_capabilities: Arc<RwLock<HashMap<String, Vec<String>>>>,
+        peer_endpoints: Arc<RwLock<HashMap<String, SocketAddr>>>,
+        peer_addresses: Arc<RwLock<HashMap<String, String>>>,
+        port: u16,
+        connection_slots: Arc<ConnectionSlots>,
+        peer_tags: Arc<PeerTags>,
+        peer_tailnets: Arc<PeerTailnets>,
+    ) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    let ip = addr.ip().to_canonical().to_string();
+                    if !peer_tags.allows(&ip) {
+                        debug!(
+                            "Refusing connection from {}: not allowed by network.peer_tags",
+                            addr
+                        );
+                        continue;
+                    }
+                    if !peer_tailnets.allows(&ip) {
+                        debug!(
+                            "Refusing connection from {}: its tailnet is not in sync.allowed_tailnets",
+                            addr
+                        );
+                        continue;
+                    }
+                    // Dropping the stream closes it, so a flood can't spawn unbounded tasks
+                    let Some(slot) = connection_slots.try_acquire(addr.ip()) else {
+                        debug!(
+                            "Rejecting connection from {}: too many open connections",
+                            addr
+                        );
+                        continue;
+                    };
+                    debug!("Accepted connection from {}", addr);
+                    let handler = Self::handle_connection(
+                        stream,
+                        addr,
+                        sender.clone(),
+                        Arc::clone(&peer_formats),
+                        Arc::clone(&peer_capabilities),
+                        Arc::clone(&peer_endpoints),
+                        Arc::clone(&peer_addresses),
+                        port,
+                    );
+                    tokio::spawn(async move {
+                        handler.await;
+                        drop(slot);
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to accept connection: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Read frames from one peer connection until it closes, errors or goes idle
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_connection(
+        mut stream: TcpStream,
+        addr: SocketAddr,
+        sender: InboxSender,
+        peer_formats: Arc<RwLock<HashMap<String, WireFormat>>>,
+        peer_capabilities: Arc<RwLock<HashMap<String, Vec<String>>>>,
+        peer_endpoints: Arc<RwLock<HashMap<String, SocketAddr>>>,
+        peer_addresses: Arc<RwLock<HashMap<String, String>>>,
+        port: u16,
+    ) {
+        let mut buffer = Vec::new();
+        let mut temp_buf = [0u8; 4096];
+        let mut idle_timeout = FIRST_FRAME_TIMEOUT;
+
+        loop {
+            let n = match tokio::time::timeout(idle_timeout, stream.read(&mut temp_buf)).await {
+                Ok(Ok(0)) => break, // EOF
+                Ok(Ok(n)) => n,
+                Ok(Err(e)) => {
+                    debug!("Connection error: {}", e);
+                    break;
+                }
+                Err(_) => {
+                    debug!(
+                        "Closing connection from {}, idle for {:?}",
+                        addr, idle_timeout
+                    );
+                    break;
+                }
+            };
+            buffer.extend_from_slice(&temp_buf[..n]);
+
+            // Look for complete messages (newline-delimited JSON or length-prefixed binary)
+            while let Some(frame) = take_frame(&mut buffer) {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        record_oversized_frame();
+                        warn!("Dropping connection from {}: {}", addr, e);
+                        return;
+                    }
+                };
+
+                if frame.trim_ascii().is_empty() {
+                    continue;
+                }
+                idle_timeout = CONNECTION_IDLE_TIMEOUT;
+
+                match decode_message(&frame) {
+                    Ok(message) => {
+                        debug!("Received message: {:?}", message.message_type);
+                        // Only what a correctly signed discovery says about its sender
+                        // is remembered
+                        let discovery = match &message.data {
+                            MessageData::NodeDiscovery(data) => message
+                                .verify_discovery(data)
+                                .inspect_err(|e| debug!("Not learning from {}: {}", addr, e))
+                                .ok()
+                                .map(|_| data),
+                            _ => None,
+                        };
+                        if let Some(data) = discovery {
+                            let peer_ip = addr.ip().to_canonical().to_string();
+                            let format = WireFormat::negotiate(&data.wire_formats);
+                            peer_formats
+                                .write()
+                                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                                .insert(peer_ip.clone(), format);
+                            peer_capabilities
+                                .write()
+                                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                                .insert(peer_ip.clone(), data.capabilities.clone());
+                            let mut endpoints = peer_endpoints
+                                .write()
+                                .unwrap_or_else(|poisoned| poisoned.into_inner());
+                            match advertised_endpoint(addr.ip().to_canonical(), data, port) {
+                                Some(endpoint) => endpoints.insert(peer_ip.clone(), endpoint),
+                                None => endpoints.remove(&peer_ip),
+                            };
+                            peer_addresses
+                                .write()
+                                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                                .insert(data.source_node.clone(), peer_ip);
+                        }
+                        if let Err(e) = sender.send(message) {
+                            error!("Failed to forward message: {}", e);
+                            return;
+                        }
+                    }
+                    Err(DecodeError::Unsupported(message_type)) => {
+                        debug!(
+                            "Ignoring {} from {}, sent by a newer version of Post",
+                            message_type, addr
+                        );
+                    }
+                    Err(e) => {
+                        warn!("Rejected message from {}: {}", addr, e);
+                    }
+                }
+            }
+
+            // A peer that never sends a newline must not grow the buffer forever
+            if buffer.first() != Some(&BINARY_FRAME_MAGIC) && buffer.len() > MAX_MESSAGE_SIZE {
+                record_oversized_frame();
+                warn!(
+                    "Dropping connection from {}: message exceeds {} bytes",
+                    addr, MAX_MESSAGE_SIZE
+                );
+                break;
+            }
+        }
+    }
+
     async fn is_socket_accessible(socket_path: &str) -> bool {
         #[cfg(unix)]
         {
@@ -343,9 +1186,14 @@ impl TailscaleTransport {
 
         #[cfg(windows)]
         {
-            // For Windows named pipes, we need to try to connect
-            // This is a simplified check - in reality we'd use Windows APIs
-            Path::new(socket_path).exists()
+            use tokio::net::windows::named_pipe::ClientOptions;
+            const ERROR_PIPE_BUSY: i32 = 231;
+
+            // A busy pipe exists but is serving another client right now
+            match ClientOptions::new().open(socket_path) {
+                Ok(_) => true,
+                Err(e) => e.raw_os_error() == Some(ERROR_PIPE_BUSY),
+            }
         }
 
         #[cfg(not(any(unix, windows)))]
@@ -435,149 +1283,415 @@ impl TailscaleTransport {
         }
     }
 
-    async fn send_to_node(&self, node_ip: &str, message: &PostMessage) -> Result<()> {
-        let serialized = serde_json::to_string(message)
-            .map_err(|e| PostError::Serialization(format!("Failed to serialize message: {}", e)))?;
+    /// Online peers to send to: the directory's once it has read them, or otherwise
+    /// looked up at most [`STATUS_CACHE_TTL`] ago
+    async fn send_targets(&self) -> Result<Vec<String>> {
+        let directory = self.peer_directory.get().and_then(Weak::upgrade);
+        if let Some(online) = directory.and_then(|directory| directory.online_addresses()) {
+            return Ok(online
+                .into_iter()
+                .filter(|address| self.may_sync_with(address))
+                .collect());
+        }
+        if let Some(nodes) = self.send_targets.get(STATUS_CACHE_TTL) {
+            return Ok(nodes);
+        }
+        // Fails while Tailscale is down, which is never cached
+        let nodes = self.get_tailnet_nodes().await?;
+        self.send_targets.put(nodes.clone());
+        Ok(nodes)
+    }
+
+    /// Format to use when sending `message` to `node_ip`
+    ///
+    /// Discovery is always JSON since it is how peers learn which formats we understand.
+    fn wire_format_for(&self, node_ip: &str, message: &PostMessage) -> WireFormat {
+        if matches!(message.data, MessageData::NodeDiscovery(_)) {
+            return WireFormat::Json;
+        }
 
-        debug!("Sending message to {}: {} bytes", node_ip, serialized.len());
+        self.peer_formats
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(node_ip)
+            .copied()
+            .unwrap_or(WireFormat::Json)
+    }
 
-        let addr = format!("{}:{}", node_ip, self.port);
-        let mut stream = TcpStream::connect(&addr)
-            .await
-            .map_err(|e| PostError::Network(format!("Failed to connect to {}: {}", addr, e)))?;
+    /// Whether `node_ip` advertised `capability`; nodes not heard from yet haven't
+    fn node_supports(&self, node_ip: &str, capability: Capability) -> bool {
+        self.peer_capabilities
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(node_ip)
+            .is_some_and(|capabilities| compat::peer_supports(capabilities, capability))
+    }
 
-        stream
-            .write_all(serialized.as_bytes())
-            .await
-            .map_err(|e| PostError::Network(format!("Failed to write message: {}", e)))?;
+    /// IP of the node that announced itself as `node_id`
+    fn address_of(&self, node_id: &str) -> Result<String> {
+        self.peer_addresses
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(node_id)
+            .cloned()
+            .ok_or_else(|| {
+                PostError::PeerUnreachable(format!(
+                    "{}: no signed discovery received from it yet",
+                    node_id
+                ))
+            })
+    }
 
-        stream
-            .write_all(b"\n")
-            .await
-            .map_err(|e| PostError::Network(format!("Failed to write newline: {}", e)))?;
+    /// Where to reach `node_ip`: the endpoint it advertised, or its IP on our port
+    fn endpoint_for(&self, node_ip: &str) -> Result<SocketAddr> {
+        if let Some(endpoint) = self
+            .peer_endpoints
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(node_ip)
+        {
+            return Ok(*endpoint);
+        }
 
-        stream
-            .shutdown()
-            .await
-            .map_err(|e| PostError::Network(format!("Failed to shutdown connection: {}", e)))?;
+        let ip: IpAddr = node_ip
+            .parse()
+            .map_err(|_| PostError::Network(format!("Invalid node address: {}", node_ip)))?;
+        Ok(SocketAddr::new(ip, self.port))
+    }
 
-        Ok(())
+    /// Send an encoded message to `node`, after the Taildrop file it announces if there
+    /// is one
+    async fn send_to_peer<'a>(
+        &self,
+        node: &'a String,
+        frame: Result<Bytes>,
+        format: WireFormat,
+        taildrop_file: Option<&Path>,
+    ) -> (&'a String, Result<()>) {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => return (node, Err(e)),
+        };
+        if let Some(path) = taildrop_file {
+            if let Err(e) = taildrop::send_file(path, node).await {
+                return (node, Err(e));
+            }
+        }
+        (node, self.send_to_node(node, &frame, format).await)
     }
-}
 
-#[async_trait]
-impl Transport for TailscaleTransport {
-    async fn send_message(&self, message: PostMessage) -> Result<()> {
+    async fn send_to_node(&self, node_ip: &str, frame: &[u8], format: WireFormat) -> Result<()> {
+        debug!(
+            "Sending message to {}: {} bytes ({})",
+            node_ip,
+            frame.len(),
+            format
+        );
+
+        let addr = self.endpoint_for(node_ip)?;
+        // A black-holed peer would otherwise hold the send until the OS gives up
+        let mut stream = tokio::time::timeout(self.connect_timeout, TcpStream::connect(addr))
+            .await
+            .map_err(|_| {
+                PostError::Timeout(format!(
+                    "connecting to {} took over {:?}",
+                    addr, self.connect_timeout
+                ))
+            })?
+            .map_err(|e| PostError::PeerUnreachable(format!("{}: {}", addr, e)))?;
+
+        let write = async {
+            stream
+                .write_all(frame)
+                .await
+                .map_err(|e| PostError::Network(format!("Failed to write message: {}", e)))?;
+            stream
+                .shutdown()
+                .await
+                .map_err(|e| PostError::Network(format!("Failed to shutdown connection: {}", e)))
+        };
+        tokio::time::timeout(self.send_timeout, write)
+            .await
+            .map_err(|_| {
+                PostError::Timeout(format!(
+                    "sending to {} took over {:?}",
+                    addr, self.send_timeout
+                ))
+            })?
+    }
+
+    /// Online peers in the status, each with whether `network.peer_tags` and
+    /// `sync.allowed_tailnets` let us sync with it, recording both for later checks
+    async fn read_online_peers(&self) -> Result<Vec<(TailnetPeer, bool)>> {
         if !self.is_tailscale_connected().await? {
             return Err(PostError::Tailscale(
-                "Cannot send message: Tailscale not connected".to_string(),
+                "Tailscale not connected or running".to_string(),
             ));
         }
 
-        let nodes = self.get_tailnet_nodes().await?;
-        let mut errors = vec![];
+        // IPs, tags, MagicDNS name, hostname and whether it is online, for each peer
+        type StatusPeer = (Vec<IpAddr>, Vec<String>, String, String, bool);
+        let (own_dns_name, peers): (String, Vec<StatusPeer>) =
+            match &self.client {
+                TailscaleClient::Unix(local_api) => {
+                    let status = local_api.status().await.map_err(|e| {
+                        PostError::Tailscale(format!("Failed to get status: {}", e))
+                    })?;
+                    let peers = status
+                        .peer
+                        .into_values()
+                        .map(|peer| {
+                            let online = peer.online;
+                            (
+                                peer.tailscale_ips,
+                                peer.tags,
+                                peer.dnsname,
+                                peer.hostname,
+                                online,
+                            )
+                        })
+                        .collect();
+                    (status.self_status.dnsname, peers)
+                }
+                TailscaleClient::Tcp(tcp_client) => {
+                    let status = tcp_client.status().await.map_err(|e| {
+                        PostError::Tailscale(format!("Failed to get status: {}", e))
+                    })?;
+                    let peers = status
+                        .peer
+                        .into_values()
+                        .map(|peer| {
+                            let ips = parse_ips(&peer.tailscale_ips);
+                            (ips, peer.tags, peer.dns_name, peer.host_name, peer.online)
+                        })
+                        .collect();
+                    (status.self_status.dns_name, peers)
+                }
+            };
 
-        if nodes.is_empty() {
-            debug!("No online Tailscale nodes found to send message to");
-            return Ok(());
+        self.peer_tailnets.record_own(&own_dns_name);
+        let mut online = Vec::new();
+        for (ips, tags, dns_name, host_name, is_online) in peers {
+            self.peer_tags.record(&ips, &tags);
+            self.peer_tailnets.record(&ips, &dns_name);
+            let (true, Some(ip)) = (is_online, self.ip_preference.pick(&ips)) else {
+                continue;
+            };
+            let address = ip.to_string();
+            let allowed = self.may_sync_with(&address);
+            if !allowed {
+                debug!(
+                    "Not syncing with {}: not allowed by network.peer_tags or sync.allowed_tailnets",
+                    address
+                );
+            }
+            let name = friendly_node_name(&dns_name, &host_name).unwrap_or_else(|| address.clone());
+            online.push((TailnetPeer { name, address }, allowed));
+        }
+        Ok(online)
+    }
+
+    /// Whether `network.peer_tags` and `sync.allowed_tailnets` let us sync with `address`
+    fn may_sync_with(&self, address: &str) -> bool {
+        let canonical = address
+            .parse::<IpAddr>()
+            .map_or_else(|_| address.to_string(), |ip| ip.to_canonical().to_string());
+        self.peer_tags.allows(&canonical) && self.peer_tailnets.allows(&canonical)
+    }
+
+    /// Send `message` to each of `nodes`, skipping those that lack what it needs or
+    /// keep failing
+    async fn send_to_nodes(&self, mut nodes: Vec<String>, message: PostMessage) -> Result<()> {
+        if let Some(capability) = Capability::required_by(&message.data) {
+            nodes.retain(|node| {
+                let supported = self.node_supports(node, capability);
+                if !supported {
+                    debug!(
+                        "Not sending {} to {}, which lacks it",
+                        capability.as_str(),
+                        node
+                    );
+                }
+                supported
+            });
         }
+        // Taildrop offers go out only after the file they announce has arrived
+        let taildrop_file = match &message.data {
+            MessageData::TaildropOffer(offer) => Some(taildrop::staging_path(&offer.file_name)?),
+            _ => None,
+        };
+
+        // Peers that keep failing would add a connect timeout to every send
+        let (targets, skipped): (Vec<&String>, Vec<&String>) =
+            nodes.iter().partition(|node| self.circuits.allow(node));
+        for node in &skipped {
+            debug!("Skipping {}, which has been unreachable", node);
+        }
+
+        // Encoded once per wire format, with every peer's send sharing the frame
+        let mut frames: HashMap<WireFormat, Bytes> = HashMap::new();
+        let mut sends = Vec::with_capacity(targets.len());
+        for node in targets {
+            let format = self.wire_format_for(node, &message);
+            let frame = match frames.get(&format) {
+                Some(frame) => Ok(frame.clone()),
+                None => encode_message(&message, format).map(|frame| {
+                    let frame = Bytes::from(frame);
+                    frames.insert(format, frame.clone());
+                    frame
+                }),
+            };
+            sends.push(self.send_to_peer(node, frame, format, taildrop_file.as_deref()));
+        }
+        let results: Vec<(&String, Result<()>)> = stream::iter(sends)
+            .buffer_unordered(self.send_concurrency)
+            .collect()
+            .await;
 
-        for node in &nodes {
-            match self.send_to_node(node, &message).await {
+        let mut errors = vec![];
+        let mut newly_unreachable = false;
+        for (node, result) in results {
+            match result {
                 Ok(()) => {
+                    self.circuits.record_success(node);
                     debug!("Successfully sent message to {}", node);
                 }
                 Err(e) => {
+                    // Only failures to reach the peer count against it, not e.g. a
+                    // Taildrop copy that tailscale refused
+                    if e.is_retryable() {
+                        newly_unreachable |= self.circuits.record_failure(node);
+                    }
                     // Only log as debug since it's expected that some nodes might not be running the daemon
                     debug!("Failed to send message to {}: {}", node, e);
-                    errors.push(e);
+                    errors.push(format!("{}: {}", node, e));
                 }
             }
         }
 
-        if !errors.is_empty() && errors.len() == nodes.len() {
-            return Err(PostError::Network(
-                "Failed to send message to any nodes".to_string(),
-            ));
+        // A peer that worked until now may have gone offline or changed address, so look
+        // again next time. Devices that never answer, e.g. for not running Post, are left
+        // to the circuit breaker rather than costing a status lookup on every send.
+        if newly_unreachable {
+            self.send_targets.invalidate();
         }
 
-        let successful_sends = nodes.len() - errors.len();
+        if let Some(path) = taildrop_file {
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                debug!(
+                    "Failed to remove staged Taildrop file {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+
+        if nodes.is_empty() {
+            debug!("No online Tailscale nodes found to send message to");
+            return Ok(());
+        }
+
+        if errors.len() + skipped.len() == nodes.len() {
+            if !skipped.is_empty() {
+                errors.push(format!("{} unreachable node(s) skipped", skipped.len()));
+            }
+            return Err(PostError::Network(format!(
+                "Failed to send message to any nodes: {}",
+                errors.join("; ")
+            )));
+        }
+
+        let successful_sends = nodes.len() - errors.len() - skipped.len();
         if successful_sends > 0 {
-            info!("Message sent to {} of {} nodes", successful_sends, nodes.len());
+            info!(
+                "Message sent to {} of {} nodes",
+                successful_sends,
+                nodes.len()
+            );
         } else {
             debug!("No nodes were reachable (this is normal if other nodes don't have the daemon running)");
         }
         Ok(())
     }
+}
 
-    async fn start_listening(&self, sender: mpsc::UnboundedSender<PostMessage>) -> Result<()> {
-        info!("Starting TCP listener on port {}", self.port);
+#[async_trait]
+impl Transport for TailscaleTransport {
+    async fn send_message(&self, message: PostMessage) -> Result<()> {
+        let nodes = match message.recipient() {
+            Some(node_id) => vec![self.address_of(node_id)?],
+            None => self.send_targets().await?,
+        };
+        self.send_to_nodes(nodes, message).await
+    }
 
-        let addr = SocketAddr::from(([0, 0, 0, 0], self.port));
-        let listener = TcpListener::bind(addr).await.map_err(|e| {
-            PostError::Network(format!("Failed to bind to port {}: {}", self.port, e))
-        })?;
+    async fn send_message_to(&self, address: &str, message: PostMessage) -> Result<()> {
+        if !self.may_sync_with(address) {
+            return Err(PostError::Filtered(format!(
+                "{} is not allowed by network.peer_tags or sync.allowed_tailnets",
+                address
+            )));
+        }
+        self.send_to_nodes(vec![address.to_string()], message).await
+    }
 
-        loop {
-            match listener.accept().await {
-                Ok((stream, addr)) => {
-                    debug!("Accepted connection from {}", addr);
-                    let sender = sender.clone();
+    fn use_peer_directory(&self, directory: Weak<PeerDirectory>) {
+        let _ = self.peer_directory.set(directory);
+    }
 
-                    tokio::spawn(async move {
-                        let mut buffer = Vec::new();
-                        let mut temp_buf = [0u8; 1024];
-
-                        loop {
-                            match stream.try_read(&mut temp_buf) {
-                                Ok(0) => break, // EOF
-                                Ok(n) => {
-                                    buffer.extend_from_slice(&temp_buf[..n]);
-
-                                    // Look for complete messages (newline-delimited)
-                                    while let Some(newline_pos) =
-                                        buffer.iter().position(|&b| b == b'\n')
-                                    {
-                                        let message_bytes =
-                                            buffer.drain(..newline_pos + 1).collect::<Vec<u8>>();
-                                        let message_str = String::from_utf8_lossy(&message_bytes);
-                                        let trimmed = message_str.trim();
-
-                                        if !trimmed.is_empty() {
-                                            match serde_json::from_str::<PostMessage>(trimmed) {
-                                                Ok(message) => {
-                                                    debug!(
-                                                        "Received message: {:?}",
-                                                        message.message_type
-                                                    );
-                                                    if let Err(e) = sender.send(message) {
-                                                        error!("Failed to forward message: {}", e);
-                                                        return;
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    warn!("Failed to parse message: {}", e);
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                                    // No data available right now, yield and try again
-                                    tokio::task::yield_now().await;
-                                }
-                                Err(e) => {
-                                    debug!("Connection error: {}", e);
-                                    break;
-                                }
-                            }
-                        }
-                    });
-                }
-                Err(e) => {
-                    error!("Failed to accept connection: {}", e);
-                }
+    async fn start_listening(&self, sender: InboxSender) -> Result<()> {
+        let mut accept_tasks = tokio::task::JoinSet::new();
+        // Shared by every listener
+        let connection_slots = Arc::new(ConnectionSlots::new(self.max_connections));
+        // Learn peers' tags and tailnets now, or their connections are judged without them
+        // until the first send
+        if let Err(e) = self.get_tailnet_nodes().await {
+            debug!("Couldn't read peers before listening: {}", e);
+        }
+
+        let listeners = self.bind_listeners().await;
+        self.listen_status.send_replace(Some(
+            listeners.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+        ));
+        for listener in listeners? {
+            if let Ok(addr) = listener.local_addr() {
+                info!("Starting TCP listener on {}", addr);
             }
+            accept_tasks.spawn(Self::accept_connections(
+                listener,
+                sender.clone(),
+                Arc::clone(&self.peer_formats),
+                Arc::clone(&self.peer_capabilities),
+                Arc::clone(&self.peer_endpoints),
+                Arc::clone(&self.peer_addresses),
+                self.port,
+                Arc::clone(&connection_slots),
+                Arc::clone(&self.peer_tags),
+                Arc::clone(&self.peer_tailnets),
+            ));
+        }
+
+        // Accept loops only end by panicking; dropping the set stops the others
+        accept_tasks.join_next().await;
+        let stopped = "TCP listener stopped".to_string();
+        self.listen_status.send_replace(Some(Err(stopped.clone())));
+        Err(PostError::Network(stopped))
+    }
+
+    fn is_listening(&self) -> bool {
+        matches!(*self.listen_status.borrow(), Some(Ok(())))
+    }
+
+    async fn wait_listening(&self) -> Result<()> {
+        let mut status = self.listen_status.subscribe();
+        let bound = status
+            .wait_for(Option::is_some)
+            .await
+            .map_err(|_| PostError::Network("TCP listener went away".to_string()))?
+            .clone();
+        match bound {
+            Some(Err(e)) => Err(PostError::Network(e)),
+            _ => Ok(()),
         }
     }
 
@@ -609,55 +1723,84 @@ impl Transport for TailscaleTransport {
     }
 
     async fn get_tailnet_nodes(&self) -> Result<Vec<String>> {
+        let nodes: Vec<String> = self
+            .read_online_peers()
+            .await?
+            .into_iter()
+            .filter(|(_, allowed)| *allowed)
+            .map(|(peer, _)| peer.address)
+            .collect();
+        info!("Found {} online Tailscale nodes", nodes.len());
+        Ok(nodes)
+    }
+
+    async fn get_node_name(&self) -> Result<String> {
         if !self.is_tailscale_connected().await? {
             return Err(PostError::Tailscale(
                 "Tailscale not connected or running".to_string(),
             ));
         }
 
-        let mut nodes = Vec::new();
+        let (id, name) =
+            match &self.client {
+                TailscaleClient::Unix(local_api) => {
+                    let status = local_api.status().await.map_err(|e| {
+                        PostError::Tailscale(format!("Failed to get status: {}", e))
+                    })?;
+                    let me = status.self_status;
+                    (me.id, friendly_node_name(&me.dnsname, &me.hostname))
+                }
+                TailscaleClient::Tcp(tcp_client) => {
+                    let status = tcp_client.status().await.map_err(|e| {
+                        PostError::Tailscale(format!("Failed to get status: {}", e))
+                    })?;
+                    let me = status.self_status;
+                    (me.id, friendly_node_name(&me.dns_name, &me.host_name))
+                }
+            };
 
-        match &self.client {
-            TailscaleClient::Unix(local_api) => {
-                let status = local_api
-                    .status()
-                    .await
-                    .map_err(|e| PostError::Tailscale(format!("Failed to get status: {}", e)))?;
+        Ok(name.unwrap_or(id))
+    }
 
-                for (node_key, peer) in status.peer {
-                    info!(
-                        "Node {}: online={}, ips={:?}",
-                        node_key, peer.online, peer.tailscale_ips
-                    );
-                    if peer.online && !peer.tailscale_ips.is_empty() {
-                        // Use the first Tailscale IP
-                        nodes.push(peer.tailscale_ips[0].to_string());
-                        info!("Added node {} to send list", peer.tailscale_ips[0]);
-                    }
+    async fn get_dns_name(&self) -> Result<String> {
+        let dns_name =
+            match &self.client {
+                TailscaleClient::Unix(local_api) => {
+                    let status = local_api.status().await.map_err(|e| {
+                        PostError::Tailscale(format!("Failed to get status: {}", e))
+                    })?;
+                    status.self_status.dnsname
                 }
-            }
-            TailscaleClient::Tcp(tcp_client) => {
-                let status = tcp_client
-                    .status()
-                    .await
-                    .map_err(|e| PostError::Tailscale(format!("Failed to get status: {}", e)))?;
-
-                for (node_key, peer) in status.peer {
-                    info!(
-                        "Node {}: online={}, ips={:?}",
-                        node_key, peer.online, peer.tailscale_ips
-                    );
-                    if peer.online && !peer.tailscale_ips.is_empty() {
-                        // Use the first Tailscale IP
-                        nodes.push(peer.tailscale_ips[0].to_string());
-                        info!("Added node {} to send list", peer.tailscale_ips[0]);
-                    }
+                TailscaleClient::Tcp(tcp_client) => {
+                    let status = tcp_client.status().await.map_err(|e| {
+                        PostError::Tailscale(format!("Failed to get status: {}", e))
+                    })?;
+                    status.self_status.dns_name
                 }
-            }
+            };
+
+        let dns_name = dns_name.trim_end_matches('.');
+        if dns_name.is_empty() {
+            return Err(PostError::Tailscale(
+                "MagicDNS name unavailable; is MagicDNS enabled?".to_string(),
+            ));
         }
+        Ok(dns_name.to_string())
+    }
 
-        info!("Found {} online Tailscale nodes", nodes.len());
-        Ok(nodes)
+    fn peer_circuits(&self) -> Vec<PeerCircuit> {
+        self.circuits.circuits()
+    }
+
+    async fn get_tailnet_peers(&self) -> Result<Vec<TailnetPeer>> {
+        let mut peers: Vec<TailnetPeer> = self
+            .read_online_peers()
+            .await?
+            .into_iter()
+            .map(|(peer, _)| peer)
+            .collect();
+        peers.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(peers)
     }
 
     async fn is_connected(&self) -> Result<bool> {
@@ -665,41 +1808,414 @@ impl Transport for TailscaleTransport {
     }
 }
 
-pub struct MockTransport {
-    node_id: String,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inbox::{self, DEFAULT_CAPACITY};
+
+    #[test]
+    fn test_http_response_body() {
+        let ok = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"BackendState\":\"Running\"}";
+        assert_eq!(
+            http_response_body(ok).unwrap(),
+            b"{\"BackendState\":\"Running\"}".to_vec()
+        );
 
-impl MockTransport {
-    pub fn new(node_id: String) -> Self {
-        Self { node_id }
+        let denied = b"HTTP/1.0 403 Forbidden\r\n\r\naccess denied";
+        assert!(matches!(
+            http_response_body(denied),
+            Err(TcpApiError::Response(_))
+        ));
+        assert!(http_response_body(b"HTTP/1.0 200 OK\r\n").is_err());
     }
-}
 
-#[async_trait]
-impl Transport for MockTransport {
-    async fn send_message(&self, message: PostMessage) -> Result<()> {
-        debug!(
-            "Mock transport: would send message {:?}",
-            message.message_type
+    #[test]
+    fn test_peer_tags_refuse_unknown_peers_under_a_policy() {
+        let ip: IpAddr = "100.64.0.2".parse().unwrap();
+        assert!(PeerTags::default().allows("100.64.0.9"));
+
+        let peer_tags = PeerTags {
+            policy: TagPolicy {
+                require: vec!["tag:personal".to_string()],
+                exclude: vec![],
+            },
+            tags: RwLock::default(),
+        };
+        assert!(!peer_tags.allows("100.64.0.2"));
+        peer_tags.record(&[ip], &["tag:personal".to_string()]);
+        assert!(peer_tags.allows("100.64.0.2"));
+        peer_tags.record(&[ip], &["tag:work".to_string()]);
+        assert!(!peer_tags.allows("100.64.0.2"));
+    }
+
+    #[test]
+    fn test_peer_tailnets_come_from_the_status_not_the_peer() {
+        let shared: IpAddr = "100.64.0.2".parse().unwrap();
+        let own: IpAddr = "100.64.0.3".parse().unwrap();
+        let tailnets = PeerTailnets::default();
+        // Nothing to compare with until this node's tailnet is known
+        assert!(tailnets.allows("100.64.0.2"));
+
+        tailnets.record_own("laptop.work.ts.net.");
+        tailnets.record(&[shared], "nas.home.ts.net.");
+        tailnets.record(&[own], "desktop.work.ts.net.");
+        assert!(!tailnets.allows("100.64.0.2"));
+        assert!(tailnets.allows("100.64.0.3"));
+        assert!(tailnets.allows("100.64.0.9"));
+
+        let tailnets = PeerTailnets {
+            allowed: vec!["home.ts.net".to_string()],
+            ..PeerTailnets::default()
+        };
+        tailnets.record_own("laptop.work.ts.net.");
+        tailnets.record(&[shared], "nas.home.ts.net.");
+        assert!(tailnets.allows("100.64.0.2"));
+        // Peers of unknown tailnets are refused once others are allowed
+        assert!(!tailnets.allows("100.64.0.9"));
+    }
+
+    #[test]
+    fn test_tailnet_of_magicdns_name() {
+        assert_eq!(
+            tailnet_of("mac-studio.Tail1234.ts.net.").as_deref(),
+            Some("tail1234.ts.net")
         );
-        Ok(())
+        assert_eq!(tailnet_of("mac-studio"), None);
+        assert_eq!(tailnet_of("mac-studio."), None);
     }
 
-    async fn start_listening(&self, _sender: mpsc::UnboundedSender<PostMessage>) -> Result<()> {
-        debug!("Mock transport: listening (no-op)");
-        tokio::time::sleep(std::time::Duration::from_secs(u64::MAX)).await;
-        Ok(())
+    #[test]
+    fn test_status_cache_expires_and_invalidates() {
+        let cache = StatusCache::default();
+        assert_eq!(cache.get(STATUS_CACHE_TTL), None);
+
+        cache.put(vec!["100.64.0.2".to_string()]);
+        assert_eq!(
+            cache.get(STATUS_CACHE_TTL),
+            Some(vec!["100.64.0.2".to_string()])
+        );
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(cache.get(Duration::from_millis(1)), None);
+
+        cache.invalidate();
+        assert_eq!(cache.get(STATUS_CACHE_TTL), None);
     }
 
-    async fn get_node_id(&self) -> Result<String> {
-        Ok(self.node_id.clone())
+    #[test]
+    fn test_ipv6_peers_get_bracketed_endpoints() {
+        let transport = TailscaleTransport::new(8412, Some("/nonexistent/tailscaled.sock"));
+
+        let endpoint = transport.endpoint_for("fd7a:115c:a1e0::1").unwrap();
+        assert!(endpoint.is_ipv6());
+        assert_eq!(endpoint.to_string(), "[fd7a:115c:a1e0::1]:8412");
+        assert_eq!(
+            transport.endpoint_for("100.64.0.2").unwrap().to_string(),
+            "100.64.0.2:8412"
+        );
+        assert!(transport.endpoint_for("[fd7a::1]:8412").is_err());
     }
 
-    async fn get_tailnet_nodes(&self) -> Result<Vec<String>> {
-        Ok(vec![])
+    #[test]
+    fn test_advertised_endpoints_stay_in_the_tailnet() {
+        let source = IpAddr::from([100, 64, 0, 2]);
+        let discovery = |address: &str| NodeDiscoveryData {
+            source_node: "node-a".to_string(),
+            timestamp: 0,
+            public_key: [1; 32],
+            signing_public_key: [2; 32],
+            wire_formats: Vec::new(),
+            node_name: None,
+            wants_reply: false,
+            version: None,
+            capabilities: Vec::new(),
+            address: Some(address.to_string()),
+            port: Some(9000),
+        };
+
+        assert_eq!(
+            advertised_endpoint(source, &discovery("100.64.0.2"), 8412),
+            Some("100.64.0.2:9000".parse().unwrap())
+        );
+        assert_eq!(
+            advertised_endpoint(source, &discovery("fd7a:115c:a1e0::2"), 8412),
+            Some("[fd7a:115c:a1e0::2]:9000".parse().unwrap())
+        );
+        assert_eq!(
+            advertised_endpoint(source, &discovery("10.0.0.1"), 8412),
+            None
+        );
+        assert_eq!(
+            advertised_endpoint(source, &discovery("100.128.0.1"), 8412),
+            None
+        );
+        assert_eq!(
+            advertised_endpoint(source, &discovery("fd7a::1"), 8412),
+            None
+        );
+
+        let outside = IpAddr::from([192, 168, 1, 5]);
+        assert_eq!(
+            advertised_endpoint(outside, &discovery("192.168.1.5"), 8412),
+            Some("192.168.1.5:9000".parse().unwrap())
+        );
     }
 
-    async fn is_connected(&self) -> Result<bool> {
-        Ok(true) // Mock transport is always "connected"
+    #[tokio::test]
+    async fn test_unverified_discovery_advertises_no_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, mut received) = inbox::channel(DEFAULT_CAPACITY);
+        let endpoints: Arc<RwLock<HashMap<String, SocketAddr>>> = Arc::default();
+        let server = tokio::spawn({
+            let endpoints = Arc::clone(&endpoints);
+            async move {
+                let (stream, peer) = listener.accept().await.unwrap();
+                TailscaleTransport::handle_connection(
+                    stream,
+                    peer,
+                    sender,
+                    Arc::default(),
+                    Arc::default(),
+                    endpoints,
+                    Arc::default(),
+                    addr.port(),
+                )
+                .await
+            }
+        });
+
+        let message = PostMessage {
+            version: 1,
+            message_type: crate::MessageType::NodeDiscovery,
+            data: MessageData::NodeDiscovery(NodeDiscoveryData {
+                source_node: "node-a".to_string(),
+                timestamp: 1,
+                public_key: [1; 32],
+                signing_public_key: [2; 32],
+                wire_formats: Vec::new(),
+                node_name: None,
+                wants_reply: false,
+                version: None,
+                capabilities: Vec::new(),
+                address: Some("100.64.0.9".to_string()),
+                port: None,
+            }),
+            signature: vec![0; 64],
+        };
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(&encode_message(&message, WireFormat::Json).unwrap())
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+
+        // Still forwarded, for SyncManager to reject
+        received.recv().await.unwrap();
+        server.await.unwrap();
+        assert!(endpoints.read().unwrap().is_empty());
+    }
+
+    /// Reports a fixed list of online peers
+    struct ListedPeers(Vec<String>);
+
+    #[async_trait]
+    impl Transport for ListedPeers {
+        async fn send_message(&self, _message: PostMessage) -> Result<()> {
+            Ok(())
+        }
+
+        async fn start_listening(&self, _sender: InboxSender) -> Result<()> {
+            Ok(())
+        }
+
+        async fn get_node_id(&self) -> Result<String> {
+            Ok("node-a".to_string())
+        }
+
+        async fn get_tailnet_nodes(&self) -> Result<Vec<String>> {
+            Ok(self.0.clone())
+        }
+
+        async fn is_connected(&self) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sends_read_online_peers_from_the_directory() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let directory = Arc::new(PeerDirectory::new(Arc::new(ListedPeers(vec![
+            "127.0.0.1".to_string()
+        ]))));
+        directory.refresh().await.unwrap();
+        // No tailscaled answers here, so the peers can only come from the directory
+        let transport = TailscaleTransport::new(port, Some("/nonexistent/tailscaled.sock"));
+        transport.use_peer_directory(Arc::downgrade(&directory));
+        assert_eq!(transport.send_targets().await.unwrap(), ["127.0.0.1"]);
+
+        let heartbeat = PostMessage {
+            version: 1,
+            message_type: crate::MessageType::Heartbeat,
+            data: MessageData::Heartbeat(crate::HeartbeatData {
+                source_node: "node-a".to_string(),
+                timestamp: 1,
+            }),
+            signature: vec![0; 64],
+        };
+        transport.send_message(heartbeat.clone()).await.unwrap();
+        transport
+            .send_message_to("127.0.0.1", heartbeat)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            tokio::time::timeout(Duration::from_secs(1), listener.accept())
+                .await
+                .expect("both sends should reach the peer")
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explicit_bind_address_skips_tailscale() {
+        // No tailscaled answers here, so only the configured address can be bound
+        let transport = TailscaleTransport::new(0, Some("/nonexistent/tailscaled.sock"))
+            .with_bind_address(Some(IpAddr::from([127, 0, 0, 1])));
+
+        let listeners = transport.bind_listeners().await.unwrap();
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(
+            listeners[0].local_addr().unwrap().ip(),
+            IpAddr::from([127, 0, 0, 1])
+        );
+        assert!(
+            TailscaleTransport::new(0, Some("/nonexistent/tailscaled.sock"))
+                .bind_listeners()
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_waiting_for_the_listeners_reports_bind_errors() {
+        let (sender, _received) = inbox::channel(DEFAULT_CAPACITY);
+        let transport = Arc::new(
+            TailscaleTransport::new(0, Some("/nonexistent/tailscaled.sock"))
+                .with_bind_address(Some(IpAddr::from([127, 0, 0, 1]))),
+        );
+        tokio::spawn({
+            let transport = Arc::clone(&transport);
+            let sender = sender.clone();
+            async move { transport.start_listening(sender).await }
+        });
+        transport.wait_listening().await.unwrap();
+        assert!(transport.is_listening());
+
+        // Without tailscaled there is no address to listen on
+        let unbound = Arc::new(TailscaleTransport::new(
+            0,
+            Some("/nonexistent/tailscaled.sock"),
+        ));
+        tokio::spawn({
+            let unbound = Arc::clone(&unbound);
+            async move { unbound.start_listening(sender).await }
+        });
+        assert!(unbound.wait_listening().await.is_err());
+        assert!(!unbound.is_listening());
+    }
+
+    #[tokio::test]
+    async fn test_connection_handler_forwards_frames_until_eof() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, mut received) = inbox::channel(DEFAULT_CAPACITY);
+        let server = tokio::spawn(async move {
+            let (stream, peer) = listener.accept().await.unwrap();
+            TailscaleTransport::handle_connection(
+                stream,
+                peer,
+                sender,
+                Arc::default(),
+                Arc::default(),
+                Arc::default(),
+                Arc::default(),
+                addr.port(),
+            )
+            .await
+        });
+
+        let message = PostMessage {
+            version: 1,
+            message_type: crate::MessageType::Heartbeat,
+            data: MessageData::Heartbeat(crate::HeartbeatData {
+                source_node: "node-a".to_string(),
+                timestamp: 1,
+            }),
+            signature: vec![0; 64],
+        };
+        let frame = encode_message(&message, WireFormat::Json).unwrap();
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        // Split the frame so the handler has to wait for the rest
+        client.write_all(&frame[..10]).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        client.write_all(&frame[10..]).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let received = received.recv().await.unwrap();
+        assert!(matches!(received.data, MessageData::Heartbeat(_)));
+        tokio::time::timeout(Duration::from_secs(1), server)
+            .await
+            .expect("handler should stop at EOF")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connections_past_the_limit_are_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, _received) = inbox::channel(DEFAULT_CAPACITY);
+        tokio::spawn(TailscaleTransport::accept_connections(
+            listener,
+            sender,
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            addr.port(),
+            Arc::new(ConnectionSlots::new(1)),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let mut second = TcpStream::connect(addr).await.unwrap();
+
+        let mut buf = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_secs(1), second.read(&mut buf))
+            .await
+            .expect("connection past the limit should be closed");
+        assert!(matches!(read, Ok(0) | Err(_)));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), first.read(&mut buf))
+                .await
+                .is_err(),
+            "connection within the limit should stay open"
+        );
+    }
+
+    #[test]
+    fn test_one_source_cannot_take_every_connection_slot() {
+        let slots = ConnectionSlots::new(64);
+        let flooder = IpAddr::from([100, 64, 0, 9]);
+
+        let held: Vec<_> = std::iter::from_fn(|| slots.try_acquire(flooder))
+            .take(100)
+            .collect();
+        assert_eq!(held.len(), MAX_CONNECTIONS_PER_SOURCE);
+        assert!(slots.try_acquire(IpAddr::from([100, 64, 0, 2])).is_some());
+
+        drop(held);
+        assert!(slots.try_acquire(flooder).is_some());
     }
 }
@@ -0,0 +1,531 @@
+use crate::{MessageType, PostError, PostMessage, Result};
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Largest encoded message accepted from the network (in bytes)
+///
+/// Clipboard content is capped at 1MB by default, but JSON escaping can expand it several times.
+pub const MAX_MESSAGE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Deepest nesting accepted; a valid `PostMessage` never goes beyond 4 levels
+pub const MAX_NESTING_DEPTH: usize = 8;
+
+/// First byte of a length-prefixed binary frame; JSON frames always start with `{` or whitespace
+pub const BINARY_FRAME_MAGIC: u8 = 0xB1;
+
+/// Magic byte followed by a big-endian `u32` payload length
+const BINARY_HEADER_LEN: usize = 5;
+
+/// Encoding used for a message on the wire
+///
+/// Signatures are always computed over the JSON encoding, so the wire format can change
+/// per peer without affecting verification.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WireFormat {
+    /// Newline-delimited JSON, understood by every node
+    #[default]
+    Json,
+    /// Length-prefixed MessagePack, smaller than JSON
+    ///
+    /// Fields are written by name, so they can be added or skipped without shifting the
+    /// others. Frames from older builds, which wrote them by position, still decode.
+    MessagePack,
+}
+
+impl WireFormat {
+    /// Formats this build understands, most preferred first
+    pub const SUPPORTED: [WireFormat; 2] = [WireFormat::MessagePack, WireFormat::Json];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WireFormat::Json => "json",
+            WireFormat::MessagePack => "msgpack",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(WireFormat::Json),
+            "msgpack" => Some(WireFormat::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// Names to advertise in node discovery
+    pub fn advertised() -> Vec<String> {
+        Self::SUPPORTED
+            .iter()
+            .map(|format| format.as_str().to_string())
+            .collect()
+    }
+
+    /// Most preferred format that a peer advertising `peer_formats` also understands
+    pub fn negotiate(peer_formats: &[String]) -> Self {
+        Self::SUPPORTED
+            .into_iter()
+            .find(|format| peer_formats.iter().any(|name| name == format.as_str()))
+            .unwrap_or(WireFormat::Json)
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Encode a message as a complete wire frame, including its delimiter or length prefix
+pub fn encode_message(message: &PostMessage, format: WireFormat) -> Result<Vec<u8>> {
+    match format {
+        WireFormat::Json => {
+            let mut frame = serde_json::to_vec(message).map_err(|e| {
+                PostError::Serialization(format!("Failed to serialize message: {}", e))
+            })?;
+            frame.push(b'\n');
+            Ok(frame)
+        }
+        WireFormat::MessagePack => {
+            let payload = rmp_serde::to_vec_named(message).map_err(|e| {
+                PostError::Serialization(format!("Failed to serialize message: {}", e))
+            })?;
+            if payload.len() > MAX_MESSAGE_SIZE {
+                return Err(DecodeError::TooLarge {
+                    size: payload.len(),
+                    max: MAX_MESSAGE_SIZE,
+                }
+                .into());
+            }
+
+            let mut frame = Vec::with_capacity(BINARY_HEADER_LEN + payload.len());
+            frame.push(BINARY_FRAME_MAGIC);
+            frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            frame.extend_from_slice(&payload);
+            Ok(frame)
+        }
+    }
+}
+
+/// Split the next complete frame off the front of a stream buffer
+///
+/// Returns `None` until a whole frame has arrived. An oversized binary header is reported
+/// immediately so the connection can be dropped without buffering the payload.
+pub fn take_frame(buffer: &mut Vec<u8>) -> Option<std::result::Result<Vec<u8>, DecodeError>> {
+    if buffer.first() == Some(&BINARY_FRAME_MAGIC) {
+        if buffer.len() < BINARY_HEADER_LEN {
+            return None;
+        }
+
+        let len = binary_payload_len(buffer);
+        if len > MAX_MESSAGE_SIZE {
+            return Some(Err(DecodeError::TooLarge {
+                size: len,
+                max: MAX_MESSAGE_SIZE,
+            }));
+        }
+        if buffer.len() < BINARY_HEADER_LEN + len {
+            return None;
+        }
+
+        return Some(Ok(buffer.drain(..BINARY_HEADER_LEN + len).collect()));
+    }
+
+    let newline_pos = buffer.iter().position(|&b| b == b'\n')?;
+    Some(Ok(buffer.drain(..newline_pos + 1).collect()))
+}
+
+fn binary_payload_len(frame: &[u8]) -> usize {
+    u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize
+}
+
+/// Why an incoming message was rejected before reaching the sync layer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    TooLarge {
+        size: usize,
+        max: usize,
+    },
+    TooDeep {
+        max: usize,
+    },
+    InvalidUtf8,
+    Malformed(String),
+    /// A message type from a newer build, which this one can't handle and ignores
+    Unsupported(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::TooLarge { size, max } => {
+                write!(
+                    f,
+                    "message of {} bytes exceeds limit of {} bytes",
+                    size, max
+                )
+            }
+            DecodeError::TooDeep { max } => {
+                write!(f, "message nesting exceeds limit of {} levels", max)
+            }
+            DecodeError::InvalidUtf8 => write!(f, "message is not valid UTF-8"),
+            DecodeError::Malformed(e) => write!(f, "malformed message: {}", e),
+            DecodeError::Unsupported(message_type) => {
+                write!(f, "unsupported message type {}", message_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for PostError {
+    fn from(e: DecodeError) -> Self {
+        PostError::Serialization(e.to_string())
+    }
+}
+
+/// Counters for messages seen by `decode_message`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeMetrics {
+    pub decoded: u64,
+    pub too_large: u64,
+    pub too_deep: u64,
+    pub invalid_utf8: u64,
+    pub malformed: u64,
+    /// Messages of types only newer builds know, which aren't counted as rejected
+    pub unsupported: u64,
+}
+
+impl DecodeMetrics {
+    /// Total number of rejected messages
+    pub fn rejected(&self) -> u64 {
+        self.too_large + self.too_deep + self.invalid_utf8 + self.malformed
+    }
+}
+
+static DECODED: AtomicU64 = AtomicU64::new(0);
+static TOO_LARGE: AtomicU64 = AtomicU64::new(0);
+static TOO_DEEP: AtomicU64 = AtomicU64::new(0);
+static INVALID_UTF8: AtomicU64 = AtomicU64::new(0);
+static MALFORMED: AtomicU64 = AtomicU64::new(0);
+static UNSUPPORTED: AtomicU64 = AtomicU64::new(0);
+
+/// Snapshot of the process-wide decode counters
+pub fn decode_metrics() -> DecodeMetrics {
+    DecodeMetrics {
+        decoded: DECODED.load(Ordering::Relaxed),
+        too_large: TOO_LARGE.load(Ordering::Relaxed),
+        too_deep: TOO_DEEP.load(Ordering::Relaxed),
+        invalid_utf8: INVALID_UTF8.load(Ordering::Relaxed),
+        malformed: MALFORMED.load(Ordering::Relaxed),
+        unsupported: UNSUPPORTED.load(Ordering::Relaxed),
+    }
+}
+
+/// Decode an untrusted wire frame (JSON or binary) into a `PostMessage`
+///
+/// Size and nesting are checked before any allocation-heavy parsing happens.
+pub fn decode_message(bytes: &[u8]) -> std::result::Result<PostMessage, DecodeError> {
+    let result = decode_unrecorded(bytes);
+
+    let counter = match &result {
+        Ok(_) => &DECODED,
+        Err(DecodeError::TooLarge { .. }) => &TOO_LARGE,
+        Err(DecodeError::TooDeep { .. }) => &TOO_DEEP,
+        Err(DecodeError::InvalidUtf8) => &INVALID_UTF8,
+        Err(DecodeError::Malformed(_)) => &MALFORMED,
+        Err(DecodeError::Unsupported(_)) => &UNSUPPORTED,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+
+    result
+}
+
+/// Record a frame that was dropped for exceeding `MAX_MESSAGE_SIZE` before it was complete
+pub fn record_oversized_frame() {
+    TOO_LARGE.fetch_add(1, Ordering::Relaxed);
+}
+
+fn decode_unrecorded(bytes: &[u8]) -> std::result::Result<PostMessage, DecodeError> {
+    if bytes.len() > MAX_MESSAGE_SIZE + BINARY_HEADER_LEN {
+        return Err(DecodeError::TooLarge {
+            size: bytes.len(),
+            max: MAX_MESSAGE_SIZE,
+        });
+    }
+
+    if bytes.first() == Some(&BINARY_FRAME_MAGIC) {
+        return decode_binary(bytes);
+    }
+
+    if bytes.len() > MAX_MESSAGE_SIZE {
+        return Err(DecodeError::TooLarge {
+            size: bytes.len(),
+            max: MAX_MESSAGE_SIZE,
+        });
+    }
+
+    let text = std::str::from_utf8(bytes).map_err(|_| DecodeError::InvalidUtf8)?;
+    check_depth(text.as_bytes(), MAX_NESTING_DEPTH)?;
+
+    serde_json::from_str::<PostMessage>(text.trim()).map_err(|e| {
+        match serde_json::from_str::<Envelope>(text.trim()) {
+            Ok(envelope) => envelope.unsupported(e.to_string()),
+            Err(_) => DecodeError::Malformed(e.to_string()),
+        }
+    })
+}
+
+fn decode_binary(frame: &[u8]) -> std::result::Result<PostMessage, DecodeError> {
+    if frame.len() < BINARY_HEADER_LEN
+        || binary_payload_len(frame) != frame.len() - BINARY_HEADER_LEN
+    {
+        return Err(DecodeError::Malformed(
+            "binary frame length does not match header".to_string(),
+        ));
+    }
+
+    let payload = &frame[BINARY_HEADER_LEN..];
+    let mut deserializer = rmp_serde::Deserializer::from_read_ref(payload);
+    deserializer.set_max_depth(MAX_NESTING_DEPTH);
+    PostMessage::deserialize(&mut deserializer).map_err(|e| match e {
+        rmp_serde::decode::Error::DepthLimitExceeded => DecodeError::TooDeep {
+            max: MAX_NESTING_DEPTH,
+        },
+        e => {
+            let mut deserializer = rmp_serde::Deserializer::from_read_ref(payload);
+            deserializer.set_max_depth(MAX_NESTING_DEPTH);
+            match Envelope::deserialize(&mut deserializer) {
+                Ok(envelope) => envelope.unsupported(e.to_string()),
+                Err(_) => DecodeError::Malformed(e.to_string()),
+            }
+        }
+    })
+}
+
+/// Just enough of a message to name its type, to tell messages from newer builds apart
+/// from malformed ones
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Envelope {
+    version: u8,
+    message_type: String,
+    data: IgnoredAny,
+    signature: IgnoredAny,
+}
+
+impl Envelope {
+    /// Why a message with this envelope failed to decode with `error`
+    fn unsupported(self, error: String) -> DecodeError {
+        let known = serde_json::from_value::<MessageType>(serde_json::Value::String(
+            self.message_type.clone(),
+        ))
+        .is_ok();
+        if known {
+            DecodeError::Malformed(error)
+        } else {
+            DecodeError::Unsupported(self.message_type)
+        }
+    }
+}
+
+/// Reject input whose array/object nesting exceeds `max`, ignoring brackets inside strings
+fn check_depth(bytes: &[u8], max: usize) -> std::result::Result<(), DecodeError> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for &b in bytes {
+        if in_string {
+            match (escaped, b) {
+                (true, _) => escaped = false,
+                (false, b'\\') => escaped = true,
+                (false, b'"') => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                if depth > max {
+                    return Err(DecodeError::TooDeep { max });
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeartbeatData, MessageData, MessageType};
+
+    fn heartbeat() -> PostMessage {
+        PostMessage {
+            version: 1,
+            message_type: MessageType::Heartbeat,
+            data: MessageData::Heartbeat(HeartbeatData {
+                source_node: "node-a".to_string(),
+                timestamp: 1,
+            }),
+            signature: vec![0; 64],
+        }
+    }
+
+    #[test]
+    fn test_decodes_valid_frame() {
+        let mut frame = serde_json::to_vec(&heartbeat()).unwrap();
+        frame.push(b'\n');
+
+        let message = decode_message(&frame).unwrap();
+        assert!(matches!(message.data, MessageData::Heartbeat(_)));
+    }
+
+    #[test]
+    fn test_rejects_oversized_frame() {
+        let frame = vec![b' '; MAX_MESSAGE_SIZE + 1];
+        assert!(matches!(
+            decode_message(&frame),
+            Err(DecodeError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_deep_nesting() {
+        let frame = "[".repeat(MAX_NESTING_DEPTH + 1);
+        assert_eq!(
+            decode_message(frame.as_bytes()).err(),
+            Some(DecodeError::TooDeep {
+                max: MAX_NESTING_DEPTH
+            })
+        );
+    }
+
+    #[test]
+    fn test_brackets_inside_strings_do_not_count_towards_depth() {
+        let mut message = heartbeat();
+        if let MessageData::Heartbeat(ref mut data) = message.data {
+            data.source_node = "[[[[[[[[[[{{{{{{{{\\\"".to_string();
+        }
+        let frame = serde_json::to_vec(&message).unwrap();
+
+        assert!(decode_message(&frame).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_invalid_utf8_and_garbage() {
+        assert_eq!(
+            decode_message(&[0xff, 0xfe]).err(),
+            Some(DecodeError::InvalidUtf8)
+        );
+        assert!(matches!(
+            decode_message(b"{\"version\":"),
+            Err(DecodeError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_binary_frame_round_trips_and_is_smaller() {
+        let message = heartbeat();
+        let json = encode_message(&message, WireFormat::Json).unwrap();
+        let mut binary = encode_message(&message, WireFormat::MessagePack).unwrap();
+
+        assert!(binary.len() < json.len());
+        let decoded = decode_message(&binary).unwrap();
+        assert_eq!(decoded.signature, message.signature);
+
+        binary.pop();
+        assert!(matches!(
+            decode_message(&binary),
+            Err(DecodeError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_take_frame_splits_mixed_stream() {
+        let message = heartbeat();
+        let mut buffer = encode_message(&message, WireFormat::MessagePack).unwrap();
+        buffer.extend(encode_message(&message, WireFormat::Json).unwrap());
+        let partial = buffer.split_off(buffer.len() - 3);
+
+        let first = take_frame(&mut buffer).unwrap().unwrap();
+        assert_eq!(first[0], BINARY_FRAME_MAGIC);
+        assert!(take_frame(&mut buffer).is_none());
+
+        buffer.extend(partial);
+        let second = take_frame(&mut buffer).unwrap().unwrap();
+        assert!(decode_message(&second).is_ok());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_take_frame_rejects_oversized_binary_header() {
+        let mut buffer = vec![BINARY_FRAME_MAGIC];
+        buffer.extend_from_slice(&u32::MAX.to_be_bytes());
+
+        assert!(matches!(
+            take_frame(&mut buffer),
+            Some(Err(DecodeError::TooLarge { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_negotiates_best_common_format() {
+        assert_eq!(
+            WireFormat::negotiate(&WireFormat::advertised()),
+            WireFormat::MessagePack
+        );
+        assert_eq!(
+            WireFormat::negotiate(&["json".to_string(), "cbor".to_string()]),
+            WireFormat::Json
+        );
+        assert_eq!(WireFormat::negotiate(&[]), WireFormat::Json);
+    }
+
+    #[test]
+    fn test_message_types_from_newer_builds_are_unsupported_rather_than_malformed() {
+        let json = br#"{"version":1,"message_type":"ImageUpdate","data":{"ImageUpdate":{}},"signature":[]}"#;
+        let before = decode_metrics();
+        assert_eq!(
+            decode_message(json).err(),
+            Some(DecodeError::Unsupported("ImageUpdate".to_string()))
+        );
+        assert!(decode_metrics().unsupported > before.unsupported);
+
+        let mut binary = encode_message(&heartbeat(), WireFormat::MessagePack).unwrap();
+        let at = binary
+            .windows(9)
+            .position(|window| window == b"Heartbeat")
+            .unwrap();
+        binary[at..at + 9].copy_from_slice(b"Heartbeep");
+        assert_eq!(
+            decode_message(&binary).err(),
+            Some(DecodeError::Unsupported("Heartbeep".to_string()))
+        );
+
+        let broken =
+            br#"{"version":1,"message_type":"Heartbeat","data":{"Heartbeat":{}},"signature":[]}"#;
+        assert!(matches!(
+            decode_message(broken),
+            Err(DecodeError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejections_are_counted() {
+        let before = decode_metrics();
+        let _ = decode_message(b"not json");
+        let after = decode_metrics();
+
+        assert!(after.malformed > before.malformed);
+        assert!(after.rejected() > before.rejected());
+    }
+}
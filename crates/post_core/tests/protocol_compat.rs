@@ -0,0 +1,113 @@
+//! Messages as earlier builds sent them, pinned so later builds keep decoding and
+//! verifying them. Never regenerate a fixture to make a test pass; a failure here means a
+//! change would break sync with peers that haven't upgraded yet.
+
+use post_core::{
+    crypto, decode_message, encode_message, DecodeError, MessageData, PostMessage, WireFormat,
+};
+use std::path::PathBuf;
+
+/// Key the signed fixtures were signed with, derived from the seed `[7; 32]`
+const VERIFYING_KEY: [u8; 32] = [
+    234, 74, 108, 99, 226, 156, 82, 10, 190, 245, 80, 123, 19, 46, 197, 249, 149, 71, 118, 174,
+    190, 190, 123, 146, 66, 30, 234, 105, 20, 70, 210, 44,
+];
+
+/// Signed JSON frames, one of each message an earlier build sent
+const SIGNED: [&str; 6] = [
+    "clipboard_update.json",
+    "clipboard_update_metadata.json",
+    "node_discovery.json",
+    "heartbeat.json",
+    "ack.json",
+    "taildrop_offer.json",
+];
+
+fn fixture(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/protocol")
+        .join(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+/// Whether `message` verifies the way the sync layer checks it: over its JSON encoding
+/// without the signature
+fn verifies(message: &PostMessage) -> bool {
+    let mut unsigned = message.clone();
+    unsigned.signature = Vec::new();
+    let bytes = serde_json::to_vec(&unsigned).unwrap();
+    crypto::verify_signature(&VERIFYING_KEY, &bytes, &message.signature).unwrap_or(false)
+}
+
+#[test]
+fn test_pinned_messages_decode_and_verify() {
+    for name in SIGNED {
+        let frame = fixture(name);
+        let message = decode_message(&frame).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert!(verifies(&message), "{} no longer verifies", name);
+        assert_eq!(
+            encode_message(&message, WireFormat::Json).unwrap(),
+            frame,
+            "{} encodes differently than it was sent",
+            name
+        );
+
+        // Over MessagePack too, which is signed as JSON all the same
+        let binary = encode_message(&message, WireFormat::MessagePack).unwrap();
+        let relayed = decode_message(&binary).unwrap();
+        assert!(
+            verifies(&relayed),
+            "{} fails to verify after MessagePack",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_pinned_fields_keep_their_meaning() {
+    let message = decode_message(&fixture("clipboard_update_metadata.json")).unwrap();
+    let MessageData::ClipboardUpdate(data) = message.data else {
+        panic!("expected a clipboard update");
+    };
+    assert_eq!(data.content_type, "text/html");
+    assert_eq!(data.metadata["origin_app"], "Safari");
+    assert_eq!(data.tailnet.as_deref(), Some("example.ts.net"));
+
+    let message = decode_message(&fixture("node_discovery.json")).unwrap();
+    let MessageData::NodeDiscovery(data) = message.data else {
+        panic!("expected a discovery message");
+    };
+    assert_eq!(data.signing_public_key, VERIFYING_KEY);
+    assert_eq!(data.wire_formats, ["msgpack", "json"]);
+    assert_eq!(data.capabilities, ["pins", "taildrop", "ping", "collect"]);
+    assert_eq!(data.port, Some(8412));
+    assert!(data.wants_reply);
+}
+
+#[test]
+fn test_positional_message_pack_from_earlier_builds_decodes() {
+    let message = decode_message(&fixture("clipboard_update_positional.msgpack")).unwrap();
+    assert!(verifies(&message));
+    let MessageData::ClipboardUpdate(data) = message.data else {
+        panic!("expected a clipboard update");
+    };
+    assert_eq!(&*data.content, "hello from node-a");
+    assert_eq!(data.sequence, 42);
+}
+
+#[test]
+fn test_messages_from_newer_builds_are_tolerated() {
+    // Fields this build doesn't know are ignored
+    let message = decode_message(&fixture("future_fields.json")).unwrap();
+    let MessageData::ClipboardUpdate(data) = message.data else {
+        panic!("expected a clipboard update");
+    };
+    assert_eq!(&*data.content, "hello from the future");
+    assert!(data.is_text());
+
+    // And message types it doesn't know are told apart from malformed messages
+    assert_eq!(
+        decode_message(&fixture("future_message_type.json")).err(),
+        Some(DecodeError::Unsupported("ImageUpdate".to_string()))
+    );
+}
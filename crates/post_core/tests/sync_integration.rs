@@ -0,0 +1,1457 @@
+use post_core::inbox::{self, InboxReceiver};
+use post_core::{
+    key_fingerprint, metadata, taildrop, ApplyMode, ClipboardData, ClipboardManager,
+    ClipboardWatcher, DeliveryState, InMemoryNetwork, InMemoryTransport, MessageData,
+    MockClipboard, PeerTrust, PostError, PostMessage, ReplayMode, StateStore, SyncConfig,
+    SyncEvent, SyncManager, Transport, WireFormat,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a bench in these tests may run in all
+const BENCH_LIMIT: Duration = Duration::from_secs(30);
+
+struct TestNode {
+    clipboard: MockClipboard,
+    sync: Arc<SyncManager>,
+    transport: Arc<InMemoryTransport>,
+    inbox: InboxReceiver,
+}
+
+impl TestNode {
+    /// Join the network as `transport_id` while identifying as `node_id` in messages
+    async fn join_as(network: &InMemoryNetwork, transport_id: &str, node_id: &str) -> Self {
+        Self::join_with(network, transport_id, node_id, SyncConfig::default()).await
+    }
+
+    async fn join_with(
+        network: &InMemoryNetwork,
+        transport_id: &str,
+        node_id: &str,
+        sync_config: SyncConfig,
+    ) -> Self {
+        Self::join_built(network, transport_id, node_id, |sync| {
+            sync.with_sync_config(sync_config)
+        })
+        .await
+    }
+
+    /// Join with a sync manager set up by `build`
+    async fn join_built(
+        network: &InMemoryNetwork,
+        transport_id: &str,
+        node_id: &str,
+        build: impl FnOnce(SyncManager) -> SyncManager,
+    ) -> Self {
+        let clipboard = MockClipboard::new();
+        let sync = Arc::new(build(
+            SyncManager::new(Arc::new(clipboard.clone()), node_id.to_string())
+                .expect("failed to create sync manager"),
+        ));
+        let transport = Arc::new(network.transport(transport_id));
+
+        let (tx, inbox) = inbox::channel(inbox::DEFAULT_CAPACITY);
+        let listener = Arc::clone(&transport);
+        tokio::spawn(async move {
+            let _ = listener.start_listening(tx).await;
+        });
+        while !network.is_listening(transport_id) {
+            tokio::task::yield_now().await;
+        }
+
+        let sender = Arc::clone(&transport);
+        sync.start_sync_loop(move |message| {
+            let sender = Arc::clone(&sender);
+            tokio::spawn(async move {
+                let _ = sender.send_message(message).await;
+            });
+        })
+        .await
+        .expect("failed to start sync loop");
+
+        Self {
+            clipboard,
+            sync,
+            transport,
+            inbox,
+        }
+    }
+
+    async fn join(network: &InMemoryNetwork, node_id: &str) -> Self {
+        Self::join_as(network, node_id, node_id).await
+    }
+
+    async fn announce(&self) {
+        let discovery = self
+            .sync
+            .create_node_discovery_message()
+            .await
+            .expect("failed to create discovery message");
+        self.transport
+            .send_message(discovery)
+            .await
+            .expect("failed to send discovery message");
+    }
+
+    async fn next_message(&mut self) -> PostMessage {
+        tokio::time::timeout(RECEIVE_TIMEOUT, self.inbox.recv())
+            .await
+            .expect("timed out waiting for message")
+            .expect("inbox closed")
+    }
+
+    async fn process_next(&mut self) -> post_core::Result<()> {
+        let message = self.next_message().await;
+        self.sync.handle_message(message).await
+    }
+
+    /// Handle incoming messages until a clipboard update has been processed
+    async fn process_update(&mut self) -> post_core::Result<()> {
+        loop {
+            let message = self.next_message().await;
+            let is_update = matches!(message.data, MessageData::ClipboardUpdate(_));
+            let result = self.sync.handle_message(message).await;
+            if is_update {
+                return result;
+            }
+            result.expect("failed to handle non-update message");
+        }
+    }
+
+    /// Handle every message that arrives within the quiet period
+    async fn drain(&mut self) {
+        while let Ok(Some(message)) =
+            tokio::time::timeout(Duration::from_millis(200), self.inbox.recv()).await
+        {
+            let _ = self.sync.handle_message(message).await;
+        }
+    }
+
+    async fn assert_no_message(&mut self) {
+        let received = tokio::time::timeout(Duration::from_millis(200), self.inbox.recv()).await;
+        assert!(received.is_err(), "unexpected message: {:?}", received);
+    }
+}
+
+/// Two nodes that have exchanged discovery messages
+async fn connected_pair(network: &InMemoryNetwork) -> (TestNode, TestNode) {
+    let mut a = TestNode::join(network, "node-a").await;
+    let mut b = TestNode::join(network, "node-b").await;
+
+    a.announce().await;
+    b.announce().await;
+    b.process_next().await.expect("node-b rejected discovery");
+    a.process_next().await.expect("node-a rejected discovery");
+
+    (a, b)
+}
+
+#[tokio::test]
+async fn test_discovery_registers_peers_and_crypto_sessions() {
+    let network = InMemoryNetwork::new();
+    let (a, b) = connected_pair(&network).await;
+
+    assert!(a.sync.get_nodes().await.contains_key("node-b"));
+    assert!(b.sync.get_nodes().await.contains_key("node-a"));
+    assert!(a.sync.get_crypto_session("node-b").await.is_some());
+    assert!(b.sync.get_crypto_session("node-a").await.is_some());
+
+    // Sessions are listed by the fingerprints of the keys behind them
+    let sessions = a.sync.peer_sessions().await;
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].node_id, "node-b");
+    assert_eq!(
+        sessions[0].verifying_key.as_deref(),
+        Some(key_fingerprint(b.sync.signing_public_key()).as_str())
+    );
+    assert!(sessions[0].public_key.is_some());
+}
+
+#[tokio::test]
+async fn test_clipboard_update_is_applied_on_peer() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    a.clipboard.simulate_copy("hello from a");
+    b.process_update().await.expect("node-b rejected update");
+    assert_eq!(b.clipboard.contents(), "hello from a");
+
+    b.clipboard.simulate_copy("hello from b");
+    a.process_update().await.expect("node-a rejected update");
+    assert_eq!(a.clipboard.contents(), "hello from b");
+}
+
+#[tokio::test]
+async fn test_clipboard_source_follows_latest_copy() {
+    let network = InMemoryNetwork::new();
+    let (a, mut b) = connected_pair(&network).await;
+    assert!(b.sync.get_clipboard_source().await.is_none());
+
+    a.clipboard.simulate_copy("from a");
+    b.process_update().await.unwrap();
+    // A real clipboard's watcher sees the applied content too
+    b.clipboard.simulate_copy("from a");
+    let source = b.sync.get_clipboard_source().await.unwrap();
+    assert_eq!(source.node_id, "node-a");
+    assert!(!source.local);
+
+    b.clipboard.simulate_copy("from b");
+    let source = b.sync.get_clipboard_source().await.unwrap();
+    assert_eq!(source.node_id, "node-b");
+    assert!(source.local);
+}
+
+#[tokio::test]
+async fn test_applied_update_is_not_echoed_back() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    a.clipboard.simulate_copy("no echo");
+    b.process_update().await.unwrap();
+
+    let reply = a.next_message().await;
+    assert!(
+        matches!(reply.data, MessageData::Ack(_)),
+        "expected only an ack, got {:?}",
+        reply
+    );
+    a.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn test_duplicate_content_is_broadcast_once() {
+    let network = InMemoryNetwork::new();
+    let (a, mut b) = connected_pair(&network).await;
+
+    a.clipboard.simulate_copy("same");
+    b.process_update().await.unwrap();
+
+    a.clipboard.simulate_copy("same");
+    b.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn test_dry_run_neither_sends_nor_applies() {
+    let network = InMemoryNetwork::new();
+    let dry_run = SyncConfig {
+        dry_run: true,
+        ..SyncConfig::default()
+    };
+    let (mut a, mut b) = replaying_pair(&network, dry_run).await;
+
+    a.clipboard.simulate_copy("observed only");
+    b.assert_no_message().await;
+
+    b.clipboard.simulate_copy("not applied");
+    a.process_update().await.unwrap();
+    assert_eq!(a.clipboard.contents(), "observed only");
+    b.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn test_update_from_undiscovered_node_is_rejected() {
+    let network = InMemoryNetwork::new();
+    let a = TestNode::join(&network, "node-a").await;
+    let mut b = TestNode::join(&network, "node-b").await;
+
+    a.clipboard.simulate_copy("before discovery");
+    let result = b.process_next().await;
+
+    assert!(matches!(result, Err(PostError::UnknownPeer(_))));
+    assert_eq!(b.clipboard.contents(), "");
+}
+
+#[tokio::test]
+async fn test_updates_from_other_tailnets_are_refused_unless_allowed() {
+    for (allowed_tailnets, applied) in [(vec![], false), (vec!["Home.ts.net".to_string()], true)] {
+        let network = InMemoryNetwork::new();
+        let sync_config = SyncConfig {
+            allowed_tailnets,
+            ..SyncConfig::default()
+        };
+        let (mut a, b) = replaying_pair(&network, sync_config).await;
+        a.sync.update_tailnet(Some("work.ts.net".to_string())).await;
+        b.sync.update_tailnet(Some("home.ts.net".to_string())).await;
+
+        b.clipboard.simulate_copy("from home");
+        let result = a.process_update().await;
+        assert_eq!(result.is_ok(), applied, "{:?}", result);
+        assert_eq!(a.clipboard.contents() == "from home", applied);
+    }
+}
+
+#[tokio::test]
+async fn test_untagged_updates_are_refused_once_other_tailnets_are_allowed() {
+    for (allowed_tailnets, applied) in [(vec![], true), (vec!["home.ts.net".to_string()], false)] {
+        let network = InMemoryNetwork::new();
+        let sync_config = SyncConfig {
+            allowed_tailnets,
+            ..SyncConfig::default()
+        };
+        let (mut a, b) = replaying_pair(&network, sync_config).await;
+        a.sync.update_tailnet(Some("work.ts.net".to_string())).await;
+
+        b.clipboard.simulate_copy("untagged");
+        let result = a.process_update().await;
+        assert_eq!(result.is_ok(), applied, "{:?}", result);
+        assert_eq!(a.clipboard.contents() == "untagged", applied);
+    }
+}
+
+#[tokio::test]
+async fn test_content_from_append_peers_is_added_to_the_clipboard() {
+    let network = InMemoryNetwork::new();
+    let sync_config = SyncConfig {
+        apply_mode_by_peer: [("node-b".to_string(), ApplyMode::Append)].into(),
+        append_separator: " | ".to_string(),
+        allow_collect: true,
+        ..SyncConfig::default()
+    };
+    let (mut a, mut b) = replaying_pair(&network, sync_config).await;
+
+    a.clipboard.simulate_copy("mine");
+    b.drain().await;
+    b.clipboard.simulate_copy("snippet");
+    a.process_update().await.expect("node-a rejected update");
+    assert_eq!(a.clipboard.contents(), "mine | snippet");
+
+    // The watcher seeing the combined content doesn't send it back out as a new copy
+    a.clipboard.simulate_copy("mine | snippet");
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(a.sync.pending_ack_count().await, 0);
+    let source = a.sync.get_clipboard_source().await.unwrap();
+    assert_eq!(source.node_id, "node-b");
+
+    // Only the appended update counts as synced, not what it was appended to
+    b.drain().await;
+    let sync = Arc::clone(&b.sync);
+    let collect = tokio::spawn(async move { sync.collect("node-a", RECEIVE_TIMEOUT).await });
+    a.process_next().await.expect("node-a rejected collect");
+    b.process_next().await.expect("node-b rejected answer");
+    let refused = collect.await.unwrap().unwrap_err();
+    assert!(refused.to_string().contains("wasn't synced"), "{}", refused);
+}
+
+#[tokio::test]
+async fn test_appending_past_the_length_limit_replaces_the_clipboard() {
+    let network = InMemoryNetwork::new();
+    let sync_config = SyncConfig {
+        apply_mode_by_peer: [("node-b".to_string(), ApplyMode::Append)].into(),
+        append_separator: " | ".to_string(),
+        ..SyncConfig::default()
+    };
+    let mut a = TestNode::join_built(&network, "node-a", "node-a", |sync| {
+        sync.with_sync_config(sync_config).with_max_length(Some(12))
+    })
+    .await;
+    let mut b = TestNode::join(&network, "node-b").await;
+    a.announce().await;
+    b.announce().await;
+    b.process_next().await.expect("node-b rejected discovery");
+    a.process_next().await.expect("node-a rejected discovery");
+
+    a.clipboard.set_contents("mine").await.unwrap();
+    b.clipboard.simulate_copy("snippet");
+    a.process_update().await.expect("node-a rejected update");
+    assert_eq!(a.clipboard.contents(), "snippet");
+}
+
+#[tokio::test]
+async fn test_tampered_update_fails_signature_verification() {
+    let network = InMemoryNetwork::new();
+    let (a, mut b) = connected_pair(&network).await;
+
+    a.clipboard.simulate_copy("original");
+    let mut message = b.next_message().await;
+    if let MessageData::ClipboardUpdate(ref mut data) = message.data {
+        data.content = "tampered".into();
+    } else {
+        panic!("expected a clipboard update");
+    }
+
+    let result = b.sync.handle_message(message).await;
+    assert!(matches!(result, Err(PostError::SignatureInvalid(_))));
+    assert_eq!(b.clipboard.contents(), "");
+}
+
+#[tokio::test]
+async fn test_refused_messages_reach_the_event_hook() {
+    let network = InMemoryNetwork::new();
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorder = Arc::clone(&seen);
+    let a = TestNode::join(&network, "node-a").await;
+    let mut b = TestNode::join_built(&network, "node-b", "node-b", |sync| {
+        sync.with_event_hook(Arc::new(move |event| {
+            recorder.lock().unwrap().push(event.clone());
+        }))
+    })
+    .await;
+
+    // Nothing is known of node-a yet, so its update can't be verified
+    a.clipboard.simulate_copy("from a stranger");
+    assert!(matches!(
+        b.process_update().await,
+        Err(PostError::UnknownPeer(_))
+    ));
+
+    let seen = seen.lock().unwrap();
+    assert!(
+        seen.iter().any(|event| matches!(
+            event,
+            SyncEvent::Filtered { from: Some(from), reason }
+                if from == "node-a" && reason.contains("ClipboardUpdate refused")
+        )),
+        "{:?}",
+        seen
+    );
+}
+
+#[tokio::test]
+async fn test_content_type_and_metadata_are_signed_and_reach_the_receiver() {
+    let network = InMemoryNetwork::new();
+    let (a, mut b) = connected_pair(&network).await;
+    let metadata = [(metadata::ORIGIN_APP.to_string(), "Safari".to_string())].into();
+
+    a.sync
+        .broadcast_content_with_metadata("from safari".to_string(), metadata)
+        .await
+        .unwrap();
+    b.process_update().await.expect("node-b rejected update");
+    let source = b.sync.get_clipboard_source().await.unwrap();
+    assert_eq!(source.app.as_deref(), Some("Safari"));
+
+    let tamperings: [fn(&mut ClipboardData); 2] = [
+        |data| data.content_type = "image/png".to_string(),
+        |data| {
+            data.metadata
+                .insert(metadata::ORIGIN_APP.to_string(), "Terminal".to_string());
+        },
+    ];
+    for (n, tamper) in tamperings.into_iter().enumerate() {
+        a.clipboard.simulate_copy(&format!("copy {}", n));
+        let mut message = b.next_message().await;
+        match message.data {
+            MessageData::ClipboardUpdate(ref mut data) => tamper(data),
+            _ => panic!("expected a clipboard update"),
+        }
+        let result = b.sync.handle_message(message).await;
+        assert!(
+            matches!(result, Err(PostError::SignatureInvalid(_))),
+            "{:?}",
+            result
+        );
+    }
+    assert_eq!(b.clipboard.contents(), "from safari");
+}
+
+#[tokio::test]
+async fn test_impersonator_cannot_replace_verifying_key() {
+    let network = InMemoryNetwork::new();
+    let (_a, mut b) = connected_pair(&network).await;
+    let mallory = TestNode::join_as(&network, "mallory", "node-a").await;
+
+    mallory.announce().await;
+    let result = b.process_next().await;
+    assert!(matches!(result, Err(PostError::Crypto(_))));
+
+    mallory.clipboard.simulate_copy("forged");
+    let result = b.process_next().await;
+    assert!(matches!(result, Err(PostError::SignatureInvalid(_))));
+    assert_eq!(b.clipboard.contents(), "");
+}
+
+#[tokio::test]
+async fn test_peers_derive_matching_encryption_sessions() {
+    let network = InMemoryNetwork::new();
+    let (a, b) = connected_pair(&network).await;
+
+    let a_to_b = a.sync.get_crypto_session("node-b").await.unwrap();
+    let b_from_a = b.sync.get_crypto_session("node-a").await.unwrap();
+
+    let ciphertext = a_to_b.encrypt(b"secret clipboard").await.unwrap();
+    assert_ne!(&ciphertext[12..], b"secret clipboard");
+    let plaintext = b_from_a.decrypt(&ciphertext).await.unwrap();
+    assert_eq!(plaintext, b"secret clipboard");
+}
+
+#[tokio::test]
+async fn test_sync_over_binary_wire_format() {
+    let network = InMemoryNetwork::with_wire_format(WireFormat::MessagePack);
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    a.clipboard.simulate_copy("binary hello");
+    b.process_update()
+        .await
+        .expect("node-b rejected binary update");
+    assert_eq!(b.clipboard.contents(), "binary hello");
+
+    b.clipboard.simulate_copy("binary reply");
+    a.process_update()
+        .await
+        .expect("node-a rejected binary update");
+    assert_eq!(a.clipboard.contents(), "binary reply");
+}
+
+#[tokio::test]
+async fn test_discovery_carries_friendly_node_name() {
+    let network = InMemoryNetwork::new();
+    let a = TestNode::join(&network, "node-a").await;
+    let mut b = TestNode::join(&network, "node-b").await;
+
+    a.sync.update_node_name("mac-studio\n".to_string()).await;
+    a.announce().await;
+    b.process_next().await.expect("node-b rejected discovery");
+
+    assert_eq!(b.sync.get_nodes().await["node-a"].name, "mac-studio");
+    assert_eq!(a.sync.get_node_name().await, "mac-studio");
+    assert_eq!(b.sync.get_node_name().await, "node-b");
+}
+
+#[tokio::test]
+async fn test_discovery_carries_advertised_endpoint() {
+    let network = InMemoryNetwork::new();
+    let b = TestNode::join(&network, "node-b").await;
+    let remapped = SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string())
+        .expect("failed to create sync manager")
+        .with_advertised_endpoint(Some("192.168.1.20".parse().unwrap()), 9412);
+
+    let discovery = remapped.create_node_discovery_message().await.unwrap();
+    b.sync
+        .handle_message(discovery)
+        .await
+        .expect("node-b rejected discovery");
+
+    let node = &b.sync.get_nodes().await["node-a"];
+    assert_eq!(node.address.as_deref(), Some("192.168.1.20"));
+    assert_eq!(node.port, Some(9412));
+}
+
+#[tokio::test]
+async fn test_ack_clears_pending_update() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    a.clipboard.simulate_copy("needs ack");
+    b.process_update().await.unwrap();
+    assert_eq!(a.sync.pending_ack_count().await, 1);
+
+    a.process_next().await.expect("node-a rejected ack");
+    assert_eq!(a.sync.pending_ack_count().await, 0);
+
+    let stats = &a.sync.get_peer_stats().await["node-b"];
+    assert_eq!(stats.updates_sent, 1);
+    assert_eq!(stats.bytes_sent, "needs ack".len() as u64);
+    assert!(stats.average_ack_latency().is_some());
+    assert!(!stats.awaiting_ack);
+}
+
+#[tokio::test]
+async fn test_last_delivery_lists_peers_that_applied_the_update() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+    assert!(a.sync.last_delivery().await.is_none());
+
+    a.clipboard.simulate_copy("receipt");
+    b.process_update().await.unwrap();
+    let report = a.sync.last_delivery().await.unwrap();
+    assert_eq!(report.peers.len(), 1);
+    assert_eq!(report.peers[0].node_id, "node-b");
+    assert_eq!(report.peers[0].state, DeliveryState::Pending);
+
+    a.process_next().await.expect("node-a rejected ack");
+    let report = a.sync.last_delivery().await.unwrap();
+    assert_eq!(report.peers[0].state, DeliveryState::Applied);
+}
+
+#[tokio::test]
+async fn test_ping_is_answered_with_signature_verdict() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    let sync = Arc::clone(&a.sync);
+    let ping = tokio::spawn(async move { sync.ping("node-b", RECEIVE_TIMEOUT).await });
+    b.process_next().await.expect("node-b rejected ping");
+    a.process_next().await.expect("node-a rejected pong");
+    let reply = ping.await.unwrap().expect("ping went unanswered");
+    assert!(reply.verified);
+
+    assert!(matches!(
+        a.sync.ping("node-c", RECEIVE_TIMEOUT).await,
+        Err(PostError::UnknownPeer(_))
+    ));
+    assert!(matches!(
+        a.sync.collect("node-c", RECEIVE_TIMEOUT).await,
+        Err(PostError::UnknownPeer(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_pings_are_answered_only_when_verified_and_only_to_the_sender() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+    let mut c = TestNode::join(&network, "node-c").await;
+    c.announce().await;
+    a.drain().await;
+    b.drain().await;
+
+    let sync = Arc::clone(&a.sync);
+    let ping = tokio::spawn(async move { sync.ping("node-b", RECEIVE_TIMEOUT).await });
+    let mut forged = b.next_message().await;
+    if let MessageData::Ping(data) = &mut forged.data {
+        data.nonce += 1;
+    }
+    assert!(b.sync.handle_message(forged).await.is_err());
+    a.assert_no_message().await;
+    assert!(ping.await.unwrap().is_err());
+
+    let sync = Arc::clone(&a.sync);
+    let ping = tokio::spawn(async move { sync.ping("node-b", RECEIVE_TIMEOUT).await });
+    b.process_next().await.expect("node-b rejected ping");
+    a.process_next().await.expect("node-a rejected pong");
+    assert!(ping.await.unwrap().unwrap().verified);
+    c.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn test_bench_payloads_are_answered_without_touching_the_clipboard() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+    let mut c = TestNode::join(&network, "node-c").await;
+    c.announce().await;
+    a.drain().await;
+    b.drain().await;
+
+    let sync = Arc::clone(&a.sync);
+    let bench = tokio::spawn(async move {
+        sync.bench("node-b", &[10, 5000], 2, RECEIVE_TIMEOUT, BENCH_LIMIT)
+            .await
+    });
+    for _ in 0..4 {
+        b.process_next().await.expect("node-b rejected payload");
+        a.process_next().await.expect("node-a rejected answer");
+    }
+    c.assert_no_message().await;
+    let results = bench.await.unwrap().expect("bench failed");
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[1].size, 5000);
+    assert!(results.iter().all(|result| result.answered == 2));
+    assert!(results[1].p95 >= results[1].p50);
+    assert_eq!(b.clipboard.contents(), "");
+
+    let too_large = a
+        .sync
+        .bench("node-b", &[usize::MAX], 1, RECEIVE_TIMEOUT, BENCH_LIMIT)
+        .await;
+    assert!(too_large.is_err());
+}
+
+#[tokio::test]
+async fn test_bench_of_a_silent_peer_stops_at_its_limit() {
+    let network = InMemoryNetwork::new();
+    let (a, _b) = connected_pair(&network).await;
+
+    let started = std::time::Instant::now();
+    let results = a
+        .sync
+        .bench(
+            "node-b",
+            &[10, 5000],
+            1000,
+            Duration::from_millis(50),
+            Duration::from_millis(200),
+        )
+        .await
+        .expect("bench failed");
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(results
+        .iter()
+        .all(|result| result.answered == 0 && result.lost == 1000));
+}
+
+#[tokio::test]
+async fn test_collect_returns_only_synced_clipboard_content() {
+    let network = InMemoryNetwork::new();
+    let sync_config = SyncConfig {
+        allow_collect: true,
+        ..SyncConfig::default()
+    };
+    let (mut a, mut b) = replaying_pair(&network, sync_config).await;
+    let mut c = TestNode::join(&network, "node-c").await;
+    c.announce().await;
+    a.drain().await;
+    b.drain().await;
+
+    a.clipboard.simulate_copy("shared notes");
+    b.process_update().await.expect("node-b rejected update");
+    a.process_next().await.expect("node-a rejected ack");
+    c.drain().await;
+
+    let sync = Arc::clone(&b.sync);
+    let collect = tokio::spawn(async move { sync.collect("node-a", RECEIVE_TIMEOUT).await });
+    a.process_next().await.expect("node-a rejected collect");
+    b.process_next().await.expect("node-b rejected answer");
+    assert_eq!(collect.await.unwrap().unwrap(), "shared notes");
+    // The clipboard goes to the peer that asked for it alone
+    c.assert_no_message().await;
+
+    // Content the daemon never synced, such as something its filters held back, stays put
+    a.clipboard.set_contents("local only").await.unwrap();
+    let sync = Arc::clone(&b.sync);
+    let collect = tokio::spawn(async move { sync.collect("node-a", RECEIVE_TIMEOUT).await });
+    a.process_next().await.expect("node-a rejected collect");
+    b.process_next().await.expect("node-b rejected answer");
+    let refused = collect.await.unwrap().unwrap_err();
+    assert!(refused.to_string().contains("wasn't synced"), "{}", refused);
+}
+
+#[tokio::test]
+async fn test_collect_is_refused_unless_allowed() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    a.clipboard.simulate_copy("shared notes");
+    b.process_next().await.expect("node-b rejected update");
+    a.process_next().await.expect("node-a rejected ack");
+
+    let sync = Arc::clone(&b.sync);
+    let collect = tokio::spawn(async move { sync.collect("node-a", RECEIVE_TIMEOUT).await });
+    a.process_next().await.expect("node-a rejected collect");
+    b.process_next().await.expect("node-b rejected answer");
+    let refused = collect.await.unwrap().unwrap_err();
+    assert!(refused.to_string().contains("allow_collect"), "{}", refused);
+}
+
+#[tokio::test]
+async fn test_stale_peers_are_listed_offline_until_they_return() {
+    let network = InMemoryNetwork::new();
+    let (mut a, b) = connected_pair(&network).await;
+
+    // Last-seen times have a resolution of a second
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    a.sync.cleanup_stale_nodes(0).await.unwrap();
+    assert!(a.sync.get_nodes().await.is_empty());
+    let offline = a.sync.offline_peers().await;
+    assert_eq!(offline.len(), 1);
+    assert_eq!(offline[0].node_id, "node-b");
+
+    b.announce().await;
+    a.process_next().await.expect("node-a rejected discovery");
+    assert!(a.sync.offline_peers().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_heartbeats_with_tampered_timestamps_are_rejected() {
+    let network = InMemoryNetwork::new();
+    let (a, b) = connected_pair(&network).await;
+
+    let heartbeat = a.sync.create_heartbeat_message().await.unwrap();
+    let mut skewed = heartbeat.clone();
+    if let MessageData::Heartbeat(ref mut data) = skewed.data {
+        data.timestamp -= 3600;
+    }
+    assert!(b.sync.handle_message(skewed).await.is_err());
+    b.sync.handle_message(heartbeat).await.unwrap();
+
+    // In-sync clocks are never reported
+    assert!(b.sync.clock_skews().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_clocks_running_ahead_are_reported_as_positive_skew() {
+    let network = InMemoryNetwork::new();
+    let (a, b) = connected_pair(&network).await;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let ahead = a
+        .sync
+        .create_heartbeat_message_at(now + 3600)
+        .await
+        .unwrap();
+    b.sync.handle_message(ahead).await.unwrap();
+    let skews = b.sync.clock_skews().await;
+    assert_eq!(skews.len(), 1);
+    assert!((3599..=3600).contains(&skews[0].skew_secs), "{:?}", skews);
+
+    // Far beyond what an i64 holds, which must not wrap around to "behind"
+    let absurd = a.sync.create_heartbeat_message_at(u64::MAX).await.unwrap();
+    b.sync.handle_message(absurd).await.unwrap();
+    assert_eq!(b.sync.clock_skews().await[0].skew_secs, i64::MAX);
+}
+
+#[tokio::test]
+async fn test_unacknowledged_update_is_retried_after_peer_returns() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    network.set_online("node-b", false);
+    a.clipboard.simulate_copy("while offline");
+    b.assert_no_message().await;
+    assert_eq!(a.sync.pending_ack_count().await, 1);
+
+    network.set_online("node-b", true);
+    b.announce().await;
+    a.process_next().await.expect("node-a rejected discovery");
+
+    b.process_update().await.expect("node-b rejected retry");
+    assert_eq!(b.clipboard.contents(), "while offline");
+
+    a.drain().await;
+    assert_eq!(a.sync.pending_ack_count().await, 0);
+}
+
+#[tokio::test]
+async fn test_stale_retry_does_not_overwrite_newer_content() {
+    let network = InMemoryNetwork::new();
+    let (a, mut b) = connected_pair(&network).await;
+
+    a.clipboard.simulate_copy("first");
+    let first = b.next_message().await;
+    b.sync.handle_message(first.clone()).await.unwrap();
+
+    a.clipboard.simulate_copy("second");
+    b.process_update().await.unwrap();
+
+    b.sync
+        .handle_message(first)
+        .await
+        .expect("stale retry should still be acknowledged");
+    assert_eq!(b.clipboard.contents(), "second");
+}
+
+/// Clipboard whose writes fail while `fail_sets` is set, after the user copies
+/// `copied_meanwhile` if there is any, and whose secret marker can't be read while
+/// `fail_concealed` is
+#[derive(Clone, Default)]
+struct FlakyClipboard {
+    inner: MockClipboard,
+    fail_sets: Arc<AtomicBool>,
+    copied_meanwhile: Arc<std::sync::Mutex<Option<String>>>,
+    fail_concealed: Arc<AtomicBool>,
+}
+
+#[async_trait::async_trait]
+impl ClipboardManager for FlakyClipboard {
+    async fn get_contents(&self) -> post_core::Result<String> {
+        self.inner.get_contents().await
+    }
+
+    async fn set_contents(&self, content: &str) -> post_core::Result<()> {
+        if self.fail_sets.load(Ordering::SeqCst) {
+            let copied = self.copied_meanwhile.lock().unwrap().take();
+            if let Some(copied) = copied {
+                self.inner.simulate_copy(&copied);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            return Err(PostError::Clipboard("clipboard is busy".to_string()));
+        }
+        self.inner.set_contents(content).await
+    }
+
+    async fn is_concealed(&self) -> post_core::Result<bool> {
+        if self.fail_concealed.load(Ordering::SeqCst) {
+            return Err(PostError::BackendUnavailable("xclip not found".to_string()));
+        }
+        self.inner.is_concealed().await
+    }
+}
+
+#[async_trait::async_trait]
+impl ClipboardWatcher for FlakyClipboard {
+    async fn watch_changes(
+        &self,
+        callback: Box<dyn Fn(String) + Send + Sync + 'static>,
+    ) -> post_core::Result<()> {
+        self.inner.watch_changes(callback).await
+    }
+}
+
+#[tokio::test]
+async fn test_failed_apply_keeps_refusing_older_updates() {
+    let a = SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string()).unwrap();
+    let b_clipboard = FlakyClipboard::default();
+    let b = SyncManager::new(Arc::new(b_clipboard.clone()), "node-b".to_string()).unwrap();
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    a.handle_message(b.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    let (tx, mut outbox) = mpsc::unbounded_channel();
+    a.start_sync_loop(move |message| {
+        let _ = tx.send(message);
+    })
+    .await
+    .unwrap();
+
+    let mut updates = Vec::new();
+    for content in ["first", "second", "third"] {
+        a.broadcast_content(content.to_string()).await.unwrap();
+        updates.push(outbox.try_recv().expect("update was not sent"));
+    }
+    let [first, second, third] = <[PostMessage; 3]>::try_from(updates).unwrap();
+    b.handle_message(first.clone()).await.unwrap();
+    b.handle_message(second).await.unwrap();
+
+    b_clipboard.fail_sets.store(true, Ordering::SeqCst);
+    assert!(b.handle_message(third.clone()).await.is_err());
+    b_clipboard.fail_sets.store(false, Ordering::SeqCst);
+
+    // The failed update can be retried, but older ones are still stale
+    b.handle_message(first).await.unwrap();
+    assert_eq!(b_clipboard.inner.contents(), "second");
+    b.handle_message(third).await.unwrap();
+    assert_eq!(b_clipboard.inner.contents(), "third");
+}
+
+#[tokio::test]
+async fn test_failed_apply_keeps_a_copy_made_meanwhile() {
+    let a = SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string()).unwrap();
+    let b_clipboard = FlakyClipboard::default();
+    let b = SyncManager::new(Arc::new(b_clipboard.clone()), "node-b".to_string()).unwrap();
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    let (tx, mut outbox) = mpsc::unbounded_channel();
+    a.start_sync_loop(move |message| {
+        let _ = tx.send(message);
+    })
+    .await
+    .unwrap();
+    b.start_sync_loop(|_| {}).await.unwrap();
+
+    a.broadcast_content("from a".to_string()).await.unwrap();
+    let update = outbox.try_recv().expect("update was not sent");
+    *b_clipboard.copied_meanwhile.lock().unwrap() = Some("copied on b".to_string());
+    b_clipboard.fail_sets.store(true, Ordering::SeqCst);
+    assert!(b.handle_message(update).await.is_err());
+
+    // The watcher already sent what was copied; undoing the failed apply keeps that
+    assert!(!b
+        .broadcast_content("copied on b".to_string())
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+async fn test_copies_are_not_sent_when_the_secret_marker_cant_be_read() {
+    let clipboard = FlakyClipboard::default();
+    let sync = SyncManager::new(Arc::new(clipboard.clone()), "node-a".to_string()).unwrap();
+    let (tx, mut outbox) = mpsc::unbounded_channel();
+    sync.start_sync_loop(move |message| {
+        let _ = tx.send(message);
+    })
+    .await
+    .unwrap();
+
+    clipboard.fail_concealed.store(true, Ordering::SeqCst);
+    clipboard.inner.simulate_copy("hunter2");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(outbox.try_recv().is_err());
+
+    clipboard.fail_concealed.store(false, Ordering::SeqCst);
+    clipboard.inner.simulate_copy("hello");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(outbox.try_recv().is_ok());
+}
+
+/// Two nodes where node-a keeps updates for replay as configured
+async fn replaying_pair(
+    network: &InMemoryNetwork,
+    sync_config: SyncConfig,
+) -> (TestNode, TestNode) {
+    let mut a = TestNode::join_with(network, "node-a", "node-a", sync_config).await;
+    let mut b = TestNode::join(network, "node-b").await;
+
+    a.announce().await;
+    b.announce().await;
+    b.process_next().await.expect("node-b rejected discovery");
+    a.process_next().await.expect("node-a rejected discovery");
+
+    (a, b)
+}
+
+/// Copy each item on node-a while node-b is offline, then bring node-b back
+async fn copy_while_offline(
+    network: &InMemoryNetwork,
+    a: &mut TestNode,
+    b: &mut TestNode,
+    items: &[&str],
+) {
+    network.set_online("node-b", false);
+    for item in items {
+        a.clipboard.simulate_copy(item);
+        // A copy replaced before it's checked for a secret marker isn't sent
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    b.assert_no_message().await;
+
+    network.set_online("node-b", true);
+    b.announce().await;
+    a.process_next().await.expect("node-a rejected discovery");
+}
+
+/// Handle everything node-b receives, returning the clipboard updates in arrival order
+async fn received_updates(b: &mut TestNode) -> Vec<String> {
+    let mut updates = Vec::new();
+    while let Ok(Some(message)) =
+        tokio::time::timeout(Duration::from_millis(200), b.inbox.recv()).await
+    {
+        if let MessageData::ClipboardUpdate(ref data) = message.data {
+            updates.push(data.content.to_string());
+        }
+        b.sync.handle_message(message).await.unwrap();
+    }
+    updates
+}
+
+#[tokio::test]
+async fn test_reappearing_peer_receives_latest_missed_update() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = replaying_pair(&network, SyncConfig::default()).await;
+
+    copy_while_offline(&network, &mut a, &mut b, &["one", "two", "three"]).await;
+
+    assert_eq!(received_updates(&mut b).await, vec!["three"]);
+    assert_eq!(b.clipboard.contents(), "three");
+}
+
+#[tokio::test]
+async fn test_reappearing_peer_receives_missed_history() {
+    let network = InMemoryNetwork::new();
+    let sync_config = SyncConfig {
+        offline_replay: ReplayMode::History,
+        replay_history_size: 2,
+        ..SyncConfig::default()
+    };
+    let (mut a, mut b) = replaying_pair(&network, sync_config).await;
+
+    a.clipboard.simulate_copy("seen");
+    b.process_update().await.unwrap();
+    a.process_next().await.expect("node-a rejected ack");
+
+    copy_while_offline(&network, &mut a, &mut b, &["one", "two", "three"]).await;
+
+    assert_eq!(received_updates(&mut b).await, vec!["two", "three"]);
+    assert_eq!(b.clipboard.contents(), "three");
+}
+
+#[tokio::test]
+async fn test_replay_skips_updates_already_acknowledged() {
+    let network = InMemoryNetwork::new();
+    let sync_config = SyncConfig {
+        offline_replay: ReplayMode::History,
+        replay_history_size: 10,
+        ..SyncConfig::default()
+    };
+    let (mut a, mut b) = replaying_pair(&network, sync_config).await;
+
+    a.clipboard.simulate_copy("delivered");
+    b.process_update().await.unwrap();
+    a.process_next().await.expect("node-a rejected ack");
+
+    b.announce().await;
+    a.process_next().await.expect("node-a rejected discovery");
+    b.assert_no_message().await;
+}
+
+#[tokio::test]
+async fn test_clipboard_stack_matches_on_both_peers() {
+    let network = InMemoryNetwork::new();
+    let sync_config = SyncConfig {
+        stack_size: 3,
+        ..SyncConfig::default()
+    };
+    let mut a = TestNode::join_with(&network, "node-a", "node-a", sync_config.clone()).await;
+    let mut b = TestNode::join_with(&network, "node-b", "node-b", sync_config).await;
+    a.announce().await;
+    b.announce().await;
+    b.process_next().await.expect("node-b rejected discovery");
+    a.process_next().await.expect("node-a rejected discovery");
+
+    for content in ["one", "two", "three", "two", "four"] {
+        a.clipboard.simulate_copy(content);
+        b.process_update().await.unwrap();
+    }
+
+    assert_eq!(
+        a.sync.get_clipboard_stack().await,
+        vec!["four", "two", "three"]
+    );
+    assert_eq!(
+        b.sync.get_clipboard_stack().await,
+        vec!["four", "two", "three"]
+    );
+}
+
+#[tokio::test]
+async fn test_clipboard_history_survives_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let start = || {
+        SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string())
+            .unwrap()
+            .with_sync_config(SyncConfig {
+                persist_history: true,
+                ..SyncConfig::default()
+            })
+            .with_state_store(open_store(&dir))
+    };
+
+    let sync = start();
+    sync.start_sync_loop(|_| {}).await.unwrap();
+    sync.broadcast_content("first".to_string()).await.unwrap();
+    sync.broadcast_content("second".to_string()).await.unwrap();
+
+    assert_eq!(start().get_clipboard_stack().await, vec!["second", "first"]);
+}
+
+#[tokio::test]
+async fn test_known_peers_survive_restart_until_they_announce_new_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let node = |id: &str| SyncManager::new(Arc::new(MockClipboard::new()), id.to_string()).unwrap();
+    let start = || node("node-b").with_state_store(open_store(&dir));
+
+    let a = node("node-a");
+    let b = start();
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+
+    // Messages from node-a verify straight after a restart
+    let b = start();
+    assert_eq!(b.get_nodes().await["node-a"].trust, PeerTrust::Remembered);
+    b.handle_message(a.create_heartbeat_message().await.unwrap())
+        .await
+        .expect("remembered key did not verify");
+
+    // node-a restarted too, so it announces new keys, which replace the remembered ones
+    let a = node("node-a");
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .expect("new keys of a remembered peer were refused");
+    assert_eq!(b.get_nodes().await["node-a"].trust, PeerTrust::Confirmed);
+    assert!(b.get_crypto_session("node-a").await.is_some());
+
+    let imposter = node("node-a");
+    assert!(b
+        .handle_message(imposter.create_node_discovery_message().await.unwrap())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_queued_updates_are_delivered_after_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let node = |id: &str| SyncManager::new(Arc::new(MockClipboard::new()), id.to_string()).unwrap();
+    let start = |sync_config: SyncConfig| {
+        node("node-a")
+            .with_sync_config(sync_config)
+            .with_state_store(open_store(&dir))
+    };
+
+    let queued = || SyncConfig {
+        persist_queue: Some(true),
+        ..SyncConfig::default()
+    };
+    let a = start(queued());
+    let b = node("node-b");
+    a.handle_message(b.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    a.start_sync_loop(|_| {}).await.unwrap();
+    a.broadcast_content("while b was away".to_string())
+        .await
+        .unwrap();
+    a.flush_state().await;
+
+    // Not kept by default, like the history
+    assert_eq!(start(SyncConfig::default()).pending_ack_count().await, 0);
+    // Too little room for the update leaves nothing to restore
+    let cramped = SyncConfig {
+        queue_max_bytes: 4,
+        ..queued()
+    };
+    assert_eq!(start(cramped).pending_ack_count().await, 0);
+
+    // Both restart, so node-b learns node-a's new key, and the update is replayed signed with it
+    let a = start(queued());
+    assert_eq!(a.pending_ack_count().await, 1);
+    let (tx, mut outbox) = mpsc::unbounded_channel();
+    a.start_sync_loop(move |message| {
+        let _ = tx.send(message);
+    })
+    .await
+    .unwrap();
+    let b_clipboard = MockClipboard::new();
+    let b = SyncManager::new(Arc::new(b_clipboard.clone()), "node-b".to_string()).unwrap();
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    a.handle_message(b.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+
+    let replayed = outbox.try_recv().expect("queued update was not replayed");
+    b.handle_message(replayed)
+        .await
+        .expect("replayed update did not verify");
+    assert_eq!(b_clipboard.contents(), "while b was away");
+}
+
+/// The state database in `dir`, opened anew as a restarted daemon would
+fn open_store(dir: &tempfile::TempDir) -> Arc<StateStore> {
+    Arc::new(StateStore::open(&dir.path().join("state.db")).unwrap())
+}
+
+/// Sequence of the update `sync` sends for `content`
+async fn sent_sequence(sync: &SyncManager, content: &str) -> u64 {
+    let (tx, mut outbox) = mpsc::unbounded_channel();
+    sync.start_sync_loop(move |message| {
+        let _ = tx.send(message);
+    })
+    .await
+    .unwrap();
+    sync.broadcast_content(content.to_string()).await.unwrap();
+    match outbox.try_recv().expect("update was not sent").data {
+        MessageData::ClipboardUpdate(data) => data.sequence,
+        _ => panic!("expected a clipboard update"),
+    }
+}
+
+#[tokio::test]
+async fn test_sequences_survive_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string()).unwrap();
+    let start_b = |clipboard: &MockClipboard| {
+        SyncManager::new(Arc::new(clipboard.clone()), "node-b".to_string())
+            .unwrap()
+            .with_state_store(open_store(&dir))
+    };
+
+    let b_clipboard = MockClipboard::new();
+    let b = start_b(&b_clipboard);
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    a.handle_message(b.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    let (tx, mut outbox) = mpsc::unbounded_channel();
+    a.start_sync_loop(move |message| {
+        let _ = tx.send(message);
+    })
+    .await
+    .unwrap();
+    a.broadcast_content("before restart".to_string())
+        .await
+        .unwrap();
+    let update = outbox.try_recv().expect("update was not sent");
+    b.handle_message(update.clone()).await.unwrap();
+    let before = sent_sequence(&b, "from b").await;
+    drop(b);
+
+    // A late retry of the same update after node-b restarts must not be applied again
+    b_clipboard.simulate_copy("copied since");
+    let b = start_b(&b_clipboard);
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    b.handle_message(update).await.unwrap();
+    assert_eq!(b_clipboard.contents(), "copied since");
+    assert!(sent_sequence(&b, "from b again").await > before);
+}
+
+#[tokio::test]
+async fn test_peer_stats_survive_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let b = SyncManager::new(Arc::new(MockClipboard::new()), "node-b".to_string()).unwrap();
+    let start_a = || {
+        SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string())
+            .unwrap()
+            .with_state_store(open_store(&dir))
+    };
+
+    let a = start_a();
+    a.handle_message(b.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    sent_sequence(&a, "counted").await;
+    a.flush_state().await;
+    drop(a);
+
+    let stats = start_a().get_peer_stats().await;
+    assert_eq!(stats["node-b"].updates_sent, 1);
+    assert_eq!(stats["node-b"].bytes_sent, "counted".len() as u64);
+}
+
+#[tokio::test]
+async fn test_updates_replayed_after_both_restart_are_not_applied_twice() {
+    let a_dir = tempfile::tempdir().unwrap();
+    let b_dir = tempfile::tempdir().unwrap();
+    let start_a = || {
+        SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string())
+            .unwrap()
+            .with_sync_config(SyncConfig {
+                persist_queue: Some(true),
+                ..SyncConfig::default()
+            })
+            .with_state_store(open_store(&a_dir))
+    };
+    let b_clipboard = MockClipboard::new();
+    let start_b = || {
+        SyncManager::new(Arc::new(b_clipboard.clone()), "node-b".to_string())
+            .unwrap()
+            .with_state_store(open_store(&b_dir))
+    };
+
+    let a = start_a();
+    let b = start_b();
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    a.handle_message(b.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    let (tx, mut outbox) = mpsc::unbounded_channel();
+    a.start_sync_loop(move |message| {
+        let _ = tx.send(message);
+    })
+    .await
+    .unwrap();
+    a.broadcast_content("applied once".to_string())
+        .await
+        .unwrap();
+    // Applied, but the acknowledgement never reaches node-a
+    b.handle_message(outbox.try_recv().expect("update was not sent"))
+        .await
+        .unwrap();
+    b_clipboard.simulate_copy("copied on b since");
+    a.flush_state().await;
+    drop((a, b));
+
+    // Both come back with new keys, and node-a replays the update it still holds
+    let a = start_a();
+    let b = start_b();
+    let (tx, mut outbox) = mpsc::unbounded_channel();
+    a.start_sync_loop(move |message| {
+        let _ = tx.send(message);
+    })
+    .await
+    .unwrap();
+    b.handle_message(a.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    a.handle_message(b.create_node_discovery_message().await.unwrap())
+        .await
+        .unwrap();
+    let replayed = outbox.try_recv().expect("queued update was not replayed");
+    b.handle_message(replayed).await.unwrap();
+    assert_eq!(b_clipboard.contents(), "copied on b since");
+}
+
+#[tokio::test]
+async fn test_pins_reach_current_and_new_peers() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    a.sync
+        .pin("work-address", "1 Main St".to_string())
+        .await
+        .unwrap();
+    b.process_next().await.expect("node-b rejected pins");
+    assert_eq!(
+        b.sync.get_pin("work-address").await.as_deref(),
+        Some("1 Main St")
+    );
+
+    let mut c = TestNode::join(&network, "node-c").await;
+    a.announce().await;
+    c.process_next().await.expect("node-c rejected discovery");
+    c.announce().await;
+    a.process_next().await.expect("node-a rejected discovery");
+    c.process_next().await.expect("node-c rejected pins");
+    assert_eq!(
+        c.sync.get_pin("work-address").await.as_deref(),
+        Some("1 Main St")
+    );
+
+    assert!(b.sync.unpin("work-address").await.unwrap());
+    a.drain().await;
+    assert!(a.sync.get_pins().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_large_content_is_offered_via_taildrop() {
+    let network = InMemoryNetwork::new();
+    let sync_config = SyncConfig {
+        taildrop_threshold: Some(16),
+        ..SyncConfig::default()
+    };
+    let (a, mut b) = replaying_pair(&network, sync_config).await;
+
+    let content = "x".repeat(64);
+    a.clipboard.simulate_copy(&content);
+
+    let message = b.next_message().await;
+    let MessageData::TaildropOffer(ref offer) = message.data else {
+        panic!("expected a Taildrop offer, got {:?}", message);
+    };
+    let staged = taildrop::staging_path(&offer.file_name).unwrap();
+    assert_eq!(offer.size, 64);
+    assert_eq!(std::fs::read_to_string(&staged).unwrap(), content);
+    std::fs::remove_file(staged).unwrap();
+
+    b.sync
+        .handle_message(message)
+        .await
+        .expect("node-b rejected Taildrop offer");
+    assert_eq!(b.clipboard.contents(), "");
+}
+
+#[tokio::test]
+async fn test_rediscovery_relearns_peers() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    assert_eq!(a.sync.forget_peers().await, 1);
+    let rediscovery = a.sync.create_rediscovery_message().await.unwrap();
+    a.transport.send_message(rediscovery).await.unwrap();
+
+    b.process_next().await.expect("node-b rejected rediscovery");
+    a.process_next()
+        .await
+        .expect("node-a rejected discovery reply");
+    assert!(a.sync.get_nodes().await.contains_key("node-b"));
+    assert!(a.sync.get_crypto_session("node-b").await.is_some());
+}
+
+#[tokio::test]
+async fn test_node_id_change_is_relearned_by_peers() {
+    let network = InMemoryNetwork::new();
+    let (mut a, mut b) = connected_pair(&network).await;
+
+    a.sync.update_node_id("node-a2".to_string()).await.unwrap();
+    assert!(a.sync.get_nodes().await.is_empty());
+    assert!(a.sync.get_crypto_session("node-b").await.is_none());
+
+    b.process_next().await.expect("node-b rejected rediscovery");
+    let nodes = b.sync.get_nodes().await;
+    assert!(nodes.contains_key("node-a2"));
+    assert!(!nodes.contains_key("node-a"));
+    assert!(b.sync.get_crypto_session("node-a").await.is_none());
+
+    a.process_next()
+        .await
+        .expect("node-a rejected discovery reply");
+    assert!(a.sync.get_crypto_session("node-b").await.is_some());
+
+    a.clipboard.simulate_copy("after re-login");
+    b.process_update().await.expect("node-b rejected update");
+    assert_eq!(b.clipboard.contents(), "after re-login");
+}
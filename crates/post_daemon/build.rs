@@ -0,0 +1,13 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generate the gRPC service from `proto/post.proto` with a bundled `protoc`
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc for this host");
+    std::env::set_var("PROTOC", protoc);
+    println!("cargo:rerun-if-changed=proto/post.proto");
+    tonic_build::compile_protos("proto/post.proto").expect("failed to compile proto/post.proto");
+}
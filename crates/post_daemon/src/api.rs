@@ -0,0 +1,3692 @@
+use crate::audit::{AuditKind, AuditLog};
+use crate::storage::Cleanup;
+use axum::body::StreamBody;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{FromRequestParts, Path, Query, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
+use axum::{async_trait, Json, Router};
+use post_core::directory::PeerDirectory;
+use post_core::{
+    key_fingerprint, ClipboardHealth, ClipboardSource, ClockSkew, DeliveryReport, DeliveryState,
+    EventSender, FilterConfig, NodeInfo, OfflinePeer, PeerStats, PeerTrust, PostConfig, PostError,
+    Result, StorageConfig, SyncEvent, SyncManager, TailscaleTransport, Transport,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{broadcast, watch, Mutex};
+use tracing::{info, warn};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+#[cfg(feature = "grpc")]
+mod grpc;
+mod pairing;
+mod tls;
+
+#[cfg(feature = "grpc")]
+pub use grpc::start_grpc_server;
+pub use pairing::{paired_devices_path, PairedDevice, PairingStore};
+
+/// How long in-flight requests may take to finish once the daemon stops
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// How long a pinged or collected peer has to answer
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Rounds per payload size a bench runs when none are given
+pub const DEFAULT_BENCH_ROUNDS: usize = 20;
+
+/// Most rounds per payload size a bench may run
+const MAX_BENCH_ROUNDS: usize = 1000;
+
+/// Longest a bench may run, so a peer that stopped answering can't hold the request
+const MAX_BENCH_DURATION: Duration = Duration::from_secs(120);
+
+/// What the API handlers need from the running daemon
+#[derive(Clone)]
+pub struct ApiState {
+    pub sync_manager: Arc<Mutex<Option<Arc<SyncManager>>>>,
+    pub transport: Arc<dyn Transport>,
+    /// Bearer token required by endpoints that accept clipboard content
+    pub token: Arc<str>,
+    pub filters: FilterConfig,
+    /// Sync activity streamed to `/api/v1/events` subscribers
+    pub events: EventSender,
+    /// Ends event streams when the daemon stops, so shutdown isn't held up by them
+    pub shutdown: watch::Receiver<bool>,
+    /// Devices such as phones that were paired instead of given the API token
+    pub pairing: Arc<PairingStore>,
+    pub storage: StorageConfig,
+    /// Set while local clipboard changes are left unsynced
+    pub paused: Arc<AtomicBool>,
+    /// Where each request is recorded, when `audit.enabled` is set
+    pub audit: Option<Arc<AuditLog>>,
+    /// Names of tailnet peers, kept current by the daemon
+    pub peer_directory: Arc<PeerDirectory>,
+    /// What `/health/ready` can't read from the rest of the daemon
+    pub health: HealthState,
+    /// Messages from peers the daemon's inbox had no room for
+    pub inbox: post_core::inbox::InboxCounters,
+    /// The config in effect, updated when the daemon reloads it
+    pub config: Arc<std::sync::RwLock<PostConfig>>,
+}
+
+/// Health the daemon records as it happens, for readiness checks
+#[derive(Clone, Default)]
+pub struct HealthState {
+    /// Why the local clipboard can't be read, until it recovers
+    clipboard_error: Arc<std::sync::Mutex<Option<String>>>,
+}
+
+impl HealthState {
+    pub fn record_clipboard(&self, health: &ClipboardHealth) {
+        *self.lock() = match health {
+            ClipboardHealth::Unavailable(error) => Some(error.clone()),
+            ClipboardHealth::Recovered => None,
+        };
+    }
+
+    pub fn clipboard_error(&self) -> Option<String> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<String>> {
+        self.clipboard_error
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Machine-readable description of every endpoint, served at `/api/v1/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Post API",
+        description = "Local control API for the Post daemon"
+    ),
+    paths(
+        get_liveness,
+        get_readiness,
+        get_status,
+        get_peers,
+        get_peer_circuits,
+        get_offline_peers,
+        ping_peer,
+        collect_peer,
+        bench_peer,
+        get_last_sync,
+        get_stats,
+        get_debug_state,
+        refresh_discovery,
+        openapi_spec,
+        push_clipboard,
+        push_url,
+        get_clipboard_stack,
+        search_clipboard_stack,
+        get_pins,
+        create_pin,
+        remove_pin,
+        set_log_level,
+        set_paused,
+        stream_events,
+        start_pairing,
+        complete_pairing,
+        get_paired_devices,
+        revoke_paired_device,
+        websocket,
+        clean_storage
+    ),
+    components(schemas(
+        LivenessResponse,
+        ReadinessResponse,
+        HealthCheck,
+        StatusResponse,
+        ClipboardSourceResponse,
+        ClockSkewResponse,
+        PeerResponse,
+        PeerCircuitResponse,
+        OfflinePeerResponse,
+        PingResponse,
+        CollectResponse,
+        BenchRequest,
+        BenchResultResponse,
+        LastSyncResponse,
+        PeerDeliveryResponse,
+        StatsResponse,
+        DroppedMessages,
+        DebugState,
+        DebugNode,
+        DebugSession,
+        DebugQueues,
+        PeerSyncStats,
+        SyncStats,
+        RediscoverResponse,
+        ClipboardPush,
+        PushUrlRequest,
+        PushResponse,
+        StackResponse,
+        StackSearchResponse,
+        StackMatch,
+        PinsResponse,
+        PinnedItem,
+        PinRequest,
+        UnpinResponse,
+        LogLevel,
+        SyncPause,
+        PairingRequest,
+        PairingOffer,
+        PairingCompletion,
+        PairingResult,
+        PairedDevice,
+        PairedDevicesResponse,
+        RevokeResponse,
+        CleanRequest,
+        Cleanup,
+        ErrorBody
+    )),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+/// The daemon process is up and answering
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LivenessResponse {
+    pub pid: u32,
+}
+
+/// Whether the daemon can sync, and what stops it if not
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReadinessResponse {
+    /// Set when every check passes
+    pub ready: bool,
+    pub checks: Vec<HealthCheck>,
+}
+
+impl ReadinessResponse {
+    /// The checks that failed
+    pub fn failing(&self) -> impl Iterator<Item = &HealthCheck> {
+        self.checks.iter().filter(|check| !check.ok)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HealthCheck {
+    /// `clipboard`, `listener`, `tailscale` or `sync`
+    pub name: String,
+    pub ok: bool,
+    /// Why the check failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl HealthCheck {
+    fn new(name: &str, failure: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            ok: failure.is_none(),
+            detail: failure,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StatusResponse {
+    /// Whether Tailscale is up
+    pub connected: bool,
+    /// Unset while the daemon is waiting for Tailscale
+    pub node_id: Option<String>,
+    pub node_name: Option<String>,
+    pub peer_count: usize,
+    /// Peers that have yet to acknowledge our latest update
+    pub pending_acks: usize,
+    /// Peers advertising a Post version that may not interoperate with this one
+    pub incompatible_peers: usize,
+    /// Whether copies here are left unsynced until sync is resumed
+    pub paused: bool,
+    /// Where the local clipboard's current content came from; unset until it changes
+    pub clipboard_source: Option<ClipboardSourceResponse>,
+    /// Peers whose clock is further off this node's than `sync.max_clock_skew_secs`
+    #[serde(default)]
+    pub clock_skew: Vec<ClockSkewResponse>,
+}
+
+/// The node the local clipboard's current content was copied on
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClipboardSourceResponse {
+    pub node_id: String,
+    pub node_name: String,
+    /// Whether it was copied on this node
+    pub local: bool,
+    /// App it was copied in, when the peer shares it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Unix time it was copied
+    pub timestamp: u64,
+}
+
+impl From<ClipboardSource> for ClipboardSourceResponse {
+    fn from(source: ClipboardSource) -> Self {
+        Self {
+            node_id: source.node_id,
+            node_name: source.node_name,
+            local: source.local,
+            app: source.app,
+            timestamp: source.timestamp,
+        }
+    }
+}
+
+/// A peer whose clock is off this node's, as seen in its latest heartbeat
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClockSkewResponse {
+    pub node_id: String,
+    pub node_name: String,
+    /// Seconds the peer's clock is ahead; negative when it is behind
+    pub skew_secs: i64,
+}
+
+impl From<ClockSkew> for ClockSkewResponse {
+    fn from(skew: ClockSkew) -> Self {
+        Self {
+            node_id: skew.node_id,
+            node_name: skew.node_name,
+            skew_secs: skew.skew_secs,
+        }
+    }
+}
+
+/// Whether copies here are synced, for `PUT /api/v1/sync/paused`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SyncPause {
+    pub paused: bool,
+}
+
+/// A peer known from discovery, with its sync activity since the daemon started
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PeerResponse {
+    pub id: String,
+    pub name: String,
+    /// Unix time of the peer's last discovery announcement
+    pub last_seen: u64,
+    /// Hex-encoded Ed25519 public key
+    pub public_key: String,
+    pub updates_received: u64,
+    pub acks_received: u64,
+    /// Unix time of the last clipboard update received from the peer
+    pub last_update: Option<u64>,
+    pub awaiting_ack: bool,
+    /// Post version the peer advertised; unset for older builds
+    pub version: Option<String>,
+    /// Optional features the peer advertised
+    pub capabilities: Vec<String>,
+    /// False when the peer's version may not interoperate with this one
+    pub compatible: bool,
+    /// Address the peer asked to be reached on instead of its Tailscale IP
+    pub address: Option<String>,
+    /// Sync port the peer advertised; unset for older builds
+    pub port: Option<u16>,
+    /// `remembered` until a peer known from an earlier run announces itself again,
+    /// `confirmed` after
+    #[serde(default)]
+    #[schema(value_type = String)]
+    pub trust: PeerTrust,
+}
+
+/// A peer dropped after going quiet, until it announces itself again
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct OfflinePeerResponse {
+    pub id: String,
+    pub name: String,
+    /// Unix time the peer was last heard from
+    pub last_seen: u64,
+    /// Unix time it was dropped
+    pub evicted_at: u64,
+}
+
+impl From<OfflinePeer> for OfflinePeerResponse {
+    fn from(peer: OfflinePeer) -> Self {
+        Self {
+            id: peer.node_id,
+            name: peer.node_name,
+            last_seen: peer.last_seen,
+            evicted_at: peer.evicted_at,
+        }
+    }
+}
+
+/// A peer address whose latest sends failed
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PeerCircuitResponse {
+    pub address: String,
+    /// Tailnet name of the peer, if it is still online
+    pub name: Option<String>,
+    pub consecutive_failures: u32,
+    /// Seconds until the next send probes the peer; unset while it is not being skipped
+    pub retry_in: Option<u64>,
+}
+
+/// A peer's answer to a ping
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PingResponse {
+    pub round_trip_ms: f64,
+    /// Whether the peer could verify the ping's signature with the key it knows for us
+    pub verified: bool,
+}
+
+/// What `POST /api/v1/peers/{node}/bench` measures
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct BenchRequest {
+    /// Payload sizes in bytes; 100 B, 10 KB, 100 KB and 1 MB when empty
+    #[serde(default)]
+    pub sizes: Vec<usize>,
+    /// Rounds per size, 20 unless given
+    #[serde(default)]
+    pub rounds: Option<usize>,
+}
+
+/// Round trips of one payload size to a peer
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BenchResultResponse {
+    pub size: usize,
+    pub answered: usize,
+    /// Rounds the peer didn't answer in time
+    pub lost: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    /// Payload bytes per second over the answered rounds
+    pub throughput_bytes_per_sec: f64,
+}
+
+/// A peer's current clipboard, as collected from it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CollectResponse {
+    pub node: String,
+    pub content: String,
+}
+
+/// Which peers have applied this node's latest clipboard update
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LastSyncResponse {
+    pub sequence: u64,
+    /// Unix time the update was sent
+    pub timestamp: u64,
+    /// The peers known when it was sent
+    pub peers: Vec<PeerDeliveryResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PeerDeliveryResponse {
+    pub node_id: String,
+    pub node_name: String,
+    /// "applied", "pending", or "offline" when the peer went away before applying it
+    #[schema(value_type = String)]
+    pub state: DeliveryState,
+}
+
+impl From<DeliveryReport> for LastSyncResponse {
+    fn from(report: DeliveryReport) -> Self {
+        Self {
+            sequence: report.sequence,
+            timestamp: report.timestamp,
+            peers: report
+                .peers
+                .into_iter()
+                .map(|peer| PeerDeliveryResponse {
+                    node_id: peer.node_id,
+                    node_name: peer.node_name,
+                    state: peer.state,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Counters since the daemon started, overall and for each peer
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StatsResponse {
+    pub total: SyncStats,
+    pub peers: Vec<PeerSyncStats>,
+    /// Messages from peers dropped because they arrived faster than they could be handled
+    #[serde(default)]
+    pub dropped: DroppedMessages,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DroppedMessages {
+    /// Queued clipboard updates replaced by newer ones
+    pub clipboard_updates: u64,
+    pub other: u64,
+}
+
+/// Daemon internals for bug reports, from `GET /api/v1/debug/state`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DebugState {
+    /// Post version of the daemon
+    pub version: String,
+    /// Unix time the state was read
+    pub generated_at: u64,
+    pub status: StatusResponse,
+    /// Discovered peers, by node ID
+    pub nodes: Vec<DebugNode>,
+    /// Peers with an encryption session
+    pub sessions: Vec<DebugSession>,
+    pub queues: DebugQueues,
+    /// Config the daemon is running with, credentials redacted
+    #[schema(value_type = Object)]
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DebugNode {
+    pub id: String,
+    pub name: String,
+    /// Unix time it was last heard from
+    pub last_seen: u64,
+    pub key_fingerprint: String,
+    pub version: Option<String>,
+    pub capabilities: Vec<String>,
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    /// `confirmed` or `remembered`
+    #[schema(value_type = String)]
+    pub trust: PeerTrust,
+}
+
+impl From<NodeInfo> for DebugNode {
+    fn from(node: NodeInfo) -> Self {
+        Self {
+            key_fingerprint: key_fingerprint(&node.public_key),
+            trust: node.trust,
+            id: node.id,
+            name: node.name,
+            last_seen: node.last_seen,
+            version: node.version,
+            capabilities: node.capabilities,
+            address: node.address,
+            port: node.port,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DebugSession {
+    pub node_id: String,
+    /// Fingerprint of the public key the session was derived from
+    pub public_key: Option<String>,
+    /// Fingerprint of the key the peer's messages are verified with
+    pub verifying_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct DebugQueues {
+    /// Peers with an unacknowledged update
+    pub pending_acks: usize,
+    /// Updates kept for peers that missed them
+    pub recent_updates: usize,
+    pub clipboard_stack: usize,
+    /// Pings, collects and benches waiting for a reply
+    pub awaiting_replies: usize,
+    pub offline_peers: usize,
+    /// Messages from peers dropped because the inbox was full
+    pub inbox_dropped: DroppedMessages,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PeerSyncStats {
+    pub id: String,
+    /// Unset for peers that have since been forgotten
+    pub name: Option<String>,
+    pub stats: SyncStats,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct SyncStats {
+    pub updates_sent: u64,
+    pub bytes_sent: u64,
+    pub updates_received: u64,
+    pub bytes_received: u64,
+    /// Updates given up on after going unacknowledged for a day
+    pub updates_failed: u64,
+    /// Mean time for a peer to acknowledge an update, when any were acknowledged first try
+    pub average_ack_latency_ms: Option<u64>,
+}
+
+impl From<&PeerStats> for SyncStats {
+    fn from(stats: &PeerStats) -> Self {
+        Self {
+            updates_sent: stats.updates_sent,
+            bytes_sent: stats.bytes_sent,
+            updates_received: stats.updates_received,
+            bytes_received: stats.bytes_received,
+            updates_failed: stats.updates_failed,
+            average_ack_latency_ms: stats
+                .average_ack_latency()
+                .map(|latency| latency.as_millis() as u64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RediscoverResponse {
+    /// Peers whose keys and sessions were dropped to be re-learned
+    pub forgotten_peers: usize,
+}
+
+/// Clipboard content from a browser; `text` wins over `html` when both are given
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClipboardPush {
+    pub text: Option<String>,
+    pub html: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PushUrlRequest {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PushResponse {
+    /// False when the content matched what was last synced
+    pub sent: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StackResponse {
+    /// Recent clipboard items, newest first
+    pub items: Vec<String>,
+}
+
+/// Query of a clipboard stack search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StackSearch {
+    pub q: String,
+    /// Most matches returned
+    #[serde(default = "default_search_limit")]
+    pub limit: usize,
+}
+
+fn default_search_limit() -> usize {
+    20
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StackSearchResponse {
+    /// Matching items, newest first
+    pub matches: Vec<StackMatch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StackMatch {
+    /// Stack position, as used by `index` when pinning
+    pub index: usize,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PinsResponse {
+    pub pins: Vec<PinnedItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PinnedItem {
+    pub name: String,
+    pub content: String,
+}
+
+/// Pin a clipboard stack item by `index`, or the given `content`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PinRequest {
+    pub name: String,
+    pub index: Option<usize>,
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UnpinResponse {
+    /// False when nothing was pinned under the name
+    pub removed: bool,
+}
+
+/// Log filter directives, e.g. `debug` or `info,post_core::sync=trace`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LogLevel {
+    pub filter: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PairingRequest {
+    /// Base URL the device will reach this API at, e.g. `http://100.64.0.1:8787`
+    pub endpoint: String,
+}
+
+/// Everything a device needs to pair, also encoded as a `post://pair` URI for a QR code
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PairingOffer {
+    /// Single-use code the device exchanges for its own API token
+    pub code: String,
+    pub endpoint: String,
+    /// Fingerprint of this node's signing key, for the device to check after pairing
+    pub fingerprint: String,
+    pub node_id: String,
+    /// Seconds until the code expires
+    pub expires_in: u64,
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PairingCompletion {
+    pub code: String,
+    pub device_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PairingResult {
+    /// Bearer token for the API and `/api/v1/ws`, until the device is revoked
+    pub token: String,
+    pub fingerprint: String,
+    pub node_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PairedDevicesResponse {
+    pub devices: Vec<PairedDevice>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RevokeResponse {
+    /// False if no device was paired under the name
+    pub revoked: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct CleanRequest {
+    /// Report what would be removed without removing it
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Messages a client sends over `/api/v1/ws`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SocketRequest {
+    /// Broadcast `text`, like `POST /api/v1/clipboard`
+    Push { text: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+struct ErrorBody {
+    error: String,
+}
+
+/// A failed request, returned to the client as JSON
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn unavailable(message: &str) -> Self {
+        Self::new(StatusCode::SERVICE_UNAVAILABLE, message)
+    }
+
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+}
+
+impl From<PostError> for ApiError {
+    fn from(error: PostError) -> Self {
+        let status = match error {
+            PostError::Filtered(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: self.message,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Extractor rejecting requests without `Authorization: Bearer <token>`, where the token
+/// is the API token or a paired device's
+struct Authenticated;
+
+#[async_trait]
+impl FromRequestParts<ApiState> for Authenticated {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApiState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        match bearer_token(parts) {
+            Some(token)
+                if tokens_match(token, &state.token) || state.pairing.is_device_token(token) =>
+            {
+                Ok(Authenticated)
+            }
+            _ => Err(invalid_token()),
+        }
+    }
+}
+
+/// Like [`Authenticated`], but only the API token itself will do, so paired devices
+/// can't pair or revoke others
+struct Owner;
+
+#[async_trait]
+impl FromRequestParts<ApiState> for Owner {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApiState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        match bearer_token(parts) {
+            Some(token) if tokens_match(token, &state.token) => Ok(Owner),
+            _ => Err(invalid_token()),
+        }
+    }
+}
+
+/// Like [`Authenticated`], but only while the API can be reached from other devices;
+/// the read-only endpoints stay open on loopback and Unix sockets
+struct LocalOrAuthenticated;
+
+#[async_trait]
+impl FromRequestParts<ApiState> for LocalOrAuthenticated {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ApiState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        let local_only = state
+            .config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .api
+            .is_local_only();
+        if local_only {
+            return Ok(LocalOrAuthenticated);
+        }
+        Authenticated::from_request_parts(parts, state)
+            .await
+            .map(|Authenticated| LocalOrAuthenticated)
+    }
+}
+
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+fn invalid_token() -> ApiError {
+    ApiError::new(StatusCode::UNAUTHORIZED, "Missing or invalid API token")
+}
+
+/// Compare without exiting early so timing doesn't reveal the token
+fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// File holding the API token, readable only by the owner
+pub fn api_token_path() -> Result<PathBuf> {
+    Ok(crate::private_data_dir()?.join("api_token"))
+}
+
+/// Read the API token the daemon generated, without making one
+///
+/// Clients use this, so running a command before the daemon ever started doesn't leave
+/// a token behind.
+pub async fn load_api_token() -> Result<String> {
+    let path = api_token_path()?;
+    match read_api_token(&path).await? {
+        Some(token) => Ok(token),
+        None => Err(PostError::Config(format!(
+            "No API token at {}; start the daemon to generate one",
+            path.display()
+        ))),
+    }
+}
+
+/// Token stored at `path`, or `None` if there is none yet
+async fn read_api_token(path: &std::path::Path) -> Result<Option<String>> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(token) => Ok(Some(token.trim().to_string()).filter(|token| !token.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read the API token, generating one the first time; only the daemon does this
+pub async fn load_or_create_api_token() -> Result<String> {
+    let path = api_token_path()?;
+    if let Some(token) = read_api_token(&path).await? {
+        return Ok(token);
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = to_hex(&bytes);
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    // Created owner-only rather than narrowed afterwards, so it's never readable by others
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&path).await?;
+    file.write_all(token.as_bytes()).await?;
+    file.flush().await?;
+
+    info!("Generated API token at {}", path.display());
+    Ok(token)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn router(state: ApiState) -> Router {
+    let router = Router::new()
+        .route("/health/live", get(get_liveness))
+        .route("/health/ready", get(get_readiness))
+        .route("/api/v1/openapi.json", get(openapi_spec))
+        .route("/api/v1/status", get(get_status))
+        .route("/api/v1/peers", get(get_peers))
+        .route("/api/v1/peers/circuits", get(get_peer_circuits))
+        .route("/api/v1/peers/offline", get(get_offline_peers))
+        .route("/api/v1/peers/:node/ping", post(ping_peer))
+        .route("/api/v1/peers/:node/collect", post(collect_peer))
+        .route("/api/v1/peers/:node/bench", post(bench_peer))
+        .route("/api/v1/stats", get(get_stats))
+        .route("/api/v1/debug/state", get(get_debug_state))
+        .route("/api/v1/discovery/refresh", post(refresh_discovery))
+        .route("/api/v1/clipboard", post(push_clipboard))
+        .route("/api/v1/clipboard/push-url", post(push_url))
+        .route("/api/v1/clipboard/stack", get(get_clipboard_stack))
+        .route(
+            "/api/v1/clipboard/stack/search",
+            get(search_clipboard_stack),
+        )
+        .route("/api/v1/pins", get(get_pins).post(create_pin))
+        .route("/api/v1/pins/:name", delete(remove_pin))
+        .route("/api/v1/log-level", put(set_log_level))
+        .route("/api/v1/sync/paused", put(set_paused))
+        .route("/api/v1/sync/last", get(get_last_sync))
+        .route("/api/v1/events", get(stream_events))
+        .route("/api/v1/pairing", post(start_pairing))
+        .route("/api/v1/pairing/complete", post(complete_pairing))
+        .route("/api/v1/pairing/devices", get(get_paired_devices))
+        .route(
+            "/api/v1/pairing/devices/:name",
+            delete(revoke_paired_device),
+        )
+        .route("/api/v1/ws", get(websocket))
+        .route("/api/v1/storage/clean", post(clean_storage));
+
+    #[cfg(feature = "swagger-ui")]
+    let router = router.merge(swagger_ui());
+
+    router
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            audit_request,
+        ))
+        .with_state(state)
+}
+
+/// Record who made each request and how it was answered in the audit log
+///
+/// Only the path is recorded: query strings such as a stack search can hold content.
+async fn audit_request<B>(
+    State(state): State<ApiState>,
+    request: axum::http::Request<B>,
+    next: axum::middleware::Next<B>,
+) -> Response {
+    let Some(audit) = state.audit.clone() else {
+        return next.run(request).await;
+    };
+    let caller = describe_caller(
+        &state,
+        request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer ")),
+    );
+    let action = format!("{} {}", request.method(), request.uri().path());
+
+    let response = next.run(request).await;
+    audit.record(
+        AuditKind::Api,
+        caller,
+        format!("{} -> {}", action, response.status().as_u16()),
+    );
+    response
+}
+
+/// Who made a request with the bearer token `token`, for the audit log
+fn describe_caller(state: &ApiState, token: Option<&str>) -> String {
+    match token {
+        None => "anonymous".to_string(),
+        Some(token) if tokens_match(token, &state.token) => "API token".to_string(),
+        Some(token) => match state.pairing.device_name(token) {
+            Some(name) => format!("paired device {}", name),
+            None => "invalid token".to_string(),
+        },
+    }
+}
+
+/// Serve the API on `addr`, over HTTPS when `tls` is set, until `shutdown` becomes true
+pub async fn start_api_server(
+    state: ApiState,
+    addr: SocketAddr,
+    tls: bool,
+    shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    bind_api_server(state, addr, tls, shutdown)?.await
+}
+
+/// Bind the API to `addr`, returning the future that serves it
+///
+/// Split from serving so the daemon can tell whoever started it that the API is up.
+pub fn bind_api_server(
+    state: ApiState,
+    addr: SocketAddr,
+    tls: bool,
+    shutdown: watch::Receiver<bool>,
+) -> Result<impl Future<Output = Result<()>> + Send + 'static> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| PostError::Network(format!("Failed to bind API to {}: {}", addr, e)))?;
+    info!("Starting HTTP API on {}", addr);
+
+    Ok(async move {
+        let shutdown = shutdown_requested(shutdown);
+        if tls {
+            tls::serve_tls(listener, state, shutdown).await
+        } else {
+            serve(listener, state, shutdown).await
+        }
+    })
+}
+
+/// Serve the API on a Unix socket at `path` until `shutdown` becomes true
+///
+/// The socket is made readable and writable by its owner only, so the API isn't reachable
+/// over the network or by other users.
+pub async fn start_unix_api_server(
+    state: ApiState,
+    path: PathBuf,
+    shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    bind_unix_api_server(state, path, shutdown)?.await
+}
+
+/// Bind the API to a Unix socket at `path`, returning the future that serves it
+#[cfg(unix)]
+pub fn bind_unix_api_server(
+    state: ApiState,
+    path: PathBuf,
+    shutdown: watch::Receiver<bool>,
+) -> Result<impl Future<Output = Result<()>> + Send + 'static> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => std::path::Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+    // Only a socket, e.g. one left by a daemon that didn't stop cleanly, is replaced
+    match std::fs::symlink_metadata(&path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(PostError::Config(format!(
+                "api.listen: {} exists and isn't a socket",
+                path.display()
+            )));
+        }
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    // Bound in a directory only the owner can enter, so nobody can connect before the
+    // socket is made private, then moved into place
+    let staging = dir.join(format!(".post-api-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staging.join("api.sock");
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .map_err(|e| PostError::Network(format!("Failed to bind API to {}: {}", path.display(), e)))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&staged, &path)?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_dir_all(&staging);
+    let listener = bound?;
+    info!("Starting HTTP API on unix:{}", path.display());
+
+    Ok(async move {
+        let connections = futures_util::stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        });
+        let result = axum::Server::builder(hyper::server::accept::from_stream(connections))
+            .serve(router(state).into_make_service())
+            .with_graceful_shutdown(shutdown_requested(shutdown))
+            .await
+            .map_err(|e| PostError::Network(format!("API server failed: {}", e)));
+        let _ = std::fs::remove_file(&path);
+        result
+    })
+}
+
+#[cfg(not(unix))]
+pub fn bind_unix_api_server(
+    _: ApiState,
+    _: PathBuf,
+    _: watch::Receiver<bool>,
+) -> Result<std::future::Ready<Result<()>>> {
+    Err(PostError::Config(
+        "api.listen needs Unix sockets, which this platform lacks".to_string(),
+    ))
+}
+
+/// Resolves once `shutdown` is set, or its sender is gone
+async fn shutdown_requested(mut shutdown: watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+    info!("Stopping HTTP API");
+}
+
+async fn serve(
+    listener: TcpListener,
+    state: ApiState,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    axum::Server::from_tcp(listener)
+        .map_err(|e| PostError::Network(format!("Failed to start API server: {}", e)))?
+        .serve(router(state).into_make_service())
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(|e| PostError::Network(format!("API server failed: {}", e)))
+}
+
+/// OpenAPI document describing this API
+#[utoipa::path(
+    get,
+    path = "/api/v1/openapi.json",
+    responses((status = 200, description = "OpenAPI 3 document", content_type = "application/json"))
+)]
+async fn openapi_spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI rendering the OpenAPI document, with its assets built into the binary
+#[cfg(feature = "swagger-ui")]
+fn swagger_ui() -> utoipa_swagger_ui::SwaggerUi {
+    utoipa_swagger_ui::SwaggerUi::new("/api/v1/docs")
+        .config(utoipa_swagger_ui::Config::from("/api/v1/openapi.json"))
+}
+
+/// Whether the daemon process is up, however well it is doing
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    responses((status = 200, description = "Daemon is running", body = LivenessResponse))
+)]
+async fn get_liveness() -> Json<LivenessResponse> {
+    Json(LivenessResponse {
+        pid: std::process::id(),
+    })
+}
+
+/// Whether the daemon can sync: its clipboard works, it accepts peers' connections,
+/// and Tailscale is up
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "Daemon is ready", body = ReadinessResponse),
+        (status = 503, description = "Some check failed", body = ReadinessResponse)
+    )
+)]
+async fn get_readiness(State(state): State<ApiState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let readiness = daemon_readiness(&state).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+async fn daemon_readiness(state: &ApiState) -> ReadinessResponse {
+    let tailscale = match state.transport.is_connected().await {
+        Ok(true) => None,
+        Ok(false) => Some("Tailscale is not connected".to_string()),
+        Err(e) => Some(e.to_string()),
+    };
+    let sync = match state.sync_manager.lock().await.is_some() {
+        true => None,
+        false => Some("Waiting for Tailscale to start syncing".to_string()),
+    };
+    let checks = vec![
+        HealthCheck::new("clipboard", state.health.clipboard_error()),
+        HealthCheck::new(
+            "listener",
+            (!state.transport.is_listening())
+                .then(|| "Not accepting connections from peers".to_string()),
+        ),
+        HealthCheck::new("tailscale", tailscale),
+        HealthCheck::new("sync", sync),
+    ];
+    ReadinessResponse {
+        ready: checks.iter().all(|check| check.ok),
+        checks,
+    }
+}
+
+/// This node's identity and sync state
+#[utoipa::path(
+    get,
+    path = "/api/v1/status",
+    responses(
+        (status = 200, description = "Daemon status", body = StatusResponse),
+        (status = 401, description = "Missing or invalid API token, when the API is reachable from other devices", body = ErrorBody)
+    ),
+    security((), ("api_token" = []))
+)]
+async fn get_status(
+    _: LocalOrAuthenticated,
+    State(state): State<ApiState>,
+) -> Json<StatusResponse> {
+    Json(daemon_status(&state.sync_manager, state.transport.as_ref(), &state.paused).await)
+}
+
+/// Status of the daemon owning `sync_manager` and `transport`
+pub async fn daemon_status(
+    sync_manager: &Mutex<Option<Arc<SyncManager>>>,
+    transport: &dyn Transport,
+    paused: &AtomicBool,
+) -> StatusResponse {
+    let connected = transport.is_connected().await.unwrap_or(false);
+    let paused = paused.load(Ordering::Relaxed);
+    let sync_manager = sync_manager.lock().await.clone();
+
+    match sync_manager {
+        Some(sync_manager) => {
+            let nodes = sync_manager.get_nodes().await;
+            StatusResponse {
+                connected,
+                node_id: Some(sync_manager.get_node_id().await),
+                node_name: Some(sync_manager.get_node_name().await),
+                peer_count: nodes.len(),
+                pending_acks: sync_manager.pending_ack_count().await,
+                incompatible_peers: nodes.values().filter(|node| !node.is_compatible()).count(),
+                paused,
+                clipboard_source: sync_manager.get_clipboard_source().await.map(Into::into),
+                clock_skew: sync_manager
+                    .clock_skews()
+                    .await
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            }
+        }
+        None => StatusResponse {
+            connected,
+            node_id: None,
+            node_name: None,
+            peer_count: 0,
+            pending_acks: 0,
+            incompatible_peers: 0,
+            paused,
+            clipboard_source: None,
+            clock_skew: Vec::new(),
+        },
+    }
+}
+
+/// Peers learned through discovery, most recently seen first
+#[utoipa::path(
+    get,
+    path = "/api/v1/peers",
+    responses(
+        (status = 200, description = "Known peers", body = [PeerResponse]),
+        (status = 401, description = "Missing or invalid API token, when the API is reachable from other devices", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security((), ("api_token" = []))
+)]
+async fn get_peers(
+    _: LocalOrAuthenticated,
+    State(state): State<ApiState>,
+) -> std::result::Result<Json<Vec<PeerResponse>>, ApiError> {
+    let sync_manager = current_sync_manager(&state).await?;
+    let mut stats = sync_manager.get_peer_stats().await;
+
+    let mut peers: Vec<PeerResponse> = sync_manager
+        .get_nodes()
+        .await
+        .into_values()
+        .map(|node| {
+            let stats = stats.remove(&node.id).unwrap_or_default();
+            PeerResponse {
+                public_key: to_hex(&node.public_key),
+                compatible: node.is_compatible(),
+                version: node.version,
+                capabilities: node.capabilities,
+                address: node.address,
+                port: node.port,
+                trust: node.trust,
+                id: node.id,
+                name: node.name,
+                last_seen: node.last_seen,
+                updates_received: stats.updates_received,
+                acks_received: stats.acks_received,
+                last_update: stats.last_update,
+                awaiting_ack: stats.awaiting_ack,
+            }
+        })
+        .collect();
+    peers.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then(a.id.cmp(&b.id)));
+
+    Ok(Json(peers))
+}
+
+/// Send a peer a signed ping and wait for its answer
+#[utoipa::path(
+    post,
+    path = "/api/v1/peers/{node}/ping",
+    params(("node" = String, Path, description = "Peer ID or name")),
+    responses(
+        (status = 200, description = "The peer answered", body = PingResponse),
+        (status = 400, description = "Unknown peer, or one that can't answer pings", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody),
+        (status = 504, description = "The peer did not answer in time", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn ping_peer(
+    State(state): State<ApiState>,
+    _: Owner,
+    Path(node): Path<String>,
+) -> std::result::Result<Json<PingResponse>, ApiError> {
+    let reply = current_sync_manager(&state)
+        .await?
+        .ping(&node, PING_TIMEOUT)
+        .await
+        .map_err(|e| match e {
+            PostError::Timeout(_) => ApiError::new(StatusCode::GATEWAY_TIMEOUT, e.to_string()),
+            e => ApiError::bad_request(e.to_string()),
+        })?;
+    Ok(Json(PingResponse {
+        round_trip_ms: reply.round_trip.as_secs_f64() * 1000.0,
+        verified: reply.verified,
+    }))
+}
+
+/// Ask a peer for its current clipboard, which it only shares if it was synced
+#[utoipa::path(
+    post,
+    path = "/api/v1/peers/{node}/collect",
+    params(("node" = String, Path, description = "Peer ID or name")),
+    responses(
+        (status = 200, description = "The peer shared its clipboard", body = CollectResponse),
+        (status = 400, description = "Unknown peer, or one that refused or can't be collected from", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody),
+        (status = 504, description = "The peer did not answer in time", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn collect_peer(
+    State(state): State<ApiState>,
+    _: Authenticated,
+    Path(node): Path<String>,
+) -> std::result::Result<Json<CollectResponse>, ApiError> {
+    let content = current_sync_manager(&state)
+        .await?
+        .collect(&node, PING_TIMEOUT)
+        .await
+        .map_err(|e| match e {
+            PostError::Timeout(_) => ApiError::new(StatusCode::GATEWAY_TIMEOUT, e.to_string()),
+            e => ApiError::bad_request(e.to_string()),
+        })?;
+    Ok(Json(CollectResponse { node, content }))
+}
+
+/// Time synthetic payloads sent to a peer and answered by it, round by round
+#[utoipa::path(
+    post,
+    path = "/api/v1/peers/{node}/bench",
+    params(("node" = String, Path, description = "Peer ID or name")),
+    request_body = BenchRequest,
+    responses(
+        (status = 200, description = "Round trips by payload size", body = [BenchResultResponse]),
+        (status = 400, description = "Unknown peer, one that can't answer benchmarks, or too many rounds or bytes", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn bench_peer(
+    State(state): State<ApiState>,
+    _: Owner,
+    Path(node): Path<String>,
+    Json(request): Json<BenchRequest>,
+) -> std::result::Result<Json<Vec<BenchResultResponse>>, ApiError> {
+    let rounds = request.rounds.unwrap_or(DEFAULT_BENCH_ROUNDS);
+    if rounds == 0 || rounds > MAX_BENCH_ROUNDS {
+        return Err(ApiError::bad_request(format!(
+            "Rounds must be between 1 and {}",
+            MAX_BENCH_ROUNDS
+        )));
+    }
+    let sizes = if request.sizes.is_empty() {
+        post_core::bench::DEFAULT_SIZES.to_vec()
+    } else {
+        request.sizes
+    };
+
+    let results = current_sync_manager(&state)
+        .await?
+        .bench(&node, &sizes, rounds, PING_TIMEOUT, MAX_BENCH_DURATION)
+        .await
+        .map_err(|e| ApiError::bad_request(e.to_string()))?;
+    Ok(Json(
+        results
+            .into_iter()
+            .map(|result| BenchResultResponse {
+                size: result.size,
+                answered: result.answered,
+                lost: result.lost,
+                p50_ms: result.p50.as_secs_f64() * 1000.0,
+                p95_ms: result.p95.as_secs_f64() * 1000.0,
+                throughput_bytes_per_sec: result.throughput,
+            })
+            .collect(),
+    ))
+}
+
+/// Which peers have applied the latest clipboard update sent from here
+#[utoipa::path(
+    get,
+    path = "/api/v1/sync/last",
+    responses(
+        (status = 200, description = "Delivery of the latest update", body = LastSyncResponse),
+        (status = 401, description = "Missing or invalid API token, when the API is reachable from other devices", body = ErrorBody),
+        (status = 404, description = "Nothing has been sent yet", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security((), ("api_token" = []))
+)]
+async fn get_last_sync(
+    _: LocalOrAuthenticated,
+    State(state): State<ApiState>,
+) -> std::result::Result<Json<LastSyncResponse>, ApiError> {
+    current_sync_manager(&state)
+        .await?
+        .last_delivery()
+        .await
+        .map(|report| Json(report.into()))
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Nothing has been sent yet"))
+}
+
+/// Peers dropped for going quiet, most recently dropped first
+#[utoipa::path(
+    get,
+    path = "/api/v1/peers/offline",
+    responses(
+        (status = 200, description = "Offline peers", body = [OfflinePeerResponse]),
+        (status = 401, description = "Missing or invalid API token, when the API is reachable from other devices", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security((), ("api_token" = []))
+)]
+async fn get_offline_peers(
+    _: LocalOrAuthenticated,
+    State(state): State<ApiState>,
+) -> std::result::Result<Json<Vec<OfflinePeerResponse>>, ApiError> {
+    let sync_manager = current_sync_manager(&state).await?;
+    Ok(Json(
+        sync_manager
+            .offline_peers()
+            .await
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    ))
+}
+
+/// Peer addresses whose latest sends failed, and whether they are skipped
+#[utoipa::path(
+    get,
+    path = "/api/v1/peers/circuits",
+    responses(
+        (status = 200, description = "Failing peers", body = [PeerCircuitResponse]),
+        (status = 401, description = "Missing or invalid API token, when the API is reachable from other devices", body = ErrorBody)
+    ),
+    security((), ("api_token" = []))
+)]
+async fn get_peer_circuits(
+    _: LocalOrAuthenticated,
+    State(state): State<ApiState>,
+) -> Json<Vec<PeerCircuitResponse>> {
+    let circuits = state.transport.peer_circuits();
+    Json(
+        circuits
+            .into_iter()
+            .map(|circuit| PeerCircuitResponse {
+                name: state.peer_directory.name_of(&circuit.address),
+                address: circuit.address,
+                consecutive_failures: circuit.consecutive_failures,
+                retry_in: circuit.retry_in,
+            })
+            .collect(),
+    )
+}
+
+/// Sync counters since the daemon started, busiest peers first
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats",
+    responses(
+        (status = 200, description = "Sync counters", body = StatsResponse),
+        (status = 401, description = "Missing or invalid API token, when the API is reachable from other devices", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security((), ("api_token" = []))
+)]
+async fn get_stats(
+    _: LocalOrAuthenticated,
+    State(state): State<ApiState>,
+) -> std::result::Result<Json<StatsResponse>, ApiError> {
+    let sync_manager = current_sync_manager(&state).await?;
+    Ok(Json(sync_stats(&sync_manager, &state.inbox).await))
+}
+
+/// Counters kept by `sync_manager`, busiest peers first, and drops from `inbox`
+pub async fn sync_stats(
+    sync_manager: &SyncManager,
+    inbox: &post_core::inbox::InboxCounters,
+) -> StatsResponse {
+    let stats = sync_manager.get_peer_stats().await;
+    let nodes = sync_manager.get_nodes().await;
+    let dropped = inbox.metrics();
+
+    let mut total = PeerStats::default();
+    for peer in stats.values() {
+        total.updates_sent += peer.updates_sent;
+        total.bytes_sent += peer.bytes_sent;
+        total.updates_received += peer.updates_received;
+        total.bytes_received += peer.bytes_received;
+        total.updates_failed += peer.updates_failed;
+        total.ack_latency_total += peer.ack_latency_total;
+        total.ack_latency_samples += peer.ack_latency_samples;
+    }
+
+    let mut peers: Vec<PeerSyncStats> = stats
+        .iter()
+        .map(|(id, peer)| PeerSyncStats {
+            id: id.clone(),
+            name: nodes.get(id).map(|node| node.name.clone()),
+            stats: SyncStats::from(peer),
+        })
+        .collect();
+    peers.sort_by(|a, b| {
+        let activity = |peer: &PeerSyncStats| peer.stats.updates_sent + peer.stats.updates_received;
+        activity(b).cmp(&activity(a)).then(a.id.cmp(&b.id))
+    });
+
+    StatsResponse {
+        total: SyncStats::from(&total),
+        peers,
+        dropped: DroppedMessages {
+            clipboard_updates: dropped.clipboard_updates_dropped,
+            other: dropped.other_dropped,
+        },
+    }
+}
+
+/// Daemon internals to attach to a bug report: peers, encryption sessions, queues and
+/// config, with keys given only as fingerprints and credentials redacted
+#[utoipa::path(
+    get,
+    path = "/api/v1/debug/state",
+    responses(
+        (status = 200, description = "Daemon state", body = DebugState),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn get_debug_state(
+    State(state): State<ApiState>,
+    _: Authenticated,
+) -> std::result::Result<Json<DebugState>, ApiError> {
+    let status = daemon_status(&state.sync_manager, state.transport.as_ref(), &state.paused).await;
+    let config = state
+        .config
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .redacted();
+    let config = serde_json::to_value(config)
+        .map_err(|e| PostError::Serialization(format!("Failed to serialize the config: {}", e)))?;
+    let dropped = state.inbox.metrics();
+    let mut dump = DebugState {
+        version: post_core::compat::VERSION.to_string(),
+        generated_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0),
+        status,
+        nodes: Vec::new(),
+        sessions: Vec::new(),
+        queues: DebugQueues {
+            inbox_dropped: DroppedMessages {
+                clipboard_updates: dropped.clipboard_updates_dropped,
+                other: dropped.other_dropped,
+            },
+            ..DebugQueues::default()
+        },
+        config,
+    };
+
+    let sync_manager = state.sync_manager.lock().await.clone();
+    if let Some(sync_manager) = sync_manager {
+        let mut nodes: Vec<DebugNode> = sync_manager
+            .get_nodes()
+            .await
+            .into_values()
+            .map(DebugNode::from)
+            .collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        dump.nodes = nodes;
+        dump.sessions = sync_manager
+            .peer_sessions()
+            .await
+            .into_iter()
+            .map(|session| DebugSession {
+                node_id: session.node_id,
+                public_key: session.public_key,
+                verifying_key: session.verifying_key,
+            })
+            .collect();
+        let queues = sync_manager.queue_sizes().await;
+        dump.queues = DebugQueues {
+            pending_acks: queues.pending_acks,
+            recent_updates: queues.recent_updates,
+            clipboard_stack: queues.clipboard_stack,
+            awaiting_replies: queues.awaiting_replies,
+            offline_peers: queues.offline_peers,
+            ..dump.queues
+        };
+    }
+    Ok(Json(dump))
+}
+
+/// Forget every peer and ask the whole tailnet to announce itself again
+#[utoipa::path(
+    post,
+    path = "/api/v1/discovery/refresh",
+    responses(
+        (status = 200, description = "Peers forgotten and rediscovery sent", body = RediscoverResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody),
+        (status = 500, description = "Rediscovery could not be sent", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn refresh_discovery(
+    State(state): State<ApiState>,
+    _: Owner,
+) -> std::result::Result<Json<RediscoverResponse>, ApiError> {
+    let sync_manager = current_sync_manager(&state).await?;
+    let forgotten_peers = sync_manager.forget_peers().await;
+    let message = sync_manager.create_rediscovery_message().await?;
+    state.transport.send_message(message).await?;
+
+    Ok(Json(RediscoverResponse { forgotten_peers }))
+}
+
+/// Broadcast clipboard content sent as `text/plain`, `text/html` or a JSON [`ClipboardPush`]
+#[utoipa::path(
+    post,
+    path = "/api/v1/clipboard",
+    request_body(
+        content = ClipboardPush,
+        description = "Also accepts a raw text/plain or text/html body"
+    ),
+    responses(
+        (status = 200, description = "Content accepted", body = PushResponse),
+        (status = 400, description = "No usable content", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 415, description = "Unsupported content type", body = ErrorBody),
+        (status = 422, description = "Rejected by the configured filters", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn push_clipboard(
+    State(state): State<ApiState>,
+    _: Authenticated,
+    headers: HeaderMap,
+    body: String,
+) -> std::result::Result<Json<PushResponse>, ApiError> {
+    let mime = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let content = match mime.as_str() {
+        "text/plain" => body,
+        "text/html" => html_to_text(&body),
+        "application/json" => {
+            let push: ClipboardPush = serde_json::from_str(&body)
+                .map_err(|e| ApiError::bad_request(format!("Invalid JSON: {}", e)))?;
+            match (push.text, push.html) {
+                (Some(text), _) => text,
+                (None, Some(html)) => html_to_text(&html),
+                (None, None) => {
+                    return Err(ApiError::bad_request("Either text or html is required"))
+                }
+            }
+        }
+        other => {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Unsupported content type: {}", other),
+            ))
+        }
+    };
+
+    broadcast(&state, content).await
+}
+
+/// Broadcast a page URL, e.g. from a browser extension's "send this tab" action
+#[utoipa::path(
+    post,
+    path = "/api/v1/clipboard/push-url",
+    request_body = PushUrlRequest,
+    responses(
+        (status = 200, description = "URL accepted", body = PushResponse),
+        (status = 400, description = "Not an http(s) URL", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 422, description = "Rejected by the configured filters", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn push_url(
+    State(state): State<ApiState>,
+    _: Authenticated,
+    Json(request): Json<PushUrlRequest>,
+) -> std::result::Result<Json<PushResponse>, ApiError> {
+    let url = request.url.trim();
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(ApiError::bad_request(
+            "Only http and https URLs can be pushed",
+        ));
+    }
+    broadcast(&state, url.to_string()).await
+}
+
+/// Recent clipboard items shared across devices, newest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/clipboard/stack",
+    responses(
+        (status = 200, description = "Clipboard stack", body = StackResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn get_clipboard_stack(
+    State(state): State<ApiState>,
+    _: Authenticated,
+) -> std::result::Result<Json<StackResponse>, ApiError> {
+    let items = current_sync_manager(&state)
+        .await?
+        .get_clipboard_stack()
+        .await;
+    Ok(Json(StackResponse { items }))
+}
+
+/// Clipboard stack items containing every word of the query, newest first
+///
+/// Words match case-insensitively and as prefixes, through an index kept with the stack.
+#[utoipa::path(
+    get,
+    path = "/api/v1/clipboard/stack/search",
+    params(
+        ("q" = String, Query, description = "Words to search for"),
+        ("limit" = Option<usize>, Query, description = "Most matches returned, 20 by default")
+    ),
+    responses(
+        (status = 200, description = "Matching stack items", body = StackSearchResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn search_clipboard_stack(
+    State(state): State<ApiState>,
+    _: Authenticated,
+    Query(search): Query<StackSearch>,
+) -> std::result::Result<Json<StackSearchResponse>, ApiError> {
+    let matches = current_sync_manager(&state)
+        .await?
+        .search_clipboard_stack(&search.q, search.limit)
+        .await
+        .into_iter()
+        .map(|found| StackMatch {
+            index: found.position,
+            content: found.content,
+        })
+        .collect();
+    Ok(Json(StackSearchResponse { matches }))
+}
+
+/// Pinned clipboard items shared across devices, by name
+#[utoipa::path(
+    get,
+    path = "/api/v1/pins",
+    responses(
+        (status = 200, description = "Pinned items", body = PinsResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn get_pins(
+    State(state): State<ApiState>,
+    _: Authenticated,
+) -> std::result::Result<Json<PinsResponse>, ApiError> {
+    let pins = current_sync_manager(&state)
+        .await?
+        .get_pins()
+        .await
+        .into_iter()
+        .map(|(name, content)| PinnedItem { name, content })
+        .collect();
+    Ok(Json(PinsResponse { pins }))
+}
+
+/// Pin a clipboard stack item, or new content, under a name on every device
+#[utoipa::path(
+    post,
+    path = "/api/v1/pins",
+    request_body = PinRequest,
+    responses(
+        (status = 200, description = "Item pinned", body = PinnedItem),
+        (status = 400, description = "Invalid name, or no such stack item", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 422, description = "Rejected by the configured filters", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn create_pin(
+    State(state): State<ApiState>,
+    _: Authenticated,
+    Json(request): Json<PinRequest>,
+) -> std::result::Result<Json<PinnedItem>, ApiError> {
+    let sync_manager = current_sync_manager(&state).await?;
+    let content = match (request.index, request.content) {
+        (Some(index), _) => sync_manager
+            .get_clipboard_stack()
+            .await
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| ApiError::bad_request(format!("No clipboard stack item {}", index)))?,
+        (None, Some(content)) => {
+            state.filters.check(&content)?;
+            content
+        }
+        (None, None) => return Err(ApiError::bad_request("Either index or content is required")),
+    };
+
+    sync_manager
+        .pin(&request.name, content.clone())
+        .await
+        .map_err(|e| match e {
+            PostError::Config(message) => ApiError::bad_request(message),
+            other => other.into(),
+        })?;
+    Ok(Json(PinnedItem {
+        name: request.name,
+        content,
+    }))
+}
+
+/// Unpin an item on every device
+#[utoipa::path(
+    delete,
+    path = "/api/v1/pins/{name}",
+    params(("name" = String, Path, description = "Pin name")),
+    responses(
+        (status = 200, description = "Pin removed, if it existed", body = UnpinResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn remove_pin(
+    State(state): State<ApiState>,
+    _: Authenticated,
+    Path(name): Path<String>,
+) -> std::result::Result<Json<UnpinResponse>, ApiError> {
+    let removed = current_sync_manager(&state).await?.unpin(&name).await?;
+    Ok(Json(UnpinResponse { removed }))
+}
+
+/// Change which log messages the daemon writes until it restarts
+#[utoipa::path(
+    put,
+    path = "/api/v1/log-level",
+    request_body = LogLevel,
+    responses(
+        (status = 200, description = "Filter applied", body = LogLevel),
+        (status = 400, description = "Invalid filter", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn set_log_level(
+    _: Authenticated,
+    Json(request): Json<LogLevel>,
+) -> std::result::Result<Json<LogLevel>, ApiError> {
+    crate::logging::set_filter(&request.filter).map_err(|e| match e {
+        PostError::Config(message) => ApiError::bad_request(message),
+        other => other.into(),
+    })?;
+    info!("Log filter changed to {}", request.filter);
+    Ok(Json(request))
+}
+
+/// Pause or resume syncing copies made here; updates from peers are still applied
+#[utoipa::path(
+    put,
+    path = "/api/v1/sync/paused",
+    request_body = SyncPause,
+    responses(
+        (status = 200, description = "Sync paused or resumed", body = SyncPause),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn set_paused(
+    State(state): State<ApiState>,
+    _: Authenticated,
+    Json(request): Json<SyncPause>,
+) -> Json<SyncPause> {
+    if state.paused.swap(request.paused, Ordering::Relaxed) != request.paused {
+        let (event, verb) = if request.paused {
+            (SyncEvent::Paused, "paused")
+        } else {
+            (SyncEvent::Resumed, "resumed")
+        };
+        info!("Sync {}", verb);
+        let _ = state.events.send(event);
+    }
+    Json(request)
+}
+
+/// Sync events as they happen, one JSON object per line, until the daemon stops
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    responses(
+        (status = 200, description = "Stream of sync events", content_type = "application/x-ndjson"),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn stream_events(State(state): State<ApiState>, _: Authenticated) -> Response {
+    let subscription = (state.events.subscribe(), state.shutdown);
+    let lines =
+        futures_util::stream::unfold(subscription, |(mut events, mut shutdown)| async move {
+            loop {
+                let received = tokio::select! {
+                    received = events.recv() => received,
+                    _ = shutdown.wait_for(|stop| *stop) => return None,
+                };
+                match received {
+                    Ok(event) => {
+                        let line = format!("{}\n", serde_json::to_string(&event).ok()?);
+                        return Some((Ok::<_, std::convert::Infallible>(line), (events, shutdown)));
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Event subscriber fell behind and missed {} events", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+    (
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(lines),
+    )
+        .into_response()
+}
+
+/// Start pairing a device that can't use Tailscale's local API, such as a phone
+#[utoipa::path(
+    post,
+    path = "/api/v1/pairing",
+    request_body = PairingRequest,
+    responses(
+        (status = 200, description = "Pairing code to show the device", body = PairingOffer),
+        (status = 400, description = "Not an http(s) endpoint", body = ErrorBody),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn start_pairing(
+    State(state): State<ApiState>,
+    _: Owner,
+    Json(request): Json<PairingRequest>,
+) -> std::result::Result<Json<PairingOffer>, ApiError> {
+    let endpoint = request.endpoint.trim().trim_end_matches('/');
+    if !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+        return Err(ApiError::bad_request(
+            "The endpoint must be an http or https URL",
+        ));
+    }
+
+    let sync_manager = current_sync_manager(&state).await?;
+    let fingerprint = key_fingerprint(sync_manager.signing_public_key());
+    let code = state.pairing.start();
+    let uri = reqwest::Url::parse_with_params(
+        "post://pair",
+        [
+            ("endpoint", endpoint),
+            ("code", &code),
+            ("fingerprint", &fingerprint),
+        ],
+    )
+    .map_err(|e| ApiError::bad_request(format!("Invalid endpoint: {}", e)))?;
+
+    Ok(Json(PairingOffer {
+        code,
+        endpoint: endpoint.to_string(),
+        fingerprint,
+        node_id: sync_manager.get_node_id().await,
+        expires_in: pairing::CODE_LIFETIME.as_secs(),
+        uri: uri.to_string(),
+    }))
+}
+
+/// Exchange a pairing code for a device API token; needs no token itself
+#[utoipa::path(
+    post,
+    path = "/api/v1/pairing/complete",
+    request_body = PairingCompletion,
+    responses(
+        (status = 200, description = "Device paired", body = PairingResult),
+        (status = 400, description = "Invalid device name", body = ErrorBody),
+        (status = 401, description = "Unknown, used or expired code", body = ErrorBody),
+        (status = 503, description = "Daemon is waiting for Tailscale", body = ErrorBody)
+    )
+)]
+async fn complete_pairing(
+    State(state): State<ApiState>,
+    Json(request): Json<PairingCompletion>,
+) -> std::result::Result<Json<PairingResult>, ApiError> {
+    let sync_manager = current_sync_manager(&state).await?;
+    let token = state
+        .pairing
+        .redeem(request.code.trim(), &request.device_name)
+        .map_err(|e| match e {
+            PostError::Config(message) => ApiError::bad_request(message),
+            other => other.into(),
+        })?
+        .ok_or_else(|| {
+            warn!("Rejected an unknown or expired pairing code");
+            ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "Unknown, used or expired pairing code",
+            )
+        })?;
+
+    Ok(Json(PairingResult {
+        token,
+        fingerprint: key_fingerprint(sync_manager.signing_public_key()),
+        node_id: sync_manager.get_node_id().await,
+    }))
+}
+
+/// Devices paired through `/api/v1/pairing`
+#[utoipa::path(
+    get,
+    path = "/api/v1/pairing/devices",
+    responses(
+        (status = 200, description = "Paired devices", body = PairedDevicesResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn get_paired_devices(
+    State(state): State<ApiState>,
+    _: Owner,
+) -> Json<PairedDevicesResponse> {
+    Json(PairedDevicesResponse {
+        devices: state.pairing.devices(),
+    })
+}
+
+/// Revoke a paired device's token
+#[utoipa::path(
+    delete,
+    path = "/api/v1/pairing/devices/{name}",
+    params(("name" = String, Path, description = "Device name")),
+    responses(
+        (status = 200, description = "Device revoked, if it was paired", body = RevokeResponse),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn revoke_paired_device(
+    State(state): State<ApiState>,
+    _: Owner,
+    Path(name): Path<String>,
+) -> std::result::Result<Json<RevokeResponse>, ApiError> {
+    let revoked = state.pairing.revoke(&name)?;
+    Ok(Json(RevokeResponse { revoked }))
+}
+
+/// WebSocket for paired devices: sync events arrive as JSON text frames, like
+/// `/api/v1/events`, and `{"type": "push", "text": ...}` frames are broadcast, each
+/// answered with `{"type": "pushed", "sent": bool}` or `{"type": "error", "error": ...}`
+#[utoipa::path(
+    get,
+    path = "/api/v1/ws",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn websocket(
+    State(state): State<ApiState>,
+    _: Authenticated,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| relay_websocket(socket, state))
+}
+
+/// Remove rotated logs and, while over `storage.max_size`, old clipboard history and logs
+#[utoipa::path(
+    post,
+    path = "/api/v1/storage/clean",
+    request_body = CleanRequest,
+    responses(
+        (status = 200, description = "What was removed", body = Cleanup),
+        (status = 401, description = "Missing or invalid API token", body = ErrorBody)
+    ),
+    security(("api_token" = []))
+)]
+async fn clean_storage(
+    State(state): State<ApiState>,
+    _: Owner,
+    Json(request): Json<CleanRequest>,
+) -> std::result::Result<Json<Cleanup>, ApiError> {
+    let sync_manager = state.sync_manager.lock().await.clone();
+    let cleanup = crate::storage::clean(
+        state.storage.max_size,
+        sync_manager.as_deref(),
+        request.dry_run,
+    )
+    .await?;
+    Ok(Json(cleanup))
+}
+
+async fn relay_websocket(mut socket: WebSocket, state: ApiState) {
+    let mut events = state.events.subscribe();
+    let mut shutdown = state.shutdown.clone();
+
+    loop {
+        let reply = tokio::select! {
+            received = events.recv() => match received {
+                Ok(event) => match serde_json::to_string(&event) {
+                    Ok(json) => json,
+                    Err(_) => continue,
+                },
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("WebSocket client fell behind and missed {} events", missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => socket_reply(&state, &text).await.to_string(),
+                // Pings are answered by axum
+                Some(Ok(Message::Binary(_) | Message::Ping(_) | Message::Pong(_))) => continue,
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+            },
+            _ = stop_signalled(&mut shutdown) => break,
+        };
+        if socket.send(Message::Text(reply)).await.is_err() {
+            return;
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Resolves once `shutdown` is set; unlike `wait_for`, its output borrows nothing
+async fn stop_signalled(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+async fn socket_reply(state: &ApiState, text: &str) -> serde_json::Value {
+    let result = match serde_json::from_str(text) {
+        Ok(SocketRequest::Push { text }) => broadcast(state, text).await,
+        Err(e) => Err(ApiError::bad_request(format!("Invalid message: {}", e))),
+    };
+    match result {
+        Ok(Json(pushed)) => serde_json::json!({ "type": "pushed", "sent": pushed.sent }),
+        Err(error) => serde_json::json!({ "type": "error", "error": error.message }),
+    }
+}
+
+async fn current_sync_manager(state: &ApiState) -> std::result::Result<Arc<SyncManager>, ApiError> {
+    state
+        .sync_manager
+        .lock()
+        .await
+        .clone()
+        .ok_or_else(|| ApiError::unavailable("Daemon is waiting for Tailscale"))
+}
+
+async fn broadcast(
+    state: &ApiState,
+    content: String,
+) -> std::result::Result<Json<PushResponse>, ApiError> {
+    if content.trim().is_empty() {
+        return Err(ApiError::bad_request("Nothing to push"));
+    }
+    state.filters.check(&content)?;
+
+    let sent = current_sync_manager(state)
+        .await?
+        .broadcast_content(content)
+        .await?;
+    Ok(Json(PushResponse { sent }))
+}
+
+/// Elements that end a line when rendered
+const BLOCK_TAGS: &[&str] = &[
+    "br",
+    "p",
+    "div",
+    "li",
+    "tr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+];
+
+/// Plain-text rendering of an HTML fragment: tags dropped, block elements on their own lines
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+
+        let tag = rest[start + 1..start + len]
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if BLOCK_TAGS.contains(&tag.as_str()) && !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        rest = &rest[start + len + 1..];
+    }
+    text.push_str(rest);
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Base URL at which this machine's daemon serves the API, given its config
+pub async fn client_base_url(config: &PostConfig) -> Result<String> {
+    if !config.api.enabled {
+        return Err(PostError::Config(
+            "The HTTP API is disabled; set api.enabled = true".to_string(),
+        ));
+    }
+    if let Some(path) = config.api.unix_socket()? {
+        return Ok(unix_base_url(&path));
+    }
+
+    let port = config.api.port;
+    if config.api.tls {
+        // The certificate is only valid for the MagicDNS name
+        let transport = TailscaleTransport::new_with_detection(
+            config.network.port,
+            config.network.socket_path()?.as_deref(),
+        )
+        .await?;
+        let domain = transport.get_dns_name().await?;
+        return Ok(format!("https://{}:{}", domain, port));
+    }
+
+    let ip = match config.api.bind_ip()? {
+        ip if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        ip => ip,
+    };
+    Ok(format!("http://{}", SocketAddr::new(ip, port)))
+}
+
+/// Scheme of base URLs naming a Unix socket, whose host is the percent-encoded socket path
+const UNIX_SCHEME: &str = "http+unix";
+
+fn unix_base_url(path: &std::path::Path) -> String {
+    let path = path.to_string_lossy();
+    format!(
+        "{}://{}",
+        UNIX_SCHEME,
+        percent_encoding::utf8_percent_encode(&path, percent_encoding::NON_ALPHANUMERIC)
+    )
+}
+
+/// `segment` escaped to stand as one path segment, so a name holding `/`, `?` or `#`
+/// reaches the handler whole
+fn path_segment(segment: &str) -> percent_encoding::PercentEncode<'_> {
+    percent_encoding::utf8_percent_encode(segment, percent_encoding::NON_ALPHANUMERIC)
+}
+
+/// A daemon's answer, received over TCP or its Unix socket
+enum ApiResponse {
+    Http(reqwest::Response),
+    Unix(hyper::Response<hyper::Body>),
+}
+
+impl ApiResponse {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiResponse::Http(response) => response.status(),
+            ApiResponse::Unix(response) => response.status(),
+        }
+    }
+
+    /// The next piece of the body, or `None` once it has all arrived
+    async fn chunk(&mut self) -> std::result::Result<Option<Vec<u8>>, String> {
+        match self {
+            ApiResponse::Http(response) => response
+                .chunk()
+                .await
+                .map(|chunk| chunk.map(|chunk| chunk.to_vec()))
+                .map_err(|e| e.to_string()),
+            ApiResponse::Unix(response) => {
+                use hyper::body::HttpBody;
+                response
+                    .body_mut()
+                    .data()
+                    .await
+                    .transpose()
+                    .map(|chunk| chunk.map(|chunk| chunk.to_vec()))
+                    .map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    async fn bytes(mut self) -> std::result::Result<Vec<u8>, String> {
+        let mut body = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// The error the API answered with, or the status when it gave none
+    async fn error(self) -> String {
+        let status = self.status();
+        self.bytes()
+            .await
+            .ok()
+            .and_then(|body| serde_json::from_slice::<ErrorBody>(&body).ok())
+            .map_or_else(|| status.to_string(), |body| body.error)
+    }
+}
+
+/// Send `request`, over the Unix socket its URL names if it has the `http+unix` scheme
+async fn send(request: reqwest::RequestBuilder) -> Result<ApiResponse> {
+    let unreachable =
+        |e: String| PostError::Network(format!("Could not reach the daemon API: {}", e));
+    let (client, request) = request.build_split();
+    let request = request.map_err(|e| unreachable(e.to_string()))?;
+    if request.url().scheme() != UNIX_SCHEME {
+        return client
+            .execute(request)
+            .await
+            .map(ApiResponse::Http)
+            .map_err(|e| unreachable(e.to_string()));
+    }
+    send_unix(request)
+        .await
+        .map(ApiResponse::Unix)
+        .map_err(unreachable)
+}
+
+#[cfg(unix)]
+async fn send_unix(
+    request: reqwest::Request,
+) -> std::result::Result<hyper::Response<hyper::Body>, String> {
+    let url = request.url();
+    let socket = percent_encoding::percent_decode_str(url.host_str().unwrap_or_default())
+        .decode_utf8_lossy()
+        .into_owned();
+    let stream = tokio::net::UnixStream::connect(&socket)
+        .await
+        .map_err(|e| format!("{}: {}", socket, e))?;
+    let (mut sender, connection) = hyper::client::conn::handshake(stream)
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::spawn(connection);
+
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let mut builder = hyper::Request::builder()
+        .method(request.method().clone())
+        .uri(path)
+        .header(axum::http::header::HOST, "localhost");
+    for (name, value) in request.headers() {
+        builder = builder.header(name, value);
+    }
+    let body = match request.body() {
+        Some(body) => body
+            .as_bytes()
+            .ok_or("streamed request bodies can't be sent to a Unix socket")?
+            .to_vec(),
+        None => Vec::new(),
+    };
+    let request = builder
+        .body(hyper::Body::from(body))
+        .map_err(|e| e.to_string())?;
+    sender
+        .send_request(request)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(unix))]
+async fn send_unix(
+    _: reqwest::Request,
+) -> std::result::Result<hyper::Response<hyper::Body>, String> {
+    Err("Unix sockets aren't available on this platform".to_string())
+}
+
+/// Ask the daemon serving the API at `base_url` to rediscover its peers, which requires
+/// the API token
+pub async fn request_rediscovery(base_url: &str, token: &str) -> Result<RediscoverResponse> {
+    let request = reqwest::Client::new()
+        .post(format!("{}/api/v1/discovery/refresh", base_url))
+        .bearer_auth(token);
+    call_api(request, "Rediscovery").await
+}
+
+/// Fetch the status of the daemon serving the API at `base_url`
+pub async fn fetch_status(base_url: &str, token: &str) -> Result<StatusResponse> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/status", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching status").await
+}
+
+/// Fetch the peers known to the daemon serving the API at `base_url`
+pub async fn fetch_peers(base_url: &str, token: &str) -> Result<Vec<PeerResponse>> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/peers", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching peers").await
+}
+
+/// Fetch the peers the daemon dropped for going quiet
+pub async fn fetch_offline_peers(base_url: &str, token: &str) -> Result<Vec<OfflinePeerResponse>> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/peers/offline", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching offline peers").await
+}
+
+/// Fetch the peer addresses whose latest sends failed
+pub async fn fetch_peer_circuits(base_url: &str, token: &str) -> Result<Vec<PeerCircuitResponse>> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/peers/circuits", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching peer circuits").await
+}
+
+/// Have the daemon ping `node`, a peer ID or name, which requires the API token
+pub async fn request_ping(base_url: &str, token: &str, node: &str) -> Result<PingResponse> {
+    let request = reqwest::Client::new()
+        .post(format!(
+            "{}/api/v1/peers/{}/ping",
+            base_url,
+            path_segment(node)
+        ))
+        .bearer_auth(token);
+    call_api(request, "Ping").await
+}
+
+/// Fetch the current clipboard of `node`, a peer ID or name, which requires the API token
+pub async fn request_collect(base_url: &str, token: &str, node: &str) -> Result<CollectResponse> {
+    let request = reqwest::Client::new()
+        .post(format!(
+            "{}/api/v1/peers/{}/collect",
+            base_url,
+            path_segment(node)
+        ))
+        .bearer_auth(token);
+    call_api(request, "Collecting").await
+}
+
+/// Have the daemon benchmark sync with `node`, a peer ID or name, which requires the API
+/// token
+pub async fn request_bench(
+    base_url: &str,
+    token: &str,
+    node: &str,
+    request: &BenchRequest,
+) -> Result<Vec<BenchResultResponse>> {
+    let request = reqwest::Client::new()
+        .post(format!(
+            "{}/api/v1/peers/{}/bench",
+            base_url,
+            path_segment(node)
+        ))
+        .bearer_auth(token)
+        .json(request);
+    call_api(request, "Benchmarking").await
+}
+
+/// Fetch which peers have applied the latest clipboard update sent from here
+pub async fn fetch_last_sync(base_url: &str, token: &str) -> Result<LastSyncResponse> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/sync/last", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching the last sync").await
+}
+
+/// Ask the daemon serving the API at `base_url` whether it is ready to sync
+pub async fn fetch_readiness(base_url: &str) -> Result<ReadinessResponse> {
+    let response = send(reqwest::Client::new().get(format!("{}/health/ready", base_url))).await?;
+    // Not being ready is answered with the failing checks
+    if !response.status().is_success() && response.status() != StatusCode::SERVICE_UNAVAILABLE {
+        return Err(PostError::Network(format!(
+            "Checking readiness failed: {}",
+            response.error().await
+        )));
+    }
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| PostError::Network(format!("API response interrupted: {}", e)))?;
+    serde_json::from_slice(&body)
+        .map_err(|e| PostError::Serialization(format!("Invalid API response: {}", e)))
+}
+
+/// Fetch the daemon's state for a bug report, which requires the API token
+pub async fn fetch_debug_state(base_url: &str, token: &str) -> Result<DebugState> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/debug/state", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching the daemon state").await
+}
+
+/// Fetch sync counters from the daemon serving the API at `base_url`
+pub async fn fetch_stats(base_url: &str, token: &str) -> Result<StatsResponse> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/stats", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching stats").await
+}
+
+/// Have the daemon broadcast `content` now, through its filters and transforms
+pub async fn request_push(base_url: &str, token: &str, content: String) -> Result<PushResponse> {
+    let request = reqwest::Client::new()
+        .post(format!("{}/api/v1/clipboard", base_url))
+        .bearer_auth(token)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(content);
+    call_api(request, "Pushing clipboard content").await
+}
+
+/// Fetch the clipboard stack, which requires the API token
+pub async fn fetch_stack(base_url: &str, token: &str) -> Result<StackResponse> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/clipboard/stack", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching the clipboard stack").await
+}
+
+/// Search the clipboard stack, which requires the API token
+pub async fn search_stack(
+    base_url: &str,
+    token: &str,
+    query: &str,
+    limit: usize,
+) -> Result<StackSearchResponse> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/clipboard/stack/search", base_url))
+        .query(&StackSearch {
+            q: query.to_string(),
+            limit,
+        })
+        .bearer_auth(token);
+    call_api(request, "Searching the clipboard stack").await
+}
+
+/// Fetch pinned items, which requires the API token
+pub async fn fetch_pins(base_url: &str, token: &str) -> Result<PinsResponse> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/pins", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching pins").await
+}
+
+pub async fn request_pin(base_url: &str, token: &str, pin: &PinRequest) -> Result<PinnedItem> {
+    let request = reqwest::Client::new()
+        .post(format!("{}/api/v1/pins", base_url))
+        .bearer_auth(token)
+        .json(pin);
+    call_api(request, "Pinning").await
+}
+
+pub async fn request_unpin(base_url: &str, token: &str, name: &str) -> Result<UnpinResponse> {
+    let request = reqwest::Client::new()
+        .delete(format!("{}/api/v1/pins/{}", base_url, path_segment(name)))
+        .bearer_auth(token);
+    call_api(request, "Unpinning").await
+}
+
+/// Change the log filter of the daemon serving the API at `base_url`
+pub async fn request_log_level(base_url: &str, token: &str, filter: &str) -> Result<LogLevel> {
+    let request = reqwest::Client::new()
+        .put(format!("{}/api/v1/log-level", base_url))
+        .bearer_auth(token)
+        .json(&LogLevel {
+            filter: filter.to_string(),
+        });
+    call_api(request, "Changing the log level").await
+}
+
+/// Start pairing a device that will reach the API at `endpoint`
+pub async fn request_pairing(base_url: &str, token: &str, endpoint: &str) -> Result<PairingOffer> {
+    let request = reqwest::Client::new()
+        .post(format!("{}/api/v1/pairing", base_url))
+        .bearer_auth(token)
+        .json(&PairingRequest {
+            endpoint: endpoint.to_string(),
+        });
+    call_api(request, "Pairing").await
+}
+
+pub async fn fetch_paired_devices(base_url: &str, token: &str) -> Result<PairedDevicesResponse> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/pairing/devices", base_url))
+        .bearer_auth(token);
+    call_api(request, "Fetching paired devices").await
+}
+
+pub async fn request_revoke_device(
+    base_url: &str,
+    token: &str,
+    name: &str,
+) -> Result<RevokeResponse> {
+    let request = reqwest::Client::new()
+        .delete(format!(
+            "{}/api/v1/pairing/devices/{}",
+            base_url,
+            path_segment(name)
+        ))
+        .bearer_auth(token);
+    call_api(request, "Revoking the device").await
+}
+
+/// Pause or resume syncing copies made on the daemon's machine
+pub async fn request_paused(base_url: &str, token: &str, paused: bool) -> Result<SyncPause> {
+    let request = reqwest::Client::new()
+        .put(format!("{}/api/v1/sync/paused", base_url))
+        .bearer_auth(token)
+        .json(&SyncPause { paused });
+    call_api(request, if paused { "Pausing" } else { "Resuming" }).await
+}
+
+/// Have the daemon clean its data directory, so its clipboard stack is trimmed too
+pub async fn request_cleanup(base_url: &str, token: &str, dry_run: bool) -> Result<Cleanup> {
+    let request = reqwest::Client::new()
+        .post(format!("{}/api/v1/storage/clean", base_url))
+        .bearer_auth(token)
+        .json(&CleanRequest { dry_run });
+    call_api(request, "Cleaning up").await
+}
+
+/// Follow the daemon's event stream, calling `on_event` with each JSON line until it ends
+pub async fn watch_events(
+    base_url: &str,
+    token: &str,
+    mut on_event: impl FnMut(&str),
+) -> Result<()> {
+    let request = reqwest::Client::new()
+        .get(format!("{}/api/v1/events", base_url))
+        .bearer_auth(token);
+    let mut response = send(request).await?;
+    if !response.status().is_success() {
+        return Err(PostError::Network(format!(
+            "Watching events failed: {}",
+            response.error().await
+        )));
+    }
+
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| PostError::Network(format!("Event stream interrupted: {}", e)))?
+    {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                on_event(line.trim_end());
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn call_api<T: serde::de::DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+    action: &str,
+) -> Result<T> {
+    let response = send(request).await?;
+    if !response.status().is_success() {
+        return Err(PostError::Network(format!(
+            "{} failed: {}",
+            action,
+            response.error().await
+        )));
+    }
+
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| PostError::Network(format!("API response interrupted: {}", e)))?;
+    serde_json::from_slice(&body)
+        .map_err(|e| PostError::Serialization(format!("Invalid API response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use post_core::{MessageData, MockClipboard, MockTransport, PatternSet, PostMessage};
+    use tokio::sync::mpsc;
+
+    pub(super) const TOKEN: &str = "test-token";
+
+    /// State for a test server with no sync manager and default settings, for tests to
+    /// override fields of
+    pub(super) fn test_state(
+        sync_manager: Option<Arc<SyncManager>>,
+        shutdown: watch::Receiver<bool>,
+    ) -> ApiState {
+        let transport = Arc::new(MockTransport::new("node-a".to_string()));
+        ApiState {
+            sync_manager: Arc::new(Mutex::new(sync_manager)),
+            transport: transport.clone(),
+            token: Arc::from(TOKEN),
+            filters: PostConfig::default().filters,
+            events: post_core::event_channel(),
+            shutdown,
+            pairing: Arc::new(PairingStore::default()),
+            storage: StorageConfig::default(),
+            paused: Arc::new(AtomicBool::new(false)),
+            audit: None,
+            peer_directory: Arc::new(PeerDirectory::new(transport)),
+            health: HealthState::default(),
+            inbox: Default::default(),
+            config: Arc::new(std::sync::RwLock::new(PostConfig::default())),
+        }
+    }
+
+    async fn spawn_api_with_events(
+        sync_manager: Option<Arc<SyncManager>>,
+        filters: FilterConfig,
+        events: EventSender,
+        config: PostConfig,
+    ) -> u16 {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (stop, shutdown) = watch::channel(false);
+        let state = ApiState {
+            filters,
+            events,
+            config: Arc::new(std::sync::RwLock::new(config)),
+            ..test_state(sync_manager, shutdown)
+        };
+        tokio::spawn(async move {
+            let _stop = stop;
+            serve(listener, state, std::future::pending()).await
+        });
+        port
+    }
+
+    async fn spawn_api_with_filters(
+        sync_manager: Option<Arc<SyncManager>>,
+        filters: FilterConfig,
+    ) -> u16 {
+        spawn_api_with_events(
+            sync_manager,
+            filters,
+            post_core::event_channel(),
+            PostConfig::default(),
+        )
+        .await
+    }
+
+    async fn spawn_api(sync_manager: Option<Arc<SyncManager>>) -> u16 {
+        spawn_api_with_filters(sync_manager, PostConfig::default().filters).await
+    }
+
+    /// Sync manager whose broadcasts land in the returned channel
+    async fn broadcasting_sync_manager() -> (Arc<SyncManager>, mpsc::UnboundedReceiver<PostMessage>)
+    {
+        let sync = sync_manager("node-a");
+        let (tx, rx) = mpsc::unbounded_channel();
+        sync.start_sync_loop(move |message| {
+            let _ = tx.send(message);
+        })
+        .await
+        .unwrap();
+        (sync, rx)
+    }
+
+    fn broadcast_content(message: PostMessage) -> String {
+        match message.data {
+            MessageData::ClipboardUpdate(data) => data.content.to_string(),
+            other => panic!("expected a clipboard update, got {:?}", other),
+        }
+    }
+
+    async fn push(port: u16, path: &str, content_type: &str, body: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{}{}", port, path))
+            .bearer_auth(TOKEN)
+            .header(CONTENT_TYPE, content_type)
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap()
+    }
+
+    fn sync_manager(node_id: &str) -> Arc<SyncManager> {
+        Arc::new(SyncManager::new(Arc::new(MockClipboard::new()), node_id.to_string()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_audited_by_caller_without_query_strings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (_stop, shutdown) = watch::channel(false);
+        let state = ApiState {
+            audit: Some(Arc::new(AuditLog::open(&path).unwrap())),
+            ..test_state(None, shutdown)
+        };
+        tokio::spawn(serve(listener, state, std::future::pending()));
+
+        let base = format!("http://127.0.0.1:{}", port);
+        reqwest::get(format!("{}/api/v1/status", base))
+            .await
+            .unwrap();
+        reqwest::Client::new()
+            .get(format!("{}/api/v1/clipboard/stack/search?q=hunter2", base))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        reqwest::Client::new()
+            .put(format!("{}/api/v1/sync/paused", base))
+            .bearer_auth("stolen")
+            .json(&serde_json::json!({ "paused": true }))
+            .send()
+            .await
+            .unwrap();
+
+        // Entries are written off the request path, so give the writer a moment
+        let mut entries = Vec::new();
+        for _ in 0..100 {
+            entries = crate::audit::read(&path, &Default::default()).unwrap();
+            if entries.len() >= 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let recorded: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|entry| (entry.actor.as_str(), entry.action.as_str()))
+            .collect();
+        assert_eq!(
+            recorded,
+            [
+                ("anonymous", "GET /api/v1/status -> 200"),
+                ("API token", "GET /api/v1/clipboard/stack/search -> 503"),
+                ("invalid token", "PUT /api/v1/sync/paused -> 401"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_forgets_known_peers() {
+        let a = sync_manager("node-a");
+        let b = sync_manager("node-b");
+        a.handle_message(b.create_node_discovery_message().await.unwrap())
+            .await
+            .unwrap();
+
+        let port = spawn_api(Some(Arc::clone(&a))).await;
+        let response = request_rediscovery(&format!("http://127.0.0.1:{}", port), TOKEN)
+            .await
+            .unwrap();
+
+        assert_eq!(response.forgotten_peers, 1);
+        assert!(a.get_nodes().await.is_empty());
+        assert!(a.get_crypto_session("node-b").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_status_and_peers_report_sync_state() {
+        let (a, _a_rx) = broadcasting_sync_manager().await;
+        let b = sync_manager("node-b");
+        let (tx, mut b_rx) = mpsc::unbounded_channel();
+        b.start_sync_loop(move |message| {
+            let _ = tx.send(message);
+        })
+        .await
+        .unwrap();
+        a.handle_message(b.create_node_discovery_message().await.unwrap())
+            .await
+            .unwrap();
+        b.handle_message(a.create_node_discovery_message().await.unwrap())
+            .await
+            .unwrap();
+
+        // b never acknowledges this one
+        a.broadcast_content("from a".to_string()).await.unwrap();
+        b.broadcast_content("from b".to_string()).await.unwrap();
+        a.handle_message(b_rx.recv().await.unwrap()).await.unwrap();
+
+        let port = spawn_api(Some(a)).await;
+        let base = format!("http://127.0.0.1:{}", port);
+
+        let status: StatusResponse = reqwest::get(format!("{}/api/v1/status", base))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(status.node_id.as_deref(), Some("node-a"));
+        assert_eq!(status.peer_count, 1);
+        assert_eq!(status.pending_acks, 1);
+        assert_eq!(status.incompatible_peers, 0);
+
+        let peers: Vec<PeerResponse> = reqwest::get(format!("{}/api/v1/peers", base))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].id, "node-b");
+        assert_eq!(peers[0].public_key.len(), 64);
+        assert_eq!(peers[0].updates_received, 1);
+        assert!(peers[0].last_update.is_some());
+        assert!(peers[0].awaiting_ack);
+        assert_eq!(
+            peers[0].version.as_deref(),
+            Some(post_core::compat::VERSION)
+        );
+        assert!(peers[0].capabilities.contains(&"pins".to_string()));
+        assert!(peers[0].compatible);
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_traffic_per_peer() {
+        let (a, _a_rx) = broadcasting_sync_manager().await;
+        let b = sync_manager("node-b");
+        let (tx, mut b_rx) = mpsc::unbounded_channel();
+        b.start_sync_loop(move |message| {
+            let _ = tx.send(message);
+        })
+        .await
+        .unwrap();
+        a.handle_message(b.create_node_discovery_message().await.unwrap())
+            .await
+            .unwrap();
+        b.handle_message(a.create_node_discovery_message().await.unwrap())
+            .await
+            .unwrap();
+
+        a.broadcast_content("12345".to_string()).await.unwrap();
+        a.broadcast_content("123".to_string()).await.unwrap();
+        b.broadcast_content("1234567".to_string()).await.unwrap();
+        a.handle_message(b_rx.recv().await.unwrap()).await.unwrap();
+
+        let port = spawn_api(Some(a)).await;
+        let stats = fetch_stats(&format!("http://127.0.0.1:{}", port), TOKEN)
+            .await
+            .unwrap();
+
+        assert_eq!(stats.peers.len(), 1);
+        assert_eq!(stats.peers[0].id, "node-b");
+        assert_eq!(stats.total.updates_sent, 2);
+        assert_eq!(stats.total.bytes_sent, 8);
+        assert_eq!(stats.total.updates_received, 1);
+        assert_eq!(stats.total.bytes_received, 7);
+        assert_eq!(stats.total.average_ack_latency_ms, None);
+    }
+
+    #[tokio::test]
+    async fn test_read_endpoints_need_a_token_off_loopback() {
+        let base_url = format!("http://127.0.0.1:{}", spawn_api(None).await);
+        assert!(fetch_status(&base_url, "wrong-token").await.is_ok());
+
+        let mut config = PostConfig::default();
+        config.api.bind = "100.64.0.1".to_string();
+        let port = spawn_api_with_events(
+            Some(sync_manager("node-a")),
+            config.filters.clone(),
+            post_core::event_channel(),
+            config,
+        )
+        .await;
+        let base_url = format!("http://127.0.0.1:{}", port);
+        for path in [
+            "status",
+            "peers",
+            "peers/offline",
+            "peers/circuits",
+            "stats",
+            "sync/last",
+        ] {
+            let response = reqwest::get(format!("{}/api/v1/{}", base_url, path))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", path);
+        }
+        assert!(fetch_status(&base_url, TOKEN).await.is_ok());
+        assert!(fetch_peers(&base_url, TOKEN).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_status_without_tailscale() {
+        let port = spawn_api(None).await;
+        let status: StatusResponse =
+            reqwest::get(format!("http://127.0.0.1:{}/api/v1/status", port))
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+
+        assert!(status.node_id.is_none());
+        assert_eq!(status.peer_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_readiness_reports_failing_checks() {
+        let port = spawn_api(None).await;
+        let base_url = format!("http://127.0.0.1:{}", port);
+        let live = reqwest::get(format!("{}/health/live", base_url))
+            .await
+            .unwrap();
+        assert_eq!(live.status(), StatusCode::OK);
+
+        let response = reqwest::get(format!("{}/health/ready", base_url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let readiness = fetch_readiness(&base_url).await.unwrap();
+        assert!(!readiness.ready);
+        let failing: Vec<_> = readiness
+            .failing()
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(failing, ["sync"]);
+
+        let (sync, _rx) = broadcasting_sync_manager().await;
+        let port = spawn_api(Some(sync)).await;
+        let readiness = fetch_readiness(&format!("http://127.0.0.1:{}", port))
+            .await
+            .unwrap();
+        assert!(readiness.ready);
+        assert_eq!(readiness.checks.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_debug_state_requires_token() {
+        let (sync, _rx) = broadcasting_sync_manager().await;
+        let port = spawn_api(Some(sync)).await;
+        let base_url = format!("http://127.0.0.1:{}", port);
+        push(port, "/api/v1/clipboard", "text/plain", "copied here").await;
+
+        let response = reqwest::get(format!("{}/api/v1/debug/state", base_url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let state = fetch_debug_state(&base_url, TOKEN).await.unwrap();
+        assert_eq!(state.version, post_core::compat::VERSION);
+        assert_eq!(state.status.node_id.as_deref(), Some("node-a"));
+        assert_eq!(state.queues.clipboard_stack, 1);
+        assert!(state.config["network"]["port"].is_number());
+    }
+
+    #[tokio::test]
+    async fn test_openapi_spec_is_served() {
+        let port = spawn_api(None).await;
+        let spec: serde_json::Value =
+            reqwest::get(format!("http://127.0.0.1:{}/api/v1/openapi.json", port))
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+
+        assert_eq!(spec["info"]["title"], "Post API");
+        assert!(spec["paths"]["/api/v1/discovery/refresh"]["post"].is_object());
+        assert!(spec["components"]["schemas"]["RediscoverResponse"].is_object());
+    }
+
+    #[cfg(feature = "swagger-ui")]
+    #[tokio::test]
+    async fn test_swagger_ui_is_served_without_a_cdn() {
+        let port = spawn_api(None).await;
+        let base_url = format!("http://127.0.0.1:{}/api/v1/docs/", port);
+
+        let page = reqwest::get(&base_url).await.unwrap().text().await.unwrap();
+        assert!(page.contains("swagger-ui"));
+        assert!(!page.contains("unpkg.com"));
+
+        let config = reqwest::get(format!("{}swagger-initializer.js", base_url))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(config.contains("/api/v1/openapi.json"));
+    }
+
+    #[tokio::test]
+    async fn test_push_requires_token() {
+        let (sync, _rx) = broadcasting_sync_manager().await;
+        let port = spawn_api(Some(sync)).await;
+
+        let response = reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{}/api/v1/clipboard", port))
+            .bearer_auth("wrong")
+            .header(CONTENT_TYPE, "text/plain")
+            .body("hello")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_push_broadcasts_by_content_type() {
+        let (sync, mut rx) = broadcasting_sync_manager().await;
+        let port = spawn_api(Some(sync)).await;
+
+        let response = push(
+            port,
+            "/api/v1/clipboard",
+            "text/plain; charset=utf-8",
+            "plain",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(broadcast_content(rx.recv().await.unwrap()), "plain");
+
+        push(
+            port,
+            "/api/v1/clipboard",
+            "text/html",
+            "<p>Fish &amp; chips</p><p>x</p>",
+        )
+        .await;
+        assert_eq!(
+            broadcast_content(rx.recv().await.unwrap()),
+            "Fish & chips\nx"
+        );
+
+        push(
+            port,
+            "/api/v1/clipboard",
+            "application/json",
+            r#"{"html":"<b>bold</b>"}"#,
+        )
+        .await;
+        assert_eq!(broadcast_content(rx.recv().await.unwrap()), "bold");
+
+        let response = push(port, "/api/v1/clipboard", "image/png", "png").await;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_push_url() {
+        let (sync, mut rx) = broadcasting_sync_manager().await;
+        let port = spawn_api(Some(sync)).await;
+
+        let body = r#"{"url":"https://example.com/page"}"#;
+        push(port, "/api/v1/clipboard/push-url", "application/json", body).await;
+        assert_eq!(
+            broadcast_content(rx.recv().await.unwrap()),
+            "https://example.com/page"
+        );
+
+        let body = r#"{"url":"javascript:alert(1)"}"#;
+        let response = push(port, "/api/v1/clipboard/push-url", "application/json", body).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_stack_lists_pushed_items() {
+        let (sync, _rx) = broadcasting_sync_manager().await;
+        let port = spawn_api(Some(sync)).await;
+        let base = format!("http://127.0.0.1:{}", port);
+
+        push(port, "/api/v1/clipboard", "text/plain", "first").await;
+        push(port, "/api/v1/clipboard", "text/plain", "second").await;
+
+        let stack = fetch_stack(&base, TOKEN).await.unwrap();
+        assert_eq!(stack.items, vec!["second", "first"]);
+        assert!(fetch_stack(&base, "wrong").await.is_err());
+
+        let found = search_stack(&base, TOKEN, "FIR", 20).await.unwrap();
+        assert_eq!(found.matches.len(), 1);
+        assert_eq!(found.matches[0].index, 1);
+        assert_eq!(found.matches[0].content, "first");
+    }
+
+    #[tokio::test]
+    async fn test_pin_stack_item_and_unpin() {
+        let (sync, _rx) = broadcasting_sync_manager().await;
+        let port = spawn_api(Some(sync)).await;
+        let base = format!("http://127.0.0.1:{}", port);
+
+        push(port, "/api/v1/clipboard", "text/plain", "1 Main St").await;
+        push(port, "/api/v1/clipboard", "text/plain", "later copy").await;
+
+        let pin = PinRequest {
+            name: "work-address".to_string(),
+            index: Some(1),
+            content: None,
+        };
+        let pinned = request_pin(&base, TOKEN, &pin).await.unwrap();
+        assert_eq!(pinned.content, "1 Main St");
+
+        let pins = fetch_pins(&base, TOKEN).await.unwrap();
+        assert_eq!(pins.pins.len(), 1);
+        assert_eq!(pins.pins[0].name, "work-address");
+
+        let bad_name = PinRequest {
+            name: "work address".to_string(),
+            ..pin
+        };
+        assert!(request_pin(&base, TOKEN, &bad_name).await.is_err());
+        // Sent whole, so it doesn't unpin "work-address"
+        assert!(
+            !request_unpin(&base, TOKEN, "work-address?x")
+                .await
+                .unwrap()
+                .removed
+        );
+        assert_eq!(fetch_pins(&base, TOKEN).await.unwrap().pins.len(), 1);
+
+        assert!(
+            request_unpin(&base, TOKEN, "work-address")
+                .await
+                .unwrap()
+                .removed
+        );
+        assert!(fetch_pins(&base, TOKEN).await.unwrap().pins.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_push_applies_filters() {
+        let (sync, mut rx) = broadcasting_sync_manager().await;
+        let mut filters = PostConfig::default().filters;
+        filters.max_length = Some(10);
+        filters.exclude_patterns = PatternSet::new([r"\d{4}-\d{4}"]).unwrap();
+        let port = spawn_api_with_filters(Some(sync), filters).await;
+
+        let response = push(
+            port,
+            "/api/v1/clipboard",
+            "text/plain",
+            "far too long to sync",
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = push(port, "/api/v1/clipboard", "text/plain", "1234-5678").await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        push(port, "/api/v1/clipboard", "text/plain", "ok").await;
+        assert_eq!(broadcast_content(rx.recv().await.unwrap()), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_client_base_url_follows_api_config() {
+        let mut config = PostConfig::default();
+        assert_eq!(
+            client_base_url(&config).await.unwrap(),
+            "http://127.0.0.1:19828"
+        );
+
+        config.api.bind = "0.0.0.0".to_string();
+        config.api.port = 8080;
+        assert_eq!(
+            client_base_url(&config).await.unwrap(),
+            "http://127.0.0.1:8080"
+        );
+
+        config.api.bind = "100.64.0.1".to_string();
+        assert_eq!(
+            client_base_url(&config).await.unwrap(),
+            "http://100.64.0.1:8080"
+        );
+
+        config.api.enabled = false;
+        assert!(client_base_url(&config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_stops_on_shutdown() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let (stop, shutdown) = watch::channel(false);
+        let state = test_state(None, shutdown.clone());
+        let server = tokio::spawn(start_api_server(state, addr, false, shutdown));
+
+        let status_url = format!("http://{}/api/v1/status", addr);
+        let mut reachable = false;
+        for _ in 0..50 {
+            if reqwest::get(&status_url).await.is_ok() {
+                reachable = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(reachable);
+
+        stop.send(true).unwrap();
+        tokio::time::timeout(SHUTDOWN_GRACE, server)
+            .await
+            .expect("server did not stop")
+            .unwrap()
+            .unwrap();
+        assert!(reqwest::get(&status_url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_binding_fails_before_serving() {
+        let taken = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let (_stop, shutdown) = watch::channel(false);
+        let state = test_state(None, shutdown.clone());
+
+        let error = bind_api_server(state, taken.local_addr().unwrap(), false, shutdown)
+            .err()
+            .expect("the address is in use");
+        assert!(error.to_string().contains("Failed to bind API"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_serves_the_api_to_its_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("run").join("post.sock");
+        let (stop, shutdown) = watch::channel(false);
+        let state = ApiState {
+            paused: Arc::new(AtomicBool::new(true)),
+            ..test_state(None, shutdown.clone())
+        };
+
+        // Whatever else is at the path is left alone
+        std::fs::create_dir_all(socket.parent().unwrap()).unwrap();
+        std::fs::write(&socket, "not a socket").unwrap();
+        let refused = start_unix_api_server(state.clone(), socket.clone(), shutdown.clone()).await;
+        assert!(refused.is_err());
+        assert_eq!(std::fs::read_to_string(&socket).unwrap(), "not a socket");
+        std::fs::remove_file(&socket).unwrap();
+
+        let server = tokio::spawn(start_unix_api_server(state, socket.clone(), shutdown));
+
+        let mut config = PostConfig::default();
+        config.api.listen = Some(format!("unix:{}", socket.display()));
+        let base_url = client_base_url(&config).await.unwrap();
+        let mut status = None;
+        for _ in 0..50 {
+            if let Ok(response) = fetch_status(&base_url, TOKEN).await {
+                status = Some(response);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(status.expect("socket never answered").paused);
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let refused = request_collect(&base_url, "wrong-token", "node-b").await;
+        assert!(refused
+            .unwrap_err()
+            .to_string()
+            .contains("Collecting failed"));
+
+        stop.send(true).unwrap();
+        tokio::time::timeout(SHUTDOWN_GRACE, server)
+            .await
+            .expect("server did not stop")
+            .unwrap()
+            .unwrap();
+        assert!(!socket.exists());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_requires_token() {
+        let a = sync_manager("node-a");
+        let b = sync_manager("node-b");
+        a.handle_message(b.create_node_discovery_message().await.unwrap())
+            .await
+            .unwrap();
+
+        let port = spawn_api(Some(Arc::clone(&a))).await;
+        let error = request_rediscovery(&format!("http://127.0.0.1:{}", port), "wrong")
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("invalid API token"));
+        assert_eq!(a.get_nodes().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ping_requires_token() {
+        let port = spawn_api(Some(sync_manager("node-a"))).await;
+        let error = request_ping(&format!("http://127.0.0.1:{}", port), "wrong", "node-b")
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("invalid API token"));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_without_tailscale_is_unavailable() {
+        let port = spawn_api(None).await;
+        let error = request_rediscovery(&format!("http://127.0.0.1:{}", port), TOKEN)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("waiting for Tailscale"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_log_filter_is_rejected() {
+        let port = spawn_api(None).await;
+        let base_url = format!("http://127.0.0.1:{}", port);
+
+        let error = request_log_level(&base_url, TOKEN, "post_core=loud")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("Invalid log filter"));
+        assert!(request_log_level(&base_url, "wrong", "debug")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_events_stream_sync_activity() {
+        let events = post_core::event_channel();
+        let sync = Arc::new(
+            SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string())
+                .unwrap()
+                .with_events(events.clone()),
+        );
+        sync.start_sync_loop(|_| {}).await.unwrap();
+        let port = spawn_api_with_events(
+            Some(Arc::clone(&sync)),
+            PostConfig::default().filters,
+            events.clone(),
+            PostConfig::default(),
+        )
+        .await;
+        let base_url = format!("http://127.0.0.1:{}", port);
+        assert!(watch_events(&base_url, "wrong", |_| {}).await.is_err());
+
+        let (tx, mut lines) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            watch_events(&base_url, TOKEN, |line| {
+                let _ = tx.send(line.to_string());
+            })
+            .await
+        });
+        while events.receiver_count() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        sync.broadcast_content("hello".to_string()).await.unwrap();
+        let line = tokio::time::timeout(Duration::from_secs(2), lines.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let event: post_core::SyncEvent = serde_json::from_str(&line).unwrap();
+        assert!(matches!(
+            event,
+            post_core::SyncEvent::Sent { ref content, peers: 0, .. } if content == "hello"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_paired_device_token_authorizes_requests() {
+        let port = spawn_api(Some(sync_manager("node-a"))).await;
+        let base_url = format!("http://127.0.0.1:{}", port);
+        assert!(request_pairing(&base_url, TOKEN, "ftp://phone")
+            .await
+            .is_err());
+
+        let offer = request_pairing(&base_url, TOKEN, "http://100.64.0.1:8787/")
+            .await
+            .unwrap();
+        assert_eq!(offer.endpoint, "http://100.64.0.1:8787");
+        assert_eq!(offer.node_id, "node-a");
+        assert!(offer
+            .uri
+            .starts_with("post://pair?endpoint=http%3A%2F%2F100.64.0.1"));
+        assert!(offer.uri.contains(&offer.code));
+
+        let complete = |code: String| {
+            reqwest::Client::new()
+                .post(format!("{}/api/v1/pairing/complete", base_url))
+                .json(&PairingCompletion {
+                    code,
+                    device_name: "phone".to_string(),
+                })
+                .send()
+        };
+        let paired: PairingResult = complete(offer.code.clone())
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(paired.fingerprint, offer.fingerprint);
+        assert_eq!(
+            complete(offer.code).await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        assert!(fetch_stack(&base_url, &paired.token).await.is_ok());
+        // Only the API token manages pairings
+        assert!(fetch_paired_devices(&base_url, &paired.token)
+            .await
+            .is_err());
+        let devices = fetch_paired_devices(&base_url, TOKEN)
+            .await
+            .unwrap()
+            .devices;
+        assert_eq!(devices[0].name, "phone");
+
+        assert!(
+            request_revoke_device(&base_url, TOKEN, "phone")
+                .await
+                .unwrap()
+                .revoked
+        );
+        assert!(fetch_stack(&base_url, &paired.token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_websocket_relays_events_and_pushes() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::Message as Frame;
+
+        let events = post_core::event_channel();
+        let (tx, mut broadcasts) = mpsc::unbounded_channel();
+        let sync = Arc::new(
+            SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string())
+                .unwrap()
+                .with_events(events.clone()),
+        );
+        sync.start_sync_loop(move |message| {
+            let _ = tx.send(message);
+        })
+        .await
+        .unwrap();
+        let port = spawn_api_with_events(
+            Some(Arc::clone(&sync)),
+            PostConfig::default().filters,
+            events,
+            PostConfig::default(),
+        )
+        .await;
+
+        let url = format!("ws://127.0.0.1:{}/api/v1/ws", port);
+        assert!(tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .is_err());
+        let mut request = url.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, format!("Bearer {}", TOKEN).parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        socket
+            .send(Frame::Text(
+                r#"{"type": "push", "text": "from the phone"}"#.to_string(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            broadcast_content(broadcasts.recv().await.unwrap()),
+            "from the phone"
+        );
+
+        let mut frames = Vec::new();
+        while frames.len() < 2 {
+            let frame = tokio::time::timeout(Duration::from_secs(2), socket.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            if let Frame::Text(text) = frame {
+                frames.push(serde_json::from_str::<serde_json::Value>(&text).unwrap());
+            }
+        }
+        // The push is seen both as a sent event and as the reply to it
+        assert!(frames.iter().any(|frame| frame["event"] == "sent"));
+        assert!(frames
+            .iter()
+            .any(|frame| frame["type"] == "pushed" && frame["sent"] == true));
+    }
+}
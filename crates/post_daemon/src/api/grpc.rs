@@ -0,0 +1,433 @@
+use super::{
+    broadcast, current_sync_manager, daemon_status, describe_caller, tokens_match, ApiError,
+    ApiState, StatusResponse, PING_TIMEOUT,
+};
+use crate::audit::AuditKind;
+use axum::http::StatusCode;
+use futures_util::Stream;
+use post_core::{PostError, Result, SyncEvent};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast as events, watch};
+use tonic::{Code, Request, Response, Status};
+use tracing::{info, warn};
+
+mod proto {
+    tonic::include_proto!("post.v1");
+}
+
+use proto::event::Event as Kind;
+use proto::post_server::{Post, PostServer};
+
+/// Serve the gRPC control service on `addr` until `shutdown` becomes true
+///
+/// The service has no TLS, so `addr` must be a loopback address.
+pub async fn start_grpc_server(
+    state: ApiState,
+    addr: SocketAddr,
+    shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    if !addr.ip().to_canonical().is_loopback() {
+        return Err(PostError::Config(format!(
+            "grpc.bind {} isn't a loopback address; the gRPC service has no TLS, so tokens \
+             and clipboard content would cross the network in the clear",
+            addr.ip()
+        )));
+    }
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| PostError::Network(format!("Failed to bind gRPC to {}: {}", addr, e)))?;
+    info!("Starting gRPC service on {}", addr);
+    serve(listener, state, shutdown).await
+}
+
+async fn serve(
+    listener: TcpListener,
+    state: ApiState,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let connections = futures_util::stream::unfold(listener, |listener| async move {
+        let connection = listener.accept().await.map(|(stream, _)| stream);
+        Some((connection, listener))
+    });
+    tonic::transport::Server::builder()
+        .add_service(PostServer::new(PostService { state }))
+        .serve_with_incoming_shutdown(connections, async move {
+            let _ = shutdown.wait_for(|stop| *stop).await;
+            info!("Stopping gRPC service");
+        })
+        .await
+        .map_err(|e| PostError::Network(format!("gRPC service failed: {}", e)))
+}
+
+/// The `post.v1.Post` service, answering from the same state as the HTTP API
+struct PostService {
+    state: ApiState,
+}
+
+impl PostService {
+    /// Whether the call carries the API token or a paired device's in `authorization` metadata
+    fn is_authorized<T>(&self, request: &Request<T>) -> bool {
+        bearer_token(request).is_some_and(|token| {
+            tokens_match(token, &self.state.token) || self.state.pairing.is_device_token(token)
+        })
+    }
+
+    /// Record the call and how it was answered in the audit log, if it is kept
+    fn audit<T, R>(
+        &self,
+        request: &Request<T>,
+        method: &str,
+        result: &std::result::Result<R, Status>,
+    ) {
+        let Some(audit) = &self.state.audit else {
+            return;
+        };
+        let code = match result {
+            Ok(_) => Code::Ok,
+            Err(status) => status.code(),
+        };
+        audit.record(
+            AuditKind::Api,
+            describe_caller(&self.state, bearer_token(request)),
+            format!("gRPC {} -> {:?}", method, code),
+        );
+    }
+
+    async fn collect(
+        &self,
+        node: String,
+    ) -> std::result::Result<Response<proto::PullReply>, Status> {
+        let content = current_sync_manager(&self.state)
+            .await?
+            .collect(&node, PING_TIMEOUT)
+            .await
+            .map_err(|e| match e {
+                PostError::Timeout(_) => Status::deadline_exceeded(e.to_string()),
+                e => Status::invalid_argument(e.to_string()),
+            })?;
+        Ok(Response::new(proto::PullReply { node, content }))
+    }
+
+    /// Sync events until the daemon stops, like `/api/v1/events`
+    fn events(&self) -> EventStream {
+        let subscription = (self.state.events.subscribe(), self.state.shutdown.clone());
+        Box::pin(futures_util::stream::unfold(
+            subscription,
+            |(mut events, mut shutdown)| async move {
+                loop {
+                    let received = tokio::select! {
+                        received = events.recv() => received,
+                        _ = shutdown.wait_for(|stop| *stop) => return None,
+                    };
+                    match received {
+                        Ok(event) => return Some((Ok(event.into()), (events, shutdown))),
+                        Err(events::error::RecvError::Lagged(missed)) => {
+                            warn!("gRPC subscriber fell behind and missed {} events", missed)
+                        }
+                        Err(events::error::RecvError::Closed) => return None,
+                    }
+                }
+            },
+        ))
+    }
+}
+
+fn bearer_token<T>(request: &Request<T>) -> Option<&str> {
+    request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+fn invalid_token() -> Status {
+    Status::unauthenticated("Missing or invalid API token")
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = std::result::Result<proto::Event, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Post for PostService {
+    async fn status(
+        &self,
+        request: Request<proto::StatusRequest>,
+    ) -> std::result::Result<Response<proto::StatusReply>, Status> {
+        let state = &self.state;
+        let result = if self.is_authorized(&request) {
+            let status =
+                daemon_status(&state.sync_manager, state.transport.as_ref(), &state.paused).await;
+            Ok(Response::new(status.into()))
+        } else {
+            Err(invalid_token())
+        };
+        self.audit(&request, "Status", &result);
+        result
+    }
+
+    async fn push(
+        &self,
+        request: Request<proto::PushRequest>,
+    ) -> std::result::Result<Response<proto::PushReply>, Status> {
+        let result = if self.is_authorized(&request) {
+            broadcast(&self.state, request.get_ref().text.clone())
+                .await
+                .map(|sent| Response::new(proto::PushReply { sent: sent.0.sent }))
+                .map_err(Status::from)
+        } else {
+            Err(invalid_token())
+        };
+        self.audit(&request, "Push", &result);
+        result
+    }
+
+    async fn pull(
+        &self,
+        request: Request<proto::PullRequest>,
+    ) -> std::result::Result<Response<proto::PullReply>, Status> {
+        let result = if self.is_authorized(&request) {
+            self.collect(request.get_ref().node.clone()).await
+        } else {
+            Err(invalid_token())
+        };
+        self.audit(&request, "Pull", &result);
+        result
+    }
+
+    type SubscribeStream = EventStream;
+
+    async fn subscribe(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> std::result::Result<Response<Self::SubscribeStream>, Status> {
+        let result = if self.is_authorized(&request) {
+            Ok(Response::new(self.events()))
+        } else {
+            Err(invalid_token())
+        };
+        self.audit(&request, "Subscribe", &result);
+        result
+    }
+}
+
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Self {
+        let code = match error.status {
+            StatusCode::BAD_REQUEST => Code::InvalidArgument,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::UNPROCESSABLE_ENTITY => Code::FailedPrecondition,
+            StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+            StatusCode::GATEWAY_TIMEOUT => Code::DeadlineExceeded,
+            _ => Code::Internal,
+        };
+        Status::new(code, error.message)
+    }
+}
+
+impl From<StatusResponse> for proto::StatusReply {
+    fn from(status: StatusResponse) -> Self {
+        Self {
+            connected: status.connected,
+            node_id: status.node_id,
+            node_name: status.node_name,
+            peer_count: status.peer_count as u64,
+            pending_acks: status.pending_acks as u64,
+            incompatible_peers: status.incompatible_peers as u64,
+            paused: status.paused,
+        }
+    }
+}
+
+impl From<SyncEvent> for proto::Event {
+    fn from(event: SyncEvent) -> Self {
+        use proto::event::*;
+
+        let kind = match event {
+            SyncEvent::Received {
+                from,
+                from_name,
+                content,
+                timestamp,
+            } => Kind::Received(Received {
+                from,
+                from_name,
+                content,
+                timestamp,
+            }),
+            SyncEvent::Sent {
+                content,
+                peers,
+                timestamp,
+            } => Kind::Sent(Sent {
+                content,
+                peers: peers as u64,
+                timestamp,
+            }),
+            SyncEvent::PeerDiscovered { id, name } => Kind::PeerDiscovered(Peer { id, name }),
+            SyncEvent::PeerOffline {
+                id,
+                name,
+                last_seen,
+            } => Kind::PeerOffline(PeerOffline {
+                id,
+                name,
+                last_seen,
+            }),
+            SyncEvent::Connected { node_id } => Kind::Connected(Connected { node_id }),
+            SyncEvent::Disconnected => Kind::Disconnected(Empty {}),
+            SyncEvent::TailnetPeerOnline { address, name } => {
+                Kind::TailnetPeerOnline(TailnetPeer { address, name })
+            }
+            SyncEvent::TailnetPeerOffline { address, name } => {
+                Kind::TailnetPeerOffline(TailnetPeer { address, name })
+            }
+            SyncEvent::ClipboardUnavailable { error } => {
+                Kind::ClipboardUnavailable(ClipboardUnavailable { error })
+            }
+            SyncEvent::ClipboardRecovered => Kind::ClipboardRecovered(Empty {}),
+            SyncEvent::Paused => Kind::Paused(Empty {}),
+            SyncEvent::Resumed => Kind::Resumed(Empty {}),
+            SyncEvent::ClockSkew {
+                node_id,
+                node_name,
+                skew_secs,
+            } => Kind::ClockSkew(ClockSkew {
+                node_id,
+                node_name,
+                skew_secs,
+            }),
+            SyncEvent::Filtered { from, reason } => Kind::Filtered(Filtered { from, reason }),
+        };
+        Self { event: Some(kind) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::post_client::PostClient;
+    use super::*;
+    use crate::api::tests::{test_state, TOKEN};
+    use futures_util::StreamExt;
+    use post_core::{EventSender, MockClipboard, SyncManager};
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+    use tonic::transport::Channel;
+
+    async fn spawn_grpc(sync_manager: Option<Arc<SyncManager>>, events: EventSender) -> u16 {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (stop, shutdown) = watch::channel(false);
+        let state = ApiState {
+            events,
+            ..test_state(sync_manager, shutdown.clone())
+        };
+        tokio::spawn(async move {
+            let _stop = stop;
+            serve(listener, state, shutdown).await
+        });
+        port
+    }
+
+    async fn connect(port: u16) -> PostClient<Channel> {
+        PostClient::connect(format!("http://127.0.0.1:{}", port))
+            .await
+            .unwrap()
+    }
+
+    fn authorized<T>(message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.metadata_mut().insert(
+            "authorization",
+            format!("Bearer {}", TOKEN).parse().unwrap(),
+        );
+        request
+    }
+
+    #[tokio::test]
+    async fn test_status_needs_a_token() {
+        let sync = Arc::new(
+            SyncManager::new(Arc::new(MockClipboard::new()), "node-a".to_string()).unwrap(),
+        );
+        let port = spawn_grpc(Some(sync), post_core::event_channel()).await;
+        let mut client = connect(port).await;
+
+        let refused = client.status(proto::StatusRequest {}).await.unwrap_err();
+        assert_eq!(refused.code(), Code::Unauthenticated);
+        let status = client
+            .status(authorized(proto::StatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.node_id.as_deref(), Some("node-a"));
+        assert_eq!(status.peer_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_push_and_pull_need_a_token() {
+        let port = spawn_grpc(None, post_core::event_channel()).await;
+        let mut client = connect(port).await;
+
+        let push = client
+            .push(proto::PushRequest {
+                text: "hello".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(push.code(), Code::Unauthenticated);
+        let pull = client
+            .pull(proto::PullRequest {
+                node: "node-b".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(pull.code(), Code::Unauthenticated);
+
+        // With the token, it is the missing sync manager that stops the push
+        let push = client
+            .push(authorized(proto::PushRequest {
+                text: "hello".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(push.code(), Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_non_loopback_bind_is_refused() {
+        let (_stop, shutdown) = watch::channel(false);
+        let state = test_state(None, shutdown.clone());
+        let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+        let refused = start_grpc_server(state, addr, shutdown).await.unwrap_err();
+        assert!(refused.to_string().contains("no TLS"), "{}", refused);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_streams_sync_events() {
+        let events = post_core::event_channel();
+        let port = spawn_grpc(None, events.clone()).await;
+
+        let mut stream = connect(port)
+            .await
+            .subscribe(authorized(proto::SubscribeRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        events.send(SyncEvent::Paused).unwrap();
+        events
+            .send(SyncEvent::PeerDiscovered {
+                id: "node-b".to_string(),
+                name: "laptop".to_string(),
+            })
+            .unwrap();
+
+        let first = stream.next().await.unwrap().unwrap();
+        assert!(matches!(first.event, Some(Kind::Paused(_))));
+        let second = stream.next().await.unwrap().unwrap();
+        match second.event {
+            Some(Kind::PeerDiscovered(peer)) => assert_eq!(peer.name, "laptop"),
+            other => panic!("expected a discovered peer, got {:?}", other),
+        }
+    }
+}
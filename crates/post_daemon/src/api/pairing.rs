@@ -0,0 +1,240 @@
+use super::{to_hex, tokens_match};
+use post_core::{PostError, Result};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// How long a pairing code shown as a QR code can be redeemed
+pub const CODE_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// Longest device name accepted when pairing
+const MAX_DEVICE_NAME: usize = 64;
+
+/// A device paired through the API, e.g. a phone without Tailscale's local API
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PairedDevice {
+    pub name: String,
+    /// Unix time the device was paired at
+    pub paired_at: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredDevice {
+    name: String,
+    token: String,
+    paired_at: u64,
+}
+
+/// File holding the tokens of paired devices, readable only by the owner
+pub fn paired_devices_path() -> Result<PathBuf> {
+    Ok(crate::private_data_dir()?.join("paired_devices.json"))
+}
+
+/// One-time pairing codes and the API tokens of devices that redeemed them
+#[derive(Default)]
+pub struct PairingStore {
+    /// Where devices are saved; `None` keeps them in memory only
+    path: Option<PathBuf>,
+    pending: Mutex<HashMap<String, Instant>>,
+    devices: Mutex<Vec<StoredDevice>>,
+}
+
+impl PairingStore {
+    /// Load the devices saved at `path`, starting empty if there are none or the file
+    /// can't be parsed
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let devices = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring invalid {}, devices have to pair again: {}",
+                    path.display(),
+                    e
+                );
+                Vec::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: Some(path),
+            pending: Mutex::new(HashMap::new()),
+            devices: Mutex::new(devices),
+        })
+    }
+
+    /// A new single-use pairing code, valid for [`CODE_LIFETIME`]
+    pub fn start(&self) -> String {
+        let code = random_hex(10);
+        let mut pending = self.lock_pending();
+        pending.retain(|_, created| created.elapsed() < CODE_LIFETIME);
+        pending.insert(code.clone(), Instant::now());
+        code
+    }
+
+    /// Exchange `code` for an API token for `device_name`, replacing any earlier pairing
+    /// under that name; `None` if the code is unknown, used or expired
+    pub fn redeem(&self, code: &str, device_name: &str) -> Result<Option<String>> {
+        let device_name = device_name.trim();
+        if device_name.is_empty() || device_name.chars().count() > MAX_DEVICE_NAME {
+            return Err(PostError::Config(format!(
+                "Device names must be 1 to {} characters",
+                MAX_DEVICE_NAME
+            )));
+        }
+
+        let created = self.lock_pending().remove(code);
+        if created.is_none_or(|created| created.elapsed() >= CODE_LIFETIME) {
+            return Ok(None);
+        }
+
+        let token = random_hex(32);
+        let mut devices = self.lock_devices();
+        devices.retain(|device| device.name != device_name);
+        devices.push(StoredDevice {
+            name: device_name.to_string(),
+            token: token.clone(),
+            paired_at: unix_now(),
+        });
+        self.save(&devices)?;
+        info!("Paired device {}", device_name);
+        Ok(Some(token))
+    }
+
+    /// Whether `token` belongs to a paired device
+    pub fn is_device_token(&self, token: &str) -> bool {
+        self.device_name(token).is_some()
+    }
+
+    /// Name of the paired device `token` belongs to
+    pub fn device_name(&self, token: &str) -> Option<String> {
+        self.lock_devices()
+            .iter()
+            .find(|device| tokens_match(token, &device.token))
+            .map(|device| device.name.clone())
+    }
+
+    pub fn devices(&self) -> Vec<PairedDevice> {
+        self.lock_devices()
+            .iter()
+            .map(|device| PairedDevice {
+                name: device.name.clone(),
+                paired_at: device.paired_at,
+            })
+            .collect()
+    }
+
+    /// Revoke the token of the device paired as `name`; false if there is none
+    pub fn revoke(&self, name: &str) -> Result<bool> {
+        let mut devices = self.lock_devices();
+        let before = devices.len();
+        devices.retain(|device| device.name != name);
+        if devices.len() == before {
+            return Ok(false);
+        }
+        self.save(&devices)?;
+        info!("Revoked paired device {}", name);
+        Ok(true)
+    }
+
+    fn lock_pending(&self) -> MutexGuard<'_, HashMap<String, Instant>> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_devices(&self) -> MutexGuard<'_, Vec<StoredDevice>> {
+        self.devices
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn save(&self, devices: &[StoredDevice]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(devices)
+            .map_err(|e| PostError::Serialization(e.to_string()))?;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        // A file from an earlier version may have been created readable by others
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(json.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_single_use_and_devices_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("paired_devices.json");
+        let store = PairingStore::load(path.clone()).unwrap();
+
+        let code = store.start();
+        assert!(store.redeem(&code, " ").is_err());
+        let token = store.redeem(&code, "phone").unwrap().unwrap();
+        assert!(store.redeem(&code, "phone").unwrap().is_none());
+        assert!(store.redeem("made-up", "phone").unwrap().is_none());
+        assert!(store.is_device_token(&token));
+
+        let reloaded = PairingStore::load(path.clone()).unwrap();
+        assert!(reloaded.is_device_token(&token));
+        assert_eq!(reloaded.devices()[0].name, "phone");
+
+        assert!(reloaded.revoke("phone").unwrap());
+        assert!(!reloaded.revoke("phone").unwrap());
+        assert!(!reloaded.is_device_token(&token));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_invalid_store_starts_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("paired_devices.json");
+        std::fs::write(&path, "{not json").unwrap();
+
+        let store = PairingStore::load(path).unwrap();
+        assert!(store.devices().is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use super::{router, ApiState, SHUTDOWN_GRACE};
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use post_core::{tailscale_cli, PostError, Result};
+use std::future::Future;
+use std::net::TcpListener;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often the certificate is re-fetched; `tailscale cert` only renews it near expiry
+const RENEW_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Serve the API over HTTPS with this node's Tailscale certificate
+pub(super) async fn serve_tls(
+    listener: TcpListener,
+    state: ApiState,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    let domain = state.transport.get_dns_name().await?;
+    let dir = crate::private_data_dir()?.join("certs");
+
+    let (cert_file, key_file) = tailscale_cli::fetch_certificate(&domain, &dir).await?;
+    let config = RustlsConfig::from_pem_file(&cert_file, &key_file)
+        .await
+        .map_err(|e| PostError::Config(format!("Failed to load TLS certificate: {}", e)))?;
+
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| PostError::Network(format!("Failed to configure API listener: {}", e)))?;
+    info!("Serving HTTPS API as https://{}", domain);
+
+    let handle = Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        shutdown.await;
+        shutdown_handle.graceful_shutdown(Some(SHUTDOWN_GRACE));
+    });
+
+    let server = axum_server::from_tcp_rustls(listener, config.clone())
+        .handle(handle)
+        .serve(router(state).into_make_service());
+
+    tokio::select! {
+        result = server => {
+            result.map_err(|e| PostError::Network(format!("API server failed: {}", e)))
+        }
+        _ = renew_certificate(&config, &domain, &dir) => Ok(()),
+    }
+}
+
+async fn renew_certificate(config: &RustlsConfig, domain: &str, dir: &Path) {
+    loop {
+        tokio::time::sleep(RENEW_INTERVAL).await;
+
+        match tailscale_cli::fetch_certificate(domain, dir).await {
+            Ok((cert_file, key_file)) => {
+                if let Err(e) = config.reload_from_pem_file(&cert_file, &key_file).await {
+                    warn!("Failed to reload TLS certificate: {}", e);
+                }
+            }
+            Err(e) => warn!("Failed to renew TLS certificate: {}", e),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use chrono::{Local, NaiveDate, TimeZone};
+use post_core::{AuditConfig, PostError, Result, SyncEvent};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Who an action done on this device is attributed to
+pub const LOCAL_ACTOR: &str = "this device";
+
+/// What an audit entry records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditKind {
+    /// A request to the HTTP API
+    Api,
+    /// Content from a peer applied to the clipboard here
+    Received,
+    /// Content copied here sent to peers
+    Sent,
+    /// Content left unsynced by a filter or rule
+    Filtered,
+}
+
+impl AuditKind {
+    pub const ALL: [AuditKind; 4] = [
+        AuditKind::Api,
+        AuditKind::Received,
+        AuditKind::Sent,
+        AuditKind::Filtered,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditKind::Api => "api",
+            AuditKind::Received => "received",
+            AuditKind::Sent => "sent",
+            AuditKind::Filtered => "filtered",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditKind {
+    type Err = PostError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        AuditKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| {
+                PostError::Other(format!(
+                    "Unknown audit entry kind {}; expected api, received, sent or filtered",
+                    s
+                ))
+            })
+    }
+}
+
+/// One line of the audit log: who did what, and when
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time
+    pub timestamp: u64,
+    pub kind: AuditKind,
+    /// The API caller, the peer content came from, or [`LOCAL_ACTOR`]
+    pub actor: String,
+    pub action: String,
+}
+
+/// Entries `post audit` shows
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    /// Unix time entries must be at or after
+    pub since: Option<u64>,
+    pub kind: Option<AuditKind>,
+    /// Text the actor must contain, ignoring case
+    pub actor: Option<String>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.kind.is_none_or(|kind| entry.kind == kind)
+            && self
+                .actor
+                .as_ref()
+                .is_none_or(|actor| entry.actor.to_lowercase().contains(&actor.to_lowercase()))
+    }
+}
+
+/// The audit log file, only ever appended to
+///
+/// Clipboard content is never written to it, only its size. Entries are written by a
+/// thread of their own, so recording one never blocks the caller on the disk; dropping
+/// the log waits for those queued to be written.
+pub struct AuditLog {
+    path: PathBuf,
+    lines: Mutex<Option<Sender<String>>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl AuditLog {
+    /// Open the log at `path` for appending, creating it readable by the owner only
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(path).map_err(|e| {
+            PostError::Other(format!(
+                "Failed to open audit log {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let (lines, queued) = mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("audit log".to_string())
+            .spawn({
+                let path = path.to_path_buf();
+                move || write_lines(file, &path, queued)
+            })?;
+        info!("Writing the audit log to {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            lines: Mutex::new(Some(lines)),
+            writer: Mutex::new(Some(writer)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, kind: AuditKind, actor: impl Into<String>, action: impl Into<String>) {
+        let entry = AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            kind,
+            actor: actor.into(),
+            action: action.into(),
+        };
+        let Ok(mut line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        line.push('\n');
+        let lines = self.lines.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(lines) = lines.as_ref() {
+            // Only fails once the writer has stopped, which it has already warned about
+            let _ = lines.send(line);
+        }
+    }
+
+    /// Record sync activity, e.g. from [`post_core::SyncManager::with_event_hook`] so
+    /// none is missed
+    pub fn record_event(&self, event: &SyncEvent) {
+        match event {
+            SyncEvent::Received {
+                from,
+                from_name,
+                content,
+                ..
+            } => self.record(
+                AuditKind::Received,
+                format!("{} ({})", from_name, from),
+                format!("applied {} bytes to the clipboard", content.len()),
+            ),
+            SyncEvent::Sent { content, peers, .. } => self.record(
+                AuditKind::Sent,
+                LOCAL_ACTOR,
+                format!("sent {} bytes to {} peer(s)", content.len(), peers),
+            ),
+            SyncEvent::Filtered { from, reason } => self.record(
+                AuditKind::Filtered,
+                from.as_deref().unwrap_or(LOCAL_ACTOR),
+                format!("not synced: {}", reason),
+            ),
+            _ => {}
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        self.lines
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        let writer = self
+            .writer
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Append each line from `queued` to `file` until every sender is gone
+fn write_lines(mut file: File, path: &Path, queued: mpsc::Receiver<String>) {
+    for line in queued {
+        // One write per entry, so a crash can only ever damage the last line
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            warn!("Failed to write to audit log {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Where the audit log is kept under `config`
+pub fn audit_log_path(config: &AuditConfig) -> Result<PathBuf> {
+    match &config.path {
+        Some(path) => Ok(path.clone()),
+        None => Ok(crate::private_data_dir()?.join("audit.log")),
+    }
+}
+
+/// Entries of the log at `path` matching `query`, oldest first; none if there is no log
+pub fn read(path: &Path, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // A crash mid-write can only damage the last line; keep reading past it
+        match serde_json::from_str::<AuditEntry>(&line) {
+            Ok(entry) if query.matches(&entry) => entries.push(entry),
+            Ok(_) => {}
+            Err(e) => warn!("Skipping line {} of {}: {}", number + 1, path.display(), e),
+        }
+    }
+    Ok(entries)
+}
+
+/// `timestamp` in local time, as `post audit` shows it
+pub fn format_time(timestamp: u64) -> String {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map_or_else(
+            || timestamp.to_string(),
+            |time| time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        )
+}
+
+/// Unix time for `--since`: an age such as `30m`, `12h` or `7d`, or a date such as
+/// `2024-05-01` in local time
+pub fn parse_since(text: &str, now: u64) -> Result<u64> {
+    let text = text.trim();
+    let invalid = || {
+        PostError::Other(format!(
+            "Invalid time {:?}; use an age such as 30m, 12h or 7d, or a date such as 2024-05-01",
+            text
+        ))
+    };
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        return Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|time| time.timestamp().max(0) as u64)
+            .ok_or_else(invalid);
+    }
+
+    let split = text.len().saturating_sub(1);
+    let (amount, unit) = (text.get(..split).ok_or_else(invalid)?, &text[split..]);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    Ok(now.saturating_sub(amount.saturating_mul(seconds)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_are_appended_and_queried() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::open(&path).unwrap();
+        log.record(AuditKind::Api, "owner", "PUT /api/v1/sync/paused -> 200");
+        log.record_event(&SyncEvent::Received {
+            from: "node-b".to_string(),
+            from_name: "laptop".to_string(),
+            content: "hunter2".to_string(),
+            timestamp: 0,
+        });
+        log.record_event(&SyncEvent::Filtered {
+            from: None,
+            reason: "marked as a secret".to_string(),
+        });
+        drop(log);
+
+        // Reopening appends rather than starting over
+        AuditLog::open(&path).unwrap().record(
+            AuditKind::Api,
+            "anonymous",
+            "GET /api/v1/status -> 200",
+        );
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"truncated")
+            .unwrap();
+
+        let all = read(&path, &AuditQuery::default()).unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[1].actor, "laptop (node-b)");
+        assert_eq!(all[1].action, "applied 7 bytes to the clipboard");
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("hunter2"));
+
+        let query = AuditQuery {
+            kind: Some(AuditKind::Api),
+            actor: Some("OWN".to_string()),
+            ..AuditQuery::default()
+        };
+        assert_eq!(read(&path, &query).unwrap().len(), 1);
+        let future = AuditQuery {
+            since: Some(u64::MAX),
+            ..AuditQuery::default()
+        };
+        assert!(read(&path, &future).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_since_takes_ages_and_dates() {
+        assert_eq!(parse_since("30m", 10_000).unwrap(), 10_000 - 30 * 60);
+        assert_eq!(parse_since("7d", 10).unwrap(), 0);
+        assert!(parse_since("2024-05-01", 0).unwrap() > 1_700_000_000);
+        for invalid in ["", "m", "5w", "yesterday", "2024-13-01"] {
+            assert!(parse_since(invalid, 0).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,486 @@
+use crate::resolve_node_identity;
+use futures_util::future::BoxFuture;
+use post_core::directory::PeerDirectory;
+use post_core::{NodeConfig, Result, TailscaleTransport, Transport};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// How often Tailscale is probed unless configured otherwise
+pub const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Longest gap between probes while disconnected unless configured otherwise
+pub const DEFAULT_MAX_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Whether this node can currently sync
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectivityState {
+    Connected,
+    /// Tailscale is up but this node's identity couldn't be read, so sync can't start
+    Degraded,
+    Disconnected,
+}
+
+/// What a single probe found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeResult {
+    Connected { node_id: String, node_name: String },
+    Degraded(String),
+    Disconnected,
+}
+
+impl ProbeResult {
+    pub fn state(&self) -> ConnectivityState {
+        match self {
+            ProbeResult::Connected { .. } => ConnectivityState::Connected,
+            ProbeResult::Degraded(_) => ConnectivityState::Degraded,
+            ProbeResult::Disconnected => ConnectivityState::Disconnected,
+        }
+    }
+}
+
+/// Checks whether Tailscale is usable, e.g. by asking its local API
+#[async_trait::async_trait]
+pub trait ConnectivityProbe: Send + Sync {
+    async fn probe(&self) -> ProbeResult;
+}
+
+/// Called with the probe result whenever the state changes
+pub type ConnectivityCallback = Arc<dyn Fn(ProbeResult) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Finds and connects a Tailscale local API client
+type Detector = Box<dyn Fn() -> BoxFuture<'static, Result<Arc<dyn Transport>>> + Send + Sync>;
+
+/// Probes connectivity on an interval and reports state changes to its callbacks
+pub struct ConnectivityManager {
+    probe: Arc<dyn ConnectivityProbe>,
+    probe_interval: Duration,
+    max_probe_interval: Duration,
+    /// Probes in a row that found Tailscale down, which stretch the interval
+    failed_probes: u32,
+    state: ConnectivityState,
+    /// Node ID from the last connected probe, to spot identity changes
+    node_id: Option<String>,
+    callbacks: Vec<ConnectivityCallback>,
+}
+
+impl ConnectivityManager {
+    pub fn new(probe: Arc<dyn ConnectivityProbe>) -> Self {
+        Self {
+            probe,
+            probe_interval: DEFAULT_PROBE_INTERVAL,
+            max_probe_interval: DEFAULT_MAX_PROBE_INTERVAL,
+            failed_probes: 0,
+            state: ConnectivityState::Disconnected,
+            node_id: None,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// Probe every `interval`, doubling it up to `max` while disconnected
+    pub fn with_probe_interval(mut self, interval: Duration, max: Duration) -> Self {
+        self.probe_interval = interval;
+        self.max_probe_interval = max.max(interval);
+        self
+    }
+
+    /// State already set up before monitoring starts, so it isn't reported again
+    pub fn with_initial_state(mut self, state: ConnectivityState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Run `callback` on every state or node ID change, in order and before the next probe
+    pub fn on_change(mut self, callback: ConnectivityCallback) -> Self {
+        self.callbacks.push(callback);
+        self
+    }
+
+    pub fn state(&self) -> ConnectivityState {
+        self.state
+    }
+
+    /// Delay before the next probe: the base interval, backed off while disconnected
+    pub fn next_delay(&self) -> Duration {
+        let factor = 1u32 << self.failed_probes.min(16);
+        self.probe_interval
+            .saturating_mul(factor)
+            .min(self.max_probe_interval)
+    }
+
+    /// Record a probe result, notifying the callbacks if the state or node ID changed
+    pub async fn observe(&mut self, result: ProbeResult) -> bool {
+        let state = result.state();
+        self.failed_probes = match state {
+            ConnectivityState::Disconnected => self.failed_probes.saturating_add(1),
+            _ => 0,
+        };
+
+        let mut previous_id = None;
+        if let ProbeResult::Connected { node_id, .. } = &result {
+            previous_id = self.node_id.replace(node_id.clone());
+        }
+        let id_changed = state == ConnectivityState::Connected
+            && previous_id.is_some_and(|previous| Some(previous) != self.node_id);
+        if state == self.state && !id_changed {
+            debug!("Connectivity check: still {:?}", state);
+            return false;
+        }
+
+        match &result {
+            ProbeResult::Connected { node_id, .. } if id_changed => {
+                info!("Tailscale node ID changed to {}", node_id)
+            }
+            ProbeResult::Connected { node_id, .. } => info!("Tailscale connected: {}", node_id),
+            ProbeResult::Degraded(reason) => warn!("Tailscale connectivity degraded: {}", reason),
+            ProbeResult::Disconnected => info!("Tailscale disconnected"),
+        }
+        self.state = state;
+
+        for callback in &self.callbacks {
+            callback(result.clone()).await;
+        }
+        true
+    }
+
+    /// Probe until the task is cancelled
+    pub async fn run(mut self) -> Result<()> {
+        info!(
+            "Monitoring Tailscale every {:?} (up to {:?} while disconnected), currently {:?}",
+            self.probe_interval, self.max_probe_interval, self.state
+        );
+        loop {
+            let result = self.probe.probe().await;
+            self.observe(result).await;
+            tokio::time::sleep(self.next_delay()).await;
+        }
+    }
+}
+
+/// Probes the Tailscale local API, detecting it and reading this node's identity again
+/// only once it stops answering
+pub struct TailscaleProbe {
+    detect: Detector,
+    node_config: NodeConfig,
+    /// Client from the last successful detection, reused while it keeps working
+    detected: Mutex<Option<Arc<dyn Transport>>>,
+    /// Node ID and name read through that client, kept for as long as it is
+    identity: Mutex<Option<(String, String)>>,
+    /// Peer directory whose successful refreshes show the local API answering, and how
+    /// recent one has to be to stand in for asking it again
+    peer_directory: Option<(Arc<PeerDirectory>, Duration)>,
+}
+
+impl TailscaleProbe {
+    /// Probe the local API on `port`, or at `socket_path` when one is configured
+    pub fn new(port: u16, socket_path: Option<String>, node_config: NodeConfig) -> Self {
+        Self::with_detector(
+            Box::new(move || {
+                let socket_path = socket_path.clone();
+                Box::pin(async move {
+                    let transport =
+                        TailscaleTransport::new_with_detection(port, socket_path.as_deref())
+                            .await?;
+                    Ok(Arc::new(transport) as Arc<dyn Transport>)
+                })
+            }),
+            node_config,
+        )
+    }
+
+    fn with_detector(detect: Detector, node_config: NodeConfig) -> Self {
+        Self {
+            detect,
+            node_config,
+            detected: Mutex::new(None),
+            identity: Mutex::new(None),
+            peer_directory: None,
+        }
+    }
+
+    /// Take a refresh of `directory` within `fresh_for` as the local API answering,
+    /// rather than asking it again
+    pub fn with_peer_directory(
+        mut self,
+        directory: Arc<PeerDirectory>,
+        fresh_for: Duration,
+    ) -> Self {
+        self.peer_directory = Some((directory, fresh_for));
+        self
+    }
+
+    fn lock_detected(&self) -> std::sync::MutexGuard<'_, Option<Arc<dyn Transport>>> {
+        self.detected
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn lock_identity(&self) -> std::sync::MutexGuard<'_, Option<(String, String)>> {
+        self.identity
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// A connected client: the cached one if it still answers, otherwise a freshly
+    /// detected one, in case the socket moved or tailscaled restarted
+    async fn connected_transport(&self) -> Option<Arc<dyn Transport>> {
+        let cached = self.lock_detected().clone();
+        if let Some(transport) = cached {
+            let refreshed = self
+                .peer_directory
+                .as_ref()
+                .is_some_and(|(directory, fresh_for)| directory.refreshed_within(*fresh_for));
+            if refreshed || transport.is_connected().await.unwrap_or(false) {
+                return Some(transport);
+            }
+            debug!("Cached Tailscale client stopped answering, detecting again");
+            self.lock_detected().take();
+            // The node may come back logged in as another one
+            self.lock_identity().take();
+        }
+
+        let transport = match (self.detect)().await {
+            Ok(transport) => transport,
+            Err(e) => {
+                debug!("Tailscale not detected: {}", e);
+                return None;
+            }
+        };
+        if !transport.is_connected().await.unwrap_or(false) {
+            return None;
+        }
+        *self.lock_detected() = Some(Arc::clone(&transport));
+        Some(transport)
+    }
+}
+
+#[async_trait::async_trait]
+impl ConnectivityProbe for TailscaleProbe {
+    async fn probe(&self) -> ProbeResult {
+        let Some(transport) = self.connected_transport().await else {
+            return ProbeResult::Disconnected;
+        };
+        if let Some((node_id, node_name)) = self.lock_identity().clone() {
+            return ProbeResult::Connected { node_id, node_name };
+        }
+
+        match transport.get_node_id().await {
+            Ok(tailscale_id) => {
+                let (node_id, node_name) =
+                    resolve_node_identity(&self.node_config, transport.as_ref(), tailscale_id)
+                        .await;
+                *self.lock_identity() = Some((node_id.clone(), node_name.clone()));
+                ProbeResult::Connected { node_id, node_name }
+            }
+            Err(e) => ProbeResult::Degraded(format!("couldn't get this node's ID: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    struct NeverProbed;
+
+    #[async_trait::async_trait]
+    impl ConnectivityProbe for NeverProbed {
+        async fn probe(&self) -> ProbeResult {
+            unreachable!("tests feed results to observe directly")
+        }
+    }
+
+    fn connected() -> ProbeResult {
+        ProbeResult::Connected {
+            node_id: "node-a".to_string(),
+            node_name: "laptop".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_state_changes_reach_callbacks() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&seen);
+        let mut manager = ConnectivityManager::new(Arc::new(NeverProbed)).on_change(Arc::new(
+            move |result: ProbeResult| {
+                recorder.lock().unwrap().push(result.state());
+                Box::pin(async {})
+            },
+        ));
+
+        assert!(!manager.observe(ProbeResult::Disconnected).await);
+        assert!(manager.observe(connected()).await);
+        assert!(!manager.observe(connected()).await);
+        assert!(
+            manager
+                .observe(ProbeResult::Degraded("no node ID".into()))
+                .await
+        );
+        assert!(
+            !manager
+                .observe(ProbeResult::Degraded("still no node ID".into()))
+                .await
+        );
+        assert!(manager.observe(connected()).await);
+        assert!(manager.observe(ProbeResult::Disconnected).await);
+
+        assert_eq!(manager.state(), ConnectivityState::Disconnected);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                ConnectivityState::Connected,
+                ConnectivityState::Degraded,
+                ConnectivityState::Connected,
+                ConnectivityState::Disconnected,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probes_back_off_while_disconnected() {
+        let mut manager = ConnectivityManager::new(Arc::new(NeverProbed))
+            .with_probe_interval(Duration::from_secs(2), Duration::from_secs(60));
+        assert_eq!(manager.next_delay(), Duration::from_secs(2));
+
+        let mut delays = Vec::new();
+        for _ in 0..7 {
+            manager.observe(ProbeResult::Disconnected).await;
+            delays.push(manager.next_delay().as_secs());
+        }
+        assert_eq!(delays, vec![4, 8, 16, 32, 60, 60, 60]);
+
+        manager.observe(connected()).await;
+        assert_eq!(manager.next_delay(), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_node_id_change_is_reported() {
+        let mut manager = ConnectivityManager::new(Arc::new(NeverProbed));
+        assert!(manager.observe(connected()).await);
+
+        let relogged = ProbeResult::Connected {
+            node_id: "node-b".to_string(),
+            node_name: "laptop".to_string(),
+        };
+        assert!(manager.observe(relogged.clone()).await);
+        assert!(!manager.observe(relogged).await);
+        assert_eq!(manager.state(), ConnectivityState::Connected);
+    }
+
+    /// Tailscale that answers while `connected` is set, as node `node_id`
+    struct FakeTailscale {
+        node_id: String,
+        connected: Arc<AtomicBool>,
+        id_reads: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for FakeTailscale {
+        async fn send_message(&self, _message: post_core::PostMessage) -> Result<()> {
+            Ok(())
+        }
+
+        async fn start_listening(&self, _sender: post_core::inbox::InboxSender) -> Result<()> {
+            Ok(())
+        }
+
+        async fn get_node_id(&self) -> Result<String> {
+            self.id_reads.fetch_add(1, Ordering::SeqCst);
+            Ok(self.node_id.clone())
+        }
+
+        async fn get_tailnet_nodes(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        async fn is_connected(&self) -> Result<bool> {
+            Ok(self.connected.load(Ordering::SeqCst))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_reuses_its_client_until_it_stops_answering() {
+        let connected = Arc::new(AtomicBool::new(true));
+        let detections = Arc::new(AtomicUsize::new(0));
+        let id_reads = Arc::new(AtomicUsize::new(0));
+        let probe = TailscaleProbe::with_detector(
+            Box::new({
+                let connected = Arc::clone(&connected);
+                let detections = Arc::clone(&detections);
+                let id_reads = Arc::clone(&id_reads);
+                move || {
+                    let detected = detections.fetch_add(1, Ordering::SeqCst) + 1;
+                    let transport: Arc<dyn Transport> = Arc::new(FakeTailscale {
+                        node_id: format!("node-{}", detected),
+                        connected: Arc::clone(&connected),
+                        id_reads: Arc::clone(&id_reads),
+                    });
+                    Box::pin(async move { Ok(transport) })
+                }
+            }),
+            post_core::PostConfig::default().node,
+        );
+        let node_id = |result: ProbeResult| match result {
+            ProbeResult::Connected { node_id, .. } => Some(node_id),
+            _ => None,
+        };
+
+        assert_eq!(node_id(probe.probe().await).as_deref(), Some("node-1"));
+        assert_eq!(node_id(probe.probe().await).as_deref(), Some("node-1"));
+        assert_eq!(detections.load(Ordering::SeqCst), 1);
+        assert_eq!(id_reads.load(Ordering::SeqCst), 1);
+
+        // A client that stops answering is detected again, and so is the identity
+        connected.store(false, Ordering::SeqCst);
+        assert_eq!(probe.probe().await, ProbeResult::Disconnected);
+        assert_eq!(detections.load(Ordering::SeqCst), 2);
+        connected.store(true, Ordering::SeqCst);
+        assert_eq!(node_id(probe.probe().await).as_deref(), Some("node-3"));
+        assert_eq!(node_id(probe.probe().await).as_deref(), Some("node-3"));
+        assert_eq!(detections.load(Ordering::SeqCst), 3);
+        assert_eq!(id_reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_probe_trusts_a_recent_directory_refresh() {
+        let connected = Arc::new(AtomicBool::new(true));
+        let detections = Arc::new(AtomicUsize::new(0));
+        let directory = Arc::new(PeerDirectory::new(Arc::new(post_core::MockTransport::new(
+            "node-a".to_string(),
+        ))));
+        let probe = TailscaleProbe::with_detector(
+            Box::new({
+                let connected = Arc::clone(&connected);
+                let detections = Arc::clone(&detections);
+                move || {
+                    detections.fetch_add(1, Ordering::SeqCst);
+                    let transport: Arc<dyn Transport> = Arc::new(FakeTailscale {
+                        node_id: "node-1".to_string(),
+                        connected: Arc::clone(&connected),
+                        id_reads: Arc::new(AtomicUsize::new(0)),
+                    });
+                    Box::pin(async move { Ok(transport) })
+                }
+            }),
+            post_core::PostConfig::default().node,
+        )
+        .with_peer_directory(Arc::clone(&directory), Duration::from_secs(60));
+
+        assert!(matches!(probe.probe().await, ProbeResult::Connected { .. }));
+        // The client isn't asked again while the directory vouches for the local API
+        directory.refresh().await.unwrap();
+        connected.store(false, Ordering::SeqCst);
+        assert!(matches!(probe.probe().await, ProbeResult::Connected { .. }));
+        assert_eq!(detections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_initial_state_is_not_reported_again() {
+        let mut manager = ConnectivityManager::new(Arc::new(NeverProbed))
+            .with_initial_state(ConnectivityState::Connected);
+
+        assert!(!manager.observe(connected()).await);
+        assert!(manager.observe(ProbeResult::Disconnected).await);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::notifications::NotificationManager;
+use crate::{get_log_file_path, private_data_dir};
+use post_core::{LoggingConfig, PostError, Result};
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::io::Write;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, warn};
+
+/// What the daemon was doing when it last panicked, kept until the next start reports it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// Unix time of the panic
+    pub time: u64,
+    pub message: String,
+    /// `file:line:column` the panic was raised at
+    pub location: Option<String>,
+    pub thread: Option<String>,
+}
+
+impl CrashReport {
+    fn from_panic(info: &PanicHookInfo<'_>) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic payload".to_string());
+
+        Self {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            message,
+            location: info.location().map(|location| location.to_string()),
+            thread: std::thread::current().name().map(str::to_string),
+        }
+    }
+
+    /// One line saying where the panic happened and why
+    pub fn summary(&self) -> String {
+        format!(
+            "thread '{}' panicked at {}: {}",
+            self.thread.as_deref().unwrap_or("<unnamed>"),
+            self.location.as_deref().unwrap_or("unknown location"),
+            self.message
+        )
+    }
+}
+
+/// File the last panic is recorded in until the next start reports it
+pub fn get_crash_marker_path() -> Result<PathBuf> {
+    Ok(private_data_dir()?.join("crash.json"))
+}
+
+pub fn write_crash_marker(path: &Path, report: &CrashReport) -> Result<()> {
+    let json = serde_json::to_vec_pretty(report)
+        .map_err(|e| PostError::Serialization(format!("Failed to encode crash report: {}", e)))?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// The crash recorded at `path`, if any, without clearing it
+pub fn read_crash_marker(path: &Path) -> Option<CrashReport> {
+    let json = std::fs::read(path).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// The crash recorded at `path`, if any, clearing it so it is only reported once
+pub fn take_crash_marker(path: &Path) -> Option<CrashReport> {
+    let report = read_crash_marker(path);
+    let _ = std::fs::remove_file(path);
+    report
+}
+
+/// Report a panic from the previous run, then record future panics with their backtraces
+/// in the log file and the crash marker
+pub fn install_panic_hook(config: &LoggingConfig) {
+    if let Ok(marker) = get_crash_marker_path() {
+        if let Some(report) = take_crash_marker(&marker) {
+            warn!(
+                "The daemon panicked during its last run (at unix time {}): {}",
+                report.time,
+                report.summary()
+            );
+        }
+    }
+
+    let notify = config.notify_on_crash;
+    std::panic::set_hook(Box::new(move |info| {
+        let report = CrashReport::from_panic(info);
+        let backtrace = Backtrace::force_capture();
+        error!("Daemon {}", report.summary());
+
+        // Under launchd or systemd stdout may not be the log file, so write there directly
+        if let Ok(log_path) = get_log_file_path() {
+            if let Ok(mut log) = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_path)
+            {
+                let _ = writeln!(
+                    log,
+                    "[crash {}] {}\n{}",
+                    report.time,
+                    report.summary(),
+                    backtrace
+                );
+            }
+        }
+        if let Ok(marker) = get_crash_marker_path() {
+            let _ = write_crash_marker(&marker, &report);
+        }
+        if notify {
+            let _ = NotificationManager::new().show_daemon_crashed(&report.message);
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crash_marker_is_reported_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("crash.json");
+        let report = CrashReport {
+            time: 1_700_000_000,
+            message: "index out of bounds".to_string(),
+            location: Some("src/sync.rs:10:5".to_string()),
+            thread: Some("tokio-runtime-worker".to_string()),
+        };
+
+        assert_eq!(take_crash_marker(&marker), None);
+        write_crash_marker(&marker, &report).unwrap();
+        assert_eq!(read_crash_marker(&marker), Some(report.clone()));
+        assert_eq!(take_crash_marker(&marker), Some(report));
+        assert_eq!(take_crash_marker(&marker), None);
+    }
+}
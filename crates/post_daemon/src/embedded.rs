@@ -0,0 +1,87 @@
+use post_core::{tailscale_cli, EmbeddedConfig, PostError, Result};
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tracing::info;
+
+/// How long tailscaled gets to open its local API socket
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A tailscaled run by the daemon with its own state and socket; stopped when dropped
+pub struct EmbeddedTailscale {
+    _child: Child,
+}
+
+impl EmbeddedTailscale {
+    /// Start tailscaled and log it in, unless its saved state is already logged in
+    pub async fn start(config: &EmbeddedConfig) -> Result<Self> {
+        if cfg!(not(unix)) {
+            return Err(PostError::Config(
+                "network.embedded is only supported on Linux and macOS".to_string(),
+            ));
+        }
+
+        let state_dir = config.state_dir()?;
+        std::fs::create_dir_all(&state_dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&state_dir, std::fs::Permissions::from_mode(0o700))?;
+        }
+
+        let socket = config.socket_path()?;
+        // A socket left by a previous run would look ready before tailscaled is
+        let _ = std::fs::remove_file(&socket);
+        let is_new = !state_dir.join("tailscaled.state").exists();
+
+        let binary = config.tailscaled.as_deref().unwrap_or("tailscaled");
+        let mut command = Command::new(binary);
+        command
+            .arg(format!("--statedir={}", state_dir.display()))
+            .arg(format!("--socket={}", socket.display()))
+            .kill_on_drop(true);
+        if let Some(tun) = &config.tun {
+            command.arg(format!("--tun={}", tun));
+        }
+
+        info!("Starting embedded tailscaled in {}", state_dir.display());
+        let mut child = command
+            .spawn()
+            .map_err(|e| PostError::Tailscale(format!("Failed to start {}: {}", binary, e)))?;
+        wait_for_socket(&mut child, &socket).await?;
+
+        match config.auth_key() {
+            Some(auth_key) => {
+                tailscale_cli::login(&socket, &auth_key, config.hostname.as_deref()).await?
+            }
+            None if is_new => return Err(PostError::Config(
+                "network.embedded needs an auth key (auth_key or TS_AUTHKEY) to join the tailnet"
+                    .to_string(),
+            )),
+            None => info!("No auth key set; using the embedded tailscaled's saved login"),
+        }
+
+        Ok(Self { _child: child })
+    }
+}
+
+async fn wait_for_socket(child: &mut Child, socket: &Path) -> Result<()> {
+    let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+    while !socket.exists() {
+        if let Some(status) = child.try_wait()? {
+            return Err(PostError::Tailscale(format!(
+                "Embedded tailscaled exited during startup ({})",
+                status
+            )));
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(PostError::Tailscale(format!(
+                "Embedded tailscaled didn't open {} within {:?}",
+                socket.display(),
+                STARTUP_TIMEOUT
+            )));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use crate::private_data_dir;
+use post_core::{PostError, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Held for the life of the daemon; the OS releases it however the process ends
+static HELD: Mutex<Option<InstanceLock>> = Mutex::new(None);
+
+/// How long taking the lock keeps trying, so another process briefly checking it
+/// doesn't look like a running daemon
+const LOCK_PATIENCE: Duration = Duration::from_millis(500);
+
+/// Lock file the daemon holds; unlike the PID file it is never removed, so the
+/// lock can't end up on a deleted file
+fn get_lock_file_path() -> Result<PathBuf> {
+    Ok(private_data_dir()?.join("post.lock"))
+}
+
+/// Take the single-instance lock, failing if another daemon holds it
+pub fn lock() -> Result<()> {
+    let mut held = HELD.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if held.is_some() {
+        return Ok(());
+    }
+
+    match acquire(&get_lock_file_path()?, LOCK_PATIENCE)? {
+        Some(lock) => {
+            *held = Some(lock);
+            Ok(())
+        }
+        None => {
+            let pid = crate::get_pid_file_path()
+                .ok()
+                .and_then(|path| std::fs::read_to_string(path).ok())
+                .map(|pid| format!(" (PID {})", pid.trim()))
+                .unwrap_or_default();
+            Err(PostError::Other(format!(
+                "Another Post daemon is already running{}; stop it with `post stop` first",
+                pid
+            )))
+        }
+    }
+}
+
+/// Whether some daemon, possibly this process, holds the lock
+pub fn is_locked() -> Result<bool> {
+    if HELD
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .is_some()
+    {
+        return Ok(true);
+    }
+    // Sharing it briefly is the only portable test; a daemon starting meanwhile waits
+    // for it to be released again on drop
+    Ok(InstanceLock::try_acquire(&get_lock_file_path()?, false)?.is_none())
+}
+
+/// Take the lock at `path` for this daemon, trying for up to `patience`
+fn acquire(path: &Path, patience: Duration) -> Result<Option<InstanceLock>> {
+    let deadline = Instant::now() + patience;
+    loop {
+        match InstanceLock::try_acquire(path, true)? {
+            None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+            lock => return Ok(lock),
+        }
+    }
+}
+
+#[cfg(unix)]
+struct InstanceLock(#[allow(dead_code)] std::fs::File);
+
+#[cfg(unix)]
+impl InstanceLock {
+    /// Take the lock at `path` if it is free: `exclusive` for a daemon, shared for a check,
+    /// so checks don't get in each other's way
+    fn try_acquire(path: &Path, exclusive: bool) -> Result<Option<Self>> {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        use std::os::unix::io::AsRawFd;
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .mode(0o600)
+            .open(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+        let mode = if exclusive {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_SH
+        };
+        if unsafe { libc::flock(file.as_raw_fd(), mode | libc::LOCK_NB) } == 0 {
+            return Ok(Some(Self(file)));
+        }
+        let error = std::io::Error::last_os_error();
+        if error.kind() == std::io::ErrorKind::WouldBlock {
+            Ok(None)
+        } else {
+            Err(PostError::Io(error))
+        }
+    }
+}
+
+#[cfg(windows)]
+struct InstanceLock(windows_sys::Win32::Foundation::HANDLE);
+
+#[cfg(windows)]
+impl InstanceLock {
+    /// Create the session-wide named mutex; the lock file isn't needed
+    fn try_acquire(_path: &Path, _exclusive: bool) -> Result<Option<Self>> {
+        use std::iter;
+        use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_ALREADY_EXISTS};
+        use windows_sys::Win32::System::Threading::CreateMutexW;
+
+        let name: Vec<u16> = "Local\\PostClipboardDaemon"
+            .encode_utf16()
+            .chain(iter::once(0))
+            .collect();
+        unsafe {
+            let handle = CreateMutexW(std::ptr::null(), 0, name.as_ptr());
+            if handle == 0 {
+                return Err(PostError::Io(std::io::Error::last_os_error()));
+            }
+            if GetLastError() == ERROR_ALREADY_EXISTS {
+                CloseHandle(handle);
+                return Ok(None);
+            }
+            Ok(Some(Self(handle)))
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.0);
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+struct InstanceLock;
+
+#[cfg(not(any(unix, windows)))]
+impl InstanceLock {
+    fn try_acquire(_path: &Path, _exclusive: bool) -> Result<Option<Self>> {
+        Ok(Some(Self))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_one_daemon_holds_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("post.lock");
+
+        let held = acquire(&path, Duration::ZERO)
+            .unwrap()
+            .expect("the lock is free");
+        assert!(acquire(&path, Duration::from_millis(50)).unwrap().is_none());
+        assert!(InstanceLock::try_acquire(&path, false).unwrap().is_none());
+
+        drop(held);
+        assert!(acquire(&path, Duration::ZERO).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_a_check_neither_blocks_other_checks_nor_a_starting_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("post.lock");
+
+        let check = InstanceLock::try_acquire(&path, false).unwrap().unwrap();
+        assert!(InstanceLock::try_acquire(&path, false).unwrap().is_some());
+
+        let released = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(check);
+        });
+        assert!(acquire(&path, LOCK_PATIENCE).unwrap().is_some());
+        released.join().unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+use chrono::{Local, TimeZone};
+use post_core::{text, EventSender, JournalConfig, PostError, Result, SyncEvent};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+/// Writes every clipboard entry this node syncs to the journal file or directory
+pub struct Journal {
+    config: JournalConfig,
+    path: PathBuf,
+    events: EventSender,
+}
+
+/// One synced entry as journaled
+struct Entry {
+    /// Where it was copied: this device, or the peer it came from
+    origin: String,
+    content: String,
+    timestamp: u64,
+}
+
+impl Journal {
+    pub fn new(config: JournalConfig, events: EventSender) -> Result<Self> {
+        let path = config
+            .path
+            .clone()
+            .ok_or_else(|| PostError::Config("journal.path is required".to_string()))?;
+        Ok(Self {
+            config,
+            path,
+            events,
+        })
+    }
+
+    /// Journal entries until the task is cancelled
+    pub async fn run(&self) -> Result<()> {
+        let mut events = self.events.subscribe();
+        info!(
+            "Journaling synced clipboard entries to {}",
+            self.path.display()
+        );
+
+        loop {
+            let entry = match events.recv().await {
+                Ok(SyncEvent::Sent {
+                    content, timestamp, ..
+                }) => Entry {
+                    origin: "copied here".to_string(),
+                    content,
+                    timestamp,
+                },
+                Ok(SyncEvent::Received {
+                    from_name,
+                    content,
+                    timestamp,
+                    ..
+                }) => Entry {
+                    origin: format!("from {}", from_name),
+                    content,
+                    timestamp,
+                },
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Journal skipped {} entries", missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+            if self
+                .config
+                .exclude_patterns
+                .first_match(&entry.content)
+                .is_some()
+            {
+                continue;
+            }
+
+            let content = text::truncate_bytes(&entry.content, self.config.max_entry_size);
+            let written = if self.config.per_entry_files {
+                write_entry_file(&self.path, &entry, content)
+                    .await
+                    .map(drop)
+            } else {
+                append_entry(
+                    &self.path,
+                    &format_entry(&entry, content),
+                    self.config.max_file_size,
+                )
+                .await
+            };
+            if let Err(e) = written {
+                warn!("Failed to journal clipboard entry: {}", e);
+            }
+        }
+    }
+}
+
+fn local_time(timestamp: u64, format: &str) -> String {
+    Local
+        .timestamp_opt(timestamp as i64, 0)
+        .single()
+        .map(|time| time.format(format).to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// The entry as appended to the journal file: a header line, then the content
+fn format_entry(entry: &Entry, content: &str) -> String {
+    format!(
+        "--- {} {} ---\n{}\n",
+        local_time(entry.timestamp, "%Y-%m-%d %H:%M:%S"),
+        entry.origin,
+        content
+    )
+}
+
+/// Create `dir` and any missing parents readable by the owner only, since entries hold
+/// whatever was copied; directories that already exist are left as they are
+async fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = tokio::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(dir).await?;
+    Ok(())
+}
+
+/// Append `record` to `path`, first moving a journal past `max_file_size` to `<path>.1`
+async fn append_entry(path: &Path, record: &str, max_file_size: u64) -> Result<()> {
+    if max_file_size > 0 {
+        let size = tokio::fs::metadata(path).await.map_or(0, |m| m.len());
+        if size > 0 && size + record.len() as u64 > max_file_size {
+            let mut rotated = path.as_os_str().to_owned();
+            rotated.push(".1");
+            tokio::fs::rename(path, rotated).await?;
+        }
+    }
+    if let Some(dir) = path.parent() {
+        create_private_dir(dir).await?;
+    }
+    let mut options = tokio::fs::OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).await?;
+    file.write_all(record.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Write `content` to a new file in `dir` named after the entry's time, e.g.
+/// `20240101-120000.txt`, or `20240101-120000-1.txt` if that is taken
+async fn write_entry_file(dir: &Path, entry: &Entry, content: &str) -> Result<PathBuf> {
+    create_private_dir(dir).await?;
+    let stamp = local_time(entry.timestamp, "%Y%m%d-%H%M%S");
+    for n in 0.. {
+        let name = match n {
+            0 => format!("{}.txt", stamp),
+            n => format!("{}-{}.txt", stamp, n),
+        };
+        let path = dir.join(name);
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        match options.open(&path).await {
+            Ok(mut file) => {
+                file.write_all(content.as_bytes()).await?;
+                file.flush().await?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!("ran out of journal file names")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(content: &str) -> Entry {
+        Entry {
+            origin: "from desktop".to_string(),
+            content: content.to_string(),
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_journal_file_rotates_past_max_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clipboard.log");
+        let record = format_entry(&entry("hello"), "hello");
+        assert!(record.ends_with("from desktop ---\nhello\n"));
+
+        append_entry(&path, &record, 0).await.unwrap();
+        append_entry(&path, &record, 0).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), record.repeat(2));
+
+        append_entry(&path, &record, record.len() as u64 * 2)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), record);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("clipboard.log.1")).unwrap(),
+            record.repeat(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_entries_in_the_same_second_get_their_own_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let first = write_entry_file(dir.path(), &entry("one"), "one")
+            .await
+            .unwrap();
+        let second = write_entry_file(dir.path(), &entry("two"), "two")
+            .await
+            .unwrap();
+
+        assert_ne!(first, second);
+        assert!(second.to_string_lossy().ends_with("-1.txt"));
+        assert_eq!(std::fs::read_to_string(second).unwrap(), "two");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_journal_is_readable_by_the_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let dir = tempfile::tempdir().unwrap();
+
+        let entries = dir.path().join("entries");
+        let file = write_entry_file(&entries, &entry("one"), "one")
+            .await
+            .unwrap();
+        assert_eq!(mode(&entries), 0o700);
+        assert_eq!(mode(&file), 0o600);
+
+        let path = dir.path().join("log").join("clipboard.log");
+        append_entry(&path, "one\n", 0).await.unwrap();
+        assert_eq!(mode(path.parent().unwrap()), 0o700);
+        assert_eq!(mode(&path), 0o600);
+    }
+}
@@ -0,0 +1,120 @@
+use post_core::{EventSender, KdeConnectConfig, PostError, Result, SyncEvent};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use zbus::Connection;
+
+const SERVICE: &str = "org.kde.kdeconnect";
+
+/// Sends content received from peers to phones through KDE Connect's clipboard plugin
+pub struct KdeConnectBridge {
+    config: KdeConnectConfig,
+    events: EventSender,
+}
+
+impl KdeConnectBridge {
+    pub fn new(config: KdeConnectConfig, events: EventSender) -> Self {
+        Self { config, events }
+    }
+
+    /// Forward received content until the task is cancelled
+    pub async fn run(&self) -> Result<()> {
+        let connection = Connection::session()
+            .await
+            .map_err(|e| dbus_error("connecting to the session bus", e))?;
+        let mut events = self.events.subscribe();
+        info!("Forwarding received clipboard content to KDE Connect");
+
+        loop {
+            let content = match events.recv().await {
+                Ok(SyncEvent::Received { content, .. }) => content,
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("KDE Connect bridge skipped {} events", missed);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+
+            let reachable = match reachable_devices(&connection).await {
+                Ok(devices) => devices,
+                Err(e) => {
+                    debug!("KDE Connect unavailable: {}", e);
+                    continue;
+                }
+            };
+            for device in target_devices(&self.config.devices, reachable) {
+                if let Err(e) = send_clipboard(&connection, &device, &content).await {
+                    warn!(
+                        "Failed to send clipboard to KDE Connect device {}: {}",
+                        device, e
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Paired devices KDE Connect can reach right now
+async fn reachable_devices(connection: &Connection) -> Result<Vec<String>> {
+    let reply = connection
+        .call_method(
+            Some(SERVICE),
+            "/modules/kdeconnect",
+            Some("org.kde.kdeconnect.daemon"),
+            "devices",
+            &(true, true),
+        )
+        .await
+        .map_err(|e| dbus_error("listing devices", e))?;
+    reply
+        .body()
+        .deserialize()
+        .map_err(|e| dbus_error("listing devices", e))
+}
+
+async fn send_clipboard(connection: &Connection, device: &str, content: &str) -> Result<()> {
+    let path = format!("/modules/kdeconnect/devices/{}/clipboard", device);
+    connection
+        .call_method(
+            Some(SERVICE),
+            path.as_str(),
+            Some("org.kde.kdeconnect.device.clipboard"),
+            "sendClipboard",
+            &(content,),
+        )
+        .await
+        .map_err(|e| dbus_error("sending clipboard", e))?;
+    debug!("Sent clipboard to KDE Connect device {}", device);
+    Ok(())
+}
+
+/// The `configured` devices that are reachable, or all reachable ones if none are configured
+fn target_devices(configured: &[String], reachable: Vec<String>) -> Vec<String> {
+    if configured.is_empty() {
+        return reachable;
+    }
+    reachable
+        .into_iter()
+        .filter(|device| configured.contains(device))
+        .collect()
+}
+
+fn dbus_error(action: &str, error: impl std::fmt::Display) -> PostError {
+    PostError::Other(format!("KDE Connect: failed {}: {}", action, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configured_devices_limit_targets() {
+        let reachable = vec!["phone".to_string(), "tablet".to_string()];
+
+        assert_eq!(target_devices(&[], reachable.clone()), reachable);
+        assert_eq!(
+            target_devices(&["tablet".to_string(), "old".to_string()], reachable),
+            vec!["tablet".to_string()]
+        );
+    }
+}
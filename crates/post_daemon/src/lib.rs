@@ -1,49 +1,163 @@
+use futures_util::future::BoxFuture;
+use post_core::directory::{self, PeerDirectory};
 use post_core::*;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{watch, Mutex};
+use tracing::level_filters::LevelFilter;
 use tracing::{debug, error, info, warn};
 
+pub mod api;
+pub mod audit;
+pub mod connectivity;
+pub mod crash;
+mod embedded;
+mod instance;
+mod journal;
+#[cfg(all(target_os = "linux", feature = "kdeconnect"))]
+mod kdeconnect;
+pub mod logging;
+#[cfg(feature = "mqtt")]
+mod mqtt;
 mod notifications;
+#[cfg(unix)]
+pub mod readiness;
+#[cfg(unix)]
+pub mod signals;
+pub mod storage;
+mod supervisor;
+use connectivity::{
+    ConnectivityCallback, ConnectivityManager, ConnectivityProbe, ConnectivityState, ProbeResult,
+    TailscaleProbe,
+};
+use embedded::EmbeddedTailscale;
 use notifications::NotificationManager;
+pub use supervisor::Supervisor;
 
 pub struct Daemon {
+    /// The config the daemon started with, which its tasks were set up from
     config: PostConfig,
-    clipboard: Arc<SystemClipboard>,
+    /// The config in effect: the startup one with what reloads applied, for state dumps
+    /// and the next reload
+    running_config: Arc<std::sync::RwLock<PostConfig>>,
+    clipboard: Arc<ClipboardService>,
     transport: Arc<dyn Transport>,
     sync_manager: Arc<Mutex<Option<Arc<SyncManager>>>>,
     notifications: NotificationManager,
+    shutdown: watch::Sender<bool>,
+    /// Sync activity for `post watch` and other API subscribers
+    events: EventSender,
+    /// Set while local clipboard changes are left unsynced, across reconnects
+    paused: Arc<AtomicBool>,
+    /// Tailnet peers and their names, shared so nothing else polls the local API for them
+    peer_directory: Arc<PeerDirectory>,
+    /// Clipboard failures, for readiness checks
+    health: api::HealthState,
+    /// Drops from the inbox of messages from peers, for stats
+    inbox: inbox::InboxCounters,
+    /// Where API requests and sync activity are recorded, when `audit.enabled` is set
+    audit: Option<Arc<audit::AuditLog>>,
+    /// Private tailscaled from `network.embedded`, stopped along with the daemon
+    _embedded: Option<EmbeddedTailscale>,
+    /// Told how startup went, once `run` has bound its listeners or failed to
+    startup_report: std::sync::Mutex<Option<StartupReport>>,
 }
 
+/// Called with the outcome of the daemon's startup
+pub type StartupReport = Box<dyn FnOnce(std::result::Result<(), &PostError>) + Send>;
+
 impl Daemon {
     pub async fn new(config: PostConfig) -> Result<Self> {
-        let clipboard = Arc::new(SystemClipboard::new()?);
-        let notifications = NotificationManager::new();
+        // Previews only ever reach debug output, so they need debug logging on as well
+        if config.logging.content_previews {
+            if LevelFilter::current() >= LevelFilter::DEBUG {
+                warn!("Logging clipboard content previews; disable logging.content_previews when done");
+                redact::set_previews_enabled(true);
+            } else {
+                warn!("logging.content_previews is ignored unless debug logging is enabled");
+            }
+        }
+
+        if config.sync.dry_run {
+            warn!("Dry run: clipboard changes are only logged, nothing is sent or applied");
+        }
+
+        let embedded = match &config.network.embedded {
+            Some(embedded) => Some(EmbeddedTailscale::start(embedded).await?),
+            None => None,
+        };
+
+        let events = event_channel();
+        let paused = Arc::new(AtomicBool::new(false));
+        let audit = open_audit_log(&config);
+        let notifications = NotificationManager::new().with_config(config.notifications.clone());
+        let health = api::HealthState::default();
+        // The backend picked by `clipboard.backend`, e.g. wl-clipboard on Wayland
+        let clipboard = create_clipboard_backend_with_config(&config.clipboard)?;
+        clipboard.set_health_callback(clipboard_health_callback(&events, &notifications, &health));
+        let clipboard = Arc::new(ClipboardService::new(clipboard));
+        let bind_address = config.network.bind_ip()?;
+        let ip_preference = config.network.ip_preference;
+        let breaker_threshold = config.network.circuit_breaker_threshold();
+        let breaker_max_backoff = config.network.circuit_breaker_max_backoff();
+        let send_concurrency = config.network.send_concurrency();
+        let connect_timeout = config.network.connect_timeout();
+        let send_timeout = config.network.send_timeout();
+        let max_connections = config.network.max_connections();
+        let socket_path = config.network.socket_path()?;
+        let peer_tags = config.network.peer_tags.clone();
+        let allowed_tailnets = config.sync.allowed_tailnets.clone();
 
         // Use the new detection method that tries multiple socket paths
         let (transport, is_connected_at_startup) = match TailscaleTransport::new_with_detection(
             config.network.port,
+            socket_path.as_deref(),
         )
         .await
         {
-            Ok(transport) => (Arc::new(transport), true),
+            Ok(transport) => (
+                Arc::new(
+                    transport
+                        .with_bind_address(bind_address)
+                        .with_ip_preference(ip_preference)
+                        .with_circuit_breaker(breaker_threshold, breaker_max_backoff)
+                        .with_send_concurrency(send_concurrency)
+                        .with_timeouts(connect_timeout, send_timeout)
+                        .with_max_connections(max_connections)
+                        .with_tag_policy(peer_tags.clone())
+                        .with_allowed_tailnets(allowed_tailnets.clone()),
+                ),
+                true,
+            ),
             Err(e) => {
                 // Fallback to old method for compatibility
                 warn!(
                     "Failed to detect Tailscale with new method: {}, falling back to default",
                     e
                 );
-                let transport = Arc::new(TailscaleTransport::new(config.network.port));
+                let transport = Arc::new(
+                    TailscaleTransport::new(config.network.port, socket_path.as_deref())
+                        .with_bind_address(bind_address)
+                        .with_ip_preference(ip_preference)
+                        .with_circuit_breaker(breaker_threshold, breaker_max_backoff)
+                        .with_send_concurrency(send_concurrency)
+                        .with_timeouts(connect_timeout, send_timeout)
+                        .with_max_connections(max_connections)
+                        .with_tag_policy(peer_tags)
+                        .with_allowed_tailnets(allowed_tailnets),
+                );
 
                 // Check connectivity but don't fail at startup
                 let connected = match transport.is_connected().await {
                     Ok(true) => true,
                     Ok(false) => {
-                        info!("Tailscale is not connected at startup - will retry every 2 seconds");
+                        info!("Tailscale is not connected at startup - will keep checking");
                         false
                     }
                     Err(e) => {
-                        info!("Unable to check Tailscale connectivity at startup: {} - will retry every 2 seconds", e);
+                        info!("Unable to check Tailscale connectivity at startup: {} - will keep checking", e);
                         false
                     }
                 };
@@ -54,15 +168,29 @@ impl Daemon {
         // Only create SyncManager if Tailscale is actually connected
         let sync_manager = if is_connected_at_startup {
             match transport.get_node_id().await {
-                Ok(node_id) => {
-                    info!("Daemon initialized with Tailscale node ID: {}", node_id);
+                Ok(tailscale_id) => {
+                    let (node_id, node_name) =
+                        resolve_node_identity(&config.node, transport.as_ref(), tailscale_id).await;
+                    info!("Daemon initialized with node ID: {}", node_id);
 
                     // Show connection notification
-                    if let Err(e) = notifications.show_tailscale_connected(&node_id) {
+                    if let Err(e) = notifications.show_tailscale_connected(&node_name) {
                         warn!("Failed to show connection notification: {}", e);
                     }
 
-                    Some(Arc::new(SyncManager::new(clipboard.clone(), node_id)?))
+                    let sync_manager = build_sync_manager(
+                        &config,
+                        clipboard.clone(),
+                        node_id,
+                        &events,
+                        &paused,
+                        audit.as_ref(),
+                    )?;
+                    sync_manager.update_node_name(node_name).await;
+                    sync_manager
+                        .update_tailnet(resolve_tailnet(transport.as_ref()).await)
+                        .await;
+                    Some(Arc::new(sync_manager))
                 }
                 Err(e) => {
                     warn!("Tailscale connected but couldn't get node ID: {}", e);
@@ -81,282 +209,214 @@ impl Daemon {
         };
 
         let sync_manager = Arc::new(Mutex::new(sync_manager));
+        let peer_directory = Arc::new(
+            PeerDirectory::new(Arc::clone(&transport) as Arc<dyn Transport>)
+                .with_events(events.clone()),
+        );
+        transport.use_peer_directory(Arc::downgrade(&peer_directory));
 
         Ok(Self {
+            running_config: Arc::new(std::sync::RwLock::new(config.clone())),
             config,
             clipboard,
             transport,
             sync_manager,
             notifications,
+            shutdown: watch::channel(false).0,
+            events,
+            paused,
+            peer_directory,
+            health,
+            inbox: Default::default(),
+            audit,
+            _embedded: embedded,
+            startup_report: Default::default(),
         })
     }
 
-    pub async fn run(&self) -> Result<()> {
-        info!("Starting Post daemon");
-
-        // Signal handling is now managed by the main daemon process
-        // No need for a separate signal handler here
-
-        let (tx, mut rx) = mpsc::unbounded_channel();
-        let transport_clone = Arc::clone(&self.transport);
-
-        tokio::spawn(async move {
-            if let Err(e) = transport_clone.start_listening(tx).await {
-                error!("Transport listener failed: {}", e);
-            }
-        });
-
-        let transport_send = Arc::clone(&self.transport);
-        let sync_manager_clone = Arc::clone(&self.sync_manager);
+    /// Tell `report` how startup went: success once `run` has bound the API and, while
+    /// Tailscale is up, the peer listeners, or the error that stopped it
+    pub fn with_startup_report(mut self, report: StartupReport) -> Self {
+        self.startup_report = std::sync::Mutex::new(Some(report));
+        self
+    }
 
-        // Start sync loop only if we have a sync manager
-        if let Some(sync_manager) = sync_manager_clone.lock().await.as_ref() {
-            let sync_manager_ref = Arc::clone(sync_manager);
-            let transport_discovery = Arc::clone(&transport_send);
-
-            // Send initial node discovery message
-            let discovery_message = sync_manager_ref.create_node_discovery_message().await?;
-            tokio::spawn(async move {
-                if let Err(e) = transport_discovery.send_message(discovery_message).await {
-                    error!("Failed to send initial node discovery: {}", e);
-                } else {
-                    info!("Sent initial node discovery message");
-                }
-            });
+    /// Ask a running daemon to stop; `run` returns once the API has finished its requests
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
 
-            tokio::spawn(async move {
-                if let Err(e) = sync_manager_ref
-                    .start_sync_loop(move |message| {
-                        let transport = Arc::clone(&transport_send);
-                        tokio::spawn(async move {
-                            if let Err(e) = transport.send_message(message).await {
-                                error!("Failed to send message: {}", e);
-                            }
-                        });
-                    })
-                    .await
-                {
-                    error!("Sync loop failed: {}", e);
-                }
-            });
+    /// Apply a re-read `config`: the log filter changes now, and any other section that
+    /// changed is reported as needing a restart
+    pub fn reload(&self, config: &PostConfig, verbose: bool) -> Result<()> {
+        logging::set_filter(&logging::filter_spec(&config.logging, verbose))?;
+        let mut running = self
+            .running_config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        running.logging = config.logging.clone();
+        let pending: Vec<String> = changed_sections(&running, config)
+            .into_iter()
+            .filter(|section| section != "logging")
+            .collect();
+        if pending.is_empty() {
+            info!("Reloaded the config");
         } else {
-            info!("Sync loop not started - waiting for Tailscale connection");
+            warn!(
+                "Reloaded the logging settings; restart the daemon to apply changes to [{}]",
+                pending.join("], [")
+            );
         }
+        Ok(())
+    }
 
-        // Tailscale connectivity monitoring task - checks every 2 seconds
-        let sync_manager_health = Arc::clone(&self.sync_manager);
-        let transport_health = Arc::clone(&self.transport);
-        let clipboard_for_sync = Arc::clone(&self.clipboard);
-        let notifications_clone = self.notifications.clone();
-        let transport_for_sync = Arc::clone(&self.transport);
-
-        tokio::spawn(async move {
-            use std::sync::atomic::{AtomicBool, Ordering};
-            use std::sync::Arc as StdArc;
-
-            let mut interval = tokio::time::interval(std::time::Duration::from_secs(2));
-            let was_connected = StdArc::new(AtomicBool::new(false));
-
-            // Determine initial state based on sync_manager existence
-            let initial_connected = {
-                let sync_manager_guard = sync_manager_health.lock().await;
-                let connected = sync_manager_guard.is_some()
-                    && matches!(transport_health.is_connected().await, Ok(true));
-
-                if connected {
-                    info!("Initial state: Tailscale is connected");
-                } else {
-                    info!("Initial state: Tailscale is not connected, monitoring for changes");
-                }
-
-                connected
-            };
-            was_connected.store(initial_connected, Ordering::Relaxed);
-
-            loop {
-                interval.tick().await;
-
-                // Re-detect Tailscale every 2 seconds to handle port changes
-                let connection_check = TailscaleTransport::new_with_detection(19827).await;
-
-                let is_connected = match &connection_check {
-                    Ok(transport) => transport.is_connected().await.unwrap_or(false),
-                    Err(_) => false,
-                };
-
-                if is_connected {
-                    let previously_connected = was_connected.load(Ordering::Relaxed);
-
-                    if !previously_connected {
-                        // Just connected - create SyncManager and show notification
-                        if let Ok(ref transport) = connection_check {
-                            match transport.get_node_id().await {
-                                Ok(node_id) => {
-                                    info!("Tailscale connected: {}", node_id);
-
-                                    // Create SyncManager if it doesn't exist
-                                    let mut sync_manager_guard = sync_manager_health.lock().await;
-                                    if sync_manager_guard.is_none() {
-                                        match SyncManager::new(
-                                            clipboard_for_sync.clone(),
-                                            node_id.clone(),
-                                        ) {
-                                            Ok(new_sync_manager) => {
-                                                let sync_manager_arc = Arc::new(new_sync_manager);
-                                                *sync_manager_guard =
-                                                    Some(Arc::clone(&sync_manager_arc));
-                                                drop(sync_manager_guard);
-
-                                                info!(
-                                                    "Created SyncManager with node ID: {}",
-                                                    node_id
-                                                );
-
-                                                // Send initial node discovery message for new SyncManager
-                                                let transport_for_discovery =
-                                                    Arc::clone(&transport_for_sync);
-                                                let sync_manager_for_discovery =
-                                                    Arc::clone(&sync_manager_arc);
-                                                tokio::spawn(async move {
-                                                    match sync_manager_for_discovery
-                                                        .create_node_discovery_message()
-                                                        .await
-                                                    {
-                                                        Ok(discovery_message) => {
-                                                            if let Err(e) = transport_for_discovery
-                                                                .send_message(discovery_message)
-                                                                .await
-                                                            {
-                                                                error!("Failed to send initial node discovery: {}", e);
-                                                            } else {
-                                                                info!("Sent initial node discovery message");
-                                                            }
-                                                        }
-                                                        Err(e) => {
-                                                            error!("Failed to create node discovery message: {}", e);
-                                                        }
-                                                    }
-                                                });
-
-                                                // Start sync loop for the new SyncManager
-                                                let transport_for_messages =
-                                                    Arc::clone(&transport_for_sync);
-                                                tokio::spawn(async move {
-                                                    if let Err(e) = sync_manager_arc
-                                                        .start_sync_loop(move |message| {
-                                                            let transport = Arc::clone(&transport_for_messages);
-                                                            tokio::spawn(async move {
-                                                                if let Err(e) = transport.send_message(message).await {
-                                                                    error!("Failed to send message: {}", e);
-                                                                }
-                                                            });
-                                                        })
-                                                        .await
-                                                    {
-                                                        error!("Sync loop failed: {}", e);
-                                                    }
-                                                });
-                                            }
-                                            Err(e) => {
-                                                error!("Failed to create SyncManager: {}", e);
-                                            }
-                                        }
-                                    }
+    /// Write the daemon's status and sync counters to the log
+    pub async fn log_state(&self) {
+        let status =
+            api::daemon_status(&self.sync_manager, self.transport.as_ref(), &self.paused).await;
+        info!("Daemon status: {}", to_json(&status));
+        let sync_manager = self.sync_manager.lock().await.clone();
+        match sync_manager {
+            Some(sync_manager) => {
+                let stats = api::sync_stats(&sync_manager, &self.inbox).await;
+                info!("Sync stats: {}", to_json(&stats));
+            }
+            None => info!("No sync stats while waiting for Tailscale"),
+        }
+        let circuits = self.transport.peer_circuits();
+        if !circuits.is_empty() {
+            info!("Peer circuits: {}", to_json(&circuits));
+        }
+    }
 
-                                    if let Err(e) =
-                                        notifications_clone.show_tailscale_connected(&node_id)
-                                    {
-                                        warn!("Failed to show connection notification: {}", e);
-                                    }
+    /// Create the SyncManager when Tailscale connects, switch its node ID when that
+    /// changes, and drop it when Tailscale disconnects
+    fn connectivity_handler(&self, supervisor: &Supervisor) -> ConnectivityCallback {
+        let sync_manager_slot = Arc::clone(&self.sync_manager);
+        let clipboard = Arc::clone(&self.clipboard);
+        let transport = Arc::clone(&self.transport);
+        let notifications = self.notifications.clone();
+        let config = self.config.clone();
+        let supervisor = supervisor.clone();
+        let events = self.events.clone();
+        let paused = Arc::clone(&self.paused);
+        let audit = self.audit.clone();
+
+        Arc::new(move |result| {
+            let sync_manager_slot = Arc::clone(&sync_manager_slot);
+            let clipboard = Arc::clone(&clipboard);
+            let transport = Arc::clone(&transport);
+            let notifications = notifications.clone();
+            let config = config.clone();
+            let supervisor = supervisor.clone();
+            let events = events.clone();
+            let paused = Arc::clone(&paused);
+            let audit = audit.clone();
+
+            Box::pin(async move {
+                match result {
+                    ProbeResult::Connected { node_id, node_name } => {
+                        let _ = events.send(SyncEvent::Connected {
+                            node_id: node_id.clone(),
+                        });
+                        let mut slot = sync_manager_slot.lock().await;
+                        if let Some(sync_manager) = slot.as_ref() {
+                            // Re-login or rename: keep syncing under the new identity
+                            if sync_manager.get_node_id().await != node_id {
+                                if let Err(e) = sync_manager.update_node_id(node_id.clone()).await {
+                                    error!("Failed to switch to node ID {}: {}", node_id, e);
                                 }
-                                Err(e) => {
-                                    warn!("Connected to Tailscale but couldn't get node ID: {}", e);
+                            }
+                            sync_manager.update_node_name(node_name.clone()).await;
+                            sync_manager
+                                .update_tailnet(resolve_tailnet(transport.as_ref()).await)
+                                .await;
+                        } else {
+                            match build_sync_manager(
+                                &config,
+                                clipboard,
+                                node_id.clone(),
+                                &events,
+                                &paused,
+                                audit.as_ref(),
+                            ) {
+                                Ok(sync_manager) => {
+                                    sync_manager.update_node_name(node_name.clone()).await;
+                                    sync_manager
+                                        .update_tailnet(resolve_tailnet(transport.as_ref()).await)
+                                        .await;
+                                    let sync_manager = Arc::new(sync_manager);
+                                    *slot = Some(Arc::clone(&sync_manager));
+                                    drop(slot);
+
+                                    info!("Created SyncManager with node ID: {}", node_id);
+                                    start_syncing(&supervisor, sync_manager, transport);
                                 }
+                                Err(e) => error!("Failed to create SyncManager: {}", e),
                             }
                         }
-                        was_connected.store(true, Ordering::Relaxed);
-                    } else {
-                        debug!("Tailscale connectivity check: OK");
-                    }
-                } else {
-                    let previously_connected = was_connected.load(Ordering::Relaxed);
-
-                    if previously_connected {
-                        // Just disconnected - remove SyncManager and show notification
-                        info!("Tailscale disconnected - will retry every 2 seconds");
 
-                        // Clear the SyncManager
-                        let mut sync_manager_guard = sync_manager_health.lock().await;
-                        *sync_manager_guard = None;
-                        drop(sync_manager_guard);
+                        if let Err(e) = notifications.show_tailscale_connected(&node_name) {
+                            warn!("Failed to show connection notification: {}", e);
+                        }
+                    }
+                    // Keep syncing with what we have; the manager logs why
+                    ProbeResult::Degraded(_) => {}
+                    ProbeResult::Disconnected => {
+                        *sync_manager_slot.lock().await = None;
+                        let _ = events.send(SyncEvent::Disconnected);
 
-                        if let Err(e) = notifications_clone.show_tailscale_disconnected() {
+                        if let Err(e) = notifications.show_tailscale_disconnected() {
                             warn!("Failed to show disconnection notification: {}", e);
                         }
-
-                        was_connected.store(false, Ordering::Relaxed);
-                    } else {
-                        debug!("Tailscale still not connected - retrying...");
                     }
                 }
-            }
-        });
+            })
+        })
+    }
 
-        // Separate health check task for other components (runs less frequently)
-        let clipboard_health = Arc::clone(&self.clipboard);
-        let cleanup_interval = self.config.network.discovery_interval * 10;
-        let heartbeat_interval = self.config.network.heartbeat_interval;
-        let transport_heartbeat = Arc::clone(&self.transport);
-        let sync_manager_cleanup = Arc::clone(&self.sync_manager);
+    pub async fn run(&self) -> Result<()> {
+        info!("Starting Post daemon");
 
-        tokio::spawn(async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
-            let mut tick_count = 0u64;
+        // Signal handling is now managed by the main daemon process
+        // No need for a separate signal handler here
 
-            loop {
-                interval.tick().await;
-                tick_count += 1;
+        let supervisor = Supervisor::new().with_notifications(self.notifications.clone());
+        let started = self.start_tasks(&supervisor).await;
+        let report = self
+            .startup_report
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        if let Some(report) = report {
+            report(started.as_ref().map(|_| ()));
+        }
+        let (mut rx, api_task) = started?;
+        let sync_manager_clone = Arc::clone(&self.sync_manager);
 
-                // Clipboard health check (every 2 minutes = every 4 ticks)
-                if tick_count % 4 == 0 {
-                    if let Err(e) = clipboard_health.get_contents().await {
-                        error!("Clipboard health check failed: {}", e);
-                    }
-                }
+        let mut shutdown = self.shutdown.subscribe();
+        loop {
+            let message = tokio::select! {
+                message = rx.recv() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = shutdown.wait_for(|stop| *stop) => break,
+            };
 
-                // Heartbeat task (based on configured interval, but max every 30 seconds)
-                if tick_count % ((heartbeat_interval / 30).max(1)) == 0 {
-                    if let Ok(nodes) = transport_heartbeat.get_tailnet_nodes().await {
-                        debug!("Heartbeat tick - found {} nodes", nodes.len());
+            let sync_manager_guard = sync_manager_clone.lock().await;
+            if let Some(ref sync_manager) = *sync_manager_guard {
+                let result = sync_manager.handle_message(message.clone()).await;
+                if let (Ok(()), MessageData::TaildropOffer(offer)) = (&result, &message.data) {
+                    if sync_manager.is_dry_run() {
+                        info!("Dry run: would receive {} via Taildrop", offer.file_name);
                     } else {
-                        debug!("Heartbeat tick - failed to get nodes");
-                    }
-                }
-
-                // Cleanup task (based on configured interval, but max every 10 minutes)
-                if tick_count % ((cleanup_interval / 30).max(20)) == 0 {
-                    let sync_manager_guard = sync_manager_cleanup.lock().await;
-                    if let Some(ref sync_manager) = *sync_manager_guard {
-                        if let Err(e) = sync_manager.cleanup_stale_nodes(cleanup_interval * 2).await
-                        {
-                            error!("Failed to cleanup stale nodes: {}", e);
-                        }
+                        self.receive_taildrop(sync_manager, offer.clone()).await;
                     }
                 }
-
-                // Prevent tick_count overflow
-                if tick_count > 200_000_000 {
-                    tick_count = 0;
-                }
-            }
-        });
-
-        while let Some(message) = rx.recv().await {
-            let sync_manager_guard = sync_manager_clone.lock().await;
-            if let Some(ref sync_manager) = *sync_manager_guard {
-                if let Err(e) = sync_manager.handle_message(message.clone()).await {
-                    // If we get a "No verifying key found" error, send node discovery
-                    if e.to_string().contains("No verifying key found for node") {
+                if let Err(e) = result {
+                    // A peer we have no keys for learns ours and announces its own
+                    if matches!(e, PostError::UnknownPeer(_)) {
                         info!("Unknown node detected, sending node discovery");
                         let transport_for_discovery = Arc::clone(&self.transport);
                         let sync_manager_for_discovery = Arc::clone(sync_manager);
@@ -392,15 +452,647 @@ impl Daemon {
             }
         }
 
+        if let Some(sync_manager) = sync_manager_clone.lock().await.clone() {
+            sync_manager.flush_state().await;
+        }
+
+        if let Some(api_task) = api_task {
+            self.shutdown();
+            if tokio::time::timeout(api::SHUTDOWN_GRACE, api_task)
+                .await
+                .is_err()
+            {
+                warn!("HTTP API did not stop within {:?}", api::SHUTDOWN_GRACE);
+            }
+        }
+
+        info!("Post daemon stopped");
+        Ok(())
+    }
+
+    /// Start the API and every background task, returning the inbox of messages from
+    /// peers once the API and, while Tailscale is up, the peer listeners are bound
+    async fn start_tasks(
+        &self,
+        supervisor: &Supervisor,
+    ) -> Result<(inbox::InboxReceiver, Option<tokio::task::JoinHandle<()>>)> {
+        let api_state = if self.config.api.enabled || self.config.grpc.enabled {
+            Some(api::ApiState {
+                sync_manager: Arc::clone(&self.sync_manager),
+                transport: Arc::clone(&self.transport),
+                token: api::load_or_create_api_token().await?.into(),
+                filters: self.config.filters.clone(),
+                events: self.events.clone(),
+                shutdown: self.shutdown.subscribe(),
+                pairing: Arc::new(api::PairingStore::load(api::paired_devices_path()?)?),
+                storage: self.config.storage.clone(),
+                paused: Arc::clone(&self.paused),
+                audit: self.audit.clone(),
+                peer_directory: Arc::clone(&self.peer_directory),
+                health: self.health.clone(),
+                inbox: self.inbox.clone(),
+                config: Arc::clone(&self.running_config),
+            })
+        } else {
+            None
+        };
+
+        let api_task =
+            if let Some(api_state) = api_state.clone().filter(|_| self.config.api.enabled) {
+                use futures_util::FutureExt;
+
+                let api_shutdown = self.shutdown.subscribe();
+                let bind: Box<dyn Fn() -> Result<BoxFuture<'static, Result<()>>> + Send + Sync> =
+                    match self.config.api.unix_socket()? {
+                        Some(socket) => Box::new(move || {
+                            let server = api::bind_unix_api_server(
+                                api_state.clone(),
+                                socket.clone(),
+                                api_shutdown.clone(),
+                            )?;
+                            Ok(server.boxed())
+                        }),
+                        None => {
+                            let api_addr =
+                                SocketAddr::new(self.config.api.bind_ip()?, self.config.api.port);
+                            let api_tls = self.config.api.tls;
+                            Box::new(move || {
+                                let server = api::bind_api_server(
+                                    api_state.clone(),
+                                    api_addr,
+                                    api_tls,
+                                    api_shutdown.clone(),
+                                )?;
+                                Ok(server.boxed())
+                            })
+                        }
+                    };
+                // Bound now, so an address in use fails startup; restarts bind again
+                let bound = std::sync::Mutex::new(Some(bind()?));
+                Some(supervisor.spawn("API server", move || {
+                    let server = bound
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .take()
+                        .map_or_else(&bind, Ok);
+                    async move { server?.await }
+                }))
+            } else {
+                info!("HTTP API disabled");
+                None
+            };
+
+        self.start_grpc(supervisor, api_state)?;
+        self.start_mqtt(supervisor);
+        self.start_kdeconnect_bridge(supervisor);
+        self.start_journal(supervisor);
+        self.start_event_notifications(supervisor);
+        self.start_peer_directory(supervisor);
+
+        if let Some(max_size) = self.config.storage.max_size {
+            let sync_manager = Arc::clone(&self.sync_manager);
+            supervisor.spawn("storage cleanup", move || {
+                storage::enforce_cap(max_size, Arc::clone(&sync_manager))
+            });
+        }
+
+        let (tx, rx) =
+            inbox::channel_counted(self.config.network.inbox_capacity(), self.inbox.clone());
+        let transport_clone = Arc::clone(&self.transport);
+
+        supervisor.spawn("transport listener", move || {
+            let transport = Arc::clone(&transport_clone);
+            let tx = tx.clone();
+            async move { transport.start_listening(tx).await }
+        });
+
+        let transport_send = Arc::clone(&self.transport);
+        let sync_manager_clone = Arc::clone(&self.sync_manager);
+
+        // Start sync loop only if we have a sync manager
+        if let Some(sync_manager) = sync_manager_clone.lock().await.as_ref() {
+            start_syncing(supervisor, Arc::clone(sync_manager), transport_send);
+        } else {
+            info!("Sync loop not started - waiting for Tailscale connection");
+        }
+
+        // Set up syncing when Tailscale comes up and tear it down when it goes away
+        let initial_state = if self.sync_manager.lock().await.is_some()
+            && matches!(self.transport.is_connected().await, Ok(true))
+        {
+            ConnectivityState::Connected
+        } else {
+            ConnectivityState::Disconnected
+        };
+        let probe: Arc<dyn ConnectivityProbe> = Arc::new(
+            TailscaleProbe::new(
+                self.config.network.port,
+                self.config.network.socket_path()?,
+                self.config.node.clone(),
+            )
+            .with_peer_directory(
+                Arc::clone(&self.peer_directory),
+                directory::DEFAULT_REFRESH_INTERVAL,
+            ),
+        );
+        let on_change = self.connectivity_handler(supervisor);
+        let probe_interval = self.config.network.probe_interval();
+        let max_probe_interval = self.config.network.max_probe_interval();
+
+        supervisor.spawn("tailscale monitor", move || {
+            ConnectivityManager::new(Arc::clone(&probe))
+                .with_probe_interval(probe_interval, max_probe_interval)
+                .with_initial_state(initial_state)
+                .on_change(Arc::clone(&on_change))
+                .run()
+        });
+
+        // Separate health check task for other components (runs less frequently)
+        let clipboard_health = Arc::clone(&self.clipboard);
+        let cleanup_interval = self.config.network.discovery_interval * 10;
+        let heartbeat_interval = self.config.network.heartbeat_interval;
+        let transport_heartbeat = Arc::clone(&self.transport);
+        let sync_manager_cleanup = Arc::clone(&self.sync_manager);
+
+        supervisor.spawn("health check", move || {
+            let clipboard_health = Arc::clone(&clipboard_health);
+            let transport_heartbeat = Arc::clone(&transport_heartbeat);
+            let sync_manager_cleanup = Arc::clone(&sync_manager_cleanup);
+
+            async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+                let mut tick_count = 0u64;
+
+                loop {
+                    interval.tick().await;
+                    tick_count += 1;
+
+                    // Clipboard health check (every 2 minutes = every 4 ticks)
+                    if tick_count.is_multiple_of(4) {
+                        if let Err(e) = clipboard_health.get_contents().await {
+                            error!("Clipboard health check failed: {}", e);
+                        }
+                    }
+
+                    // Heartbeat task (based on configured interval, but max every 30 seconds)
+                    // Peers compare its timestamp with their clock to spot skew
+                    if tick_count.is_multiple_of((heartbeat_interval / 30).max(1)) {
+                        let sync_manager = sync_manager_cleanup.lock().await.clone();
+                        if let Some(sync_manager) = sync_manager {
+                            match sync_manager.create_heartbeat_message().await {
+                                Ok(heartbeat) => {
+                                    if let Err(e) =
+                                        transport_heartbeat.send_message(heartbeat).await
+                                    {
+                                        debug!("Failed to send heartbeat: {}", e);
+                                    }
+                                }
+                                Err(e) => error!("Failed to create heartbeat: {}", e),
+                            }
+                        }
+                    }
+
+                    // Cleanup task (based on configured interval, but max every 10 minutes)
+                    if tick_count.is_multiple_of((cleanup_interval / 30).max(20)) {
+                        let sync_manager_guard = sync_manager_cleanup.lock().await;
+                        if let Some(ref sync_manager) = *sync_manager_guard {
+                            if let Err(e) =
+                                sync_manager.cleanup_stale_nodes(cleanup_interval * 2).await
+                            {
+                                error!("Failed to cleanup stale nodes: {}", e);
+                            }
+                        }
+                    }
+
+                    // Prevent tick_count overflow
+                    if tick_count > 200_000_000 {
+                        tick_count = 0;
+                    }
+                }
+            }
+        });
+
+        // Re-send clipboard updates that peers haven't acknowledged yet
+        let sync_manager_retry = Arc::clone(&self.sync_manager);
+
+        supervisor.spawn("ack retry", move || {
+            let sync_manager_retry = Arc::clone(&sync_manager_retry);
+
+            async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(2));
+
+                loop {
+                    interval.tick().await;
+
+                    let sync_manager = sync_manager_retry.lock().await.clone();
+                    if let Some(sync_manager) = sync_manager {
+                        let retried = sync_manager.retry_unacknowledged().await;
+                        if retried > 0 {
+                            debug!("Retried {} unacknowledged updates", retried);
+                        }
+                    }
+                }
+            }
+        });
+
+        // Syncing can't start before Tailscale is up, so neither can its listeners
+        if matches!(initial_state, ConnectivityState::Connected) {
+            self.transport.wait_listening().await?;
+        }
+        Ok((rx, api_task))
+    }
+
+    #[cfg(feature = "grpc")]
+    fn start_grpc(&self, supervisor: &Supervisor, state: Option<api::ApiState>) -> Result<()> {
+        let Some(state) = state.filter(|_| self.config.grpc.enabled) else {
+            return Ok(());
+        };
+        let addr = SocketAddr::new(self.config.grpc.bind_ip()?, self.config.grpc.port);
+        let shutdown = self.shutdown.subscribe();
+        supervisor.spawn("gRPC service", move || {
+            api::start_grpc_server(state.clone(), addr, shutdown.clone())
+        });
         Ok(())
     }
+
+    #[cfg(not(feature = "grpc"))]
+    fn start_grpc(&self, _supervisor: &Supervisor, _state: Option<api::ApiState>) -> Result<()> {
+        if self.config.grpc.enabled {
+            warn!("grpc.enabled is ignored; this build lacks the grpc feature");
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "mqtt")]
+    fn start_mqtt(&self, supervisor: &Supervisor) {
+        if !self.config.mqtt.enabled {
+            return;
+        }
+        // The client has no TLS, so the password would cross the network in the clear
+        if self.config.mqtt.username.is_some() && !self.config.mqtt.is_local() {
+            warn!(
+                "Not publishing to MQTT: credentials are only sent to a broker on this \
+                 machine, not {}",
+                self.config.mqtt.host
+            );
+            return;
+        }
+        let publisher = mqtt::MqttPublisher::new(
+            self.config.mqtt.clone(),
+            &self.config.node.name,
+            Arc::clone(&self.sync_manager),
+            Arc::clone(&self.transport),
+            Arc::clone(&self.paused),
+            self.events.clone(),
+        );
+        supervisor.spawn("MQTT publisher", move || publisher.clone().run());
+    }
+
+    #[cfg(not(feature = "mqtt"))]
+    fn start_mqtt(&self, _supervisor: &Supervisor) {
+        if self.config.mqtt.enabled {
+            warn!("mqtt.enabled is ignored; this build lacks the mqtt feature");
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "kdeconnect"))]
+    fn start_kdeconnect_bridge(&self, supervisor: &Supervisor) {
+        if !self.config.kdeconnect.enabled {
+            return;
+        }
+        let bridge = Arc::new(kdeconnect::KdeConnectBridge::new(
+            self.config.kdeconnect.clone(),
+            self.events.clone(),
+        ));
+        supervisor.spawn("KDE Connect bridge", move || {
+            let bridge = Arc::clone(&bridge);
+            async move { bridge.run().await }
+        });
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "kdeconnect")))]
+    fn start_kdeconnect_bridge(&self, _supervisor: &Supervisor) {
+        if self.config.kdeconnect.enabled {
+            warn!(
+                "kdeconnect.enabled is ignored; the KDE Connect bridge is Linux only and needs \
+                 the kdeconnect feature"
+            );
+        }
+    }
+
+    fn start_journal(&self, supervisor: &Supervisor) {
+        if !self.config.journal.enabled {
+            return;
+        }
+        let journal = match journal::Journal::new(self.config.journal.clone(), self.events.clone())
+        {
+            Ok(journal) => Arc::new(journal),
+            Err(e) => {
+                error!("Clipboard journal disabled: {}", e);
+                return;
+            }
+        };
+        supervisor.spawn("clipboard journal", move || {
+            let journal = Arc::clone(&journal);
+            async move { journal.run().await }
+        });
+    }
+
+    fn start_event_notifications(&self, supervisor: &Supervisor) {
+        let config = &self.config.notifications;
+        if !config.enabled
+            || !(config.received
+                || config.clock_skew
+                || config.peer_offline
+                || config.tailnet_peers)
+        {
+            return;
+        }
+        let notifications = self.notifications.clone();
+        let events = self.events.clone();
+        supervisor.spawn("event notifications", move || {
+            let notifications = notifications.clone();
+            let events = events.clone();
+            async move { notifications.show_sync_events(&events).await }
+        });
+    }
+
+    /// Keep the peer directory current, and announce this node to peers as they come
+    /// online rather than at the next discovery round
+    fn start_peer_directory(&self, supervisor: &Supervisor) {
+        let peer_directory = Arc::clone(&self.peer_directory);
+        supervisor.spawn("peer directory", move || {
+            let peer_directory = Arc::clone(&peer_directory);
+            async move {
+                peer_directory
+                    .run(directory::DEFAULT_REFRESH_INTERVAL)
+                    .await
+            }
+        });
+
+        let events = self.events.clone();
+        let sync_manager = Arc::clone(&self.sync_manager);
+        let transport = Arc::clone(&self.transport);
+        supervisor.spawn("peer announcements", move || {
+            let mut events = events.subscribe();
+            let sync_manager = Arc::clone(&sync_manager);
+            let transport = Arc::clone(&transport);
+            async move {
+                loop {
+                    let (address, name) = match events.recv().await {
+                        Ok(SyncEvent::TailnetPeerOnline { address, name }) => (address, name),
+                        Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                            continue
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => return Ok(()),
+                    };
+                    let Some(sync_manager) = sync_manager.lock().await.clone() else {
+                        continue;
+                    };
+                    debug!("{} came online, announcing this node", name);
+                    let sent = match sync_manager.create_node_discovery_message().await {
+                        Ok(message) => transport.send_message_to(&address, message).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = sent {
+                        debug!("Failed to announce this node to {}: {}", name, e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Fetch a Taildrop payload in the background and tell the user where it landed
+    async fn receive_taildrop(&self, sync_manager: &SyncManager, offer: TaildropData) {
+        let sender = sync_manager
+            .get_nodes()
+            .await
+            .get(&offer.source_node)
+            .map(|node| node.name.clone())
+            .unwrap_or_else(|| offer.source_node.clone());
+        let dir = self.config.sync.taildrop_receive_dir();
+        let notifications = self.notifications.clone();
+
+        tokio::spawn(async move {
+            match taildrop::receive_file(&offer.file_name, &dir).await {
+                Ok(path) => {
+                    info!("Received {} from {} via Taildrop", path.display(), sender);
+                    if let Err(e) = notifications.show_taildrop_received(&sender, &path) {
+                        warn!("Failed to show Taildrop notification: {}", e);
+                    }
+                }
+                Err(e) => warn!("Failed to receive Taildrop payload from {}: {}", sender, e),
+            }
+        });
+    }
+}
+
+/// The audit log from `config`, if it is enabled and opens
+fn open_audit_log(config: &PostConfig) -> Option<Arc<audit::AuditLog>> {
+    if !config.audit.enabled {
+        return None;
+    }
+    match audit::audit_log_path(&config.audit).and_then(|path| audit::AuditLog::open(&path)) {
+        Ok(log) => Some(Arc::new(log)),
+        Err(e) => {
+            error!("Audit log disabled: {}", e);
+            None
+        }
+    }
+}
+
+/// SyncManager for `node_id` with the sync, filter and endpoint settings from `config`,
+/// publishing its activity to `events` and recording it to `audit`
+fn build_sync_manager(
+    config: &PostConfig,
+    clipboard: Arc<dyn ClipboardBackend>,
+    node_id: String,
+    events: &EventSender,
+    paused: &Arc<AtomicBool>,
+    audit: Option<&Arc<audit::AuditLog>>,
+) -> Result<SyncManager> {
+    let mut sync_manager = SyncManager::new(clipboard, node_id)?
+        .with_events(events.clone())
+        .with_pause_switch(Arc::clone(paused))
+        .with_sync_config(config.sync.clone());
+    if let Some(audit) = audit {
+        let audit = Arc::clone(audit);
+        sync_manager =
+            sync_manager.with_event_hook(Arc::new(move |event| audit.record_event(event)));
+    }
+    // A locked or damaged database costs the state of earlier runs, not syncing
+    let sync_manager = match get_state_db_path().and_then(|path| StateStore::open(&path)) {
+        Ok(store) => sync_manager.with_state_store(Arc::new(store)),
+        Err(e) => {
+            warn!(
+                "Failed to open the state database, so nothing is kept between runs: {}",
+                e
+            );
+            sync_manager
+        }
+    };
+    let sync_manager = sync_manager
+        .with_app_rules(config.filters.app_rules.clone())
+        .with_concealed_sync(config.filters.sync_concealed)
+        .with_transforms(config.filters.transforms.clone())
+        .with_max_length(config.filters.max_length)
+        .with_advertised_endpoint(
+            config.network.advertise_ip()?,
+            config.network.advertised_port(),
+        );
+    Ok(sync_manager)
+}
+
+/// Top-level config sections that differ between `old` and `new`
+fn changed_sections(old: &PostConfig, new: &PostConfig) -> Vec<String> {
+    let table = |config: &PostConfig| match config::reveal_secrets(|| serde_json::to_value(config))
+    {
+        Ok(serde_json::Value::Object(table)) => table,
+        _ => serde_json::Map::new(),
+    };
+    let (old, new) = (table(old), table(new));
+    let mut changed: Vec<String> = new
+        .iter()
+        .filter(|(section, value)| old.get(*section) != Some(*value))
+        .map(|(section, _)| section.clone())
+        .chain(
+            old.keys()
+                .filter(|section| !new.contains_key(*section))
+                .cloned(),
+        )
+        .collect();
+    changed.sort();
+    changed
+}
+
+fn to_json(value: &impl serde::Serialize) -> String {
+    serde_json::to_string(value).unwrap_or_else(|e| format!("<unserializable: {}>", e))
+}
+
+/// Tell the user, and event subscribers, when the local clipboard stops or resumes working
+fn clipboard_health_callback(
+    events: &EventSender,
+    notifications: &NotificationManager,
+    state: &api::HealthState,
+) -> HealthCallback {
+    let events = events.clone();
+    let notifications = notifications.clone();
+    let state = state.clone();
+    Arc::new(move |health| {
+        state.record_clipboard(&health);
+        let (event, shown) = match health {
+            ClipboardHealth::Unavailable(error) => {
+                let shown = notifications.show_clipboard_unavailable(&error);
+                (SyncEvent::ClipboardUnavailable { error }, shown)
+            }
+            ClipboardHealth::Recovered => (
+                SyncEvent::ClipboardRecovered,
+                notifications.show_clipboard_recovered(),
+            ),
+        };
+        if let Err(e) = shown {
+            debug!("Failed to show clipboard notification: {}", e);
+        }
+        let _ = events.send(event);
+    })
+}
+
+/// Announce this node to peers and start the sync loop
+fn start_syncing(
+    supervisor: &Supervisor,
+    sync_manager: Arc<SyncManager>,
+    transport: Arc<dyn Transport>,
+) {
+    let discovery_manager = Arc::clone(&sync_manager);
+    let discovery_transport = Arc::clone(&transport);
+    tokio::spawn(async move {
+        let sent = match discovery_manager.create_node_discovery_message().await {
+            Ok(message) => discovery_transport.send_message(message).await,
+            Err(e) => Err(e),
+        };
+        match sent {
+            Ok(()) => info!("Sent initial node discovery message"),
+            Err(e) => error!("Failed to send initial node discovery: {}", e),
+        }
+    });
+
+    spawn_sync_loop(supervisor, sync_manager, transport);
+}
+
+/// Start broadcasting local clipboard changes, retrying under the supervisor if it fails
+fn spawn_sync_loop(
+    supervisor: &Supervisor,
+    sync_manager: Arc<SyncManager>,
+    transport: Arc<dyn Transport>,
+) {
+    supervisor.spawn("sync loop", move || {
+        let sync_manager = Arc::clone(&sync_manager);
+        let transport = Arc::clone(&transport);
+
+        async move {
+            sync_manager
+                .start_sync_loop(move |message| {
+                    let transport = Arc::clone(&transport);
+                    tokio::spawn(async move {
+                        if let Err(e) = transport.send_message(message).await {
+                            error!("Failed to send message: {}", e);
+                        }
+                    });
+                })
+                .await
+        }
+    });
+}
+
+/// Node ID and display name to sync as, preferring configured values over Tailscale's
+async fn resolve_node_identity(
+    node_config: &NodeConfig,
+    transport: &dyn Transport,
+    tailscale_id: String,
+) -> (String, String) {
+    let node_id = node_config.resolve_id(tailscale_id);
+
+    let node_name = match node_config.display_name() {
+        Some(name) => name.to_string(),
+        None => match transport.get_node_name().await {
+            Ok(name) => name,
+            Err(e) => {
+                debug!("Couldn't get node name, using ID: {}", e);
+                node_id.clone()
+            }
+        },
+    };
+
+    (node_id, node_name)
+}
+
+/// Tailnet to tag clipboard updates with, or `None` when Tailscale doesn't say
+async fn resolve_tailnet(transport: &dyn Transport) -> Option<String> {
+    match transport.get_tailnet().await {
+        Ok(tailnet) => Some(tailnet),
+        Err(e) => {
+            debug!("Couldn't get tailnet, sending untagged updates: {}", e);
+            None
+        }
+    }
 }
 
 /// Get the PID file path
 pub fn get_pid_file_path() -> Result<PathBuf> {
+    Ok(private_data_dir()?.join("post.pid"))
+}
+
+/// Database pins, known peers and their stats, sequence numbers and, as configured, the clipboard stack
+/// and unacknowledged updates are kept in between runs
+pub fn get_state_db_path() -> Result<PathBuf> {
+    Ok(private_data_dir()?.join(storage::STATE_DB))
+}
+
+/// Post's data directory, created readable by the owner only
+pub(crate) fn private_data_dir() -> Result<PathBuf> {
     let mut path = dirs::data_dir()
         .ok_or_else(|| PostError::Other("Could not find data directory".to_string()))?;
-    path.push("post");
+    path.push(post_core::config::dir_name());
 
     // Create directory with secure permissions (700 - owner only)
     std::fs::create_dir_all(&path).map_err(PostError::Io)?;
@@ -411,12 +1103,13 @@ pub fn get_pid_file_path() -> Result<PathBuf> {
         std::fs::set_permissions(&path, permissions).map_err(PostError::Io)?;
     }
 
-    path.push("post.pid");
     Ok(path)
 }
 
-/// Write the current process PID to file
+/// Take the single-instance lock and write the current process PID to file
 pub fn write_pid_file() -> Result<()> {
+    instance::lock()?;
+
     let pid_path = get_pid_file_path()?;
     let pid = std::process::id();
 
@@ -464,28 +1157,27 @@ pub fn is_daemon_running() -> Result<Option<u32>> {
         use nix::sys::signal::kill;
         use nix::unistd::Pid;
 
-        match kill(Pid::from_raw(pid as i32), None) {
-            Ok(_) => Ok(Some(pid)),
-            Err(_) => {
-                // Process not running, clean up stale PID file
-                let _ = std::fs::remove_file(&pid_path);
-                Ok(None)
-            }
+        if kill(Pid::from_raw(pid as i32), None).is_err() {
+            // Process not running, clean up stale PID file
+            let _ = std::fs::remove_file(&pid_path);
+            return Ok(None);
         }
     }
 
-    #[cfg(not(unix))]
-    {
-        // On non-Unix systems, just assume the process is running if PID file exists
-        Ok(Some(pid))
+    // The PID may have been reused by an unrelated process; only a live daemon holds the lock
+    if !instance::is_locked()? {
+        let _ = std::fs::remove_file(&pid_path);
+        return Ok(None);
     }
+
+    Ok(Some(pid))
 }
 
 /// Get log file path
 pub fn get_log_file_path() -> Result<PathBuf> {
     let mut path = dirs::data_dir()
         .ok_or_else(|| PostError::Other("Could not find data directory".to_string()))?;
-    path.push("post");
+    path.push(post_core::config::dir_name());
 
     // Create directory with secure permissions (700 - owner only)
     std::fs::create_dir_all(&path).map_err(PostError::Io)?;
@@ -502,13 +1194,23 @@ pub fn get_log_file_path() -> Result<PathBuf> {
 
 #[cfg(all(unix, not(target_os = "macos")))]
 pub async fn daemonize() -> Result<()> {
+    if detach().await?.is_some() {
+        std::process::exit(0);
+    }
+    Ok(())
+}
+
+/// Fork the daemon into the background. The parent gets its PID back and carries on,
+/// e.g. to wait for it to become ready; the daemon gets `None`
+#[cfg(all(unix, not(target_os = "macos")))]
+pub async fn detach() -> Result<Option<u32>> {
     use nix::unistd::{fork, setsid, ForkResult};
     use std::fs::File;
     use std::os::unix::io::AsRawFd;
 
     match unsafe { fork() } {
-        Ok(ForkResult::Parent { .. }) => {
-            std::process::exit(0);
+        Ok(ForkResult::Parent { child }) => {
+            return Ok(Some(child.as_raw() as u32));
         }
         Ok(ForkResult::Child) => {
             setsid()
@@ -556,7 +1258,19 @@ pub async fn daemonize() -> Result<()> {
         }
     }
 
-    Ok(())
+    Ok(None)
+}
+
+/// Whether the daemon [`detach`] forked has exited, reaping it if so
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn detached_daemon_exited(pid: u32) -> bool {
+    use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+    use nix::unistd::Pid;
+
+    !matches!(
+        waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG)),
+        Ok(WaitStatus::StillAlive)
+    )
 }
 
 #[cfg(target_os = "macos")]
@@ -614,6 +1328,16 @@ pub async fn daemonize() -> Result<()> {
     Ok(())
 }
 
+#[cfg(not(unix))]
+pub async fn detach() -> Result<Option<u32>> {
+    daemonize().await.map(|()| None)
+}
+
+#[cfg(not(unix))]
+pub fn detached_daemon_exited(_pid: u32) -> bool {
+    false
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -792,4 +1516,16 @@ mod tests {
             std::mem::size_of_val(&config)
         );
     }
+
+    #[test]
+    fn test_changed_sections_lists_what_a_reload_touches() {
+        let old = PostConfig::default();
+        let mut new = old.clone();
+        assert!(changed_sections(&old, &new).is_empty());
+
+        new.logging.level = Some("debug".to_string());
+        new.network.port += 1;
+        new.sync.dry_run = true;
+        assert_eq!(changed_sections(&old, &new), ["logging", "network"]);
+    }
 }
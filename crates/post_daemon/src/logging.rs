@@ -0,0 +1,74 @@
+use post_core::{LoggingConfig, PostError, Result};
+use std::sync::OnceLock;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload, Registry};
+
+static FILTER: OnceLock<reload::Handle<Targets, Registry>> = OnceLock::new();
+
+/// Filter directives for `config`, e.g. `info,post_core::sync=debug`; `verbose` makes the default `debug`
+pub fn filter_spec(config: &LoggingConfig, verbose: bool) -> String {
+    let level = match config.level.as_deref() {
+        _ if verbose => "debug",
+        Some(level) if !level.trim().is_empty() => level.trim(),
+        _ => "info",
+    };
+
+    let mut spec = level.to_string();
+    for (target, level) in &config.filters {
+        spec.push_str(&format!(",{}={}", target.trim(), level.trim()));
+    }
+    spec
+}
+
+/// Parse directives like `warn,post_core=debug` into a filter
+pub fn parse_filter(spec: &str) -> Result<Targets> {
+    spec.parse()
+        .map_err(|e| PostError::Config(format!("Invalid log filter {:?}: {}", spec, e)))
+}
+
+/// Install the global subscriber with a filter [`set_filter`] can replace later
+pub fn init(config: &LoggingConfig, verbose: bool) -> Result<()> {
+    let targets = parse_filter(&filter_spec(config, verbose))?;
+    let (filter, handle) = reload::Layer::new(targets);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .try_init()
+        .map_err(|e| PostError::Other(format!("Failed to set up logging: {}", e)))?;
+    let _ = FILTER.set(handle);
+    Ok(())
+}
+
+/// Swap the active filter without restarting
+pub fn set_filter(spec: &str) -> Result<()> {
+    let targets = parse_filter(spec)?;
+    let handle = FILTER
+        .get()
+        .ok_or_else(|| PostError::Other("Logging has not been set up".to_string()))?;
+    handle
+        .reload(targets)
+        .map_err(|e| PostError::Other(format!("Failed to change the log filter: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_filter_spec_combines_level_and_targets() {
+        let config = LoggingConfig {
+            level: Some("warn".to_string()),
+            filters: BTreeMap::from([("post_core::sync".to_string(), "trace".to_string())]),
+            ..LoggingConfig::default()
+        };
+
+        assert_eq!(filter_spec(&config, false), "warn,post_core::sync=trace");
+        assert_eq!(filter_spec(&config, true), "debug,post_core::sync=trace");
+        assert_eq!(filter_spec(&LoggingConfig::default(), false), "info");
+        assert!(parse_filter(&filter_spec(&config, false)).is_ok());
+        assert!(parse_filter("post_core=loud").is_err());
+    }
+}
@@ -1,13 +1,7 @@
 use clap::Parser;
-use futures_util::stream::StreamExt;
-use post_core::{PostConfig, Result};
+use post_core::Result;
 use post_daemon::{daemonize, Daemon};
-use signal_hook::consts::SIGTERM;
-use signal_hook_tokio::Signals;
-use std::sync::Arc;
-use tokio::sync::Notify;
 use tracing::{error, info};
-use tracing_subscriber;
 
 #[derive(Parser)]
 #[command(name = "postd")]
@@ -16,32 +10,32 @@ struct Args {
     #[arg(short, long)]
     config: Option<String>,
 
+    /// Run as a separate profile, with its own config, data, ports and keys
+    #[arg(long)]
+    profile: Option<String>,
+
     #[arg(short, long)]
     foreground: bool,
 
     #[arg(short, long)]
     verbose: bool,
+
+    /// Log what would be synced without sending or applying anything
+    #[arg(long)]
+    dry_run: bool,
 }
 
 pub async fn daemon_main() -> Result<()> {
     let args = Args::parse();
-
-    if args.verbose {
-        tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::DEBUG)
-            .init();
-    } else {
-        tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::INFO)
-            .init();
+    if let Some(profile) = &args.profile {
+        post_core::config::set_profile(profile)?;
     }
 
-    let config = if let Some(config_path) = args.config {
-        let contents = tokio::fs::read_to_string(&config_path).await?;
-        toml::from_str(&contents)?
-    } else {
-        PostConfig::load().await?
-    };
+    let mut config = post_daemon::signals::load_config(args.config.as_deref()).await?;
+    post_daemon::logging::init(&config.logging, args.verbose)?;
+    post_daemon::crash::install_panic_hook(&config.logging);
+    post_daemon::storage::migrate()?;
+    config.sync.dry_run = args.dry_run;
 
     if !args.foreground {
         daemonize().await?;
@@ -54,48 +48,12 @@ pub async fn daemon_main() -> Result<()> {
         );
     }
 
-    let shutdown = Arc::new(Notify::new());
-    let shutdown_clone = Arc::clone(&shutdown);
-
-    tokio::spawn(async move {
-        let mut signals = match Signals::new(&[SIGTERM]) {
-            Ok(s) => s,
-            Err(e) => {
-                error!("Failed to create signal handler: {}", e);
-                return;
-            }
-        };
-
-        while let Some(signal) = signals.next().await {
-            match signal {
-                SIGTERM => {
-                    info!("Received SIGTERM, shutting down gracefully");
-                    shutdown_clone.notify_one();
-                    break;
-                }
-                _ => {}
-            }
-        }
-    });
-
+    // Registered before startup so a stop requested meanwhile isn't lost
+    let signals = post_daemon::signals::register();
     let daemon = Daemon::new(config).await?;
-
-    tokio::select! {
-        result = daemon.run() => {
-            if let Err(e) = result {
-                error!("Daemon error: {}", e);
-            }
-        }
-        _ = shutdown.notified() => {
-            info!("Shutting down daemon");
-        }
+    if let Err(e) = post_daemon::signals::run(daemon, signals, args.config, args.verbose).await {
+        error!("Daemon error: {}", e);
     }
-
-    // Cleanup PID file on shutdown
-    if let Err(e) = post_daemon::remove_pid_file() {
-        error!("Failed to remove PID file: {}", e);
-    }
-
     Ok(())
 }
 
@@ -0,0 +1,198 @@
+use crate::api::daemon_status;
+use post_core::{EventSender, MqttConfig, Result, SyncEvent, SyncManager, Transport};
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{debug, info, warn};
+
+/// Publishes daemon status and sync events to an MQTT broker
+///
+/// `<topic>/availability` is a retained `online`/`offline`, `<topic>/status` a retained
+/// JSON status updated every `status_interval`, and `<topic>/events` one JSON object per
+/// sync event. Clipboard content is left out; events only give its size.
+#[derive(Clone)]
+pub struct MqttPublisher {
+    config: MqttConfig,
+    client_id: String,
+    sync_manager: Arc<Mutex<Option<Arc<SyncManager>>>>,
+    transport: Arc<dyn Transport>,
+    paused: Arc<AtomicBool>,
+    events: EventSender,
+}
+
+impl MqttPublisher {
+    pub fn new(
+        config: MqttConfig,
+        node_name: &str,
+        sync_manager: Arc<Mutex<Option<Arc<SyncManager>>>>,
+        transport: Arc<dyn Transport>,
+        paused: Arc<AtomicBool>,
+        events: EventSender,
+    ) -> Self {
+        let client_id = config.client_id.clone().unwrap_or_else(|| {
+            let name: String = node_name
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                .collect();
+            format!("post-{}{}", name, post_core::config::profile_suffix("-"))
+        });
+        Self {
+            config,
+            client_id,
+            sync_manager,
+            transport,
+            paused,
+            events,
+        }
+    }
+
+    /// `<topic>/<name>`, under the profile if this is one, so profiles don't overwrite each
+    /// other's retained status
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.topic_prefix(), name)
+    }
+
+    fn topic_prefix(&self) -> String {
+        format!(
+            "{}{}",
+            self.config.topic.trim_end_matches('/'),
+            post_core::config::profile_suffix("/")
+        )
+    }
+
+    /// Publish until the task is cancelled, reconnecting to the broker as needed
+    pub async fn run(self) -> Result<()> {
+        let availability = self.topic("availability");
+        let mut options = MqttOptions::new(&self.client_id, &self.config.host, self.config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(
+            &availability,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let Some(username) = &self.config.username {
+            options.set_credentials(
+                username,
+                self.config
+                    .password
+                    .as_ref()
+                    .map(|password| password.expose().to_string())
+                    .unwrap_or_default(),
+            );
+        }
+
+        let (client, mut connection) = AsyncClient::new(options, 64);
+        let mut events = self.events.subscribe();
+        let mut status_timer =
+            tokio::time::interval(Duration::from_secs(self.config.status_interval.max(1)));
+        info!(
+            "Publishing to MQTT broker {}:{} under {}",
+            self.config.host,
+            self.config.port,
+            self.topic_prefix()
+        );
+
+        loop {
+            tokio::select! {
+                notification = connection.poll() => match notification {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        debug!("Connected to MQTT broker");
+                        publish(&client, &availability, true, "online".to_string());
+                        self.publish_status(&client).await;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        // Polling again reconnects
+                        warn!("MQTT connection error: {}", e);
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                },
+                _ = status_timer.tick() => self.publish_status(&client).await,
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        let payload = event_payload(&event).to_string();
+                        publish(&client, &self.topic("events"), false, payload);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("MQTT publisher skipped {} events", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
+
+    async fn publish_status(&self, client: &AsyncClient) {
+        let status = daemon_status(&self.sync_manager, self.transport.as_ref(), &self.paused).await;
+        match serde_json::to_string(&status) {
+            Ok(payload) => publish(client, &self.topic("status"), true, payload),
+            Err(e) => warn!("Failed to encode status for MQTT: {}", e),
+        }
+    }
+}
+
+/// Queue a message to send as the connection is polled, dropping it if the queue is full
+/// because the broker has been unreachable for a while
+fn publish(client: &AsyncClient, topic: &str, retain: bool, payload: String) {
+    if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, retain, payload) {
+        debug!("Not publishing to {}: {}", topic, e);
+    }
+}
+
+/// `event` as published, with clipboard content replaced by its size in bytes
+fn event_payload(event: &SyncEvent) -> serde_json::Value {
+    match event {
+        SyncEvent::Received {
+            from,
+            from_name,
+            content,
+            timestamp,
+        } => json!({
+            "event": "received",
+            "from": from,
+            "from_name": from_name,
+            "bytes": content.len(),
+            "timestamp": timestamp,
+        }),
+        SyncEvent::Sent {
+            content,
+            peers,
+            timestamp,
+        } => json!({
+            "event": "sent",
+            "peers": peers,
+            "bytes": content.len(),
+            "timestamp": timestamp,
+        }),
+        other => serde_json::to_value(other).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_payload_leaves_out_content() {
+        let payload = event_payload(&SyncEvent::Received {
+            from: "node-b".to_string(),
+            from_name: "desktop".to_string(),
+            content: "hunter2".to_string(),
+            timestamp: 1,
+        });
+
+        assert_eq!(payload["event"], "received");
+        assert_eq!(payload["bytes"], 7);
+        assert!(!payload.to_string().contains("hunter2"));
+        assert_eq!(
+            event_payload(&SyncEvent::Connected {
+                node_id: "node-a".to_string()
+            })["node_id"],
+            "node-a"
+        );
+    }
+}
@@ -1,39 +1,298 @@
 use notify_rust::Notification;
-use post_core::Result;
-use std::time::Duration;
+use post_core::{describe_clock_skew, text, EventSender, NotificationConfig, Result, SyncEvent};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
 use tracing::{debug, warn};
 
+/// Characters of received content shown when `preview` is on
+const PREVIEW_CHARS: usize = 80;
+
+/// Connection notifications wait for the state to settle: one is shown only if no other
+/// change followed it, and only if it differs from the last state shown
+#[derive(Debug, Default)]
+struct ConnectionNotices {
+    /// Last state a notification was shown for; `true` when connected
+    shown: Option<bool>,
+    /// Bumped on every change, so a superseded pending notification can tell
+    generation: u64,
+}
+
+impl ConnectionNotices {
+    /// Record a change, returning its generation
+    fn changed(&mut self) -> u64 {
+        self.generation += 1;
+        self.generation
+    }
+
+    /// Whether the change at `generation` is still the latest and should be shown
+    fn settled(&mut self, generation: u64, connected: bool) -> bool {
+        if generation != self.generation || self.shown == Some(connected) {
+            return false;
+        }
+        self.shown = Some(connected);
+        true
+    }
+}
+
+/// Drops notifications identical to one shown within the cooldown window
+#[derive(Debug, Default)]
+struct Cooldown {
+    shown: HashMap<String, Instant>,
+}
+
+impl Cooldown {
+    fn allow_at(&mut self, key: String, window: Duration, now: Instant) -> bool {
+        self.shown.retain(|_, at| now.duration_since(*at) < window);
+        if self.shown.contains_key(&key) {
+            return false;
+        }
+        self.shown.insert(key, now);
+        true
+    }
+}
+
 #[derive(Clone)]
 pub struct NotificationManager {
     app_name: String,
+    config: NotificationConfig,
+    connection: Arc<Mutex<ConnectionNotices>>,
+    cooldown: Arc<Mutex<Cooldown>>,
 }
 
 impl NotificationManager {
     pub fn new() -> Self {
         Self {
             app_name: "Post Clipboard Sync".to_string(),
+            config: NotificationConfig::default(),
+            connection: Arc::new(Mutex::new(ConnectionNotices::default())),
+            cooldown: Arc::new(Mutex::new(Cooldown::default())),
         }
     }
 
+    /// Show only the notifications `config` enables
+    pub fn with_config(mut self, config: NotificationConfig) -> Self {
+        self.config = config;
+        self
+    }
+
     /// Show a notification that Tailscale connection was lost
     pub fn show_tailscale_disconnected(&self) -> Result<()> {
-        self.show_notification(
+        self.notify_connection(
+            false,
             "Tailscale Disconnected",
-            "Post clipboard sync is offline. Will retry every 2 seconds.",
+            "Post clipboard sync is offline. Will keep checking for Tailscale.".to_string(),
         )
     }
 
     /// Show a notification that Tailscale connection was established
-    pub fn show_tailscale_connected(&self, node_id: &str) -> Result<()> {
-        self.show_notification(
+    pub fn show_tailscale_connected(&self, node_name: &str) -> Result<()> {
+        self.notify_connection(
+            true,
             "Tailscale Connected",
-            &format!("Post clipboard sync is online ({})", node_id),
+            format!("Post clipboard sync is online ({})", node_name),
+        )
+    }
+
+    /// Show a notification that a daemon task failed and is being restarted
+    pub fn show_task_failed(&self, task: &str, error: &str) -> Result<()> {
+        self.notify(
+            self.config.task_failures,
+            "Post Daemon Problem",
+            &format!("The {} stopped ({}). Restarting...", task, error),
+        )
+    }
+
+    /// Show a notification that a large clipboard payload arrived as a file
+    pub fn show_taildrop_received(&self, node_name: &str, path: &Path) -> Result<()> {
+        self.notify(
+            self.config.taildrop,
+            "Clipboard Received as File",
+            &format!(
+                "{} sent clipboard content too large to sync. Saved to {}",
+                node_name,
+                path.display()
+            ),
+        )
+    }
+
+    /// Show a notification that a peer sent clipboard content, with its start if `preview` is on
+    pub fn show_clipboard_received(&self, node_name: &str, content: &str) -> Result<()> {
+        let body = if self.config.preview {
+            format!("{}: {}", node_name, text::preview(content, PREVIEW_CHARS))
+        } else {
+            format!("New clipboard content from {}", node_name)
+        };
+        self.notify(self.config.received, "Clipboard Received", &body)
+    }
+
+    /// Show a notification that a peer's clock is too far off this node's
+    pub fn show_clock_skew(&self, node_name: &str, skew_secs: i64) -> Result<()> {
+        self.notify(
+            self.config.clock_skew,
+            "Peer Clock Out of Sync",
+            &format!(
+                "The clock on {} is {}. Check that both devices set their time automatically.",
+                node_name,
+                describe_clock_skew(skew_secs)
+            ),
+        )
+    }
+
+    /// Show a notification that a peer stopped answering and was dropped
+    pub fn show_peer_offline(&self, node_name: &str, last_seen: u64) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.notify(
+            self.config.peer_offline,
+            "Peer Offline",
+            &format!(
+                "{} hasn't been heard from in {} minutes and was dropped until it's back.",
+                node_name,
+                now.saturating_sub(last_seen) / 60
+            ),
+        )
+    }
+
+    /// Show a notification that a tailnet device came online or went offline
+    pub fn show_tailnet_peer(&self, name: &str, online: bool) -> Result<()> {
+        let (title, state) = if online {
+            ("Device Online", "came online")
+        } else {
+            ("Device Offline", "went offline")
+        };
+        self.notify(
+            self.config.tailnet_peers,
+            title,
+            &format!("{} {} on your tailnet.", name, state),
+        )
+    }
+
+    /// Notify about content received from peers, peers coming and going and peer clocks
+    /// drifting until the task is cancelled
+    pub async fn show_sync_events(&self, events: &EventSender) -> Result<()> {
+        let mut events = events.subscribe();
+        loop {
+            match events.recv().await {
+                Ok(SyncEvent::Received {
+                    from_name, content, ..
+                }) => self.show_clipboard_received(&from_name, &content)?,
+                Ok(SyncEvent::ClockSkew {
+                    node_name,
+                    skew_secs,
+                    ..
+                }) => self.show_clock_skew(&node_name, skew_secs)?,
+                Ok(SyncEvent::PeerOffline {
+                    name, last_seen, ..
+                }) => self.show_peer_offline(&name, last_seen)?,
+                Ok(SyncEvent::TailnetPeerOnline { name, .. }) => {
+                    self.show_tailnet_peer(&name, true)?
+                }
+                Ok(SyncEvent::TailnetPeerOffline { name, .. }) => {
+                    self.show_tailnet_peer(&name, false)?
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
+
+    /// Show a notification that the daemon hit a panic, which may have stopped it
+    pub fn show_daemon_crashed(&self, message: &str) -> Result<()> {
+        self.show_notification(
+            "Post Daemon Crashed",
+            &format!("{}. Details are in the Post log file.", message),
+        )
+    }
+
+    /// Show a notification that the local clipboard can't be read, so copies aren't synced
+    pub fn show_clipboard_unavailable(&self, error: &str) -> Result<()> {
+        self.notify(
+            self.config.clipboard_health,
+            "Clipboard Unavailable",
+            &format!(
+                "Post can't read the clipboard ({}). Copies won't sync until it's back.",
+                error
+            ),
+        )
+    }
+
+    /// Show a notification that the local clipboard can be read again
+    pub fn show_clipboard_recovered(&self) -> Result<()> {
+        self.notify(
+            self.config.clipboard_health,
+            "Clipboard Available",
+            "Post is syncing copies again.",
         )
     }
 
     /// Show a notification that the daemon started without Tailscale
     pub fn show_daemon_started_offline(&self) -> Result<()> {
-        self.show_notification("Post Daemon Started", "Waiting for Tailscale connection...")
+        self.notify_connection(
+            false,
+            "Post Daemon Started",
+            "Waiting for Tailscale connection...".to_string(),
+        )
+    }
+
+    /// Show a connection notification once the state has held for `settle_secs`, so a
+    /// flapping link doesn't notify on every change
+    fn notify_connection(
+        &self,
+        connected: bool,
+        summary: &'static str,
+        body: String,
+    ) -> Result<()> {
+        if !self.config.enabled || !self.config.connection {
+            return Ok(());
+        }
+        let generation = lock(&self.connection).changed();
+        let settle = Duration::from_secs(self.config.settle_secs);
+        if settle.is_zero() {
+            return self.show_settled_connection(generation, connected, summary, &body);
+        }
+
+        let manager = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(settle).await;
+            if let Err(e) = manager.show_settled_connection(generation, connected, summary, &body) {
+                warn!("Failed to show connection notification: {}", e);
+            }
+        });
+        Ok(())
+    }
+
+    fn show_settled_connection(
+        &self,
+        generation: u64,
+        connected: bool,
+        summary: &str,
+        body: &str,
+    ) -> Result<()> {
+        if !lock(&self.connection).settled(generation, connected) {
+            debug!("Connection changed again, not showing: {}", summary);
+            return Ok(());
+        }
+        self.notify(self.config.connection, summary, body)
+    }
+
+    /// Show a notification if both notifications and its kind are enabled
+    fn notify(&self, kind_enabled: bool, summary: &str, body: &str) -> Result<()> {
+        if !self.config.enabled || !kind_enabled {
+            debug!("Notification disabled by config: {}", summary);
+            return Ok(());
+        }
+        let window = Duration::from_secs(self.config.cooldown_secs);
+        if !lock(&self.cooldown).allow_at(format!("{}\n{}", summary, body), window, Instant::now())
+        {
+            debug!("Notification shown recently, skipping: {}", summary);
+            return Ok(());
+        }
+        self.show_notification(summary, body)
     }
 
     fn show_notification(&self, summary: &str, body: &str) -> Result<()> {
@@ -41,7 +300,7 @@ impl NotificationManager {
             .summary(summary)
             .body(body)
             .appname(&self.app_name)
-            .timeout(Duration::from_secs(5))
+            .timeout(Duration::from_secs(self.config.timeout_secs))
             .show();
 
         match result {
@@ -63,3 +322,43 @@ impl Default for NotificationManager {
         Self::new()
     }
 }
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flapping_connection_notifies_only_settled_changes() {
+        let mut notices = ConnectionNotices::default();
+
+        let connected = notices.changed();
+        assert!(notices.settled(connected, true));
+
+        // Dropped and back before the first change settled: nothing to show
+        let dropped = notices.changed();
+        let back = notices.changed();
+        assert!(!notices.settled(dropped, false));
+        assert!(!notices.settled(back, true));
+
+        let dropped = notices.changed();
+        assert!(notices.settled(dropped, false));
+    }
+
+    #[test]
+    fn test_identical_notifications_wait_out_the_cooldown() {
+        let mut cooldown = Cooldown::default();
+        let window = Duration::from_secs(60);
+        let start = Instant::now();
+
+        assert!(cooldown.allow_at("a".to_string(), window, start));
+        assert!(!cooldown.allow_at("a".to_string(), window, start + window / 2));
+        assert!(cooldown.allow_at("b".to_string(), window, start + window / 2));
+        assert!(cooldown.allow_at("a".to_string(), window, start + window));
+    }
+}
@@ -0,0 +1,164 @@
+//! Startup handshake between `post daemon` and the daemon process it spawns
+//!
+//! The parent listens on a Unix socket whose path it passes to the child with
+//! `--ready-socket`; the child connects once it has started, or failed to, and says
+//! which. The parent only reports success after hearing `ready`, and otherwise shows
+//! the child's startup error.
+
+use post_core::{PostError, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::UnixListener;
+
+/// How long the parent waits for the daemon to finish starting
+pub const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+const READY: &str = "ready";
+const ERROR_PREFIX: &str = "error: ";
+
+/// The parent's end of the handshake; the socket file is removed when it is dropped
+pub struct ReadinessListener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ReadinessListener {
+    /// Listen in the private data directory, where only this user can connect
+    pub fn bind() -> Result<Self> {
+        let path = crate::private_data_dir()?.join(format!("ready-{}.sock", std::process::id()));
+        Self::bind_at(path)
+    }
+
+    fn bind_at(path: PathBuf) -> Result<Self> {
+        // Left behind by an earlier run that was killed
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).map_err(|e| {
+            PostError::Other(format!(
+                "Failed to listen for the daemon at {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Ok(Self { listener, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Wait for `child` to report its startup, failing with its error if it reports
+    /// one, exits first, or doesn't answer within `timeout`
+    pub async fn wait(&self, child: &mut Child, timeout: Duration) -> Result<()> {
+        let pid = child.id();
+        let report = async {
+            let (mut stream, _) = self.listener.accept().await?;
+            let mut report = String::new();
+            stream.read_to_string(&mut report).await?;
+            Ok::<_, std::io::Error>(report)
+        };
+        let exited = async {
+            loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => return status,
+                    Ok(None) => tokio::time::sleep(Duration::from_millis(100)).await,
+                    // Can't tell; leave it to the timeout
+                    Err(_) => std::future::pending::<()>().await,
+                }
+            }
+        };
+
+        let report = tokio::select! {
+            report = report => report.map_err(|e| {
+                PostError::Other(format!("Failed to hear from the daemon: {}", e))
+            })?,
+            status = exited => {
+                return Err(PostError::Other(format!(
+                    "Daemon exited during startup ({})",
+                    status
+                )))
+            }
+            _ = tokio::time::sleep(timeout) => {
+                return Err(PostError::Other(format!(
+                    "Daemon (PID {}) didn't finish starting within {}s",
+                    pid,
+                    timeout.as_secs()
+                )))
+            }
+        };
+        parse_report(&report)
+    }
+}
+
+impl Drop for ReadinessListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn parse_report(report: &str) -> Result<()> {
+    let report = report.trim();
+    if report == READY {
+        return Ok(());
+    }
+    let error = report.strip_prefix(ERROR_PREFIX).unwrap_or(report);
+    Err(PostError::Other(if error.is_empty() {
+        "Daemon stopped before reporting its startup".to_string()
+    } else {
+        error.to_string()
+    }))
+}
+
+/// Tell the `post daemon` listening on `socket` how startup went
+pub fn report(socket: &Path, started: std::result::Result<(), &PostError>) {
+    if let Err(e) = report_to(socket, started) {
+        tracing::warn!("Failed to report startup to the launching process: {}", e);
+    }
+}
+
+fn report_to(path: &Path, started: std::result::Result<(), &PostError>) -> std::io::Result<()> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path)?;
+    match started {
+        Ok(()) => stream.write_all(READY.as_bytes()),
+        Err(e) => write!(stream, "{}{}", ERROR_PREFIX, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[tokio::test]
+    async fn test_parent_hears_startup_errors_and_early_exits() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = ReadinessListener::bind_at(dir.path().join("ready.sock")).unwrap();
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+
+        let path = listener.path().to_path_buf();
+        std::thread::spawn(move || {
+            let error = PostError::Config("invalid network.port".to_string());
+            report_to(&path, Err(&error)).unwrap();
+        });
+        let error = listener.wait(&mut child, READY_TIMEOUT).await.unwrap_err();
+        assert!(error.to_string().contains("invalid network.port"));
+
+        let path = listener.path().to_path_buf();
+        std::thread::spawn(move || report_to(&path, Ok(())).unwrap());
+        listener.wait(&mut child, READY_TIMEOUT).await.unwrap();
+        child.kill().unwrap();
+
+        let mut crashed = Command::new("false").spawn().unwrap();
+        let error = listener
+            .wait(&mut crashed, READY_TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("exited during startup"));
+
+        let socket = listener.path().to_path_buf();
+        drop(listener);
+        assert!(!socket.exists());
+    }
+}
@@ -0,0 +1,108 @@
+//! Signals a running daemon answers to, for `postd` and `post daemon` alike
+
+use crate::Daemon;
+use futures_util::stream::StreamExt;
+use post_core::{PostConfig, Result};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+use signal_hook::low_level::signal_name;
+use signal_hook_tokio::Signals;
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::{error, info};
+
+/// Start catching the signals [`run`] answers to, before the daemon is set up, so a stop
+/// requested meanwhile isn't lost
+pub fn register() -> Option<Signals> {
+    match Signals::new([SIGTERM, SIGINT, SIGHUP, SIGUSR1]) {
+        Ok(signals) => Some(signals),
+        Err(e) => {
+            error!("Failed to create signal handler: {}", e);
+            None
+        }
+    }
+}
+
+/// Run `daemon` until it stops or a signal stops it, then remove the PID file
+///
+/// The config is read again from `config_path`, or the usual file, on SIGHUP.
+pub async fn run(
+    daemon: Daemon,
+    signals: Option<Signals>,
+    config_path: Option<String>,
+    verbose: bool,
+) -> Result<()> {
+    let daemon = Arc::new(daemon);
+    let shutdown = Arc::new(Notify::new());
+    if let Some(signals) = signals {
+        tokio::spawn(handle_signals(
+            signals,
+            Arc::clone(&daemon),
+            Arc::clone(&shutdown),
+            config_path,
+            verbose,
+        ));
+    }
+
+    let run = daemon.run();
+    tokio::pin!(run);
+
+    let result = tokio::select! {
+        result = &mut run => result,
+        _ = shutdown.notified() => {
+            info!("Shutting down daemon");
+            daemon.shutdown();
+            run.await
+        }
+    };
+
+    if let Err(e) = crate::remove_pid_file() {
+        error!("Failed to remove PID file: {}", e);
+    }
+    result
+}
+
+/// The config at `path`, or the usual config file
+pub async fn load_config(path: Option<&str>) -> Result<PostConfig> {
+    match path {
+        Some(path) => {
+            let contents = tokio::fs::read_to_string(path).await?;
+            Ok(toml::from_str(&contents)?)
+        }
+        None => PostConfig::load().await,
+    }
+}
+
+/// Stop on SIGTERM, or SIGINT when run in a terminal; reload the config on SIGHUP; and
+/// write the daemon's state to the log on SIGUSR1
+async fn handle_signals(
+    mut signals: Signals,
+    daemon: Arc<Daemon>,
+    shutdown: Arc<Notify>,
+    config_path: Option<String>,
+    verbose: bool,
+) {
+    while let Some(signal) = signals.next().await {
+        match signal {
+            SIGTERM | SIGINT => {
+                info!(
+                    "Received {}, shutting down gracefully",
+                    signal_name(signal).unwrap_or("signal")
+                );
+                shutdown.notify_one();
+                break;
+            }
+            SIGHUP => {
+                info!("Received SIGHUP, reloading the config");
+                let reloaded = match load_config(config_path.as_deref()).await {
+                    Ok(config) => daemon.reload(&config, verbose),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = reloaded {
+                    error!("Keeping the current config: {}", e);
+                }
+            }
+            SIGUSR1 => daemon.log_state().await,
+            _ => {}
+        }
+    }
+}
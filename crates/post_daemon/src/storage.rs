@@ -0,0 +1,417 @@
+use crate::{get_log_file_path, get_state_db_path, private_data_dir};
+use post_core::{PostConfig, PostError, Result, StateKey, StateStore, SyncManager};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+/// Layout of the data directory this build reads and writes
+pub const LAYOUT_VERSION: u32 = 2;
+
+/// Database in the data directory that state is kept in from layout 2
+pub const STATE_DB: &str = "state.db";
+
+/// File in the data directory recording its layout; missing before layout 1
+const VERSION_FILE: &str = "layout_version";
+
+/// State that lived next to `config.toml` before layout 1
+const MOVED_FROM_CONFIG_DIR: &[&str] = &["api_token", "paired_devices.json", "certs"];
+
+/// How often the daemon enforces `storage.max_size`
+pub const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// JSON files state was kept in before layout 2
+const MOVED_INTO_STATE_DB: &[(&str, StateKey)] = &[
+    ("history.json", StateKey::History),
+    ("pins.json", StateKey::Pins),
+    ("peers.json", StateKey::Peers),
+    ("queue.json", StateKey::Queue),
+    ("sequences.json", StateKey::Sequences),
+];
+
+/// Migrations by the layout they upgrade from
+const MIGRATIONS: &[fn(&Path, &Path) -> Result<()>] =
+    &[move_state_out_of_config_dir, move_files_into_state_db];
+
+/// Directory the daemon keeps its state in, created readable by the owner only
+pub fn data_dir() -> Result<PathBuf> {
+    private_data_dir()
+}
+
+/// Upgrade the data directory to [`LAYOUT_VERSION`]; run before anything reads it
+pub fn migrate() -> Result<()> {
+    migrate_dirs(&private_data_dir()?, &PostConfig::config_dir()?)?;
+    Ok(())
+}
+
+/// Upgrade `data_dir`, taking state left in `config_dir` by older layouts, and return
+/// the layout it was at
+pub fn migrate_dirs(data_dir: &Path, config_dir: &Path) -> Result<u32> {
+    let found = layout_version(data_dir)?;
+    if found > LAYOUT_VERSION {
+        return Err(PostError::Config(format!(
+            "{} was written by a newer version of Post (layout {}, this build reads {})",
+            data_dir.display(),
+            found,
+            LAYOUT_VERSION
+        )));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(found as usize) {
+        migration(data_dir, config_dir)?;
+        info!("Migrated {} to layout {}", data_dir.display(), from + 1);
+    }
+    if found < LAYOUT_VERSION {
+        std::fs::write(data_dir.join(VERSION_FILE), LAYOUT_VERSION.to_string())?;
+    }
+    Ok(found)
+}
+
+/// Layout recorded in `data_dir`, 0 if it predates versioning
+pub fn layout_version(data_dir: &Path) -> Result<u32> {
+    match std::fs::read_to_string(data_dir.join(VERSION_FILE)) {
+        Ok(version) => version.trim().parse().map_err(|_| {
+            PostError::Config(format!(
+                "Invalid {} in {}",
+                VERSION_FILE,
+                data_dir.display()
+            ))
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Layout 1 keeps the API token, paired devices and certificates with the rest of the
+/// daemon's state, leaving only settings in the config directory
+fn move_state_out_of_config_dir(data_dir: &Path, config_dir: &Path) -> Result<()> {
+    for name in MOVED_FROM_CONFIG_DIR {
+        let (from, to) = (config_dir.join(name), data_dir.join(name));
+        if !from.exists() || to.exists() {
+            continue;
+        }
+        std::fs::rename(&from, &to).map_err(|e| {
+            PostError::Other(format!(
+                "Failed to move {} to {}: {}",
+                from.display(),
+                to.display(),
+                e
+            ))
+        })?;
+        info!("Moved {} to {}", from.display(), to.display());
+    }
+    Ok(())
+}
+
+/// Layout 2 keeps the state that had a JSON file each in the state database
+fn move_files_into_state_db(data_dir: &Path, _config_dir: &Path) -> Result<()> {
+    let store = StateStore::open(&data_dir.join(STATE_DB))?;
+    for (name, key) in MOVED_INTO_STATE_DB {
+        let path = data_dir.join(name);
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        // A damaged file would only fail to load later; leave it for the user to look at
+        if serde_json::from_str::<serde_json::Value>(&json).is_err() {
+            warn!(
+                "Not moving {} into the state database: invalid JSON",
+                path.display()
+            );
+            continue;
+        }
+        if store.get(*key)?.is_none() {
+            store.put(*key, &json)?;
+        }
+        std::fs::remove_file(&path)?;
+        info!("Moved {} into {}", path.display(), store.path().display());
+    }
+    Ok(())
+}
+
+/// What Post keeps on disk with `config`, labelled for `post storage info`
+pub fn known_paths(config: &PostConfig) -> Result<Vec<(&'static str, PathBuf)>> {
+    let data_dir = private_data_dir()?;
+    Ok(vec![
+        ("Config", PostConfig::config_path()?),
+        ("State database", get_state_db_path()?),
+        (
+            "State database (WAL)",
+            data_dir.join(format!("{}-wal", STATE_DB)),
+        ),
+        ("API token", crate::api::api_token_path()?),
+        ("Paired devices", crate::api::paired_devices_path()?),
+        ("TLS certificates", data_dir.join("certs")),
+        ("Embedded Tailscale", data_dir.join("tailscale")),
+        ("Log", crate::get_log_file_path()?),
+        ("Audit log", crate::audit::audit_log_path(&config.audit)?),
+        ("Crash report", crate::crash::get_crash_marker_path()?),
+        ("PID file", crate::get_pid_file_path()?),
+    ])
+}
+
+/// Compact the state database, returning its size with the write-ahead log before and after
+pub fn vacuum() -> Result<(u64, u64)> {
+    let path = get_state_db_path()?;
+    let size = || disk_usage(&path) + disk_usage(&path.with_file_name(format!("{}-wal", STATE_DB)));
+    let before = size();
+    if let Some(store) = StateStore::open_existing(&path)? {
+        store.vacuum()?;
+    }
+    Ok((before, size()))
+}
+
+/// Bytes taken by `path` and, for a directory, everything in it; 0 if it doesn't exist
+pub fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// What [`clean`] removed, or would remove on a dry run
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Cleanup {
+    /// Size of the data directory before cleaning, in bytes
+    pub size: u64,
+    /// Bytes removed, or that would be removed on a dry run
+    pub freed: u64,
+    /// Rotated logs (`post.log.*`) deleted, oldest first
+    pub rotated_logs: Vec<String>,
+    /// Oldest clipboard history items dropped
+    pub history_items: usize,
+    /// Whether the current log was emptied
+    pub log_truncated: bool,
+}
+
+/// Remove rotated logs and, while the data directory is over `max_size`, the oldest
+/// clipboard history and then the current log's contents
+///
+/// `sync_manager` is the running daemon's, whose stack is trimmed along with the file.
+pub async fn clean(
+    max_size: Option<u64>,
+    sync_manager: Option<&SyncManager>,
+    dry_run: bool,
+) -> Result<Cleanup> {
+    let data_dir = private_data_dir()?;
+    let log_path = get_log_file_path()?;
+    let store = StateStore::open(&get_state_db_path()?)?;
+    let history: Vec<String> = store
+        .load(StateKey::History)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+
+    let cleanup = plan_cleanup(
+        disk_usage(&data_dir),
+        rotated_logs(&log_path),
+        &history,
+        disk_usage(&log_path),
+        max_size,
+    );
+    if dry_run {
+        return Ok(cleanup);
+    }
+
+    for log in &cleanup.rotated_logs {
+        std::fs::remove_file(log)?;
+    }
+    if cleanup.history_items > 0 {
+        let keep = history.len() - cleanup.history_items;
+        match sync_manager.filter(|sync_manager| sync_manager.persists_history()) {
+            Some(sync_manager) => sync_manager.trim_clipboard_stack(keep).await,
+            None => store.save(StateKey::History, &history[..keep])?,
+        }
+        // Rewritten state only gives its space back once the database is rebuilt
+        store.vacuum()?;
+    }
+    if cleanup.log_truncated {
+        // The daemon appends, so it carries on at the start of the emptied file
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&log_path)?
+            .set_len(0)?;
+    }
+
+    if cleanup.freed > 0 {
+        info!("Freed {} bytes in {}", cleanup.freed, data_dir.display());
+    }
+    Ok(cleanup)
+}
+
+/// Enforce `max_size` every [`CLEANUP_INTERVAL`] for as long as the daemon runs
+pub async fn enforce_cap(
+    max_size: u64,
+    sync_manager: std::sync::Arc<tokio::sync::Mutex<Option<std::sync::Arc<SyncManager>>>>,
+) -> Result<()> {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let current = sync_manager.lock().await.clone();
+        if let Err(e) = clean(Some(max_size), current.as_deref(), false).await {
+            warn!("Failed to clean up the data directory: {}", e);
+        }
+    }
+}
+
+/// What to remove from a data directory of `size` bytes to get under `max_size`;
+/// rotated logs always go
+fn plan_cleanup(
+    size: u64,
+    rotated_logs: Vec<(PathBuf, u64)>,
+    history: &[String],
+    log_size: u64,
+    max_size: Option<u64>,
+) -> Cleanup {
+    let mut cleanup = Cleanup {
+        size,
+        ..Cleanup::default()
+    };
+    for (path, bytes) in rotated_logs {
+        cleanup.rotated_logs.push(path.display().to_string());
+        cleanup.freed += bytes;
+    }
+
+    let Some(max_size) = max_size else {
+        return cleanup;
+    };
+    let over = |cleanup: &Cleanup| size.saturating_sub(cleanup.freed) > max_size;
+    // Oldest items are last; each takes its JSON-escaped length plus a comma
+    for item in history.iter().rev() {
+        if !over(&cleanup) {
+            break;
+        }
+        cleanup.history_items += 1;
+        cleanup.freed += serde_json::to_string(item).map_or(0, |json| json.len() as u64 + 1);
+    }
+    if over(&cleanup) && log_size > 0 {
+        cleanup.log_truncated = true;
+        cleanup.freed += log_size;
+    }
+    cleanup
+}
+
+/// Rotated copies of the log at `log_path`, e.g. by logrotate, oldest first
+fn rotated_logs(log_path: &Path) -> Vec<(PathBuf, u64)> {
+    let (Some(dir), Some(name)) = (log_path.parent(), log_path.file_name()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut logs: Vec<_> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().ok()?;
+            Some((modified, entry.path(), metadata.len()))
+        })
+        .collect();
+    logs.sort();
+    logs.into_iter()
+        .map(|(_, path, bytes)| (path, bytes))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_layout_is_migrated_once() {
+        let root = tempfile::tempdir().unwrap();
+        let (data_dir, config_dir) = (root.path().join("data"), root.path().join("config"));
+        std::fs::create_dir_all(config_dir.join("certs")).unwrap();
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(config_dir.join("api_token"), "old-token").unwrap();
+        std::fs::write(config_dir.join("config.toml"), "").unwrap();
+
+        assert_eq!(migrate_dirs(&data_dir, &config_dir).unwrap(), 0);
+        assert_eq!(
+            std::fs::read_to_string(data_dir.join("api_token")).unwrap(),
+            "old-token"
+        );
+        assert!(data_dir.join("certs").is_dir());
+        assert!(!config_dir.join("api_token").exists());
+        assert!(config_dir.join("config.toml").exists());
+        assert_eq!(layout_version(&data_dir).unwrap(), LAYOUT_VERSION);
+
+        assert_eq!(
+            migrate_dirs(&data_dir, &config_dir).unwrap(),
+            LAYOUT_VERSION
+        );
+        std::fs::write(data_dir.join(VERSION_FILE), "99").unwrap();
+        assert!(migrate_dirs(&data_dir, &config_dir).is_err());
+    }
+
+    #[test]
+    fn test_state_files_move_into_the_database() {
+        let root = tempfile::tempdir().unwrap();
+        let (data_dir, config_dir) = (root.path().join("data"), root.path().join("config"));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(data_dir.join(VERSION_FILE), "1").unwrap();
+        std::fs::write(data_dir.join("history.json"), r#"["newest","oldest"]"#).unwrap();
+        std::fs::write(data_dir.join("peers.json"), "{damaged").unwrap();
+
+        assert_eq!(migrate_dirs(&data_dir, &config_dir).unwrap(), 1);
+        let store = StateStore::open(&data_dir.join(STATE_DB)).unwrap();
+        assert_eq!(
+            store.load::<Vec<String>>(StateKey::History).unwrap(),
+            Some(vec!["newest".to_string(), "oldest".to_string()])
+        );
+        assert!(!data_dir.join("history.json").exists());
+        assert!(store.get(StateKey::Peers).unwrap().is_none());
+        assert!(data_dir.join("peers.json").exists());
+    }
+
+    #[test]
+    fn test_disk_usage_counts_nested_files() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("certs")).unwrap();
+        std::fs::write(root.path().join("certs").join("node.crt"), [0u8; 100]).unwrap();
+        std::fs::write(root.path().join("pins.json"), [0u8; 20]).unwrap();
+
+        assert_eq!(disk_usage(root.path()), 120);
+        assert_eq!(disk_usage(&root.path().join("missing")), 0);
+    }
+
+    #[test]
+    fn test_cleanup_prunes_oldest_history_before_the_log() {
+        let rotated = vec![(PathBuf::from("post.log.1"), 500)];
+        let history = vec![
+            "newest".to_string(),
+            "older".to_string(),
+            "oldest".to_string(),
+        ];
+
+        // Rotated logs go even without a cap
+        let cleanup = plan_cleanup(1_000, rotated.clone(), &history, 300, None);
+        assert_eq!(cleanup.rotated_logs, vec!["post.log.1"]);
+        assert_eq!((cleanup.freed, cleanup.history_items), (500, 0));
+
+        // `"oldest",` is 9 bytes, which is enough
+        let cleanup = plan_cleanup(1_000, rotated.clone(), &history, 300, Some(495));
+        assert_eq!(cleanup.history_items, 1);
+        assert!(!cleanup.log_truncated);
+
+        let cleanup = plan_cleanup(1_000, rotated, &history, 300, Some(200));
+        assert_eq!(cleanup.history_items, 3);
+        assert!(cleanup.log_truncated);
+        assert_eq!(cleanup.freed, 500 + 9 + 8 + 9 + 300);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::notifications::NotificationManager;
+use post_core::Result;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
+
+/// A task that has run this long without failing is considered healthy again
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+/// Restarts long-running daemon tasks with exponential backoff when they fail or panic
+#[derive(Clone)]
+pub struct Supervisor {
+    notifications: Option<NotificationManager>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self {
+            notifications: None,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// Show a desktop notification the first time a task starts failing
+    pub fn with_notifications(mut self, notifications: NotificationManager) -> Self {
+        self.notifications = Some(notifications);
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Run the task built by `make_task`, building and running a fresh one whenever it
+    /// returns an error or panics. A task that returns `Ok` is not restarted.
+    pub fn spawn<F, Fut>(&self, name: &'static str, make_task: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let supervisor = self.clone();
+
+        tokio::spawn(async move {
+            let mut backoff = supervisor.initial_backoff;
+            let mut consecutive_failures = 0u32;
+
+            loop {
+                let started = Instant::now();
+
+                let failure = match tokio::spawn(make_task()).await {
+                    Ok(Ok(())) => {
+                        debug!("Task {} finished", name);
+                        return;
+                    }
+                    Ok(Err(e)) => e.to_string(),
+                    Err(e) if e.is_panic() => "task panicked".to_string(),
+                    Err(_) => {
+                        debug!("Task {} was cancelled", name);
+                        return;
+                    }
+                };
+
+                if started.elapsed() >= STABLE_RUN {
+                    backoff = supervisor.initial_backoff;
+                    consecutive_failures = 0;
+                }
+                consecutive_failures += 1;
+
+                if consecutive_failures == 1 {
+                    error!("Task {} failed: {}", name, failure);
+                    if let Some(ref notifications) = supervisor.notifications {
+                        if let Err(e) = notifications.show_task_failed(name, &failure) {
+                            warn!("Failed to show task failure notification: {}", e);
+                        }
+                    }
+                }
+                warn!(
+                    "Restarting task {} in {:?} (failure #{}): {}",
+                    name, backoff, consecutive_failures, failure
+                );
+
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(supervisor.max_backoff);
+            }
+        })
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use post_core::PostError;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn fast_supervisor() -> Supervisor {
+        Supervisor::new().with_backoff(Duration::from_millis(1), Duration::from_millis(5))
+    }
+
+    #[tokio::test]
+    async fn test_failed_task_is_restarted_until_it_succeeds() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&attempts);
+
+        let handle = fast_supervisor().spawn("flaky", move || {
+            let counter = Arc::clone(&counter);
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(PostError::Network("bind failed".to_string()))
+                } else {
+                    Ok(())
+                }
+            }
+        });
+
+        tokio::time::timeout(Duration::from_secs(2), handle)
+            .await
+            .expect("supervisor did not settle")
+            .unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_task_is_restarted() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = Arc::clone(&attempts);
+
+        let handle = fast_supervisor().spawn("panicky", move || {
+            let counter = Arc::clone(&counter);
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("boom");
+                }
+                Ok(())
+            }
+        });
+
+        tokio::time::timeout(Duration::from_secs(2), handle)
+            .await
+            .expect("supervisor did not settle")
+            .unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::state::{Command, Tray, RECENT_ITEMS};
+use post_core::{PostConfig, Result, SyncEvent};
+use post_daemon::api;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Wait before trying to reach the daemon again after its API stopped answering
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Follow the daemon's status and events into `tray`, and carry out menu commands,
+/// reconnecting whenever the daemon goes away
+pub async fn follow(
+    config: PostConfig,
+    tray: Arc<Tray>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+) {
+    loop {
+        if let Err(e) = follow_events(&config, &tray, &mut commands).await {
+            debug!("Daemon API unavailable: {}", e);
+        }
+        tray.update(|state| state.reachable = false);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn follow_events(
+    config: &PostConfig,
+    tray: &Tray,
+    commands: &mut mpsc::UnboundedReceiver<Command>,
+) -> Result<()> {
+    // Requests made while the daemon was unreachable are stale by now
+    while commands.try_recv().is_ok() {}
+
+    let base_url = api::client_base_url(config).await?;
+    let token = api::load_api_token().await?;
+    let status = api::fetch_status(&base_url, &token).await?;
+    let stack = api::fetch_stack(&base_url, &token).await?;
+    tray.update(|state| {
+        state.reachable = true;
+        state.connected = status.connected;
+        state.paused = status.paused;
+        state.recent = stack.items.into_iter().take(RECENT_ITEMS).collect();
+    });
+
+    let events = api::watch_events(&base_url, &token, |line| {
+        if let Ok(event) = serde_json::from_str::<SyncEvent>(line) {
+            tray.update(|state| state.apply(&event));
+        }
+    });
+    tokio::pin!(events);
+    loop {
+        tokio::select! {
+            result = &mut events => return result,
+            Some(command) = commands.recv() => {
+                if let Err(e) = run_command(config, &base_url, &token, command).await {
+                    warn!("{}", e);
+                }
+            }
+        }
+    }
+}
+
+async fn run_command(
+    config: &PostConfig,
+    base_url: &str,
+    token: &str,
+    command: Command,
+) -> Result<()> {
+    match command {
+        Command::SetPaused(paused) => api::request_paused(base_url, token, paused).await.map(drop),
+        // The daemon picks the copy up from the clipboard and syncs it like any other
+        Command::Copy(content) => {
+            post_core::create_clipboard_with_config(&config.clipboard)?
+                .set_contents(&content)
+                .await
+        }
+    }
+}
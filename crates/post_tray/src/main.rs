@@ -0,0 +1,65 @@
+use clap::Parser;
+use post_core::PostConfig;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+mod daemon;
+#[cfg(target_os = "linux")]
+mod sni;
+mod state;
+#[cfg(windows)]
+mod win32;
+
+use state::{Command, Tray};
+
+#[derive(Parser)]
+#[command(name = "post_tray")]
+#[command(about = "System tray icon for the Post clipboard sync daemon")]
+#[command(version = "0.1.0")]
+struct Args {
+    #[arg(short, long)]
+    config: Option<String>,
+
+    /// Follow the daemon of this profile
+    #[arg(long)]
+    profile: Option<String>,
+
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    if let Some(profile) = &args.profile {
+        post_core::config::set_profile(profile)?;
+    }
+    let config: PostConfig = if let Some(ref config_path) = args.config {
+        let contents = tokio::fs::read_to_string(config_path).await?;
+        toml::from_str(&contents)?
+    } else {
+        PostConfig::load().await?
+    };
+    post_daemon::logging::init(&config.logging, args.verbose)?;
+
+    let tray = Arc::new(Tray::default());
+    let (commands, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(daemon::follow(config, Arc::clone(&tray), receiver));
+
+    run(tray, commands).await
+}
+
+#[cfg(target_os = "linux")]
+async fn run(tray: Arc<Tray>, commands: mpsc::UnboundedSender<Command>) -> anyhow::Result<()> {
+    Ok(sni::run(tray, commands).await?)
+}
+
+#[cfg(windows)]
+async fn run(tray: Arc<Tray>, commands: mpsc::UnboundedSender<Command>) -> anyhow::Result<()> {
+    Ok(win32::run(tray, commands)?)
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+async fn run(_: Arc<Tray>, _: mpsc::UnboundedSender<Command>) -> anyhow::Result<()> {
+    anyhow::bail!("post_tray supports Linux and Windows; on macOS use `post menubar`")
+}
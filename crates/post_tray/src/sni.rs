@@ -0,0 +1,393 @@
+//! Tray icon through the StatusNotifierItem and DBusMenu D-Bus interfaces, which KDE,
+//! GNOME's AppIndicator extension, waybar and most other Linux panels host
+
+use crate::state::{icon_rgba, Action, Command, MenuEntry, Tray};
+use post_core::{PostError, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tracing::{debug, info, warn};
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedValue, Str, Structure};
+use zbus::Connection;
+
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+
+/// Icon sizes offered to the panel, which picks the closest
+const ICON_SIZES: [u32; 3] = [16, 22, 32];
+
+/// How often to retry registering while no panel hosts tray icons
+const REGISTER_RETRY: Duration = Duration::from_secs(10);
+
+/// An icon as StatusNotifierItem sends it: width, height and ARGB32 pixels in network
+/// byte order
+type Pixmap = (i32, i32, Vec<u8>);
+
+struct StatusNotifierItem {
+    tray: Arc<Tray>,
+}
+
+#[zbus::interface(name = "org.kde.StatusNotifierItem")]
+impl StatusNotifierItem {
+    #[zbus(property)]
+    fn category(&self) -> &str {
+        "ApplicationStatus"
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> &str {
+        "post"
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> &str {
+        "Post"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "Active"
+    }
+
+    #[zbus(property)]
+    fn icon_name(&self) -> &str {
+        ""
+    }
+
+    #[zbus(property)]
+    fn icon_pixmap(&self) -> Vec<Pixmap> {
+        let color = self.tray.state().health().color();
+        ICON_SIZES
+            .iter()
+            .map(|&size| (size as i32, size as i32, argb(&icon_rgba(size, color))))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn tool_tip(&self) -> (String, Vec<Pixmap>, String, String) {
+        let status = self.tray.state().status();
+        (String::new(), Vec::new(), "Post".to_string(), status)
+    }
+
+    #[zbus(property)]
+    fn item_is_menu(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn menu(&self) -> ObjectPath<'_> {
+        ObjectPath::from_static_str_unchecked(MENU_PATH)
+    }
+
+    fn activate(&self, _x: i32, _y: i32) {}
+
+    fn secondary_activate(&self, _x: i32, _y: i32) {}
+
+    fn context_menu(&self, _x: i32, _y: i32) {}
+
+    fn scroll(&self, _delta: i32, _orientation: &str) {}
+
+    #[zbus(signal)]
+    async fn new_icon(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn new_tool_tip(emitter: &SignalEmitter<'_>) -> zbus::Result<()>;
+}
+
+/// RGBA pixels as ARGB
+fn argb(rgba: &[u8]) -> Vec<u8> {
+    rgba.chunks_exact(4)
+        .flat_map(|pixel| [pixel[3], pixel[0], pixel[1], pixel[2]])
+        .collect()
+}
+
+/// A menu item as DBusMenu lays it out: id, properties and child items
+type Layout = (i32, HashMap<String, OwnedValue>, Vec<OwnedValue>);
+
+struct DbusMenu {
+    tray: Arc<Tray>,
+    commands: mpsc::UnboundedSender<Command>,
+    quit: Arc<Notify>,
+    /// Bumped whenever the menu changes, so the panel asks for the new layout
+    revision: AtomicU32,
+    /// Actions of the layout last sent, by item id
+    actions: Mutex<HashMap<i32, Action>>,
+}
+
+#[zbus::interface(name = "com.canonical.dbusmenu")]
+impl DbusMenu {
+    /// The whole menu, whatever part was asked for; panels cope with getting more
+    fn get_layout(
+        &self,
+        _parent_id: i32,
+        _recursion_depth: i32,
+        _property_names: Vec<String>,
+    ) -> (u32, Layout) {
+        let mut actions = HashMap::new();
+        let mut next_id = 1;
+        let children = self
+            .tray
+            .state()
+            .menu()
+            .into_iter()
+            .map(|entry| layout_value(entry, &mut next_id, &mut actions))
+            .collect();
+        *self.lock_actions() = actions;
+
+        let mut root = HashMap::new();
+        root.insert("children-display".to_string(), text("submenu"));
+        (self.revision.load(Ordering::Relaxed), (0, root, children))
+    }
+
+    fn get_group_properties(
+        &self,
+        _ids: Vec<i32>,
+        _property_names: Vec<String>,
+    ) -> Vec<(i32, HashMap<String, OwnedValue>)> {
+        Vec::new()
+    }
+
+    fn get_property(&self, _id: i32, name: String) -> zbus::fdo::Result<OwnedValue> {
+        Err(zbus::fdo::Error::InvalidArgs(format!(
+            "Unknown property {}",
+            name
+        )))
+    }
+
+    fn event(&self, id: i32, event_id: &str, _data: OwnedValue, _timestamp: u32) {
+        if event_id == "clicked" {
+            self.clicked(id);
+        }
+    }
+
+    fn event_group(&self, events: Vec<(i32, String, OwnedValue, u32)>) -> Vec<i32> {
+        for (id, event_id, _, _) in events {
+            if event_id == "clicked" {
+                self.clicked(id);
+            }
+        }
+        Vec::new()
+    }
+
+    fn about_to_show(&self, _id: i32) -> bool {
+        false
+    }
+
+    fn about_to_show_group(&self, _ids: Vec<i32>) -> (Vec<i32>, Vec<i32>) {
+        (Vec::new(), Vec::new())
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> u32 {
+        3
+    }
+
+    #[zbus(property)]
+    fn text_direction(&self) -> &str {
+        "ltr"
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> &str {
+        "normal"
+    }
+
+    #[zbus(property)]
+    fn icon_theme_path(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(signal)]
+    async fn layout_updated(
+        emitter: &SignalEmitter<'_>,
+        revision: u32,
+        parent: i32,
+    ) -> zbus::Result<()>;
+}
+
+impl DbusMenu {
+    fn clicked(&self, id: i32) {
+        let Some(action) = self.lock_actions().get(&id).cloned() else {
+            return;
+        };
+        let command = match action {
+            Action::TogglePause => Command::SetPaused(!self.tray.state().paused),
+            Action::Copy(content) => Command::Copy(content),
+            Action::Quit => {
+                self.quit.notify_one();
+                return;
+            }
+        };
+        let _ = self.commands.send(command);
+    }
+
+    fn lock_actions(&self) -> std::sync::MutexGuard<'_, HashMap<i32, Action>> {
+        self.actions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// `entry` laid out as a DBusMenu item numbered from `next_id`, recording its actions
+fn layout_value(
+    entry: MenuEntry,
+    next_id: &mut i32,
+    actions: &mut HashMap<i32, Action>,
+) -> OwnedValue {
+    let id = *next_id;
+    *next_id += 1;
+    let mut properties = HashMap::new();
+    let mut children = Vec::new();
+    match entry {
+        MenuEntry::Item { label, action } => {
+            properties.insert("label".to_string(), text(&escape(&label)));
+            properties.insert("enabled".to_string(), action.is_some().into());
+            if let Some(action) = action {
+                actions.insert(id, action);
+            }
+        }
+        MenuEntry::Submenu { label, entries } => {
+            properties.insert("label".to_string(), text(&escape(&label)));
+            properties.insert("children-display".to_string(), text("submenu"));
+            children = entries
+                .into_iter()
+                .map(|entry| layout_value(entry, next_id, actions))
+                .collect();
+        }
+        MenuEntry::Separator => {
+            properties.insert("type".to_string(), text("separator"));
+        }
+    }
+    let layout: Layout = (id, properties, children);
+    OwnedValue::try_from(Structure::from(layout)).expect("menu layouts hold no file descriptors")
+}
+
+fn text(value: &str) -> OwnedValue {
+    Str::from(value.to_string()).into()
+}
+
+/// `label` with underscores doubled, since a single one marks an access key
+fn escape(label: &str) -> String {
+    label.replace('_', "__")
+}
+
+/// Show the tray icon until it is quit from its menu
+pub async fn run(tray: Arc<Tray>, commands: mpsc::UnboundedSender<Command>) -> Result<()> {
+    let name = format!("org.kde.StatusNotifierItem-{}-1", std::process::id());
+    let quit = Arc::new(Notify::new());
+    let menu = DbusMenu {
+        tray: Arc::clone(&tray),
+        commands,
+        quit: Arc::clone(&quit),
+        revision: AtomicU32::new(1),
+        actions: Mutex::new(HashMap::new()),
+    };
+    let connection = zbus::connection::Builder::session()
+        .and_then(|builder| builder.name(name.as_str()))
+        .and_then(|builder| {
+            builder.serve_at(
+                ITEM_PATH,
+                StatusNotifierItem {
+                    tray: Arc::clone(&tray),
+                },
+            )
+        })
+        .and_then(|builder| builder.serve_at(MENU_PATH, menu))
+        .map_err(|e| dbus_error("setting up the tray icon", e))?
+        .build()
+        .await
+        .map_err(|e| dbus_error("connecting to the session bus", e))?;
+
+    let mut registered = false;
+    let mut retry = tokio::time::interval(REGISTER_RETRY);
+    loop {
+        tokio::select! {
+            _ = retry.tick(), if !registered => {
+                match register(&connection, &name).await {
+                    Ok(()) => {
+                        info!("Tray icon registered");
+                        registered = true;
+                    }
+                    Err(e) => debug!("{}", e),
+                }
+            }
+            _ = tray.changed() => {
+                if let Err(e) = announce_changes(&connection).await {
+                    warn!("{}", e);
+                }
+            }
+            _ = quit.notified() => return Ok(()),
+        }
+    }
+}
+
+/// Ask the panel's StatusNotifierWatcher to show the item served as `name`
+async fn register(connection: &Connection, name: &str) -> Result<()> {
+    connection
+        .call_method(
+            Some("org.kde.StatusNotifierWatcher"),
+            "/StatusNotifierWatcher",
+            Some("org.kde.StatusNotifierWatcher"),
+            "RegisterStatusNotifierItem",
+            &(name,),
+        )
+        .await
+        .map(drop)
+        .map_err(|e| dbus_error("registering the tray icon; is a tray host running?", e))
+}
+
+/// Tell the panel to fetch the icon, tooltip and menu again
+async fn announce_changes(connection: &Connection) -> Result<()> {
+    let server = connection.object_server();
+    let item = server
+        .interface::<_, StatusNotifierItem>(ITEM_PATH)
+        .await
+        .map_err(|e| dbus_error("updating the tray icon", e))?;
+    let menu = server
+        .interface::<_, DbusMenu>(MENU_PATH)
+        .await
+        .map_err(|e| dbus_error("updating the tray menu", e))?;
+
+    let revision = menu.get().await.revision.fetch_add(1, Ordering::Relaxed) + 1;
+    StatusNotifierItem::new_icon(item.signal_emitter())
+        .await
+        .and(StatusNotifierItem::new_tool_tip(item.signal_emitter()).await)
+        .and(DbusMenu::layout_updated(menu.signal_emitter(), revision, 0).await)
+        .map_err(|e| dbus_error("updating the tray icon", e))
+}
+
+fn dbus_error(action: &str, error: impl std::fmt::Display) -> PostError {
+    PostError::Other(format!("Tray: failed {}: {}", action, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_numbers_items_and_records_their_actions() {
+        let mut actions = HashMap::new();
+        let mut next_id = 1;
+        let entries = vec![
+            MenuEntry::Separator,
+            MenuEntry::Submenu {
+                label: "Recent".to_string(),
+                entries: vec![MenuEntry::Item {
+                    label: "snake_case".to_string(),
+                    action: Some(Action::Copy("snake_case".to_string())),
+                }],
+            },
+        ];
+        for entry in entries {
+            layout_value(entry, &mut next_id, &mut actions);
+        }
+
+        assert_eq!(next_id, 4);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[&3], Action::Copy("snake_case".to_string()));
+        assert_eq!(escape("snake_case"), "snake__case");
+    }
+}
@@ -0,0 +1,276 @@
+use post_core::{text, SyncEvent};
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard};
+use tokio::sync::Notify;
+
+/// Recent clipboard items listed in the menu
+pub const RECENT_ITEMS: usize = 10;
+
+/// Longest menu label for a recent item, in characters
+const LABEL_CHARS: usize = 40;
+
+/// What the tray shows, as last learned from the daemon
+#[derive(Debug, Default)]
+pub struct TrayState {
+    /// Whether the daemon's API answered; nothing else is known until it does
+    pub reachable: bool,
+    pub connected: bool,
+    pub paused: bool,
+    pub clipboard_error: Option<String>,
+    /// Synced content, newest first
+    pub recent: VecDeque<String>,
+}
+
+/// Overall state, shown as the icon's color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    /// The daemon is not running
+    Offline,
+    Paused,
+    /// Not connected to Tailscale, or the clipboard can't be used
+    Degraded,
+    Syncing,
+}
+
+impl Health {
+    /// Icon color as RGB
+    pub fn color(self) -> [u8; 3] {
+        match self {
+            Health::Offline => [0x8e, 0x8e, 0x93],
+            Health::Paused => [0xff, 0x9f, 0x0a],
+            Health::Degraded => [0xff, 0x3b, 0x30],
+            Health::Syncing => [0x34, 0xc7, 0x59],
+        }
+    }
+}
+
+/// What picking a menu item does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    TogglePause,
+    /// Put this recent item back on the clipboard
+    Copy(String),
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuEntry {
+    /// An item without an action is shown disabled, as plain text
+    Item {
+        label: String,
+        action: Option<Action>,
+    },
+    Submenu {
+        label: String,
+        entries: Vec<MenuEntry>,
+    },
+    Separator,
+}
+
+impl MenuEntry {
+    fn item(label: impl Into<String>, action: Option<Action>) -> Self {
+        MenuEntry::Item {
+            label: label.into(),
+            action,
+        }
+    }
+}
+
+impl TrayState {
+    pub fn apply(&mut self, event: &SyncEvent) {
+        match event {
+            SyncEvent::Received { content, .. } | SyncEvent::Sent { content, .. } => {
+                self.remember(content.clone())
+            }
+            SyncEvent::Connected { .. } => self.connected = true,
+            SyncEvent::Disconnected => self.connected = false,
+            SyncEvent::ClipboardUnavailable { error } => self.clipboard_error = Some(error.clone()),
+            SyncEvent::ClipboardRecovered => self.clipboard_error = None,
+            SyncEvent::Paused => self.paused = true,
+            SyncEvent::Resumed => self.paused = false,
+            SyncEvent::PeerDiscovered { .. }
+            | SyncEvent::PeerOffline { .. }
+            | SyncEvent::TailnetPeerOnline { .. }
+            | SyncEvent::TailnetPeerOffline { .. }
+            | SyncEvent::ClockSkew { .. }
+            | SyncEvent::Filtered { .. } => {}
+        }
+    }
+
+    /// Move `content` to the front of the recent items
+    fn remember(&mut self, content: String) {
+        self.recent.retain(|item| *item != content);
+        self.recent.push_front(content);
+        self.recent.truncate(RECENT_ITEMS);
+    }
+
+    pub fn health(&self) -> Health {
+        if !self.reachable {
+            Health::Offline
+        } else if self.paused {
+            Health::Paused
+        } else if !self.connected || self.clipboard_error.is_some() {
+            Health::Degraded
+        } else {
+            Health::Syncing
+        }
+    }
+
+    pub fn status(&self) -> String {
+        if !self.reachable {
+            "Daemon not running".to_string()
+        } else if self.paused {
+            "Sync paused".to_string()
+        } else if !self.connected {
+            "Waiting for Tailscale".to_string()
+        } else if let Some(error) = &self.clipboard_error {
+            format!("Clipboard unavailable: {}", error)
+        } else {
+            "Syncing".to_string()
+        }
+    }
+
+    pub fn menu(&self) -> Vec<MenuEntry> {
+        let pause_label = if self.paused {
+            "Resume Sync"
+        } else {
+            "Pause Sync"
+        };
+        let recent = if self.recent.is_empty() {
+            MenuEntry::item("No Recent Items", None)
+        } else {
+            MenuEntry::Submenu {
+                label: "Recent Items".to_string(),
+                entries: self
+                    .recent
+                    .iter()
+                    .map(|content| {
+                        MenuEntry::item(label(content), Some(Action::Copy(content.clone())))
+                    })
+                    .collect(),
+            }
+        };
+
+        vec![
+            MenuEntry::item(self.status(), None),
+            MenuEntry::Separator,
+            MenuEntry::item(pause_label, self.reachable.then_some(Action::TogglePause)),
+            recent,
+            MenuEntry::Separator,
+            MenuEntry::item("Quit Post Tray", Some(Action::Quit)),
+        ]
+    }
+}
+
+/// A recent item as one short line
+fn label(content: &str) -> String {
+    let line = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    text::preview(&line, LABEL_CHARS)
+}
+
+/// A filled circle in `color` on a transparent `size` by `size` square, as RGBA rows
+pub fn icon_rgba(size: u32, color: [u8; 3]) -> Vec<u8> {
+    let center = (size as f32 - 1.0) / 2.0;
+    let radius = size as f32 * 0.4;
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let distance = (x as f32 - center).hypot(y as f32 - center);
+            let alpha = if distance <= radius { 0xff } else { 0 };
+            pixels.extend_from_slice(&[color[0], color[1], color[2], alpha]);
+        }
+    }
+    pixels
+}
+
+/// Tray state shared between the daemon follower and the platform tray
+#[derive(Debug, Default)]
+pub struct Tray {
+    state: Mutex<TrayState>,
+    changed: Notify,
+}
+
+impl Tray {
+    pub fn state(&self) -> MutexGuard<'_, TrayState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Change the state and wake [`Tray::changed`]
+    pub fn update(&self, change: impl FnOnce(&mut TrayState)) {
+        change(&mut self.state());
+        self.changed.notify_one();
+    }
+
+    /// Wait for the next [`Tray::update`]
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+}
+
+/// Requests from the menu, carried out against the daemon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    SetPaused(bool),
+    Copy(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn received(content: &str) -> SyncEvent {
+        SyncEvent::Received {
+            from: "100.64.0.2".to_string(),
+            from_name: "desktop".to_string(),
+            content: content.to_string(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_recent_items_are_newest_first_without_repeats() {
+        let mut state = TrayState::default();
+        for content in ["one", "two", "one"] {
+            state.apply(&received(content));
+        }
+        assert_eq!(state.recent, ["one", "two"]);
+
+        for n in 0..RECENT_ITEMS {
+            state.apply(&received(&n.to_string()));
+        }
+        assert_eq!(state.recent.len(), RECENT_ITEMS);
+        assert_eq!(state.recent[0], (RECENT_ITEMS - 1).to_string());
+    }
+
+    #[test]
+    fn test_health_follows_daemon_state() {
+        let mut state = TrayState::default();
+        assert_eq!(state.health(), Health::Offline);
+        assert_eq!(
+            state.menu()[2],
+            MenuEntry::item("Pause Sync", None),
+            "pausing needs the daemon"
+        );
+
+        state.reachable = true;
+        assert_eq!(state.health(), Health::Degraded);
+        state.apply(&SyncEvent::Connected {
+            node_id: "laptop".to_string(),
+        });
+        assert_eq!(state.health(), Health::Syncing);
+        state.apply(&SyncEvent::Paused);
+        assert_eq!(state.health(), Health::Paused);
+        assert_eq!(
+            state.menu()[2],
+            MenuEntry::item("Resume Sync", Some(Action::TogglePause))
+        );
+    }
+
+    #[test]
+    fn test_recent_item_labels_are_one_short_line() {
+        assert_eq!(label("fn main() {\n    run();\n}"), "fn main() { run(); }");
+        assert!(label(&"x".repeat(100)).ends_with("..."));
+    }
+}
@@ -0,0 +1,260 @@
+//! Tray icon in the Windows notification area, through Shell_NotifyIconW
+
+use crate::state::{icon_rgba, Action, Command, Health, MenuEntry, Tray};
+use post_core::{PostError, Result};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::mpsc;
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::UI::Shell::{
+    Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE, NIM_MODIFY,
+    NOTIFYICONDATAW,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    AppendMenuW, CreateIcon, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyIcon,
+    DestroyMenu, DispatchMessageW, GetCursorPos, GetMessageW, PostQuitMessage, RegisterClassW,
+    SetForegroundWindow, SetTimer, TrackPopupMenu, TranslateMessage, HICON, HMENU, HWND_MESSAGE,
+    MF_GRAYED, MF_POPUP, MF_SEPARATOR, MF_STRING, MSG, TPM_RETURNCMD, TPM_RIGHTBUTTON, WM_APP,
+    WM_LBUTTONUP, WM_RBUTTONUP, WM_TIMER, WNDCLASSW,
+};
+
+/// Message the notification area sends clicks on the icon as
+const WM_TRAY: u32 = WM_APP + 1;
+
+const ICON_ID: u32 = 1;
+const ICON_SIZE: u32 = 16;
+
+/// How often the icon and tooltip are refreshed from the tray state, in milliseconds
+const REFRESH_INTERVAL_MS: u32 = 1000;
+
+/// What the window procedure needs, since it can't be handed any state
+struct Context {
+    tray: Arc<Tray>,
+    commands: mpsc::UnboundedSender<Command>,
+    /// The icon and tooltip last shown, to skip updates that change nothing
+    shown: Mutex<Option<(Health, String)>>,
+}
+
+static CONTEXT: OnceLock<Context> = OnceLock::new();
+
+/// Show the tray icon until it is quit from its menu
+///
+/// Runs the window message loop, so this blocks the calling thread.
+pub fn run(tray: Arc<Tray>, commands: mpsc::UnboundedSender<Command>) -> Result<()> {
+    let _ = CONTEXT.set(Context {
+        tray,
+        commands,
+        shown: Mutex::new(None),
+    });
+
+    unsafe {
+        let instance = GetModuleHandleW(std::ptr::null());
+        let class_name = wide("PostTray");
+        let mut class: WNDCLASSW = std::mem::zeroed();
+        class.lpfnWndProc = Some(window_proc);
+        class.hInstance = instance;
+        class.lpszClassName = class_name.as_ptr();
+        if RegisterClassW(&class) == 0 {
+            return Err(win32_error("registering the tray window class"));
+        }
+
+        // A message-only window: never shown, it just receives the icon's messages
+        let window = CreateWindowExW(
+            0,
+            class_name.as_ptr(),
+            class_name.as_ptr(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            0,
+            instance,
+            std::ptr::null(),
+        );
+        if window == 0 {
+            return Err(win32_error("creating the tray window"));
+        }
+
+        let mut data = notify_icon_data(window);
+        data.uFlags = NIF_MESSAGE;
+        data.uCallbackMessage = WM_TRAY;
+        if Shell_NotifyIconW(NIM_ADD, &data) == 0 {
+            return Err(win32_error("adding the tray icon"));
+        }
+        refresh(window);
+        SetTimer(window, 1, REFRESH_INTERVAL_MS, None);
+
+        let mut message: MSG = std::mem::zeroed();
+        while GetMessageW(&mut message, 0, 0, 0) > 0 {
+            TranslateMessage(&message);
+            DispatchMessageW(&message);
+        }
+    }
+    Ok(())
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match message {
+        WM_TIMER => refresh(window),
+        WM_TRAY if matches!(lparam as u32, WM_LBUTTONUP | WM_RBUTTONUP) => show_menu(window),
+        _ => return DefWindowProcW(window, message, wparam, lparam),
+    }
+    0
+}
+
+/// Update the icon's color and tooltip if the state changed since they were last shown
+unsafe fn refresh(window: HWND) {
+    let Some(context) = CONTEXT.get() else {
+        return;
+    };
+    let (health, status) = {
+        let state = context.tray.state();
+        (state.health(), state.status())
+    };
+    let mut shown = context
+        .shown
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if shown.as_ref() == Some(&(health, status.clone())) {
+        return;
+    }
+
+    let icon = create_icon(health.color());
+    let mut data = notify_icon_data(window);
+    data.uFlags = NIF_ICON | NIF_TIP;
+    data.hIcon = icon;
+    let tip = wide(&format!("Post: {}", status));
+    let len = tip.len().min(data.szTip.len());
+    data.szTip[..len].copy_from_slice(&tip[..len]);
+    // Keep the terminating nul when the tooltip had to be cut
+    data.szTip[data.szTip.len() - 1] = 0;
+    Shell_NotifyIconW(NIM_MODIFY, &data);
+    if icon != 0 {
+        DestroyIcon(icon);
+    }
+    *shown = Some((health, status));
+}
+
+/// Show the menu at the cursor and carry out whatever is picked from it
+unsafe fn show_menu(window: HWND) {
+    let Some(context) = CONTEXT.get() else {
+        return;
+    };
+    let entries = context.tray.state().menu();
+    let mut actions = Vec::new();
+    let menu = CreatePopupMenu();
+    append_entries(menu, entries, &mut actions);
+
+    let mut cursor = POINT { x: 0, y: 0 };
+    GetCursorPos(&mut cursor);
+    // Without this the menu stays open when clicking elsewhere
+    SetForegroundWindow(window);
+    let picked = TrackPopupMenu(
+        menu,
+        TPM_RETURNCMD | TPM_RIGHTBUTTON,
+        cursor.x,
+        cursor.y,
+        0,
+        window,
+        std::ptr::null(),
+    );
+    DestroyMenu(menu);
+
+    // Command ids are positions in `actions`, counted from 1
+    let Some(action) = (picked as usize)
+        .checked_sub(1)
+        .and_then(|index| actions.get(index))
+    else {
+        return;
+    };
+    let command = match action.clone() {
+        Action::TogglePause => Command::SetPaused(!context.tray.state().paused),
+        Action::Copy(content) => Command::Copy(content),
+        Action::Quit => {
+            Shell_NotifyIconW(NIM_DELETE, &notify_icon_data(window));
+            PostQuitMessage(0);
+            return;
+        }
+    };
+    let _ = context.commands.send(command);
+}
+
+unsafe fn append_entries(menu: HMENU, entries: Vec<MenuEntry>, actions: &mut Vec<Action>) {
+    for entry in entries {
+        match entry {
+            MenuEntry::Item { label, action } => {
+                let label = wide(&escape(&label));
+                match action {
+                    Some(action) => {
+                        actions.push(action);
+                        AppendMenuW(menu, MF_STRING, actions.len(), label.as_ptr());
+                    }
+                    None => {
+                        AppendMenuW(menu, MF_STRING | MF_GRAYED, 0, label.as_ptr());
+                    }
+                }
+            }
+            MenuEntry::Submenu { label, entries } => {
+                let submenu = CreatePopupMenu();
+                append_entries(submenu, entries, actions);
+                let label = wide(&escape(&label));
+                AppendMenuW(menu, MF_STRING | MF_POPUP, submenu as usize, label.as_ptr());
+            }
+            MenuEntry::Separator => {
+                AppendMenuW(menu, MF_SEPARATOR, 0, std::ptr::null());
+            }
+        }
+    }
+}
+
+/// A filled circle in `color`, or no icon if Windows refuses to create it
+unsafe fn create_icon(color: [u8; 3]) -> HICON {
+    // 32-bit icons are BGRA, and their alpha makes the monochrome mask unused
+    let bgra: Vec<u8> = icon_rgba(ICON_SIZE, color)
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+        .collect();
+    let mask = vec![0u8; (ICON_SIZE * ICON_SIZE / 8) as usize];
+    CreateIcon(
+        GetModuleHandleW(std::ptr::null()),
+        ICON_SIZE as i32,
+        ICON_SIZE as i32,
+        1,
+        32,
+        mask.as_ptr(),
+        bgra.as_ptr(),
+    )
+}
+
+unsafe fn notify_icon_data(window: HWND) -> NOTIFYICONDATAW {
+    let mut data: NOTIFYICONDATAW = std::mem::zeroed();
+    data.cbSize = std::mem::size_of::<NOTIFYICONDATAW>() as u32;
+    data.hWnd = window;
+    data.uID = ICON_ID;
+    data
+}
+
+/// `text` as a nul-terminated UTF-16 string
+fn wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// `label` with ampersands doubled, since a single one marks an access key
+fn escape(label: &str) -> String {
+    label.replace('&', "&&")
+}
+
+fn win32_error(action: &str) -> PostError {
+    PostError::Other(format!(
+        "Tray: failed {}: {}",
+        action,
+        std::io::Error::last_os_error()
+    ))
+}
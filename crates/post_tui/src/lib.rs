@@ -3,24 +3,45 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use post_core::{NodeMap, PostConfig, PostError, Result};
+use post_core::search::SearchIndex;
+use post_core::{
+    ClipboardManager, DeliveryState, NodeMap, PeerDelivery, PostConfig, PostError, Result,
+    SystemClipboard,
+};
 use ratatui::{
     backend::{Backend, CrosstermBackend},
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
 use std::io;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+/// Most search matches listed at once
+const SEARCH_LIMIT: usize = 200;
+
 pub struct App {
     pub should_quit: bool,
     pub nodes: Arc<RwLock<NodeMap>>,
     pub last_clipboard: Arc<RwLock<String>>,
     pub status: Arc<RwLock<AppStatus>>,
+    /// Recent clipboard items synced between devices, newest first
+    pub stack: Arc<RwLock<Vec<String>>>,
+    /// Full-text index of `stack`, rebuilt when it changes
+    stack_index: Arc<RwLock<SearchIndex>>,
+    /// The stack search, if one is shown; only matching items are listed then
+    pub search: Arc<RwLock<Option<String>>>,
+    /// Whether keys are typed into the search rather than treated as commands
+    pub editing_search: Arc<RwLock<bool>>,
+    /// Position in the listed items, which are the search matches while searching
+    pub selected: Arc<RwLock<usize>>,
+    /// Where the clipboard's current content came from, as told by the daemon
+    pub clipboard_source: Arc<RwLock<Option<String>>>,
+    /// Which peers have applied the latest update sent from here
+    pub last_sync: Arc<RwLock<Vec<PeerDelivery>>>,
     pub config: PostConfig,
 }
 
@@ -39,6 +60,13 @@ impl App {
             nodes: Arc::new(RwLock::new(NodeMap::new())),
             last_clipboard: Arc::new(RwLock::new(String::new())),
             status: Arc::new(RwLock::new(AppStatus::Connecting)),
+            stack: Arc::new(RwLock::new(Vec::new())),
+            stack_index: Arc::new(RwLock::new(SearchIndex::new())),
+            search: Arc::new(RwLock::new(None)),
+            editing_search: Arc::new(RwLock::new(false)),
+            selected: Arc::new(RwLock::new(0)),
+            clipboard_source: Arc::new(RwLock::new(None)),
+            last_sync: Arc::new(RwLock::new(Vec::new())),
             config,
         }
     }
@@ -68,6 +96,87 @@ impl App {
         };
     }
 
+    pub async fn update_stack(&self, items: Vec<String>) {
+        let mut stack = self.stack.write().await;
+        if *stack == items {
+            return;
+        }
+        *self.stack_index.write().await = SearchIndex::with_items(&items);
+        *stack = items;
+        drop(stack);
+        self.clamp_selection().await;
+    }
+
+    /// Stack items listed with their stack positions: all of them, or the search matches
+    pub async fn listed_stack(&self) -> Vec<(usize, String)> {
+        match self.search.read().await.as_deref() {
+            Some(query) if !query.trim().is_empty() => self
+                .stack_index
+                .read()
+                .await
+                .search(query, SEARCH_LIMIT)
+                .into_iter()
+                .map(|found| (found.position, found.content))
+                .collect(),
+            _ => self
+                .stack
+                .read()
+                .await
+                .iter()
+                .cloned()
+                .enumerate()
+                .collect(),
+        }
+    }
+
+    async fn clamp_selection(&self) {
+        let len = self.listed_stack().await.len();
+        let mut selected = self.selected.write().await;
+        *selected = (*selected).min(len.saturating_sub(1));
+    }
+
+    /// Change the search, or stop searching with `None`, and select the first item listed
+    async fn set_search(&self, search: Option<String>) {
+        *self.editing_search.write().await = search.is_some();
+        *self.search.write().await = search;
+        *self.selected.write().await = 0;
+    }
+
+    async fn edit_search(&self, edit: impl FnOnce(&mut String)) {
+        if let Some(query) = self.search.write().await.as_mut() {
+            edit(query);
+        }
+        *self.selected.write().await = 0;
+    }
+
+    pub async fn update_clipboard_source(&self, source: Option<String>) {
+        *self.clipboard_source.write().await = source;
+    }
+
+    pub async fn update_last_sync(&self, peers: Vec<PeerDelivery>) {
+        *self.last_sync.write().await = peers;
+    }
+
+    async fn move_selection(&self, down: bool) {
+        let len = self.listed_stack().await.len();
+        let mut selected = self.selected.write().await;
+        *selected = if down {
+            (*selected + 1).min(len.saturating_sub(1))
+        } else {
+            selected.saturating_sub(1)
+        };
+    }
+
+    /// Put the selected stack item on the clipboard; the daemon then syncs it as usual
+    async fn copy_selected(&self) -> Result<()> {
+        let selected = *self.selected.read().await;
+        let item = self.listed_stack().await.into_iter().nth(selected);
+        if let Some((_, item)) = item {
+            SystemClipboard::new()?.set_contents(&item).await?;
+        }
+        Ok(())
+    }
+
     pub async fn set_error(&self, error: String) {
         let mut status = self.status.write().await;
         *status = AppStatus::Error(error);
@@ -122,14 +231,43 @@ async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: Arc<App>) -> Resul
             if let Event::Key(key) = event::read()
                 .map_err(|e| PostError::Other(format!("Failed to read event: {}", e)))?
             {
-                if key.kind == KeyEventKind::Press {
+                if key.kind == KeyEventKind::Press && *app.editing_search.read().await {
                     match key.code {
+                        KeyCode::Char(c) => app.edit_search(|query| query.push(c)).await,
+                        KeyCode::Backspace => {
+                            app.edit_search(|query| {
+                                query.pop();
+                            })
+                            .await
+                        }
+                        KeyCode::Enter => *app.editing_search.write().await = false,
+                        KeyCode::Esc => app.set_search(None).await,
+                        KeyCode::Up => app.move_selection(false).await,
+                        KeyCode::Down => app.move_selection(true).await,
+                        _ => {}
+                    }
+                } else if key.kind == KeyEventKind::Press {
+                    let vim_keys = app.config.ui.vim_keys;
+                    match key.code {
+                        KeyCode::Char('/') => app.set_search(Some(String::new())).await,
+                        KeyCode::Esc if app.search.read().await.is_some() => {
+                            app.set_search(None).await
+                        }
                         KeyCode::Char('q') => break,
                         KeyCode::Esc => break,
                         KeyCode::Char('r') => {
                             let mut status = app.status.write().await;
                             *status = AppStatus::Connecting;
                         }
+                        KeyCode::Up => app.move_selection(false).await,
+                        KeyCode::Down => app.move_selection(true).await,
+                        KeyCode::Char('k') if vim_keys => app.move_selection(false).await,
+                        KeyCode::Char('j') if vim_keys => app.move_selection(true).await,
+                        KeyCode::Enter => {
+                            if let Err(e) = app.copy_selected().await {
+                                app.set_error(format!("Copy failed: {}", e)).await;
+                            }
+                        }
                         _ => {}
                     }
                 }
@@ -169,7 +307,7 @@ async fn draw_header(f: &mut Frame<'_>, area: Rect, app: &App) {
         AppStatus::Error(err) => (err.as_str(), Color::Red),
     };
 
-    let header = Paragraph::new(vec![Line::from(vec![
+    let mut spans = vec![
         Span::styled("Post Clipboard Sync - ", Style::default()),
         Span::styled(
             status_text,
@@ -177,8 +315,16 @@ async fn draw_header(f: &mut Frame<'_>, area: Rect, app: &App) {
                 .fg(status_color)
                 .add_modifier(Modifier::BOLD),
         ),
-    ])])
-    .block(Block::default().borders(Borders::ALL).title("Status"));
+    ];
+    if let Some(source) = &*app.clipboard_source.read().await {
+        spans.push(Span::styled(
+            format!(" | Clipboard from {}", source),
+            Style::default().fg(Color::Gray),
+        ));
+    }
+
+    let header = Paragraph::new(vec![Line::from(spans)])
+        .block(Block::default().borders(Borders::ALL).title("Status"));
 
     f.render_widget(header, area);
 }
@@ -189,15 +335,30 @@ async fn draw_main_content(f: &mut Frame<'_>, area: Rect, app: &App) {
         .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(area);
 
-    draw_nodes_list(f, chunks[0], app).await;
-    draw_clipboard_content(f, chunks[1], app).await;
+    let clipboard_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[1]);
+
+    let node_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(chunks[0]);
+
+    draw_nodes_list(f, node_chunks[0], app).await;
+    draw_last_sync(f, node_chunks[1], app).await;
+    draw_clipboard_content(f, clipboard_chunks[0], app).await;
+    draw_clipboard_stack(f, clipboard_chunks[1], app).await;
 }
 
 async fn draw_nodes_list(f: &mut Frame<'_>, area: Rect, app: &App) {
     let nodes = app.nodes.read().await;
-    let items: Vec<ListItem> = nodes
-        .iter()
-        .map(|(_id, node)| {
+    let mut sorted_nodes: Vec<_> = nodes.values().collect();
+    sorted_nodes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let items: Vec<ListItem> = sorted_nodes
+        .into_iter()
+        .map(|node| {
             let age = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap_or_default()
@@ -226,17 +387,40 @@ async fn draw_nodes_list(f: &mut Frame<'_>, area: Rect, app: &App) {
     f.render_widget(nodes_list, area);
 }
 
+async fn draw_last_sync(f: &mut Frame<'_>, area: Rect, app: &App) {
+    let peers = app.last_sync.read().await;
+    let items: Vec<ListItem> = peers
+        .iter()
+        .map(|peer| {
+            let color = match peer.state {
+                DeliveryState::Applied => Color::Green,
+                DeliveryState::Pending => Color::Yellow,
+                DeliveryState::Offline => Color::Gray,
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled("●", Style::default().fg(color)),
+                Span::raw(" "),
+                Span::raw(&peer.node_name),
+                Span::styled(
+                    format!(" ({})", peer.state.as_str()),
+                    Style::default().fg(Color::Gray),
+                ),
+            ]))
+        })
+        .collect();
+
+    let last_sync =
+        List::new(items).block(Block::default().borders(Borders::ALL).title("Last Sync"));
+
+    f.render_widget(last_sync, area);
+}
+
 async fn draw_clipboard_content(f: &mut Frame<'_>, area: Rect, app: &App) {
     let clipboard = app.last_clipboard.read().await;
     let content = if clipboard.is_empty() {
         "No clipboard content".to_string()
     } else {
-        let preview = if clipboard.len() > 500 {
-            format!("{}...", &clipboard[..500])
-        } else {
-            clipboard.clone()
-        };
-        preview
+        post_core::text::preview(&clipboard, 500)
     };
 
     let clipboard_widget = Paragraph::new(content)
@@ -250,9 +434,42 @@ async fn draw_clipboard_content(f: &mut Frame<'_>, area: Rect, app: &App) {
     f.render_widget(clipboard_widget, area);
 }
 
+async fn draw_clipboard_stack(f: &mut Frame<'_>, area: Rect, app: &App) {
+    let stack = app.listed_stack().await;
+    let items: Vec<ListItem> = stack
+        .iter()
+        .map(|(index, item)| {
+            let first_line = item.lines().next().unwrap_or_default();
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{} ", index), Style::default().fg(Color::Gray)),
+                Span::raw(first_line.to_string()),
+            ]))
+        })
+        .collect();
+
+    let mut state = ListState::default();
+    if !stack.is_empty() {
+        state.select(Some(*app.selected.read().await));
+    }
+
+    let title = match &*app.search.read().await {
+        Some(query) if *app.editing_search.read().await => format!("Search: {}_", query),
+        Some(query) => format!("Search: {} ({} found)", query, stack.len()),
+        None => "Clipboard Stack".to_string(),
+    };
+    let stack_list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    f.render_stateful_widget(stack_list, area, &mut state);
+}
+
 fn draw_footer(f: &mut Frame<'_>, area: Rect) {
-    let footer = Paragraph::new("Press 'q' or 'Esc' to quit, 'r' to reconnect")
-        .block(Block::default().borders(Borders::ALL).title("Controls"));
+    let footer = Paragraph::new(
+        "Press 'q' or 'Esc' to quit, 'r' to reconnect, '/' to search the stack, Enter to copy \
+         the selected stack item",
+    )
+    .block(Block::default().borders(Borders::ALL).title("Controls"));
 
     f.render_widget(footer, area);
 }
@@ -1,8 +1,12 @@
 use clap::{Parser, Subcommand};
+use post_core::import::{self, HistorySource};
+use post_core::selftest::StageOutcome;
 use post_core::*;
 use std::sync::Arc;
 use tracing::info;
 
+#[cfg(all(target_os = "macos", feature = "menubar"))]
+mod menubar;
 mod service;
 
 #[cfg(feature = "tui")]
@@ -19,6 +23,10 @@ struct Args {
     #[arg(short, long)]
     config: Option<String>,
 
+    /// Run as a separate profile, with its own config, data, ports and keys
+    #[arg(long)]
+    profile: Option<String>,
+
     #[arg(short, long)]
     verbose: bool,
 
@@ -29,15 +37,28 @@ struct Args {
 #[derive(Subcommand)]
 enum Commands {
     /// Show clipboard status and nodes
-    Status,
+    Status {
+        /// Show which peers have applied the latest update sent from here instead
+        #[arg(long)]
+        last_sync: bool,
+    },
 
     /// Get current clipboard content
-    Get,
+    Get {
+        /// Print the content exactly, without adding a trailing newline
+        #[arg(long)]
+        raw: bool,
+    },
 
-    /// Set clipboard content
+    /// Set clipboard content, read from stdin when neither content nor --file is given
     Set {
         /// Content to set
-        content: String,
+        #[arg(conflicts_with = "file")]
+        content: Option<String>,
+
+        /// Set the content of this file
+        #[arg(long)]
+        file: Option<std::path::PathBuf>,
     },
 
     /// Run the TUI interface
@@ -48,6 +69,18 @@ enum Commands {
     Daemon {
         #[arg(short, long)]
         foreground: bool,
+
+        /// Log what would be synced without sending or applying anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Return once the daemon reports it is ready to sync, failing if it doesn't
+        #[arg(long, conflicts_with = "foreground")]
+        wait_ready: bool,
+
+        /// Socket to report startup on, passed by the `post daemon` that spawned this one
+        #[arg(long, hide = true, requires = "foreground")]
+        ready_socket: Option<std::path::PathBuf>,
     },
 
     /// Stop the running daemon
@@ -62,12 +95,187 @@ enum Commands {
     /// Show daemon status (running/stopped)
     DaemonStatus,
 
+    /// Re-send node discovery and re-handshake with all peers
+    Rediscover,
+
+    /// Send a peer a signed ping and report the round trip and whether it verified us
+    Ping {
+        /// Peer ID or name, as listed by `post peers`
+        node: String,
+    },
+
+    /// Gather the clipboards of several peers and copy them, joined in the order given
+    Collect {
+        /// Peer IDs or names, as listed by `post peers`
+        #[arg(long, value_delimiter = ',', required = true)]
+        from: Vec<String>,
+        /// Put between the collected clipboards
+        #[arg(short, long, default_value = "\n")]
+        separator: String,
+        /// Print the result instead of copying (and syncing) it
+        #[arg(short, long)]
+        print: bool,
+    },
+
+    /// Time synthetic clipboard payloads of several sizes sent to a peer and answered by
+    /// it, reporting median and 95th percentile round trips and throughput
+    Bench {
+        /// Peer ID or name, as listed by `post peers`
+        node: String,
+        /// Payload sizes in bytes [default: 100,10000,100000,1000000]
+        #[arg(long, value_delimiter = ',')]
+        sizes: Vec<usize>,
+        /// Rounds per payload size
+        #[arg(short = 'n', long, default_value_t = post_daemon::api::DEFAULT_BENCH_ROUNDS)]
+        rounds: usize,
+    },
+
+    /// Run discovery, key exchange, encryption and a clipboard round trip against an
+    /// in-process peer, reporting which stages pass
+    Selftest,
+
+    /// Print the token HTTP API clients such as the browser extension must send, once the
+    /// daemon has generated it
+    ApiToken,
+
+    /// Print an item from the clipboard stack shared between devices
+    Paste {
+        /// Position in the stack; 0 is the most recent copy
+        #[arg(short, long, default_value = "0")]
+        index: usize,
+        /// Print the item pinned under this name instead
+        #[arg(long, conflicts_with = "index")]
+        pin: Option<String>,
+    },
+
+    /// Copy stdin to the clipboard, like macOS pbcopy; also run when invoked as `pbcopy`
+    Pbcopy {
+        /// Have the daemon sync it to peers right away instead of on its next poll
+        #[arg(short, long)]
+        sync: bool,
+    },
+
+    /// Print the clipboard exactly, like macOS pbpaste; also run when invoked as `pbpaste`
+    Pbpaste,
+
+    /// Copy a snippet from the config to the clipboard, or list them without a name
+    Snippet {
+        name: Option<String>,
+        /// Print the rendered snippet instead of copying (and syncing) it
+        #[arg(short, long)]
+        print: bool,
+    },
+
+    /// List and pin recent clipboard items
+    History {
+        #[command(subcommand)]
+        action: HistoryCommand,
+    },
+
+    /// Change the running daemon's log verbosity until it restarts
+    LogLevel {
+        /// A level (error, warn, info, debug, trace) or filters like `info,post_core::sync=trace`
+        filter: String,
+    },
+
+    /// List known peers and the ones being skipped after failed sends
+    Peers,
+
+    /// Show sync counters for each peer since the daemon started
+    Stats {
+        /// Refresh every few seconds until Ctrl+C
+        #[arg(short, long)]
+        watch: bool,
+    },
+
+    /// Stop syncing copies made here until `post resume`; content from peers still arrives
+    Pause,
+
+    /// Sync copies made here again after `post pause`
+    Resume,
+
+    /// Show sync state and the last clipboard source in the macOS menu bar
+    #[cfg(all(target_os = "macos", feature = "menubar"))]
+    Menubar,
+
+    /// Print a line for each sync event until Ctrl+C, e.g. to react to received content
+    Watch {
+        /// Print each event as a JSON object instead
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Pair a phone or other device that can't use Tailscale's local API, via a QR code
+    Pair {
+        /// URL the device reaches the API at, e.g. `http://100.64.0.1:8787`; defaults to
+        /// where this machine reaches it
+        #[arg(long)]
+        endpoint: Option<String>,
+        #[command(subcommand)]
+        action: Option<PairCommand>,
+    },
+
+    /// Save config, pins, clipboard history and paired devices to an encrypted file,
+    /// e.g. to move them to a new machine
+    Export {
+        /// Archive to write
+        path: std::path::PathBuf,
+    },
+
+    /// Restore what `post export` saved, or add another clipboard manager's history to
+    /// the clipboard stack; stop the daemon first
+    Import {
+        /// Archive written by `post export`, or the history file of the `--from` manager
+        path: std::path::PathBuf,
+        /// Clipboard manager the history comes from: clipman, copyq or maccy
+        #[arg(long)]
+        from: Option<HistorySource>,
+    },
+
+    /// Delete rotated logs and, while over `storage.max_size`, old clipboard history and logs
+    Clean {
+        /// Only show what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Inspect what Post keeps on disk
+    Storage {
+        #[command(subcommand)]
+        action: StorageCommand,
+    },
+
+    /// Manage the installed system service
+    Service {
+        #[command(subcommand)]
+        action: ServiceCommand,
+    },
+
     /// Install daemon as system service (boot startup)
     Install,
 
     /// Uninstall daemon system service
     Uninstall,
 
+    /// Show the audit log of API calls and of content synced or refused (`audit.enabled`)
+    Audit {
+        /// Only entries since an age such as 30m, 12h or 7d, or a date such as 2024-05-01
+        #[arg(long)]
+        since: Option<String>,
+        /// Only entries of this kind: api, received, sent or filtered
+        #[arg(long)]
+        kind: Option<post_daemon::audit::AuditKind>,
+        /// Only entries whose actor, an API caller or peer, contains this
+        #[arg(long)]
+        actor: Option<String>,
+        /// Most recent entries shown
+        #[arg(short = 'n', long, default_value = "50")]
+        lines: usize,
+        /// Print entries as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+
     /// Show daemon logs
     Logs {
         #[arg(short, long)]
@@ -76,63 +284,183 @@ enum Commands {
         lines: usize,
     },
 
-    /// Generate default configuration
-    Config,
+    /// Generate default configuration, or change one setting
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigCommand>,
+    },
+
+    /// Help with bug reports
+    Debug {
+        #[command(subcommand)]
+        action: DebugCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum DebugCommand {
+    /// Save the daemon's peers, encryption sessions, queues and config to a JSON file to
+    /// attach to a bug report; keys appear only as fingerprints and credentials are redacted
+    DumpState {
+        /// File to write; defaults to post-state-<unix time>.json here
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServiceCommand {
+    /// Restart the daemon through launchctl or systemctl
+    Restart,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Set a value in the config file, e.g. `post config set network.port 8413`
+    Set {
+        /// Dotted key such as `sync.dry_run` or `filters.max_length`
+        key: String,
+        /// TOML value such as `true`, `42` or `["a", "b"]`; anything else is taken as a string
+        value: String,
+        /// Restart the running daemon so it picks up the change
+        #[arg(short, long)]
+        restart: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StorageCommand {
+    /// Show where each file is kept and how much space it takes
+    Info,
+
+    /// Compact the state database, giving back space freed by trimmed history and the
+    /// write-ahead log; safe while the daemon runs
+    Vacuum,
+}
+
+#[derive(Subcommand)]
+enum PairCommand {
+    /// Show paired devices
+    List,
+
+    /// Stop accepting a paired device's token
+    Revoke { name: String },
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// Show the clipboard stack with the IDs used by `pin`
+    List {
+        /// Only show items containing every one of these words, newest first
+        #[arg(short, long)]
+        search: Option<String>,
+        /// Most items shown when searching
+        #[arg(long, default_value = "20", requires = "search")]
+        limit: usize,
+    },
+
+    /// Pin a stack item under a name on every device
+    Pin {
+        /// Stack position shown by `post history list`
+        id: usize,
+        /// Name to recall it by with `post paste --pin`
+        name: String,
+    },
+
+    /// Remove a pin on every device
+    Unpin { name: String },
+
+    /// Show pinned items
+    Pins,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
-
-    if args.verbose || args.foreground {
-        tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::DEBUG)
-            .init();
-    } else {
-        tracing_subscriber::fmt()
-            .with_max_level(tracing::Level::INFO)
-            .init();
+    let args = Args::parse_from(shell_alias_args(std::env::args_os()));
+    if let Some(profile) = &args.profile {
+        post_core::config::set_profile(profile)?;
     }
 
     // Handle config command first, before trying to load config
-    if let Some(Commands::Config) = args.command {
+    if let Some(Commands::Config { action: None }) = args.command {
         let config_path = PostConfig::config_path()?;
-        let config = PostConfig::default();
+        let config = PostConfig::profile_default();
         config.save().await?;
         println!("Generated default config at: {}", config_path.display());
         return Ok(());
     }
 
-    let config = if let Some(ref config_path) = args.config {
+    let config: PostConfig = if let Some(ref config_path) = args.config {
         let contents = tokio::fs::read_to_string(config_path).await?;
         toml::from_str(&contents)?
     } else {
         PostConfig::load().await?
     };
+    let verbose = args.verbose || args.foreground;
+    post_daemon::logging::init(&config.logging, verbose)?;
+    post_daemon::storage::migrate()?;
 
     match args.command {
-        Some(Commands::Status) => {
+        Some(Commands::Status { last_sync: true }) => show_last_sync(&config).await?,
+
+        Some(Commands::Status { last_sync: false }) => {
             println!("Post Clipboard Status");
 
             // Try the improved detection method first
-            match TailscaleTransport::new_with_detection(config.network.port).await {
+            match TailscaleTransport::new_with_detection(
+                config.network.port,
+                config.network.socket_path()?.as_deref(),
+            )
+            .await
+            {
                 Ok(transport) => {
+                    let transport = transport.with_ip_preference(config.network.ip_preference);
                     println!("Tailscale: Connected");
 
                     match transport.get_node_id().await {
-                        Ok(node_id) => println!("Node ID: {}", node_id),
+                        Ok(node_id) => println!("Node ID: {}", config.node.resolve_id(node_id)),
                         Err(e) => println!("Node ID: Failed to get ({:?})", e),
                     }
 
-                    match transport.get_tailnet_nodes().await {
-                        Ok(nodes) => {
-                            println!("Connected nodes: {}", nodes.len());
-                            for node in nodes {
-                                println!("  - {}", node);
+                    match config.node.display_name() {
+                        Some(node_name) => println!("Node name: {}", node_name),
+                        None => {
+                            if let Ok(node_name) = transport.get_node_name().await {
+                                println!("Node name: {}", node_name);
+                            }
+                        }
+                    }
+
+                    match transport.get_tailnet_peers().await {
+                        Ok(peers) => {
+                            println!("Connected nodes: {}", peers.len());
+                            for peer in peers {
+                                println!("  - {} ({})", peer.name, peer.address);
                             }
                         }
                         Err(e) => println!("Connected nodes: Failed to get ({:?})", e),
                     }
+
+                    // Only the running daemon knows where the clipboard came from and how
+                    // peer clocks compare
+                    if let (Ok(base_url), Ok(token)) = (
+                        post_daemon::api::client_base_url(&config).await,
+                        post_daemon::api::load_api_token().await,
+                    ) {
+                        if let Ok(status) = post_daemon::api::fetch_status(&base_url, &token).await
+                        {
+                            if let Some(source) = status.clipboard_source {
+                                println!("Clipboard from: {}", describe_source(&source));
+                            }
+                            for skew in status.clock_skew {
+                                println!(
+                                    "Warning: the clock on {} is {}; timestamps from it are unreliable",
+                                    skew.node_name,
+                                    describe_clock_skew(skew.skew_secs)
+                                );
+                            }
+                        }
+                    }
                 }
                 Err(e) => {
                     println!("Tailscale: Could not connect to daemon");
@@ -142,7 +470,10 @@ async fn main() -> Result<()> {
                     // Show what paths were tried for debugging
                     if args.verbose {
                         println!("\nDebugging information:");
-                        let paths = TailscaleTransport::get_possible_socket_paths();
+                        let paths = match config.network.socket_path().ok().flatten() {
+                            Some(path) => vec![path],
+                            None => TailscaleTransport::get_possible_socket_paths(),
+                        };
                         for path in paths {
                             let exists = std::path::Path::new(&path).exists();
                             println!("  Tried: {} (exists: {})", path, exists);
@@ -159,79 +490,130 @@ async fn main() -> Result<()> {
             }
         }
 
-        Some(Commands::Get) => {
+        Some(Commands::Get { raw }) => {
             let clipboard = SystemClipboard::new()?;
             let content = clipboard.get_contents().await?;
-            println!("{}", content);
+            if raw {
+                use std::io::Write;
+                let mut stdout = std::io::stdout();
+                stdout.write_all(content.as_bytes())?;
+                stdout.flush()?;
+            } else {
+                println!("{}", content);
+            }
         }
 
-        Some(Commands::Set { content }) => {
+        Some(Commands::Set { content, file }) => {
+            let content = match (content, file) {
+                (Some(content), _) => content,
+                (None, Some(file)) => tokio::fs::read_to_string(&file).await.map_err(|e| {
+                    PostError::Other(format!("Failed to read {}: {}", file.display(), e))
+                })?,
+                (None, None) => {
+                    use tokio::io::AsyncReadExt;
+                    let mut content = String::new();
+                    tokio::io::stdin().read_to_string(&mut content).await?;
+                    content
+                }
+            };
             let clipboard = SystemClipboard::new()?;
             clipboard.set_contents(&content).await?;
-            println!("Clipboard updated");
+            eprintln!("Clipboard updated");
         }
 
         #[cfg(feature = "tui")]
         Some(Commands::Tui) => {
             let app = Arc::new(App::new(config));
-            run_tui(app).await?;
-        }
-
-        Some(Commands::Daemon { foreground }) => {
-            if !foreground {
-                #[cfg(target_os = "macos")]
-                {
-                    // On macOS, avoid fork() entirely by spawning a new process
-                    use std::env;
-                    use std::process::Command;
-
-                    let current_exe = env::current_exe().map_err(|e| {
-                        PostError::Other(format!("Failed to get current executable: {}", e))
-                    })?;
-
-                    let mut cmd = Command::new(&current_exe);
-                    cmd.arg("daemon").arg("--foreground"); // Start in foreground in the new process
 
-                    if let Some(ref config_path) = args.config {
-                        cmd.arg("--config").arg(config_path);
+            // Keep the stack pane current while the daemon is reachable
+            if let Ok(base_url) = post_daemon::api::client_base_url(&app.config).await {
+                let token = post_daemon::api::load_api_token().await?;
+                let stack_app = Arc::clone(&app);
+                tokio::spawn(async move {
+                    loop {
+                        if let Ok(stack) = post_daemon::api::fetch_stack(&base_url, &token).await {
+                            stack_app.update_stack(stack.items).await;
+                        }
+                        if let Ok(report) =
+                            post_daemon::api::fetch_last_sync(&base_url, &token).await
+                        {
+                            let peers = report
+                                .peers
+                                .into_iter()
+                                .map(|peer| PeerDelivery {
+                                    state: peer.state,
+                                    node_id: peer.node_id,
+                                    node_name: peer.node_name,
+                                })
+                                .collect();
+                            stack_app.update_last_sync(peers).await;
+                        }
+                        if let Ok(status) = post_daemon::api::fetch_status(&base_url, &token).await
+                        {
+                            let source = status.clipboard_source.as_ref().map(describe_source);
+                            stack_app.update_clipboard_source(source).await;
+                        }
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                     }
+                });
+            }
 
-                    if args.verbose {
-                        cmd.arg("--verbose");
-                    }
+            run_tui(app).await?;
+        }
 
-                    // Redirect stdout/stderr to log file
-                    let log_path = post_daemon::get_log_file_path()?;
-                    let log_file = std::fs::OpenOptions::new()
-                        .create(true)
-                        .append(true)
-                        .open(&log_path)
-                        .map_err(PostError::Io)?;
+        Some(Commands::Daemon {
+            foreground,
+            dry_run,
+            wait_ready,
+            ready_socket,
+        }) => {
+            let mut config = config;
+            config.sync.dry_run = dry_run;
 
-                    cmd.stdout(log_file.try_clone().map_err(PostError::Io)?)
-                        .stderr(log_file)
-                        .stdin(std::process::Stdio::null());
+            // Check before forking or spawning, where a failure would only reach the log
+            if let Some(pid) = post_daemon::is_daemon_running()? {
+                return Err(PostError::Other(format!(
+                    "Post daemon is already running (PID: {}); use `post stop` or `post restart`",
+                    pid
+                )));
+            }
 
-                    let child = cmd.spawn().map_err(|e| {
-                        PostError::Other(format!("Failed to spawn daemon process: {}", e))
-                    })?;
+            if !foreground {
+                // Known up front, so a disabled API fails before anything is started
+                let ready_url = match wait_ready {
+                    true => Some(post_daemon::api::client_base_url(&config).await?),
+                    false => None,
+                };
 
-                    println!("Daemon started with PID: {}", child.id());
+                #[cfg(target_os = "macos")]
+                {
+                    let pid = spawn_daemon(args.config.as_deref(), args.verbose, dry_run).await?;
+                    if let Some(base_url) = ready_url {
+                        wait_until_ready(&base_url, || false).await?;
+                    }
+                    println!("Daemon started with PID: {}", pid);
                     return Ok(());
                 }
 
                 #[cfg(not(target_os = "macos"))]
                 {
-                    post_daemon::daemonize().await?;
-                    let daemon = post_daemon::Daemon::new(config).await?;
-                    daemon.run().await?;
+                    if let Some(pid) = post_daemon::detach().await? {
+                        if let Some(base_url) = ready_url {
+                            wait_until_ready(&base_url, || {
+                                post_daemon::detached_daemon_exited(pid)
+                            })
+                            .await?;
+                            println!("Daemon started with PID: {}", pid);
+                        }
+                        return Ok(());
+                    }
+                    post_daemon::crash::install_panic_hook(&config.logging);
+                    run_daemon(post_daemon::Daemon::new(config), args.config, verbose).await?;
                 }
             } else {
-                // Even in foreground mode, write PID file for status checking
-                post_daemon::write_pid_file()?;
                 info!("Running daemon in foreground mode");
-                let daemon = post_daemon::Daemon::new(config).await?;
-                daemon.run().await?;
+                post_daemon::crash::install_panic_hook(&config.logging);
+                run_daemon(start_daemon(config, ready_socket), args.config, verbose).await?;
             }
         }
 
@@ -269,6 +651,13 @@ async fn main() -> Result<()> {
         }
 
         Some(Commands::Restart { foreground }) => {
+            // A service manager would respawn the daemon we stop, so let it do the restart
+            if !foreground && service::is_service_installed() {
+                service::restart_service().await?;
+                println!("Service restarted");
+                return Ok(());
+            }
+
             // Stop the daemon first
             if let Some(pid) = post_daemon::is_daemon_running()? {
                 #[cfg(unix)]
@@ -285,61 +674,32 @@ async fn main() -> Result<()> {
                 println!("Stopped existing daemon (PID: {})", pid);
             }
 
+            if let Some(pid) = post_daemon::is_daemon_running()? {
+                return Err(PostError::Other(format!(
+                    "Existing daemon (PID: {}) has not exited yet; try again shortly",
+                    pid
+                )));
+            }
+
             // Start new daemon directly in this process
             if !foreground {
                 #[cfg(target_os = "macos")]
                 {
-                    // On macOS, avoid fork() entirely by spawning a new process
-                    use std::env;
-                    use std::process::Command;
-
-                    let current_exe = env::current_exe().map_err(|e| {
-                        PostError::Other(format!("Failed to get current executable: {}", e))
-                    })?;
-
-                    let mut cmd = Command::new(&current_exe);
-                    cmd.arg("daemon").arg("--foreground"); // Start in foreground in the new process
-
-                    if let Some(ref config_path) = args.config {
-                        cmd.arg("--config").arg(config_path);
-                    }
-
-                    if args.verbose {
-                        cmd.arg("--verbose");
-                    }
-
-                    // Redirect stdout/stderr to log file
-                    let log_path = post_daemon::get_log_file_path()?;
-                    let log_file = std::fs::OpenOptions::new()
-                        .create(true)
-                        .append(true)
-                        .open(&log_path)
-                        .map_err(PostError::Io)?;
-
-                    cmd.stdout(log_file.try_clone().map_err(PostError::Io)?)
-                        .stderr(log_file)
-                        .stdin(std::process::Stdio::null());
-
-                    let child = cmd.spawn().map_err(|e| {
-                        PostError::Other(format!("Failed to spawn daemon process: {}", e))
-                    })?;
-
-                    println!("Daemon restarted with PID: {}", child.id());
+                    let pid = spawn_daemon(args.config.as_deref(), args.verbose, false).await?;
+                    println!("Daemon restarted with PID: {}", pid);
                     return Ok(());
                 }
 
                 #[cfg(not(target_os = "macos"))]
                 {
                     post_daemon::daemonize().await?;
-                    let daemon = post_daemon::Daemon::new(config).await?;
-                    daemon.run().await?;
+                    post_daemon::crash::install_panic_hook(&config.logging);
+                    run_daemon(post_daemon::Daemon::new(config), args.config, verbose).await?;
                 }
             } else {
-                // Even in foreground mode, write PID file for status checking
-                post_daemon::write_pid_file()?;
                 println!("Starting daemon in foreground...");
-                let daemon = post_daemon::Daemon::new(config).await?;
-                daemon.run().await?;
+                post_daemon::crash::install_panic_hook(&config.logging);
+                run_daemon(start_daemon(config, None), args.config, verbose).await?;
             }
         }
 
@@ -358,6 +718,340 @@ async fn main() -> Result<()> {
                     println!("Daemon is not running");
                 }
             }
+
+            let marker = post_daemon::crash::get_crash_marker_path()?;
+            if let Some(report) = post_daemon::crash::read_crash_marker(&marker) {
+                println!(
+                    "Last panic (unix time {}): {}; the log file has the backtrace",
+                    report.time,
+                    report.summary()
+                );
+            }
+        }
+
+        Some(Commands::Ping { node }) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            let reply = post_daemon::api::request_ping(&base_url, &token, &node).await?;
+            println!("Reply from {} in {:.1} ms", node, reply.round_trip_ms);
+            if reply.verified {
+                println!("{} verified this node's signature", node);
+            } else {
+                println!(
+                    "{} could NOT verify this node's signature, so it rejects updates from here; \
+                     it may have missed this node's discovery or hold an older key for it",
+                    node
+                );
+            }
+        }
+
+        Some(Commands::Collect {
+            from,
+            separator,
+            print,
+        }) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            let replies = futures_util::future::join_all(
+                from.iter()
+                    .map(|node| post_daemon::api::request_collect(&base_url, &token, node)),
+            )
+            .await;
+
+            let mut collected = Vec::new();
+            for (node, reply) in from.iter().zip(replies) {
+                match reply {
+                    Ok(reply) => collected.push(reply.content),
+                    Err(e) => eprintln!("Skipped {}: {}", node, e),
+                }
+            }
+            if collected.is_empty() {
+                return Err(PostError::Other("No peer shared its clipboard".to_string()));
+            }
+            let joined = collected.join(&separator);
+
+            if print {
+                println!("{}", joined);
+            } else {
+                // The daemon picks this up and syncs it like any other copy
+                SystemClipboard::new()?.set_contents(&joined).await?;
+                println!("Copied the clipboards of {} peer(s)", collected.len());
+            }
+        }
+
+        Some(Commands::Bench {
+            node,
+            sizes,
+            rounds,
+        }) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            println!(
+                "Benchmarking sync with {}, {} rounds per size",
+                node, rounds
+            );
+            let request = post_daemon::api::BenchRequest {
+                sizes,
+                rounds: Some(rounds),
+            };
+            let results =
+                post_daemon::api::request_bench(&base_url, &token, &node, &request).await?;
+
+            println!(
+                "{:>10}  {:>10}  {:>10}  {:>12}  {:>5}",
+                "SIZE", "P50", "P95", "THROUGHPUT", "LOST"
+            );
+            for result in &results {
+                if result.answered == 0 {
+                    println!(
+                        "{:>10}  {:>10}  {:>10}  {:>12}  {:>5}",
+                        format_bytes(result.size as u64),
+                        "-",
+                        "-",
+                        "-",
+                        result.lost
+                    );
+                    continue;
+                }
+                println!(
+                    "{:>10}  {:>7.1} ms  {:>7.1} ms  {:>10}/s  {:>5}",
+                    format_bytes(result.size as u64),
+                    result.p50_ms,
+                    result.p95_ms,
+                    format_bytes(result.throughput_bytes_per_sec as u64),
+                    result.lost
+                );
+            }
+        }
+
+        Some(Commands::Selftest) => {
+            let results = post_core::selftest::run().await;
+            for result in &results {
+                match &result.outcome {
+                    StageOutcome::Passed => println!(
+                        "PASS  {} ({:.1} ms)",
+                        result.name,
+                        result.elapsed.as_secs_f64() * 1000.0
+                    ),
+                    StageOutcome::Failed(error) => {
+                        println!("FAIL  {}: {}", result.name, error)
+                    }
+                    StageOutcome::Skipped => println!("SKIP  {}", result.name),
+                }
+            }
+            if results
+                .iter()
+                .any(|result| result.outcome != StageOutcome::Passed)
+            {
+                return Err(PostError::Other("Self-test failed".to_string()));
+            }
+            println!("All stages passed");
+        }
+
+        Some(Commands::Rediscover) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            let response = post_daemon::api::request_rediscovery(&base_url, &token).await?;
+            println!(
+                "Rediscovery started; forgot {} peer(s), waiting for them to announce themselves",
+                response.forgotten_peers
+            );
+        }
+
+        Some(Commands::ApiToken) => {
+            println!("{}", post_daemon::api::load_api_token().await?);
+        }
+
+        Some(Commands::Paste {
+            pin: Some(name), ..
+        }) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            let pins = post_daemon::api::fetch_pins(&base_url, &token).await?;
+            match pins.pins.into_iter().find(|pin| pin.name == name) {
+                Some(pin) => println!("{}", pin.content),
+                None => return Err(PostError::Other(format!("Nothing is pinned as {}", name))),
+            }
+        }
+
+        Some(Commands::Paste { index, pin: None }) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            let stack = post_daemon::api::fetch_stack(&base_url, &token).await?;
+            match stack.items.get(index) {
+                Some(item) => println!("{}", item),
+                None => {
+                    return Err(PostError::Other(format!(
+                        "No item {} in the clipboard stack ({} item(s))",
+                        index,
+                        stack.items.len()
+                    )))
+                }
+            }
+        }
+
+        Some(Commands::Snippet { name: None, .. }) => {
+            if config.snippets.is_empty() {
+                println!("No snippets configured; add them under [snippets] in the config file");
+            }
+            for (name, template) in &config.snippets {
+                println!(
+                    "{:<20} {}",
+                    name,
+                    template.lines().next().unwrap_or_default()
+                );
+            }
+        }
+
+        Some(Commands::Snippet {
+            name: Some(name),
+            print,
+        }) => {
+            let template = config
+                .snippets
+                .get(&name)
+                .ok_or_else(|| PostError::Config(format!("No snippet named {}", name)))?;
+            let rendered = snippets::render(template, &snippets::SnippetContext::current())?;
+
+            if print {
+                println!("{}", rendered);
+            } else {
+                // The daemon picks this up and syncs it like any other copy
+                SystemClipboard::new()?.set_contents(&rendered).await?;
+                println!("Copied snippet {}", name);
+            }
+        }
+
+        Some(Commands::Pbcopy { sync }) => {
+            let mut content = String::new();
+            tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut content).await?;
+            SystemClipboard::new()?.set_contents(&content).await?;
+
+            // Content the daemon would not sync stays on this device
+            if let Err(e) = config.filters.check(&content) {
+                eprintln!("Copied, but not synced: {}", e);
+            } else if sync {
+                let base_url = post_daemon::api::client_base_url(&config).await?;
+                let token = post_daemon::api::load_api_token().await?;
+                if let Err(e) = post_daemon::api::request_push(&base_url, &token, content).await {
+                    eprintln!("Copied, but not synced: {}", e);
+                }
+            }
+        }
+
+        Some(Commands::Pbpaste) => {
+            use std::io::Write;
+            let content = SystemClipboard::new()?.get_contents().await?;
+            let mut stdout = std::io::stdout();
+            stdout.write_all(content.as_bytes())?;
+            stdout.flush()?;
+        }
+
+        Some(Commands::History { action }) => {
+            run_history_command(&config, action).await?;
+        }
+
+        Some(Commands::LogLevel { filter }) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            let applied = post_daemon::api::request_log_level(&base_url, &token, &filter).await?;
+            println!("Daemon log filter set to {}", applied.filter);
+        }
+
+        Some(Commands::Peers) => {
+            show_peers(&config).await?;
+        }
+
+        Some(Commands::Stats { watch }) => {
+            show_stats(&config, watch).await?;
+        }
+
+        Some(command @ (Commands::Pause | Commands::Resume)) => {
+            let paused = matches!(command, Commands::Pause);
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            post_daemon::api::request_paused(&base_url, &token, paused).await?;
+            println!("Sync {}", if paused { "paused" } else { "resumed" });
+        }
+
+        #[cfg(all(target_os = "macos", feature = "menubar"))]
+        Some(Commands::Menubar) => {
+            menubar::run(config)?;
+        }
+
+        Some(Commands::Watch { json }) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            let print_event = |line: &str| {
+                if json {
+                    println!("{}", line);
+                } else if let Ok(event) = serde_json::from_str::<SyncEvent>(line) {
+                    println!("{}", describe_event(&event));
+                }
+            };
+
+            tokio::select! {
+                result = post_daemon::api::watch_events(&base_url, &token, print_event) => {
+                    result?;
+                    eprintln!("The daemon stopped");
+                }
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+
+        Some(Commands::Pair { endpoint, action }) => {
+            run_pair_command(&config, endpoint, action).await?;
+        }
+
+        Some(Commands::Export { path }) => {
+            export_archive(args.config.as_deref(), &path).await?;
+        }
+
+        Some(Commands::Import { path, from: None }) => {
+            import_archive(args.config.as_deref(), &path).await?;
+        }
+
+        Some(Commands::Import {
+            path,
+            from: Some(source),
+        }) => {
+            import_history(&config, source, &path)?;
+        }
+
+        Some(Commands::Clean { dry_run }) => {
+            // The daemon has to trim its own clipboard stack, or it would write it back
+            let cleanup = if post_daemon::is_daemon_running()?.is_some() {
+                let base_url = post_daemon::api::client_base_url(&config).await?;
+                let token = post_daemon::api::load_api_token().await?;
+                post_daemon::api::request_cleanup(&base_url, &token, dry_run).await?
+            } else {
+                post_daemon::storage::clean(config.storage.max_size, None, dry_run).await?
+            };
+            print_cleanup(&cleanup, dry_run);
+        }
+
+        Some(Commands::Storage {
+            action: StorageCommand::Info,
+        }) => {
+            show_storage_info(&config)?;
+        }
+
+        Some(Commands::Storage {
+            action: StorageCommand::Vacuum,
+        }) => {
+            let (before, after) = post_daemon::storage::vacuum()?;
+            println!(
+                "Compacted the state database from {} to {}",
+                format_bytes(before),
+                format_bytes(after)
+            );
+        }
+
+        Some(Commands::Service {
+            action: ServiceCommand::Restart,
+        }) => {
+            service::restart_service().await?;
+            println!("Service restarted");
         }
 
         Some(Commands::Install) => {
@@ -368,11 +1062,62 @@ async fn main() -> Result<()> {
             service::uninstall_service().await?;
         }
 
+        Some(Commands::Audit {
+            since,
+            kind,
+            actor,
+            lines,
+            json,
+        }) => {
+            show_audit_log(&config, since.as_deref(), kind, actor, lines, json)?;
+        }
+
         Some(Commands::Logs { follow, lines }) => {
             show_logs(follow, lines).await?;
         }
 
-        Some(Commands::Config) => {
+        Some(Commands::Debug {
+            action: DebugCommand::DumpState { output },
+        }) => {
+            let base_url = post_daemon::api::client_base_url(&config).await?;
+            let token = post_daemon::api::load_api_token().await?;
+            let state = post_daemon::api::fetch_debug_state(&base_url, &token).await?;
+            let path =
+                output.unwrap_or_else(|| format!("post-state-{}.json", state.generated_at).into());
+            let json = serde_json::to_string_pretty(&state)
+                .map_err(|e| PostError::Serialization(e.to_string()))?;
+            std::fs::write(&path, json).map_err(PostError::Io)?;
+            println!(
+                "Wrote the daemon state to {}; review it before sharing, as it names your devices",
+                path.display()
+            );
+        }
+
+        Some(Commands::Config {
+            action:
+                Some(ConfigCommand::Set {
+                    key,
+                    value,
+                    restart,
+                }),
+        }) => {
+            let updated = config.with_value(&key, &value)?;
+            match args.config {
+                Some(ref config_path) => {
+                    tokio::fs::write(config_path, updated.to_toml()?).await?;
+                }
+                None => updated.save().await?,
+            }
+            println!("Set {} = {}", key, value);
+
+            if restart {
+                restart_for_config_change(args.config.as_deref()).await?;
+            } else if post_daemon::is_daemon_running()?.is_some() {
+                println!("The running daemon keeps its old settings until restarted (`post config set --restart` or `post restart`)");
+            }
+        }
+
+        Some(Commands::Config { action: None }) => {
             // This is handled earlier in main() before config loading
             unreachable!("Config command should be handled before this match")
         }
@@ -388,6 +1133,837 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Restart the daemon, if running, so it rereads the config
+async fn restart_for_config_change(config_path: Option<&str>) -> Result<()> {
+    if service::is_service_installed() {
+        service::restart_service().await?;
+        println!("Service restarted");
+        return Ok(());
+    }
+    if post_daemon::is_daemon_running()?.is_none() {
+        println!("Daemon is not running; the change applies when it next starts");
+        return Ok(());
+    }
+
+    // `post restart` detaches the new daemon, so run it as its own process
+    let current_exe = std::env::current_exe()
+        .map_err(|e| PostError::Other(format!("Failed to get current executable: {}", e)))?;
+    let mut cmd = tokio::process::Command::new(current_exe);
+    if let Some(profile) = post_core::config::profile() {
+        cmd.arg("--profile").arg(profile);
+    }
+    cmd.arg("restart");
+    if let Some(config_path) = config_path {
+        cmd.arg("--config").arg(config_path);
+    }
+    let status = cmd.status().await?;
+    if !status.success() {
+        return Err(PostError::Other(format!(
+            "Failed to restart the daemon ({})",
+            status
+        )));
+    }
+    Ok(())
+}
+
+async fn run_history_command(config: &PostConfig, action: HistoryCommand) -> Result<()> {
+    let base_url = post_daemon::api::client_base_url(config).await?;
+    let token = post_daemon::api::load_api_token().await?;
+
+    match action {
+        HistoryCommand::List { search: None, .. } => {
+            let stack = post_daemon::api::fetch_stack(&base_url, &token).await?;
+            for (id, item) in stack.items.iter().enumerate() {
+                println!("{:>3}  {}", id, item.lines().next().unwrap_or_default());
+            }
+        }
+        HistoryCommand::List {
+            search: Some(query),
+            limit,
+        } => {
+            let found = post_daemon::api::search_stack(&base_url, &token, &query, limit).await?;
+            if found.matches.is_empty() {
+                println!("Nothing in the clipboard stack matches {}", query);
+            }
+            for item in found.matches {
+                println!(
+                    "{:>3}  {}",
+                    item.index,
+                    item.content.lines().next().unwrap_or_default()
+                );
+            }
+        }
+        HistoryCommand::Pin { id, name } => {
+            let pin = post_daemon::api::PinRequest {
+                name,
+                index: Some(id),
+                content: None,
+            };
+            let pinned = post_daemon::api::request_pin(&base_url, &token, &pin).await?;
+            println!("Pinned item {} as {}", id, pinned.name);
+        }
+        HistoryCommand::Unpin { name } => {
+            if post_daemon::api::request_unpin(&base_url, &token, &name)
+                .await?
+                .removed
+            {
+                println!("Unpinned {}", name);
+            } else {
+                println!("Nothing is pinned as {}", name);
+            }
+        }
+        HistoryCommand::Pins => {
+            for pin in post_daemon::api::fetch_pins(&base_url, &token).await?.pins {
+                println!(
+                    "{:<20} {}",
+                    pin.name,
+                    pin.content.lines().next().unwrap_or_default()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_pair_command(
+    config: &PostConfig,
+    endpoint: Option<String>,
+    action: Option<PairCommand>,
+) -> Result<()> {
+    let base_url = post_daemon::api::client_base_url(config).await?;
+    let token = post_daemon::api::load_api_token().await?;
+
+    match action {
+        None => {
+            let endpoint = endpoint.unwrap_or_else(|| base_url.clone());
+            if is_loopback_endpoint(&endpoint) {
+                eprintln!(
+                    "Warning: {} is only reachable from this machine; bind the API to a tailnet address (api.bind) and pass --endpoint",
+                    endpoint
+                );
+            }
+            let offer = post_daemon::api::request_pairing(&base_url, &token, &endpoint).await?;
+            let qr = qrcode::QrCode::new(offer.uri.as_bytes())
+                .map_err(|e| PostError::Other(format!("Failed to encode QR code: {}", e)))?;
+            // Inverted, since terminals are usually dark
+            let image = qr
+                .render::<qrcode::render::unicode::Dense1x2>()
+                .dark_color(qrcode::render::unicode::Dense1x2::Light)
+                .light_color(qrcode::render::unicode::Dense1x2::Dark)
+                .build();
+
+            println!("{}", image);
+            println!("Scan with the Post app, or enter:");
+            println!("  Endpoint:    {}", offer.endpoint);
+            println!("  Code:        {}", offer.code);
+            println!("  Fingerprint: {}", offer.fingerprint);
+            println!("The code works once, for {} minutes", offer.expires_in / 60);
+        }
+        Some(PairCommand::List) => {
+            let devices = post_daemon::api::fetch_paired_devices(&base_url, &token)
+                .await?
+                .devices;
+            if devices.is_empty() {
+                println!("No paired devices");
+            }
+            for device in devices {
+                println!("{:<24} paired at {}", device.name, device.paired_at);
+            }
+        }
+        Some(PairCommand::Revoke { name }) => {
+            if post_daemon::api::request_revoke_device(&base_url, &token, &name)
+                .await?
+                .revoked
+            {
+                println!("Revoked {}", name);
+            } else {
+                println!("No device is paired as {}", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `endpoint`'s host is this machine only, e.g. `http://127.0.0.1:8787`
+fn is_loopback_endpoint(endpoint: &str) -> bool {
+    let authority = endpoint
+        .split("://")
+        .nth(1)
+        .unwrap_or(endpoint)
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    let host = match authority.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => authority.split(':').next().unwrap_or_default(),
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Command-line arguments with `pbcopy`/`pbpaste` inserted as the subcommand when the
+/// binary is invoked under that name, e.g. through a symlink
+fn shell_alias_args(args: impl Iterator<Item = std::ffi::OsString>) -> Vec<std::ffi::OsString> {
+    let mut args: Vec<_> = args.collect();
+    let alias = args
+        .first()
+        .and_then(|program| std::path::Path::new(program).file_stem())
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| matches!(*stem, "pbcopy" | "pbpaste"))
+        .map(std::ffi::OsString::from);
+    if let Some(alias) = alias {
+        args[0] = "post".into();
+        args.insert(1, alias);
+    }
+    args
+}
+
+/// Files `post export` saves, by their name in the archive
+fn archive_files(config_path: Option<&str>) -> Result<Vec<(&'static str, std::path::PathBuf)>> {
+    let config_path = match config_path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => PostConfig::config_path()?,
+    };
+    Ok(vec![
+        ("config.toml", config_path),
+        (
+            "paired_devices.json",
+            post_daemon::api::paired_devices_path()?,
+        ),
+    ])
+}
+
+/// State `post export` saves from the state database, by its name in the archive; the
+/// names are those of the files it was kept in before
+const ARCHIVED_STATE: [(&str, StateKey); 2] = [
+    ("pins.json", StateKey::Pins),
+    ("history.json", StateKey::History),
+];
+
+async fn export_archive(config_path: Option<&str>, path: &std::path::Path) -> Result<()> {
+    let mut contents = archive::Archive::new();
+    for (name, file) in archive_files(config_path)? {
+        match tokio::fs::read_to_string(&file).await {
+            Ok(data) => {
+                contents.files.insert(name.to_string(), data);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    // Only read the state database; exporting before the daemon ever ran mustn't create it
+    if let Some(store) = StateStore::open_existing(&post_daemon::get_state_db_path()?)? {
+        for (name, key) in ARCHIVED_STATE {
+            if let Some(json) = store.get(key)? {
+                contents.files.insert(name.to_string(), json);
+            }
+        }
+    }
+    if contents.files.is_empty() {
+        return Err(PostError::Other(
+            "There is nothing to export yet".to_string(),
+        ));
+    }
+
+    let sealed = archive::seal(&contents, &archive_passphrase(true)?)?;
+    tokio::fs::write(path, sealed).await?;
+    let names: Vec<&str> = contents.files.keys().map(String::as_str).collect();
+    println!("Exported {} to {}", names.join(", "), path.display());
+    Ok(())
+}
+
+async fn import_archive(config_path: Option<&str>, path: &std::path::Path) -> Result<()> {
+    if post_daemon::is_daemon_running()?.is_some() {
+        return Err(PostError::Other(
+            "Stop the daemon (`post stop`) before importing, or it will overwrite the imported pins and history"
+                .to_string(),
+        ));
+    }
+
+    let sealed = tokio::fs::read(path).await?;
+    let contents = archive::open(&sealed, &archive_passphrase(false)?)?;
+    if let Some(config) = contents.files.get("config.toml") {
+        toml::from_str::<PostConfig>(config)?;
+    }
+    for (name, _) in ARCHIVED_STATE {
+        if let Some(json) = contents.files.get(name) {
+            serde_json::from_str::<serde_json::Value>(json).map_err(|e| {
+                PostError::Serialization(format!("Invalid {} in the archive: {}", name, e))
+            })?;
+        }
+    }
+
+    for (name, file) in archive_files(config_path)? {
+        let Some(data) = contents.files.get(name) else {
+            continue;
+        };
+        if let Some(dir) = file.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&file, data).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o600))?;
+        }
+        println!("Restored {}", file.display());
+    }
+
+    let store = StateStore::open(&post_daemon::get_state_db_path()?)?;
+    for (name, key) in ARCHIVED_STATE {
+        let Some(json) = contents.files.get(name) else {
+            continue;
+        };
+        store.put(key, json)?;
+        println!("Restored {} into {}", key, store.path().display());
+    }
+    Ok(())
+}
+
+/// Add the history another clipboard manager kept at `path` behind the clipboard stack
+fn import_history(
+    config: &PostConfig,
+    source: HistorySource,
+    path: &std::path::Path,
+) -> Result<()> {
+    if post_daemon::is_daemon_running()?.is_some() {
+        return Err(PostError::Other(
+            "Stop the daemon (`post stop`) before importing, or it will overwrite the imported history"
+                .to_string(),
+        ));
+    }
+
+    let imported = source.read(path)?;
+    let store = StateStore::open(&post_daemon::get_state_db_path()?)?;
+    let history: Vec<String> = store.load(StateKey::History)?.unwrap_or_default();
+    let before = history.len();
+    let limit = config.sync.stack_size.max(1);
+    let merged = import::merge(history, imported.clone(), limit);
+    let added = merged.len() - before.min(merged.len());
+    store.save(StateKey::History, &merged)?;
+
+    println!(
+        "Imported {} of {} items from {} into {}",
+        added,
+        imported.len(),
+        source,
+        store.path().display()
+    );
+    if merged.len() == limit && added < imported.len() {
+        println!(
+            "The clipboard stack keeps {} items; raise sync.stack_size to import more",
+            limit
+        );
+    }
+    if !config.sync.persist_history {
+        println!("sync.persist_history is off, so the daemon won't load the imported history");
+    }
+    Ok(())
+}
+
+/// Passphrase from `POST_ARCHIVE_PASSPHRASE`, or asked for on the terminal
+fn archive_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("POST_ARCHIVE_PASSPHRASE") {
+        if passphrase.is_empty() {
+            return Err(PostError::Other(
+                "POST_ARCHIVE_PASSPHRASE can't be empty".to_string(),
+            ));
+        }
+        return Ok(passphrase);
+    }
+
+    let prompt = |text: &str| {
+        rpassword::prompt_password(text)
+            .map_err(|e| PostError::Other(format!("Failed to read passphrase: {}", e)))
+    };
+    let passphrase = prompt("Archive passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(PostError::Other(
+            "The passphrase can't be empty".to_string(),
+        ));
+    }
+    if confirm && prompt("Repeat passphrase: ")? != passphrase {
+        return Err(PostError::Other("The passphrases don't match".to_string()));
+    }
+    Ok(passphrase)
+}
+
+/// One line describing `event`, with the content's line breaks escaped
+fn describe_event(event: &SyncEvent) -> String {
+    let one_line = |content: &str| content.replace('\r', "\\r").replace('\n', "\\n");
+    match event {
+        SyncEvent::Received {
+            from_name, content, ..
+        } => format!("received from {}: {}", from_name, one_line(content)),
+        SyncEvent::Sent { content, peers, .. } => {
+            format!("sent to {} peer(s): {}", peers, one_line(content))
+        }
+        SyncEvent::PeerDiscovered { id, name } => format!("discovered {} ({})", name, id),
+        SyncEvent::PeerOffline { id, name, .. } => format!("lost {} ({})", name, id),
+        SyncEvent::TailnetPeerOnline { address, name } => {
+            format!("{} ({}) came online", name, address)
+        }
+        SyncEvent::TailnetPeerOffline { address, name } => {
+            format!("{} ({}) went offline", name, address)
+        }
+        SyncEvent::Connected { node_id } => format!("connected as {}", node_id),
+        SyncEvent::Disconnected => "disconnected".to_string(),
+        SyncEvent::ClipboardUnavailable { error } => format!("clipboard unavailable: {}", error),
+        SyncEvent::ClipboardRecovered => "clipboard available again".to_string(),
+        SyncEvent::Paused => "sync paused".to_string(),
+        SyncEvent::Resumed => "sync resumed".to_string(),
+        SyncEvent::ClockSkew {
+            node_name,
+            skew_secs,
+            ..
+        } => format!(
+            "clock on {} is {}",
+            node_name,
+            describe_clock_skew(*skew_secs)
+        ),
+        SyncEvent::Filtered {
+            from: Some(from),
+            reason,
+        } => format!("refused update from {}: {}", from, reason),
+        SyncEvent::Filtered { from: None, reason } => format!("not synced: {}", reason),
+    }
+}
+
+fn show_audit_log(
+    config: &PostConfig,
+    since: Option<&str>,
+    kind: Option<post_daemon::audit::AuditKind>,
+    actor: Option<String>,
+    lines: usize,
+    json: bool,
+) -> Result<()> {
+    use post_daemon::audit;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let query = audit::AuditQuery {
+        since: since
+            .map(|since| audit::parse_since(since, now))
+            .transpose()?,
+        kind,
+        actor,
+    };
+    let path = audit::audit_log_path(&config.audit)?;
+    let entries = audit::read(&path, &query)?;
+    if entries.is_empty() {
+        if !config.audit.enabled {
+            println!("The audit log is off; set audit.enabled = true to start it");
+        } else {
+            println!("No audit entries in {}", path.display());
+        }
+        return Ok(());
+    }
+
+    for entry in &entries[entries.len().saturating_sub(lines)..] {
+        if json {
+            let line = serde_json::to_string(entry)
+                .map_err(|e| PostError::Serialization(e.to_string()))?;
+            println!("{}", line);
+        } else {
+            println!(
+                "{}  {:<8}  {:<28}  {}",
+                audit::format_time(entry.timestamp),
+                entry.kind.as_str(),
+                entry.actor,
+                entry.action
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_cleanup(cleanup: &post_daemon::storage::Cleanup, dry_run: bool) {
+    let verb = if dry_run { "Would free" } else { "Freed" };
+    for log in &cleanup.rotated_logs {
+        println!("Rotated log: {}", log);
+    }
+    if cleanup.history_items > 0 {
+        println!("Oldest clipboard history items: {}", cleanup.history_items);
+    }
+    if cleanup.log_truncated {
+        println!("Current log: emptied");
+    }
+    println!(
+        "{} {} of {}",
+        verb,
+        format_bytes(cleanup.freed),
+        format_bytes(cleanup.size)
+    );
+}
+
+fn show_storage_info(config: &PostConfig) -> Result<()> {
+    use post_daemon::storage;
+
+    let data_dir = storage::data_dir()?;
+    println!(
+        "Data directory:   {} (layout {})",
+        data_dir.display(),
+        storage::layout_version(&data_dir)?
+    );
+    println!("Config directory: {}", PostConfig::config_dir()?.display());
+    println!();
+
+    for (label, path) in storage::known_paths(config)? {
+        let size = if path.exists() {
+            format_bytes(storage::disk_usage(&path))
+        } else {
+            "-".to_string()
+        };
+        println!("{:<20} {:>10}  {}", label, size, path.display());
+    }
+    println!();
+    println!(
+        "{:<20} {:>10}",
+        "Data directory",
+        format_bytes(storage::disk_usage(&data_dir))
+    );
+    Ok(())
+}
+
+async fn show_last_sync(config: &PostConfig) -> Result<()> {
+    let base_url = post_daemon::api::client_base_url(config).await?;
+    let token = post_daemon::api::load_api_token().await?;
+    let report = post_daemon::api::fetch_last_sync(&base_url, &token).await?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    println!(
+        "Last update sent {}s ago (sequence {})",
+        now.saturating_sub(report.timestamp),
+        report.sequence
+    );
+    if report.peers.is_empty() {
+        println!("No peers were known when it was sent");
+        return Ok(());
+    }
+    println!("{:<28} STATE", "PEER");
+    for peer in &report.peers {
+        println!("{:<28} {}", peer.node_name, peer.state.as_str());
+    }
+    Ok(())
+}
+
+async fn show_peers(config: &PostConfig) -> Result<()> {
+    let base_url = post_daemon::api::client_base_url(config).await?;
+    let token = post_daemon::api::load_api_token().await?;
+    let peers = post_daemon::api::fetch_peers(&base_url, &token).await?;
+    let circuits = post_daemon::api::fetch_peer_circuits(&base_url, &token).await?;
+    let offline = post_daemon::api::fetch_offline_peers(&base_url, &token).await?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    if peers.is_empty() {
+        println!("No peers discovered yet");
+    } else {
+        println!(
+            "{:<28} {:<12} {:>10}  STATUS",
+            "PEER", "VERSION", "LAST SEEN"
+        );
+        for peer in &peers {
+            let status = if !peer.compatible {
+                "incompatible"
+            } else if peer.awaiting_ack {
+                "awaiting ack"
+            } else if peer.trust == PeerTrust::Remembered {
+                "not seen since restart"
+            } else {
+                "ok"
+            };
+            println!(
+                "{:<28} {:<12} {:>10}  {}",
+                peer.name,
+                peer.version.as_deref().unwrap_or("-"),
+                format!("{}s ago", now.saturating_sub(peer.last_seen)),
+                status
+            );
+        }
+    }
+
+    if !offline.is_empty() {
+        println!(
+            "\n{:<28} {:>10}  {:>10}",
+            "OFFLINE PEER", "LAST SEEN", "DROPPED"
+        );
+        for peer in &offline {
+            println!(
+                "{:<28} {:>10}  {:>10}",
+                peer.name,
+                format!("{}s ago", now.saturating_sub(peer.last_seen)),
+                format!("{}s ago", now.saturating_sub(peer.evicted_at))
+            );
+        }
+    }
+
+    if !circuits.is_empty() {
+        println!(
+            "\n{:<28} {:<20} {:>8}  NEXT PROBE",
+            "FAILING PEER", "ADDRESS", "FAILURES"
+        );
+        for circuit in &circuits {
+            println!(
+                "{:<28} {:<20} {:>8}  {}",
+                circuit.name.as_deref().unwrap_or("-"),
+                circuit.address,
+                circuit.consecutive_failures,
+                match circuit.retry_in {
+                    None => "not skipped".to_string(),
+                    Some(0) => "next send".to_string(),
+                    Some(secs) => format!("in {}s", secs),
+                }
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn show_stats(config: &PostConfig, watch: bool) -> Result<()> {
+    let base_url = post_daemon::api::client_base_url(config).await?;
+    let token = post_daemon::api::load_api_token().await?;
+
+    if !watch {
+        print_stats(&post_daemon::api::fetch_stats(&base_url, &token).await?);
+        return Ok(());
+    }
+
+    loop {
+        let stats = post_daemon::api::fetch_stats(&base_url, &token).await?;
+        // Clear the screen and move the cursor home before redrawing
+        print!("\x1B[2J\x1B[H");
+        print_stats(&stats);
+        println!("\nRefreshing every 2s (Press Ctrl+C to stop)");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = tokio::time::sleep(std::time::Duration::from_secs(2)) => {}
+        }
+    }
+    Ok(())
+}
+
+fn print_stats(stats: &post_daemon::api::StatsResponse) {
+    println!(
+        "{:<28} {:>6} {:>6} {:>10} {:>10} {:>6} {:>9}",
+        "PEER", "SENT", "RECV", "BYTES OUT", "BYTES IN", "FAILED", "AVG ACK"
+    );
+
+    let rows = stats.peers.iter().map(|peer| {
+        let label = match &peer.name {
+            Some(name) => name.clone(),
+            None => format!("{} (gone)", peer.id),
+        };
+        (label, &peer.stats)
+    });
+    for (label, row) in rows.chain(std::iter::once(("TOTAL".to_string(), &stats.total))) {
+        println!(
+            "{:<28} {:>6} {:>6} {:>10} {:>10} {:>6} {:>9}",
+            label,
+            row.updates_sent,
+            row.updates_received,
+            format_bytes(row.bytes_sent),
+            format_bytes(row.bytes_received),
+            row.updates_failed,
+            row.average_ack_latency_ms
+                .map(|ms| format!("{} ms", ms))
+                .unwrap_or_else(|| "-".to_string())
+        );
+    }
+
+    let dropped = &stats.dropped;
+    if dropped.clipboard_updates + dropped.other > 0 {
+        println!(
+            "\nDropped while busy: {} clipboard update(s) replaced by newer ones, {} other message(s)",
+            dropped.clipboard_updates, dropped.other
+        );
+    }
+}
+
+/// Where the clipboard came from and how long ago, e.g. "desktop, 12s ago"
+fn describe_source(source: &post_daemon::api::ClipboardSourceResponse) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let age = now.saturating_sub(source.timestamp);
+    match (source.local, &source.app) {
+        (true, _) => format!("this device ({}), {}s ago", source.node_name, age),
+        (false, Some(app)) => format!("{} in {}, {}s ago", source.node_name, app, age),
+        (false, None) => format!("{}, {}s ago", source.node_name, age),
+    }
+}
+
+/// Write the PID file, for status checks, and set up the daemon, telling the `post daemon`
+/// listening on `ready_socket`, if any, how startup goes
+async fn start_daemon(
+    config: PostConfig,
+    ready_socket: Option<std::path::PathBuf>,
+) -> Result<post_daemon::Daemon> {
+    let started = match post_daemon::write_pid_file() {
+        Ok(()) => post_daemon::Daemon::new(config).await,
+        Err(e) => Err(e),
+    };
+    #[cfg(unix)]
+    if let Some(socket) = ready_socket {
+        return match started {
+            // Ready once `run` has bound its listeners
+            Ok(daemon) => Ok(daemon.with_startup_report(Box::new(move |started| {
+                post_daemon::readiness::report(&socket, started)
+            }))),
+            Err(e) => {
+                post_daemon::readiness::report(&socket, Err(&e));
+                Err(e)
+            }
+        };
+    }
+    #[cfg(not(unix))]
+    let _ = ready_socket;
+    started
+}
+
+/// Set up the daemon with `setup` and run it until it stops or a signal stops it,
+/// reloading the config from `config_path` on SIGHUP
+async fn run_daemon(
+    setup: impl std::future::Future<Output = Result<post_daemon::Daemon>>,
+    config_path: Option<String>,
+    verbose: bool,
+) -> Result<()> {
+    // Caught before setup, so a stop requested meanwhile isn't lost
+    #[cfg(unix)]
+    {
+        let signals = post_daemon::signals::register();
+        post_daemon::signals::run(setup.await?, signals, config_path, verbose).await
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (config_path, verbose);
+        setup.await?.run().await
+    }
+}
+
+/// How long `post daemon --wait-ready` waits for the daemon to become ready
+const WAIT_READY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Poll the new daemon's `/health/ready` at `base_url` until every check passes, failing
+/// with the checks that didn't if it takes too long or the daemon `exited`
+async fn wait_until_ready(base_url: &str, exited: impl Fn() -> bool) -> Result<()> {
+    let deadline = std::time::Instant::now() + WAIT_READY_TIMEOUT;
+    loop {
+        let waiting_on = match post_daemon::api::fetch_readiness(base_url).await {
+            Ok(readiness) if readiness.ready => return Ok(()),
+            Ok(readiness) => readiness
+                .failing()
+                .map(|check| match &check.detail {
+                    Some(detail) => format!("{}: {}", check.name, detail),
+                    None => check.name.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join("; "),
+            // The API isn't up until the daemon has started
+            Err(e) => e.to_string(),
+        };
+
+        if exited() {
+            return Err(PostError::Other(format!(
+                "Daemon exited before becoming ready; see {}",
+                post_daemon::get_log_file_path()?.display()
+            )));
+        }
+        if std::time::Instant::now() >= deadline {
+            return Err(PostError::Other(format!(
+                "Daemon isn't ready after {}s ({}); it keeps running, see `post status`",
+                WAIT_READY_TIMEOUT.as_secs(),
+                waiting_on
+            )));
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+}
+
+/// Start the daemon in a new process logging to the log file, returning its PID once it
+/// has reported a successful startup
+///
+/// macOS frameworks don't survive fork(), so the daemon is a fresh process instead.
+#[cfg(target_os = "macos")]
+async fn spawn_daemon(config_path: Option<&str>, verbose: bool, dry_run: bool) -> Result<u32> {
+    use post_daemon::readiness::{ReadinessListener, READY_TIMEOUT};
+    use std::process::Command;
+
+    let current_exe = std::env::current_exe()
+        .map_err(|e| PostError::Other(format!("Failed to get current executable: {}", e)))?;
+
+    let mut cmd = Command::new(&current_exe);
+    if let Some(profile) = post_core::config::profile() {
+        cmd.arg("--profile").arg(profile);
+    }
+    cmd.arg("daemon").arg("--foreground");
+    if let Some(config_path) = config_path {
+        cmd.arg("--config").arg(config_path);
+    }
+    if verbose {
+        cmd.arg("--verbose");
+    }
+    if dry_run {
+        cmd.arg("--dry-run");
+    }
+
+    let log_path = post_daemon::get_log_file_path()?;
+    let log_file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(PostError::Io)?;
+    cmd.stdout(log_file.try_clone().map_err(PostError::Io)?)
+        .stderr(log_file)
+        .stdin(std::process::Stdio::null());
+
+    let listener = ReadinessListener::bind()?;
+    cmd.arg("--ready-socket").arg(listener.path());
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| PostError::Other(format!("Failed to spawn daemon process: {}", e)))?;
+
+    match listener.wait(&mut child, READY_TIMEOUT).await {
+        Ok(()) => Ok(child.id()),
+        Err(e) => {
+            // Don't leave a half-started daemon behind
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(PostError::Other(format!(
+                "Daemon failed to start: {}. See {} for details.",
+                e,
+                log_path.display()
+            )))
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
 async fn show_logs(follow: bool, lines: usize) -> Result<()> {
     let log_path = post_daemon::get_log_file_path()?;
 
@@ -0,0 +1,319 @@
+//! macOS menu bar item showing the daemon's sync state, driven by its event stream
+
+use objc::declare::ClassDecl;
+use objc::runtime::{Class, Object, Sel, NO, YES};
+use objc::{class, msg_send, sel, sel_impl};
+use post_core::{PostConfig, Result, SyncEvent};
+use std::ffi::CString;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+#[link(name = "AppKit", kind = "framework")]
+extern "C" {}
+
+/// Wait before trying to reach the daemon again after its API stopped answering
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How often the menu is refreshed from [`STATE`], in seconds
+const REFRESH_INTERVAL: f64 = 1.0;
+
+/// NSVariableStatusItemLength
+const VARIABLE_LENGTH: f64 = -1.0;
+
+/// NSApplicationActivationPolicyAccessory: no Dock icon or app menu
+const ACCESSORY_POLICY: isize = 1;
+
+static STATE: Mutex<MenuState> = Mutex::new(MenuState::new());
+
+/// Pause (`true`) or resume requests from the menu, sent on by [`follow_daemon`]
+static PAUSE_REQUESTS: OnceLock<mpsc::UnboundedSender<bool>> = OnceLock::new();
+
+/// What the menu shows, as last learned from the daemon
+struct MenuState {
+    /// Whether the daemon's API answered; nothing else is known until it does
+    reachable: bool,
+    connected: bool,
+    paused: bool,
+    clipboard_error: Option<String>,
+    /// Where the last synced content came from: "copied here" or "from <peer>"
+    last_source: Option<String>,
+}
+
+impl MenuState {
+    const fn new() -> Self {
+        Self {
+            reachable: false,
+            connected: false,
+            paused: false,
+            clipboard_error: None,
+            last_source: None,
+        }
+    }
+
+    fn apply(&mut self, event: &SyncEvent) {
+        match event {
+            SyncEvent::Received { from_name, .. } => {
+                self.last_source = Some(format!("from {}", from_name))
+            }
+            SyncEvent::Sent { .. } => self.last_source = Some("copied here".to_string()),
+            SyncEvent::Connected { .. } => self.connected = true,
+            SyncEvent::Disconnected => self.connected = false,
+            SyncEvent::ClipboardUnavailable { error } => self.clipboard_error = Some(error.clone()),
+            SyncEvent::ClipboardRecovered => self.clipboard_error = None,
+            SyncEvent::Paused => self.paused = true,
+            SyncEvent::Resumed => self.paused = false,
+            SyncEvent::PeerDiscovered { .. }
+            | SyncEvent::PeerOffline { .. }
+            | SyncEvent::TailnetPeerOnline { .. }
+            | SyncEvent::TailnetPeerOffline { .. }
+            | SyncEvent::ClockSkew { .. }
+            | SyncEvent::Filtered { .. } => {}
+        }
+    }
+
+    /// Text shown in the menu bar itself
+    fn title(&self) -> &'static str {
+        if !self.reachable {
+            "Post ✕"
+        } else if self.paused {
+            "Post ❚❚"
+        } else if !self.connected || self.clipboard_error.is_some() {
+            "Post ○"
+        } else {
+            "Post ●"
+        }
+    }
+
+    fn status(&self) -> String {
+        if !self.reachable {
+            "Daemon not running".to_string()
+        } else if self.paused {
+            "Sync paused".to_string()
+        } else if !self.connected {
+            "Waiting for Tailscale".to_string()
+        } else if let Some(error) = &self.clipboard_error {
+            format!("Clipboard unavailable: {}", error)
+        } else {
+            "Syncing".to_string()
+        }
+    }
+
+    fn source(&self) -> String {
+        match &self.last_source {
+            Some(source) => format!("Last clipboard: {}", source),
+            None => "Nothing synced yet".to_string(),
+        }
+    }
+}
+
+fn state() -> MutexGuard<'static, MenuState> {
+    STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Show the status item until it is quit from its menu
+///
+/// AppKit has to own the main thread, so this blocks it; the daemon is followed on the
+/// runtime's worker threads.
+pub fn run(config: PostConfig) -> Result<()> {
+    let (requests, receiver) = mpsc::unbounded_channel();
+    let _ = PAUSE_REQUESTS.set(requests);
+    tokio::spawn(follow_daemon(config, receiver));
+
+    unsafe {
+        let app: *mut Object = msg_send![class!(NSApplication), sharedApplication];
+        let _: () = msg_send![app, setActivationPolicy: ACCESSORY_POLICY];
+
+        let status_bar: *mut Object = msg_send![class!(NSStatusBar), systemStatusBar];
+        let status_item: *mut Object = msg_send![status_bar, statusItemWithLength: VARIABLE_LENGTH];
+        let _: *mut Object = msg_send![status_item, retain];
+
+        let target: *mut Object = msg_send![menu_target_class(), new];
+        let menu: *mut Object = msg_send![class!(NSMenu), new];
+        let _: () = msg_send![menu, setAutoenablesItems: NO];
+
+        let status_line = add_item(menu, target, "", None, "");
+        let source_line = add_item(menu, target, "", None, "");
+        let separator: *mut Object = msg_send![class!(NSMenuItem), separatorItem];
+        let _: () = msg_send![menu, addItem: separator];
+        let pause_item = add_item(menu, target, "Pause Sync", Some(sel!(togglePause:)), "p");
+        add_item(menu, target, "Open Logs", Some(sel!(openLogs:)), "l");
+        let separator: *mut Object = msg_send![class!(NSMenuItem), separatorItem];
+        let _: () = msg_send![menu, addItem: separator];
+        // No target: terminate: goes up the responder chain to the application
+        add_item(
+            menu,
+            std::ptr::null_mut(),
+            "Quit Post Menu",
+            Some(sel!(terminate:)),
+            "q",
+        );
+        let _: () = msg_send![status_item, setMenu: menu];
+
+        (*target).set_ivar("statusItem", status_item);
+        (*target).set_ivar("statusLine", status_line);
+        (*target).set_ivar("sourceLine", source_line);
+        (*target).set_ivar("pauseItem", pause_item);
+        refresh(&*target, sel!(refresh:), std::ptr::null_mut());
+
+        let _: *mut Object = msg_send![class!(NSTimer),
+            scheduledTimerWithTimeInterval: REFRESH_INTERVAL
+            target: target
+            selector: sel!(refresh:)
+            userInfo: std::ptr::null_mut::<Object>()
+            repeats: YES];
+
+        let _: () = msg_send![app, run];
+    }
+    Ok(())
+}
+
+/// Follow the daemon's status and events into [`STATE`], and pass on pause requests,
+/// reconnecting whenever the daemon goes away
+async fn follow_daemon(config: PostConfig, mut pause_requests: mpsc::UnboundedReceiver<bool>) {
+    loop {
+        if let Err(e) = follow_events(&config, &mut pause_requests).await {
+            debug!("Daemon API unavailable: {}", e);
+        }
+        state().reachable = false;
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn follow_events(
+    config: &PostConfig,
+    pause_requests: &mut mpsc::UnboundedReceiver<bool>,
+) -> Result<()> {
+    // Requests made while the daemon was unreachable are stale by now
+    while pause_requests.try_recv().is_ok() {}
+
+    let base_url = post_daemon::api::client_base_url(config).await?;
+    let token = post_daemon::api::load_api_token().await?;
+    let status = post_daemon::api::fetch_status(&base_url, &token).await?;
+    {
+        let mut state = state();
+        state.reachable = true;
+        state.connected = status.connected;
+        state.paused = status.paused;
+    }
+
+    let events = post_daemon::api::watch_events(&base_url, &token, |line| {
+        if let Ok(event) = serde_json::from_str::<SyncEvent>(line) {
+            state().apply(&event);
+        }
+    });
+    tokio::pin!(events);
+    loop {
+        tokio::select! {
+            result = &mut events => return result,
+            Some(paused) = pause_requests.recv() => {
+                if let Err(e) = post_daemon::api::request_paused(&base_url, &token, paused).await {
+                    warn!("Failed to {} sync: {}", if paused { "pause" } else { "resume" }, e);
+                }
+            }
+        }
+    }
+}
+
+/// The class of the object menu items and the refresh timer call back into
+fn menu_target_class() -> &'static Class {
+    static CLASS: OnceLock<&'static Class> = OnceLock::new();
+    CLASS.get_or_init(|| {
+        let mut decl = ClassDecl::new("PostMenuTarget", class!(NSObject))
+            .expect("PostMenuTarget is only declared here");
+        unsafe {
+            decl.add_ivar::<*mut Object>("statusItem");
+            decl.add_ivar::<*mut Object>("statusLine");
+            decl.add_ivar::<*mut Object>("sourceLine");
+            decl.add_ivar::<*mut Object>("pauseItem");
+            decl.add_method(
+                sel!(refresh:),
+                refresh as extern "C" fn(&Object, Sel, *mut Object),
+            );
+            decl.add_method(
+                sel!(togglePause:),
+                toggle_pause as extern "C" fn(&Object, Sel, *mut Object),
+            );
+            decl.add_method(
+                sel!(openLogs:),
+                open_logs as extern "C" fn(&Object, Sel, *mut Object),
+            );
+        }
+        decl.register()
+    })
+}
+
+extern "C" fn refresh(this: &Object, _: Sel, _: *mut Object) {
+    let state = state();
+    unsafe {
+        let status_item = *this.get_ivar::<*mut Object>("statusItem");
+        let button: *mut Object = msg_send![status_item, button];
+        set_title(button, state.title());
+        set_title(*this.get_ivar("statusLine"), &state.status());
+        set_title(*this.get_ivar("sourceLine"), &state.source());
+
+        let pause_item = *this.get_ivar::<*mut Object>("pauseItem");
+        set_title(
+            pause_item,
+            if state.paused {
+                "Resume Sync"
+            } else {
+                "Pause Sync"
+            },
+        );
+        let _: () = msg_send![pause_item, setEnabled: if state.reachable { YES } else { NO }];
+    }
+}
+
+extern "C" fn toggle_pause(_: &Object, _: Sel, _: *mut Object) {
+    let paused = !state().paused;
+    if let Some(requests) = PAUSE_REQUESTS.get() {
+        let _ = requests.send(paused);
+    }
+}
+
+extern "C" fn open_logs(_: &Object, _: Sel, _: *mut Object) {
+    let opened = post_daemon::get_log_file_path().and_then(|path| {
+        std::process::Command::new("open")
+            .arg(path)
+            .spawn()
+            .map(drop)
+            .map_err(Into::into)
+    });
+    if let Err(e) = opened {
+        warn!("Failed to open the log file: {}", e);
+    }
+}
+
+/// Add an item to `menu`; items without an action are shown disabled, as plain text
+unsafe fn add_item(
+    menu: *mut Object,
+    target: *mut Object,
+    title: &str,
+    action: Option<Sel>,
+    key: &str,
+) -> *mut Object {
+    let item: *mut Object = msg_send![class!(NSMenuItem), alloc];
+    let item: *mut Object = msg_send![item,
+        initWithTitle: ns_string(title)
+        action: action.unwrap_or(sel!(refresh:))
+        keyEquivalent: ns_string(key)];
+    let _: () = msg_send![item, setTarget: target];
+    if action.is_none() {
+        let _: () = msg_send![item, setEnabled: NO];
+    }
+    let _: () = msg_send![menu, addItem: item];
+    item
+}
+
+unsafe fn set_title(object: *mut Object, title: &str) {
+    let _: () = msg_send![object, setTitle: ns_string(title)];
+}
+
+unsafe fn ns_string(text: &str) -> *mut Object {
+    let text = CString::new(text.replace('\0', "")).unwrap_or_default();
+    msg_send![class!(NSString), stringWithUTF8String: text.as_ptr()]
+}
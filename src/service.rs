@@ -30,6 +30,10 @@ fn set_file_permissions(_path: &Path, _mode: u32) -> Result<()> {
 pub mod macos {
     use super::*;
 
+    fn label() -> String {
+        format!("com.post.daemon{}", post_core::config::profile_suffix("."))
+    }
+
     /// Install the daemon as a macOS LaunchAgent service
     pub async fn install_service() -> Result<()> {
         let current_exe = std::env::current_exe().map_err(PostError::Io)?;
@@ -42,7 +46,14 @@ pub mod macos {
         // Set secure permissions on plist directory (755 - standard for LaunchAgents)
         set_file_permissions(&plist_dir, 0o755)?;
 
-        let plist_path = plist_dir.join("com.post.daemon.plist");
+        let plist_path = plist_dir.join(format!("{}.plist", label()));
+        let profile_args = match post_core::config::profile() {
+            Some(profile) => format!(
+                "<string>--profile</string>\n        <string>{}</string>\n        ",
+                xml_escape(profile)
+            ),
+            None => String::new(),
+        };
 
         let current_exe_escaped = xml_escape(&current_exe.display().to_string());
         let log_path_escaped = xml_escape(&post_daemon::get_log_file_path()?.display().to_string());
@@ -53,11 +64,11 @@ pub mod macos {
 <plist version="1.0">
 <dict>
     <key>Label</key>
-    <string>com.post.daemon</string>
+    <string>{}</string>
     <key>ProgramArguments</key>
     <array>
         <string>{}</string>
-        <string>daemon</string>
+        {}<string>daemon</string>
     </array>
     <key>RunAtLoad</key>
     <true/>
@@ -69,7 +80,11 @@ pub mod macos {
     <string>{}</string>
 </dict>
 </plist>"#,
-            current_exe_escaped, log_path_escaped, log_path_escaped
+            label(),
+            current_exe_escaped,
+            profile_args,
+            log_path_escaped,
+            log_path_escaped
         );
 
         std::fs::write(&plist_path, plist_content).map_err(PostError::Io)?;
@@ -108,7 +123,9 @@ pub mod macos {
         let home_dir = dirs::home_dir()
             .ok_or_else(|| PostError::Other("Could not find home directory".to_string()))?;
 
-        let plist_path = home_dir.join("Library/LaunchAgents/com.post.daemon.plist");
+        let plist_path = home_dir
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", label()));
 
         if plist_path.exists() {
             // Unload the service
@@ -137,6 +154,38 @@ pub mod macos {
 
         Ok(())
     }
+
+    fn plist_path() -> Result<std::path::PathBuf> {
+        let home_dir = dirs::home_dir()
+            .ok_or_else(|| PostError::Other("Could not find home directory".to_string()))?;
+        Ok(home_dir
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", label())))
+    }
+
+    /// Whether the LaunchAgent is installed
+    pub fn is_service_installed() -> bool {
+        plist_path().is_ok_and(|path| path.exists())
+    }
+
+    /// Restart the LaunchAgent through launchd, which would otherwise respawn a killed daemon
+    pub async fn restart_service() -> Result<()> {
+        let target = format!("gui/{}/{}", nix::unistd::getuid(), label());
+        let output = tokio::process::Command::new("launchctl")
+            .args(["kickstart", "-k", &target])
+            .output()
+            .await
+            .map_err(PostError::Io)?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(PostError::Other(format!(
+                "Failed to restart service: {}",
+                error
+            )));
+        }
+        Ok(())
+    }
 }
 
 /// Linux-specific service management
@@ -144,6 +193,13 @@ pub mod macos {
 pub mod linux {
     use super::*;
 
+    fn unit() -> String {
+        format!(
+            "post-daemon{}.service",
+            post_core::config::profile_suffix("-")
+        )
+    }
+
     /// Install the daemon as a systemd user service
     pub async fn install_service() -> Result<()> {
         let current_exe = std::env::current_exe().map_err(PostError::Io)?;
@@ -158,7 +214,10 @@ pub mod linux {
         // Set secure permissions on systemd directory (755 - standard for systemd user services)
         set_file_permissions(&systemd_dir, 0o755)?;
 
-        let service_path = systemd_dir.join("post-daemon.service");
+        let service_path = systemd_dir.join(unit());
+        let profile_args = post_core::config::profile()
+            .map(|profile| format!("--profile {} ", profile))
+            .unwrap_or_default();
 
         let service_content = format!(
             r#"[Unit]
@@ -167,7 +226,7 @@ After=network.target
 
 [Service]
 Type=simple
-ExecStart={} daemon --foreground
+ExecStart={} {}daemon --foreground
 Restart=always
 RestartSec=5
 StandardOutput=append:{}
@@ -177,6 +236,7 @@ StandardError=append:{}
 WantedBy=default.target
 "#,
             current_exe.display(),
+            profile_args,
             post_daemon::get_log_file_path()?.display(),
             post_daemon::get_log_file_path()?.display()
         );
@@ -202,7 +262,7 @@ WantedBy=default.target
         }
 
         let enable_output = tokio::process::Command::new("systemctl")
-            .args(["--user", "enable", "post-daemon.service"])
+            .args(["--user", "enable", &unit()])
             .output()
             .await
             .map_err(PostError::Io)?;
@@ -216,7 +276,7 @@ WantedBy=default.target
         }
 
         let start_output = tokio::process::Command::new("systemctl")
-            .args(["--user", "start", "post-daemon.service"])
+            .args(["--user", "start", &unit()])
             .output()
             .await
             .map_err(PostError::Io)?;
@@ -240,17 +300,17 @@ WantedBy=default.target
         let home_dir = dirs::home_dir()
             .ok_or_else(|| PostError::Other("Could not find home directory".to_string()))?;
 
-        let service_path = home_dir.join(".config/systemd/user/post-daemon.service");
+        let service_path = home_dir.join(".config/systemd/user").join(unit());
 
         if service_path.exists() {
             // Stop and disable the service
             let _ = tokio::process::Command::new("systemctl")
-                .args(["--user", "stop", "post-daemon.service"])
+                .args(["--user", "stop", &unit()])
                 .output()
                 .await;
 
             let _ = tokio::process::Command::new("systemctl")
-                .args(["--user", "disable", "post-daemon.service"])
+                .args(["--user", "disable", &unit()])
                 .output()
                 .await;
 
@@ -270,6 +330,29 @@ WantedBy=default.target
 
         Ok(())
     }
+
+    /// Whether the systemd user service is installed
+    pub fn is_service_installed() -> bool {
+        dirs::home_dir().is_some_and(|home| home.join(".config/systemd/user").join(unit()).exists())
+    }
+
+    /// Restart the systemd user service, which would otherwise restart a killed daemon itself
+    pub async fn restart_service() -> Result<()> {
+        let output = tokio::process::Command::new("systemctl")
+            .args(["--user", "restart", &unit()])
+            .output()
+            .await
+            .map_err(PostError::Io)?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(PostError::Other(format!(
+                "Failed to restart service: {}",
+                error
+            )));
+        }
+        Ok(())
+    }
 }
 
 /// Cross-platform service management interface
@@ -299,3 +382,36 @@ pub async fn uninstall_service() -> Result<()> {
         "Service uninstallation is not supported on this platform".to_string(),
     ));
 }
+
+/// Whether the daemon is installed as a system service
+pub fn is_service_installed() -> bool {
+    #[cfg(target_os = "macos")]
+    return macos::is_service_installed();
+
+    #[cfg(target_os = "linux")]
+    return linux::is_service_installed();
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    return false;
+}
+
+/// Cross-platform service restart interface
+pub async fn restart_service() -> Result<()> {
+    if !is_service_installed() {
+        return Err(PostError::Other(
+            "Service is not installed; install it with `post install` or use `post restart`"
+                .to_string(),
+        ));
+    }
+
+    #[cfg(target_os = "macos")]
+    return macos::restart_service().await;
+
+    #[cfg(target_os = "linux")]
+    return linux::restart_service().await;
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    return Err(PostError::Other(
+        "Service management is not supported on this platform".to_string(),
+    ));
+}